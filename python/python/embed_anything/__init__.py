@@ -6,6 +6,7 @@ The module includes the following functions:
  - `embed_query`: Embeds the given query and returns an EmbedData object.
  - `embed_file`: Embeds the file at the given path and returns a list of EmbedData objects.
  - `embed_directory`: Embeds all the files in the given directory and returns a list of EmbedData objects.
+ - `embed_records`: Embeds (id, text) records, e.g. rows of a database, keeping their ids in the metadata.
 
 The module also includes the `EmbedData` class, which represents the data of an embedded file.
 
@@ -1,7 +1,9 @@
 from enum import Enum
-from typing import List, Dict, Optional
+from typing import Callable, List, Dict, Optional
 from abc import ABC, abstractmethod
 
+import numpy as np
+
 class Adapter(ABC):
     def __init__(self, api_key: str):
         """
@@ -52,6 +54,12 @@ class Adapter(ABC):
             data: The list of data to upsert.
         """
 
+    def flush(self):
+        """
+        Called once after the last upsert when embedding succeeds, to write the records an
+        adapter buffered. Does nothing by default.
+        """
+
 def embed_query(
     query: list[str], embedder: EmbeddingModel, config: TextEmbedConfig | None = None
 ) -> list[EmbedData]:
@@ -78,11 +86,80 @@ def embed_query(
     ```
     """
 
+def try_embed_query(
+    query: list[str], embedder: EmbeddingModel, config: TextEmbedConfig | None = None
+) -> list[EmbedData | ValueError]:
+    """
+    Embeds the given queries like `embed_query`, but a query that fails doesn't fail the
+    others. Empty and whitespace-only queries always fail.
+
+    Args:
+        query: The queries to embed.
+        embedder: The embedding model to use.
+        config: The configuration for the embedding model.
+
+    Returns:
+        A list with, for each query in order, its EmbedData or the ValueError it failed
+        with. The errors are returned, not raised.
+
+    Example:
+
+    ```python
+    import embed_anything
+    model = embed_anything.EmbeddingModel.from_pretrained_hf(
+        embed_anything.WhichModel.Bert,
+        model_id="sentence-transformers/all-MiniLM-L6-v2",
+    )
+    results = embed_anything.try_embed_query(["What is Rust?", ""], embedder=model)
+    for result in results:
+        if isinstance(result, ValueError):
+            print("failed:", result)
+    ```
+    """
+
+def embed_records(
+    records: list[dict],
+    embedder: EmbeddingModel,
+    config: TextEmbedConfig | None = None,
+    adapter: Adapter | None = None,
+) -> list[EmbedData]:
+    """
+    Embeds texts that don't come from files, such as rows of a database, keeping their ids.
+
+    Args:
+        records: Dicts with an "id", a "text" and an optional "metadata" dict of strings. Ids
+            that aren't strings are converted with str.
+        embedder: The embedding model to use.
+        config: The configuration for the embedding model. Records are embedded whole unless
+            chunk_records is set.
+        adapter: The adapter to use for storing the embeddings in a vector database.
+
+    Returns:
+        A list of EmbedData objects. The id of the record is stored under "record_id" in their
+        metadata, along with the metadata of the record and, when chunked, the "chunk_index".
+
+    Example:
+    ```python
+    import embed_anything
+    model = embed_anything.EmbeddingModel.from_pretrained_hf(
+        embed_anything.WhichModel.Bert,
+        model_id="sentence-transformers/all-MiniLM-L6-v2",
+    )
+    rows = [
+        {"id": 42, "text": "The invoice was paid on time.", "metadata": {"table": "invoices"}},
+        {"id": 43, "text": "The delivery was late."},
+    ]
+    data = embed_anything.embed_records(rows, embedder=model)
+    ```
+    """
+
 def embed_file(
     file_path: str,
     embedder: EmbeddingModel,
     config: TextEmbedConfig | None = None,
     adapter: Adapter | None = None,
+    chunk_size: int | None = None,
+    batch_size: int | None = None,
 ) -> list[EmbedData]:
     """
     Embeds the given file and returns a list of EmbedData objects.
@@ -92,10 +169,19 @@ def embed_file(
         embedder: The embedding model to use.
         config: The configuration for the embedding model.
         adapter: The adapter to use for storing the embeddings in a vector database.
+        chunk_size: Overrides the chunk size of config for this call.
+        batch_size: Overrides the batch size of config for this call. A setting takes the value
+            passed to the function first, then the one of config, then the default.
 
     Returns:
         A list of EmbedData objects.
 
+    Raises:
+        FileNotFoundError: If the file doesn't exist.
+        PermissionError: If the file can't be read.
+        RuntimeError: If the text of the file can't be extracted, e.g. from a corrupted file.
+        ValueError: If the file type is unsupported, the file is encrypted or it holds no text.
+
     Example:
     ```python
     import embed_anything
@@ -108,12 +194,87 @@ def embed_file(
     ```
     """
 
+def embed_file_oneshot(
+    file_path: str,
+    model: WhichModel,
+    model_id: str | None = None,
+    config: TextEmbedConfig | None = None,
+) -> list[EmbedData]:
+    """
+    Loads a model from the Hugging Face model hub, embeds the given file and frees the model
+    before returning. Meant for scripts and CLI tools that embed once and exit.
+
+    Loading a model is slow, so this is inefficient when called repeatedly. Load an
+    EmbeddingModel once and use embed_file to embed several files.
+
+    Args:
+        file_path: The path to the file to embed.
+        model: The type of the model.
+        model_id: The ID of the model, the default model of the type when not given.
+        config: The configuration for the embedding model.
+
+    Returns:
+        A list of EmbedData objects.
+
+    Example:
+    ```python
+    import embed_anything
+    data = embed_anything.embed_file_oneshot(
+        "test_files/test.pdf",
+        embed_anything.WhichModel.Bert,
+        model_id="sentence-transformers/all-MiniLM-L6-v2",
+    )
+    ```
+    """
+
+def embed_file_multi(
+    file_path: str,
+    embedders: list[tuple[str, EmbeddingModel]],
+    config: TextEmbedConfig | None = None,
+    adapter: Adapter | None = None,
+) -> list[EmbedData]:
+    """
+    Embeds the given file with several models, extracting and chunking it only once. Useful for
+    hybrid search, where every chunk is stored with a dense and a sparse vector.
+
+    Args:
+        file_path: The path to the file to embed.
+        embedders: Pairs of a name and an embedding model.
+        config: The configuration for the embedding models.
+        adapter: The adapter to use for storing the embeddings in a vector database. Its
+            `upsert` receives EmbedData objects whose `embeddings` dict holds one vector per
+            model name.
+
+    Returns:
+        A list of EmbedData objects. `embeddings` maps each model name to its embedding, while
+        `embedding` and `is_normalized` are the ones of the first model.
+
+    Example:
+    ```python
+    import embed_anything
+    dense = embed_anything.EmbeddingModel.from_pretrained_hf(
+        embed_anything.WhichModel.Bert,
+        model_id="sentence-transformers/all-MiniLM-L6-v2",
+    )
+    sparse = embed_anything.EmbeddingModel.from_pretrained_hf(
+        embed_anything.WhichModel.SparseBert,
+        model_id="prithivida/Splade_PP_en_v1",
+    )
+    data = embed_anything.embed_file_multi(
+        "test_files/test.pdf", [("dense", dense), ("sparse", sparse)]
+    )
+    sparse_vector = data[0].embeddings["sparse"]
+    ```
+    """
+
 def embed_directory(
     file_path: str,
     embedder: EmbeddingModel,
     extensions: list[str],
     config: TextEmbedConfig | None = None,
     adapter: Adapter | None = None,
+    chunk_size: int | None = None,
+    batch_size: int | None = None,
 ) -> list[EmbedData]:
     """
     Embeds the files in the given directory and returns a list of EmbedData objects.
@@ -124,6 +285,9 @@ def embed_directory(
         extensions: The list of file extensions to consider for embedding.
         config: The configuration for the embedding model.
         adapter: The adapter to use for storing the embeddings in a vector database.
+        chunk_size: Overrides the chunk size of config for this call.
+        batch_size: Overrides the batch size of config for this call. A setting takes the value
+            passed to the function first, then the one of config, then the default.
 
     Returns:
         A list of EmbedData objects.
@@ -190,6 +354,205 @@ def embed_webpage(
     ```
     """
 
+def embed_urls(
+    urls: list[str],
+    embedder: EmbeddingModel,
+    config: TextEmbedConfig | None = None,
+    adapter: Adapter | None = None,
+) -> list[EmbedData] | None:
+    """Embeds several webpages, fetching them concurrently. Each EmbedData has the page it
+    comes from in its "url" metadata. Pages that cannot be fetched are skipped.
+
+    Args:
+        urls: The URLs of the webpages to embed.
+        embedder: The embedding model to use.
+        config: The configuration for the embedding model.
+        adapter: The adapter to use for storing the embeddings. It is called once per page.
+
+    Returns:
+        A list of EmbedData objects, or None when an adapter is given.
+
+    Example:
+    ```python
+    import embed_anything
+
+    model = embed_anything.EmbeddingModel.from_pretrained_hf(
+        embed_anything.WhichModel.Bert, "sentence-transformers/all-MiniLM-L6-v2"
+    )
+    data = embed_anything.embed_urls(
+        ["https://www.akshaymakes.com/", "https://example.com/"], embedder=model
+    )
+    ```
+    """
+
+def chunk_preview(
+    path: str,
+    config: TextEmbedConfig | None = None,
+) -> list[ChunkPreview]:
+    """Extracts and chunks a file, or every supported file of a directory, without running any
+    embedding model. The chunks match the ones embed_file and embed_directory would embed with
+    the same config, which helps to tune chunk_size and estimate the cost of cloud models.
+
+    Args:
+        path: The file or directory to chunk.
+        config: The configuration used for chunking.
+
+    Returns:
+        A list of ChunkPreview objects.
+
+    Example:
+    ```python
+    import embed_anything
+
+    config = embed_anything.TextEmbedConfig(chunk_size=512)
+    chunks = embed_anything.chunk_preview("test_files", config=config)
+    print(len(chunks), sum(chunk.token_count for chunk in chunks))
+    ```
+    """
+
+def chunk_text(text: str, config: TextEmbedConfig | None = None) -> list[Chunk]:
+    """Splits a text into chunks without running any embedding model, e.g. to use the chunkers
+    of embed_anything in another pipeline. The text is split with the splitting_strategy of the
+    config, the chunks match the ones embed_file would embed for a file holding the text.
+
+    Args:
+        text: The text to split.
+        config: The configuration used for chunking. Only the chunking options are used.
+
+    Returns:
+        A list of Chunk objects.
+
+    Example:
+    ```python
+    import embed_anything
+
+    config = embed_anything.TextEmbedConfig(chunk_size=128, splitting_strategy="code:python")
+    for chunk in embed_anything.chunk_text(source, config=config):
+        print(chunk.start_char, chunk.end_char, chunk.metadata.get("symbol"))
+    ```
+    """
+
+def similarity_search(
+    query: str,
+    path: str,
+    embedder: EmbeddingModel,
+    config: TextEmbedConfig | None = None,
+    k: int = 5,
+) -> list[SearchResult]:
+    """Embeds a file, or every supported file of a directory, and returns the k chunks most
+    similar to the query, best first. The chunks are ranked in memory, so no vector database is
+    needed. Everything is embedded again on each call, which suits small collections.
+
+    Args:
+        query: The text to search for.
+        path: The file or directory to search.
+        embedder: The embedding model to use.
+        config: The configuration used for chunking and embedding.
+        k: The number of chunks to return.
+
+    Returns:
+        A list of SearchResult objects.
+
+    Example:
+    ```python
+    import embed_anything
+    from embed_anything import EmbeddingModel, WhichModel
+
+    model = EmbeddingModel.from_pretrained_hf(WhichModel.Bert, model_id="BAAI/bge-small-en-v1.5")
+    results = embed_anything.similarity_search("What is attention?", "test_files", model, k=3)
+    for result in results:
+        print(result.score, result.metadata["file_name"])
+    ```
+    """
+
+def pairwise_similarity(
+    texts: list[str],
+    embedder: EmbeddingModel,
+    config: TextEmbedConfig | None = None,
+) -> np.ndarray:
+    """Embeds the texts and returns the matrix of their cosine similarities, e.g. to find near
+    duplicates or cluster a small set of texts. Requires numpy.
+
+    Args:
+        texts: The texts to compare.
+        embedder: The embedding model to use. Multi-vector models are not supported.
+        config: The configuration, only its batch_size is used.
+
+    Returns:
+        A float32 array of shape (len(texts), len(texts)).
+
+    Example:
+    ```python
+    import embed_anything
+    from embed_anything import EmbeddingModel, WhichModel
+
+    model = EmbeddingModel.from_pretrained_hf(WhichModel.Bert, model_id="BAAI/bge-small-en-v1.5")
+    similarities = embed_anything.pairwise_similarity(["A cat sat on the mat.", "A cat sits on a mat."], model)
+    print(similarities[0, 1])
+    ```
+    """
+
+def hybrid_score(
+    dense_query: list[float],
+    dense_document: list[float],
+    sparse_query: list[float],
+    sparse_document: list[float],
+    alpha: float = 0.5,
+) -> float:
+    """Scores a document for hybrid search as alpha * dense + (1 - alpha) * sparse, where dense
+    is the cosine similarity of the dense embeddings and sparse the dot product of the sparse
+    embeddings, e.g. from a SPLADE model.
+
+    Args:
+        dense_query: The dense embedding of the query.
+        dense_document: The dense embedding of the document.
+        sparse_query: The sparse embedding of the query.
+        sparse_document: The sparse embedding of the document.
+        alpha: The weight of the dense score, 1.0 only uses the dense score and 0.0 only the sparse score.
+
+    Returns:
+        The fused score.
+
+    Raises:
+        ValueError: If the query and document embeddings of a kind have different dimensions.
+    """
+
+def rrf(rankings: list[list[int]], k: int = 60) -> list[tuple[int, float]]:
+    """Merges rankings with reciprocal rank fusion. A document scores 1 / (k + rank) in every
+    ranking it appears in, with ranks starting at 1.
+
+    Args:
+        rankings: Lists of document ids, best first, e.g. the results of a dense and a sparse search.
+        k: Larger values flatten the difference between the top ranks. Defaults to 60.
+
+    Returns:
+        Every document id with its fused score, best first.
+
+    Example:
+    ```python
+    import embed_anything
+
+    fused = embed_anything.rrf([[1, 2, 3], [3, 1, 4]])
+    print(fused[0])  # (1, 0.0325...)
+    ```
+    """
+
+def supported_extensions() -> list[str]:
+    """Lists the extensions of the files that `embed_file` can read,
+    without the leading dot. Formats read by registered extractors are not listed.
+
+    Returns:
+        The extensions, e.g. ["pdf", "md", "txt", ...].
+
+    Example:
+    ```python
+    import embed_anything
+
+    extensions = embed_anything.supported_extensions()
+    files = [f for f in files if f.rsplit(".", 1)[-1] in extensions]
+    ```
+    """
+
 def embed_audio_file(
     file_path: str,
     audio_decoder: AudioDecoderModel,
@@ -197,18 +560,24 @@ def embed_audio_file(
     text_embed_config: TextEmbedConfig | None = TextEmbedConfig(
         chunk_size=200, batch_size=32
     ),
-) -> list[EmbedData]:
+    return_transcript: bool = False,
+) -> list[EmbedData] | tuple[list[EmbedData], str]:
     """
     Embeds the given audio file and returns a list of EmbedData objects.
 
     Args:
-        file_path: The path to the audio file to embed.
+        file_path: The path to the audio file to embed. WAV, MP3, FLAC and M4A/AAC files are
+            supported, at any sample rate.
         audio_decoder: The audio decoder model to use.
         embedder: The embedding model to use.
         text_embed_config: The configuration for the embedding model.
+        return_transcript: Whether to also return the full transcript, so that it can be
+            displayed with search results without transcribing the file again.
 
     Returns:
-        A list of EmbedData objects.
+        A list of EmbedData objects, one per transcribed segment with the segment text as
+        text. With return_transcript, a tuple of that list and the transcript, the text of
+        all segments separated by spaces.
 
     Example:
     ```python
@@ -235,22 +604,277 @@ def embed_audio_file(
 
     """
 
+def embed_audio_directory(
+    directory: str,
+    audio_decoder: AudioDecoderModel,
+    embedder: EmbeddingModel,
+    text_embed_config: TextEmbedConfig | None = None,
+) -> list[EmbedData]:
+    """
+    Transcribes and embeds the WAV files of a directory. The decoder transcribes one file at a
+    time while the transcripts of the previous files are embedded. Files that fail to decode
+    are skipped with a warning.
+
+    Args:
+        directory: The path to the directory containing the audio files.
+        audio_decoder: The audio decoder model to use.
+        embedder: The embedding model to use.
+        text_embed_config: The configuration for the embedding model. Its file_filter, seed,
+            batch_size and output_dtype are used.
+
+    Returns:
+        A list of EmbedData objects, one per segment. The metadata of each segment holds the
+        file_name, created and modified time of its file, along with its start, end, text
+        and segment_index.
+
+    Raises:
+        BudgetExceededError: If a cloud embedder goes over its token budget. No later file is
+            embedded.
+    """
+
+class BudgetExceededError(RuntimeError):
+    """Raised when a cloud model would go over the `max_total_tokens` of
+    `EmbeddingModel.from_pretrained_cloud`.
+
+    Attributes:
+        embeddings: The embeddings made before the budget ran out.
+        indices: The positions of the texts of `embeddings` in the embedded batch, in increasing order.
+        used: The tokens charged when the budget ran out.
+        max_total_tokens: The budget of the model.
+        embed_data: Raised by `embed_directory`, the embeddings of the chunks embedded before the budget ran out. Empty when an adapter is used.
+    """
+
+    embeddings: list[list[float]]
+    indices: list[int]
+    used: int
+    max_total_tokens: int
+    embed_data: list[EmbedData]
+
 class EmbedData:
     """Represents the data of an embedded file.
 
     Attributes:
-        embedding: The embedding of the file.
+        embedding: The embedding of the file. With the "f16" output_dtype of TextEmbedConfig, the little endian bytes of the float16 vector.
         text: The text for which the embedding is generated for.
         metadata: Additional metadata associated with the embedding.
+        is_normalized: Whether the embedding is L2-normalized. If True, the dot product equals the cosine similarity.
+        embeddings: The embeddings by model name, filled by `embed_file_multi`.
     """
 
     def __init__(self, embedding: list[float], text: str, metadata: dict[str, str]):
         self.embedding = embedding
         self.text = text
         self.metadata = metadata
-    embedding: list[float]
+    embedding: list[float] | bytes
     text: str
     metadata: dict[str, str]
+    is_normalized: bool
+    embeddings: dict[str, list[float] | bytes]
+
+    def embedding_base64(self) -> str:
+        """
+        Encodes the embedding as the base64 of its little endian float32 bytes, about a quarter
+        of the size of the JSON list, as the `encoding_format="base64"` of the OpenAI API.
+
+        Example:
+        ```python
+        vector = np.frombuffer(base64.b64decode(data.embedding_base64()), dtype="<f4")
+        ```
+
+        Raises:
+            ValueError: If the embedding is a multi-vector embedding.
+        """
+
+class WeaviateAdapter:
+    """
+    Stores embeddings in a Weaviate class using the batch API. Available when the package is
+    built with the `weaviate` feature.
+
+    The class is created with the `none` vectorizer if it does not exist, the vectors are
+    supplied by EmbedAnything.
+
+    Example:
+    ```python
+    adapter = WeaviateAdapter("http://localhost:8080", "Document")
+    embed_anything.embed_file("test_files/test.pdf", embedder=model, adapter=adapter)
+    ```
+    """
+
+    def __init__(
+        self,
+        url: str,
+        class_name: str,
+        api_key: str | None = None,
+        batch_size: int | None = None,
+    ):
+        """
+        Connects to Weaviate and creates the class if needed.
+        """
+
+    def upsert(self, data: list[EmbedData]) -> list[tuple[int, str]]:
+        """
+        Inserts the embeddings. `text` and the metadata entries are stored as properties.
+
+        Returns:
+            The objects rejected by Weaviate as (index, error message) pairs.
+        """
+    class_name: str
+
+class ElasticsearchAdapter:
+    """
+    Stores embeddings in an Elasticsearch or OpenSearch index using the `_bulk` API. Available
+    when the package is built with the `elasticsearch` feature.
+
+    The index is created on the first upsert with a vector field sized to the embeddings, a
+    `dense_vector` on Elasticsearch or a `knn_vector` on OpenSearch. `text` and `metadata` are
+    stored as document fields.
+
+    Example:
+    ```python
+    adapter = ElasticsearchAdapter("http://localhost:9200", "documents", api_key="...")
+    embed_anything.embed_file("test_files/test.pdf", embedder=model, adapter=adapter)
+    ```
+    """
+
+    def __init__(
+        self,
+        url: str,
+        index_name: str,
+        api_key: str | None = None,
+        username: str | None = None,
+        password: str | None = None,
+        similarity: str | None = None,
+        vector_field: str | None = None,
+        opensearch: bool = False,
+        batch_size: int | None = None,
+    ):
+        """
+        Creates the adapter. The index is not touched until the first upsert.
+
+        Args:
+            url: The url of the cluster.
+            index_name: The index to write to.
+            api_key: An Elasticsearch API key.
+            username: The user for basic authentication, e.g. on OpenSearch.
+            password: The password for basic authentication.
+            similarity: "cosine" (default), "dot_product" or "l2_norm".
+            vector_field: The field holding the vector, "embedding" by default.
+            opensearch: Whether the cluster is OpenSearch rather than Elasticsearch.
+            batch_size: The number of documents per `_bulk` request, 500 by default.
+        """
+
+    def upsert(self, data: list[EmbedData]) -> list[tuple[int, str]]:
+        """
+        Indexes the embeddings, creating the index first if needed.
+
+        Returns:
+            The documents rejected by the cluster as (index, error message) pairs.
+        """
+
+    def flush(self):
+        """
+        Refreshes the index so that the indexed documents show up in searches. Called once at
+        the end of embedding when passed as an adapter.
+        """
+    index_name: str
+
+class ChromaAdapter:
+    """
+    Stores embeddings in a Chroma collection using the HTTP API. Available when the package is
+    built with the `chroma` feature.
+
+    The collection is created if it does not exist. Records are upserted with the text as the
+    document, the metadata as is and the `id` metadata entry as the id, or a hash of the text
+    and metadata when there is none.
+
+    Example:
+    ```python
+    adapter = ChromaAdapter("http://localhost:8000", "documents")
+    embed_anything.embed_file("test_files/test.pdf", embedder=model, adapter=adapter)
+    ```
+    """
+
+    def __init__(
+        self,
+        host: str,
+        collection_name: str,
+        tenant: str | None = None,
+        database: str | None = None,
+        batch_size: int | None = None,
+    ):
+        """
+        Connects to Chroma and creates the collection if needed.
+
+        Args:
+            host: The url of the Chroma server.
+            collection_name: The collection to write to.
+            tenant: The tenant, "default_tenant" by default.
+            database: The database, "default_database" by default.
+            batch_size: The number of records per upsert request, 1000 by default.
+        """
+
+    def upsert(self, data: list[EmbedData]) -> int:
+        """
+        Upserts the embeddings.
+
+        Returns:
+            The number of records upserted.
+        """
+    collection_name: str
+
+class MilvusAdapter:
+    """
+    Stores embeddings in a Milvus or Zilliz Cloud collection using the v2 RESTful API of
+    Milvus 2.4 and later. Available when the package is built with the `milvus` feature.
+
+    The collection is created on the first upsert with an `id` primary key, a `vector` field
+    sized to the embeddings with an AUTOINDEX index, a `text` field and a `metadata` JSON field.
+    Milvus only stores declared fields, so the metadata is kept as JSON and can be filtered
+    with expressions such as `metadata["file_name"] == "report.pdf"`. Texts longer than 65535
+    bytes are cut. Entities are upserted with the `id` metadata entry as the id, or a hash of
+    the text and metadata when there is none.
+
+    Example:
+    ```python
+    # Milvus standalone started with Docker listens on port 19530.
+    adapter = MilvusAdapter("http://localhost:19530", "documents")
+    embed_anything.embed_file("test_files/test.pdf", embedder=model, adapter=adapter)
+    ```
+    """
+
+    def __init__(
+        self,
+        uri: str,
+        collection_name: str,
+        token: str | None = None,
+        username: str | None = None,
+        password: str | None = None,
+        database: str | None = None,
+        metric_type: str | None = None,
+        batch_size: int | None = None,
+    ):
+        """
+        Creates the adapter. The collection is created on the first upsert if needed.
+
+        Args:
+            uri: The url of the Milvus server, or the public endpoint of a Zilliz Cloud cluster.
+            collection_name: The collection to write to.
+            token: An API key, or "username:password".
+            username: The user name, used with password instead of token.
+            password: The password of username.
+            database: The database, "default" by default.
+            metric_type: The metric of the vector index, one of "cosine", "ip" and "l2". Defaults to "cosine".
+            batch_size: The number of entities per upsert request, 500 by default.
+        """
+
+    def upsert(self, data: list[EmbedData]) -> int:
+        """
+        Upserts the embeddings.
+
+        Returns:
+            The number of entities upserted.
+        """
+    collection_name: str
 
 class ColpaliModel:
     """
@@ -426,23 +1050,85 @@ class TextEmbedConfig:
 
     Attributes:
         chunk_size: The chunk size for the Text Embedding model.
-        batch_size: The batch size for processing the embeddings. Default is 32. Based on the memory, you can increase or decrease the batch size.
-        splitting_strategy: The strategy to use for splitting the text into chunks. Default is "sentence". If semantic splitting is used, semantic_encoder is required.
+        batch_size: The batch size for processing the embeddings. Defaults to the recommended batch size of the model, e.g. 32 for BERT models and 16 for long context models. Based on the memory, you can increase or decrease the batch size.
+        splitting_strategy: The strategy to use for splitting the text into chunks. Default is "sentence". If semantic splitting is used, semantic_encoder is required. Use "code:rust", "code:python", "code:javascript" or "code:typescript" to split source files at function and class boundaries. Use "sliding_window:<window>:<stride>", e.g. "sliding_window:512:256", to split the text into windows of <window> tokens starting every <stride> tokens, with the token span of each window in the "start_token" and "end_token" metadata entries.
         semantic_encoder: The semantic encoder for the Text Embedding model. Default is None.
         use_ocr: A flag indicating whether to use OCR for the Text Embedding model. Default is False.
         tesseract_path: The path to the Tesseract OCR executable. Default is None and uses the system path.
+        language: ISO 639-1 code of the document language, e.g. "fr", "zh" or "ja". Enables Unicode sentence splitting with locale specific rules. Default is None.
+        readability: Only keep the main content (article body) of webpages and HTML files, dropping navigation, footers and ads. The page title is stored in the metadata. Defaults to False.
+        sidecar_metadata: Merge the JSON object in "<file>.meta.json" into the metadata of every chunk of that file. Sidecar values override created/modified but never file_name. Defaults to False.
+        auto_batch_size: Time a few batches of growing size on the first chunks and keep the fastest batch size. batch_size, when set, is the largest size tried. Defaults to False.
+        min_chunk_size: Chunks with fewer tokens than this are merged into the previous chunk or dropped, see min_chunk_policy. Does not apply to code splitting. Defaults to None, which keeps every chunk.
+        min_chunk_policy: "merge" to append small chunks to the previous chunk or "drop" to leave them out. Defaults to "merge".
+        seed: Seed of the temperature fallback sampling of the audio decoder. Embedding is deterministic given the same inputs and model, so this only affects audio transcription. Defaults to None, which keeps the seed of the decoder.
+        output_dtype: "f16" returns the vectors of EmbedData.embedding as the bytes of float16 values, halving their memory. Read them with np.frombuffer(data.embedding, dtype=np.float16). Defaults to None, which returns lists of floats.
+        file_filter: A callable taking a FileMeta and returning False for the files of a directory to skip, before they are read, e.g. lambda meta: meta.size < 10_000_000. Defaults to None, which keeps every file.
+        error_on_unsupported: When embedding a directory, whether to raise on files of a type that can't be embedded, e.g. a .zip matched by the requested extensions, instead of skipping them with a warning. Defaults to False.
+        chunk_records: Split the texts given to embed_records into chunks like the text of a file, instead of embedding every record whole. Defaults to False.
+        max_document_bytes: Files bigger than this many bytes are not embedded. embed_file raises a ValueError for them and embed_directory skips them. Defaults to None, which accepts files of any size.
+        streaming_extraction: Read PDF files one page at a time and embed their chunks buffer_size at a time, handing each batch to the adapter as soon as it is ready. Chunks don't span pages and carry their page_number in the metadata. Their start_char and end_char count from the start of the embedded pages, each trimmed and cleaned, joined by a blank line. Ignored with OCR. Defaults to False.
+        text_cleaning: The cleanup applied to extracted text before chunking, any of "dehyphenate", "collapse_whitespace", "strip_control" and "normalize_unicode". An empty list keeps the text as extracted. Defaults to None, which is ["collapse_whitespace", "strip_control"]. Source code split with "code:<language>" is never cleaned.
+        on_truncation: A callable called with a TruncationEvent for every chunk or query cut by the tokenizer because it is longer than the model accepts, which usually means chunk_size is too large. Only local models report truncation. Defaults to None.
+        store_original_text: Whether the stored text of chunks and queries is the text as given, without the query or document prefix the model embeds them with. Defaults to True.
+        sentence_splitter: How sentences are found by the "sentence" splitting strategy. "regex" is the fastest but also splits after abbreviations such as "Dr.", "unicode" uses Unicode sentence boundaries and keeps known abbreviations, and a callable takes the text and returns its sentences. Defaults to None, which lets the chunker split sentences, or uses "unicode" when a language or an overlap_ratio is set.
+        redact: Masks personal data in the extracted text before it is embedded and stored, any of "emails", "phone_numbers" and "credit_cards", which are replaced with [EMAIL], [PHONE] and [CARD]. Redaction is regex based and best effort, not a compliance guarantee. Defaults to None, which keeps the text as is.
+        document_vector: Whether embed_file also returns one embedding for the whole file, the mean of its chunk embeddings weighted by chunk length and normalized, after the chunk embeddings. Embeddings get a "granularity" metadata entry, "chunk" or "document". Files read with streaming_extraction get no document embedding. Defaults to None, which is False.
+        max_pages: Only embed the first max_pages pages of PDF files. Chunks of a cut document get a "truncated" metadata entry set to "true". Defaults to None, which embeds every page.
+        max_chars: Only embed the first max_chars characters of the extracted text of each file, recorded like max_pages. Ignored with streaming_extraction. Defaults to None, which embeds the whole text.
+        extract_archives: Embed the files inside .zip, .tar.gz and .tgz archives given to embed_file or found by embed_directory, instead of treating archives as unsupported files. Their chunks get archive and archive_path metadata entries. Defaults to False.
+        max_archive_bytes: Archives unpacking to more bytes than this are not embedded, to guard against zip bombs. embed_file raises a ValueError for them and embed_directory skips them. Defaults to None, which allows 1 GiB.
+        table_mode: How the tables of PDF files are written in the text: "linear" writes each row on a line, "markdown" as Markdown tables and "skip" leaves them out. The chunks of a PDF file then get a contains_table metadata entry, which with streaming_extraction tells about the page of the chunk. Ignored with OCR. Defaults to None, which reads PDF files without looking for tables.
+        detect_language: Whether to detect the language of each chunk and store it in the language metadata entry as an ISO 639-3 code, e.g. "eng", with the confidence of the detection. Code chunks keep their programming language. Defaults to False.
+        validate_dimensions: Whether to check that every vector has the dimension of the model. embed_file and embed_records raise a ValueError on the first mismatch, naming the file and chunk, while embed_directory skips the chunk and logs it. Defaults to False.
+        page_range: The first and last PDF pages to embed, both included and counted from 1, e.g. (10, 50) to leave out the front and back matter of a manual. Files with fewer pages raise a ValueError, or are skipped in a directory. The chunks get a page_range metadata entry such as "10-50", and with streaming_extraction the page_number of their page. Other formats are read whole. Defaults to None, which embeds every page.
+        sort_by_length: Whether to embed the texts from the shortest to the longest so that batches waste less compute on padding. Embeddings are returned in the original order. Defaults to False.
+        prepend_filename: Whether to embed the path of each file, relative to the embedded directory, on the line before its first chunk so that queries naming a file find it. The stored text is unchanged and the path is stored in the `file_path` metadata entry. Defaults to False.
+        prepend_filename_to_every_chunk: Whether to embed the path before every chunk of the file instead of only the first one. Defaults to False.
+        devices: Devices to load a copy of the model on, e.g. ["cuda:0", "cuda:1"]. Chunks from embed_directory are spread across the copies. Needs a model loaded with from_pretrained_hf or from_pretrained_onnx. Defaults to None.
     """
 
     def __init__(
         self,
         chunk_size: int | None = 256,
         overlap_ratio: float | None = 0.0,
-        batch_size: int | None = 32,
+        batch_size: int | None = None,
         buffer_size: int | None = 100,
         splitting_strategy: str | None = "sentence",
         semantic_encoder: EmbeddingModel | None = None,
         use_ocr: bool | None = False,
         tesseract_path: str | None = None,
+        language: str | None = None,
+        readability: bool | None = None,
+        sidecar_metadata: bool | None = None,
+        auto_batch_size: bool | None = None,
+        min_chunk_size: int | None = None,
+        min_chunk_policy: str | None = None,
+        seed: int | None = None,
+        output_dtype: str | None = None,
+        file_filter: Callable[[FileMeta], bool] | None = None,
+        error_on_unsupported: bool | None = None,
+        chunk_records: bool | None = None,
+        max_document_bytes: int | None = None,
+        streaming_extraction: bool | None = None,
+        text_cleaning: list[str] | None = None,
+        on_truncation: Callable[[TruncationEvent], None] | None = None,
+        store_original_text: bool | None = None,
+        sentence_splitter: str | Callable[[str], list[str]] | None = None,
+        redact: list[str] | None = None,
+        document_vector: bool | None = None,
+        max_pages: int | None = None,
+        max_chars: int | None = None,
+        extract_archives: bool | None = None,
+        max_archive_bytes: int | None = None,
+        table_mode: str | None = None,
+        detect_language: bool | None = None,
+        validate_dimensions: bool | None = None,
+        page_range: tuple[int, int] | None = None,
+        sort_by_length: bool | None = None,
+        prepend_filename: bool | None = None,
+        prepend_filename_to_every_chunk: bool | None = None,
+        devices: list[str] | None = None,
     ):
         self.chunk_size = chunk_size
         self.overlap_ratio = overlap_ratio
@@ -452,6 +1138,37 @@ class TextEmbedConfig:
         self.semantic_encoder = semantic_encoder
         self.use_ocr = use_ocr
         self.tesseract_path = tesseract_path
+        self.language = language
+        self.readability = readability
+        self.sidecar_metadata = sidecar_metadata
+        self.auto_batch_size = auto_batch_size
+        self.min_chunk_size = min_chunk_size
+        self.min_chunk_policy = min_chunk_policy
+        self.seed = seed
+        self.output_dtype = output_dtype
+        self.file_filter = file_filter
+        self.error_on_unsupported = error_on_unsupported
+        self.chunk_records = chunk_records
+        self.max_document_bytes = max_document_bytes
+        self.streaming_extraction = streaming_extraction
+        self.text_cleaning = text_cleaning
+        self.on_truncation = on_truncation
+        self.store_original_text = store_original_text
+        self.sentence_splitter = sentence_splitter
+        self.redact = redact
+        self.document_vector = document_vector
+        self.max_pages = max_pages
+        self.max_chars = max_chars
+        self.extract_archives = extract_archives
+        self.max_archive_bytes = max_archive_bytes
+        self.table_mode = table_mode
+        self.detect_language = detect_language
+        self.validate_dimensions = validate_dimensions
+        self.page_range = page_range
+        self.sort_by_length = sort_by_length
+        self.prepend_filename = prepend_filename
+        self.prepend_filename_to_every_chunk = prepend_filename_to_every_chunk
+        self.devices = devices
     chunk_size: int | None
     overlap_ratio: float | None
     batch_size: int | None
@@ -460,6 +1177,37 @@ class TextEmbedConfig:
     semantic_encoder: EmbeddingModel | None
     use_ocr: bool | None
     tesseract_path: str | None
+    language: str | None
+    readability: bool | None
+    sidecar_metadata: bool | None
+    auto_batch_size: bool | None
+    min_chunk_size: int | None
+    min_chunk_policy: str | None
+    seed: int | None
+    output_dtype: str | None
+    file_filter: Callable[[FileMeta], bool] | None
+    error_on_unsupported: bool | None
+    chunk_records: bool | None
+    max_document_bytes: int | None
+    streaming_extraction: bool | None
+    text_cleaning: list[str] | None
+    on_truncation: Callable[[TruncationEvent], None] | None
+    store_original_text: bool | None
+    sentence_splitter: str | Callable[[str], list[str]] | None
+    redact: list[str] | None
+    document_vector: bool | None
+    max_pages: int | None
+    max_chars: int | None
+    extract_archives: bool | None
+    max_archive_bytes: int | None
+    table_mode: str | None
+    detect_language: bool | None
+    validate_dimensions: bool | None
+    page_range: tuple[int, int] | None
+    sort_by_length: bool | None
+    prepend_filename: bool | None
+    prepend_filename_to_every_chunk: bool | None
+    devices: list[str] | None
 
 class ImageEmbedConfig:
     """
@@ -467,11 +1215,50 @@ class ImageEmbedConfig:
 
     Attributes:
         buffer_size: The buffer size for the Image Embedding model. Default is 100.
+        decode_threads: The number of threads decoding images while the model embeds the ones already decoded. Default is one thread per CPU.
+        file_filter: A callable taking a FileMeta and returning False for the images to skip, before they are decoded. Defaults to None, which keeps every image.
     """
 
-    def __init__(self, buffer_size: int | None = None):
+    def __init__(
+        self,
+        buffer_size: int | None = None,
+        decode_threads: int | None = None,
+        file_filter: Callable[[FileMeta], bool] | None = None,
+    ):
         self.buffer_size = buffer_size
+        self.decode_threads = decode_threads
+        self.file_filter = file_filter
     buffer_size: int | None
+    decode_threads: int | None
+    file_filter: Callable[[FileMeta], bool] | None
+
+class FileMeta:
+    """
+    A file of a directory, passed to the file_filter of TextEmbedConfig and ImageEmbedConfig.
+
+    Attributes:
+        path: The path of the file.
+        size: The size of the file in bytes.
+        modified: The modification time in seconds since the epoch, None if the platform does not record it.
+    """
+
+    path: str
+    size: int
+    modified: float | None
+
+class TruncationEvent:
+    """
+    A text longer than the model accepts, passed to the on_truncation of TextEmbedConfig.
+
+    Attributes:
+        text_len: The length of the text in characters.
+        max_length: The number of tokens the model reads. The rest of the text is not embedded.
+        file: The file the text was read from, None for queries and records.
+    """
+
+    text_len: int
+    max_length: int
+    file: str | None
 
 class EmbeddingModel:
     """
@@ -479,7 +1266,13 @@ class EmbeddingModel:
     """
 
     def from_pretrained_hf(
-        model: WhichModel, model_id: str, revision: str | None = None, token: str | None = None
+        model: WhichModel,
+        model_id: str,
+        revision: str | None = None,
+        token: str | None = None,
+        cache_dir: str | None = None,
+        add_special_tokens: bool | None = None,
+        model_dtype: str | None = None,
     ) -> EmbeddingModel:
         """
         Loads an embedding model from the Hugging Face model hub.
@@ -488,6 +1281,9 @@ class EmbeddingModel:
             model_id: The ID of the model.
             revision: The revision of the model.
             token: The Hugging Face token.
+            cache_dir: The directory the model is downloaded to and loaded from, e.g. a shared mount. Defaults to $HF_HOME/hub, or ~/.cache/huggingface/hub.
+            add_special_tokens: Whether the tokenizer wraps texts in the special tokens of the model, e.g. [CLS] text [SEP]. Query and document prefixes are added inside them. Set it to False for models that expect no special tokens, such as some instruction models. Defaults to True.
+            model_dtype: The precision the weights of Bert models are loaded in, "f32", "f16" or "bf16". f16 and bf16 halve the memory of large models on GPUs, the embeddings are still returned in f32. bf16 falls back to f32 on the CPU. Defaults to the precision of the saved weights on GPUs and to f32 on the CPU.
         Returns:
             An EmbeddingModel object.
 
@@ -501,20 +1297,65 @@ class EmbeddingModel:
 
         """
 
+    def from_local_dir(
+        model: WhichModel,
+        path: str,
+        add_special_tokens: bool | None = None,
+        model_dtype: str | None = None,
+    ) -> EmbeddingModel:
+        """
+        Loads an embedding model from a local directory without contacting the Hugging Face Hub,
+        e.g. on an air-gapped machine or for a fine-tuned model.
+
+        Attributes:
+            model (WhichModel): The architecture of the model, WhichModel.Bert, WhichModel.Jina or WhichModel.Clip.
+            path (str): The directory holding config.json, tokenizer.json and the weights as model.safetensors or pytorch_model.bin, laid out like a model repository.
+            add_special_tokens (bool | None, optional): Whether the tokenizer wraps texts in the special tokens of the model, e.g. [CLS] text [SEP]. Query and document prefixes are added inside them. Set it to False for models that expect no special tokens, such as some instruction models. Defaults to True.
+            model_dtype (str | None, optional): The precision the weights of Bert models are loaded in, "f32", "f16" or "bf16". f16 and bf16 halve the memory of large models on GPUs, the embeddings are still returned in f32. bf16 falls back to f32 on the CPU. Defaults to the precision of the saved weights on GPUs and to f32 on the CPU.
+
+        Returns:
+            EmbeddingModel: An initialized EmbeddingModel object.
+
+        Raises:
+            ValueError: If the model is not supported or files are missing from the directory.
+
+        Example:
+        ```python
+        model = EmbeddingModel.from_local_dir(
+            model=WhichModel.Bert,
+            path="/models/my-fine-tuned-bert"
+        )
+        ```
+        """
+
     def from_pretrained_cloud(
-        model: WhichModel, model_id: str, api_key: str | None = None
+        model: WhichModel,
+        model_id: str,
+        api_key: str | None = None,
+        output_dimensionality: int | None = None,
+        extra_headers: dict[str, str] | None = None,
+        proxy: str | None = None,
+        max_total_tokens: int | None = None,
     ) -> EmbeddingModel:
         """
         Loads an embedding model from a cloud-based service.
 
         Attributes:
-            model (WhichModel): The cloud service to use. Currently supports WhichModel.OpenAI and WhichModel.Cohere.
+            model (WhichModel): The cloud service to use. Currently supports WhichModel.OpenAI, WhichModel.Cohere, WhichModel.Gemini and WhichModel.Mistral.
             model_id (str): The ID of the model to use.
                 - For OpenAI, see available models at https://platform.openai.com/docs/guides/embeddings/embedding-models
                 - For Cohere, see available models at https://docs.cohere.com/docs/cohere-embed
+                - For Gemini, see available models at https://ai.google.dev/gemini-api/docs/models#text-embedding
+                - For Mistral, use "mistral-embed"
             api_key (str | None, optional): The API key for accessing the model. If not provided, it is taken from the environment variable:
                 - For OpenAI: OPENAI_API_KEY
                 - For Cohere: CO_API_KEY
+                - For Gemini: GEMINI_API_KEY
+                - For Mistral: MISTRAL_API_KEY
+            output_dimensionality (int | None, optional): Size of the returned embeddings. Only used by Gemini.
+            extra_headers (dict[str, str] | None, optional): Headers added to every request, e.g. the headers required by an API gateway.
+            proxy (str | None, optional): An http or https proxy URL for the requests, e.g. "http://proxy.internal:3128". Hosts listed in NO_PROXY bypass it. Defaults to the HTTPS_PROXY and HTTP_PROXY environment variables. Models downloaded from Hugging Face always use the environment variables.
+            max_total_tokens (int | None, optional): The most tokens the model may send over its lifetime, to avoid surprise bills. Texts are sent in requests of the largest batch size of the provider, and the embedding functions raise a BudgetExceededError once the next request would go over the budget. Requests are charged with the tokens reported by the API, or for Gemini, which reports none, an estimate of 4 characters per token. Defaults to no limit.
 
         Returns:
             EmbeddingModel: An initialized EmbeddingModel object.
@@ -545,6 +1386,9 @@ class EmbeddingModel:
         revision: Optional[str] | None = None,
         dtype: Optional[Dtype] | None = None,
         path_in_repo: Optional[str] | None = None,
+        cache_dir: Optional[str] | None = None,
+        session_pool_size: Optional[int] | None = None,
+        add_special_tokens: Optional[bool] | None = None,
     ) -> EmbeddingModel:
         """
         Loads an ONNX embedding model.
@@ -555,7 +1399,10 @@ class EmbeddingModel:
             hf_model_id (str | None, optional): The ID of the model from Hugging Face. Defaults to None.
             revision (str | None, optional): The revision of the model. Defaults to None.
             dtype (Dtype | None, optional): The dtype of the model. Defaults to None.
-            path_in_repo (str | None, optional): The path to the model in the repository. Defaults to None.
+            path_in_repo (str | None, optional): The ONNX file to load from the repository, e.g. "onnx/model.onnx". When given it is used as is and dtype does not change the file name. Defaults to None.
+            cache_dir (str | None, optional): The directory the model is downloaded to and loaded from, e.g. a shared mount. Defaults to $HF_HOME/hub, or ~/.cache/huggingface/hub.
+            session_pool_size (int | None, optional): The number of ONNX Runtime sessions to load, so that as many concurrent calls to the model, e.g. from the threads of a server, run in parallel. Each session holds a copy of the weights. Defaults to 1.
+            add_special_tokens (bool | None, optional): Whether the tokenizer wraps texts in the special tokens of the model, e.g. [CLS] text [SEP]. Query and document prefixes are added inside them. Set it to False for models that expect no special tokens, such as some instruction models. Defaults to True.
         Returns:
             EmbeddingModel: An initialized EmbeddingModel object.
 
@@ -563,8 +1410,8 @@ class EmbeddingModel:
             - model_name
             - hf_model_id
 
-        If hf_model_id is provided, dtype is ignored and the path_in_repo has to be provided pointing to the model file in the repository.
-        If model_name is provided, dtype is used to determine the model file to load.
+        If path_in_repo is provided, it is loaded as is and dtype is ignored.
+        Otherwise dtype is used to determine the model file to load, e.g. "onnx/model_fp16.onnx" for Dtype.F16.
 
         Example:
         ```python
@@ -587,6 +1434,202 @@ class EmbeddingModel:
         scenarios where performance is critical.
         """
 
+    def self_check(self) -> SelfCheckReport:
+        """
+        Embeds a few fixed sentences and checks that the model produces one embedding per
+        sentence, with a consistent dimension, no NaN or Inf values, and different vectors
+        for different sentences. Useful to validate a model at startup.
+
+        Returns:
+            SelfCheckReport: Summary of the produced embeddings.
+
+        Raises:
+            ValueError: If any of the checks fails.
+        """
+
+    def encode_image_and_text_pairs(
+        self, images: list[str], labels: list[str]
+    ) -> np.ndarray:
+        """
+        Scores every image against every text label with a vision model such as CLIP, e.g. for
+        zero-shot image classification. Requires numpy.
+
+        Args:
+            images: The paths of the images.
+            labels: The text labels, e.g. "a photo of a cat".
+
+        Returns:
+            A float32 array of shape (len(images), len(labels)) holding the cosine similarity of
+            each image and label. The best label of an image is the argmax of its row.
+
+        Raises:
+            ValueError: If the model is not a vision model.
+
+        Example:
+        ```python
+        model = EmbeddingModel.from_pretrained_hf(WhichModel.Clip, model_id="openai/clip-vit-base-patch32")
+        labels = ["a photo of a cat", "a photo of a dog"]
+        scores = model.encode_image_and_text_pairs(["pet.jpg"], labels)
+        print(labels[scores[0].argmax()])
+        ```
+        """
+
+    def model_id(self) -> str:
+        """
+        Returns the Hugging Face model id, or the model name of cloud models. Embeddings
+        record it in their `embedding_model` metadata entry.
+        """
+
+    def revision(self) -> str | None:
+        """
+        Returns the revision the model was loaded from, None for the default branch and
+        cloud models.
+        """
+
+    def metadata(self) -> ModelMetadata:
+        """
+        Returns a description of the model, to record next to stored vectors.
+        """
+
+class ModelRegistry:
+    """
+    Several models served by name, for servers where each request picks its model. Models are
+    loaded on first use, and once `max_models` models are loaded, loading one more evicts the
+    least recently used one, which is loaded again on its next use.
+
+    Example:
+    ```python
+    registry = ModelRegistry(max_models=2)
+    registry.register(
+        "minilm",
+        lambda: EmbeddingModel.from_pretrained_hf(
+            WhichModel.Bert, model_id="sentence-transformers/all-MiniLM-L6-v2"
+        ),
+    )
+    embeddings = registry.embed_query("minilm", ["What is Rust?"])
+    ```
+
+    Attributes:
+        registered: The names of the registered models, sorted.
+        loaded: The names of the loaded models, from the least to the most recently used.
+        max_models: The most models kept loaded, None for no limit.
+    """
+
+    def __init__(self, max_models: int | None = None): ...
+    def register(self, name: str, loader: Callable[[], EmbeddingModel]) -> None:
+        """
+        Registers a model under `name`, loaded by calling `loader` on its first use. Replaces
+        the model registered under the same name.
+        """
+
+    def get(self, name: str) -> EmbeddingModel:
+        """
+        Returns the model registered under `name`, loading it if needed.
+
+        Raises:
+            ValueError: If no model is registered under `name` or it fails to load.
+        """
+
+    def embed_query(
+        self, name: str, query: list[str], config: TextEmbedConfig | None = None
+    ) -> list[EmbedData]:
+        """
+        Embeds the queries with the model registered under `name`, see `embed_query`.
+        """
+
+    def unload(self, name: str) -> bool:
+        """
+        Unloads the model registered under `name`, which stays registered. Returns whether it
+        was loaded.
+        """
+    registered: list[str]
+    loaded: list[str]
+    max_models: int | None
+
+class ModelMetadata:
+    """
+    Describes the model behind an EmbeddingModel.
+
+    Attributes:
+        architecture: The architecture, e.g. "bert" or "clip", or the provider of cloud models, e.g. "openai".
+        model_id: The Hugging Face model id, or the model name of cloud models.
+        revision: The revision the model was loaded from.
+        dimension: The size of the vectors, None when it is only known after embedding.
+        pooling: How token embeddings are pooled, e.g. "mean" or "cls", None for models that do not pool.
+        normalized: Whether the vectors are L2-normalized.
+    """
+
+    architecture: str
+    model_id: str
+    revision: str | None
+    dimension: int | None
+    pooling: str | None
+    normalized: bool
+
+class SelfCheckReport:
+    """
+    Represents the result of EmbeddingModel.self_check.
+
+    Attributes:
+        dimension: The size of the embedding vectors.
+        vectors_per_text: The number of vectors per text, greater than 1 for multi-vector models.
+        unit_norm: Whether all vectors have a unit L2 norm.
+        similarity: The cosine similarity between the two check sentences.
+    """
+
+    dimension: int
+    vectors_per_text: int
+    unit_norm: bool
+    similarity: float
+
+class ChunkPreview:
+    """
+    Represents a chunk returned by chunk_preview.
+
+    Attributes:
+        text: The text of the chunk.
+        token_count: The number of tokens in the chunk, counted with the chunking tokenizer.
+        file: The file the chunk comes from.
+        chunk_index: The position of the chunk within its file.
+    """
+
+    text: str
+    token_count: int
+    file: str
+    chunk_index: int
+
+class Chunk:
+    """
+    Represents a chunk returned by chunk_text.
+
+    Attributes:
+        text: The text of the chunk.
+        token_count: The number of tokens in the chunk, counted with the chunking tokenizer.
+        start_char: The character offset of the chunk in the text, None when it could not be found.
+        end_char: The character offset just past the end of the chunk.
+        metadata: The symbol and language of code chunks, the token span of sliding windows and the detected language.
+    """
+
+    text: str
+    token_count: int
+    start_char: int | None
+    end_char: int | None
+    metadata: dict[str, str]
+
+class SearchResult:
+    """
+    Represents a chunk returned by similarity_search.
+
+    Attributes:
+        text: The text of the chunk.
+        score: The cosine similarity of the chunk with the query.
+        metadata: The metadata of the chunk, including the file it comes from.
+    """
+
+    text: str | None
+    score: float
+    metadata: dict[str, str] | None
+
 class AudioDecoderModel:
     """
     Represents an audio decoder model.
@@ -596,6 +1639,19 @@ class AudioDecoderModel:
         revision: The revision of the audio decoder model.
         model_type: The type of the audio decoder model.
         quantized: A flag indicating whether the audio decoder model is quantized or not.
+        vad: Cut the audio on silences before transcription, so that every segment is an
+            utterance instead of a fixed 30s window. Defaults to False.
+        vad_sensitivity: Between 0 and 1. Higher values count quieter sounds as speech. Defaults
+            to 0.5.
+        beam_size: Number of candidate transcripts kept by beam search. 1 is greedy decoding.
+            Defaults to 1.
+        temperature: Temperature of the first decoding of a segment. Defaults to 0.
+        temperature_increment: Added to the temperature every time the decoding of an unlikely
+            segment is retried, up to 1. 0 disables the retries. Defaults to 0.2.
+        no_speech_threshold: Segments more likely than this to hold no speech are skipped.
+            Defaults to 0.6.
+        logprob_threshold: Decoding is retried at a higher temperature when the average log
+            probability of the tokens is lower. Defaults to -1.
 
     Example:
     ```python
@@ -604,7 +1660,9 @@ class AudioDecoderModel:
         model_id="openai/whisper-tiny.en",
         revision="main",
         model_type="tiny-en",
-        quantized=False
+        quantized=False,
+        vad=True,
+        beam_size=5,
     )
     ```
     """
@@ -613,17 +1671,33 @@ class AudioDecoderModel:
     revision: str
     model_type: str
     quantized: bool
+    vad: bool
+    vad_sensitivity: float
+    beam_size: int
+    temperature: float
+    temperature_increment: float
+    no_speech_threshold: float
+    logprob_threshold: float
 
     def from_pretrained_hf(
         model_id: str | None = None,
         revision: str | None = None,
         model_type: str | None = None,
         quantized: bool | None = None,
+        vad: bool | None = None,
+        vad_sensitivity: float | None = None,
+        beam_size: int | None = None,
+        temperature: float | None = None,
+        temperature_increment: float | None = None,
+        no_speech_threshold: float | None = None,
+        logprob_threshold: float | None = None,
     ): ...
 
 class WhichModel(Enum):
     OpenAI = ("OpenAI",)
     Cohere = ("Cohere",)
+    Gemini = ("Gemini",)
+    Mistral = ("Mistral",)
     Bert = ("Bert",)
     Jina = ("Jina",)
     Clip = ("Clip",)
@@ -671,6 +1745,10 @@ class ONNXModel(Enum):
     | `JINAV3`                         | jinaai/jina-embeddings-v3                        |
     | `SPLADEPPENV1`                   | prithivida/Splade_PP_en_v1                      |
     | `SPLADEPPENV2`                   | prithivida/Splade_PP_en_v2                      |
+    | `SnowflakeArcticEmbedXS`         | Snowflake/snowflake-arctic-embed-xs              |
+    | `SnowflakeArcticEmbedS`          | Snowflake/snowflake-arctic-embed-s               |
+    | `SnowflakeArcticEmbedM`          | Snowflake/snowflake-arctic-embed-m               |
+    | `SnowflakeArcticEmbedL`          | Snowflake/snowflake-arctic-embed-l               |
     ```
     """
 
@@ -739,3 +1817,11 @@ class ONNXModel(Enum):
     SPLADEPPENV1 = "SPLADEPPENV1"
 
     SPLADEPPENV2 = "SPLADEPPENV2"
+
+    SnowflakeArcticEmbedXS = "SnowflakeArcticEmbedXS"
+
+    SnowflakeArcticEmbedS = "SnowflakeArcticEmbedS"
+
+    SnowflakeArcticEmbedM = "SnowflakeArcticEmbedM"
+
+    SnowflakeArcticEmbedL = "SnowflakeArcticEmbedL"
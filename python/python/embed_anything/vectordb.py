@@ -26,3 +26,6 @@ class Adapter(ABC):
     def upsert(self, data: List[Dict]):
         data = self.convert(data)
         pass
+
+    def flush(self):
+        pass
@@ -0,0 +1,49 @@
+use pyo3::exceptions::PyValueError;
+use pyo3::prelude::*;
+
+use crate::EmbedData;
+
+#[pyclass]
+pub struct ChromaAdapter {
+    pub inner: embed_anything::adapters::chroma::ChromaAdapter,
+}
+
+#[pymethods]
+impl ChromaAdapter {
+    #[new]
+    #[pyo3(signature = (host, collection_name, tenant=None, database=None, batch_size=None))]
+    fn new(
+        host: &str,
+        collection_name: &str,
+        tenant: Option<&str>,
+        database: Option<&str>,
+        batch_size: Option<usize>,
+    ) -> PyResult<Self> {
+        let mut inner = embed_anything::adapters::chroma::ChromaAdapter::new(
+            host,
+            collection_name,
+            tenant,
+            database,
+        )
+        .map_err(|e| PyValueError::new_err(e.to_string()))?;
+        if let Some(batch_size) = batch_size {
+            inner = inner.with_batch_size(batch_size);
+        }
+        Ok(Self { inner })
+    }
+
+    /// Upserts the embeddings and returns the number of records upserted.
+    fn upsert(&self, data: Vec<PyRef<EmbedData>>) -> PyResult<usize> {
+        let data = data.iter().map(|d| d.inner.clone()).collect::<Vec<_>>();
+        let report = self
+            .inner
+            .upsert(data)
+            .map_err(|e| PyValueError::new_err(e.to_string()))?;
+        Ok(report.inserted)
+    }
+
+    #[getter(collection_name)]
+    fn collection_name(&self) -> String {
+        self.inner.collection_name().to_string()
+    }
+}
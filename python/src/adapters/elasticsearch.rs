@@ -0,0 +1,78 @@
+use pyo3::exceptions::PyValueError;
+use pyo3::prelude::*;
+
+use embed_anything::adapters::elasticsearch::{Engine, Similarity};
+
+use crate::EmbedData;
+
+#[pyclass]
+pub struct ElasticsearchAdapter {
+    pub inner: embed_anything::adapters::elasticsearch::ElasticsearchAdapter,
+}
+
+#[pymethods]
+impl ElasticsearchAdapter {
+    #[new]
+    #[pyo3(signature = (url, index_name, api_key=None, username=None, password=None, similarity=None, vector_field=None, opensearch=false, batch_size=None))]
+    #[allow(clippy::too_many_arguments)]
+    fn new(
+        url: &str,
+        index_name: &str,
+        api_key: Option<String>,
+        username: Option<&str>,
+        password: Option<&str>,
+        similarity: Option<&str>,
+        vector_field: Option<&str>,
+        opensearch: bool,
+        batch_size: Option<usize>,
+    ) -> PyResult<Self> {
+        let mut inner = embed_anything::adapters::elasticsearch::ElasticsearchAdapter::new(
+            url, index_name, api_key,
+        );
+        if let Some(username) = username {
+            inner = inner.with_basic_auth(username, password.unwrap_or_default());
+        }
+        if let Some(similarity) = similarity {
+            let similarity = similarity
+                .parse::<Similarity>()
+                .map_err(|e| PyValueError::new_err(e.to_string()))?;
+            inner = inner.with_similarity(similarity);
+        }
+        if let Some(vector_field) = vector_field {
+            inner = inner.with_vector_field(vector_field);
+        }
+        if opensearch {
+            inner = inner.with_engine(Engine::OpenSearch);
+        }
+        if let Some(batch_size) = batch_size {
+            inner = inner.with_batch_size(batch_size);
+        }
+        Ok(Self { inner })
+    }
+
+    /// Indexes the embeddings and returns the documents the cluster rejected as (index, message) pairs.
+    fn upsert(&self, data: Vec<PyRef<EmbedData>>) -> PyResult<Vec<(usize, String)>> {
+        let data = data.iter().map(|d| d.inner.clone()).collect::<Vec<_>>();
+        let report = self
+            .inner
+            .upsert(data)
+            .map_err(|e| PyValueError::new_err(e.to_string()))?;
+        Ok(report
+            .errors
+            .into_iter()
+            .map(|error| (error.index, error.message))
+            .collect())
+    }
+
+    /// Refreshes the index so that the indexed documents show up in searches.
+    fn flush(&self) -> PyResult<()> {
+        self.inner
+            .refresh()
+            .map_err(|e| PyValueError::new_err(e.to_string()))
+    }
+
+    #[getter(index_name)]
+    fn index_name(&self) -> String {
+        self.inner.index_name().to_string()
+    }
+}
@@ -0,0 +1,62 @@
+use pyo3::exceptions::PyValueError;
+use pyo3::prelude::*;
+
+use embed_anything::adapters::milvus::MetricType;
+
+use crate::EmbedData;
+
+#[pyclass]
+pub struct MilvusAdapter {
+    pub inner: embed_anything::adapters::milvus::MilvusAdapter,
+}
+
+#[pymethods]
+impl MilvusAdapter {
+    #[new]
+    #[pyo3(signature = (uri, collection_name, token=None, username=None, password=None, database=None, metric_type=None, batch_size=None))]
+    #[allow(clippy::too_many_arguments)]
+    fn new(
+        uri: &str,
+        collection_name: &str,
+        token: Option<String>,
+        username: Option<&str>,
+        password: Option<&str>,
+        database: Option<&str>,
+        metric_type: Option<&str>,
+        batch_size: Option<usize>,
+    ) -> PyResult<Self> {
+        let mut inner =
+            embed_anything::adapters::milvus::MilvusAdapter::new(uri, collection_name, token);
+        if let Some(username) = username {
+            inner = inner.with_basic_auth(username, password.unwrap_or_default());
+        }
+        if let Some(database) = database {
+            inner = inner.with_database(database);
+        }
+        if let Some(metric_type) = metric_type {
+            let metric_type = metric_type
+                .parse::<MetricType>()
+                .map_err(|e| PyValueError::new_err(e.to_string()))?;
+            inner = inner.with_metric_type(metric_type);
+        }
+        if let Some(batch_size) = batch_size {
+            inner = inner.with_batch_size(batch_size);
+        }
+        Ok(Self { inner })
+    }
+
+    /// Upserts the embeddings and returns the number of entities upserted.
+    fn upsert(&self, data: Vec<PyRef<EmbedData>>) -> PyResult<usize> {
+        let data = data.iter().map(|d| d.inner.clone()).collect::<Vec<_>>();
+        let report = self
+            .inner
+            .upsert(data)
+            .map_err(|e| PyValueError::new_err(e.to_string()))?;
+        Ok(report.inserted)
+    }
+
+    #[getter(collection_name)]
+    fn collection_name(&self) -> String {
+        self.inner.collection_name().to_string()
+    }
+}
@@ -0,0 +1,8 @@
+#[cfg(feature = "weaviate")]
+pub mod weaviate;
+#[cfg(feature = "elasticsearch")]
+pub mod elasticsearch;
+#[cfg(feature = "chroma")]
+pub mod chroma;
+#[cfg(feature = "milvus")]
+pub mod milvus;
@@ -0,0 +1,48 @@
+use pyo3::exceptions::PyValueError;
+use pyo3::prelude::*;
+
+use crate::EmbedData;
+
+#[pyclass]
+pub struct WeaviateAdapter {
+    pub inner: embed_anything::adapters::weaviate::WeaviateAdapter,
+}
+
+#[pymethods]
+impl WeaviateAdapter {
+    #[new]
+    #[pyo3(signature = (url, class_name, api_key=None, batch_size=None))]
+    fn new(
+        url: &str,
+        class_name: &str,
+        api_key: Option<String>,
+        batch_size: Option<usize>,
+    ) -> PyResult<Self> {
+        let mut inner =
+            embed_anything::adapters::weaviate::WeaviateAdapter::new(url, class_name, api_key)
+                .map_err(|e| PyValueError::new_err(e.to_string()))?;
+        if let Some(batch_size) = batch_size {
+            inner = inner.with_batch_size(batch_size);
+        }
+        Ok(Self { inner })
+    }
+
+    /// Inserts the embeddings and returns the objects Weaviate rejected as (index, message) pairs.
+    fn upsert(&self, data: Vec<PyRef<EmbedData>>) -> PyResult<Vec<(usize, String)>> {
+        let data = data.iter().map(|d| d.inner.clone()).collect::<Vec<_>>();
+        let report = self
+            .inner
+            .upsert(data)
+            .map_err(|e| PyValueError::new_err(e.to_string()))?;
+        Ok(report
+            .errors
+            .into_iter()
+            .map(|error| (error.index, error.message))
+            .collect())
+    }
+
+    #[getter(class_name)]
+    fn class_name(&self) -> String {
+        self.inner.class_name().to_string()
+    }
+}
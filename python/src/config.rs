@@ -1,10 +1,128 @@
 use std::sync::Arc;
 
-use embed_anything::text_loader::SplittingStrategy;
+use embed_anything::chunkers::code::CodeLanguage;
+use embed_anything::chunkers::sentence::SentenceBackend;
+use embed_anything::embeddings::embed::OutputDtype;
+use embed_anything::embeddings::utils::TruncationCallback;
+use embed_anything::file_loader::FileFilter;
+use embed_anything::file_processor::cleaning::CleaningOptions;
+use embed_anything::file_processor::pdf_tables::TableMode;
+use embed_anything::file_processor::redaction::RedactionConfig;
+use embed_anything::text_loader::{MinChunkPolicy, SplittingStrategy};
 use pyo3::prelude::*;
 
 use crate::EmbeddingModel;
 
+/// The file passed to a `file_filter` callable.
+#[pyclass]
+pub struct FileMeta {
+    #[pyo3(get)]
+    pub path: String,
+    #[pyo3(get)]
+    pub size: u64,
+    /// Modification time in seconds since the epoch.
+    #[pyo3(get)]
+    pub modified: Option<f64>,
+}
+
+#[pymethods]
+impl FileMeta {
+    fn __repr__(&self) -> String {
+        format!("FileMeta(path={:?}, size={})", self.path, self.size)
+    }
+}
+
+/// Wraps a Python callable taking a [FileMeta] and returning a bool. Files are kept when the
+/// callable raises, the error is printed.
+fn file_filter(callable: PyObject) -> FileFilter {
+    Arc::new(move |meta: &embed_anything::file_loader::FileMeta| {
+        Python::with_gil(|py| {
+            let meta = FileMeta {
+                path: meta.path.to_string_lossy().to_string(),
+                size: meta.size,
+                modified: meta
+                    .modified
+                    .and_then(|modified| modified.duration_since(std::time::UNIX_EPOCH).ok())
+                    .map(|modified| modified.as_secs_f64()),
+            };
+            match callable
+                .call1(py, (meta,))
+                .and_then(|keep| keep.extract::<bool>(py))
+            {
+                Ok(keep) => keep,
+                Err(e) => {
+                    e.print(py);
+                    true
+                }
+            }
+        })
+    })
+}
+
+/// The text passed to an `on_truncation` callable.
+#[pyclass]
+pub struct TruncationEvent {
+    /// The length of the text in characters.
+    #[pyo3(get)]
+    pub text_len: usize,
+    #[pyo3(get)]
+    pub max_length: usize,
+    #[pyo3(get)]
+    pub file: Option<String>,
+}
+
+#[pymethods]
+impl TruncationEvent {
+    fn __repr__(&self) -> String {
+        format!(
+            "TruncationEvent(text_len={}, max_length={}, file={:?})",
+            self.text_len, self.max_length, self.file
+        )
+    }
+}
+
+/// Wraps a Python callable taking a [TruncationEvent]. Errors raised by the callable are
+/// printed.
+fn on_truncation(callable: PyObject) -> TruncationCallback {
+    Arc::new(
+        move |event: &embed_anything::embeddings::utils::TruncationEvent| {
+            Python::with_gil(|py| {
+                let event = TruncationEvent {
+                    text_len: event.text_len,
+                    max_length: event.max_length,
+                    file: event.file.clone(),
+                };
+                if let Err(e) = callable.call1(py, (event,)) {
+                    e.print(py);
+                }
+            })
+        },
+    )
+}
+
+/// Reads a `sentence_splitter`, either "regex", "unicode" or a callable taking a str and
+/// returning a list of sentences. The whole text is kept as one sentence when the callable
+/// raises, the error is printed.
+fn sentence_splitter(py: Python<'_>, splitter: PyObject) -> Option<SentenceBackend> {
+    if let Ok(name) = splitter.extract::<String>(py) {
+        return name.parse().ok();
+    }
+    Some(SentenceBackend::custom(move |text: &str| {
+        Python::with_gil(|py| {
+            match splitter
+                .call1(py, (text,))
+                .and_then(|sentences| sentences.extract::<Vec<String>>(py))
+            {
+                Ok(sentences) => sentences,
+                Err(e) => {
+                    e.print(py);
+                    vec![text.to_string()]
+                }
+            }
+        })
+    }))
+}
+
 #[pyclass]
 #[derive(Default)]
 pub struct TextEmbedConfig {
@@ -14,7 +132,7 @@ pub struct TextEmbedConfig {
 #[pymethods]
 impl TextEmbedConfig {
     #[new]
-    #[pyo3(signature = (chunk_size=None, batch_size=None, buffer_size=None, overlap_ratio=None, splitting_strategy=None, semantic_encoder=None, use_ocr=None, tesseract_path=None))]
+    #[pyo3(signature = (chunk_size=None, batch_size=None, buffer_size=None, overlap_ratio=None, splitting_strategy=None, semantic_encoder=None, use_ocr=None, tesseract_path=None, language=None, readability=None, sidecar_metadata=None, auto_batch_size=None, min_chunk_size=None, min_chunk_policy=None, seed=None, output_dtype=None, file_filter=None, error_on_unsupported=None, chunk_records=None, max_document_bytes=None, streaming_extraction=None, text_cleaning=None, on_truncation=None, store_original_text=None, sentence_splitter=None, redact=None, document_vector=None, max_pages=None, max_chars=None, extract_archives=None, max_archive_bytes=None, table_mode=None, detect_language=None, validate_dimensions=None, page_range=None, sort_by_length=None, prepend_filename=None, prepend_filename_to_every_chunk=None, devices=None))]
     pub fn new(
         chunk_size: Option<usize>,
         batch_size: Option<usize>,
@@ -24,11 +142,60 @@ impl TextEmbedConfig {
         semantic_encoder: Option<&EmbeddingModel>,
         use_ocr: Option<bool>,
         tesseract_path: Option<&str>,
+        language: Option<&str>,
+        readability: Option<bool>,
+        sidecar_metadata: Option<bool>,
+        auto_batch_size: Option<bool>,
+        min_chunk_size: Option<usize>,
+        min_chunk_policy: Option<&str>,
+        seed: Option<u64>,
+        output_dtype: Option<&str>,
+        file_filter: Option<PyObject>,
+        error_on_unsupported: Option<bool>,
+        chunk_records: Option<bool>,
+        max_document_bytes: Option<u64>,
+        streaming_extraction: Option<bool>,
+        text_cleaning: Option<Vec<String>>,
+        on_truncation: Option<PyObject>,
+        store_original_text: Option<bool>,
+        sentence_splitter: Option<PyObject>,
+        redact: Option<Vec<String>>,
+        document_vector: Option<bool>,
+        max_pages: Option<usize>,
+        max_chars: Option<usize>,
+        extract_archives: Option<bool>,
+        max_archive_bytes: Option<u64>,
+        table_mode: Option<&str>,
+        detect_language: Option<bool>,
+        validate_dimensions: Option<bool>,
+        page_range: Option<(usize, usize)>,
+        sort_by_length: Option<bool>,
+        prepend_filename: Option<bool>,
+        prepend_filename_to_every_chunk: Option<bool>,
+        devices: Option<Vec<String>>,
     ) -> Self {
         let strategy = match splitting_strategy {
             Some(strategy) => match strategy {
                 "sentence" => Some(SplittingStrategy::Sentence),
                 "semantic" => Some(SplittingStrategy::Semantic),
+                // e.g. "code:rust" or "code:python"
+                code if code.starts_with("code:") => code[5..]
+                    .parse::<CodeLanguage>()
+                    .ok()
+                    .map(|language| SplittingStrategy::Code { language }),
+                // e.g. "sliding_window:512:256" for windows of 512 tokens every 256 tokens
+                window if window.starts_with("sliding_window:") => {
+                    let sizes = window[15..].split_once(':');
+                    match sizes.map(|(window, stride)| (window.parse(), stride.parse())) {
+                        Some((Ok(window_tokens), Ok(stride_tokens))) => {
+                            Some(SplittingStrategy::SlidingWindow {
+                                window_tokens,
+                                stride_tokens,
+                            })
+                        }
+                        _ => None,
+                    }
+                }
                 _ => None,
             },
             None => None,
@@ -37,15 +204,68 @@ impl TextEmbedConfig {
         if matches!(strategy, Some(SplittingStrategy::Semantic)) && semantic_encoder.is_none() {
             panic!("Semantic encoder is required when using Semantic splitting strategy");
         }
-        Self {
-            inner: embed_anything::config::TextEmbedConfig::default()
-                .with_chunk_size(chunk_size.unwrap_or(256), overlap_ratio)
-                .with_batch_size(batch_size.unwrap_or(32))
-                .with_buffer_size(buffer_size.unwrap_or(100))
-                .with_splitting_strategy(strategy.unwrap_or(SplittingStrategy::Sentence))
-                .with_semantic_encoder(semantic_encoder)
-                .with_ocr(use_ocr.unwrap_or(false), tesseract_path)
-        }
+        let mut inner = embed_anything::config::TextEmbedConfig::default()
+            .with_chunk_size(chunk_size.unwrap_or(256), overlap_ratio)
+            .with_buffer_size(buffer_size.unwrap_or(100))
+            .with_splitting_strategy(strategy.unwrap_or(SplittingStrategy::Sentence))
+            .with_semantic_encoder(semantic_encoder)
+            .with_ocr(use_ocr.unwrap_or(false), tesseract_path)
+            .with_language(language)
+            .with_readability(readability.unwrap_or(false))
+            .with_sidecar_metadata(sidecar_metadata.unwrap_or(false))
+            .with_auto_batch_size(auto_batch_size.unwrap_or(false));
+        inner.batch_size = batch_size;
+        inner.min_chunk_size = min_chunk_size;
+        inner.min_chunk_policy = match min_chunk_policy {
+            Some("drop") => Some(MinChunkPolicy::Drop),
+            Some(_) => Some(MinChunkPolicy::Merge),
+            None => None,
+        };
+        inner.seed = seed;
+        inner.output_dtype = match output_dtype {
+            Some("f16") | Some("float16") => Some(OutputDtype::F16),
+            Some(_) => Some(OutputDtype::F32),
+            None => None,
+        };
+        inner.file_filter = file_filter.map(self::file_filter);
+        inner.error_on_unsupported = error_on_unsupported;
+        inner.chunk_records = chunk_records;
+        inner.max_document_bytes = max_document_bytes;
+        inner.streaming_extraction = streaming_extraction;
+        inner.text_cleaning = text_cleaning.map(|operations| {
+            let enabled = |operation: &str| operations.iter().any(|o| o == operation);
+            CleaningOptions::none()
+                .with_dehyphenate(enabled("dehyphenate"))
+                .with_collapse_whitespace(enabled("collapse_whitespace"))
+                .with_strip_control(enabled("strip_control"))
+                .with_normalize_unicode(enabled("normalize_unicode"))
+        });
+        inner.on_truncation = on_truncation.map(self::on_truncation);
+        inner.store_original_text = store_original_text;
+        inner.sentence_splitter = sentence_splitter
+            .and_then(|splitter| Python::with_gil(|py| self::sentence_splitter(py, splitter)));
+        inner.redact = redact.map(|kinds| {
+            let enabled = |kind: &str| kinds.iter().any(|k| k == kind);
+            RedactionConfig::default()
+                .with_emails(enabled("emails"))
+                .with_phone_numbers(enabled("phone_numbers"))
+                .with_credit_cards(enabled("credit_cards"))
+        });
+        inner.document_vector = document_vector;
+        inner.max_pages = max_pages;
+        inner.max_chars = max_chars;
+        inner.extract_archives = extract_archives;
+        inner.max_archive_bytes = max_archive_bytes;
+        // "linear", "markdown" or "skip"
+        inner.table_mode = table_mode.and_then(|mode| mode.parse::<TableMode>().ok());
+        inner.detect_language = detect_language;
+        inner.validate_dimensions = validate_dimensions;
+        inner.page_range = page_range;
+        inner.sort_by_length = sort_by_length;
+        inner.prepend_filename = prepend_filename;
+        inner.prepend_filename_to_every_chunk = prepend_filename_to_every_chunk;
+        inner.devices = devices;
+        Self { inner }
     }
 
     #[getter]
@@ -68,15 +288,25 @@ pub struct ImageEmbedConfig {
 #[pymethods]
 impl ImageEmbedConfig {
     #[new]
-    #[pyo3(signature = (buffer_size=None))]
-    pub fn new(buffer_size: Option<usize>) -> Self {
-        Self {
-            inner: embed_anything::config::ImageEmbedConfig::new(buffer_size),
-        }
+    #[pyo3(signature = (buffer_size=None, decode_threads=None, file_filter=None))]
+    pub fn new(
+        buffer_size: Option<usize>,
+        decode_threads: Option<usize>,
+        file_filter: Option<PyObject>,
+    ) -> Self {
+        let mut inner = embed_anything::config::ImageEmbedConfig::new(buffer_size);
+        inner.decode_threads = decode_threads;
+        inner.file_filter = file_filter.map(self::file_filter);
+        Self { inner }
     }
 
     #[getter]
     pub fn buffer_size(&self) -> Option<usize> {
         self.inner.buffer_size
     }
+
+    #[getter]
+    pub fn decode_threads(&self) -> Option<usize> {
+        self.inner.decode_threads
+    }
 }
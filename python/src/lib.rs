@@ -1,21 +1,35 @@
+pub mod adapters;
 pub mod config;
 pub mod models;
 use embed_anything::embeddings::embed::{TextEmbedder, VisionEmbedder};
 use embed_anything::{
     self,
     config::TextEmbedConfig,
-    emb_audio,
-    embeddings::embed::{Embedder, EmbeddingResult},
-    file_processor::audio::audio_processor,
+    emb_audio_directory, emb_audio_with_transcript,
+    embeddings::{
+        cloud::BudgetExceeded,
+        embed::{Embedder, EmbeddingResult, ModelDtype},
+        local::session_pool::scoped_session_pool_size,
+        registry::ModelLoader,
+        scoped_model_dtype,
+        utils::{scoped_add_special_tokens, scoped_cache_dir},
+    },
+    file_processor::audio::{
+        audio_processor::{self, DecodingParams},
+        vad::VadConfig,
+    },
     text_loader::FileLoadingError,
+    PartialEmbeddings,
 };
 use models::colbert::ColbertModel;
 use models::colpali::ColpaliModel;
+use models::registry::ModelRegistry;
 use models::reranker::{DocumentRank, Dtype, Reranker, RerankerResult};
 use pyo3::{
-    exceptions::{PyFileNotFoundError, PyValueError},
+    create_exception,
+    exceptions::{PyFileNotFoundError, PyPermissionError, PyRuntimeError, PyValueError},
     prelude::*,
-    types::PyList,
+    types::{PyBytes, PyDict, PyList, PyTuple},
 };
 use std::fmt;
 use std::str::FromStr;
@@ -35,20 +49,30 @@ pub struct EmbedData {
 #[pymethods]
 impl EmbedData {
     #[getter(embedding)]
-    fn embedding(&self) -> Py<PyList> {
+    fn embedding(&self) -> PyObject {
+        Python::with_gil(|py| embedding_to_py(py, &self.inner.embedding))
+    }
+
+    /// Embeddings by model name, filled by `embed_file_multi`.
+    #[getter(embeddings)]
+    fn embeddings(&self) -> HashMap<String, PyObject> {
         Python::with_gil(|py| {
-            let embedding = self.inner.embedding.clone();
-            match embedding {
-                EmbeddingResult::DenseVector(x) => PyList::new(py, x).unwrap().into(),
-                EmbeddingResult::MultiVector(x) => {
-                    PyList::new(py, x.iter().map(|inner| PyList::new(py, inner).unwrap()))
-                        .unwrap()
-                        .into()
-                }
-            }
+            self.inner
+                .embeddings
+                .iter()
+                .map(|(name, embedding)| (name.clone(), embedding_to_py(py, embedding)))
+                .collect()
         })
     }
 
+    /// The dense vector as the base64 of its little endian f32 bytes, which
+    /// `np.frombuffer(base64.b64decode(data), dtype="<f4")` decodes.
+    fn embedding_base64(&self) -> PyResult<String> {
+        self.inner
+            .embedding_base64()
+            .map_err(|e| PyValueError::new_err(e.to_string()))
+    }
+
     #[getter(text)]
     fn text(&self) -> Option<String> {
         self.inner.text.clone()
@@ -59,6 +83,11 @@ impl EmbedData {
         self.inner.metadata.clone()
     }
 
+    #[getter(is_normalized)]
+    fn is_normalized(&self) -> bool {
+        self.inner.is_normalized
+    }
+
     #[setter(text)]
     fn set_text(&mut self, text: Option<String>) {
         self.inner.text = text;
@@ -71,10 +100,11 @@ impl EmbedData {
 
     fn __str__(&self) -> String {
         format!(
-            "EmbedData(embedding: {:?}, text: {:?}, metadata: {:?})",
+            "EmbedData(embedding: {:?}, text: {:?}, metadata: {:?}, is_normalized: {})",
             self.inner.embedding,
             self.inner.text,
-            self.inner.metadata.clone()
+            self.inner.metadata.clone(),
+            self.inner.is_normalized
         )
     }
 
@@ -83,12 +113,45 @@ impl EmbedData {
     }
 }
 
+/// Converts an embedding to a list, or to the little endian bytes of the vector when it is in
+/// half precision, which `np.frombuffer(data, dtype=np.float16)` reads without a copy. A
+/// vector with its tokens becomes a `(pooled, tokens)` tuple.
+fn embedding_to_py(py: Python, embedding: &EmbeddingResult) -> PyObject {
+    match embedding {
+        EmbeddingResult::DenseVector(x) => PyList::new(py, x).unwrap().into_any().unbind(),
+        EmbeddingResult::MultiVector(x) => {
+            PyList::new(py, x.iter().map(|inner| PyList::new(py, inner).unwrap()))
+                .unwrap()
+                .into_any()
+                .unbind()
+        }
+        EmbeddingResult::HalfVector(x) => {
+            let bytes = x.iter().flat_map(|v| v.to_le_bytes()).collect::<Vec<_>>();
+            PyBytes::new(py, &bytes).into_any().unbind()
+        }
+        EmbeddingResult::DenseWithTokens { pooled, tokens } => {
+            let pooled = PyList::new(py, pooled).unwrap();
+            let tokens = PyList::new(
+                py,
+                tokens.iter().map(|inner| PyList::new(py, inner).unwrap()),
+            )
+            .unwrap();
+            PyTuple::new(py, [pooled, tokens])
+                .unwrap()
+                .into_any()
+                .unbind()
+        }
+    }
+}
+
 #[pyclass(eq, eq_int)]
-#[derive(PartialEq, Eq, Hash, EnumString, Debug)]
+#[derive(PartialEq, Eq, Hash, EnumString, Debug, Clone)]
 #[strum(serialize_all = "snake_case")]
 pub enum WhichModel {
     OpenAI,
     Cohere,
+    Gemini,
+    Mistral,
     Bert,
     SparseBert,
     ColBert,
@@ -133,6 +196,10 @@ pub enum ONNXModel {
     JINAV3,
     SPLADEPPENV1,
     SPLADEPPENV2,
+    SnowflakeArcticEmbedXS,
+    SnowflakeArcticEmbedS,
+    SnowflakeArcticEmbedM,
+    SnowflakeArcticEmbedL,
 }
 impl fmt::Display for ONNXModel {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
@@ -143,131 +210,328 @@ impl fmt::Display for ONNXModel {
 #[pyclass]
 pub struct EmbeddingModel {
     pub inner: Arc<Embedder>,
+    /// Loads the model again, for the models that can be loaded on the `devices` of a
+    /// TextEmbedConfig.
+    pub loader: Option<ModelLoader>,
+}
+
+/// Reads a `model_dtype`, either "f32", "f16" or "bf16".
+fn model_dtype(model_dtype: Option<&str>) -> PyResult<Option<ModelDtype>> {
+    match model_dtype {
+        None => Ok(None),
+        Some("f32") | Some("float32") => Ok(Some(ModelDtype::F32)),
+        Some("f16") | Some("float16") => Ok(Some(ModelDtype::F16)),
+        Some("bf16") | Some("bfloat16") => Ok(Some(ModelDtype::BF16)),
+        Some(other) => Err(PyValueError::new_err(format!(
+            "Invalid model_dtype {:?}, expected \"f32\", \"f16\" or \"bf16\"",
+            other
+        ))),
+    }
+}
+
+/// Loads a model of the Hugging Face Hub for [EmbeddingModel::from_pretrained_hf].
+fn load_hf(
+    model: &WhichModel,
+    model_id: Option<&str>,
+    revision: Option<&str>,
+    token: Option<&str>,
+) -> PyResult<Embedder> {
+    match model {
+        WhichModel::Bert => {
+            let model_id = model_id.unwrap_or("sentence-transformers/all-MiniLM-L12-v2");
+            let model = Embedder::Text(TextEmbedder::Bert(Box::new(
+                embed_anything::embeddings::local::bert::BertEmbedder::new(
+                    model_id.to_string(),
+                    revision.map(|s| s.to_string()),
+                    token,
+                )
+                .unwrap(),
+            )));
+            Ok(model)
+        }
+        WhichModel::SparseBert => {
+            let model_id = model_id.unwrap_or("prithivida/Splade_PP_en_v1");
+            let model = Embedder::Text(TextEmbedder::Bert(Box::new(
+                embed_anything::embeddings::local::bert::SparseBertEmbedder::new(
+                    model_id.to_string(),
+                    revision.map(|s| s.to_string()),
+                    token,
+                )
+                .unwrap(),
+            )));
+            Ok(model)
+        }
+        WhichModel::Clip => {
+            let model_id = model_id.unwrap_or("openai/clip-vit-base-patch32");
+            let model = Embedder::Vision(VisionEmbedder::Clip(
+                embed_anything::embeddings::local::clip::ClipEmbedder::new(
+                    model_id.to_string(),
+                    revision,
+                    token,
+                )
+                .map_err(|e| PyValueError::new_err(e.to_string()))?,
+            ));
+            Ok(model)
+        }
+        WhichModel::Jina => {
+            let model_id = model_id.unwrap_or("jinaai/jina-embeddings-v2-small-en");
+            let model = Embedder::Text(TextEmbedder::Jina(Box::new(
+                embed_anything::embeddings::local::jina::JinaEmbedder::new(
+                    model_id,
+                    revision,
+                    token,
+                )
+                .unwrap(),
+            )));
+            Ok(model)
+        }
+        WhichModel::Colpali => {
+            let model_id = model_id.unwrap_or("vidore/colpali-v1.2-merged");
+            let model = Embedder::Vision(VisionEmbedder::ColPali(Box::new(
+                embed_anything::embeddings::local::colpali::ColPaliEmbedder::new(
+                    model_id, revision,
+                )
+                .unwrap(),
+            )));
+            Ok(model)
+        }
+
+        _ => panic!("Invalid model"),
+    }
+}
+
+/// Loads an ONNX model for [EmbeddingModel::from_pretrained_onnx].
+fn load_onnx(
+    model: &WhichModel,
+    model_name: Option<&ONNXModel>,
+    hf_model_id: Option<&str>,
+    revision: Option<&str>,
+    dtype: Option<&Dtype>,
+    path_in_repo: Option<&str>,
+) -> PyResult<Embedder> {
+    let dtype = match dtype {
+        Some(Dtype::Q4F16) => Some(embed_anything::Dtype::Q4F16),
+        Some(Dtype::F16) => Some(embed_anything::Dtype::F16),
+        Some(Dtype::INT8) => Some(embed_anything::Dtype::INT8),
+        Some(Dtype::Q4) => Some(embed_anything::Dtype::Q4),
+        Some(Dtype::UINT8) => Some(embed_anything::Dtype::UINT8),
+        Some(Dtype::BNB4) => Some(embed_anything::Dtype::BNB4),
+        Some(Dtype::F32) => Some(embed_anything::Dtype::F32),
+        None => None,
+    };
+    let model_name = model_name.map(|model_name| {
+        embed_anything::embeddings::local::text_embedding::ONNXModel::from_str(
+            &model_name.to_string(),
+        )
+        .unwrap()
+    });
+    match model {
+        WhichModel::Bert => {
+            let model = Embedder::Text(TextEmbedder::Bert(Box::new(
+                embed_anything::embeddings::local::ort_bert::OrtBertEmbedder::new(
+                    model_name,
+                    hf_model_id,
+                    revision,
+                    dtype,
+                    path_in_repo,
+                )
+                .map_err(|e| PyValueError::new_err(e.to_string()))?,
+            )));
+            Ok(model)
+        }
+        WhichModel::SparseBert => {
+            let model = Embedder::Text(TextEmbedder::Bert(Box::new(
+                embed_anything::embeddings::local::ort_bert::OrtSparseBertEmbedder::new(
+                    model_name,
+                    hf_model_id,
+                    revision,
+                    path_in_repo,
+                )
+                .map_err(|e| PyValueError::new_err(e.to_string()))?,
+            )));
+            Ok(model)
+        }
+        WhichModel::Jina => {
+            let model = Embedder::Text(TextEmbedder::Jina(Box::new(
+                embed_anything::embeddings::local::ort_jina::OrtJinaEmbedder::new(
+                    model_name,
+                    hf_model_id,
+                    revision,
+                    dtype,
+                    path_in_repo,
+                )
+                .map_err(|e| PyValueError::new_err(e.to_string()))?,
+            )));
+            Ok(model)
+        }
+        WhichModel::ColBert => {
+            let model = Embedder::Text(TextEmbedder::Bert(Box::new(
+                embed_anything::embeddings::local::colbert::OrtColbertEmbedder::new(
+                    hf_model_id,
+                    revision,
+                    path_in_repo,
+                )
+                .map_err(|e| PyValueError::new_err(e.to_string()))?,
+            )));
+            Ok(model)
+        }
+        _ => panic!("Invalid model"),
+    }
+}
+
+impl EmbeddingModel {
+    /// Loads the model with `load`, which is kept to load copies of the model on the `devices`
+    /// of a TextEmbedConfig.
+    fn with_loader(
+        load: impl Fn() -> PyResult<Embedder> + Send + Sync + 'static,
+    ) -> PyResult<Self> {
+        let inner = Arc::new(load()?);
+        Ok(Self {
+            inner,
+            loader: Some(Arc::new(move || Ok(Arc::new(load()?)))),
+        })
+    }
 }
 
 #[pymethods]
 impl EmbeddingModel {
     #[staticmethod]
-    #[pyo3(signature = (model, model_id, revision=None, token=None))]
+    #[pyo3(signature = (model, model_id, revision=None, token=None, cache_dir=None, add_special_tokens=None, model_dtype=None))]
     fn from_pretrained_hf(
         model: &WhichModel,
         model_id: Option<&str>,
         revision: Option<&str>,
         token: Option<&str>,
+        cache_dir: Option<PathBuf>,
+        add_special_tokens: Option<bool>,
+        model_dtype: Option<&str>,
     ) -> PyResult<Self> {
-        // let model = WhichModel::from(model);
-        match model {
-            WhichModel::Bert => {
-                let model_id = model_id.unwrap_or("sentence-transformers/all-MiniLM-L12-v2");
-                let model = Embedder::Text(TextEmbedder::Bert(Box::new(
-                    embed_anything::embeddings::local::bert::BertEmbedder::new(
-                        model_id.to_string(),
-                        revision.map(|s| s.to_string()),
-                        token,
-                    )
-                    .unwrap(),
-                )));
-                Ok(EmbeddingModel {
-                    inner: Arc::new(model),
-                })
-            }
-            WhichModel::SparseBert => {
-                let model_id = model_id.unwrap_or("prithivida/Splade_PP_en_v1");
-                let model = Embedder::Text(TextEmbedder::Bert(Box::new(
-                    embed_anything::embeddings::local::bert::SparseBertEmbedder::new(
-                        model_id.to_string(),
-                        revision.map(|s| s.to_string()),
-                        token,
-                    )
-                    .unwrap(),
-                )));
-                Ok(EmbeddingModel {
-                    inner: Arc::new(model),
-                })
-            }
-            WhichModel::Clip => {
-                let model_id = model_id.unwrap_or("openai/clip-vit-base-patch32");
-                let model = Embedder::Vision(VisionEmbedder::Clip(
-                    embed_anything::embeddings::local::clip::ClipEmbedder::new(
-                        model_id.to_string(),
-                        revision,
-                        token,
-                    )
-                    .map_err(|e| PyValueError::new_err(e.to_string()))?,
-                ));
-                Ok(EmbeddingModel {
-                    inner: Arc::new(model),
-                })
-            }
-            WhichModel::Jina => {
-                let model_id = model_id.unwrap_or("jinaai/jina-embeddings-v2-small-en");
-                let model = Embedder::Text(TextEmbedder::Jina(Box::new(
-                    embed_anything::embeddings::local::jina::JinaEmbedder::new(
-                        model_id,
-                        revision,
-                        token,
-                    )
-                    .unwrap(),
-                )));
-                Ok(EmbeddingModel {
-                    inner: Arc::new(model),
-                })
-            }
-            WhichModel::Colpali => {
-                let model_id = model_id.unwrap_or("vidore/colpali-v1.2-merged");
-                let model = Embedder::Vision(VisionEmbedder::ColPali(Box::new(
-                    embed_anything::embeddings::local::colpali::ColPaliEmbedder::new(
-                        model_id, revision,
-                    )
-                    .unwrap(),
-                )));
-                Ok(EmbeddingModel {
-                    inner: Arc::new(model),
-                })
-            }
+        let model_dtype = self::model_dtype(model_dtype)?;
+        let model = model.clone();
+        let model_id = model_id.map(str::to_string);
+        let revision = revision.map(str::to_string);
+        let token = token.map(str::to_string);
+        Self::with_loader(move || {
+            let _cache_dir = scoped_cache_dir(cache_dir.clone());
+            let _special_tokens = scoped_add_special_tokens(add_special_tokens);
+            let _model_dtype = scoped_model_dtype(model_dtype);
+            load_hf(
+                &model,
+                model_id.as_deref(),
+                revision.as_deref(),
+                token.as_deref(),
+            )
+        })
+    }
 
-            _ => panic!("Invalid model"),
-        }
+    #[staticmethod]
+    #[pyo3(signature = (model, path, add_special_tokens=None, model_dtype=None))]
+    fn from_local_dir(
+        model: &WhichModel,
+        path: PathBuf,
+        add_special_tokens: Option<bool>,
+        model_dtype: Option<&str>,
+    ) -> PyResult<Self> {
+        let architecture = match model {
+            WhichModel::Bert => "bert",
+            WhichModel::Jina => "jina",
+            WhichModel::Clip => "clip",
+            _ => {
+                return Err(PyValueError::new_err(
+                    "Only Bert, Jina and Clip models can be loaded from a local directory",
+                ))
+            }
+        };
+        let model = embed_anything::embeddings::embed::EmbedderBuilder::new()
+            .model_architecture(architecture)
+            .add_special_tokens(add_special_tokens)
+            .model_dtype(self::model_dtype(model_dtype)?)
+            .from_local_dir(path)
+            .map_err(|e| PyValueError::new_err(e.to_string()))?;
+        Ok(EmbeddingModel {
+            inner: Arc::new(model),
+            loader: None,
+        })
     }
 
     #[staticmethod]
-    #[pyo3(signature = (model, model_id,  api_key=None))]
+    #[pyo3(signature = (model, model_id,  api_key=None, output_dimensionality=None, extra_headers=None, proxy=None, max_total_tokens=None))]
     fn from_pretrained_cloud(
         model: &WhichModel,
         model_id: Option<&str>,
         api_key: Option<String>,
+        output_dimensionality: Option<usize>,
+        extra_headers: Option<HashMap<String, String>>,
+        proxy: Option<&str>,
+        max_total_tokens: Option<u64>,
     ) -> PyResult<Self> {
-        match model {
+        let model = match model {
             WhichModel::OpenAI => {
                 let model_id = model_id.unwrap_or("text-embedding-3-small");
-                let model = Embedder::Text(TextEmbedder::OpenAI(
+                TextEmbedder::OpenAI(
                     embed_anything::embeddings::cloud::openai::OpenAIEmbedder::new(
                         model_id.to_string(),
                         api_key,
                     ),
-                ));
-                Ok(EmbeddingModel {
-                    inner: Arc::new(model),
-                })
+                )
             }
             WhichModel::Cohere => {
                 let model_id = model_id.unwrap_or("embed-english-v3.0");
-                let model = Embedder::Text(TextEmbedder::Cohere(
+                TextEmbedder::Cohere(
                     embed_anything::embeddings::cloud::cohere::CohereEmbedder::new(
                         model_id.to_string(),
                         api_key,
                     ),
-                ));
-                Ok(EmbeddingModel {
-                    inner: Arc::new(model),
-                })
+                )
+            }
+            WhichModel::Gemini => {
+                let model_id = model_id.unwrap_or("text-embedding-004");
+                TextEmbedder::Gemini(
+                    embed_anything::embeddings::cloud::gemini::GeminiEmbedder::new(
+                        model_id.to_string(),
+                        api_key,
+                    )
+                    .with_output_dimensionality(output_dimensionality),
+                )
+            }
+            WhichModel::Mistral => {
+                let model_id = model_id.unwrap_or("mistral-embed");
+                TextEmbedder::Mistral(
+                    embed_anything::embeddings::cloud::mistral::MistralEmbedder::new(
+                        model_id.to_string(),
+                        api_key,
+                    ),
+                )
             }
             _ => panic!("Invalid model"),
-        }
+        };
+        let model = match extra_headers {
+            Some(extra_headers) => model
+                .with_extra_headers(&extra_headers)
+                .map_err(|e| PyValueError::new_err(e.to_string()))?,
+            None => model,
+        };
+        let model = match proxy {
+            Some(proxy) => model
+                .with_proxy(proxy)
+                .map_err(|e| PyValueError::new_err(e.to_string()))?,
+            None => model,
+        };
+        let model = match max_total_tokens {
+            Some(max_total_tokens) => model
+                .with_max_total_tokens(max_total_tokens)
+                .map_err(|e| PyValueError::new_err(e.to_string()))?,
+            None => model,
+        };
+        Ok(EmbeddingModel {
+            inner: Arc::new(Embedder::Text(model)),
+            loader: None,
+        })
     }
 
     #[staticmethod]
-    #[pyo3(signature = (model, model_name=None, hf_model_id=None, revision=None, dtype=None, path_in_repo=None))]
+    #[pyo3(signature = (model, model_name=None, hf_model_id=None, revision=None, dtype=None, path_in_repo=None, cache_dir=None, session_pool_size=None, add_special_tokens=None))]
+    #[allow(clippy::too_many_arguments)]
     fn from_pretrained_onnx(
         model: &WhichModel,
         model_name: Option<&ONNXModel>,
@@ -275,84 +539,276 @@ impl EmbeddingModel {
         revision: Option<&str>,
         dtype: Option<&Dtype>,
         path_in_repo: Option<&str>,
+        cache_dir: Option<PathBuf>,
+        session_pool_size: Option<usize>,
+        add_special_tokens: Option<bool>,
     ) -> PyResult<Self> {
-        let dtype = match dtype {
-            Some(Dtype::Q4F16) => Some(embed_anything::Dtype::Q4F16),
-            Some(Dtype::F16) => Some(embed_anything::Dtype::F16),
-            Some(Dtype::INT8) => Some(embed_anything::Dtype::INT8),
-            Some(Dtype::Q4) => Some(embed_anything::Dtype::Q4),
-            Some(Dtype::UINT8) => Some(embed_anything::Dtype::UINT8),
-            Some(Dtype::BNB4) => Some(embed_anything::Dtype::BNB4),
-            Some(Dtype::F32) => Some(embed_anything::Dtype::F32),
-            None => None,
-        };
-        let model_name = model_name.map(|model_name| embed_anything::embeddings::local::text_embedding::ONNXModel::from_str(
-                    &model_name.to_string(),
-                )
-                .unwrap());
-        match model {
-            WhichModel::Bert => {
-                let model = Embedder::Text(TextEmbedder::Bert(Box::new(
-                    embed_anything::embeddings::local::ort_bert::OrtBertEmbedder::new(
-                        model_name,
-                        hf_model_id,
-                        revision,
-                        dtype,
-                        path_in_repo,
-                    )
-                    .map_err(|e| PyValueError::new_err(e.to_string()))?,
-                )));
-                Ok(EmbeddingModel {
-                    inner: Arc::new(model),
-                })
-            }
-            WhichModel::SparseBert => {
-                let model = Embedder::Text(TextEmbedder::Bert(Box::new(
-                    embed_anything::embeddings::local::ort_bert::OrtSparseBertEmbedder::new(
-                        model_name,
-                        hf_model_id,
-                        revision,
-                        path_in_repo,
-                    )
-                    .map_err(|e| PyValueError::new_err(e.to_string()))?,
-                )));
-                Ok(EmbeddingModel {
-                    inner: Arc::new(model),
-                })
-            }
-            WhichModel::Jina => {
-                let model = Embedder::Text(TextEmbedder::Jina(Box::new(
-                    embed_anything::embeddings::local::ort_jina::OrtJinaEmbedder::new(
-                        model_name,
-                        hf_model_id,
-                        revision,
-                        dtype,
-                        path_in_repo,
-                    )
-                    .map_err(|e| PyValueError::new_err(e.to_string()))?,
-                )));
-                Ok(EmbeddingModel {
-                    inner: Arc::new(model),
-                })
-            }
-            WhichModel::ColBert => {
-                let model = Embedder::Text(TextEmbedder::Bert(Box::new(
-                    embed_anything::embeddings::local::colbert::OrtColbertEmbedder::new(
-                        hf_model_id,
-                        revision,
-                        path_in_repo,
-                    )
-                    .map_err(|e| PyValueError::new_err(e.to_string()))?,
-                )));
-                Ok(EmbeddingModel {
-                    inner: Arc::new(model),
-                })
-            }
-            _ => panic!("Invalid model"),
+        let model = model.clone();
+        let model_name = model_name.cloned();
+        let hf_model_id = hf_model_id.map(str::to_string);
+        let revision = revision.map(str::to_string);
+        let dtype = dtype.cloned();
+        let path_in_repo = path_in_repo.map(str::to_string);
+        Self::with_loader(move || {
+            let _cache_dir = scoped_cache_dir(cache_dir.clone());
+            let _session_pool_size = scoped_session_pool_size(session_pool_size);
+            let _special_tokens = scoped_add_special_tokens(add_special_tokens);
+            load_onnx(
+                &model,
+                model_name.as_ref(),
+                hf_model_id.as_deref(),
+                revision.as_deref(),
+                dtype.as_ref(),
+                path_in_repo.as_deref(),
+            )
+        })
+    }
+
+    /// Embeds a few fixed sentences and checks the output is sane. Raises a ValueError
+    /// describing the problem otherwise.
+    fn self_check(&self) -> PyResult<SelfCheckReport> {
+        let rt = Builder::new_multi_thread().enable_all().build().unwrap();
+        let report = rt
+            .block_on(self.inner.self_check())
+            .map_err(|e| PyValueError::new_err(e.to_string()))?;
+        Ok(SelfCheckReport { inner: report })
+    }
+
+    /// Scores every image against every label, returned as a 2D numpy array with one row per
+    /// image.
+    fn encode_image_and_text_pairs(
+        &self,
+        py: Python<'_>,
+        images: Vec<PathBuf>,
+        labels: Vec<String>,
+    ) -> PyResult<PyObject> {
+        let scores = self
+            .inner
+            .encode_image_and_text_pairs(&images, &labels)
+            .map_err(|e| PyValueError::new_err(e.to_string()))?;
+        let array = py
+            .import("numpy")?
+            .call_method1("array", (scores, "float32"))?;
+        // Keep the shape when there are no images or no labels.
+        Ok(array
+            .call_method1("reshape", (images.len(), labels.len()))?
+            .unbind())
+    }
+
+    /// The Hugging Face model id, or the model name of cloud models.
+    fn model_id(&self) -> String {
+        self.inner.model_id()
+    }
+
+    /// The revision the model was loaded from, None for the default branch.
+    fn revision(&self) -> Option<String> {
+        self.inner.revision()
+    }
+
+    fn metadata(&self) -> ModelMetadata {
+        ModelMetadata {
+            inner: self.inner.metadata(),
         }
     }
 }
 
+#[pyclass]
+pub struct ModelMetadata {
+    pub inner: embed_anything::embeddings::embed::ModelMetadata,
+}
+
+#[pymethods]
+impl ModelMetadata {
+    #[getter]
+    fn architecture(&self) -> &str {
+        &self.inner.architecture
+    }
+
+    #[getter]
+    fn model_id(&self) -> &str {
+        &self.inner.model_id
+    }
+
+    #[getter]
+    fn revision(&self) -> Option<String> {
+        self.inner.revision.clone()
+    }
+
+    #[getter]
+    fn dimension(&self) -> Option<usize> {
+        self.inner.dimension
+    }
+
+    /// The pooling name in lower case, e.g. "mean" or "cls".
+    #[getter]
+    fn pooling(&self) -> Option<String> {
+        self.inner
+            .pooling
+            .as_ref()
+            .map(|pooling| format!("{:?}", pooling).to_lowercase())
+    }
+
+    #[getter]
+    fn normalized(&self) -> bool {
+        self.inner.normalized
+    }
+
+    fn __repr__(&self) -> String {
+        format!(
+            "ModelMetadata(architecture={:?}, model_id={:?}, revision={:?}, dimension={:?}, pooling={:?}, normalized={})",
+            self.inner.architecture,
+            self.inner.model_id,
+            self.inner.revision,
+            self.inner.dimension,
+            self.pooling(),
+            self.inner.normalized
+        )
+    }
+}
+
+#[pyclass]
+pub struct SelfCheckReport {
+    pub inner: embed_anything::embeddings::embed::SelfCheckReport,
+}
+
+#[pymethods]
+impl SelfCheckReport {
+    #[getter]
+    fn dimension(&self) -> usize {
+        self.inner.dimension
+    }
+
+    #[getter]
+    fn vectors_per_text(&self) -> usize {
+        self.inner.vectors_per_text
+    }
+
+    #[getter]
+    fn unit_norm(&self) -> bool {
+        self.inner.unit_norm
+    }
+
+    #[getter]
+    fn similarity(&self) -> f32 {
+        self.inner.similarity
+    }
+
+    fn __repr__(&self) -> String {
+        format!(
+            "SelfCheckReport(dimension={}, vectors_per_text={}, unit_norm={}, similarity={})",
+            self.inner.dimension,
+            self.inner.vectors_per_text,
+            self.inner.unit_norm,
+            self.inner.similarity
+        )
+    }
+}
+
+#[pyclass]
+pub struct SearchResult {
+    pub inner: embed_anything::SearchResult,
+}
+
+#[pymethods]
+impl SearchResult {
+    #[getter]
+    fn text(&self) -> Option<String> {
+        self.inner.text.clone()
+    }
+
+    #[getter]
+    fn score(&self) -> f32 {
+        self.inner.score
+    }
+
+    #[getter]
+    fn metadata(&self) -> Option<HashMap<String, String>> {
+        self.inner.metadata.clone()
+    }
+
+    fn __repr__(&self) -> String {
+        format!(
+            "SearchResult(score={}, text={:?})",
+            self.inner.score, self.inner.text
+        )
+    }
+}
+
+#[pyclass]
+pub struct ChunkPreview {
+    pub inner: embed_anything::ChunkPreview,
+}
+
+#[pymethods]
+impl ChunkPreview {
+    #[getter]
+    fn text(&self) -> &str {
+        &self.inner.text
+    }
+
+    #[getter]
+    fn token_count(&self) -> usize {
+        self.inner.token_count
+    }
+
+    #[getter]
+    fn file(&self) -> &str {
+        &self.inner.file
+    }
+
+    #[getter]
+    fn chunk_index(&self) -> usize {
+        self.inner.chunk_index
+    }
+
+    fn __repr__(&self) -> String {
+        format!(
+            "ChunkPreview(file={}, chunk_index={}, token_count={})",
+            self.inner.file, self.inner.chunk_index, self.inner.token_count
+        )
+    }
+}
+
+#[pyclass]
+pub struct Chunk {
+    pub inner: embed_anything::chunkers::Chunk,
+}
+
+#[pymethods]
+impl Chunk {
+    #[getter]
+    fn text(&self) -> &str {
+        &self.inner.text
+    }
+
+    #[getter]
+    fn token_count(&self) -> usize {
+        self.inner.token_count
+    }
+
+    #[getter]
+    fn start_char(&self) -> Option<usize> {
+        self.inner.start_char
+    }
+
+    #[getter]
+    fn end_char(&self) -> Option<usize> {
+        self.inner.end_char
+    }
+
+    #[getter]
+    fn metadata(&self) -> HashMap<String, String> {
+        self.inner.metadata.clone()
+    }
+
+    fn __repr__(&self) -> String {
+        format!(
+            "Chunk(start_char={:?}, end_char={:?}, token_count={})",
+            self.inner.start_char, self.inner.end_char, self.inner.token_count
+        )
+    }
+}
+
 #[pyclass]
 pub struct AudioDecoderModel {
     pub inner: audio_processor::AudioDecoderModel,
@@ -361,12 +817,20 @@ pub struct AudioDecoderModel {
 #[pymethods]
 impl AudioDecoderModel {
     #[staticmethod]
-    #[pyo3(signature = (model_id, revision=None, model_type=None, quantized=None))]
+    #[pyo3(signature = (model_id, revision=None, model_type=None, quantized=None, vad=None, vad_sensitivity=None, beam_size=None, temperature=None, temperature_increment=None, no_speech_threshold=None, logprob_threshold=None))]
+    #[allow(clippy::too_many_arguments)]
     fn from_pretrained_hf(
         model_id: Option<&str>,
         revision: Option<&str>,
         model_type: Option<&str>,
         quantized: Option<bool>,
+        vad: Option<bool>,
+        vad_sensitivity: Option<f32>,
+        beam_size: Option<usize>,
+        temperature: Option<f64>,
+        temperature_increment: Option<f64>,
+        no_speech_threshold: Option<f64>,
+        logprob_threshold: Option<f64>,
     ) -> PyResult<Self> {
         let model_id = model_id.unwrap_or("openai/whisper-tiny.en");
         let model_type = model_type.unwrap_or("tiny-en");
@@ -378,7 +842,22 @@ impl AudioDecoderModel {
             quantized.unwrap_or(false),
         )
         .map_err(|e| PyValueError::new_err(e.to_string()))?;
-        Ok(AudioDecoderModel { inner: model })
+        let vad = vad.unwrap_or(false).then(|| match vad_sensitivity {
+            Some(sensitivity) => VadConfig::new(sensitivity),
+            None => VadConfig::default(),
+        });
+        let defaults = DecodingParams::default();
+        let decoding = defaults
+            .with_beam_size(beam_size.unwrap_or(defaults.beam_size))
+            .with_temperature(
+                temperature.unwrap_or(defaults.temperature),
+                temperature_increment.unwrap_or(defaults.temperature_increment),
+            )
+            .with_no_speech_threshold(no_speech_threshold.unwrap_or(defaults.no_speech_threshold))
+            .with_logprob_threshold(logprob_threshold.unwrap_or(defaults.logprob_threshold));
+        Ok(AudioDecoderModel {
+            inner: model.with_vad(vad).with_decoding(decoding),
+        })
     }
 }
 
@@ -392,30 +871,233 @@ pub fn embed_query(
     let config = config.map(|c| &c.inner);
     let embedding_model = &embedder.inner;
     let rt = Builder::new_multi_thread().enable_all().build().unwrap();
-    Ok(rt.block_on(async {
-        embed_anything::embed_query(
+    let embeddings = rt
+        .block_on(embed_anything::embed_query(
             query,
             embedding_model,
             Some(config.unwrap_or(&TextEmbedConfig::default())),
-        )
-        .await
-        .map_err(|e| PyValueError::new_err(e.to_string()))
-        .unwrap()
+        ))
+        .map_err(embedding_error)?;
+    Ok(embeddings
         .into_iter()
         .map(|data| EmbedData { inner: data })
+        .collect())
+}
+
+/// Embeds each query separately from the others: the list holds an `EmbedData` for every query
+/// that was embedded and the `ValueError` of every query that failed, in the order of `query`.
+#[pyfunction]
+#[pyo3(signature = (query, embedder, config=None))]
+pub fn try_embed_query(
+    py: Python,
+    query: Vec<String>,
+    embedder: &EmbeddingModel,
+    config: Option<&config::TextEmbedConfig>,
+) -> PyResult<Vec<PyObject>> {
+    let config = config.map(|c| &c.inner);
+    let embedding_model = &embedder.inner;
+    let rt = Builder::new_multi_thread().enable_all().build().unwrap();
+    let results = rt.block_on(embed_anything::try_embed_query(
+        query,
+        embedding_model,
+        Some(config.unwrap_or(&TextEmbedConfig::default())),
+    ));
+    results
+        .into_iter()
+        .map(|result| match result {
+            Ok(data) => Ok(Py::new(py, EmbedData { inner: data })?.into_any()),
+            Err(e) => Ok(embedding_error(e).into_value(py).into_any()),
+        })
         .collect()
+}
+
+/// Reads a record given as a dict with an `id`, a `text` and an optional `metadata` dict. Ids
+/// that aren't strings, such as integer primary keys, are converted with `str`.
+fn record(record: &Bound<'_, PyDict>) -> PyResult<embed_anything::Record> {
+    let id = record
+        .get_item("id")?
+        .ok_or_else(|| PyValueError::new_err("record has no \"id\""))?;
+    let id = match id.extract::<String>() {
+        Ok(id) => id,
+        Err(_) => id.str()?.to_string(),
+    };
+    let text = record
+        .get_item("text")?
+        .ok_or_else(|| PyValueError::new_err(format!("record {} has no \"text\"", id)))?
+        .extract::<String>()?;
+    let metadata = match record.get_item("metadata")? {
+        Some(metadata) if !metadata.is_none() => metadata.extract::<HashMap<String, String>>()?,
+        _ => HashMap::new(),
+    };
+    Ok(embed_anything::Record::new(id, text).with_metadata(metadata))
+}
+
+#[pyfunction]
+#[pyo3(signature = (records, embedder, config=None, adapter=None))]
+pub fn embed_records(
+    records: Vec<Bound<'_, PyDict>>,
+    embedder: &EmbeddingModel,
+    config: Option<&config::TextEmbedConfig>,
+    adapter: Option<PyObject>,
+) -> PyResult<Option<Vec<EmbedData>>> {
+    let records = records.iter().map(record).collect::<PyResult<Vec<_>>>()?;
+    let config = config.map(|c| &c.inner);
+    let embedding_model = &embedder.inner;
+    let rt = Builder::new_multi_thread().enable_all().build().unwrap();
+    let adapter = adapter.map(PyAdapter);
+
+    let embeddings = rt
+        .block_on(async {
+            embed_anything::embed_records(records, embedding_model, config, adapter).await
+        })
+        .map_err(embedding_error)?;
+
+    Ok(embeddings.map(|embs| {
+        embs.into_iter()
+            .map(|data| EmbedData { inner: data })
+            .collect()
     }))
 }
 
+/// Passes the embeddings to the `upsert` method of a Python adapter, and calls its `flush`
+/// method, when it has one, once embedding is done.
+struct PyAdapter(PyObject);
+
+impl embed_anything::adapters::Adapter for PyAdapter {
+    fn upsert(&self, data: Vec<embed_anything::embeddings::embed::EmbedData>) {
+        Python::with_gil(|py| {
+            let upsert_fn = self.0.getattr(py, "upsert").unwrap();
+            let converted_data = data
+                .into_iter()
+                .map(|data| EmbedData { inner: data })
+                .collect::<Vec<EmbedData>>();
+            upsert_fn
+                .call1(py, (converted_data,))
+                .map_err(|e| PyValueError::new_err(e.to_string()))
+                .unwrap();
+        });
+    }
+
+    fn flush(&self) {
+        Python::with_gil(|py| {
+            if let Ok(flush_fn) = self.0.getattr(py, "flush") {
+                flush_fn
+                    .call0(py)
+                    .map_err(|e| PyValueError::new_err(e.to_string()))
+                    .unwrap();
+            }
+        });
+    }
+}
+
+create_exception!(
+    _embed_anything,
+    BudgetExceededError,
+    PyRuntimeError,
+    "Raised when a cloud model would go over its `max_total_tokens`. `embeddings` holds the \
+     embeddings made before the budget ran out, of the texts at the positions `indices` of the \
+     batch, with `used` and `max_total_tokens` the tokens of the budget."
+);
+
+/// Raises [BudgetExceededError] with the embeddings made before the budget ran out.
+fn budget_exceeded_error(exceeded: &BudgetExceeded) -> PyErr {
+    let error = BudgetExceededError::new_err(exceeded.to_string());
+    let attributes = Python::with_gil(|py| {
+        let value = error.value(py);
+        let embeddings = exceeded
+            .embeddings
+            .iter()
+            .map(|embedding| embedding_to_py(py, embedding));
+        value.setattr("embeddings", PyList::new(py, embeddings)?)?;
+        value.setattr("indices", exceeded.indices.clone())?;
+        value.setattr("used", exceeded.used)?;
+        value.setattr("max_total_tokens", exceeded.max_total_tokens)
+    });
+    match attributes {
+        Ok(()) => error,
+        Err(e) => e,
+    }
+}
+
+/// Raises [BudgetExceededError] for a [BudgetExceeded], also when it is the source of the error,
+/// and a ValueError for other errors.
+fn embedding_error(e: impl AsRef<dyn std::error::Error + Send + Sync>) -> PyErr {
+    let e = e.as_ref();
+    let exceeded = std::iter::successors(Some(e as &dyn std::error::Error), |e| e.source())
+        .find_map(|e| e.downcast_ref::<BudgetExceeded>());
+    match exceeded {
+        Some(exceeded) => budget_exceeded_error(exceeded),
+        None => PyValueError::new_err(e.to_string()),
+    }
+}
+
+/// Raises [embedding_error] with the embeddings made before embedding stopped, see
+/// [PartialEmbeddings], in the `embed_data` attribute of the exception.
+fn directory_error(e: anyhow::Error) -> PyErr {
+    let error = embedding_error(&e);
+    let Some(partial) = e.downcast_ref::<PartialEmbeddings>() else {
+        return error;
+    };
+    let embed_data = partial
+        .embeddings
+        .iter()
+        .map(|data| EmbedData {
+            inner: data.clone(),
+        })
+        .collect::<Vec<_>>();
+    match Python::with_gil(|py| error.value(py).setattr("embed_data", embed_data)) {
+        Ok(()) => error,
+        Err(e) => e,
+    }
+}
+
+/// Raises the Python exception matching a [FileLoadingError], and [embedding_error] for other
+/// errors.
+fn file_loading_error(e: impl AsRef<dyn std::error::Error + Send + Sync>) -> PyErr {
+    let e = e.as_ref();
+    match e.downcast_ref::<FileLoadingError>() {
+        Some(FileLoadingError::FileNotFound(_)) => PyFileNotFoundError::new_err(e.to_string()),
+        Some(FileLoadingError::PermissionDenied(_)) => PyPermissionError::new_err(e.to_string()),
+        Some(FileLoadingError::ExtractionFailed { .. }) => PyRuntimeError::new_err(e.to_string()),
+        Some(
+            FileLoadingError::UnsupportedFileType(_)
+            | FileLoadingError::EncryptedFile(_)
+            | FileLoadingError::EmptyDocument(_)
+            | FileLoadingError::DocumentTooLarge { .. },
+        )
+        | None => embedding_error(e),
+    }
+}
+
+/// The config of a call, with the `chunk_size` and `batch_size` keyword arguments taking
+/// precedence over the ones of `config`.
+fn with_overrides(
+    config: Option<&config::TextEmbedConfig>,
+    chunk_size: Option<usize>,
+    batch_size: Option<usize>,
+) -> embed_anything::config::TextEmbedConfig {
+    let overrides = embed_anything::config::Overrides {
+        chunk_size,
+        batch_size,
+    };
+    config
+        .map(|config| config.inner.clone())
+        .unwrap_or_default()
+        .with_overrides(&overrides)
+}
+
 #[pyfunction]
-#[pyo3(signature = (file_name, embedder, config=None, adapter=None))]
+#[pyo3(signature = (file_name, embedder, config=None, adapter=None, chunk_size=None, batch_size=None))]
 pub fn embed_file(
     file_name: &str,
     embedder: &EmbeddingModel,
     config: Option<&config::TextEmbedConfig>,
     adapter: Option<PyObject>,
+    chunk_size: Option<usize>,
+    batch_size: Option<usize>,
 ) -> PyResult<Option<Vec<EmbedData>>> {
-    let config = config.map(|c| &c.inner);
+    let config = with_overrides(config, chunk_size, batch_size);
+    let config = Some(&config);
     let embedding_model = &embedder.inner;
     let rt = Builder::new_multi_thread().enable_all().build().unwrap();
     if !Path::new(file_name).exists() {
@@ -425,39 +1107,62 @@ pub fn embed_file(
             file_name
         )));
     };
-    let adapter = match adapter {
-        Some(adapter) => {
-            let callback = move |data: Vec<embed_anything::embeddings::embed::EmbedData>| {
-                Python::with_gil(|py| {
-                    let upsert_fn = adapter.getattr(py, "upsert").unwrap();
-                    let converted_data = data
-                        .into_iter()
-                        .map(|data| EmbedData { inner: data })
-                        .collect::<Vec<EmbedData>>();
-                    upsert_fn
-                        .call1(py, (converted_data,))
-                        .map_err(|e| PyValueError::new_err(e.to_string()))
-                        .unwrap();
-                });
-            };
-            Some(callback)
-        }
-        None => None,
-    };
+    let adapter = adapter.map(PyAdapter);
 
     let embeddings = rt
         .block_on(async {
             embed_anything::embed_file(file_name, embedding_model, config, adapter).await
         })
-        .map_err(|e| match e.downcast_ref::<FileLoadingError>() {
-            Some(FileLoadingError::FileNotFound(file)) => {
-                PyFileNotFoundError::new_err(file.clone())
-            }
-            Some(FileLoadingError::UnsupportedFileType(file)) => {
-                PyValueError::new_err(file.clone())
-            }
-            None => PyValueError::new_err(e.to_string()),
-        })?;
+        .map_err(file_loading_error)?;
+
+    Ok(embeddings.map(|embs| {
+        embs.into_iter()
+            .map(|data| EmbedData { inner: data })
+            .collect()
+    }))
+}
+
+#[pyfunction]
+#[pyo3(signature = (file_name, model, model_id=None, config=None))]
+pub fn embed_file_oneshot(
+    file_name: &str,
+    model: &WhichModel,
+    model_id: Option<&str>,
+    config: Option<&config::TextEmbedConfig>,
+) -> PyResult<Vec<EmbedData>> {
+    let embedder = EmbeddingModel::from_pretrained_hf(model, model_id, None, None)?;
+    // The model is freed when `embedder` goes out of scope.
+    let embeddings = embed_file(file_name, &embedder, config, None)?;
+    Ok(embeddings.unwrap_or_default())
+}
+
+#[pyfunction]
+#[pyo3(signature = (file_name, embedders, config=None, adapter=None))]
+pub fn embed_file_multi(
+    file_name: &str,
+    embedders: Vec<(String, PyRef<EmbeddingModel>)>,
+    config: Option<&config::TextEmbedConfig>,
+    adapter: Option<PyObject>,
+) -> PyResult<Option<Vec<EmbedData>>> {
+    let config = config.map(|c| &c.inner);
+    let embedders = embedders
+        .iter()
+        .map(|(name, embedder)| (name.as_str(), embedder.inner.as_ref()))
+        .collect::<Vec<_>>();
+    let rt = Builder::new_multi_thread().enable_all().build().unwrap();
+    if !Path::new(file_name).exists() {
+        return Err(PyFileNotFoundError::new_err(format!(
+            "File not found: {:?}",
+            file_name
+        )));
+    };
+    let adapter = adapter.map(PyAdapter);
+
+    let embeddings = rt
+        .block_on(async {
+            embed_anything::embed_file_multi(file_name, &embedders, config, adapter).await
+        })
+        .map_err(file_loading_error)?;
 
     Ok(embeddings.map(|embs| {
         embs.into_iter()
@@ -467,83 +1172,241 @@ pub fn embed_file(
 }
 
 #[pyfunction]
-#[pyo3(signature = (audio_file, audio_decoder, embedder, text_embed_config=None))]
+#[pyo3(signature = (audio_file, audio_decoder, embedder, text_embed_config=None, return_transcript=false))]
 pub fn embed_audio_file(
+    py: Python<'_>,
     audio_file: String,
     audio_decoder: &mut AudioDecoderModel,
     embedder: &EmbeddingModel,
     text_embed_config: Option<&config::TextEmbedConfig>,
-) -> PyResult<Option<Vec<EmbedData>>> {
+    return_transcript: bool,
+) -> PyResult<PyObject> {
     let config = text_embed_config.map(|c| &c.inner);
     let embedding_model = &embedder.inner;
     let audio_decoder = &mut audio_decoder.inner;
     let rt = Builder::new_multi_thread().enable_all().build().unwrap();
-    let data = rt.block_on(async {
-        emb_audio(audio_file, audio_decoder, embedding_model, config)
-            .await
-            .map_err(|e| PyValueError::new_err(e.to_string()))
-            .unwrap()
-            .map(|data| {
-                data.into_iter()
-                    .map(|data| EmbedData { inner: data })
-                    .collect::<Vec<_>>()
-            })
-    });
-    Ok(data)
+    let audio = rt
+        .block_on(emb_audio_with_transcript(
+            audio_file,
+            audio_decoder,
+            embedding_model,
+            config,
+        ))
+        .map_err(embedding_error)?;
+    let data = audio
+        .embeddings
+        .into_iter()
+        .map(|data| EmbedData { inner: data })
+        .collect::<Vec<_>>();
+    if return_transcript {
+        Ok((data, audio.transcript)
+            .into_pyobject(py)?
+            .into_any()
+            .unbind())
+    } else {
+        Ok(data.into_pyobject(py)?.into_any().unbind())
+    }
+}
+
+#[pyfunction]
+#[pyo3(signature = (directory, audio_decoder, embedder, text_embed_config=None))]
+pub fn embed_audio_directory(
+    directory: PathBuf,
+    audio_decoder: &mut AudioDecoderModel,
+    embedder: &EmbeddingModel,
+    text_embed_config: Option<&config::TextEmbedConfig>,
+) -> PyResult<Vec<EmbedData>> {
+    let config = text_embed_config.map(|c| &c.inner);
+    let embedding_model = &embedder.inner;
+    let audio_decoder = &mut audio_decoder.inner;
+    let rt = Builder::new_multi_thread().enable_all().build().unwrap();
+    let data = rt
+        .block_on(emb_audio_directory(
+            directory,
+            audio_decoder,
+            embedding_model,
+            config,
+        ))
+        .map_err(embedding_error)?;
+    Ok(data
+        .into_iter()
+        .map(|data| EmbedData { inner: data })
+        .collect())
 }
 
 #[pyfunction]
-#[pyo3(signature = (directory, embedder, extensions=None, config=None, adapter = None))]
+#[pyo3(signature = (directory, embedder, extensions=None, config=None, adapter = None, chunk_size=None, batch_size=None))]
 pub fn embed_directory(
     directory: PathBuf,
     embedder: &EmbeddingModel,
     extensions: Option<Vec<String>>,
     config: Option<&config::TextEmbedConfig>,
     adapter: Option<PyObject>,
+    chunk_size: Option<usize>,
+    batch_size: Option<usize>,
 ) -> PyResult<Option<Vec<EmbedData>>> {
-    let config = config.map(|c| &c.inner);
+    let mut config = with_overrides(config, chunk_size, batch_size);
+    if config.devices.is_some() && config.model_loader.is_none() {
+        config.model_loader = Some(embedder.loader.clone().ok_or_else(|| {
+            PyValueError::new_err(
+                "devices needs a model loaded with from_pretrained_hf or from_pretrained_onnx",
+            )
+        })?);
+    }
+    let config = Some(&config);
     let embedding_model = &embedder.inner;
 
     let rt = Builder::new_multi_thread().enable_all().build().unwrap();
     println!("Runtime created");
-    let adapter = match adapter {
-        Some(adapter) => {
-            let callback = move |data: Vec<embed_anything::embeddings::embed::EmbedData>| {
-                Python::with_gil(|py| {
-                    let upsert_fn = adapter.getattr(py, "upsert").unwrap();
-                    let converted_data = data
-                        .into_iter()
-                        .map(|data| EmbedData { inner: data })
-                        .collect::<Vec<EmbedData>>();
-                    upsert_fn
-                        .call1(py, (converted_data,))
-                        .map_err(|e| PyValueError::new_err(e.to_string()))
-                        .unwrap();
-                });
-            };
-            Some(callback)
-        }
-        None => None,
-    };
+    let adapter = adapter.map(PyAdapter);
 
-    let data = rt.block_on(async {
-        embed_anything::embed_directory_stream(
+    let data = rt
+        .block_on(embed_anything::embed_directory_stream(
             directory,
             embedding_model,
             extensions,
             config,
             adapter,
-        )
-        .await
-        .map_err(|e| PyValueError::new_err(e.to_string()))
-        .unwrap()
-        .map(|data| {
-            data.into_iter()
-                .map(|data| EmbedData { inner: data })
-                .collect::<Vec<_>>()
-        })
-    });
-    Ok(data)
+        ))
+        .map_err(directory_error)?;
+    Ok(data.map(|data| {
+        data.into_iter()
+            .map(|data| EmbedData { inner: data })
+            .collect::<Vec<_>>()
+    }))
+}
+
+#[pyfunction]
+#[pyo3(signature = (path, config=None))]
+pub fn chunk_preview(
+    path: PathBuf,
+    config: Option<&config::TextEmbedConfig>,
+) -> PyResult<Vec<ChunkPreview>> {
+    let config = config.map(|c| &c.inner);
+    let rt = Builder::new_multi_thread().enable_all().build().unwrap();
+    let previews = rt
+        .block_on(embed_anything::chunk_preview(path, config))
+        .map_err(|e| PyValueError::new_err(e.to_string()))?;
+    Ok(previews
+        .into_iter()
+        .map(|preview| ChunkPreview { inner: preview })
+        .collect())
+}
+
+#[pyfunction]
+#[pyo3(signature = (text, config=None))]
+pub fn chunk_text(text: &str, config: Option<&config::TextEmbedConfig>) -> PyResult<Vec<Chunk>> {
+    let binding = embed_anything::config::TextEmbedConfig::default();
+    let config = config.map(|c| &c.inner).unwrap_or(&binding);
+    let strategy = config
+        .splitting_strategy
+        .unwrap_or(embed_anything::text_loader::SplittingStrategy::Sentence);
+    let chunks = embed_anything::chunkers::chunk_text(text, strategy, config)
+        .map_err(|e| PyValueError::new_err(e.to_string()))?;
+    Ok(chunks
+        .into_iter()
+        .map(|chunk| Chunk { inner: chunk })
+        .collect())
+}
+
+/// The extensions of the files that embed_file can read.
+#[pyfunction]
+pub fn supported_extensions() -> Vec<&'static str> {
+    embed_anything::supported_extensions()
+}
+
+/// Mixes the dense cosine similarity and the sparse dot product of a query and a document.
+#[pyfunction]
+#[pyo3(signature = (dense_query, dense_document, sparse_query, sparse_document, alpha=0.5))]
+pub fn hybrid_score(
+    dense_query: Vec<f32>,
+    dense_document: Vec<f32>,
+    sparse_query: Vec<f32>,
+    sparse_document: Vec<f32>,
+    alpha: f32,
+) -> PyResult<f32> {
+    if dense_query.len() != dense_document.len() || sparse_query.len() != sparse_document.len() {
+        return Err(PyValueError::new_err(
+            "Query and document embeddings have different dimensions",
+        ));
+    }
+    Ok(embed_anything::embeddings::fusion::hybrid_score(
+        &dense_query,
+        &dense_document,
+        &sparse_query,
+        &sparse_document,
+        alpha,
+    ))
+}
+
+/// Merges rankings of document ids with reciprocal rank fusion.
+#[pyfunction]
+#[pyo3(signature = (rankings, k=embed_anything::embeddings::fusion::RRF_K))]
+pub fn rrf(rankings: Vec<Vec<usize>>, k: usize) -> Vec<(usize, f32)> {
+    embed_anything::embeddings::fusion::rrf(rankings, k)
+}
+
+/// Returns the pairwise cosine similarities of `texts` as a 2D numpy array.
+#[pyfunction]
+#[pyo3(signature = (texts, embedder, config=None))]
+pub fn pairwise_similarity(
+    py: Python<'_>,
+    texts: Vec<String>,
+    embedder: &EmbeddingModel,
+    config: Option<&config::TextEmbedConfig>,
+) -> PyResult<PyObject> {
+    let config = config.map(|c| &c.inner);
+    let rt = Builder::new_multi_thread().enable_all().build().unwrap();
+    let similarities = rt
+        .block_on(embed_anything::pairwise_similarity(
+            &texts,
+            &embedder.inner,
+            config,
+        ))
+        .map_err(embedding_error)?;
+    let rows = similarities
+        .outer_iter()
+        .map(|row| row.to_vec())
+        .collect::<Vec<_>>();
+    let array = py
+        .import("numpy")?
+        .call_method1("array", (rows, "float32"))?;
+    // Keep the (0, 0) shape for no texts, which an empty list would lose.
+    Ok(array
+        .call_method1("reshape", (texts.len(), texts.len()))?
+        .unbind())
+}
+
+#[pyfunction]
+#[pyo3(signature = (query, path, embedder, config=None, k=5))]
+pub fn similarity_search(
+    query: &str,
+    path: PathBuf,
+    embedder: &EmbeddingModel,
+    config: Option<&config::TextEmbedConfig>,
+    k: usize,
+) -> PyResult<Vec<SearchResult>> {
+    let config = config.map(|c| &c.inner);
+    if !path.exists() {
+        return Err(PyFileNotFoundError::new_err(format!(
+            "File not found: {:?}",
+            path
+        )));
+    }
+    let rt = Builder::new_multi_thread().enable_all().build().unwrap();
+    let results = rt
+        .block_on(embed_anything::similarity_search(
+            query,
+            path,
+            &embedder.inner,
+            config,
+            k,
+        ))
+        .map_err(embedding_error)?;
+    Ok(results
+        .into_iter()
+        .map(|result| SearchResult { inner: result })
+        .collect())
 }
 
 #[pyfunction]
@@ -559,25 +1422,7 @@ pub fn embed_image_directory(
     let rt = Builder::new_multi_thread().enable_all().build().unwrap();
     println!("Runtime created");
 
-    let adapter = match adapter {
-        Some(adapter) => {
-            let callback = move |data: Vec<embed_anything::embeddings::embed::EmbedData>| {
-                Python::with_gil(|py| {
-                    let upsert_fn = adapter.getattr(py, "upsert").unwrap();
-                    let converted_data = data
-                        .into_iter()
-                        .map(|data| EmbedData { inner: data })
-                        .collect::<Vec<EmbedData>>();
-                    upsert_fn
-                        .call1(py, (converted_data,))
-                        .map_err(|e| PyValueError::new_err(e.to_string()))
-                        .unwrap();
-                });
-            };
-            Some(callback)
-        }
-        None => None,
-    };
+    let adapter = adapter.map(PyAdapter);
 
     let data = rt.block_on(async {
         embed_anything::embed_image_directory(directory, embedding_model, config, adapter)
@@ -603,59 +1448,100 @@ pub fn embed_webpage(
     let embedding_model = &embedder.inner;
     let config = config.map(|c| &c.inner);
     let rt = Builder::new_multi_thread().enable_all().build().unwrap();
-    let adapter = match adapter {
-        Some(adapter) => {
-            let callback = move |data: Vec<embed_anything::embeddings::embed::EmbedData>| {
-                Python::with_gil(|py| {
-                    let upsert_fn = adapter.getattr(py, "upsert").unwrap();
-                    let converted_data = data
-                        .into_iter()
-                        .map(|data| EmbedData { inner: data })
-                        .collect::<Vec<EmbedData>>();
-                    upsert_fn
-                        .call1(py, (converted_data,))
-                        .map_err(|e| PyValueError::new_err(e.to_string()))
-                        .unwrap();
-                });
-            };
-            Some(callback)
-        }
-        None => None,
-    };
+    let adapter = adapter.map(PyAdapter);
+
+    let data = rt
+        .block_on(embed_anything::embed_webpage(
+            url,
+            embedding_model,
+            config,
+            adapter,
+        ))
+        .map_err(embedding_error)?;
+    Ok(data.map(|data| {
+        data.into_iter()
+            .map(|data| EmbedData { inner: data })
+            .collect::<Vec<_>>()
+    }))
+}
+
+#[pyfunction]
+#[pyo3(signature = (urls, embedder, config=None, adapter = None))]
+pub fn embed_urls(
+    urls: Vec<String>,
+    embedder: &EmbeddingModel,
+    config: Option<&config::TextEmbedConfig>,
+    adapter: Option<PyObject>,
+) -> PyResult<Option<Vec<EmbedData>>> {
+    let embedding_model = &embedder.inner;
+    let config = config.map(|c| &c.inner);
+    let rt = Builder::new_multi_thread().enable_all().build().unwrap();
+    let adapter = adapter.map(PyAdapter);
 
     let data = rt.block_on(async {
-        embed_anything::embed_webpage(url, embedding_model, config, adapter)
+        embed_anything::embed_urls(urls, embedding_model, config, adapter)
             .await
-            .map_err(|e| PyValueError::new_err(e.to_string()))
-            .unwrap()
-            .map(|data| {
-                data.into_iter()
-                    .map(|data| EmbedData { inner: data })
-                    .collect::<Vec<_>>()
-            })
-    });
-    Ok(data)
+            .map_err(embedding_error)
+    })?;
+    Ok(data.map(|data| {
+        data.into_iter()
+            .map(|data| EmbedData { inner: data })
+            .collect::<Vec<_>>()
+    }))
 }
 
 #[pymodule]
 fn _embed_anything(m: &Bound<'_, PyModule>) -> PyResult<()> {
     m.add_function(wrap_pyfunction!(embed_file, m)?)?;
+    m.add_function(wrap_pyfunction!(embed_file_multi, m)?)?;
+    m.add_function(wrap_pyfunction!(embed_file_oneshot, m)?)?;
     m.add_function(wrap_pyfunction!(embed_directory, m)?)?;
     m.add_function(wrap_pyfunction!(embed_image_directory, m)?)?;
     m.add_function(wrap_pyfunction!(embed_query, m)?)?;
+    m.add_function(wrap_pyfunction!(try_embed_query, m)?)?;
+    m.add_function(wrap_pyfunction!(embed_records, m)?)?;
     m.add_function(wrap_pyfunction!(embed_webpage, m)?)?;
+    m.add_function(wrap_pyfunction!(embed_urls, m)?)?;
     m.add_function(wrap_pyfunction!(embed_audio_file, m)?)?;
+    m.add_function(wrap_pyfunction!(embed_audio_directory, m)?)?;
+    m.add_function(wrap_pyfunction!(chunk_preview, m)?)?;
+    m.add_function(wrap_pyfunction!(chunk_text, m)?)?;
+    m.add_function(wrap_pyfunction!(supported_extensions, m)?)?;
+    m.add_function(wrap_pyfunction!(similarity_search, m)?)?;
+    m.add_function(wrap_pyfunction!(pairwise_similarity, m)?)?;
+    m.add_function(wrap_pyfunction!(hybrid_score, m)?)?;
+    m.add_function(wrap_pyfunction!(rrf, m)?)?;
     m.add_class::<ColpaliModel>()?;
     m.add_class::<ColbertModel>()?;
     m.add_class::<EmbeddingModel>()?;
+    m.add_class::<ModelRegistry>()?;
+    m.add_class::<SelfCheckReport>()?;
+    m.add_class::<ModelMetadata>()?;
+    m.add_class::<ChunkPreview>()?;
+    m.add_class::<Chunk>()?;
+    m.add_class::<SearchResult>()?;
     m.add_class::<AudioDecoderModel>()?;
     m.add_class::<WhichModel>()?;
     m.add_class::<EmbedData>()?;
     m.add_class::<config::TextEmbedConfig>()?;
+    m.add_class::<config::FileMeta>()?;
+    m.add_class::<config::TruncationEvent>()?;
     m.add_class::<ONNXModel>()?;
     m.add_class::<Reranker>()?;
+    m.add(
+        "BudgetExceededError",
+        m.py().get_type::<BudgetExceededError>(),
+    )?;
     m.add_class::<Dtype>()?;
     m.add_class::<RerankerResult>()?;
     m.add_class::<DocumentRank>()?;
+    #[cfg(feature = "weaviate")]
+    m.add_class::<adapters::weaviate::WeaviateAdapter>()?;
+    #[cfg(feature = "elasticsearch")]
+    m.add_class::<adapters::elasticsearch::ElasticsearchAdapter>()?;
+    #[cfg(feature = "chroma")]
+    m.add_class::<adapters::chroma::ChromaAdapter>()?;
+    #[cfg(feature = "milvus")]
+    m.add_class::<adapters::milvus::MilvusAdapter>()?;
     Ok(())
 }
@@ -1,3 +1,4 @@
 pub mod colbert;
 pub mod colpali;
+pub mod registry;
 pub mod reranker;
@@ -0,0 +1,95 @@
+use std::sync::Arc;
+
+use anyhow::anyhow;
+use embed_anything::embeddings::registry::ModelLoader;
+use pyo3::exceptions::PyValueError;
+use pyo3::prelude::*;
+use tokio::runtime::Builder;
+
+use crate::{config::TextEmbedConfig, EmbedData, EmbeddingModel};
+
+#[pyclass]
+pub struct ModelRegistry {
+    pub inner: embed_anything::embeddings::registry::ModelRegistry,
+}
+
+#[pymethods]
+impl ModelRegistry {
+    #[new]
+    #[pyo3(signature = (max_models=None))]
+    pub fn new(max_models: Option<usize>) -> Self {
+        Self {
+            inner: embed_anything::embeddings::registry::ModelRegistry::new(max_models),
+        }
+    }
+
+    /// Registers `loader`, a callable taking no argument and returning an `EmbeddingModel`,
+    /// under `name`. It is called on the first use of the model.
+    pub fn register(&self, name: &str, loader: PyObject) {
+        let loader: ModelLoader = Arc::new(move || {
+            Python::with_gil(|py| {
+                loader
+                    .call0(py)
+                    .and_then(|model| {
+                        model
+                            .extract::<PyRef<EmbeddingModel>>(py)
+                            .map(|model| Arc::clone(&model.inner))
+                    })
+                    .map_err(|e| anyhow!("Failed to load the model: {}", e))
+            })
+        });
+        self.inner.register_loader(name, loader);
+    }
+
+    pub fn get(&self, name: &str) -> PyResult<EmbeddingModel> {
+        let inner = self
+            .inner
+            .get(name)
+            .map_err(|e| PyValueError::new_err(e.to_string()))?;
+        Ok(EmbeddingModel {
+            inner,
+            loader: None,
+        })
+    }
+
+    #[pyo3(signature = (name, query, config=None))]
+    pub fn embed_query(
+        &self,
+        name: &str,
+        query: Vec<String>,
+        config: Option<&TextEmbedConfig>,
+    ) -> PyResult<Vec<EmbedData>> {
+        let embedder = self
+            .inner
+            .get(name)
+            .map_err(|e| PyValueError::new_err(e.to_string()))?;
+        let config = config.map(|c| &c.inner);
+        let rt = Builder::new_multi_thread().enable_all().build().unwrap();
+        let embeddings = rt
+            .block_on(embed_anything::embed_query(query, &embedder, config))
+            .map_err(|e| PyValueError::new_err(e.to_string()))?;
+        Ok(embeddings
+            .into_iter()
+            .map(|data| EmbedData { inner: data })
+            .collect())
+    }
+
+    pub fn unload(&self, name: &str) -> bool {
+        self.inner.unload(name)
+    }
+
+    #[getter]
+    pub fn registered(&self) -> Vec<String> {
+        self.inner.registered()
+    }
+
+    #[getter]
+    pub fn loaded(&self) -> Vec<String> {
+        self.inner.loaded()
+    }
+
+    #[getter]
+    pub fn max_models(&self) -> Option<usize> {
+        self.inner.max_models()
+    }
+}
@@ -8,7 +8,7 @@ pub struct Reranker {
 }
 
 #[pyclass(eq, eq_int)]
-#[derive(PartialEq)]
+#[derive(PartialEq, Clone)]
 
 pub enum Dtype {
     F16,
@@ -0,0 +1,54 @@
+//! Compares serial and pipelined image decoding when embedding a directory of images with CLIP.
+//!
+//! cargo run --release --example image_decode -- --directory path/to/images
+
+use std::{path::PathBuf, time::Instant};
+
+use clap::Parser;
+use embed_anything::{
+    embeddings::{embed::EmbedImage, local::clip::ClipEmbedder},
+    file_loader::FileParser,
+};
+
+#[derive(Parser, Debug)]
+#[command(author, version, about, long_about = None)]
+struct Args {
+    /// Directory of images to embed
+    #[arg(short, long, default_value = "test_files/clip")]
+    directory: PathBuf,
+    /// Number of decoding threads, one per CPU when not set
+    #[arg(short = 't', long)]
+    decode_threads: Option<usize>,
+    /// Maximum number of decoded images waiting for the model
+    #[arg(short, long, default_value_t = 100)]
+    buffer_size: usize,
+}
+
+fn main() -> anyhow::Result<()> {
+    let args = Args::parse();
+    let images = FileParser::new().get_image_paths(&args.directory)?;
+    anyhow::ensure!(!images.is_empty(), "No images in {:?}", args.directory);
+    let model = ClipEmbedder::default();
+
+    // Warm up so that one-time initialization is not counted.
+    model.embed_image_batch(&images[..1])?;
+
+    let now = Instant::now();
+    let serial = model.embed_image_batch(&images)?;
+    let serial_time = now.elapsed();
+
+    let now = Instant::now();
+    let pipelined =
+        model.embed_image_batch_pipelined(&images, args.decode_threads, args.buffer_size)?;
+    let pipelined_time = now.elapsed();
+
+    assert_eq!(serial.len(), pipelined.len());
+    println!("{} images", images.len());
+    println!("Serial:    {:?}", serial_time);
+    println!("Pipelined: {:?}", pipelined_time);
+    println!(
+        "Speedup:   {:.2}x",
+        serial_time.as_secs_f64() / pipelined_time.as_secs_f64()
+    );
+    Ok(())
+}
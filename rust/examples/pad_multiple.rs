@@ -0,0 +1,71 @@
+//! Compares padding batches to their longest sequence with padding them to a multiple of 8
+//! tokens, which keeps the tensor cores of GPUs busy when the model runs in fp16. Build with the
+//! `cuda` or `metal` feature, padding has no benefit on CPU.
+//!
+//! cargo run --release --features cuda --example pad_multiple -- --multiple 8
+
+use std::time::Instant;
+
+use clap::Parser;
+use embed_anything::embeddings::{
+    embed::ModelDtype,
+    local::bert::{BertEmbed, BertEmbedder},
+    scoped_model_dtype,
+    utils::set_pad_to_multiple_of,
+};
+
+#[derive(Parser, Debug)]
+#[command(author, version, about, long_about = None)]
+struct Args {
+    /// Multiple the sequence lengths are padded to
+    #[arg(short, long, default_value_t = 8)]
+    multiple: usize,
+    /// Number of texts to embed
+    #[arg(short, long, default_value_t = 2048)]
+    texts: usize,
+    /// Batch size
+    #[arg(short, long, default_value_t = 32)]
+    batch_size: usize,
+}
+
+fn main() -> anyhow::Result<()> {
+    let args = Args::parse();
+    let mut model = {
+        let _dtype = scoped_model_dtype(Some(ModelDtype::F16));
+        BertEmbedder::new(
+            "sentence-transformers/all-MiniLM-L6-v2".to_string(),
+            None,
+            None,
+        )?
+    };
+    let device = model.model.device.clone();
+    if device.is_cpu() {
+        println!("Running on CPU, padding to a multiple is not expected to help.");
+    }
+
+    // Lengths vary so that the longest sequence of most batches is not a multiple of 8.
+    let texts = (0..args.texts)
+        .map(|i| "The quick brown fox jumps over the lazy dog. ".repeat(1 + i % 7) + &i.to_string())
+        .collect::<Vec<_>>();
+
+    let mut time = |multiple: Option<usize>| -> anyhow::Result<std::time::Duration> {
+        set_pad_to_multiple_of(&mut model.tokenizer, multiple);
+        // Warm up so that one-time initialization is not counted.
+        let warmup = args.batch_size.min(texts.len());
+        model.embed(&texts[..warmup], Some(args.batch_size))?;
+        let now = Instant::now();
+        model.embed(&texts, Some(args.batch_size))?;
+        Ok(now.elapsed())
+    };
+    let longest = time(None)?;
+    let padded = time(Some(args.multiple))?;
+
+    println!("{} texts on {:?}", texts.len(), device);
+    println!("Longest:              {:?}", longest);
+    println!("Multiple of {:<2}:       {:?}", args.multiple, padded);
+    println!(
+        "Speedup:              {:.2}x",
+        longest.as_secs_f64() / padded.as_secs_f64()
+    );
+    Ok(())
+}
@@ -0,0 +1,61 @@
+//! Compares embedding texts of very different lengths in their order with embedding them
+//! sorted by length, where each batch is padded to a length close to that of its texts.
+//!
+//! cargo run --release --example sort_by_length -- --texts 1024 --batch-size 32
+
+use std::time::Instant;
+
+use clap::Parser;
+use embed_anything::{config::TextEmbedConfig, embed_query, embeddings::embed::Embedder};
+
+#[derive(Parser, Debug)]
+#[command(author, version, about, long_about = None)]
+struct Args {
+    /// Number of texts to embed
+    #[arg(short, long, default_value_t = 1024)]
+    texts: usize,
+    /// Batch size
+    #[arg(short, long, default_value_t = 32)]
+    batch_size: usize,
+}
+
+#[tokio::main]
+async fn main() -> anyhow::Result<()> {
+    let args = Args::parse();
+    let embedder =
+        Embedder::from_pretrained_hf("bert", "sentence-transformers/all-MiniLM-L6-v2", None, None)?;
+
+    // Lengths range from a few tokens to a few hundred, as the chunks of a mix of documents.
+    let texts = (0..args.texts)
+        .map(|i| {
+            "The quick brown fox jumps over the lazy dog. ".repeat(1 + i * 7 % 40) + &i.to_string()
+        })
+        .collect::<Vec<_>>();
+
+    let time = |sort_by_length: bool| {
+        let config = TextEmbedConfig::default()
+            .with_batch_size(args.batch_size)
+            .with_sort_by_length(sort_by_length);
+        let texts = texts.clone();
+        let embedder = &embedder;
+        async move {
+            // Warm up so that one-time initialization is not counted.
+            let warmup = texts[..args.batch_size.min(texts.len())].to_vec();
+            embed_query(warmup, embedder, Some(&config)).await?;
+            let now = Instant::now();
+            embed_query(texts, embedder, Some(&config)).await?;
+            anyhow::Ok(now.elapsed())
+        }
+    };
+    let unsorted = time(false).await?;
+    let sorted = time(true).await?;
+
+    println!("{} texts", texts.len());
+    println!("In order:             {:?}", unsorted);
+    println!("Sorted by length:     {:?}", sorted);
+    println!(
+        "Speedup:              {:.2}x",
+        unsorted.as_secs_f64() / sorted.as_secs_f64()
+    );
+    Ok(())
+}
@@ -0,0 +1,288 @@
+use std::collections::HashMap;
+
+use anyhow::{anyhow, Result};
+use reqwest::blocking::Client;
+use serde::Deserialize;
+use serde_json::{json, Value};
+
+use crate::embeddings::embed::EmbedData;
+
+pub use super::BatchReport;
+use super::{blocking, record_id, Adapter};
+
+/// Number of records sent to Chroma in a single upsert request.
+const DEFAULT_BATCH_SIZE: usize = 1000;
+
+const DEFAULT_TENANT: &str = "default_tenant";
+const DEFAULT_DATABASE: &str = "default_database";
+
+#[derive(Deserialize, Debug)]
+struct Collection {
+    id: String,
+}
+
+/// Stores [EmbedData] in a Chroma collection through the v2 HTTP API. The embeddings computed
+/// by embed_anything are upserted with the text as the document and the metadata as is.
+///
+/// Chroma requires an id for every record. The `id` metadata entry is used when present,
+/// otherwise the id is a hash of the text and metadata, so that embedding the same file again
+/// updates its records instead of duplicating them.
+///
+/// # Example
+///
+/// ```rust,no_run
+/// use embed_anything::adapters::chroma::ChromaAdapter;
+///
+/// let adapter = ChromaAdapter::new("http://localhost:8000", "documents", None, None).unwrap();
+/// let callback = adapter.callback();
+/// ```
+pub struct ChromaAdapter {
+    url: String,
+    collection_name: String,
+    collection_id: String,
+    tenant: String,
+    database: String,
+    batch_size: usize,
+    client: Client,
+}
+
+impl ChromaAdapter {
+    /// Connects to the Chroma server at `host` and creates `collection_name` if it does not
+    /// exist. `tenant` and `database` default to Chroma's `default_tenant` and
+    /// `default_database`.
+    pub fn new(
+        host: &str,
+        collection_name: &str,
+        tenant: Option<&str>,
+        database: Option<&str>,
+    ) -> Result<Self> {
+        blocking(|| {
+            let mut adapter = Self {
+                url: host.trim_end_matches('/').to_string(),
+                collection_name: collection_name.to_string(),
+                collection_id: String::new(),
+                tenant: tenant.unwrap_or(DEFAULT_TENANT).to_string(),
+                database: database.unwrap_or(DEFAULT_DATABASE).to_string(),
+                batch_size: DEFAULT_BATCH_SIZE,
+                client: Client::new(),
+            };
+            adapter.collection_id = adapter.get_or_create_collection()?;
+            Ok(adapter)
+        })
+    }
+
+    pub fn with_batch_size(mut self, batch_size: usize) -> Self {
+        self.batch_size = batch_size.max(1);
+        self
+    }
+
+    pub fn collection_name(&self) -> &str {
+        &self.collection_name
+    }
+
+    fn collections_url(&self) -> String {
+        format!(
+            "{}/api/v2/tenants/{}/databases/{}/collections",
+            self.url, self.tenant, self.database
+        )
+    }
+
+    fn get_or_create_collection(&self) -> Result<String> {
+        let response = self
+            .client
+            .post(self.collections_url())
+            .json(&json!({ "name": self.collection_name, "get_or_create": true }))
+            .send()?;
+        if !response.status().is_success() {
+            return Err(anyhow!(
+                "Failed to create Chroma collection {}: {}",
+                self.collection_name,
+                response.text()?
+            ));
+        }
+        Ok(response.json::<Collection>()?.id)
+    }
+
+    /// Builds the body of an upsert request for `data`.
+    pub fn to_upsert_body(&self, data: &[EmbedData]) -> Result<Value> {
+        let mut ids = Vec::with_capacity(data.len());
+        let mut embeddings = Vec::with_capacity(data.len());
+        let mut metadatas = Vec::with_capacity(data.len());
+        let mut documents = Vec::with_capacity(data.len());
+        // Chroma rejects a request with duplicate ids, repeated chunks get a suffix.
+        let mut seen = HashMap::<String, usize>::new();
+        for embed_data in data {
+            let mut id = record_id(embed_data);
+            let count = seen.entry(id.clone()).or_default();
+            if *count > 0 {
+                id = format!("{}-{}", id, count);
+            }
+            *count += 1;
+            ids.push(id);
+            embeddings.push(embed_data.embedding.to_dense()?);
+            // Chroma rejects empty metadata, records without any get null.
+            metadatas.push(
+                embed_data
+                    .metadata
+                    .as_ref()
+                    .filter(|metadata| !metadata.is_empty())
+                    .map(|metadata| json!(metadata))
+                    .unwrap_or(Value::Null),
+            );
+            documents.push(embed_data.text.clone());
+        }
+        Ok(json!({
+            "ids": ids,
+            "embeddings": embeddings,
+            "metadatas": metadatas,
+            "documents": documents,
+        }))
+    }
+
+    /// Upserts the embeddings into the collection in batches. Chroma accepts or rejects a
+    /// request as a whole, so any failure is returned as an error.
+    pub fn upsert(&self, data: Vec<EmbedData>) -> Result<BatchReport> {
+        blocking(|| self.upsert_batches(&data))
+    }
+
+    fn upsert_batches(&self, data: &[EmbedData]) -> Result<BatchReport> {
+        let mut report = BatchReport::default();
+        for batch in data.chunks(self.batch_size) {
+            let response = self
+                .client
+                .post(format!(
+                    "{}/{}/upsert",
+                    self.collections_url(),
+                    self.collection_id
+                ))
+                .json(&self.to_upsert_body(batch)?)
+                .send()?;
+            if !response.status().is_success() {
+                return Err(anyhow!(
+                    "Chroma upsert request failed: {}",
+                    response.text()?
+                ));
+            }
+            report.inserted += batch.len();
+        }
+        Ok(report)
+    }
+
+    /// Returns a closure that can be passed as the `adapter` argument of the embedding
+    /// functions. Failures are logged.
+    pub fn callback(&self) -> impl Fn(Vec<EmbedData>) + '_ {
+        move |data| {
+            if let Err(e) = self.upsert(data) {
+                tracing::error!("Failed to upsert into {}: {}", self.collection_name, e);
+            }
+        }
+    }
+}
+
+/// Same as [ChromaAdapter::callback]. Records are written on every upsert, so there is nothing to
+/// flush.
+impl Adapter for ChromaAdapter {
+    fn upsert(&self, data: Vec<EmbedData>) {
+        self.callback()(data)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::embeddings::embed::EmbeddingResult;
+
+    fn embed_data(text: &str) -> EmbedData {
+        let metadata = HashMap::from([("file_name".to_string(), "test.pdf".to_string())]);
+        EmbedData::new(
+            EmbeddingResult::DenseVector(vec![0.1, 0.2, 0.3]),
+            Some(text.to_string()),
+            Some(metadata),
+        )
+    }
+
+    #[test]
+    fn test_record_id() {
+        assert_eq!(
+            record_id(&embed_data("hello")),
+            record_id(&embed_data("hello"))
+        );
+        assert_ne!(
+            record_id(&embed_data("hello")),
+            record_id(&embed_data("world"))
+        );
+
+        let mut data = embed_data("hello");
+        data.metadata
+            .as_mut()
+            .unwrap()
+            .insert("id".to_string(), "doc-1".to_string());
+        assert_eq!(record_id(&data), "doc-1");
+    }
+
+    #[test]
+    fn test_upsert_creates_collection() {
+        let mut server = mockito::Server::new();
+        let collections = "/api/v2/tenants/default_tenant/databases/default_database/collections";
+        let create = server
+            .mock("POST", collections)
+            .match_body(mockito::Matcher::PartialJson(json!({
+                "name": "documents",
+                "get_or_create": true
+            })))
+            .with_body(r#"{"id": "1234", "name": "documents"}"#)
+            .expect(1)
+            .create();
+        let id = record_id(&embed_data("hello"));
+        let upsert = server
+            .mock("POST", format!("{}/1234/upsert", collections).as_str())
+            .match_body(mockito::Matcher::PartialJson(json!({
+                "ids": [id, format!("{}-1", id), record_id(&embed_data("world"))],
+                "documents": ["hello", "hello", "world"],
+                "metadatas": [
+                    { "file_name": "test.pdf" },
+                    { "file_name": "test.pdf" },
+                    { "file_name": "test.pdf" }
+                ],
+            })))
+            .with_body("{}")
+            .expect(1)
+            .create();
+
+        let adapter = ChromaAdapter::new(&server.url(), "documents", None, None).unwrap();
+        let report = adapter
+            .upsert(vec![
+                embed_data("hello"),
+                embed_data("hello"),
+                embed_data("world"),
+            ])
+            .unwrap();
+        assert_eq!(report.inserted, 3);
+        assert!(report.is_ok());
+        create.assert();
+        upsert.assert();
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn test_upsert_in_runtime() {
+        // The embedding functions call adapters from async code, where the blocking requests
+        // would panic if they were not moved out of the runtime.
+        let mut server = mockito::Server::new_async().await;
+        let collections = "/api/v2/tenants/default_tenant/databases/default_database/collections";
+        server
+            .mock("POST", collections)
+            .with_body(r#"{"id": "1234", "name": "documents"}"#)
+            .create_async()
+            .await;
+        let upsert = server
+            .mock("POST", format!("{}/1234/upsert", collections).as_str())
+            .with_body("{}")
+            .create_async()
+            .await;
+
+        let adapter = ChromaAdapter::new(&server.url(), "documents", None, None).unwrap();
+        Adapter::upsert(&adapter, vec![embed_data("hello")]);
+
+        upsert.assert_async().await;
+    }
+}
@@ -0,0 +1,524 @@
+use std::{collections::HashMap, str::FromStr, sync::Mutex};
+
+use anyhow::{anyhow, Result};
+use reqwest::blocking::{Client, RequestBuilder};
+use serde::Deserialize;
+use serde_json::{json, Value};
+
+use crate::embeddings::embed::EmbedData;
+
+use super::{blocking, Adapter};
+pub use super::{BatchReport, ObjectError};
+
+/// Number of documents sent to the cluster in a single `_bulk` request.
+const DEFAULT_BATCH_SIZE: usize = 500;
+
+/// Name of the field that holds the vector.
+const DEFAULT_VECTOR_FIELD: &str = "embedding";
+
+/// The search engine behind the url. They accept the same `_bulk` requests but use different
+/// vector mappings.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum Engine {
+    /// Elasticsearch, vectors are stored in a `dense_vector` field.
+    #[default]
+    Elasticsearch,
+    /// OpenSearch, vectors are stored in a `knn_vector` field of a k-NN enabled index.
+    OpenSearch,
+}
+
+/// Similarity used by kNN search on the vector field.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum Similarity {
+    #[default]
+    Cosine,
+    /// Requires normalized vectors on Elasticsearch.
+    DotProduct,
+    L2Norm,
+}
+
+impl Similarity {
+    fn name(&self, engine: Engine) -> &'static str {
+        match (engine, self) {
+            (Engine::Elasticsearch, Similarity::Cosine) => "cosine",
+            (Engine::Elasticsearch, Similarity::DotProduct) => "dot_product",
+            (Engine::Elasticsearch, Similarity::L2Norm) => "l2_norm",
+            (Engine::OpenSearch, Similarity::Cosine) => "cosinesimil",
+            (Engine::OpenSearch, Similarity::DotProduct) => "innerproduct",
+            (Engine::OpenSearch, Similarity::L2Norm) => "l2",
+        }
+    }
+}
+
+impl FromStr for Similarity {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s {
+            "cosine" => Ok(Similarity::Cosine),
+            "dot_product" => Ok(Similarity::DotProduct),
+            "l2_norm" => Ok(Similarity::L2Norm),
+            _ => Err(anyhow!(
+                "Unknown similarity {}, expected one of cosine, dot_product, l2_norm",
+                s
+            )),
+        }
+    }
+}
+
+enum Auth {
+    ApiKey(String),
+    Basic(String, String),
+}
+
+#[derive(Deserialize, Debug)]
+struct BulkResponse {
+    errors: bool,
+    items: Vec<HashMap<String, BulkItem>>,
+}
+
+#[derive(Deserialize, Debug)]
+struct BulkItem {
+    #[serde(rename = "_id")]
+    id: Option<String>,
+    error: Option<BulkItemError>,
+}
+
+#[derive(Deserialize, Debug)]
+struct BulkItemError {
+    #[serde(rename = "type")]
+    kind: String,
+    reason: Option<String>,
+}
+
+/// Stores [EmbedData] in an Elasticsearch or OpenSearch index for kNN search. The index is
+/// created on the first upsert, with a vector field sized to the embeddings. `text` and
+/// `metadata` are stored as document fields of the same name.
+///
+/// # Example
+///
+/// ```rust,no_run
+/// use embed_anything::adapters::elasticsearch::{ElasticsearchAdapter, Similarity};
+///
+/// let adapter = ElasticsearchAdapter::new("http://localhost:9200", "documents", None)
+///     .with_similarity(Similarity::DotProduct);
+/// let callback = adapter.callback();
+/// ```
+pub struct ElasticsearchAdapter {
+    url: String,
+    index_name: String,
+    auth: Option<Auth>,
+    engine: Engine,
+    similarity: Similarity,
+    vector_field: String,
+    batch_size: usize,
+    client: Client,
+    index_ready: Mutex<bool>,
+}
+
+impl ElasticsearchAdapter {
+    /// Creates an adapter for `index_name` on the cluster at `url`. `api_key` is sent as an
+    /// `ApiKey` authorization header.
+    pub fn new(url: &str, index_name: &str, api_key: Option<String>) -> Self {
+        Self {
+            url: url.trim_end_matches('/').to_string(),
+            index_name: index_name.to_string(),
+            auth: api_key.map(Auth::ApiKey),
+            engine: Engine::default(),
+            similarity: Similarity::default(),
+            vector_field: DEFAULT_VECTOR_FIELD.to_string(),
+            batch_size: DEFAULT_BATCH_SIZE,
+            client: blocking(Client::new),
+            index_ready: Mutex::new(false),
+        }
+    }
+
+    pub fn with_basic_auth(mut self, username: &str, password: &str) -> Self {
+        self.auth = Some(Auth::Basic(username.to_string(), password.to_string()));
+        self
+    }
+
+    pub fn with_engine(mut self, engine: Engine) -> Self {
+        self.engine = engine;
+        self
+    }
+
+    pub fn with_similarity(mut self, similarity: Similarity) -> Self {
+        self.similarity = similarity;
+        self
+    }
+
+    pub fn with_vector_field(mut self, vector_field: &str) -> Self {
+        self.vector_field = vector_field.to_string();
+        self
+    }
+
+    pub fn with_batch_size(mut self, batch_size: usize) -> Self {
+        self.batch_size = batch_size.max(1);
+        self
+    }
+
+    pub fn index_name(&self) -> &str {
+        &self.index_name
+    }
+
+    fn authorize(&self, request: RequestBuilder) -> RequestBuilder {
+        match &self.auth {
+            Some(Auth::ApiKey(api_key)) => {
+                request.header("Authorization", format!("ApiKey {}", api_key))
+            }
+            Some(Auth::Basic(username, password)) => request.basic_auth(username, Some(password)),
+            None => request,
+        }
+    }
+
+    /// Index settings and mappings for vectors of `dims` dimensions.
+    pub fn index_body(&self, dims: usize) -> Value {
+        let similarity = self.similarity.name(self.engine);
+        let vector = match self.engine {
+            Engine::Elasticsearch => json!({
+                "type": "dense_vector",
+                "dims": dims,
+                "index": true,
+                "similarity": similarity,
+            }),
+            Engine::OpenSearch => json!({
+                "type": "knn_vector",
+                "dimension": dims,
+                "method": { "name": "hnsw", "space_type": similarity },
+            }),
+        };
+        let mut body = json!({
+            "mappings": {
+                "properties": {
+                    "text": { "type": "text" },
+                    "metadata": { "type": "object" },
+                    self.vector_field.clone(): vector,
+                }
+            }
+        });
+        if self.engine == Engine::OpenSearch {
+            body["settings"] = json!({ "index": { "knn": true } });
+        }
+        body
+    }
+
+    fn create_index_if_absent(&self, dims: usize) -> Result<()> {
+        let mut index_ready = self.index_ready.lock().unwrap();
+        if *index_ready {
+            return Ok(());
+        }
+        let index_url = format!("{}/{}", self.url, self.index_name);
+        let response = self.authorize(self.client.head(&index_url)).send()?;
+        if response.status() == reqwest::StatusCode::NOT_FOUND {
+            let response = self
+                .authorize(self.client.put(&index_url))
+                .json(&self.index_body(dims))
+                .send()?;
+            if !response.status().is_success() {
+                let message = response.text()?;
+                // Another writer created the index in the meantime.
+                if !message.contains("resource_already_exists_exception") {
+                    return Err(anyhow!(
+                        "Failed to create index {}: {}",
+                        self.index_name,
+                        message
+                    ));
+                }
+            }
+        } else if !response.status().is_success() {
+            return Err(anyhow!(
+                "Failed to fetch index {}: {}",
+                self.index_name,
+                response.status()
+            ));
+        }
+        *index_ready = true;
+        Ok(())
+    }
+
+    /// Builds the newline delimited body of a `_bulk` request indexing `data`.
+    pub fn to_bulk_body(&self, data: &[EmbedData]) -> Result<String> {
+        let action = json!({ "index": { "_index": self.index_name } }).to_string();
+        let mut body = String::new();
+        for embed_data in data {
+            let mut document = json!({
+                "text": embed_data.text,
+                "metadata": embed_data.metadata.clone().unwrap_or_default(),
+            });
+            document[&self.vector_field] = json!(embed_data.embedding.to_dense()?);
+            body.push_str(&action);
+            body.push('\n');
+            body.push_str(&document.to_string());
+            body.push('\n');
+        }
+        Ok(body)
+    }
+
+    /// Indexes the embeddings with the `_bulk` API, creating the index first if needed.
+    /// Request level failures are returned as an error, failures of individual documents are
+    /// collected in the [BatchReport].
+    pub fn upsert(&self, data: Vec<EmbedData>) -> Result<BatchReport> {
+        blocking(|| self.upsert_batches(&data))
+    }
+
+    fn upsert_batches(&self, data: &[EmbedData]) -> Result<BatchReport> {
+        let mut report = BatchReport::default();
+        let Some(first) = data.first() else {
+            return Ok(report);
+        };
+        self.create_index_if_absent(first.embedding.to_dense()?.len())?;
+
+        for (batch_index, batch) in data.chunks(self.batch_size).enumerate() {
+            let offset = batch_index * self.batch_size;
+            let response = self
+                .authorize(self.client.post(format!("{}/_bulk", self.url)))
+                .header("Content-Type", "application/x-ndjson")
+                .body(self.to_bulk_body(batch)?)
+                .send()?;
+            if !response.status().is_success() {
+                return Err(anyhow!("Bulk request failed: {}", response.text()?));
+            }
+            let response = response.json::<BulkResponse>()?;
+            let errors = collect_errors(response, offset);
+            report.inserted += batch.len() - errors.len();
+            report.errors.extend(errors);
+        }
+        Ok(report)
+    }
+
+    /// Returns a closure that can be passed as the `adapter` argument of the embedding
+    /// functions. Failed documents are logged.
+    pub fn callback(&self) -> impl Fn(Vec<EmbedData>) + '_ {
+        move |data| match self.upsert(data) {
+            Ok(report) => {
+                for error in report.errors {
+                    tracing::error!(
+                        "Failed to index document {} into {}: {}",
+                        error.index,
+                        self.index_name,
+                        error.message
+                    );
+                }
+            }
+            Err(e) => tracing::error!("Failed to upsert into {}: {}", self.index_name, e),
+        }
+    }
+
+    /// Refreshes the index, so that the documents indexed so far show up in searches without
+    /// waiting for the periodic refresh. Does nothing before the first upsert.
+    pub fn refresh(&self) -> Result<()> {
+        if !*self.index_ready.lock().unwrap() {
+            return Ok(());
+        }
+        blocking(|| self.refresh_index())
+    }
+
+    fn refresh_index(&self) -> Result<()> {
+        let response = self
+            .authorize(
+                self.client
+                    .post(format!("{}/{}/_refresh", self.url, self.index_name)),
+            )
+            .send()?;
+        if !response.status().is_success() {
+            return Err(anyhow!(
+                "Failed to refresh index {}: {}",
+                self.index_name,
+                response.text()?
+            ));
+        }
+        Ok(())
+    }
+}
+
+/// Same as [ElasticsearchAdapter::callback], the index is refreshed on flush.
+impl Adapter for ElasticsearchAdapter {
+    fn upsert(&self, data: Vec<EmbedData>) {
+        self.callback()(data)
+    }
+
+    fn flush(&self) {
+        if let Err(e) = self.refresh() {
+            tracing::error!("{}", e);
+        }
+    }
+}
+
+fn collect_errors(response: BulkResponse, offset: usize) -> Vec<ObjectError> {
+    if !response.errors {
+        return Vec::new();
+    }
+    response
+        .items
+        .into_iter()
+        .enumerate()
+        .filter_map(|(index, item)| {
+            // Each item is keyed by its action, `index` here.
+            let item = item.into_values().next()?;
+            let error = item.error?;
+            let message = match error.reason {
+                Some(reason) => format!("{}: {}", error.kind, reason),
+                None => error.kind,
+            };
+            Some(ObjectError {
+                index: offset + index,
+                id: item.id,
+                message,
+            })
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::embeddings::embed::EmbeddingResult;
+
+    fn embed_data(text: &str) -> EmbedData {
+        let metadata = HashMap::from([("file_name".to_string(), "test.pdf".to_string())]);
+        EmbedData::new(
+            EmbeddingResult::DenseVector(vec![0.1, 0.2, 0.3]),
+            Some(text.to_string()),
+            Some(metadata),
+        )
+    }
+
+    #[test]
+    fn test_index_body() {
+        let adapter = ElasticsearchAdapter::new("http://localhost:9200", "documents", None);
+        let body = adapter.index_body(384);
+        let vector = &body["mappings"]["properties"]["embedding"];
+        assert_eq!(vector["type"], "dense_vector");
+        assert_eq!(vector["dims"], 384);
+        assert_eq!(vector["similarity"], "cosine");
+
+        let adapter = adapter
+            .with_engine(Engine::OpenSearch)
+            .with_similarity(Similarity::DotProduct);
+        let body = adapter.index_body(384);
+        let vector = &body["mappings"]["properties"]["embedding"];
+        assert_eq!(vector["type"], "knn_vector");
+        assert_eq!(vector["method"]["space_type"], "innerproduct");
+        assert_eq!(body["settings"]["index"]["knn"], true);
+    }
+
+    #[test]
+    fn test_to_bulk_body() {
+        let adapter = ElasticsearchAdapter::new("http://localhost:9200", "documents", None);
+        let body = adapter.to_bulk_body(&[embed_data("hello")]).unwrap();
+        let lines = body.lines().collect::<Vec<_>>();
+        assert_eq!(lines.len(), 2);
+        let action: Value = serde_json::from_str(lines[0]).unwrap();
+        assert_eq!(action["index"]["_index"], "documents");
+        let document: Value = serde_json::from_str(lines[1]).unwrap();
+        assert_eq!(document["text"], "hello");
+        assert_eq!(document["metadata"]["file_name"], "test.pdf");
+        assert_eq!(document["embedding"].as_array().unwrap().len(), 3);
+    }
+
+    #[test]
+    fn test_upsert_creates_index_and_reports_errors() {
+        let mut server = mockito::Server::new();
+        let head = server
+            .mock("HEAD", "/documents")
+            .with_status(404)
+            .expect(1)
+            .create();
+        let create = server
+            .mock("PUT", "/documents")
+            .match_body(mockito::Matcher::PartialJson(json!({
+                "mappings": { "properties": { "embedding": { "dims": 3 } } }
+            })))
+            .with_body(r#"{"acknowledged": true}"#)
+            .expect(1)
+            .create();
+        let bulk = server
+            .mock("POST", "/_bulk")
+            .match_header("authorization", "ApiKey secret")
+            .with_body(
+                json!({
+                    "errors": true,
+                    "items": [
+                        { "index": { "_id": "a", "status": 201 } },
+                        { "index": { "_id": "b", "status": 400, "error": {
+                            "type": "document_parsing_exception",
+                            "reason": "bad vector"
+                        } } }
+                    ]
+                })
+                .to_string(),
+            )
+            .expect(2)
+            .create();
+
+        let adapter =
+            ElasticsearchAdapter::new(&server.url(), "documents", Some("secret".to_string()));
+        let report = adapter
+            .upsert(vec![embed_data("hello"), embed_data("world")])
+            .unwrap();
+        assert_eq!(report.inserted, 1);
+        assert_eq!(report.errors.len(), 1);
+        assert_eq!(report.errors[0].index, 1);
+        assert_eq!(report.errors[0].id.as_deref(), Some("b"));
+        assert_eq!(
+            report.errors[0].message,
+            "document_parsing_exception: bad vector"
+        );
+
+        // The index is only checked once.
+        adapter
+            .upsert(vec![embed_data("hello"), embed_data("world")])
+            .unwrap();
+        head.assert();
+        create.assert();
+        bulk.assert();
+    }
+
+    #[test]
+    fn test_flush_refreshes_index() {
+        let mut server = mockito::Server::new();
+        server.mock("HEAD", "/documents").create();
+        server
+            .mock("POST", "/_bulk")
+            .with_body(r#"{"errors": false, "items": []}"#)
+            .create();
+        let refresh = server
+            .mock("POST", "/documents/_refresh")
+            .with_body(r#"{"_shards": {"failed": 0}}"#)
+            .expect(1)
+            .create();
+
+        let adapter = ElasticsearchAdapter::new(&server.url(), "documents", None);
+        // Nothing to refresh before the index exists.
+        Adapter::flush(&adapter);
+        Adapter::upsert(&adapter, vec![embed_data("hello")]);
+        Adapter::flush(&adapter);
+        refresh.assert();
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn test_upsert_in_runtime() {
+        // The embedding functions call adapters from async code, where the blocking requests
+        // would panic if they were not moved out of the runtime.
+        let mut server = mockito::Server::new_async().await;
+        server.mock("HEAD", "/documents").create_async().await;
+        let bulk = server
+            .mock("POST", "/_bulk")
+            .with_body(r#"{"errors": false, "items": []}"#)
+            .create_async()
+            .await;
+        let refresh = server
+            .mock("POST", "/documents/_refresh")
+            .with_body(r#"{"_shards": {"failed": 0}}"#)
+            .create_async()
+            .await;
+
+        let adapter = ElasticsearchAdapter::new(&server.url(), "documents", None);
+        Adapter::upsert(&adapter, vec![embed_data("hello")]);
+        Adapter::flush(&adapter);
+
+        bulk.assert_async().await;
+        refresh.assert_async().await;
+    }
+}
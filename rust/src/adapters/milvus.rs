@@ -0,0 +1,440 @@
+//! Stores embeddings in Milvus, or Zilliz Cloud, through the v2 RESTful API served by Milvus
+//! 2.4 and later on the same port as gRPC, 19530 by default.
+//!
+//! The collection is created on the first upsert with four fields:
+//!
+//! - `id`, a `VarChar` primary key, see [MilvusAdapter],
+//! - `vector`, a `FloatVector` sized to the embeddings, with an `AUTOINDEX` index,
+//! - `text`, a `VarChar` of at most 65535 bytes, longer texts are cut,
+//! - `metadata`, a `JSON` field.
+//!
+//! Milvus only stores the fields declared in the schema, and the metadata keys differ between
+//! files. They are kept together in the `metadata` JSON field, which can still be filtered on
+//! with expressions such as `metadata["file_name"] == "report.pdf"`.
+//!
+//! To try it locally, start Milvus standalone with Docker as described in the Milvus
+//! documentation, then pass `http://localhost:19530` as the uri. On Zilliz Cloud, use the
+//! public endpoint of the cluster and an API key as the token.
+
+use std::{collections::HashMap, str::FromStr, sync::Mutex};
+
+use anyhow::{anyhow, Result};
+use reqwest::blocking::Client;
+use serde::Deserialize;
+use serde_json::{json, Value};
+
+use crate::embeddings::embed::EmbedData;
+
+pub use super::BatchReport;
+use super::{blocking, record_id, Adapter};
+
+/// Number of entities sent to Milvus in a single upsert request.
+const DEFAULT_BATCH_SIZE: usize = 500;
+
+/// Maximum length in bytes of the `text` field.
+const MAX_TEXT_LENGTH: usize = 65535;
+
+/// Metric used to compare vectors, set when the index is created.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum MetricType {
+    #[default]
+    Cosine,
+    /// Inner product, which equals the cosine similarity for normalized vectors.
+    Ip,
+    L2,
+}
+
+impl MetricType {
+    fn name(&self) -> &'static str {
+        match self {
+            MetricType::Cosine => "COSINE",
+            MetricType::Ip => "IP",
+            MetricType::L2 => "L2",
+        }
+    }
+}
+
+impl FromStr for MetricType {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s.to_lowercase().as_str() {
+            "cosine" => Ok(MetricType::Cosine),
+            "ip" => Ok(MetricType::Ip),
+            "l2" => Ok(MetricType::L2),
+            _ => Err(anyhow!(
+                "Unknown metric type {}, expected one of cosine, ip, l2",
+                s
+            )),
+        }
+    }
+}
+
+/// Body of every Milvus response. Errors are reported with a non zero `code` and a status of
+/// 200.
+#[derive(Deserialize, Debug)]
+struct MilvusResponse {
+    code: i64,
+    #[serde(default)]
+    message: String,
+    #[serde(default)]
+    data: Value,
+}
+
+/// Stores [EmbedData] in a Milvus collection, which is created on the first upsert. See the
+/// [module documentation](self) for the schema.
+///
+/// Entities are upserted, so that embedding the same file again updates them. The id is the
+/// `id` metadata entry when present, otherwise a hash of the text and metadata.
+///
+/// # Example
+///
+/// ```rust,no_run
+/// use embed_anything::adapters::milvus::MilvusAdapter;
+///
+/// let adapter = MilvusAdapter::new("http://localhost:19530", "documents", None);
+/// let callback = adapter.callback();
+/// ```
+pub struct MilvusAdapter {
+    uri: String,
+    collection_name: String,
+    token: Option<String>,
+    database: Option<String>,
+    metric_type: MetricType,
+    batch_size: usize,
+    client: Client,
+    collection_ready: Mutex<bool>,
+}
+
+impl MilvusAdapter {
+    /// Creates an adapter for `collection_name` on the server at `uri`. `token` is either an
+    /// API key or `username:password`, and is sent as a bearer token.
+    pub fn new(uri: &str, collection_name: &str, token: Option<String>) -> Self {
+        Self {
+            uri: uri.trim_end_matches('/').to_string(),
+            collection_name: collection_name.to_string(),
+            token,
+            database: None,
+            metric_type: MetricType::default(),
+            batch_size: DEFAULT_BATCH_SIZE,
+            client: blocking(Client::new),
+            collection_ready: Mutex::new(false),
+        }
+    }
+
+    pub fn with_basic_auth(mut self, username: &str, password: &str) -> Self {
+        self.token = Some(format!("{}:{}", username, password));
+        self
+    }
+
+    /// Uses the database `database` instead of `default`.
+    pub fn with_database(mut self, database: &str) -> Self {
+        self.database = Some(database.to_string());
+        self
+    }
+
+    pub fn with_metric_type(mut self, metric_type: MetricType) -> Self {
+        self.metric_type = metric_type;
+        self
+    }
+
+    pub fn with_batch_size(mut self, batch_size: usize) -> Self {
+        self.batch_size = batch_size.max(1);
+        self
+    }
+
+    pub fn collection_name(&self) -> &str {
+        &self.collection_name
+    }
+
+    /// Sends `body` to the endpoint `path` and returns the `data` of the response, or an error
+    /// when the request or the operation failed.
+    fn post(&self, path: &str, mut body: Value) -> Result<Value> {
+        body["collectionName"] = json!(self.collection_name);
+        if let Some(database) = &self.database {
+            body["dbName"] = json!(database);
+        }
+        let mut request = self
+            .client
+            .post(format!("{}/v2/vectordb/{}", self.uri, path))
+            .json(&body);
+        if let Some(token) = &self.token {
+            request = request.bearer_auth(token);
+        }
+        let response = request.send()?;
+        if !response.status().is_success() {
+            return Err(anyhow!(
+                "Milvus request {} failed: {}",
+                path,
+                response.text()?
+            ));
+        }
+        let response = response.json::<MilvusResponse>()?;
+        if response.code != 0 {
+            return Err(anyhow!(
+                "Milvus request {} failed with code {}: {}",
+                path,
+                response.code,
+                response.message
+            ));
+        }
+        Ok(response.data)
+    }
+
+    /// Schema and index parameters of a collection for vectors of `dim` dimensions.
+    pub fn collection_body(&self, dim: usize) -> Value {
+        json!({
+            "schema": {
+                "autoId": false,
+                "enableDynamicField": false,
+                "fields": [
+                    {
+                        "fieldName": "id",
+                        "dataType": "VarChar",
+                        "isPrimary": true,
+                        "elementTypeParams": { "max_length": 512 }
+                    },
+                    {
+                        "fieldName": "vector",
+                        "dataType": "FloatVector",
+                        "elementTypeParams": { "dim": dim }
+                    },
+                    {
+                        "fieldName": "text",
+                        "dataType": "VarChar",
+                        "elementTypeParams": { "max_length": MAX_TEXT_LENGTH }
+                    },
+                    { "fieldName": "metadata", "dataType": "JSON" }
+                ]
+            },
+            "indexParams": [
+                {
+                    "fieldName": "vector",
+                    "indexName": "vector",
+                    "indexType": "AUTOINDEX",
+                    "metricType": self.metric_type.name()
+                }
+            ]
+        })
+    }
+
+    fn create_collection_if_absent(&self, dim: usize) -> Result<()> {
+        let mut collection_ready = self.collection_ready.lock().unwrap();
+        if *collection_ready {
+            return Ok(());
+        }
+        let exists = self.post("collections/has", json!({}))?["has"]
+            .as_bool()
+            .unwrap_or(false);
+        if !exists {
+            if let Err(e) = self.post("collections/create", self.collection_body(dim)) {
+                // Another writer created the collection in the meantime.
+                if !e.to_string().contains("already exist") {
+                    return Err(anyhow!(
+                        "Failed to create collection {}: {}",
+                        self.collection_name,
+                        e
+                    ));
+                }
+            }
+        }
+        *collection_ready = true;
+        Ok(())
+    }
+
+    /// Builds the body of an upsert request for `data`.
+    pub fn to_upsert_body(&self, data: &[EmbedData]) -> Result<Value> {
+        let mut entities = Vec::with_capacity(data.len());
+        // Repeated chunks get a suffix, so that they are not collapsed into one entity.
+        let mut seen = HashMap::<String, usize>::new();
+        for embed_data in data {
+            let mut id = record_id(embed_data);
+            let count = seen.entry(id.clone()).or_default();
+            if *count > 0 {
+                id = format!("{}-{}", id, count);
+            }
+            *count += 1;
+            entities.push(json!({
+                "id": id,
+                "vector": embed_data.embedding.to_dense()?,
+                "text": truncate_text(embed_data.text.as_deref().unwrap_or_default()),
+                "metadata": embed_data.metadata.clone().unwrap_or_default(),
+            }));
+        }
+        Ok(json!({ "data": entities }))
+    }
+
+    /// Upserts the embeddings into the collection in batches, creating it first if needed.
+    /// Milvus accepts or rejects a request as a whole, so any failure is returned as an error.
+    pub fn upsert(&self, data: Vec<EmbedData>) -> Result<BatchReport> {
+        blocking(|| self.upsert_batches(&data))
+    }
+
+    fn upsert_batches(&self, data: &[EmbedData]) -> Result<BatchReport> {
+        let mut report = BatchReport::default();
+        let Some(first) = data.first() else {
+            return Ok(report);
+        };
+        self.create_collection_if_absent(first.embedding.to_dense()?.len())?;
+
+        for batch in data.chunks(self.batch_size) {
+            let response = self.post("entities/upsert", self.to_upsert_body(batch)?)?;
+            report.inserted += response["upsertCount"]
+                .as_u64()
+                .map_or(batch.len(), |count| count as usize);
+        }
+        Ok(report)
+    }
+
+    /// Returns a closure that can be passed as the `adapter` argument of the embedding
+    /// functions. Failures are logged.
+    pub fn callback(&self) -> impl Fn(Vec<EmbedData>) + '_ {
+        move |data| {
+            if let Err(e) = self.upsert(data) {
+                tracing::error!("Failed to upsert into {}: {}", self.collection_name, e);
+            }
+        }
+    }
+}
+
+/// Same as [MilvusAdapter::callback]. Records are written on every upsert, so there is nothing to
+/// flush.
+impl Adapter for MilvusAdapter {
+    fn upsert(&self, data: Vec<EmbedData>) {
+        self.callback()(data)
+    }
+}
+
+/// Cuts `text` to the maximum length of the `text` field, at a character boundary.
+fn truncate_text(text: &str) -> &str {
+    if text.len() <= MAX_TEXT_LENGTH {
+        return text;
+    }
+    let mut end = MAX_TEXT_LENGTH;
+    while !text.is_char_boundary(end) {
+        end -= 1;
+    }
+    &text[..end]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::embeddings::embed::EmbeddingResult;
+
+    fn embed_data(text: &str) -> EmbedData {
+        let metadata = HashMap::from([("file_name".to_string(), "test.pdf".to_string())]);
+        EmbedData::new(
+            EmbeddingResult::DenseVector(vec![0.1, 0.2, 0.3]),
+            Some(text.to_string()),
+            Some(metadata),
+        )
+    }
+
+    #[test]
+    fn test_collection_body() {
+        let adapter = MilvusAdapter::new("http://localhost:19530", "documents", None)
+            .with_metric_type(MetricType::Ip);
+        let body = adapter.collection_body(384);
+        let fields = body["schema"]["fields"].as_array().unwrap();
+        assert_eq!(fields[1]["elementTypeParams"]["dim"], 384);
+        assert_eq!(fields[3]["dataType"], "JSON");
+        assert_eq!(body["indexParams"][0]["metricType"], "IP");
+        assert_eq!("L2".parse::<MetricType>().unwrap(), MetricType::L2);
+    }
+
+    #[test]
+    fn test_truncate_text() {
+        let text = "é".repeat(MAX_TEXT_LENGTH);
+        let truncated = truncate_text(&text);
+        assert!(truncated.len() <= MAX_TEXT_LENGTH);
+        assert_eq!(truncated.chars().count(), MAX_TEXT_LENGTH / 2);
+        assert_eq!(truncate_text("hello"), "hello");
+    }
+
+    #[test]
+    fn test_upsert_creates_collection() {
+        let mut server = mockito::Server::new();
+        let has = server
+            .mock("POST", "/v2/vectordb/collections/has")
+            .match_header("authorization", "Bearer root:Milvus")
+            .match_body(mockito::Matcher::PartialJson(json!({
+                "collectionName": "documents"
+            })))
+            .with_body(r#"{"code": 0, "data": {"has": false}}"#)
+            .expect(1)
+            .create();
+        let create = server
+            .mock("POST", "/v2/vectordb/collections/create")
+            .match_body(mockito::Matcher::PartialJson(json!({
+                "collectionName": "documents",
+                "indexParams": [{ "fieldName": "vector", "metricType": "COSINE" }]
+            })))
+            .with_body(r#"{"code": 0, "data": {}}"#)
+            .expect(1)
+            .create();
+        let id = record_id(&embed_data("hello"));
+        let upsert = server
+            .mock("POST", "/v2/vectordb/entities/upsert")
+            .match_body(mockito::Matcher::PartialJson(json!({
+                "collectionName": "documents",
+                "data": [
+                    { "id": id, "text": "hello", "metadata": { "file_name": "test.pdf" } },
+                    { "id": format!("{}-1", id), "text": "hello" },
+                    { "id": record_id(&embed_data("world")), "text": "world" }
+                ]
+            })))
+            .with_body(r#"{"code": 0, "data": {"upsertCount": 3}}"#)
+            .expect(1)
+            .create();
+
+        let adapter =
+            MilvusAdapter::new(&server.url(), "documents", None).with_basic_auth("root", "Milvus");
+        let data = vec![
+            embed_data("hello"),
+            embed_data("hello"),
+            embed_data("world"),
+        ];
+        let report = adapter.upsert(data).unwrap();
+        assert_eq!(report.inserted, 3);
+        assert!(report.is_ok());
+        has.assert();
+        create.assert();
+        upsert.assert();
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn test_upsert_in_runtime() {
+        // The embedding functions call adapters from async code, where the blocking requests
+        // would panic if they were not moved out of the runtime.
+        let mut server = mockito::Server::new_async().await;
+        server
+            .mock("POST", "/v2/vectordb/collections/has")
+            .with_body(r#"{"code": 0, "data": {"has": true}}"#)
+            .create_async()
+            .await;
+        let upsert = server
+            .mock("POST", "/v2/vectordb/entities/upsert")
+            .with_body(r#"{"code": 0, "data": {"upsertCount": 1}}"#)
+            .create_async()
+            .await;
+
+        let adapter = MilvusAdapter::new(&server.url(), "documents", None);
+        Adapter::upsert(&adapter, vec![embed_data("hello")]);
+
+        upsert.assert_async().await;
+    }
+
+    #[test]
+    fn test_error_code() {
+        let mut server = mockito::Server::new();
+        server
+            .mock("POST", "/v2/vectordb/collections/has")
+            .with_body(r#"{"code": 1800, "message": "user hasn't authenticated"}"#)
+            .create();
+
+        let adapter = MilvusAdapter::new(&server.url(), "documents", None);
+        let error = adapter.upsert(vec![embed_data("hello")]).unwrap_err();
+        assert!(error.to_string().contains("user hasn't authenticated"));
+    }
+}
@@ -0,0 +1,144 @@
+//! Vector database adapters that can be passed as the `adapter` argument of the embedding
+//! functions, see [Adapter]. Each adapter lives behind its own cargo feature.
+
+/// Weaviate adapter using the REST batch API.
+#[cfg(feature = "weaviate")]
+pub mod weaviate;
+
+/// Elasticsearch and OpenSearch adapter using the `_bulk` API.
+#[cfg(feature = "elasticsearch")]
+pub mod elasticsearch;
+
+/// Chroma adapter using the v2 HTTP API.
+#[cfg(feature = "chroma")]
+pub mod chroma;
+
+/// Milvus adapter using the v2 RESTful API.
+#[cfg(feature = "milvus")]
+pub mod milvus;
+
+use crate::embeddings::embed::EmbedData;
+
+/// Receives the embeddings produced by the embedding functions, e.g. to store them in a vector
+/// database. Closures taking a `Vec<EmbedData>` are adapters that don't need flushing.
+///
+/// Adapters that buffer records to write them in large batches commit the last, partial batch
+/// in [Adapter::flush]. [embed_file](crate::embed_file),
+/// [embed_directory_stream](crate::embed_directory_stream) and the other functions taking an
+/// adapter call it once, after the last upsert, when embedding succeeds. When it fails, the
+/// records upserted so far are left to the caller, who can still flush them.
+///
+/// ```rust
+/// use std::sync::Mutex;
+///
+/// use embed_anything::{adapters::Adapter, embeddings::embed::EmbedData};
+///
+/// struct BufferedWriter {
+///     buffer: Mutex<Vec<EmbedData>>,
+/// }
+///
+/// impl BufferedWriter {
+///     fn write(&self, records: Vec<EmbedData>) {
+///         println!("Writing {} records", records.len());
+///     }
+/// }
+///
+/// impl Adapter for BufferedWriter {
+///     fn upsert(&self, data: Vec<EmbedData>) {
+///         let mut buffer = self.buffer.lock().unwrap();
+///         buffer.extend(data);
+///         if buffer.len() >= 1000 {
+///             self.write(std::mem::take(&mut buffer));
+///         }
+///     }
+///
+///     fn flush(&self) {
+///         self.write(std::mem::take(&mut self.buffer.lock().unwrap()));
+///     }
+/// }
+/// ```
+pub trait Adapter {
+    /// Receives a batch of embeddings, of at most
+    /// [buffer_size](crate::config::TextEmbedConfig::buffer_size) embeddings when streaming.
+    fn upsert(&self, data: Vec<EmbedData>);
+
+    /// Called once all embeddings were upserted. Does nothing by default.
+    fn flush(&self) {}
+}
+
+impl<F: Fn(Vec<EmbedData>)> Adapter for F {
+    fn upsert(&self, data: Vec<EmbedData>) {
+        self(data)
+    }
+}
+
+/// Error reported by the database for a single object of a batch.
+#[derive(Debug, Clone)]
+pub struct ObjectError {
+    /// Position of the object in the data passed to `upsert`.
+    pub index: usize,
+    /// Id assigned by the database, if any.
+    pub id: Option<String>,
+    pub message: String,
+}
+
+/// Result of an upsert. Objects that failed are listed in `errors`, the others were stored.
+#[derive(Debug, Default, Clone)]
+pub struct BatchReport {
+    pub inserted: usize,
+    pub errors: Vec<ObjectError>,
+}
+
+impl BatchReport {
+    pub fn is_ok(&self) -> bool {
+        self.errors.is_empty()
+    }
+}
+
+/// Runs the blocking requests of an adapter. Adapters are called from the async embedding
+/// functions, where a blocking request would stall a worker of the runtime or panic, so `f`
+/// runs in [block_in_place](tokio::task::block_in_place) on a multi-thread runtime and on its
+/// own thread on a current-thread runtime.
+#[cfg(any(
+    feature = "weaviate",
+    feature = "elasticsearch",
+    feature = "chroma",
+    feature = "milvus"
+))]
+fn blocking<T: Send>(f: impl FnOnce() -> T + Send) -> T {
+    use tokio::runtime::{Handle, RuntimeFlavor};
+
+    match Handle::try_current() {
+        Ok(handle) if handle.runtime_flavor() == RuntimeFlavor::MultiThread => {
+            tokio::task::block_in_place(f)
+        }
+        Ok(_) => std::thread::scope(|scope| scope.spawn(f).join().unwrap()),
+        Err(_) => f(),
+    }
+}
+
+/// The `id` metadata entry, or the FNV-1a hash of the text and sorted metadata, which unlike
+/// the std hasher is stable across Rust releases.
+#[cfg(any(feature = "chroma", feature = "milvus"))]
+fn record_id(embed_data: &EmbedData) -> String {
+    if let Some(id) = embed_data.metadata.as_ref().and_then(|m| m.get("id")) {
+        return id.clone();
+    }
+    let mut hash: u64 = 0xcbf29ce484222325;
+    let mut write = |bytes: &[u8]| {
+        for byte in bytes.iter().chain([0xff].iter()) {
+            hash ^= *byte as u64;
+            hash = hash.wrapping_mul(0x100000001b3);
+        }
+    };
+    write(embed_data.text.as_deref().unwrap_or_default().as_bytes());
+    if let Some(metadata) = &embed_data.metadata {
+        let mut entries = metadata.iter().collect::<Vec<_>>();
+        entries.sort();
+        for (key, value) in entries {
+            write(key.as_bytes());
+            write(value.as_bytes());
+        }
+    }
+    format!("{:016x}", hash)
+}
@@ -0,0 +1,317 @@
+use anyhow::{anyhow, Result};
+use reqwest::blocking::{Client, RequestBuilder};
+use serde::Deserialize;
+use serde_json::{json, Map, Value};
+
+use crate::embeddings::embed::EmbedData;
+
+use super::{blocking, Adapter};
+pub use super::{BatchReport, ObjectError};
+
+/// Number of objects sent to Weaviate in a single batch request.
+const DEFAULT_BATCH_SIZE: usize = 100;
+
+#[derive(Deserialize, Debug)]
+struct BatchObjectResponse {
+    id: Option<String>,
+    result: Option<BatchObjectResult>,
+}
+
+#[derive(Deserialize, Debug)]
+struct BatchObjectResult {
+    errors: Option<BatchErrors>,
+}
+
+#[derive(Deserialize, Debug)]
+struct BatchErrors {
+    error: Vec<BatchErrorMessage>,
+}
+
+#[derive(Deserialize, Debug)]
+struct BatchErrorMessage {
+    message: String,
+}
+
+/// Stores [EmbedData] in a Weaviate class. Since embeddings are computed by embed_anything,
+/// the class is created with the `none` vectorizer and every object carries its vector.
+///
+/// # Example
+///
+/// ```rust,no_run
+/// use embed_anything::adapters::weaviate::WeaviateAdapter;
+///
+/// let adapter = WeaviateAdapter::new("http://localhost:8080", "Document", None).unwrap();
+/// let callback = adapter.callback();
+/// ```
+pub struct WeaviateAdapter {
+    url: String,
+    class_name: String,
+    api_key: Option<String>,
+    batch_size: usize,
+    client: Client,
+}
+
+impl WeaviateAdapter {
+    /// Connects to the Weaviate instance at `url` and creates `class_name` if it does not exist.
+    pub fn new(url: &str, class_name: &str, api_key: Option<String>) -> Result<Self> {
+        blocking(|| {
+            let adapter = Self {
+                url: url.trim_end_matches('/').to_string(),
+                class_name: class_name.to_string(),
+                api_key,
+                batch_size: DEFAULT_BATCH_SIZE,
+                client: Client::new(),
+            };
+            adapter.create_class_if_absent()?;
+            Ok(adapter)
+        })
+    }
+
+    pub fn with_batch_size(mut self, batch_size: usize) -> Self {
+        self.batch_size = batch_size.max(1);
+        self
+    }
+
+    pub fn class_name(&self) -> &str {
+        &self.class_name
+    }
+
+    fn authorize(&self, request: RequestBuilder) -> RequestBuilder {
+        match &self.api_key {
+            Some(api_key) => request.bearer_auth(api_key),
+            None => request,
+        }
+    }
+
+    fn create_class_if_absent(&self) -> Result<()> {
+        let response = self
+            .authorize(
+                self.client
+                    .get(format!("{}/v1/schema/{}", self.url, self.class_name)),
+            )
+            .send()?;
+        if response.status().is_success() {
+            return Ok(());
+        }
+        if response.status() != reqwest::StatusCode::NOT_FOUND {
+            return Err(anyhow!(
+                "Failed to fetch Weaviate class {}: {}",
+                self.class_name,
+                response.text()?
+            ));
+        }
+
+        let response = self
+            .authorize(self.client.post(format!("{}/v1/schema", self.url)))
+            .json(&self.class_schema())
+            .send()?;
+        if !response.status().is_success() {
+            return Err(anyhow!(
+                "Failed to create Weaviate class {}: {}",
+                self.class_name,
+                response.text()?
+            ));
+        }
+        Ok(())
+    }
+
+    fn class_schema(&self) -> Value {
+        json!({
+            "class": self.class_name,
+            "vectorizer": "none",
+            "properties": [
+                { "name": "text", "dataType": ["text"] }
+            ]
+        })
+    }
+
+    /// Converts embeddings to Weaviate objects. `text` and every metadata entry become
+    /// properties, metadata keys are sanitized to valid property names.
+    pub fn to_objects(&self, data: &[EmbedData]) -> Result<Vec<Value>> {
+        data.iter()
+            .map(|embed_data| {
+                let mut properties = Map::new();
+                if let Some(metadata) = &embed_data.metadata {
+                    for (key, value) in metadata {
+                        properties.insert(property_name(key), Value::String(value.clone()));
+                    }
+                }
+                if let Some(text) = &embed_data.text {
+                    properties.insert("text".to_string(), Value::String(text.clone()));
+                }
+                Ok(json!({
+                    "class": self.class_name,
+                    "properties": properties,
+                    "vector": embed_data.embedding.to_dense()?,
+                }))
+            })
+            .collect()
+    }
+
+    /// Sends the embeddings to Weaviate in batches. Request level failures are returned as an
+    /// error, failures of individual objects are collected in the [BatchReport].
+    pub fn upsert(&self, data: Vec<EmbedData>) -> Result<BatchReport> {
+        blocking(|| self.upsert_batches(&data))
+    }
+
+    fn upsert_batches(&self, data: &[EmbedData]) -> Result<BatchReport> {
+        let mut report = BatchReport::default();
+        for (batch_index, batch) in data.chunks(self.batch_size).enumerate() {
+            let offset = batch_index * self.batch_size;
+            let objects = self.to_objects(batch)?;
+            let response = self
+                .authorize(self.client.post(format!("{}/v1/batch/objects", self.url)))
+                .json(&json!({ "objects": objects }))
+                .send()?;
+            if !response.status().is_success() {
+                return Err(anyhow!(
+                    "Weaviate batch request failed: {}",
+                    response.text()?
+                ));
+            }
+            let results = response.json::<Vec<BatchObjectResponse>>()?;
+            let errors = collect_errors(results, offset);
+            report.inserted += batch.len() - errors.len();
+            report.errors.extend(errors);
+        }
+        Ok(report)
+    }
+
+    /// Returns a closure that can be passed as the `adapter` argument of the embedding
+    /// functions. Failed objects are logged.
+    pub fn callback(&self) -> impl Fn(Vec<EmbedData>) + '_ {
+        move |data| match self.upsert(data) {
+            Ok(report) => {
+                for error in report.errors {
+                    tracing::error!(
+                        "Failed to insert object {} into {}: {}",
+                        error.index,
+                        self.class_name,
+                        error.message
+                    );
+                }
+            }
+            Err(e) => tracing::error!("Failed to upsert into {}: {}", self.class_name, e),
+        }
+    }
+}
+
+/// Same as [WeaviateAdapter::callback]. Records are written on every upsert, so there is nothing to
+/// flush.
+impl Adapter for WeaviateAdapter {
+    fn upsert(&self, data: Vec<EmbedData>) {
+        self.callback()(data)
+    }
+}
+
+fn collect_errors(results: Vec<BatchObjectResponse>, offset: usize) -> Vec<ObjectError> {
+    results
+        .into_iter()
+        .enumerate()
+        .filter_map(|(index, object)| {
+            let errors = object.result?.errors?;
+            let message = errors
+                .error
+                .into_iter()
+                .map(|e| e.message)
+                .collect::<Vec<_>>()
+                .join("; ");
+            Some(ObjectError {
+                index: offset + index,
+                id: object.id,
+                message,
+            })
+        })
+        .collect()
+}
+
+fn property_name(key: &str) -> String {
+    let mut name = key
+        .chars()
+        .map(|c| if c.is_ascii_alphanumeric() { c } else { '_' })
+        .collect::<String>();
+    if name.chars().next().map_or(true, |c| c.is_ascii_digit()) {
+        name.insert(0, '_');
+    }
+    name
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::embeddings::embed::EmbeddingResult;
+    use std::collections::HashMap;
+
+    #[test]
+    fn test_property_name() {
+        assert_eq!(property_name("file_name"), "file_name");
+        assert_eq!(property_name("created-at"), "created_at");
+        assert_eq!(property_name("1st"), "_1st");
+    }
+
+    #[test]
+    fn test_collect_errors() {
+        let results: Vec<BatchObjectResponse> = serde_json::from_value(json!([
+            { "id": "a", "result": {} },
+            { "id": "b", "result": { "errors": { "error": [{ "message": "bad vector" }] } } }
+        ]))
+        .unwrap();
+        let errors = collect_errors(results, 10);
+        assert_eq!(errors.len(), 1);
+        assert_eq!(errors[0].index, 11);
+        assert_eq!(errors[0].id.as_deref(), Some("b"));
+        assert_eq!(errors[0].message, "bad vector");
+    }
+
+    #[test]
+    fn test_to_objects() {
+        let adapter = WeaviateAdapter {
+            url: "http://localhost:8080".to_string(),
+            class_name: "Document".to_string(),
+            api_key: None,
+            batch_size: DEFAULT_BATCH_SIZE,
+            client: Client::new(),
+        };
+        let metadata = HashMap::from([("file_name".to_string(), "test.pdf".to_string())]);
+        let data = vec![EmbedData::new(
+            EmbeddingResult::DenseVector(vec![0.1, 0.2]),
+            Some("hello".to_string()),
+            Some(metadata),
+        )];
+        let objects = adapter.to_objects(&data).unwrap();
+        assert_eq!(objects[0]["class"], "Document");
+        assert_eq!(objects[0]["properties"]["text"], "hello");
+        assert_eq!(objects[0]["properties"]["file_name"], "test.pdf");
+        assert_eq!(objects[0]["vector"].as_array().unwrap().len(), 2);
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn test_upsert_in_runtime() {
+        // The embedding functions call adapters from async code, where the blocking requests
+        // would panic if they were not moved out of the runtime.
+        let mut server = mockito::Server::new_async().await;
+        let schema = server
+            .mock("GET", "/v1/schema/Document")
+            .with_status(200)
+            .create_async()
+            .await;
+        let batch = server
+            .mock("POST", "/v1/batch/objects")
+            .with_header("content-type", "application/json")
+            .with_body(json!([{ "id": "a", "result": {} }]).to_string())
+            .create_async()
+            .await;
+
+        let adapter = WeaviateAdapter::new(&server.url(), "Document", None).unwrap();
+        let data = vec![EmbedData::new(
+            EmbeddingResult::DenseVector(vec![0.1, 0.2]),
+            Some("hello".to_string()),
+            None,
+        )];
+        let report = adapter.upsert(data).unwrap();
+
+        schema.assert_async().await;
+        batch.assert_async().await;
+        assert_eq!(report.inserted, 1);
+    }
+}
@@ -0,0 +1,345 @@
+use std::{collections::HashMap, str::FromStr};
+
+use anyhow::{anyhow, Result};
+use tree_sitter::{Language, Node, Parser};
+
+/// Programming languages supported by [CodeSplitter].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CodeLanguage {
+    Rust,
+    Python,
+    JavaScript,
+    TypeScript,
+}
+
+impl CodeLanguage {
+    /// Extensions of the source files read as code, see [CodeLanguage::from_extension].
+    pub const EXTENSIONS: &'static [&'static str] =
+        &["rs", "py", "js", "jsx", "mjs", "cjs", "ts", "tsx"];
+
+    pub fn from_extension(extension: &str) -> Option<Self> {
+        match extension {
+            "rs" => Some(CodeLanguage::Rust),
+            "py" => Some(CodeLanguage::Python),
+            "js" | "jsx" | "mjs" | "cjs" => Some(CodeLanguage::JavaScript),
+            "ts" | "tsx" => Some(CodeLanguage::TypeScript),
+            _ => None,
+        }
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            CodeLanguage::Rust => "rust",
+            CodeLanguage::Python => "python",
+            CodeLanguage::JavaScript => "javascript",
+            CodeLanguage::TypeScript => "typescript",
+        }
+    }
+
+    fn grammar(&self) -> Language {
+        match self {
+            CodeLanguage::Rust => tree_sitter_rust::LANGUAGE.into(),
+            CodeLanguage::Python => tree_sitter_python::LANGUAGE.into(),
+            CodeLanguage::JavaScript => tree_sitter_javascript::LANGUAGE.into(),
+            CodeLanguage::TypeScript => tree_sitter_typescript::LANGUAGE_TSX.into(),
+        }
+    }
+
+    /// Top level nodes that define a symbol and are kept in a single chunk when possible.
+    fn is_symbol(&self, kind: &str) -> bool {
+        match self {
+            CodeLanguage::Rust => matches!(
+                kind,
+                "function_item"
+                    | "impl_item"
+                    | "struct_item"
+                    | "enum_item"
+                    | "trait_item"
+                    | "mod_item"
+                    | "macro_definition"
+            ),
+            CodeLanguage::Python => matches!(
+                kind,
+                "function_definition" | "class_definition" | "decorated_definition"
+            ),
+            CodeLanguage::JavaScript | CodeLanguage::TypeScript => matches!(
+                kind,
+                "function_declaration"
+                    | "generator_function_declaration"
+                    | "class_declaration"
+                    | "abstract_class_declaration"
+                    | "interface_declaration"
+                    | "enum_declaration"
+                    | "type_alias_declaration"
+                    | "export_statement"
+            ),
+        }
+    }
+
+    fn is_comment(&self, kind: &str) -> bool {
+        matches!(kind, "comment" | "line_comment" | "block_comment")
+    }
+}
+
+impl FromStr for CodeLanguage {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s.to_lowercase().as_str() {
+            "rust" | "rs" => Ok(CodeLanguage::Rust),
+            "python" | "py" => Ok(CodeLanguage::Python),
+            "javascript" | "js" => Ok(CodeLanguage::JavaScript),
+            "typescript" | "ts" => Ok(CodeLanguage::TypeScript),
+            _ => Err(anyhow!("Unsupported code language: {}", s)),
+        }
+    }
+}
+
+/// A piece of source code along with the symbol it defines, if any.
+#[derive(Debug, Clone, PartialEq)]
+pub struct CodeChunk {
+    pub text: String,
+    pub symbol: Option<String>,
+    pub language: CodeLanguage,
+}
+
+impl CodeChunk {
+    /// Metadata entries describing the chunk, merged into the file metadata when embedding.
+    pub fn metadata(&self) -> HashMap<String, String> {
+        let mut metadata = HashMap::new();
+        metadata.insert("language".to_string(), self.language.name().to_string());
+        if let Some(symbol) = &self.symbol {
+            metadata.insert("symbol".to_string(), symbol.clone());
+        }
+        metadata
+    }
+}
+
+/// Splits source files at function and class boundaries using tree-sitter. Definitions that
+/// fit in `chunk_size` are kept whole together with the comments right above them, larger
+/// ones are split by lines. Code between definitions (imports, constants) is grouped.
+pub struct CodeSplitter {
+    language: CodeLanguage,
+    chunk_size: usize,
+}
+
+impl CodeSplitter {
+    pub fn new(language: CodeLanguage, chunk_size: usize) -> Self {
+        Self {
+            language,
+            chunk_size,
+        }
+    }
+
+    /// Splits `source`, measuring chunks with `size`.
+    pub fn split<F>(&self, source: &str, size: F) -> Result<Vec<CodeChunk>>
+    where
+        F: Fn(&str) -> usize,
+    {
+        let mut parser = Parser::new();
+        parser.set_language(&self.language.grammar())?;
+        let tree = parser
+            .parse(source, None)
+            .ok_or_else(|| anyhow!("Failed to parse {} source", self.language.name()))?;
+
+        let root = tree.root_node();
+        let mut cursor = root.walk();
+        let mut chunks = Vec::new();
+        // Byte range of code that is not part of a symbol yet.
+        let mut pending: Option<(usize, usize)> = None;
+        // Start of the comments directly preceding the next node.
+        let mut comment_start: Option<usize> = None;
+
+        for node in root.named_children(&mut cursor) {
+            let kind = node.kind();
+            if self.language.is_comment(kind) {
+                comment_start.get_or_insert(node.start_byte());
+                continue;
+            }
+            if !self.language.is_symbol(kind) {
+                let start = comment_start.take().unwrap_or(node.start_byte());
+                let (pending_start, _) = pending.unwrap_or((start, start));
+                if size(&source[pending_start..node.end_byte()]) > self.chunk_size {
+                    self.flush(source, pending.take(), &size, &mut chunks);
+                    pending = Some((start, node.end_byte()));
+                } else {
+                    pending = Some((pending_start, node.end_byte()));
+                }
+                continue;
+            }
+
+            self.flush(source, pending.take(), &size, &mut chunks);
+            let start = comment_start.take().unwrap_or(node.start_byte());
+            let text = &source[start..node.end_byte()];
+            let symbol = self.symbol_name(node, source);
+            self.push_text(text, symbol, &size, &mut chunks);
+        }
+        if let Some(start) = comment_start {
+            let (pending_start, _) = pending.unwrap_or((start, start));
+            pending = Some((pending_start, source.len()));
+        }
+        self.flush(source, pending, &size, &mut chunks);
+
+        Ok(chunks)
+    }
+
+    fn flush<F>(
+        &self,
+        source: &str,
+        range: Option<(usize, usize)>,
+        size: &F,
+        chunks: &mut Vec<CodeChunk>,
+    ) where
+        F: Fn(&str) -> usize,
+    {
+        if let Some((start, end)) = range {
+            self.push_text(&source[start..end], None, size, chunks);
+        }
+    }
+
+    fn push_text<F>(
+        &self,
+        text: &str,
+        symbol: Option<String>,
+        size: &F,
+        chunks: &mut Vec<CodeChunk>,
+    ) where
+        F: Fn(&str) -> usize,
+    {
+        if text.trim().is_empty() {
+            return;
+        }
+        let pieces = if size(text) <= self.chunk_size {
+            vec![text.to_string()]
+        } else {
+            self.split_lines(text, size)
+        };
+        chunks.extend(pieces.into_iter().map(|text| CodeChunk {
+            text,
+            symbol: symbol.clone(),
+            language: self.language,
+        }));
+    }
+
+    /// Fallback for definitions larger than the chunk size.
+    fn split_lines<F>(&self, text: &str, size: &F) -> Vec<String>
+    where
+        F: Fn(&str) -> usize,
+    {
+        let mut pieces = Vec::new();
+        let mut current = String::new();
+        for line in text.lines() {
+            if !current.is_empty() && size(&format!("{}\n{}", current, line)) > self.chunk_size {
+                pieces.push(std::mem::take(&mut current));
+            }
+            if !current.is_empty() {
+                current.push('\n');
+            }
+            current.push_str(line);
+        }
+        if !current.trim().is_empty() {
+            pieces.push(current);
+        }
+        pieces
+    }
+
+    fn symbol_name(&self, node: Node, source: &str) -> Option<String> {
+        let name_node = match node.kind() {
+            "impl_item" => node.child_by_field_name("type"),
+            "decorated_definition" => node
+                .child_by_field_name("definition")
+                .and_then(|definition| definition.child_by_field_name("name")),
+            "export_statement" => node
+                .child_by_field_name("declaration")
+                .and_then(|declaration| declaration.child_by_field_name("name")),
+            _ => node.child_by_field_name("name"),
+        }?;
+        name_node
+            .utf8_text(source.as_bytes())
+            .ok()
+            .map(|name| name.to_string())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn line_count(text: &str) -> usize {
+        text.lines().count()
+    }
+
+    #[test]
+    fn test_rust_functions() {
+        let source = r#"use std::fmt;
+
+/// Adds two numbers.
+fn add(a: i32, b: i32) -> i32 {
+    a + b
+}
+
+struct Point {
+    x: i32,
+}
+
+impl Point {
+    fn new(x: i32) -> Self {
+        Self { x }
+    }
+}
+"#;
+        let chunks = CodeSplitter::new(CodeLanguage::Rust, 20)
+            .split(source, line_count)
+            .unwrap();
+        let symbols = chunks
+            .iter()
+            .map(|chunk| chunk.symbol.as_deref())
+            .collect::<Vec<_>>();
+        assert_eq!(
+            symbols,
+            vec![None, Some("add"), Some("Point"), Some("Point")]
+        );
+        assert!(chunks[1].text.starts_with("/// Adds two numbers."));
+        assert!(chunks[1].text.ends_with('}'));
+        assert!(chunks
+            .iter()
+            .all(|chunk| chunk.language == CodeLanguage::Rust));
+    }
+
+    #[test]
+    fn test_python_functions() {
+        let source = r#"import os
+
+def greet(name):
+    return f"Hello {name}"
+
+class Greeter:
+    def __init__(self, name):
+        self.name = name
+
+    def greet(self):
+        return greet(self.name)
+"#;
+        let chunks = CodeSplitter::new(CodeLanguage::Python, 20)
+            .split(source, line_count)
+            .unwrap();
+        assert_eq!(chunks.len(), 3);
+        assert_eq!(chunks[0].text, "import os");
+        assert_eq!(chunks[1].symbol.as_deref(), Some("greet"));
+        assert_eq!(chunks[2].symbol.as_deref(), Some("Greeter"));
+        assert!(chunks[2].text.contains("def __init__"));
+        assert!(chunks[2].text.contains("return greet(self.name)"));
+    }
+
+    #[test]
+    fn test_oversized_function_is_split_by_lines() {
+        let source = "def long():\n    a = 1\n    b = 2\n    c = 3\n    return a + b + c\n";
+        let chunks = CodeSplitter::new(CodeLanguage::Python, 2)
+            .split(source, line_count)
+            .unwrap();
+        assert_eq!(chunks.len(), 3);
+        assert!(chunks
+            .iter()
+            .all(|chunk| chunk.symbol.as_deref() == Some("long")));
+    }
+}
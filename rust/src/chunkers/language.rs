@@ -0,0 +1,60 @@
+use std::collections::HashMap;
+
+/// Metadata entry holding the language of a chunk as an ISO 639-3 code, e.g. "eng" or "spa".
+pub const LANGUAGE_KEY: &str = "language";
+/// Metadata entry holding the confidence of the detected language, between 0 and 1.
+pub const LANGUAGE_CONFIDENCE_KEY: &str = "confidence";
+
+/// Detects the language of `text` with [whatlang], from the frequency of its trigrams. Returns
+/// the ISO 639-3 code of the language and the confidence of the detection, between 0 and 1, or
+/// None when the text has too few letters to tell.
+///
+/// Detection takes microseconds but is unreliable on a few words, the confidence is then low.
+pub fn detect_language(text: &str) -> Option<(&'static str, f64)> {
+    let info = whatlang::detect(text)?;
+    Some((info.lang().code(), info.confidence()))
+}
+
+/// Adds the language of each chunk to its metadata. Chunks that already have a language, such
+/// as code chunks tagged with their programming language, are left as is.
+pub fn add_chunk_languages(chunks: &[String], chunk_metadata: &mut [HashMap<String, String>]) {
+    for (chunk, metadata) in chunks.iter().zip(chunk_metadata) {
+        if metadata.contains_key(LANGUAGE_KEY) {
+            continue;
+        }
+        if let Some((language, confidence)) = detect_language(chunk) {
+            metadata.insert(LANGUAGE_KEY.to_string(), language.to_string());
+            metadata.insert(
+                LANGUAGE_CONFIDENCE_KEY.to_string(),
+                format!("{:.2}", confidence),
+            );
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_add_chunk_languages() {
+        let chunks = [
+            "The weather was lovely this morning, so we walked along the river to the old town.",
+            "El tiempo era estupendo esta mañana, así que caminamos junto al río hasta el centro.",
+            "fn main() {}",
+            "42",
+        ]
+        .map(String::from);
+        let mut chunk_metadata = vec![HashMap::new(); chunks.len()];
+        chunk_metadata[2].insert(LANGUAGE_KEY.to_string(), "rust".to_string());
+        add_chunk_languages(&chunks, &mut chunk_metadata);
+
+        assert_eq!(chunk_metadata[0][LANGUAGE_KEY], "eng");
+        assert_eq!(chunk_metadata[1][LANGUAGE_KEY], "spa");
+        let confidence: f64 = chunk_metadata[1][LANGUAGE_CONFIDENCE_KEY].parse().unwrap();
+        assert!(confidence > 0.5);
+        assert_eq!(chunk_metadata[2][LANGUAGE_KEY], "rust");
+        assert!(!chunk_metadata[2].contains_key(LANGUAGE_CONFIDENCE_KEY));
+        assert!(chunk_metadata[3].is_empty());
+    }
+}
@@ -1,2 +1,174 @@
+pub mod code;
 pub mod cumulative;
+pub mod language;
+pub mod sentence;
+pub mod spans;
 pub mod statistical;
+pub mod window;
+
+use std::collections::HashMap;
+
+use anyhow::Result;
+
+use crate::config::TextEmbedConfig;
+use crate::text_loader::{SplittingStrategy, TextLoader};
+
+/// A chunk of text produced by [chunk_text].
+#[derive(Debug, Clone, PartialEq)]
+pub struct Chunk {
+    pub text: String,
+    /// Size of the chunk in the tokens of the chunking tokenizer, which is close to but not
+    /// always equal to the count of an embedding model.
+    pub token_count: usize,
+    /// Character offset of the chunk in the text it was split from, None when the chunk
+    /// couldn't be found in it, see [chunk_spans](spans::chunk_spans).
+    pub start_char: Option<usize>,
+    /// Character offset just past the end of the chunk.
+    pub end_char: Option<usize>,
+    /// The symbol and language of code chunks, the token span of windows and, with
+    /// [TextEmbedConfig::detect_language], the language of the chunk.
+    pub metadata: HashMap<String, String>,
+}
+
+/// Splits `text` with `strategy`, without embedding anything. The chunks are the ones
+/// [embed_file](crate::embed_file) would embed for a file holding `text` with the same config.
+///
+/// Only the chunking options of `config` are used: the chunk size and overlap, the language
+/// and sentence splitter, the minimum chunk size and [TextEmbedConfig::detect_language].
+/// [SplittingStrategy::Semantic] embeds sentences with [TextEmbedConfig::semantic_encoder] to
+/// find the boundaries, or with a Jina model when it is None.
+///
+/// # Example
+///
+/// ```rust
+/// use embed_anything::chunkers::chunk_text;
+/// use embed_anything::config::TextEmbedConfig;
+/// use embed_anything::text_loader::SplittingStrategy;
+///
+/// fn chunk() {
+///     let config = TextEmbedConfig::default().with_chunk_size(128, None);
+///     let chunks = chunk_text("Some long text.", SplittingStrategy::Sentence, &config).unwrap();
+///     for chunk in chunks {
+///         println!("{:?} {} tokens", chunk.start_char, chunk.token_count);
+///     }
+/// }
+/// ```
+pub fn chunk_text(
+    text: &str,
+    strategy: SplittingStrategy,
+    config: &TextEmbedConfig,
+) -> Result<Vec<Chunk>> {
+    let textloader = TextLoader::new(
+        config.chunk_size.unwrap_or(256),
+        config.overlap_ratio.unwrap_or(0.0),
+    )
+    .with_language(config.language.as_deref())
+    .with_sentence_backend(config.sentence_splitter.clone())
+    .with_min_chunk_size(
+        config.min_chunk_size,
+        config.min_chunk_policy.unwrap_or_default(),
+    );
+    let (texts, mut chunk_metadata) =
+        textloader.split_with_metadata(text, strategy, config.semantic_encoder.clone())?;
+    if config.detect_language.unwrap_or(false) {
+        language::add_chunk_languages(&texts, &mut chunk_metadata);
+    }
+    let spans = spans::chunk_spans(text, &texts);
+    Ok(texts
+        .into_iter()
+        .zip(chunk_metadata)
+        .zip(spans)
+        .map(|((text, metadata), span)| Chunk {
+            token_count: textloader.token_count(&text),
+            text,
+            start_char: span.map(|(start, _)| start),
+            end_char: span.map(|(_, end)| end),
+            metadata,
+        })
+        .collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use super::*;
+    use crate::chunkers::code::CodeLanguage;
+    use crate::embeddings::embed::EmbedderBuilder;
+
+    const TEXT: &str = "The cat sat on the mat. It was a sunny day and the cat was happy. \
+        Stock markets fell sharply on Monday. Investors worried about rising interest rates.";
+
+    /// Checks that the span of every chunk points at its text.
+    fn assert_spans(text: &str, chunks: &[Chunk]) {
+        for chunk in chunks {
+            let (start, end) = (chunk.start_char.unwrap(), chunk.end_char.unwrap());
+            let span = text
+                .chars()
+                .skip(start)
+                .take(end - start)
+                .collect::<String>();
+            assert!(span.split_whitespace().eq(chunk.text.split_whitespace()));
+        }
+    }
+
+    #[test]
+    fn test_chunk_text_sentence() {
+        let config = TextEmbedConfig::default().with_chunk_size(20, None);
+        let chunks = chunk_text(TEXT, SplittingStrategy::Sentence, &config).unwrap();
+        assert!(chunks.len() > 1);
+        assert!(chunks
+            .iter()
+            .all(|chunk| chunk.token_count > 0 && chunk.token_count <= 20));
+        assert_eq!(chunks[0].start_char, Some(0));
+        assert_spans(TEXT, &chunks);
+    }
+
+    #[test]
+    fn test_chunk_text_code() {
+        let code = "def add(a, b):\n    return a + b\n\n\ndef sub(a, b):\n    return a - b\n";
+        let config = TextEmbedConfig::default().with_chunk_size(64, None);
+        let strategy = SplittingStrategy::Code {
+            language: CodeLanguage::Python,
+        };
+        let chunks = chunk_text(code, strategy, &config).unwrap();
+        assert_eq!(chunks.len(), 2);
+        assert_eq!(chunks[0].metadata["symbol"], "add");
+        assert_eq!(chunks[1].metadata["symbol"], "sub");
+        assert_eq!(chunks[1].metadata["language"], "python");
+        assert_spans(code, &chunks);
+    }
+
+    #[test]
+    fn test_chunk_text_sliding_window() {
+        let config = TextEmbedConfig::default();
+        let strategy = SplittingStrategy::SlidingWindow {
+            window_tokens: 16,
+            stride_tokens: 8,
+        };
+        let chunks = chunk_text(TEXT, strategy, &config).unwrap();
+        assert!(chunks.len() > 2);
+        assert!(chunks.iter().all(|chunk| chunk.token_count <= 16));
+        // Consecutive windows overlap.
+        assert!(chunks[1].start_char.unwrap() < chunks[0].end_char.unwrap());
+        assert_spans(TEXT, &chunks);
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn test_chunk_text_semantic() {
+        let encoder = EmbedderBuilder::new()
+            .model_architecture("bert")
+            .model_id(Some("sentence-transformers/all-MiniLM-L6-v2"))
+            .from_pretrained_hf()
+            .unwrap();
+        let config = TextEmbedConfig::default().with_semantic_encoder(Some(Arc::new(encoder)));
+        let chunks = chunk_text(TEXT, SplittingStrategy::Semantic, &config).unwrap();
+        assert!(!chunks.is_empty());
+        let words = |text: &str| text.split_whitespace().collect::<Vec<_>>().join(" ");
+        let joined = chunks
+            .iter()
+            .map(|chunk| chunk.text.as_str())
+            .collect::<Vec<_>>();
+        assert_eq!(words(&joined.join(" ")), words(TEXT));
+    }
+}
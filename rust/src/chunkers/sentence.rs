@@ -0,0 +1,345 @@
+use std::{
+    fmt,
+    sync::{Arc, OnceLock},
+};
+
+use regex::Regex;
+use unicode_segmentation::UnicodeSegmentation;
+
+/// Abbreviations that end with a period but do not end a sentence.
+const EN_ABBREVIATIONS: &[&str] = &[
+    "Mr.", "Mrs.", "Ms.", "Dr.", "Prof.", "Sr.", "Jr.", "St.", "vs.", "etc.", "e.g.", "i.e.",
+    "Fig.", "No.",
+];
+const FR_ABBREVIATIONS: &[&str] = &[
+    "M.", "MM.", "Mme.", "Mmes.", "Mlle.", "Dr.", "Pr.", "St.", "Ste.", "etc.", "cf.", "p.", "éd.",
+    "av.", "apr.", "J.-C.",
+];
+const DE_ABBREVIATIONS: &[&str] = &[
+    "Hr.", "Fr.", "Dr.", "Prof.", "bzw.", "z.B.", "usw.", "ca.", "Nr.",
+];
+
+/// A function splitting text into sentences, see [SentenceBackend::Custom].
+pub type SentenceSplitFn = Arc<dyn Fn(&str) -> Vec<String> + Send + Sync>;
+
+/// How a [SentenceSplitter] finds sentence boundaries.
+#[derive(Clone, Default)]
+pub enum SentenceBackend {
+    /// Ends a sentence after `.`, `!` or `?` followed by whitespace, and after `。！？`. This is
+    /// the fastest backend, but it also splits after abbreviations such as "Dr." or "e.g.".
+    Regex,
+    /// Uses the Unicode sentence boundary rules (UAX #29) and keeps known abbreviations of the
+    /// language within their sentence.
+    #[default]
+    Unicode,
+    /// Splits with a user function, e.g. one calling an NLP library. Returned sentences are
+    /// trimmed and empty ones dropped.
+    Custom(SentenceSplitFn),
+}
+
+impl SentenceBackend {
+    pub fn custom<F>(split: F) -> Self
+    where
+        F: Fn(&str) -> Vec<String> + Send + Sync + 'static,
+    {
+        Self::Custom(Arc::new(split))
+    }
+}
+
+impl fmt::Debug for SentenceBackend {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Regex => write!(f, "Regex"),
+            Self::Unicode => write!(f, "Unicode"),
+            Self::Custom(_) => write!(f, "Custom(..)"),
+        }
+    }
+}
+
+impl std::str::FromStr for SentenceBackend {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "regex" => Ok(Self::Regex),
+            "unicode" => Ok(Self::Unicode),
+            _ => Err(anyhow::anyhow!(
+                "Unknown sentence splitter {:?}, expected \"regex\" or \"unicode\"",
+                s
+            )),
+        }
+    }
+}
+
+fn sentence_end() -> &'static Regex {
+    static SENTENCE_END: OnceLock<Regex> = OnceLock::new();
+    SENTENCE_END.get_or_init(|| Regex::new(r#"[.!?]+["'”’)\]]*\s+|[。！？]+"#).unwrap())
+}
+
+/// Splits text into sentences using the Unicode sentence boundary rules (UAX #29), which
+/// handle full-width terminators such as `。！？` as well as latin punctuation. Another
+/// [SentenceBackend] can be picked with [SentenceSplitter::with_backend].
+///
+/// The optional `language` is an ISO 639-1 code used to pick locale specific rules: known
+/// abbreviations are not treated as sentence ends, and for Chinese, Japanese and Korean the
+/// sentences are joined without spaces.
+#[derive(Debug, Clone, Default)]
+pub struct SentenceSplitter {
+    language: Option<String>,
+    backend: SentenceBackend,
+}
+
+impl SentenceSplitter {
+    pub fn new(language: Option<&str>) -> Self {
+        Self {
+            language: language.map(|l| l.to_lowercase()),
+            backend: SentenceBackend::default(),
+        }
+    }
+
+    pub fn with_backend(mut self, backend: SentenceBackend) -> Self {
+        self.backend = backend;
+        self
+    }
+
+    pub fn backend(&self) -> &SentenceBackend {
+        &self.backend
+    }
+
+    pub fn language(&self) -> Option<&str> {
+        self.language.as_deref()
+    }
+
+    /// Whether the language writes sentences without spaces between words.
+    pub fn is_cjk(&self) -> bool {
+        matches!(self.language.as_deref(), Some("zh" | "ja" | "ko"))
+    }
+
+    /// String used to put sentences back together.
+    pub fn separator(&self) -> &'static str {
+        if self.is_cjk() {
+            ""
+        } else {
+            " "
+        }
+    }
+
+    fn abbreviations(&self) -> &'static [&'static str] {
+        match self.language.as_deref() {
+            Some("fr") => FR_ABBREVIATIONS,
+            Some("de") => DE_ABBREVIATIONS,
+            Some("zh" | "ja" | "ko") => &[],
+            _ => EN_ABBREVIATIONS,
+        }
+    }
+
+    fn ends_with_abbreviation(&self, sentence: &str) -> bool {
+        let last_word = sentence.split_whitespace().last().unwrap_or_default();
+        self.abbreviations().contains(&last_word)
+    }
+
+    pub fn split(&self, text: &str) -> Vec<String> {
+        match &self.backend {
+            SentenceBackend::Regex => split_regex(text),
+            SentenceBackend::Unicode => self.split_unicode(text),
+            SentenceBackend::Custom(split) => split(text)
+                .iter()
+                .map(|sentence| sentence.trim())
+                .filter(|sentence| !sentence.is_empty())
+                .map(str::to_string)
+                .collect(),
+        }
+    }
+
+    fn split_unicode(&self, text: &str) -> Vec<String> {
+        let mut sentences: Vec<String> = Vec::new();
+        let mut pending = String::new();
+        for segment in text.split_sentence_bounds() {
+            pending.push_str(segment);
+            let trimmed = pending.trim();
+            if trimmed.is_empty() || self.ends_with_abbreviation(trimmed) {
+                continue;
+            }
+            sentences.push(trimmed.to_string());
+            pending.clear();
+        }
+        let trimmed = pending.trim();
+        if !trimmed.is_empty() {
+            sentences.push(trimmed.to_string());
+        }
+        sentences
+    }
+
+    /// Greedily packs consecutive sentences into chunks whose size, as measured by `size`,
+    /// does not exceed `chunk_size`. A single sentence larger than `chunk_size` becomes its
+    /// own chunk.
+    pub fn pack<F>(&self, sentences: &[String], chunk_size: usize, size: F) -> Vec<String>
+    where
+        F: Fn(&str) -> usize,
+    {
+        self.pack_with_overlap(sentences, chunk_size, 0.0, size)
+    }
+
+    /// Like [SentenceSplitter::pack], but every chunk after the first starts with the last
+    /// whole sentences of the previous chunk. As many trailing sentences are repeated as fit
+    /// in `overlap_ratio * chunk_size`, so overlapping regions always begin at a sentence
+    /// boundary.
+    pub fn pack_with_overlap<F>(
+        &self,
+        sentences: &[String],
+        chunk_size: usize,
+        overlap_ratio: f32,
+        size: F,
+    ) -> Vec<String>
+    where
+        F: Fn(&str) -> usize,
+    {
+        let overlap_size = (chunk_size as f32 * overlap_ratio) as usize;
+        let join = |sentences: &[String]| sentences.join(self.separator());
+        let mut chunks = Vec::new();
+        let mut start = 0;
+        while start < sentences.len() {
+            let mut end = start + 1;
+            while end < sentences.len() && size(&join(&sentences[start..=end])) <= chunk_size {
+                end += 1;
+            }
+            chunks.push(join(&sentences[start..end]));
+            if end == sentences.len() {
+                break;
+            }
+            // Step back over the trailing sentences that fit in the overlap, as long as the
+            // next chunk still has room for a new sentence.
+            let mut next = end;
+            while next > start + 1
+                && size(&join(&sentences[next - 1..end])) <= overlap_size
+                && size(&join(&sentences[next - 1..=end])) <= chunk_size
+            {
+                next -= 1;
+            }
+            start = next;
+        }
+        chunks
+    }
+}
+
+fn split_regex(text: &str) -> Vec<String> {
+    let mut sentences = Vec::new();
+    let mut start = 0;
+    let ends = sentence_end()
+        .find_iter(text)
+        .map(|m| m.end())
+        .chain([text.len()]);
+    for end in ends {
+        let sentence = text[start..end].trim();
+        if !sentence.is_empty() {
+            sentences.push(sentence.to_string());
+        }
+        start = end;
+    }
+    sentences
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_chinese_sentences() {
+        let splitter = SentenceSplitter::new(Some("zh"));
+        let sentences = splitter.split("今天天气很好。我们去公园散步吧！你觉得怎么样？好的。");
+        assert_eq!(sentences.len(), 4);
+        assert_eq!(sentences[0], "今天天气很好。");
+        assert_eq!(sentences[2], "你觉得怎么样？");
+    }
+
+    #[test]
+    fn test_japanese_sentences() {
+        let splitter = SentenceSplitter::new(Some("ja"));
+        let sentences = splitter.split("今日は晴れです。散歩に行きましょう！どう思いますか？");
+        assert_eq!(sentences.len(), 3);
+        assert_eq!(
+            splitter.pack(&sentences, 100, |s| s.chars().count()),
+            vec!["今日は晴れです。散歩に行きましょう！どう思いますか？"]
+        );
+    }
+
+    #[test]
+    fn test_french_abbreviations() {
+        let splitter = SentenceSplitter::new(Some("fr"));
+        let sentences = splitter.split("M. Dupont est arrivé. Comment allez-vous ? Très bien !");
+        assert_eq!(
+            sentences,
+            vec![
+                "M. Dupont est arrivé.",
+                "Comment allez-vous ?",
+                "Très bien !"
+            ]
+        );
+    }
+
+    #[test]
+    fn test_backends() {
+        let text = "Dr. Smith arrived at 5 p.m. on Monday. Was he late? No!";
+        let regex = SentenceSplitter::new(None).with_backend(SentenceBackend::Regex);
+        assert_eq!(
+            regex.split(text),
+            vec![
+                "Dr.",
+                "Smith arrived at 5 p.m.",
+                "on Monday.",
+                "Was he late?",
+                "No!"
+            ]
+        );
+        let unicode = SentenceSplitter::new(None).with_backend(SentenceBackend::Unicode);
+        assert_eq!(
+            unicode.split(text),
+            vec![
+                "Dr. Smith arrived at 5 p.m. on Monday.",
+                "Was he late?",
+                "No!"
+            ]
+        );
+        let lines = SentenceSplitter::new(None).with_backend(SentenceBackend::custom(|text| {
+            text.split('\n').map(str::to_string).collect()
+        }));
+        assert_eq!(
+            lines.split("One. Two\n\n Three "),
+            vec!["One. Two", "Three"]
+        );
+
+        let chinese = SentenceSplitter::new(Some("zh")).with_backend(SentenceBackend::Regex);
+        assert_eq!(chinese.split("今天天气很好。我们去公园散步吧！").len(), 2);
+        assert!("Unicode".parse::<SentenceBackend>().is_ok());
+        assert!("spacy".parse::<SentenceBackend>().is_err());
+    }
+
+    #[test]
+    fn test_pack() {
+        let splitter = SentenceSplitter::new(None);
+        let sentences = splitter.split("One two. Three four. Five six.");
+        let chunks = splitter.pack(&sentences, 4, |s| s.split_whitespace().count());
+        assert_eq!(chunks, vec!["One two. Three four.", "Five six."]);
+    }
+
+    #[test]
+    fn test_pack_with_overlap() {
+        let splitter = SentenceSplitter::new(None);
+        let sentences = splitter.split("One two. Three four. Five six. Seven eight. Nine ten.");
+        let chunks =
+            splitter.pack_with_overlap(&sentences, 6, 0.4, |s| s.split_whitespace().count());
+        assert_eq!(
+            chunks,
+            vec![
+                "One two. Three four. Five six.",
+                "Five six. Seven eight. Nine ten."
+            ]
+        );
+        // Every chunk after the first begins with a whole sentence of the previous chunk.
+        for pair in chunks.windows(2) {
+            let first_sentence = splitter.split(&pair[1]).remove(0);
+            assert!(sentences.contains(&first_sentence));
+            assert!(pair[0].ends_with(&first_sentence));
+        }
+    }
+}
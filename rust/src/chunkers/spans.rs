@@ -0,0 +1,125 @@
+use std::collections::HashMap;
+
+/// Metadata entry holding the offset of the first character of a chunk in the text of its
+/// file.
+pub const START_CHAR_KEY: &str = "start_char";
+/// Metadata entry holding the offset just past the last character of a chunk.
+pub const END_CHAR_KEY: &str = "end_char";
+
+/// Finds the span of each chunk in the `text` it was split from, as character offsets with the
+/// end excluded. Offsets count Unicode scalar values, e.g. `text.chars().skip(start)`.
+///
+/// Chunks are searched in order, each from just after the start of the previous one, so
+/// overlapping chunks get overlapping spans. Whitespace is ignored when comparing, since
+/// splitters may join sentences with a space where the text has a line break. A chunk that
+/// is not found, e.g. one rewritten by a custom splitter, gets None.
+pub fn chunk_spans(text: &str, chunks: &[String]) -> Vec<Option<(usize, usize)>> {
+    let mut byte_spans = Vec::with_capacity(chunks.len());
+    let mut search_from = 0;
+    for chunk in chunks {
+        let span = find_chunk(text, chunk.trim(), search_from);
+        if let Some((start, _)) = span {
+            search_from = start + text[start..].chars().next().map_or(1, char::len_utf8);
+        }
+        byte_spans.push(span);
+    }
+
+    // Byte offsets are converted in increasing order, so the text is only scanned once.
+    let mut offsets = byte_spans
+        .iter()
+        .flatten()
+        .flat_map(|&(start, end)| [start, end])
+        .collect::<Vec<_>>();
+    offsets.sort_unstable();
+    offsets.dedup();
+    let mut char_offsets = HashMap::with_capacity(offsets.len());
+    let (mut byte, mut chars) = (0, 0);
+    for offset in offsets {
+        chars += text[byte..offset].chars().count();
+        byte = offset;
+        char_offsets.insert(offset, chars);
+    }
+    byte_spans
+        .into_iter()
+        .map(|span| span.map(|(start, end)| (char_offsets[&start], char_offsets[&end])))
+        .collect()
+}
+
+/// Byte span of the first occurrence of `chunk` in `text` at or after `from`.
+fn find_chunk(text: &str, chunk: &str, from: usize) -> Option<(usize, usize)> {
+    let first_word = chunk.split_whitespace().next()?;
+    let mut from = from;
+    while let Some(position) = text[from..].find(first_word) {
+        let start = from + position;
+        if let Some(length) = match_ignoring_whitespace(&text[start..], chunk) {
+            return Some((start, start + length));
+        }
+        from = start + first_word.chars().next().map_or(1, char::len_utf8);
+    }
+    None
+}
+
+/// The length in bytes of the prefix of `text` equal to `chunk` once whitespace is removed
+/// from both, or None when `text` doesn't start with `chunk`.
+fn match_ignoring_whitespace(text: &str, chunk: &str) -> Option<usize> {
+    let mut text_chars = text.char_indices().filter(|(_, c)| !c.is_whitespace());
+    let mut end = 0;
+    for expected in chunk.chars().filter(|c| !c.is_whitespace()) {
+        let (index, c) = text_chars.next()?;
+        if c != expected {
+            return None;
+        }
+        end = index + c.len_utf8();
+    }
+    Some(end)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::chunkers::sentence::SentenceSplitter;
+
+    fn slice(text: &str, start: usize, end: usize) -> String {
+        text.chars().skip(start).take(end - start).collect()
+    }
+
+    #[test]
+    fn test_spans_reconstruct_text() {
+        let text = "Le café est fermé.\nIl ouvre à 8 h.  Nous attendons.\n\nLe soleil brille. \
+                    Il fait chaud. Tout va bien.";
+        let splitter = SentenceSplitter::new(Some("fr"));
+        let sentences = splitter.split(text);
+        let chunks =
+            splitter.pack_with_overlap(&sentences, 8, 0.5, |s| s.split_whitespace().count());
+        assert!(chunks.len() > 2);
+
+        let spans = chunk_spans(text, &chunks)
+            .into_iter()
+            .map(Option::unwrap)
+            .collect::<Vec<_>>();
+        let mut reconstructed = String::new();
+        let mut previous_end = spans[0].0;
+        for (chunk, &(start, end)) in chunks.iter().zip(&spans) {
+            let span_text = slice(text, start, end);
+            assert_eq!(
+                span_text.split_whitespace().collect::<Vec<_>>(),
+                chunk.split_whitespace().collect::<Vec<_>>()
+            );
+            // Overlapping chunks start before the end of the previous one.
+            reconstructed.push_str(&slice(text, previous_end.max(start), end));
+            previous_end = end;
+        }
+        assert!(spans.windows(2).any(|pair| pair[1].0 < pair[0].1));
+        assert_eq!(reconstructed, text);
+    }
+
+    #[test]
+    fn test_missing_and_repeated_chunks() {
+        let text = "one two. one two. three.";
+        let chunks = ["one two.", "one two.", "rewritten", "three."].map(str::to_string);
+        assert_eq!(
+            chunk_spans(text, &chunks),
+            vec![Some((0, 8)), Some((9, 17)), None, Some((18, 24))]
+        );
+    }
+}
@@ -0,0 +1,118 @@
+use std::collections::HashMap;
+
+use anyhow::{anyhow, Result};
+use tokenizers::Tokenizer;
+
+/// Metadata entry holding the index of the first token of a window in the tokens of its file.
+pub const START_TOKEN_KEY: &str = "start_token";
+/// Metadata entry holding the index just past the last token of a window.
+pub const END_TOKEN_KEY: &str = "end_token";
+
+/// A window of consecutive tokens of a text, see [sliding_windows].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct WindowChunk {
+    pub text: String,
+    pub start_token: usize,
+    pub end_token: usize,
+}
+
+impl WindowChunk {
+    /// Metadata entries holding the token span of the window, merged into the file metadata
+    /// when embedding.
+    pub fn metadata(&self) -> HashMap<String, String> {
+        HashMap::from([
+            (START_TOKEN_KEY.to_string(), self.start_token.to_string()),
+            (END_TOKEN_KEY.to_string(), self.end_token.to_string()),
+        ])
+    }
+}
+
+/// Splits `text` into windows of `window_tokens` tokens, a new one starting every
+/// `stride_tokens` tokens. A stride smaller than the window makes overlapping windows, an equal
+/// one contiguous windows, and a larger one leaves the tokens between windows out, to sample
+/// long documents. The last window is shorter when the text ends first, and no window starts
+/// after a window reaching the end of the text.
+///
+/// Tokens are counted with `tokenizer`, without special tokens. Each window holds the text
+/// from its first to its last token, as written in `text` with the surrounding whitespace
+/// trimmed.
+pub fn sliding_windows(
+    tokenizer: &Tokenizer,
+    text: &str,
+    window_tokens: usize,
+    stride_tokens: usize,
+) -> Result<Vec<WindowChunk>> {
+    if window_tokens == 0 || stride_tokens == 0 {
+        return Err(anyhow!(
+            "Sliding windows need a window and a stride of at least one token"
+        ));
+    }
+    let encoding = tokenizer.encode(text, false).map_err(anyhow::Error::msg)?;
+    let offsets = encoding.get_offsets();
+    let mut windows = Vec::new();
+    let mut start_token = 0;
+    while start_token < offsets.len() {
+        let end_token = (start_token + window_tokens).min(offsets.len());
+        // Byte-level tokens may cut a character, the window keeps it whole.
+        let mut start = offsets[start_token].0;
+        while !text.is_char_boundary(start) {
+            start -= 1;
+        }
+        let mut end = offsets[end_token - 1].1;
+        while !text.is_char_boundary(end) {
+            end += 1;
+        }
+        windows.push(WindowChunk {
+            text: text[start..end].trim().to_string(),
+            start_token,
+            end_token,
+        });
+        if end_token == offsets.len() {
+            break;
+        }
+        start_token += stride_tokens;
+    }
+    Ok(windows)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn spans(windows: &[WindowChunk]) -> Vec<(usize, usize)> {
+        windows
+            .iter()
+            .map(|window| (window.start_token, window.end_token))
+            .collect()
+    }
+
+    #[test]
+    fn test_sliding_windows() {
+        let tokenizer = Tokenizer::from_pretrained("BEE-spoke-data/cl100k_base-mlm", None).unwrap();
+        let text = "one two three four five six seven eight nine ten";
+        assert_eq!(tokenizer.encode(text, false).unwrap().len(), 10);
+
+        // Overlapping windows.
+        let windows = sliding_windows(&tokenizer, text, 4, 2).unwrap();
+        assert_eq!(spans(&windows), [(0, 4), (2, 6), (4, 8), (6, 10)]);
+        assert_eq!(windows[1].text, "three four five six");
+        assert_eq!(windows[1].metadata()[START_TOKEN_KEY], "2");
+        assert_eq!(windows[1].metadata()[END_TOKEN_KEY], "6");
+
+        // Contiguous windows, the last one shorter.
+        let windows = sliding_windows(&tokenizer, text, 4, 4).unwrap();
+        assert_eq!(spans(&windows), [(0, 4), (4, 8), (8, 10)]);
+        assert_eq!(windows[2].text, "nine ten");
+
+        // Gaps between windows.
+        let windows = sliding_windows(&tokenizer, text, 2, 4).unwrap();
+        assert_eq!(spans(&windows), [(0, 2), (4, 6), (8, 10)]);
+        assert_eq!(
+            windows.iter().map(|w| w.text.as_str()).collect::<Vec<_>>(),
+            ["one two", "five six", "nine ten"]
+        );
+
+        assert!(sliding_windows(&tokenizer, "", 4, 2).unwrap().is_empty());
+        assert!(sliding_windows(&tokenizer, text, 4, 0).is_err());
+    }
+}
@@ -1,6 +1,27 @@
-use std::sync::Arc;
+use std::{collections::HashMap, sync::Arc};
 
-use crate::{embeddings::embed::Embedder, text_loader::SplittingStrategy};
+#[cfg(feature = "cache")]
+use crate::embeddings::cache::EmbeddingCache;
+use crate::{
+    chunkers::sentence::SentenceBackend,
+    embeddings::{
+        async_embedder::AsyncEmbedder,
+        cloud::BudgetExceeded,
+        convert_output_dtype,
+        embed::{EmbedData, Embedder, EmbeddingResult, OutputDtype},
+        pool::EmbedderPool,
+        registry::ModelLoader,
+        utils::{length_order, restore_order, TruncationCallback},
+    },
+    file_loader::FileFilter,
+    file_processor::{
+        cleaning::CleaningOptions,
+        extractor::PdfExtractor,
+        pdf_tables::{TableMode, CONTAINS_TABLE_KEY},
+        redaction::RedactionConfig,
+    },
+    text_loader::{MinChunkPolicy, SplittingStrategy, TextLoader},
+};
 
 /// Configuration for text embedding.
 ///
@@ -36,11 +57,28 @@ pub struct TextEmbedConfig {
     /// 256.
     pub chunk_size: Option<usize>,
     /// Controls the ratio of overlapping data across "chunks" of your input text. Defaults to 0.0,
-    /// or no overlap.
+    /// or no overlap. With [SplittingStrategy::Sentence], the overlap is made of the last whole
+    /// sentences of the previous chunk that fit in `overlap_ratio * chunk_size` tokens.
     pub overlap_ratio: Option<f32>,
-    /// Controls the size of each "batch" of data sent to the embedder. The default value depends
-    /// largely on the embedder, but will be set to 32 when using [TextEmbedConfig::default()]
+    /// Controls the size of each "batch" of data sent to the embedder. Defaults to the
+    /// recommended batch size of the embedder, see
+    /// [TextEmbedder::recommended_batch_size](crate::embeddings::embed::TextEmbedder::recommended_batch_size).
     pub batch_size: Option<usize>,
+    /// Times a few batches of growing size on the first chunks and keeps the fastest batch size
+    /// for the rest of the run. `batch_size`, when set, is the largest size tried. Defaults to
+    /// false.
+    pub auto_batch_size: Option<bool>,
+    /// Embeds the texts of each call from the shortest to the longest, in tokens, so that each
+    /// batch holds texts of similar length and less compute is spent on padding. The
+    /// embeddings are returned in the order of the texts. Helps most with local models and
+    /// chunks of very different lengths. Defaults to false.
+    pub sort_by_length: Option<bool>,
+    /// Chunks of files and directories with fewer tokens than this, such as a trailing list
+    /// item, are merged into the previous chunk or dropped, see `min_chunk_policy`. Does not
+    /// apply to [SplittingStrategy::Code]. Defaults to None, which keeps every chunk.
+    pub min_chunk_size: Option<usize>,
+    /// What happens to chunks below `min_chunk_size`. Defaults to [MinChunkPolicy::Merge].
+    pub min_chunk_policy: Option<MinChunkPolicy>,
     /// When using an adapter, this controls the size of the buffer. Defaults to 100.
     pub buffer_size: Option<usize>,
     /// Controls how documents are split into segments. See [SplittingStrategy] for options.
@@ -54,6 +92,152 @@ pub struct TextEmbedConfig {
     /// extracting text from the images. Defaults to false.
     pub use_ocr: Option<bool>,
     pub tesseract_path: Option<String>,
+    /// ISO 639-1 code of the document language, e.g. "fr", "zh" or "ja". When set, the
+    /// [SplittingStrategy::Sentence] strategy uses Unicode sentence boundaries with locale
+    /// specific rules, which is needed for languages that don't separate sentences with spaces.
+    pub language: Option<String>,
+    /// Backend finding sentence boundaries for [SplittingStrategy::Sentence], see
+    /// [SentenceBackend]. When unset, sentences are split by the chunker unless a `language`
+    /// or an `overlap_ratio` is set, in which case [SentenceBackend::Unicode] is used.
+    pub sentence_splitter: Option<SentenceBackend>,
+    /// Politeness settings used when fetching webpages. Defaults to [CrawlConfig::default()].
+    pub crawl_config: Option<CrawlConfig>,
+    /// When embedding webpages and HTML files, only keep the main content of the page (the
+    /// article body) and drop navigation, footers and ads. Defaults to false.
+    pub readability: Option<bool>,
+    /// Merge the JSON object stored in `<file>.meta.json`, next to each embedded file, into
+    /// the metadata of every chunk of that file. See
+    /// [TextLoader::get_metadata_with_sidecar](crate::text_loader::TextLoader::get_metadata_with_sidecar)
+    /// for the merge precedence. Defaults to false.
+    pub sidecar_metadata: Option<bool>,
+    /// Seed of the stochastic steps of the pipeline. Embedding is deterministic given the same
+    /// inputs and model, so this only affects the temperature fallback sampling of the audio
+    /// decoder, see [AudioDecoderModel::set_seed](crate::file_processor::audio::audio_processor::AudioDecoderModel::set_seed).
+    /// Defaults to None, which keeps the seed of the decoder.
+    pub seed: Option<u64>,
+    /// Precision of the returned dense vectors. [OutputDtype::F16] halves their memory use.
+    /// Defaults to None, which returns f32 vectors.
+    pub output_dtype: Option<OutputDtype>,
+    /// Skips the files of a directory for which it returns false, before they are read, e.g. to
+    /// leave out huge files or only embed recently modified ones. Defaults to None, which keeps
+    /// every file.
+    pub file_filter: Option<FileFilter>,
+    /// When embedding a directory, fail on files of a type that can't be embedded, e.g. a
+    /// `.zip` matched by the requested extensions, instead of skipping them with a warning.
+    /// Single files always fail. Defaults to false.
+    pub error_on_unsupported: Option<bool>,
+    /// Split the texts given to [embed_records](crate::embed_records) into chunks like the text
+    /// of a file, instead of embedding every record whole. Defaults to false.
+    pub chunk_records: Option<bool>,
+    /// Files bigger than this are not embedded: single files fail with
+    /// [FileLoadingError::DocumentTooLarge](crate::text_loader::FileLoadingError::DocumentTooLarge)
+    /// and directories skip them. Defaults to None, which accepts files of any size.
+    pub max_document_bytes: Option<u64>,
+    /// Embed the files inside `.zip`, `.tar.gz` and `.tgz` archives given to
+    /// [embed_file](crate::embed_file) or found in a directory, instead of treating archives as
+    /// unsupported files. Only the inner files of a supported type are embedded, and in a
+    /// directory only those matching the requested extensions and the `file_filter`. Their
+    /// chunks get the `archive` and `archive_path` metadata entries, see
+    /// [file_processor::archive](crate::file_processor::archive). Defaults to false.
+    pub extract_archives: Option<bool>,
+    /// Archives unpacking to more bytes than this are not embedded, to guard against zip bombs:
+    /// single files fail and directories skip them. Defaults to None, which is
+    /// [DEFAULT_MAX_ARCHIVE_BYTES](crate::file_processor::archive::DEFAULT_MAX_ARCHIVE_BYTES).
+    pub max_archive_bytes: Option<u64>,
+    /// Only embed the first `max_pages` pages of PDF files, e.g. to index the introduction of
+    /// large manuals. The chunks of a cut document get a `truncated` metadata entry set to
+    /// "true". Defaults to None, which embeds every page.
+    pub max_pages: Option<usize>,
+    /// Only embed the pages `first` to `last` of PDF files, both included and counted from 1,
+    /// e.g. `(10, 50)` to leave out the front and back matter of a manual. Files with fewer
+    /// pages fail, or are skipped in a directory. The chunks get a `page_range` metadata entry,
+    /// e.g. "10-50", and with `streaming_extraction` the `page_number` of the page they come
+    /// from. `max_pages` then counts pages from `first`. Ignored for other formats. Defaults to
+    /// None, which embeds every page.
+    pub page_range: Option<(usize, usize)>,
+    /// Only embed the first `max_chars` characters of the extracted text of each file, recorded
+    /// like [TextEmbedConfig::max_pages]. Ignored with `streaming_extraction`. Defaults to
+    /// None, which embeds the whole text.
+    pub max_chars: Option<usize>,
+    /// Read PDF files from the layout of their pages and write their tables as the
+    /// [TableMode] says: cell after cell, as Markdown tables, or not at all. The chunks of a
+    /// PDF file then get a `contains_table` metadata entry, "true" or "false", which with
+    /// `streaming_extraction` tells about the page of the chunk. Ignored with OCR. Defaults to
+    /// None, which reads PDF files in reading order without looking for tables.
+    pub table_mode: Option<TableMode>,
+    /// Read PDF files one page at a time, and embed their chunks and hand them to the adapter
+    /// `buffer_size` chunks at a time, instead of extracting the whole text first. Chunks
+    /// don't span pages and get the `page_number` they come from in their metadata. Their
+    /// `start_char` and `end_char` count from the start of the embedded pages, each trimmed and
+    /// cleaned, joined by a blank line. Ignored with OCR. Defaults to false.
+    pub streaming_extraction: Option<bool>,
+    /// Cleanup applied to the extracted text before chunking. Defaults to None, which is
+    /// [CleaningOptions::default]: whitespace is collapsed and control characters removed.
+    /// Source code split with [SplittingStrategy::Code] is never cleaned.
+    pub text_cleaning: Option<CleaningOptions>,
+    /// Masks emails, phone numbers and card numbers in the extracted text, so they are
+    /// neither embedded nor stored in [EmbedData](crate::embeddings::embed::EmbedData). Also
+    /// applied to the text of records. Redaction is best effort, see [RedactionConfig].
+    /// Defaults to None, which keeps the text as is.
+    pub redact: Option<RedactionConfig>,
+    /// Called for every chunk or query longer than the model accepts, whose end is cut by the
+    /// tokenizer and not embedded. This usually means `chunk_size` is too large for the model.
+    /// Texts are tokenized once more to detect it, and only local models report truncation.
+    /// Defaults to None.
+    pub on_truncation: Option<TruncationCallback>,
+    /// Cache the chunks and queries are looked up in before being embedded, see
+    /// [EmbeddingCache]. Defaults to None, which embeds every text.
+    #[cfg(feature = "cache")]
+    pub embedding_cache: Option<Arc<EmbeddingCache>>,
+    /// Keep the chunks and queries as given in
+    /// [EmbedData::text](crate::embeddings::embed::EmbedData::text), while the model embeds them
+    /// with its query or document prefix, e.g. "query: " for E5 models. When false, the stored
+    /// text is the prefixed text the model embedded. Cleaning is applied to the extracted text
+    /// before it is chunked, so stored chunks are always cleaned. Defaults to true.
+    pub store_original_text: Option<bool>,
+    /// Make [embed_file](crate::embed_file) also return one embedding for the whole file, the
+    /// mean of its chunk embeddings weighted by chunk length and normalized, after the chunk
+    /// embeddings. Embeddings are tagged with a `granularity` metadata entry, "chunk" or
+    /// "document", so both can be stored in one index. Only dense embeddings are averaged, and
+    /// files read with `streaming_extraction` get no document embedding. Defaults to false.
+    pub document_vector: Option<bool>,
+    /// Detect the language of every chunk of a file and store it in the `language` metadata
+    /// entry as an ISO 639-3 code, e.g. "eng", with the `confidence` of the detection, see
+    /// [detect_language](crate::chunkers::language::detect_language). Code chunks keep their
+    /// programming language. Defaults to false.
+    pub detect_language: Option<bool>,
+    /// Embed the path of the file, relative to the embedded directory or as given to
+    /// [embed_file](crate::embed_file), on the line before the text of its first chunk, so
+    /// that queries naming a file or module find it. The stored text stays the chunk, and the
+    /// path is stored in the `file_path` metadata entry of every chunk. Defaults to false.
+    pub prepend_filename: Option<bool>,
+    /// With `prepend_filename`, embed the path before the text of every chunk of the file
+    /// instead of only the first one. Defaults to false.
+    pub prepend_filename_to_every_chunk: Option<bool>,
+    /// Check that every vector has the dimension of the model, or of the first vector when the
+    /// model doesn't tell, to catch a model loaded from the wrong files or a pool mixing
+    /// models. [embed_file](crate::embed_file) and [embed_records](crate::embed_records) fail
+    /// on the first mismatch, naming the file and chunk, while directory runs skip the chunk
+    /// and log it. See [DimensionValidator](crate::embeddings::dimensions::DimensionValidator).
+    /// Defaults to false.
+    pub validate_dimensions: Option<bool>,
+    /// Copies of the model on several devices that
+    /// [embed_directory_stream](crate::embed_directory_stream) spreads the chunks of the
+    /// directory over, instead of embedding them with the embedder it is given, see
+    /// [EmbedderPool]. Embeddings then arrive in the order the copies finish. Defaults to None.
+    pub embedder_pool: Option<Arc<EmbedderPool>>,
+    /// Devices to load a copy of the model on with [TextEmbedConfig::model_loader], e.g.
+    /// `["cuda:0", "cuda:1"]`, see [parse_device](crate::embeddings::pool::parse_device).
+    /// [embed_directory_stream](crate::embed_directory_stream) loads them into an
+    /// [EmbedderPool] when no [TextEmbedConfig::embedder_pool] is set. Defaults to None.
+    pub devices: Option<Vec<String>>,
+    /// Loads the copies of the model on [TextEmbedConfig::devices]. Defaults to None.
+    pub model_loader: Option<ModelLoader>,
+    /// Cosine similarity from which [embed_diff](crate::embed_diff) pairs a chunk of the new
+    /// version with a chunk of the old version as a modified chunk, instead of an added and a
+    /// removed one. Defaults to None, which is
+    /// [DEFAULT_DIFF_THRESHOLD](crate::embeddings::diff::DEFAULT_DIFF_THRESHOLD).
+    pub diff_threshold: Option<f32>,
 }
 
 impl Default for TextEmbedConfig {
@@ -61,12 +245,49 @@ impl Default for TextEmbedConfig {
         Self {
             chunk_size: Some(256),
             overlap_ratio: Some(0.0),
-            batch_size: Some(32),
+            batch_size: None,
+            auto_batch_size: None,
+            min_chunk_size: None,
+            min_chunk_policy: None,
             buffer_size: Some(100),
             splitting_strategy: None,
             semantic_encoder: None,
             use_ocr: None,
             tesseract_path: None,
+            language: None,
+            sentence_splitter: None,
+            crawl_config: None,
+            readability: None,
+            sidecar_metadata: None,
+            seed: None,
+            output_dtype: None,
+            file_filter: None,
+            error_on_unsupported: None,
+            chunk_records: None,
+            max_document_bytes: None,
+            extract_archives: None,
+            max_archive_bytes: None,
+            max_pages: None,
+            page_range: None,
+            max_chars: None,
+            table_mode: None,
+            streaming_extraction: None,
+            text_cleaning: None,
+            redact: None,
+            on_truncation: None,
+            #[cfg(feature = "cache")]
+            embedding_cache: None,
+            store_original_text: None,
+            document_vector: None,
+            detect_language: None,
+            prepend_filename: None,
+            prepend_filename_to_every_chunk: None,
+            validate_dimensions: None,
+            sort_by_length: None,
+            embedder_pool: None,
+            devices: None,
+            model_loader: None,
+            diff_threshold: None,
         }
     }
 }
@@ -82,11 +303,11 @@ impl TextEmbedConfig {
         use_ocr: Option<bool>,
         tesseract_path: Option<String>,
     ) -> Self {
-        let config = Self::default()
+        let mut config = Self::default()
             .with_chunk_size(chunk_size.unwrap_or(256), overlap_ratio)
-            .with_batch_size(batch_size.unwrap_or(32))
             .with_buffer_size(buffer_size.unwrap_or(100))
             .with_ocr(use_ocr.unwrap_or(false), tesseract_path.as_deref());
+        config.batch_size = batch_size;
 
         match splitting_strategy {
             Some(SplittingStrategy::Semantic) => {
@@ -113,6 +334,17 @@ impl TextEmbedConfig {
         self
     }
 
+    pub fn with_auto_batch_size(mut self, auto_batch_size: bool) -> Self {
+        self.auto_batch_size = Some(auto_batch_size);
+        self
+    }
+
+    pub fn with_min_chunk_size(mut self, size: usize, policy: MinChunkPolicy) -> Self {
+        self.min_chunk_size = Some(size);
+        self.min_chunk_policy = Some(policy);
+        self
+    }
+
     pub fn with_buffer_size(mut self, size: usize) -> Self {
         self.buffer_size = Some(size);
         self
@@ -138,29 +370,447 @@ impl TextEmbedConfig {
         self
     }
 
+    pub fn with_language(mut self, language: Option<&str>) -> Self {
+        self.language = language.map(|l| l.to_string());
+        self
+    }
+
+    pub fn with_sentence_splitter(mut self, sentence_splitter: SentenceBackend) -> Self {
+        self.sentence_splitter = Some(sentence_splitter);
+        self
+    }
+
+    pub fn with_crawl_config(mut self, crawl_config: CrawlConfig) -> Self {
+        self.crawl_config = Some(crawl_config);
+        self
+    }
+
+    pub fn with_readability(mut self, readability: bool) -> Self {
+        self.readability = Some(readability);
+        self
+    }
+
+    pub fn with_sidecar_metadata(mut self, sidecar_metadata: bool) -> Self {
+        self.sidecar_metadata = Some(sidecar_metadata);
+        self
+    }
+
+    pub fn with_seed(mut self, seed: u64) -> Self {
+        self.seed = Some(seed);
+        self
+    }
+
+    pub fn with_output_dtype(mut self, output_dtype: OutputDtype) -> Self {
+        self.output_dtype = Some(output_dtype);
+        self
+    }
+
+    pub fn with_file_filter(mut self, file_filter: FileFilter) -> Self {
+        self.file_filter = Some(file_filter);
+        self
+    }
+
+    pub fn with_error_on_unsupported(mut self, error_on_unsupported: bool) -> Self {
+        self.error_on_unsupported = Some(error_on_unsupported);
+        self
+    }
+
+    pub fn with_chunk_records(mut self, chunk_records: bool) -> Self {
+        self.chunk_records = Some(chunk_records);
+        self
+    }
+
+    pub fn with_max_document_bytes(mut self, max_document_bytes: u64) -> Self {
+        self.max_document_bytes = Some(max_document_bytes);
+        self
+    }
+
+    pub fn with_extract_archives(mut self, extract_archives: bool) -> Self {
+        self.extract_archives = Some(extract_archives);
+        self
+    }
+
+    pub fn with_max_archive_bytes(mut self, max_archive_bytes: u64) -> Self {
+        self.max_archive_bytes = Some(max_archive_bytes);
+        self
+    }
+
+    pub fn with_table_mode(mut self, table_mode: TableMode) -> Self {
+        self.table_mode = Some(table_mode);
+        self
+    }
+
+    pub fn with_diff_threshold(mut self, diff_threshold: f32) -> Self {
+        self.diff_threshold = Some(diff_threshold);
+        self
+    }
+
+    pub fn with_max_pages(mut self, max_pages: usize) -> Self {
+        self.max_pages = Some(max_pages);
+        self
+    }
+
+    pub fn with_page_range(mut self, first: usize, last: usize) -> Self {
+        self.page_range = Some((first, last));
+        self
+    }
+
+    pub fn with_max_chars(mut self, max_chars: usize) -> Self {
+        self.max_chars = Some(max_chars);
+        self
+    }
+
+    pub fn with_streaming_extraction(mut self, streaming_extraction: bool) -> Self {
+        self.streaming_extraction = Some(streaming_extraction);
+        self
+    }
+
+    pub fn with_text_cleaning(mut self, text_cleaning: CleaningOptions) -> Self {
+        self.text_cleaning = Some(text_cleaning);
+        self
+    }
+
+    pub fn with_redact(mut self, redact: RedactionConfig) -> Self {
+        self.redact = Some(redact);
+        self
+    }
+
+    pub fn with_on_truncation(mut self, on_truncation: TruncationCallback) -> Self {
+        self.on_truncation = Some(on_truncation);
+        self
+    }
+
+    #[cfg(feature = "cache")]
+    pub fn with_embedding_cache(mut self, embedding_cache: Arc<EmbeddingCache>) -> Self {
+        self.embedding_cache = Some(embedding_cache);
+        self
+    }
+
+    pub fn with_store_original_text(mut self, store_original_text: bool) -> Self {
+        self.store_original_text = Some(store_original_text);
+        self
+    }
+
+    pub fn with_document_vector(mut self, document_vector: bool) -> Self {
+        self.document_vector = Some(document_vector);
+        self
+    }
+
+    pub fn with_detect_language(mut self, detect_language: bool) -> Self {
+        self.detect_language = Some(detect_language);
+        self
+    }
+
+    pub fn with_prepend_filename(mut self, prepend_filename: bool, every_chunk: bool) -> Self {
+        self.prepend_filename = Some(prepend_filename);
+        self.prepend_filename_to_every_chunk = Some(every_chunk);
+        self
+    }
+
+    pub fn with_validate_dimensions(mut self, validate_dimensions: bool) -> Self {
+        self.validate_dimensions = Some(validate_dimensions);
+        self
+    }
+
+    pub fn with_sort_by_length(mut self, sort_by_length: bool) -> Self {
+        self.sort_by_length = Some(sort_by_length);
+        self
+    }
+
+    pub fn with_embedder_pool(mut self, embedder_pool: Arc<EmbedderPool>) -> Self {
+        self.embedder_pool = Some(embedder_pool);
+        self
+    }
+
+    pub fn with_devices(mut self, devices: Vec<String>, model_loader: ModelLoader) -> Self {
+        self.devices = Some(devices);
+        self.model_loader = Some(model_loader);
+        self
+    }
+
+    /// Applies the output settings to `embeddings` before they are returned or upserted, such as
+    /// [TextEmbedConfig::output_dtype]. Every embedding function ends with it.
+    pub(crate) fn finish_embeddings(&self, embeddings: &mut [EmbedData]) {
+        convert_output_dtype(embeddings, self.output_dtype);
+    }
+
+    /// The [TextEmbedConfig::embedder_pool], else the one loaded on [TextEmbedConfig::devices],
+    /// else None.
+    pub(crate) fn load_embedder_pool(&self) -> anyhow::Result<Option<Arc<EmbedderPool>>> {
+        if let Some(embedder_pool) = &self.embedder_pool {
+            return Ok(Some(embedder_pool.clone()));
+        }
+        let Some(devices) = &self.devices else {
+            return Ok(None);
+        };
+        let Some(load) = &self.model_loader else {
+            return Err(anyhow::anyhow!(
+                "Loading the model on {:?} needs a model loader",
+                devices
+            ));
+        };
+        Ok(Some(Arc::new(EmbedderPool::load(devices, || load())?)))
+    }
+
+    /// Embeds queries with `embedder`, through [TextEmbedConfig::embedding_cache] when the
+    /// `cache` feature is enabled and a cache is set, and in the order of
+    /// [TextEmbedConfig::sort_by_length].
+    pub(crate) async fn embed_query_batch<E: AsyncEmbedder + ?Sized>(
+        &self,
+        embedder: &E,
+        text_batch: &[String],
+        batch_size: Option<usize>,
+    ) -> anyhow::Result<Vec<EmbeddingResult>> {
+        let sorted = self.sort_texts(embedder, text_batch);
+        let texts = sorted.as_ref().map_or(text_batch, |(_, texts)| texts);
+        #[cfg(feature = "cache")]
+        let encodings = match &self.embedding_cache {
+            Some(cache) => cache.embed_query_batch(embedder, texts, batch_size).await,
+            None => embedder.embed_query_batch(texts, batch_size).await,
+        };
+        #[cfg(not(feature = "cache"))]
+        let encodings = embedder.embed_query_batch(texts, batch_size).await;
+        in_original_order(encodings, sorted)
+    }
+
+    /// Embeds documents with `embedder`, see [TextEmbedConfig::embed_query_batch].
+    pub(crate) async fn embed_document_batch<E: AsyncEmbedder + ?Sized>(
+        &self,
+        embedder: &E,
+        text_batch: &[String],
+        batch_size: Option<usize>,
+    ) -> anyhow::Result<Vec<EmbeddingResult>> {
+        let sorted = self.sort_texts(embedder, text_batch);
+        let texts = sorted.as_ref().map_or(text_batch, |(_, texts)| texts);
+        #[cfg(feature = "cache")]
+        let encodings = match &self.embedding_cache {
+            Some(cache) => {
+                cache
+                    .embed_document_batch(embedder, texts, batch_size)
+                    .await
+            }
+            None => embedder.embed_document_batch(texts, batch_size).await,
+        };
+        #[cfg(not(feature = "cache"))]
+        let encodings = embedder.embed_document_batch(texts, batch_size).await;
+        in_original_order(encodings, sorted)
+    }
+
+    /// The order of [TextEmbedConfig::sort_by_length] and the texts in that order, None when
+    /// the texts are embedded as given.
+    fn sort_texts<E: AsyncEmbedder + ?Sized>(
+        &self,
+        embedder: &E,
+        text_batch: &[String],
+    ) -> Option<(Vec<usize>, Vec<String>)> {
+        if !self.sort_by_length.unwrap_or(false) || text_batch.len() < 2 {
+            return None;
+        }
+        let order = length_order(embedder.tokenizer(), text_batch);
+        let texts = order
+            .iter()
+            .map(|&index| text_batch[index].clone())
+            .collect();
+        Some((order, texts))
+    }
+
+    /// Extracts the text of `file`, keeps the part allowed by [TextEmbedConfig::page_range],
+    /// [TextEmbedConfig::max_pages] and [TextEmbedConfig::max_chars] and cleans it. Also
+    /// returns the metadata entries learned while extracting: `page_range` and `truncated` when
+    /// the text was cut, and `contains_table` for PDF files read with a
+    /// [TextEmbedConfig::table_mode].
+    pub(crate) fn extract_text<T: AsRef<std::path::Path>>(
+        &self,
+        file: &T,
+    ) -> anyhow::Result<(String, HashMap<String, String>)> {
+        let pdf = PdfExtractor::new(
+            self.use_ocr.unwrap_or(false),
+            self.tesseract_path.as_deref(),
+        )
+        .with_table_mode(self.table_mode);
+        let mut document = TextLoader::extract(file, pdf)?;
+        let mut metadata = HashMap::new();
+        if let (Some((first, last)), false) = (self.page_range, document.pages.is_empty()) {
+            document.select_pages(first, last)?;
+            metadata.insert("page_range".to_string(), format!("{}-{}", first, last));
+        }
+        if document.truncate(self.max_pages, self.max_chars) {
+            metadata.insert("truncated".to_string(), "true".to_string());
+        }
+        if let Some(contains_table) = document.metadata.remove(CONTAINS_TABLE_KEY) {
+            metadata.insert(CONTAINS_TABLE_KEY.to_string(), contains_table);
+        }
+        Ok((self.clean_text(&document.text), metadata))
+    }
+
+    /// Applies [TextEmbedConfig::text_cleaning] and [TextEmbedConfig::redact] to extracted
+    /// text.
+    pub(crate) fn clean_text(&self, text: &str) -> String {
+        let text = match self.splitting_strategy {
+            Some(SplittingStrategy::Code { .. }) => text.to_string(),
+            _ => self.text_cleaning.unwrap_or_default().clean(text),
+        };
+        self.redact_text(text)
+    }
+
+    /// Applies [TextEmbedConfig::redact] to `text`.
+    pub(crate) fn redact_text(&self, text: String) -> String {
+        match &self.redact {
+            Some(redact) => redact.redact(&text),
+            None => text,
+        }
+    }
+
+    /// Replaces the settings set in `overrides`, e.g. to try several chunk sizes with one
+    /// config. A setting takes the value of `overrides` first, then the one of the config, then
+    /// the default.
+    ///
+    /// ```rust
+    /// use embed_anything::config::{Overrides, TextEmbedConfig};
+    ///
+    /// let config = TextEmbedConfig::default().with_batch_size(16);
+    /// let tuned = config
+    ///     .clone()
+    ///     .with_overrides(&Overrides::default().with_chunk_size(512));
+    /// assert_eq!(tuned.chunk_size, Some(512));
+    /// assert_eq!(tuned.batch_size, Some(16));
+    /// ```
+    pub fn with_overrides(mut self, overrides: &Overrides) -> Self {
+        self.chunk_size = overrides.chunk_size.or(self.chunk_size);
+        self.batch_size = overrides.batch_size.or(self.batch_size);
+        self
+    }
+
     pub fn build(self) -> TextEmbedConfig {
-        if self.semantic_encoder.is_none() && self.splitting_strategy.is_some() {
+        if self.semantic_encoder.is_none()
+            && matches!(self.splitting_strategy, Some(SplittingStrategy::Semantic))
+        {
             panic!("Semantic encoder is required when using Semantic splitting strategy");
         }
         self
     }
 }
 
+/// Puts the embeddings of texts sorted by [TextEmbedConfig::sort_texts] back in the order of the
+/// texts as given, including the ones of a [BudgetExceeded] error.
+fn in_original_order(
+    encodings: anyhow::Result<Vec<EmbeddingResult>>,
+    sorted: Option<(Vec<usize>, Vec<String>)>,
+) -> anyhow::Result<Vec<EmbeddingResult>> {
+    let Some((order, _)) = sorted else {
+        return encodings;
+    };
+    match encodings {
+        Ok(encodings) => Ok(restore_order(encodings, &order)),
+        Err(error) => match error.downcast::<BudgetExceeded>() {
+            Ok(exceeded) => Err(exceeded.remap(&order).into()),
+            Err(error) => Err(error),
+        },
+    }
+}
+
+/// Settings replacing the ones of a [TextEmbedConfig] for a single call, see
+/// [TextEmbedConfig::with_overrides]. Unset fields keep the value of the config.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Overrides {
+    pub chunk_size: Option<usize>,
+    pub batch_size: Option<usize>,
+}
+
+impl Overrides {
+    pub fn with_chunk_size(mut self, chunk_size: usize) -> Self {
+        self.chunk_size = Some(chunk_size);
+        self
+    }
+
+    pub fn with_batch_size(mut self, batch_size: usize) -> Self {
+        self.batch_size = Some(batch_size);
+        self
+    }
+}
+
+/// Controls how webpages are fetched by [embed_webpage](crate::embed_webpage) and
+/// [embed_urls](crate::embed_urls).
+///
+/// ```rust
+/// use embed_anything::config::CrawlConfig;
+/// let config = CrawlConfig {
+///     user_agent: "MyCrawler/1.0".to_string(),
+///     delay_ms: 1000,
+///     ..Default::default()
+/// };
+/// ```
+#[derive(Clone, Debug)]
+pub struct CrawlConfig {
+    /// User-Agent header sent with every request, also used to pick the robots.txt rules.
+    pub user_agent: String,
+    /// Skip pages that the site's robots.txt disallows. Defaults to true.
+    pub respect_robots: bool,
+    /// Minimum time between two requests to the same host, in milliseconds. Defaults to 0.
+    pub delay_ms: u64,
+    /// Maximum number of redirects followed for a page. Defaults to 10.
+    pub max_redirects: usize,
+    /// Time allowed for a request, from connecting to reading the whole page, in milliseconds.
+    /// Defaults to 30 000.
+    pub timeout_ms: u64,
+    /// Number of times a request is sent again after a timeout, a connection error or a 5xx or
+    /// 429 status. Defaults to 2.
+    pub max_retries: usize,
+    /// Wait before the first retry in milliseconds, doubled for each following retry. Defaults
+    /// to 500.
+    pub retry_backoff_ms: u64,
+}
+
+impl Default for CrawlConfig {
+    fn default() -> Self {
+        Self {
+            user_agent: format!("EmbedAnything/{}", env!("CARGO_PKG_VERSION")),
+            respect_robots: true,
+            delay_ms: 0,
+            max_redirects: 10,
+            timeout_ms: 30_000,
+            max_retries: 2,
+            retry_backoff_ms: 500,
+        }
+    }
+}
+
 #[derive(Clone)]
 pub struct ImageEmbedConfig {
     pub buffer_size: Option<usize>, // Required for adapter. Default is 100.
+    /// Number of threads decoding images while the model embeds the ones already decoded.
+    /// Decoding runs ahead of the model by at most `buffer_size` images. Defaults to None, which
+    /// uses one thread per CPU.
+    pub decode_threads: Option<usize>,
+    /// Skips the images for which it returns false, see [TextEmbedConfig::file_filter].
+    pub file_filter: Option<FileFilter>,
 }
 
 impl Default for ImageEmbedConfig {
     fn default() -> Self {
         Self {
             buffer_size: Some(100),
+            decode_threads: None,
+            file_filter: None,
         }
     }
 }
 
 impl ImageEmbedConfig {
     pub fn new(buffer_size: Option<usize>) -> Self {
-        Self { buffer_size }
+        Self {
+            buffer_size,
+            ..Default::default()
+        }
+    }
+
+    pub fn with_decode_threads(mut self, decode_threads: usize) -> Self {
+        self.decode_threads = Some(decode_threads);
+        self
+    }
+
+    pub fn with_file_filter(mut self, file_filter: FileFilter) -> Self {
+        self.file_filter = Some(file_filter);
+        self
     }
 }
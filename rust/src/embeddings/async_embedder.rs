@@ -0,0 +1,207 @@
+//! An object safe embedding trait, for code that picks the model at runtime.
+//!
+//! [Embedder] is an enum, so adding a model of your own means wrapping it in another enum.
+//! [AsyncEmbedder] can be used as a trait object instead, which allows registries such as a
+//! `HashMap<String, Arc<dyn AsyncEmbedder>>` that hold the built-in models next to custom
+//! ones.
+//!
+//! ```rust,no_run
+//! use std::{collections::HashMap, sync::Arc};
+//!
+//! use embed_anything::embeddings::{async_embedder::AsyncEmbedder, embed::Embedder};
+//!
+//! async fn embed(registry: &HashMap<String, Arc<dyn AsyncEmbedder>>, model: &str) {
+//!     let embedder = registry.get(model).unwrap();
+//!     let embeddings = embedder
+//!         .embed_query_batch(&["What is Rust?".to_string()], None)
+//!         .await
+//!         .unwrap();
+//! }
+//!
+//! let mut registry: HashMap<String, Arc<dyn AsyncEmbedder>> = HashMap::new();
+//! let bert = Embedder::from_pretrained_hf("bert", "BAAI/bge-small-en-v1.5", None, None).unwrap();
+//! registry.insert("bge-small".to_string(), Arc::new(bert));
+//! ```
+
+use futures::future::BoxFuture;
+use tokenizers::Tokenizer;
+
+use super::embed::{Embedder, EmbeddingResult, ModelMetadata, TextEmbedder};
+
+/// Embeds text behind `dyn`. The methods return boxed futures so the trait stays object safe.
+pub trait AsyncEmbedder: Send + Sync {
+    fn embed<'a>(
+        &'a self,
+        text_batch: &'a [String],
+        batch_size: Option<usize>,
+    ) -> BoxFuture<'a, Result<Vec<EmbeddingResult>, anyhow::Error>>;
+
+    /// See [TextEmbed::embed_query_batch](super::embed::TextEmbed::embed_query_batch).
+    fn embed_query_batch<'a>(
+        &'a self,
+        text_batch: &'a [String],
+        batch_size: Option<usize>,
+    ) -> BoxFuture<'a, Result<Vec<EmbeddingResult>, anyhow::Error>> {
+        self.embed(text_batch, batch_size)
+    }
+
+    /// See [TextEmbed::embed_document_batch](super::embed::TextEmbed::embed_document_batch).
+    fn embed_document_batch<'a>(
+        &'a self,
+        text_batch: &'a [String],
+        batch_size: Option<usize>,
+    ) -> BoxFuture<'a, Result<Vec<EmbeddingResult>, anyhow::Error>> {
+        self.embed(text_batch, batch_size)
+    }
+
+    /// Whether the embeddings are L2-normalized.
+    fn is_normalized(&self) -> bool {
+        false
+    }
+
+    /// Describes the model. Embedders that do not override this report an unknown model.
+    fn model_metadata(&self) -> ModelMetadata {
+        ModelMetadata::default()
+    }
+
+    /// The tokenizer of the model, None for models that don't expose it such as cloud models.
+    fn tokenizer(&self) -> Option<&Tokenizer> {
+        None
+    }
+}
+
+impl AsyncEmbedder for Embedder {
+    fn embed<'a>(
+        &'a self,
+        text_batch: &'a [String],
+        batch_size: Option<usize>,
+    ) -> BoxFuture<'a, Result<Vec<EmbeddingResult>, anyhow::Error>> {
+        Box::pin(Embedder::embed(self, text_batch, batch_size))
+    }
+
+    fn embed_query_batch<'a>(
+        &'a self,
+        text_batch: &'a [String],
+        batch_size: Option<usize>,
+    ) -> BoxFuture<'a, Result<Vec<EmbeddingResult>, anyhow::Error>> {
+        Box::pin(Embedder::embed_query_batch(self, text_batch, batch_size))
+    }
+
+    fn embed_document_batch<'a>(
+        &'a self,
+        text_batch: &'a [String],
+        batch_size: Option<usize>,
+    ) -> BoxFuture<'a, Result<Vec<EmbeddingResult>, anyhow::Error>> {
+        Box::pin(Embedder::embed_document_batch(self, text_batch, batch_size))
+    }
+
+    fn is_normalized(&self) -> bool {
+        Embedder::is_normalized(self)
+    }
+
+    fn model_metadata(&self) -> ModelMetadata {
+        self.metadata()
+    }
+
+    fn tokenizer(&self) -> Option<&Tokenizer> {
+        Embedder::tokenizer(self)
+    }
+}
+
+impl AsyncEmbedder for TextEmbedder {
+    fn embed<'a>(
+        &'a self,
+        text_batch: &'a [String],
+        batch_size: Option<usize>,
+    ) -> BoxFuture<'a, Result<Vec<EmbeddingResult>, anyhow::Error>> {
+        Box::pin(TextEmbedder::embed(self, text_batch, batch_size))
+    }
+
+    fn embed_query_batch<'a>(
+        &'a self,
+        text_batch: &'a [String],
+        batch_size: Option<usize>,
+    ) -> BoxFuture<'a, Result<Vec<EmbeddingResult>, anyhow::Error>> {
+        Box::pin(TextEmbedder::embed_query_batch(
+            self, text_batch, batch_size,
+        ))
+    }
+
+    fn embed_document_batch<'a>(
+        &'a self,
+        text_batch: &'a [String],
+        batch_size: Option<usize>,
+    ) -> BoxFuture<'a, Result<Vec<EmbeddingResult>, anyhow::Error>> {
+        Box::pin(TextEmbedder::embed_document_batch(
+            self, text_batch, batch_size,
+        ))
+    }
+
+    fn is_normalized(&self) -> bool {
+        TextEmbedder::is_normalized(self)
+    }
+
+    fn model_metadata(&self) -> ModelMetadata {
+        self.metadata()
+    }
+
+    fn tokenizer(&self) -> Option<&Tokenizer> {
+        TextEmbedder::tokenizer(self)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{collections::HashMap, sync::Arc};
+
+    use super::*;
+
+    /// Embeds every text as its length in bytes.
+    struct LengthEmbedder;
+
+    impl AsyncEmbedder for LengthEmbedder {
+        fn embed<'a>(
+            &'a self,
+            text_batch: &'a [String],
+            _batch_size: Option<usize>,
+        ) -> BoxFuture<'a, Result<Vec<EmbeddingResult>, anyhow::Error>> {
+            Box::pin(async move {
+                Ok(text_batch
+                    .iter()
+                    .map(|text| EmbeddingResult::DenseVector(vec![text.len() as f32]))
+                    .collect())
+            })
+        }
+    }
+
+    #[tokio::test]
+    async fn test_registry_of_trait_objects() {
+        let mut registry: HashMap<String, Arc<dyn AsyncEmbedder>> = HashMap::new();
+        registry.insert("length".to_string(), Arc::new(LengthEmbedder));
+        registry.insert(
+            "minilm".to_string(),
+            Arc::new(
+                Embedder::from_pretrained_hf(
+                    "bert",
+                    "sentence-transformers/all-MiniLM-L6-v2",
+                    None,
+                    None,
+                )
+                .unwrap(),
+            ),
+        );
+
+        let texts = vec!["Hello".to_string(), "World!".to_string()];
+        let length = registry["length"].embed(&texts, None).await.unwrap();
+        assert_eq!(length[1].to_dense().unwrap(), vec![6.0]);
+        assert!(!registry["length"].is_normalized());
+
+        let minilm = registry["minilm"]
+            .embed_document_batch(&texts, None)
+            .await
+            .unwrap();
+        assert_eq!(minilm.len(), 2);
+        assert_eq!(minilm[0].to_dense().unwrap().len(), 384);
+        assert!(registry["minilm"].is_normalized());
+    }
+}
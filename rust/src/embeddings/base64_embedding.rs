@@ -0,0 +1,131 @@
+//! Encoding embeddings as base64 for JSON transport, the `encoding_format=base64` of the
+//! OpenAI API. A vector is sent as the base64 of its little endian f32 bytes, about a quarter
+//! of the size of its decimal JSON array, and is decoded without loss of precision.
+//!
+//! The module can be used as a serde `with` module on an [EmbeddingResult] field:
+//!
+//! ```rust
+//! use embed_anything::embeddings::embed::EmbeddingResult;
+//! use serde::{Deserialize, Serialize};
+//!
+//! #[derive(Serialize, Deserialize)]
+//! struct Response {
+//!     #[serde(with = "embed_anything::embeddings::base64_embedding")]
+//!     embedding: EmbeddingResult,
+//! }
+//! ```
+//!
+//! Dense vectors are serialized to a string, multi-vector embeddings to a list of strings.
+//! Half precision vectors are sent as f32 and come back as [EmbeddingResult::DenseVector], like
+//! the pooled vector of [EmbeddingResult::DenseWithTokens], whose tokens are not sent.
+
+use anyhow::{anyhow, Result};
+use base64::Engine;
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+
+use super::embed::EmbeddingResult;
+
+const ENGINE: base64::engine::GeneralPurpose = base64::engine::general_purpose::STANDARD;
+
+/// Encodes a vector as the base64 of its little endian f32 bytes.
+pub fn encode(vector: &[f32]) -> String {
+    let bytes = vector
+        .iter()
+        .flat_map(|value| value.to_le_bytes())
+        .collect::<Vec<_>>();
+    ENGINE.encode(bytes)
+}
+
+/// Decodes a vector encoded with [encode].
+pub fn decode(encoded: &str) -> Result<Vec<f32>> {
+    let bytes = ENGINE.decode(encoded)?;
+    if bytes.len() % 4 != 0 {
+        return Err(anyhow!(
+            "Expected a multiple of 4 bytes of f32 values, got {} bytes",
+            bytes.len()
+        ));
+    }
+    Ok(bytes
+        .chunks_exact(4)
+        .map(|value| f32::from_le_bytes([value[0], value[1], value[2], value[3]]))
+        .collect())
+}
+
+#[derive(Serialize, Deserialize)]
+#[serde(untagged)]
+enum Encoded {
+    Dense(String),
+    MultiVector(Vec<String>),
+}
+
+pub fn serialize<S: Serializer>(
+    embedding: &EmbeddingResult,
+    serializer: S,
+) -> Result<S::Ok, S::Error> {
+    let encoded = match embedding {
+        EmbeddingResult::MultiVector(vectors) => {
+            Encoded::MultiVector(vectors.iter().map(|vector| encode(vector)).collect())
+        }
+        dense => Encoded::Dense(encode(
+            &dense.to_dense().map_err(serde::ser::Error::custom)?,
+        )),
+    };
+    encoded.serialize(serializer)
+}
+
+pub fn deserialize<'de, D: Deserializer<'de>>(
+    deserializer: D,
+) -> Result<EmbeddingResult, D::Error> {
+    let embedding = match Encoded::deserialize(deserializer)? {
+        Encoded::Dense(encoded) => decode(&encoded).map(EmbeddingResult::DenseVector),
+        Encoded::MultiVector(encoded) => encoded
+            .iter()
+            .map(|vector| decode(vector))
+            .collect::<Result<Vec<_>>>()
+            .map(EmbeddingResult::MultiVector),
+    };
+    embedding.map_err(serde::de::Error::custom)
+}
+
+#[cfg(test)]
+mod tests {
+    use half::f16;
+
+    use super::*;
+
+    #[derive(Serialize, Deserialize)]
+    struct Response {
+        #[serde(with = "crate::embeddings::base64_embedding")]
+        embedding: EmbeddingResult,
+    }
+
+    fn round_trip(embedding: EmbeddingResult) -> EmbeddingResult {
+        let json = serde_json::to_string(&Response { embedding }).unwrap();
+        serde_json::from_str::<Response>(&json).unwrap().embedding
+    }
+
+    #[test]
+    fn test_base64_round_trip() {
+        let vector = vec![0.1, -2.5, f32::MIN_POSITIVE, 1e30, 0.0];
+        assert_eq!(decode(&encode(&vector)).unwrap(), vector);
+        // As Python's base64.b64encode(struct.pack("<2f", 1.0, 2.0)).
+        assert_eq!(encode(&[1.0, 2.0]), "AACAPwAAAEA=");
+
+        let json = serde_json::to_string(&Response {
+            embedding: EmbeddingResult::DenseVector(vec![1.0, 2.0]),
+        })
+        .unwrap();
+        assert_eq!(json, r#"{"embedding":"AACAPwAAAEA="}"#);
+
+        let dense = round_trip(EmbeddingResult::DenseVector(vector.clone()));
+        assert_eq!(dense.to_dense().unwrap(), vector);
+        let multi_vector = vec![vec![1.0, 2.0], vec![-3.0, 4.5]];
+        let multi = round_trip(EmbeddingResult::MultiVector(multi_vector.clone()));
+        assert_eq!(multi.to_multi_vector().unwrap(), multi_vector);
+        let half = round_trip(EmbeddingResult::HalfVector(vec![f16::from_f32(0.5)]));
+        assert!(matches!(half, EmbeddingResult::DenseVector(v) if v == [0.5]));
+
+        assert!(decode("AACAPwAA").is_err());
+        assert!(serde_json::from_str::<Response>(r#"{"embedding":"not base64"}"#).is_err());
+    }
+}
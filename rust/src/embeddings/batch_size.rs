@@ -0,0 +1,189 @@
+//! Picks a batch size for a local model by timing a few batches on the current hardware.
+//!
+//! [BatchSizeProbe] tries batch sizes 1, 2, 4, ... and stops when a batch fails, usually
+//! because it ran out of memory, or when doubling the batch no longer makes each text
+//! noticeably faster to embed. The fastest size seen so far is then kept.
+//!
+//! [embed_with_oom_fallback] covers the other direction: when a batch runs out of memory in the
+//! middle of a job, it is split in halves and retried instead of failing the job.
+
+use std::time::Duration;
+
+/// Upper bound of the probe when no batch size is configured.
+pub const MAX_AUTO_BATCH_SIZE: usize = 256;
+
+/// Doubling the batch must cut the time per text by at least this ratio to keep probing.
+const MIN_SPEEDUP: f64 = 0.1;
+
+pub struct BatchSizeProbe {
+    max_batch_size: usize,
+    next: Option<usize>,
+    best: Option<(usize, Duration)>,
+}
+
+impl BatchSizeProbe {
+    pub fn new(max_batch_size: usize) -> Self {
+        Self {
+            max_batch_size: max_batch_size.max(1),
+            next: Some(1),
+            best: None,
+        }
+    }
+
+    /// The batch size to time next, or None once the probe is done.
+    pub fn next_size(&self) -> Option<usize> {
+        self.next
+    }
+
+    /// Records how long a batch of `size` texts took, or that it failed.
+    pub fn record(&mut self, size: usize, elapsed: Result<Duration, anyhow::Error>) {
+        let Ok(elapsed) = elapsed else {
+            // Out of memory, or any other failure: fall back to the last size that worked.
+            self.next = None;
+            return;
+        };
+        let per_text = elapsed / size as u32;
+        let improved = match self.best {
+            Some((_, best)) => per_text.as_secs_f64() < best.as_secs_f64() * (1.0 - MIN_SPEEDUP),
+            None => true,
+        };
+        if improved {
+            self.best = Some((size, per_text));
+        }
+        self.next =
+            (improved && size < self.max_batch_size).then(|| (size * 2).min(self.max_batch_size));
+    }
+
+    /// The fastest batch size seen, 1 when every batch failed.
+    pub fn best(&self) -> usize {
+        self.best.map_or(1, |(size, _)| size)
+    }
+}
+
+/// Whether `error` reports that the device ran out of memory, as raised by CUDA, Metal or ONNX
+/// Runtime.
+pub fn is_out_of_memory(error: &anyhow::Error) -> bool {
+    let message = format!("{:?}", error).to_lowercase();
+    [
+        "out of memory",
+        "out_of_memory",
+        "failed to allocate memory",
+    ]
+    .iter()
+    .any(|pattern| message.contains(pattern))
+}
+
+/// Runs `embed_batch` on consecutive batches of `batch_size` texts. When a batch runs out of
+/// memory, the batch size is halved and the failed texts are retried, down to a single text.
+/// The smaller batch size is kept for the rest of the texts.
+pub fn embed_with_oom_fallback<T>(
+    text_batch: &[String],
+    batch_size: usize,
+    mut embed_batch: impl FnMut(&[String]) -> Result<Vec<T>, anyhow::Error>,
+) -> Result<Vec<T>, anyhow::Error> {
+    let mut batch_size = batch_size.max(1);
+    let mut encodings = Vec::with_capacity(text_batch.len());
+    let mut start = 0;
+    while start < text_batch.len() {
+        let end = (start + batch_size).min(text_batch.len());
+        match embed_batch(&text_batch[start..end]) {
+            Ok(batch_encodings) => {
+                encodings.extend(batch_encodings);
+                start = end;
+            }
+            Err(e) if end - start > 1 && is_out_of_memory(&e) => {
+                batch_size = (end - start) / 2;
+                tracing::warn!(
+                    "Out of memory on a batch of {} texts, retrying with batch size {}",
+                    end - start,
+                    batch_size
+                );
+            }
+            Err(e) => return Err(e),
+        }
+    }
+    Ok(encodings)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn run(
+        max_batch_size: usize,
+        timing: impl Fn(usize) -> Result<Duration, anyhow::Error>,
+    ) -> usize {
+        let mut probe = BatchSizeProbe::new(max_batch_size);
+        while let Some(size) = probe.next_size() {
+            probe.record(size, timing(size));
+        }
+        probe.best()
+    }
+
+    #[test]
+    fn test_stops_at_latency_knee() {
+        // A fixed overhead of 10ms per batch and 1ms per text, until the device is saturated
+        // at 16 texts where the time per text starts growing.
+        let timing = |size: usize| {
+            let per_text = if size <= 16 { 1.0 } else { size as f64 / 16.0 };
+            Ok(Duration::from_secs_f64(
+                (10.0 + per_text * size as f64) / 1000.0,
+            ))
+        };
+        assert_eq!(run(256, timing), 16);
+    }
+
+    #[test]
+    fn test_falls_back_on_oom() {
+        let timing = |size: usize| {
+            if size > 8 {
+                Err(anyhow::anyhow!("CUDA_ERROR_OUT_OF_MEMORY"))
+            } else {
+                Ok(Duration::from_millis(10 + size as u64))
+            }
+        };
+        assert_eq!(run(256, timing), 8);
+        assert_eq!(run(256, |_| Err(anyhow::anyhow!("out of memory"))), 1);
+    }
+
+    #[test]
+    fn test_respects_max_batch_size() {
+        assert_eq!(
+            run(24, |size| Ok(Duration::from_millis(100 + size as u64))),
+            24
+        );
+    }
+
+    #[test]
+    fn test_oom_fallback_halves_batch() {
+        let texts = (0..10).map(|i| i.to_string()).collect::<Vec<_>>();
+        let mut sizes = Vec::new();
+        let encodings = embed_with_oom_fallback(&texts, 8, |batch| {
+            sizes.push(batch.len());
+            if batch.len() > 2 {
+                anyhow::bail!("DriverError(CUDA_ERROR_OUT_OF_MEMORY, \"out of memory\")");
+            }
+            Ok(batch.to_vec())
+        })
+        .unwrap();
+        assert_eq!(encodings, texts);
+        assert_eq!(sizes, vec![8, 4, 2, 2, 2, 2, 2]);
+    }
+
+    #[test]
+    fn test_oom_fallback_gives_up() {
+        let texts = vec!["a".to_string(), "b".to_string()];
+        let oom = embed_with_oom_fallback(&texts, 2, |_| -> Result<Vec<()>, _> {
+            anyhow::bail!("out of memory")
+        });
+        assert!(is_out_of_memory(&oom.unwrap_err()));
+
+        let mut calls = 0;
+        let other = embed_with_oom_fallback(&texts, 2, |_| -> Result<Vec<()>, _> {
+            calls += 1;
+            anyhow::bail!("shape mismatch")
+        });
+        assert!(other.is_err());
+        assert_eq!(calls, 1);
+    }
+}
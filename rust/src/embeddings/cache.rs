@@ -0,0 +1,311 @@
+//! An on-disk cache of embeddings, enabled with the `cache` feature.
+//!
+//! Set an [EmbeddingCache] with
+//! [TextEmbedConfig::with_embedding_cache](crate::config::TextEmbedConfig::with_embedding_cache)
+//! and texts already embedded by the same model, e.g. the unchanged files of a directory, are
+//! read from disk instead of being embedded again. This saves inference time with local models
+//! and API costs with cloud models when the same documents are embedded across runs.
+//!
+//! ```rust,no_run
+//! use std::sync::Arc;
+//!
+//! use embed_anything::{
+//!     config::TextEmbedConfig, embed_file, embeddings::cache::EmbeddingCache,
+//!     embeddings::embed::Embedder,
+//! };
+//!
+//! async fn embed_twice(embedder: &Embedder) {
+//!     let cache = Arc::new(EmbeddingCache::new(".embedding_cache").unwrap());
+//!     let config = TextEmbedConfig::default().with_embedding_cache(cache.clone());
+//!     for _ in 0..2 {
+//!         embed_file("notes.md", embedder, Some(&config), None).await.unwrap();
+//!     }
+//!     println!("{:?}", cache.stats());
+//! }
+//! ```
+
+use std::{
+    fs,
+    path::{Path, PathBuf},
+    sync::atomic::{AtomicU64, Ordering},
+};
+
+use anyhow::Result;
+use sha2::{Digest, Sha256};
+use unicode_normalization::UnicodeNormalization;
+
+use super::{
+    async_embedder::AsyncEmbedder,
+    cloud::BudgetExceeded,
+    embed::{EmbeddingResult, ModelMetadata},
+};
+
+/// Bumped when the key or the file format changes, so that old entries are no longer read.
+const CACHE_VERSION: u32 = 1;
+
+/// Hits and misses of an [EmbeddingCache] since it was created or last reset.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct CacheStats {
+    /// Texts whose embedding was read from the cache.
+    pub hits: u64,
+    /// Texts that were embedded by the model and added to the cache.
+    pub misses: u64,
+}
+
+impl CacheStats {
+    /// Share of the texts read from the cache, 0.0 when nothing was embedded.
+    pub fn hit_rate(&self) -> f64 {
+        match self.hits + self.misses {
+            0 => 0.0,
+            total => self.hits as f64 / total as f64,
+        }
+    }
+}
+
+/// Stores embeddings as files of a directory, one per text. An entry is keyed by the SHA-256 of
+/// the model id and revision, the other settings of the model that change its vectors, whether
+/// the text is embedded as a query or a document, and the text trimmed and NFC normalized. The
+/// keys are stable across runs and machines, so the directory can be shared.
+///
+/// Entries are never evicted, delete the directory to clear the cache. Unreadable entries are
+/// embedded again and entries that can't be written are only logged, so the cache never fails
+/// an embedding.
+pub struct EmbeddingCache {
+    dir: PathBuf,
+    hits: AtomicU64,
+    misses: AtomicU64,
+}
+
+impl EmbeddingCache {
+    /// Opens the cache stored in `dir`, which is created if it doesn't exist.
+    pub fn new(dir: impl Into<PathBuf>) -> Result<Self> {
+        let dir = dir.into();
+        fs::create_dir_all(&dir)?;
+        Ok(Self {
+            dir,
+            hits: AtomicU64::new(0),
+            misses: AtomicU64::new(0),
+        })
+    }
+
+    pub fn dir(&self) -> &Path {
+        &self.dir
+    }
+
+    pub fn stats(&self) -> CacheStats {
+        CacheStats {
+            hits: self.hits.load(Ordering::Relaxed),
+            misses: self.misses.load(Ordering::Relaxed),
+        }
+    }
+
+    pub fn reset_stats(&self) {
+        self.hits.store(0, Ordering::Relaxed);
+        self.misses.store(0, Ordering::Relaxed);
+    }
+
+    /// Embeds search queries like [AsyncEmbedder::embed_query_batch], only running the model on
+    /// the texts missing from the cache.
+    pub async fn embed_query_batch<E: AsyncEmbedder + ?Sized>(
+        &self,
+        embedder: &E,
+        text_batch: &[String],
+        batch_size: Option<usize>,
+    ) -> Result<Vec<EmbeddingResult>> {
+        self.embed(embedder, "query", text_batch, batch_size).await
+    }
+
+    /// Embeds documents like [AsyncEmbedder::embed_document_batch], only running the model on
+    /// the texts missing from the cache.
+    pub async fn embed_document_batch<E: AsyncEmbedder + ?Sized>(
+        &self,
+        embedder: &E,
+        text_batch: &[String],
+        batch_size: Option<usize>,
+    ) -> Result<Vec<EmbeddingResult>> {
+        self.embed(embedder, "document", text_batch, batch_size)
+            .await
+    }
+
+    async fn embed<E: AsyncEmbedder + ?Sized>(
+        &self,
+        embedder: &E,
+        kind: &str,
+        text_batch: &[String],
+        batch_size: Option<usize>,
+    ) -> Result<Vec<EmbeddingResult>> {
+        let model = model_key(&embedder.model_metadata(), kind);
+        let paths = text_batch
+            .iter()
+            .map(|text| self.entry_path(&model, text))
+            .collect::<Vec<_>>();
+        let mut embeddings = paths
+            .iter()
+            .map(|path| read_entry(path))
+            .collect::<Vec<_>>();
+        let missing = embeddings
+            .iter()
+            .enumerate()
+            .filter(|(_, embedding)| embedding.is_none())
+            .map(|(i, _)| i)
+            .collect::<Vec<_>>();
+        self.hits
+            .fetch_add((text_batch.len() - missing.len()) as u64, Ordering::Relaxed);
+        self.misses
+            .fetch_add(missing.len() as u64, Ordering::Relaxed);
+
+        if !missing.is_empty() {
+            let texts = missing
+                .iter()
+                .map(|&i| text_batch[i].clone())
+                .collect::<Vec<_>>();
+            let embedded = match kind {
+                "query" => embedder.embed_query_batch(&texts, batch_size).await,
+                _ => embedder.embed_document_batch(&texts, batch_size).await,
+            };
+            // The texts sent were the missing ones, so the partial embeddings of a budget error
+            // are moved to the positions of the batch.
+            let embedded = embedded.map_err(|error| match error.downcast::<BudgetExceeded>() {
+                Ok(exceeded) => exceeded.remap(&missing).into(),
+                Err(error) => error,
+            })?;
+            for (&i, embedding) in missing.iter().zip(embedded) {
+                if let Err(e) = write_entry(&paths[i], &embedding) {
+                    tracing::warn!("Could not cache embedding in {}: {}", paths[i].display(), e);
+                }
+                embeddings[i] = Some(embedding);
+            }
+        }
+        Ok(embeddings.into_iter().flatten().collect())
+    }
+
+    fn entry_path(&self, model: &str, text: &str) -> PathBuf {
+        let mut hasher = Sha256::new();
+        hasher.update(model.as_bytes());
+        hasher.update(text.trim().nfc().collect::<String>().as_bytes());
+        let key = hasher
+            .finalize()
+            .iter()
+            .map(|byte| format!("{:02x}", byte))
+            .collect::<String>();
+        // Entries are spread over subdirectories so that none grows too large.
+        self.dir.join(&key[..2]).join(format!("{}.json", key))
+    }
+}
+
+/// The part of the key shared by every text embedded by a model. Null bytes separate the fields
+/// so that no text can be confused with another model.
+fn model_key(metadata: &ModelMetadata, kind: &str) -> String {
+    format!(
+        "v{}\0{}\0{}\0{}\0{:?}\0{:?}\0{}\0{}\0",
+        CACHE_VERSION,
+        metadata.architecture,
+        metadata.model_id,
+        metadata.revision.as_deref().unwrap_or_default(),
+        metadata.dimension,
+        metadata.pooling,
+        metadata.normalized,
+        kind
+    )
+}
+
+fn read_entry(path: &Path) -> Option<EmbeddingResult> {
+    let bytes = fs::read(path).ok()?;
+    serde_json::from_slice(&bytes).ok()
+}
+
+fn write_entry(path: &Path, embedding: &EmbeddingResult) -> Result<()> {
+    let dir = path.parent().unwrap_or(Path::new("."));
+    fs::create_dir_all(dir)?;
+    // Written to a temporary file first, so that concurrent readers never see half an entry.
+    let mut file = tempfile::NamedTempFile::new_in(dir)?;
+    serde_json::to_writer(&mut file, embedding)?;
+    file.persist(path)?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::atomic::AtomicUsize;
+
+    use futures::future::BoxFuture;
+    use tempdir::TempDir;
+
+    use super::*;
+
+    /// Embeds every text as its length in bytes, counting the texts it embeds.
+    #[derive(Default)]
+    struct CountingEmbedder {
+        embedded: AtomicUsize,
+    }
+
+    impl AsyncEmbedder for CountingEmbedder {
+        fn embed<'a>(
+            &'a self,
+            text_batch: &'a [String],
+            _batch_size: Option<usize>,
+        ) -> BoxFuture<'a, Result<Vec<EmbeddingResult>, anyhow::Error>> {
+            self.embedded.fetch_add(text_batch.len(), Ordering::Relaxed);
+            Box::pin(async move {
+                Ok(text_batch
+                    .iter()
+                    .map(|text| EmbeddingResult::DenseVector(vec![text.len() as f32, 1.0]))
+                    .collect())
+            })
+        }
+
+        fn model_metadata(&self) -> ModelMetadata {
+            ModelMetadata::new("counting", "counting-model", None)
+        }
+    }
+
+    #[tokio::test]
+    async fn test_second_embed_is_a_cache_hit() {
+        let dir = TempDir::new("embedding_cache").unwrap();
+        let cache = EmbeddingCache::new(dir.path()).unwrap();
+        let embedder = CountingEmbedder::default();
+        let texts = vec!["Hello".to_string(), "World!".to_string()];
+
+        let first = cache
+            .embed_document_batch(&embedder, &texts, None)
+            .await
+            .unwrap();
+        assert_eq!(cache.stats(), CacheStats { hits: 0, misses: 2 });
+
+        let second = cache
+            .embed_document_batch(&embedder, &texts, None)
+            .await
+            .unwrap();
+        assert_eq!(cache.stats(), CacheStats { hits: 2, misses: 2 });
+        assert_eq!(embedder.embedded.load(Ordering::Relaxed), 2);
+        for (first, second) in first.iter().zip(&second) {
+            assert_eq!(first.to_dense().unwrap(), second.to_dense().unwrap());
+        }
+
+        // The text is normalized, but queries are kept apart from documents.
+        let mixed = vec!["  Hello\n".to_string(), "New".to_string()];
+        let embeddings = cache
+            .embed_document_batch(&embedder, &mixed, None)
+            .await
+            .unwrap();
+        assert_eq!(embeddings[0].to_dense().unwrap(), vec![5.0, 1.0]);
+        assert_eq!(embeddings[1].to_dense().unwrap(), vec![3.0, 1.0]);
+        assert_eq!(embedder.embedded.load(Ordering::Relaxed), 3);
+        cache
+            .embed_query_batch(&embedder, &texts[..1], None)
+            .await
+            .unwrap();
+        assert_eq!(embedder.embedded.load(Ordering::Relaxed), 4);
+        assert_eq!(cache.stats(), CacheStats { hits: 3, misses: 4 });
+        assert!((cache.stats().hit_rate() - 3.0 / 7.0).abs() < 1e-9);
+
+        // A new cache on the same directory reads the entries written by the first one.
+        let reopened = EmbeddingCache::new(dir.path()).unwrap();
+        reopened
+            .embed_document_batch(&embedder, &texts, None)
+            .await
+            .unwrap();
+        assert_eq!(reopened.stats(), CacheStats { hits: 2, misses: 0 });
+        assert_eq!(embedder.embedded.load(Ordering::Relaxed), 4);
+    }
+}
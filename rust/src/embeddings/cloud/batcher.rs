@@ -0,0 +1,203 @@
+//! Batching of chunks across files, for cloud embedders.
+//!
+//! Embedding files one at a time sends one request per file, which for corpora of many small
+//! files is dominated by the per-request overhead. [CrossFileBatcher] queues the chunks of
+//! several files and only sends a request once it has a full batch, then hands back each file
+//! with its embeddings once all of its chunks are embedded.
+
+use std::collections::{HashMap, VecDeque};
+
+use crate::embeddings::async_embedder::AsyncEmbedder;
+use crate::embeddings::embed::{EmbedData, EmbeddingResult};
+
+/// A file whose chunks are queued or embedded.
+struct PendingFile {
+    chunks: Vec<String>,
+    metadata: Vec<Option<HashMap<String, String>>>,
+    embeddings: Vec<Option<EmbeddingResult>>,
+}
+
+impl PendingFile {
+    fn is_complete(&self) -> bool {
+        self.embeddings.iter().all(Option::is_some)
+    }
+}
+
+/// Queues the chunks of several files and embeds them in requests of `max_batch_size` chunks.
+///
+/// Files are returned in the order they were added, each with one [EmbedData] per chunk.
+pub struct CrossFileBatcher<'a> {
+    embedder: &'a dyn AsyncEmbedder,
+    max_batch_size: usize,
+    files: VecDeque<PendingFile>,
+    /// Index in `files` of the first file, which grows as completed files are returned.
+    first_file: usize,
+    /// Chunks waiting for a request, as (file, chunk) indices.
+    queue: VecDeque<(usize, usize)>,
+}
+
+impl<'a> CrossFileBatcher<'a> {
+    pub fn new(embedder: &'a dyn AsyncEmbedder, max_batch_size: usize) -> Self {
+        Self {
+            embedder,
+            max_batch_size: max_batch_size.max(1),
+            files: VecDeque::new(),
+            first_file: 0,
+            queue: VecDeque::new(),
+        }
+    }
+
+    /// Queues the chunks of a file with the metadata of each chunk, sends the requests that are
+    /// full and returns the files whose chunks are all embedded.
+    pub async fn add_file(
+        &mut self,
+        chunks: Vec<String>,
+        metadata: Vec<Option<HashMap<String, String>>>,
+    ) -> Result<Vec<Vec<EmbedData>>, anyhow::Error> {
+        if metadata.len() != chunks.len() {
+            return Err(anyhow::anyhow!(
+                "Expected metadata for {} chunks, got {}",
+                chunks.len(),
+                metadata.len()
+            ));
+        }
+        let file = self.first_file + self.files.len();
+        self.queue
+            .extend((0..chunks.len()).map(|chunk| (file, chunk)));
+        self.files.push_back(PendingFile {
+            embeddings: vec![None; chunks.len()],
+            chunks,
+            metadata,
+        });
+        while self.queue.len() >= self.max_batch_size {
+            self.send_batch().await?;
+        }
+        Ok(self.take_completed())
+    }
+
+    /// Embeds the chunks left in the queue and returns the remaining files.
+    pub async fn finish(mut self) -> Result<Vec<Vec<EmbedData>>, anyhow::Error> {
+        while !self.queue.is_empty() {
+            self.send_batch().await?;
+        }
+        Ok(self.take_completed())
+    }
+
+    /// Embeds the next batch of the queue and stores each embedding with its chunk.
+    async fn send_batch(&mut self) -> Result<(), anyhow::Error> {
+        let size = self.queue.len().min(self.max_batch_size);
+        let batch = self.queue.drain(..size).collect::<Vec<_>>();
+        let texts = batch
+            .iter()
+            .map(|(file, chunk)| self.files[file - self.first_file].chunks[*chunk].clone())
+            .collect::<Vec<_>>();
+        let encodings = self.embedder.embed_document_batch(&texts, None).await?;
+        if encodings.len() != texts.len() {
+            return Err(anyhow::anyhow!(
+                "Expected {} embeddings, got {}",
+                texts.len(),
+                encodings.len()
+            ));
+        }
+        for ((file, chunk), encoding) in batch.into_iter().zip(encodings) {
+            self.files[file - self.first_file].embeddings[chunk] = Some(encoding);
+        }
+        Ok(())
+    }
+
+    /// Removes the completed files at the front, so that files are returned in order.
+    fn take_completed(&mut self) -> Vec<Vec<EmbedData>> {
+        let is_normalized = self.embedder.is_normalized();
+        let model_id = self.embedder.model_metadata().model_id;
+        let mut completed = Vec::new();
+        while self.files.front().is_some_and(PendingFile::is_complete) {
+            let file = self.files.pop_front().unwrap();
+            self.first_file += 1;
+            completed.push(
+                file.chunks
+                    .into_iter()
+                    .zip(file.embeddings.into_iter().flatten())
+                    .zip(file.metadata)
+                    .map(|((chunk, embedding), metadata)| {
+                        EmbedData::new(embedding, Some(chunk), metadata)
+                            .with_normalized(is_normalized)
+                            .with_embedding_model(&model_id)
+                    })
+                    .collect(),
+            );
+        }
+        completed
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Mutex;
+
+    use futures::future::BoxFuture;
+
+    use super::*;
+
+    /// Embeds `"<file>:<chunk>"` as `[file, chunk]` and records the size of every request.
+    #[derive(Default)]
+    struct RecordingEmbedder {
+        requests: Mutex<Vec<usize>>,
+    }
+
+    impl AsyncEmbedder for RecordingEmbedder {
+        fn embed<'a>(
+            &'a self,
+            text_batch: &'a [String],
+            _batch_size: Option<usize>,
+        ) -> BoxFuture<'a, Result<Vec<EmbeddingResult>, anyhow::Error>> {
+            self.requests.lock().unwrap().push(text_batch.len());
+            Box::pin(async move {
+                Ok(text_batch
+                    .iter()
+                    .map(|text| {
+                        let ids = text.split(':').map(|id| id.parse().unwrap()).collect();
+                        EmbeddingResult::DenseVector(ids)
+                    })
+                    .collect())
+            })
+        }
+    }
+
+    #[tokio::test]
+    async fn test_scatter_back_to_files() {
+        let embedder = RecordingEmbedder::default();
+        let mut batcher = CrossFileBatcher::new(&embedder, 4);
+        let sizes = [3, 1, 0, 2, 5];
+        let mut files = Vec::new();
+        for (file, size) in sizes.iter().enumerate() {
+            let chunks = (0..*size)
+                .map(|chunk| format!("{}:{}", file, chunk))
+                .collect();
+            let metadata = HashMap::from([("file_name".to_string(), file.to_string())]);
+            let metadata = vec![Some(metadata); *size];
+            files.extend(batcher.add_file(chunks, metadata).await.unwrap());
+        }
+        files.extend(batcher.finish().await.unwrap());
+
+        // 11 chunks in full requests of 4, instead of one request per file.
+        assert_eq!(*embedder.requests.lock().unwrap(), vec![4, 4, 3]);
+        assert_eq!(files.len(), sizes.len());
+        for (file, (embeddings, size)) in files.iter().zip(sizes).enumerate() {
+            assert_eq!(embeddings.len(), size);
+            for (chunk, embedding) in embeddings.iter().enumerate() {
+                assert_eq!(
+                    embedding.text.as_deref(),
+                    Some(format!("{}:{}", file, chunk).as_str())
+                );
+                assert_eq!(
+                    embedding.embedding.to_dense().unwrap(),
+                    vec![file as f32, chunk as f32]
+                );
+                assert_eq!(
+                    embedding.metadata.as_ref().unwrap()["file_name"],
+                    file.to_string()
+                );
+            }
+        }
+    }
+}
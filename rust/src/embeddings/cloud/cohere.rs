@@ -1,14 +1,34 @@
-use reqwest::Client;
+use std::collections::HashMap;
+
+use reqwest::{header::HeaderMap, Client};
 use serde::Deserialize;
 use serde_json::json;
 
-use crate::embeddings::embed::EmbeddingResult;
+use super::{embed_within_budget, header_map, http_client, TokenBudget};
+use crate::embeddings::embed::{EmbeddingResult, ModelMetadata};
+
+/// Maximum number of texts accepted by a single embed request.
+pub(crate) const MAX_BATCH_SIZE: usize = 96;
 
 /// Represents the response from the Cohere embedding API.
 #[derive(Deserialize, Debug, Default)]
 pub struct CohereEmbedResponse {
     /// A vector of embeddings, where each embedding is a vector of 32-bit floating point numbers.
     pub embeddings: Vec<Vec<f32>>,
+    #[serde(default)]
+    pub meta: CohereMeta,
+}
+
+#[derive(Deserialize, Debug, Default)]
+pub struct CohereMeta {
+    #[serde(default)]
+    pub billed_units: CohereBilledUnits,
+}
+
+/// The units a request is billed for.
+#[derive(Deserialize, Debug, Default)]
+pub struct CohereBilledUnits {
+    pub input_tokens: Option<u64>,
 }
 
 /// Represents a CohereEmbeder struct that contains the URL and API key for making requests to the Cohere API.
@@ -20,8 +40,12 @@ pub struct CohereEmbedder {
     model: String,
     /// The API key for authenticating requests to the Cohere API.
     api_key: String,
+    /// Headers added to every request.
+    extra_headers: HeaderMap,
     /// The HTTP client for making requests.
     client: Client,
+    /// The tokens the embedder may send, unlimited when None.
+    budget: Option<TokenBudget>,
 }
 
 impl Default for CohereEmbedder {
@@ -50,43 +74,123 @@ impl CohereEmbedder {
             model,
             url: "https://api.cohere.com/v1/embed".to_string(),
             api_key,
+            extra_headers: HeaderMap::new(),
             client: Client::new(),
+            budget: None,
         }
     }
 
+    pub fn model_metadata(&self) -> ModelMetadata {
+        ModelMetadata::new("cohere", &self.model, None)
+    }
+
+    /// Overrides the URL of the embed endpoint, e.g. to go through a proxy.
+    pub fn with_url(mut self, url: &str) -> Self {
+        self.url = url.to_string();
+        self
+    }
+
+    /// Adds headers to every request, e.g. the headers required by an API gateway. They are
+    /// sent after the built-in ones, so they can also replace them.
+    pub fn with_extra_headers(
+        mut self,
+        extra_headers: &HashMap<String, String>,
+    ) -> Result<Self, anyhow::Error> {
+        self.extra_headers = header_map(extra_headers)?;
+        Ok(self)
+    }
+
+    /// Sends every request through `proxy`, see [http_client].
+    pub fn with_proxy(mut self, proxy: &str) -> Result<Self, anyhow::Error> {
+        self.client = http_client(Some(proxy))?;
+        Ok(self)
+    }
+
+    /// Sends every request with `client` instead of a client of its own, e.g. a client with
+    /// custom root certificates or a client certificate for mTLS, or one whose connection pool
+    /// is shared with the rest of the application. Replaces the proxy of `with_proxy`.
+    pub fn with_client(mut self, client: Client) -> Self {
+        self.client = client;
+        self
+    }
+
+    /// Stops embedding with a [BudgetExceeded](super::BudgetExceeded) error once
+    /// `max_total_tokens` tokens were sent, see [TokenBudget].
+    pub fn with_max_total_tokens(mut self, max_total_tokens: u64) -> Self {
+        self.budget = Some(TokenBudget::new(max_total_tokens));
+        self
+    }
+
+    pub fn token_budget(&self) -> Option<&TokenBudget> {
+        self.budget.as_ref()
+    }
+
+    /// Embeds documents using the `search_document` input type.
     pub async fn embed(
         &self,
         text_batch: &[String],
     ) -> Result<Vec<EmbeddingResult>, anyhow::Error> {
+        self.embed_with_input_type(text_batch, "search_document")
+            .await
+    }
+
+    /// Embeds search queries using the `search_query` input type.
+    pub async fn embed_query(
+        &self,
+        text_batch: &[String],
+    ) -> Result<Vec<EmbeddingResult>, anyhow::Error> {
+        self.embed_with_input_type(text_batch, "search_query").await
+    }
+
+    /// Embeds texts with one of the input types listed at
+    /// <https://docs.cohere.com/reference/embed>.
+    pub async fn embed_with_input_type(
+        &self,
+        text_batch: &[String],
+        input_type: &str,
+    ) -> Result<Vec<EmbeddingResult>, anyhow::Error> {
+        embed_within_budget(self.budget.as_ref(), text_batch, MAX_BATCH_SIZE, |batch| {
+            self.request(batch, input_type)
+        })
+        .await
+    }
+
+    /// Sends one request, returning the embeddings and the input tokens billed.
+    async fn request(
+        &self,
+        text_batch: &[String],
+        input_type: &str,
+    ) -> Result<(Vec<EmbeddingResult>, Option<u64>), anyhow::Error> {
         let response = self
             .client
             .post(&self.url)
             .header("Accept", "application/json")
             .header("Content-Type", "application/json")
             .header("Authorization", format!("Bearer {}", self.api_key))
+            .headers(self.extra_headers.clone())
             .json(&json!({
                 "texts": text_batch,
                 "model": self.model,
-                "input_type": "search_document"
+                "input_type": input_type
             }))
             .send()
             .await?;
 
         let data = response.json::<CohereEmbedResponse>().await?;
-        let encodings = data.embeddings;
-
-        let encodings = encodings
+        let encodings = data
+            .embeddings
             .iter()
             .map(|embedding| EmbeddingResult::DenseVector(embedding.clone()))
             .collect::<Vec<_>>();
 
-        Ok(encodings)
+        Ok((encodings, data.meta.billed_units.input_tokens))
     }
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::embeddings::embed::TextEmbedder;
 
     #[tokio::test]
     async fn test_cohere_embed() {
@@ -99,4 +203,34 @@ mod tests {
         let embeddings = cohere.embed(&text_batch).await.unwrap();
         assert_eq!(embeddings.len(), 2);
     }
+
+    #[tokio::test]
+    async fn test_cohere_input_type_of_queries_and_documents() {
+        let mut server = mockito::Server::new_async().await;
+        let mut mocks = Vec::new();
+        for input_type in ["search_query", "search_document"] {
+            let mock = server
+                .mock("POST", "/v1/embed")
+                .match_header("authorization", "Bearer test-key")
+                .match_body(mockito::Matcher::PartialJson(json!({
+                    "input_type": input_type
+                })))
+                .with_header("content-type", "application/json")
+                .with_body(r#"{"embeddings": [[0.1, 0.2]]}"#)
+                .create_async()
+                .await;
+            mocks.push(mock);
+        }
+
+        let cohere = CohereEmbedder::new("embed-english-v3.0".to_string(), Some("test-key".into()))
+            .with_url(&format!("{}/v1/embed", server.url()));
+        let embedder = TextEmbedder::Cohere(cohere);
+        let texts = ["Hello".to_string()];
+        embedder.embed_query_batch(&texts, None).await.unwrap();
+        embedder.embed_document_batch(&texts, None).await.unwrap();
+
+        for mock in mocks {
+            mock.assert_async().await;
+        }
+    }
 }
@@ -0,0 +1,278 @@
+use std::collections::HashMap;
+
+use reqwest::{header::HeaderMap, Client};
+use serde::Deserialize;
+use serde_json::{json, Value};
+
+use super::{embed_within_budget, header_map, http_client, TokenBudget};
+use crate::embeddings::embed::{EmbeddingResult, ModelMetadata};
+
+/// Maximum number of texts accepted by a single `batchEmbedContents` request.
+pub(crate) const MAX_BATCH_SIZE: usize = 100;
+
+/// The task the embeddings are optimized for. See
+/// <https://ai.google.dev/api/embeddings#tasktype>.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum GeminiTaskType {
+    RetrievalQuery,
+    RetrievalDocument,
+    SemanticSimilarity,
+    Classification,
+    Clustering,
+}
+
+impl GeminiTaskType {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            GeminiTaskType::RetrievalQuery => "RETRIEVAL_QUERY",
+            GeminiTaskType::RetrievalDocument => "RETRIEVAL_DOCUMENT",
+            GeminiTaskType::SemanticSimilarity => "SEMANTIC_SIMILARITY",
+            GeminiTaskType::Classification => "CLASSIFICATION",
+            GeminiTaskType::Clustering => "CLUSTERING",
+        }
+    }
+}
+
+#[derive(Deserialize, Debug, Default)]
+pub struct GeminiEmbedResponse {
+    pub embeddings: Vec<GeminiEmbedding>,
+}
+
+#[derive(Deserialize, Debug, Default)]
+pub struct GeminiEmbedding {
+    pub values: Vec<f32>,
+}
+
+/// Represents a GeminiEmbedder struct that calls the Google Generative Language embedding API.
+#[derive(Debug)]
+pub struct GeminiEmbedder {
+    /// Base URL of the API, without the model path.
+    url: String,
+    model: String,
+    api_key: String,
+    /// Task type used for documents. Queries always use [GeminiTaskType::RetrievalQuery].
+    task_type: GeminiTaskType,
+    /// Truncates the embeddings to this size. Supported by `text-embedding-004` and newer.
+    output_dimensionality: Option<usize>,
+    extra_headers: HeaderMap,
+    client: Client,
+    budget: Option<TokenBudget>,
+}
+
+impl Default for GeminiEmbedder {
+    fn default() -> Self {
+        Self::new("text-embedding-004".to_string(), None)
+    }
+}
+
+impl GeminiEmbedder {
+    /// Creates a new instance of `GeminiEmbedder`.
+    ///
+    /// # Arguments
+    ///
+    /// * `model` - The embedding model, e.g. `text-embedding-004` or `gemini-embedding-exp-03-07`.
+    /// * `api_key` - The API key. Read from the `GEMINI_API_KEY` environment variable if None.
+    pub fn new(model: String, api_key: Option<String>) -> Self {
+        let api_key =
+            api_key.unwrap_or_else(|| std::env::var("GEMINI_API_KEY").expect("API Key not set"));
+
+        Self {
+            url: "https://generativelanguage.googleapis.com/v1beta".to_string(),
+            model: model.trim_start_matches("models/").to_string(),
+            api_key,
+            task_type: GeminiTaskType::RetrievalDocument,
+            output_dimensionality: None,
+            extra_headers: HeaderMap::new(),
+            client: Client::new(),
+            budget: None,
+        }
+    }
+
+    /// Gemini returns unit vectors unless they are truncated with `output_dimensionality`.
+    pub fn is_normalized(&self) -> bool {
+        self.output_dimensionality.is_none()
+    }
+
+    pub fn with_task_type(mut self, task_type: GeminiTaskType) -> Self {
+        self.task_type = task_type;
+        self
+    }
+
+    pub fn with_output_dimensionality(mut self, output_dimensionality: Option<usize>) -> Self {
+        self.output_dimensionality = output_dimensionality;
+        self
+    }
+
+    /// Overrides the API base URL, e.g. to go through a proxy.
+    pub fn with_url(mut self, url: &str) -> Self {
+        self.url = url.trim_end_matches('/').to_string();
+        self
+    }
+
+    pub fn model_metadata(&self) -> ModelMetadata {
+        ModelMetadata::new("gemini", &self.model, None).with_dimension(self.output_dimensionality)
+    }
+
+    /// Adds headers to every request, e.g. the headers required by an API gateway. They are
+    /// sent after the built-in ones, so they can also replace them.
+    pub fn with_extra_headers(
+        mut self,
+        extra_headers: &HashMap<String, String>,
+    ) -> Result<Self, anyhow::Error> {
+        self.extra_headers = header_map(extra_headers)?;
+        Ok(self)
+    }
+
+    /// Sends every request through `proxy`, see [http_client].
+    pub fn with_proxy(mut self, proxy: &str) -> Result<Self, anyhow::Error> {
+        self.client = http_client(Some(proxy))?;
+        Ok(self)
+    }
+
+    /// Sends every request with `client` instead of a client of its own, e.g. a client with
+    /// custom root certificates or a client certificate for mTLS, or one whose connection pool
+    /// is shared with the rest of the application. Replaces the proxy of `with_proxy`.
+    pub fn with_client(mut self, client: Client) -> Self {
+        self.client = client;
+        self
+    }
+
+    /// Stops embedding with a [BudgetExceeded](super::BudgetExceeded) error once
+    /// `max_total_tokens` tokens were sent, see [TokenBudget]. The API does not report usage,
+    /// requests are charged with [estimate_tokens](super::estimate_tokens).
+    pub fn with_max_total_tokens(mut self, max_total_tokens: u64) -> Self {
+        self.budget = Some(TokenBudget::new(max_total_tokens));
+        self
+    }
+
+    pub fn token_budget(&self) -> Option<&TokenBudget> {
+        self.budget.as_ref()
+    }
+
+    fn request_body(&self, text_batch: &[String], task_type: GeminiTaskType) -> Value {
+        let requests = text_batch
+            .iter()
+            .map(|text| {
+                let mut request = json!({
+                    "model": format!("models/{}", self.model),
+                    "content": { "parts": [{ "text": text }] },
+                    "taskType": task_type.as_str(),
+                });
+                if let Some(output_dimensionality) = self.output_dimensionality {
+                    request["outputDimensionality"] = json!(output_dimensionality);
+                }
+                request
+            })
+            .collect::<Vec<_>>();
+        json!({ "requests": requests })
+    }
+
+    /// Embeds documents with the configured task type.
+    pub async fn embed(
+        &self,
+        text_batch: &[String],
+    ) -> Result<Vec<EmbeddingResult>, anyhow::Error> {
+        self.embed_with_task_type(text_batch, self.task_type).await
+    }
+
+    /// Embeds search queries using the `RETRIEVAL_QUERY` task type.
+    pub async fn embed_query(
+        &self,
+        text_batch: &[String],
+    ) -> Result<Vec<EmbeddingResult>, anyhow::Error> {
+        self.embed_with_task_type(text_batch, GeminiTaskType::RetrievalQuery)
+            .await
+    }
+
+    pub async fn embed_with_task_type(
+        &self,
+        text_batch: &[String],
+        task_type: GeminiTaskType,
+    ) -> Result<Vec<EmbeddingResult>, anyhow::Error> {
+        embed_within_budget(self.budget.as_ref(), text_batch, MAX_BATCH_SIZE, |batch| {
+            self.request(batch, task_type)
+        })
+        .await
+    }
+
+    /// Sends the texts in requests of at most [MAX_BATCH_SIZE] texts. The API does not report
+    /// the tokens used.
+    async fn request(
+        &self,
+        text_batch: &[String],
+        task_type: GeminiTaskType,
+    ) -> Result<(Vec<EmbeddingResult>, Option<u64>), anyhow::Error> {
+        let mut encodings = Vec::with_capacity(text_batch.len());
+        for batch in text_batch.chunks(MAX_BATCH_SIZE) {
+            let response = self
+                .client
+                .post(format!(
+                    "{}/models/{}:batchEmbedContents",
+                    self.url, self.model
+                ))
+                .query(&[("key", &self.api_key)])
+                .headers(self.extra_headers.clone())
+                .json(&self.request_body(batch, task_type))
+                .send()
+                .await?;
+            if !response.status().is_success() {
+                return Err(anyhow::anyhow!(
+                    "Gemini embedding request failed: {}",
+                    response.text().await?
+                ));
+            }
+            let data = response.json::<GeminiEmbedResponse>().await?;
+            encodings.extend(
+                data.embeddings
+                    .into_iter()
+                    .map(|embedding| EmbeddingResult::DenseVector(embedding.values)),
+            );
+        }
+
+        Ok((encodings, None))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use mockito::Matcher;
+
+    #[test]
+    fn test_request_body() {
+        let gemini = GeminiEmbedder::new("text-embedding-004".to_string(), Some("key".into()))
+            .with_output_dimensionality(Some(256));
+        let body = gemini.request_body(
+            &["Hello".to_string(), "World".to_string()],
+            GeminiTaskType::RetrievalQuery,
+        );
+        let requests = body["requests"].as_array().unwrap();
+        assert_eq!(requests.len(), 2);
+        assert_eq!(requests[0]["model"], "models/text-embedding-004");
+        assert_eq!(requests[0]["content"]["parts"][0]["text"], "Hello");
+        assert_eq!(requests[0]["taskType"], "RETRIEVAL_QUERY");
+        assert_eq!(requests[1]["outputDimensionality"], 256);
+    }
+
+    #[tokio::test]
+    async fn test_gemini_embed_mocked() {
+        let mut server = mockito::Server::new_async().await;
+        let mock = server
+            .mock("POST", "/models/text-embedding-004:batchEmbedContents")
+            .match_query(Matcher::UrlEncoded("key".into(), "test-key".into()))
+            .match_body(Matcher::Regex(
+                r#""taskType":"RETRIEVAL_DOCUMENT""#.to_string(),
+            ))
+            .with_header("content-type", "application/json")
+            .with_body(r#"{"embeddings": [{"values": [0.1, 0.2, 0.3]}]}"#)
+            .create_async()
+            .await;
+
+        let gemini = GeminiEmbedder::new("text-embedding-004".to_string(), Some("test-key".into()))
+            .with_url(&server.url());
+        let embeddings = gemini.embed(&["Hello world".to_string()]).await.unwrap();
+
+        mock.assert_async().await;
+        assert_eq!(embeddings[0].to_dense().unwrap(), vec![0.1, 0.2, 0.3]);
+    }
+}
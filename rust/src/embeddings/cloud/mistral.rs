@@ -0,0 +1,408 @@
+use std::{collections::HashMap, time::Duration};
+
+use reqwest::{header::HeaderMap, Client, StatusCode};
+use serde_json::json;
+
+use super::openai::OpenAIEmbedResponse;
+use super::{embed_within_budget, header_map, http_client, TokenBudget};
+use crate::embeddings::embed::{EmbeddingResult, ModelMetadata};
+
+/// Number of texts sent in a single request. The API limits requests by their total number of
+/// tokens, which this keeps chunks of the default size below.
+pub(crate) const MAX_BATCH_SIZE: usize = 32;
+
+/// Number of times a rate limited or failed request is retried.
+const MAX_RETRIES: u32 = 3;
+
+/// Represents a MistralEmbedder struct that calls the Mistral embeddings API.
+#[derive(Debug)]
+pub struct MistralEmbedder {
+    url: String,
+    model: String,
+    api_key: String,
+    max_retries: u32,
+    extra_headers: HeaderMap,
+    client: Client,
+    budget: Option<TokenBudget>,
+}
+
+impl Default for MistralEmbedder {
+    fn default() -> Self {
+        Self::new("mistral-embed".to_string(), None)
+    }
+}
+
+impl MistralEmbedder {
+    /// Creates a new instance of `MistralEmbedder`.
+    ///
+    /// # Arguments
+    ///
+    /// * `model` - The embedding model, `mistral-embed`.
+    /// * `api_key` - The API key. Read from the `MISTRAL_API_KEY` environment variable if None.
+    pub fn new(model: String, api_key: Option<String>) -> Self {
+        let api_key =
+            api_key.unwrap_or_else(|| std::env::var("MISTRAL_API_KEY").expect("API Key not set"));
+
+        Self {
+            url: "https://api.mistral.ai/v1/embeddings".to_string(),
+            model,
+            api_key,
+            max_retries: MAX_RETRIES,
+            extra_headers: HeaderMap::new(),
+            client: Client::new(),
+            budget: None,
+        }
+    }
+
+    pub fn with_url(mut self, url: &str) -> Self {
+        self.url = url.to_string();
+        self
+    }
+
+    pub fn with_max_retries(mut self, max_retries: u32) -> Self {
+        self.max_retries = max_retries;
+        self
+    }
+
+    pub fn model_metadata(&self) -> ModelMetadata {
+        ModelMetadata::new("mistral", &self.model, None)
+    }
+
+    /// Adds headers to every request, e.g. the headers required by an API gateway. They are
+    /// sent after the built-in ones, so they can also replace them.
+    pub fn with_extra_headers(
+        mut self,
+        extra_headers: &HashMap<String, String>,
+    ) -> Result<Self, anyhow::Error> {
+        self.extra_headers = header_map(extra_headers)?;
+        Ok(self)
+    }
+
+    /// Sends every request through `proxy`, see [http_client].
+    pub fn with_proxy(mut self, proxy: &str) -> Result<Self, anyhow::Error> {
+        self.client = http_client(Some(proxy))?;
+        Ok(self)
+    }
+
+    /// Sends every request with `client` instead of a client of its own, e.g. a client with
+    /// custom root certificates or a client certificate for mTLS, or one whose connection pool
+    /// is shared with the rest of the application. Replaces the proxy of `with_proxy`.
+    pub fn with_client(mut self, client: Client) -> Self {
+        self.client = client;
+        self
+    }
+
+    /// Stops embedding with a [BudgetExceeded](super::BudgetExceeded) error once
+    /// `max_total_tokens` tokens were sent, see [TokenBudget].
+    pub fn with_max_total_tokens(mut self, max_total_tokens: u64) -> Self {
+        self.budget = Some(TokenBudget::new(max_total_tokens));
+        self
+    }
+
+    pub fn token_budget(&self) -> Option<&TokenBudget> {
+        self.budget.as_ref()
+    }
+
+    pub async fn embed(
+        &self,
+        text_batch: &[String],
+    ) -> Result<Vec<EmbeddingResult>, anyhow::Error> {
+        embed_within_budget(self.budget.as_ref(), text_batch, MAX_BATCH_SIZE, |batch| {
+            self.request(batch)
+        })
+        .await
+    }
+
+    /// Sends one request, retrying rate limits and server errors. Returns the embeddings and
+    /// the tokens used.
+    async fn request(
+        &self,
+        text_batch: &[String],
+    ) -> Result<(Vec<EmbeddingResult>, Option<u64>), anyhow::Error> {
+        let mut attempt = 0;
+        let response = loop {
+            let response = self
+                .client
+                .post(&self.url)
+                .header("Content-Type", "application/json")
+                .header("Authorization", format!("Bearer {}", self.api_key))
+                .headers(self.extra_headers.clone())
+                .json(&json!({
+                    "input": text_batch,
+                    "model": self.model,
+                    "encoding_format": "float"
+                }))
+                .send()
+                .await?;
+
+            let status = response.status();
+            let retryable = status == StatusCode::TOO_MANY_REQUESTS || status.is_server_error();
+            if !retryable || attempt >= self.max_retries {
+                break response;
+            }
+            // Honour Retry-After when the API sends it, otherwise back off exponentially.
+            let delay = response
+                .headers()
+                .get("retry-after")
+                .and_then(|value| value.to_str().ok())
+                .and_then(|value| value.parse::<u64>().ok())
+                .map(Duration::from_secs)
+                .unwrap_or_else(|| Duration::from_millis(500 * 2u64.pow(attempt)));
+            tracing::warn!(
+                "Mistral returned {}, retrying in {:?} ({}/{})",
+                status,
+                delay,
+                attempt + 1,
+                self.max_retries
+            );
+            tokio::time::sleep(delay).await;
+            attempt += 1;
+        };
+
+        if !response.status().is_success() {
+            return Err(anyhow::anyhow!(
+                "Mistral embedding request failed with {}: {}",
+                response.status(),
+                response.text().await?
+            ));
+        }
+        let mut data = response.json::<OpenAIEmbedResponse>().await?;
+        data.data.sort_by_key(|embedding| embedding.index);
+
+        let encodings = data
+            .data
+            .into_iter()
+            .map(|data| EmbeddingResult::DenseVector(data.embedding))
+            .collect::<Vec<_>>();
+
+        Ok((encodings, Some(data.usage.total_tokens as u64)))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use super::*;
+    use crate::{
+        config::TextEmbedConfig,
+        embed_directory_stream,
+        embeddings::{
+            cloud::BudgetExceeded,
+            embed::{EmbedData, Embedder, TextEmbedder},
+        },
+        PartialEmbeddings,
+    };
+
+    #[tokio::test]
+    async fn test_mistral_embed_mocked() {
+        let mut server = mockito::Server::new_async().await;
+        let mock = server
+            .mock("POST", "/v1/embeddings")
+            .match_header("authorization", "Bearer test-key")
+            .with_header("content-type", "application/json")
+            .with_body(
+                r#"{"id": "1", "object": "list", "model": "mistral-embed",
+                    "data": [{"object": "embedding", "embedding": [0.3, 0.4], "index": 1},
+                             {"object": "embedding", "embedding": [0.1, 0.2], "index": 0}],
+                    "usage": {"prompt_tokens": 4, "total_tokens": 4, "completion_tokens": 0}}"#,
+            )
+            .create_async()
+            .await;
+
+        let mistral = MistralEmbedder::new("mistral-embed".to_string(), Some("test-key".into()))
+            .with_url(&format!("{}/v1/embeddings", server.url()));
+        let embeddings = mistral
+            .embed(&["Hello".to_string(), "World".to_string()])
+            .await
+            .unwrap();
+
+        mock.assert_async().await;
+        assert_eq!(embeddings[0].to_dense().unwrap(), vec![0.1, 0.2]);
+        assert_eq!(embeddings[1].to_dense().unwrap(), vec![0.3, 0.4]);
+    }
+
+    #[tokio::test]
+    async fn test_mistral_sends_extra_headers() {
+        let mut server = mockito::Server::new_async().await;
+        let mock = server
+            .mock("POST", "/v1/embeddings")
+            .match_header("authorization", "Bearer test-key")
+            .match_header("x-org-id", "org-1")
+            .match_header("traceparent", "00-trace-span-01")
+            .with_header("content-type", "application/json")
+            .with_body(
+                r#"{"id": "1", "object": "list", "model": "mistral-embed",
+                    "data": [{"object": "embedding", "embedding": [0.1, 0.2], "index": 0}],
+                    "usage": {"prompt_tokens": 2, "total_tokens": 2, "completion_tokens": 0}}"#,
+            )
+            .create_async()
+            .await;
+
+        let extra_headers = HashMap::from([
+            ("X-Org-Id".to_string(), "org-1".to_string()),
+            ("traceparent".to_string(), "00-trace-span-01".to_string()),
+        ]);
+        let mistral = MistralEmbedder::new("mistral-embed".to_string(), Some("test-key".into()))
+            .with_url(&format!("{}/v1/embeddings", server.url()))
+            .with_extra_headers(&extra_headers)
+            .unwrap();
+        mistral.embed(&["Hello".to_string()]).await.unwrap();
+
+        mock.assert_async().await;
+    }
+
+    #[tokio::test]
+    async fn test_mistral_through_proxy() {
+        // The mock server acts as the proxy, the API host itself does not resolve.
+        let mut proxy = mockito::Server::new_async().await;
+        let mock = proxy
+            .mock("POST", "/v1/embeddings")
+            .match_header("host", "mistral.invalid")
+            .with_header("content-type", "application/json")
+            .with_body(
+                r#"{"id": "1", "object": "list", "model": "mistral-embed",
+                    "data": [{"object": "embedding", "embedding": [0.1, 0.2], "index": 0}],
+                    "usage": {"prompt_tokens": 2, "total_tokens": 2, "completion_tokens": 0}}"#,
+            )
+            .create_async()
+            .await;
+
+        let mistral = MistralEmbedder::new("mistral-embed".to_string(), Some("test-key".into()))
+            .with_url("http://mistral.invalid/v1/embeddings")
+            .with_proxy(&proxy.url())
+            .unwrap();
+        let embeddings = mistral.embed(&["Hello".to_string()]).await.unwrap();
+
+        mock.assert_async().await;
+        assert_eq!(embeddings[0].to_dense().unwrap(), vec![0.1, 0.2]);
+    }
+
+    #[tokio::test]
+    async fn test_mistral_with_client() {
+        let mut server = mockito::Server::new_async().await;
+        let mock = server
+            .mock("POST", "/v1/embeddings")
+            .match_header("x-client", "shared-pool")
+            .with_header("content-type", "application/json")
+            .with_body(
+                r#"{"id": "1", "object": "list", "model": "mistral-embed",
+                    "data": [{"object": "embedding", "embedding": [0.1, 0.2], "index": 0}],
+                    "usage": {"prompt_tokens": 2, "total_tokens": 2, "completion_tokens": 0}}"#,
+            )
+            .create_async()
+            .await;
+
+        // Only the given client sends the header.
+        let headers = HashMap::from([("X-Client".to_string(), "shared-pool".to_string())]);
+        let client = Client::builder()
+            .default_headers(header_map(&headers).unwrap())
+            .build()
+            .unwrap();
+        let mistral = MistralEmbedder::new("mistral-embed".to_string(), Some("test-key".into()))
+            .with_url(&format!("{}/v1/embeddings", server.url()))
+            .with_client(client);
+        let embeddings = mistral.embed(&["Hello".to_string()]).await.unwrap();
+
+        mock.assert_async().await;
+        assert_eq!(embeddings[0].to_dense().unwrap(), vec![0.1, 0.2]);
+    }
+
+    #[tokio::test]
+    async fn test_mistral_token_budget() {
+        // Answers every request of 32 texts, the batch size of Mistral, with 80 tokens used.
+        let data = (0..MAX_BATCH_SIZE)
+            .map(|index| json!({"embedding": [index as f32], "index": index}))
+            .collect::<Vec<_>>();
+        let mut server = mockito::Server::new_async().await;
+        let mock = server
+            .mock("POST", "/v1/embeddings")
+            .with_header("content-type", "application/json")
+            .with_body(
+                json!({"id": "1", "object": "list", "model": "mistral-embed", "data": data,
+                       "usage": {"prompt_tokens": 80, "total_tokens": 80, "completion_tokens": 0}})
+                .to_string(),
+            )
+            .expect(1)
+            .create_async()
+            .await;
+
+        // 40 texts of about 3 tokens: the first request fits in the budget, the second would
+        // go over it after the 80 tokens of the first.
+        let mistral = MistralEmbedder::new("mistral-embed".to_string(), Some("test-key".into()))
+            .with_url(&format!("{}/v1/embeddings", server.url()))
+            .with_max_total_tokens(100);
+        let texts = vec!["Hello world".to_string(); 40];
+        let error = mistral.embed(&texts).await.unwrap_err();
+
+        mock.assert_async().await;
+        let exceeded = error.downcast_ref::<BudgetExceeded>().unwrap();
+        assert_eq!(exceeded.used, 80);
+        assert_eq!(exceeded.embeddings.len(), MAX_BATCH_SIZE);
+        assert_eq!(exceeded.indices, (0..MAX_BATCH_SIZE).collect::<Vec<_>>());
+        assert_eq!(exceeded.embeddings[1].to_dense().unwrap(), vec![1.0]);
+        assert_eq!(mistral.token_budget().unwrap().used(), 80);
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn test_mistral_token_budget_of_directory() {
+        let directory = tempdir::TempDir::new("budget").unwrap();
+        for i in 0..4 {
+            let text = format!("File {} is embedded until the budget runs out.", i);
+            std::fs::write(directory.path().join(format!("{}.txt", i)), text).unwrap();
+        }
+        // Every file is a buffer of its own, sent in a request charged 10 tokens.
+        let mut server = mockito::Server::new_async().await;
+        let mock = server
+            .mock("POST", "/v1/embeddings")
+            .with_header("content-type", "application/json")
+            .with_body(
+                r#"{"id": "1", "object": "list", "model": "mistral-embed",
+                    "data": [{"object": "embedding", "embedding": [0.1, 0.2], "index": 0}],
+                    "usage": {"prompt_tokens": 10, "total_tokens": 10, "completion_tokens": 0}}"#,
+            )
+            .expect(2)
+            .create_async()
+            .await;
+
+        let mistral = MistralEmbedder::new("mistral-embed".to_string(), Some("test-key".into()))
+            .with_url(&format!("{}/v1/embeddings", server.url()))
+            .with_max_total_tokens(25);
+        let embedder = Arc::new(Embedder::Text(TextEmbedder::Mistral(mistral)));
+        let config = TextEmbedConfig::default().with_buffer_size(1);
+        let error = embed_directory_stream(
+            directory.path().to_path_buf(),
+            &embedder,
+            None,
+            Some(&config),
+            None::<fn(Vec<EmbedData>)>,
+        )
+        .await
+        .unwrap_err();
+
+        mock.assert_async().await;
+        assert_eq!(error.downcast_ref::<BudgetExceeded>().unwrap().used, 20);
+        let partial = error.downcast_ref::<PartialEmbeddings>().unwrap();
+        assert_eq!(partial.embeddings.len(), 2);
+    }
+
+    #[tokio::test]
+    async fn test_mistral_retries_rate_limit() {
+        let mut server = mockito::Server::new_async().await;
+        let mock = server
+            .mock("POST", "/v1/embeddings")
+            .with_status(429)
+            .with_header("retry-after", "0")
+            .expect(3)
+            .create_async()
+            .await;
+
+        let mistral = MistralEmbedder::new("mistral-embed".to_string(), Some("test-key".into()))
+            .with_url(&format!("{}/v1/embeddings", server.url()))
+            .with_max_retries(2);
+        let result = mistral.embed(&["Hello".to_string()]).await;
+
+        mock.assert_async().await;
+        assert!(result.is_err());
+    }
+}
@@ -1,2 +1,269 @@
+use std::{
+    collections::HashMap,
+    future::Future,
+    sync::atomic::{AtomicU64, Ordering},
+};
+
+use reqwest::{
+    header::{HeaderMap, HeaderName, HeaderValue},
+    Client, NoProxy, Proxy,
+};
+
+use crate::embeddings::embed::EmbeddingResult;
+
+pub mod batcher;
 pub mod cohere;
+pub mod gemini;
+pub mod mistral;
 pub mod openai;
+
+/// Converts the extra headers of a cloud embedder to a [HeaderMap], failing on invalid header
+/// names or values instead of at the first request.
+pub fn header_map(headers: &HashMap<String, String>) -> Result<HeaderMap, anyhow::Error> {
+    headers
+        .iter()
+        .map(|(name, value)| {
+            let name = HeaderName::from_bytes(name.as_bytes())
+                .map_err(|_| anyhow::anyhow!("Invalid header name {:?}", name))?;
+            let value = HeaderValue::from_str(value)
+                .map_err(|_| anyhow::anyhow!("Invalid value for header {}", name))?;
+            Ok((name, value))
+        })
+        .collect()
+}
+
+/// Builds the HTTP client of a cloud embedder. Without `proxy`, requests go through the proxies
+/// of the `HTTPS_PROXY`/`HTTP_PROXY`/`ALL_PROXY` environment variables, if any. With `proxy`,
+/// every request goes through it except for the hosts listed in `NO_PROXY`. Only `http` and
+/// `https` proxy URLs are supported.
+pub fn http_client(proxy: Option<&str>) -> Result<Client, anyhow::Error> {
+    let mut builder = Client::builder();
+    if let Some(proxy) = proxy {
+        let proxy = Proxy::all(proxy)
+            .map_err(|e| anyhow::anyhow!("Invalid proxy {}: {}", proxy, e))?
+            .no_proxy(NoProxy::from_env());
+        builder = builder.proxy(proxy);
+    }
+    Ok(builder.build()?)
+}
+
+/// Rough number of characters per token of English text, used to estimate the tokens of a
+/// request before sending it.
+const CHARS_PER_TOKEN: usize = 4;
+
+/// Estimates the tokens of `texts` from their length, for providers that do not report usage.
+pub fn estimate_tokens(texts: &[String]) -> u64 {
+    texts
+        .iter()
+        .map(|text| text.chars().count().div_ceil(CHARS_PER_TOKEN) as u64)
+        .sum()
+}
+
+/// Limits the tokens a cloud embedder may send over its lifetime, to avoid surprise bills when
+/// embedding large corpora. Requests are charged with the tokens reported by the API, or with
+/// [estimate_tokens] when it reports none.
+#[derive(Debug)]
+pub struct TokenBudget {
+    max_total_tokens: u64,
+    used: AtomicU64,
+}
+
+impl TokenBudget {
+    pub fn new(max_total_tokens: u64) -> Self {
+        Self {
+            max_total_tokens,
+            used: AtomicU64::new(0),
+        }
+    }
+
+    pub fn max_total_tokens(&self) -> u64 {
+        self.max_total_tokens
+    }
+
+    /// The tokens charged so far.
+    pub fn used(&self) -> u64 {
+        self.used.load(Ordering::Relaxed)
+    }
+
+    /// Charges `tokens` if they fit in the budget, in one step so that concurrent requests
+    /// can't both fit in the same remaining tokens. Returns the tokens used otherwise.
+    fn reserve(&self, tokens: u64) -> Result<(), u64> {
+        self.used
+            .fetch_update(Ordering::Relaxed, Ordering::Relaxed, |used| {
+                (used + tokens <= self.max_total_tokens).then_some(used + tokens)
+            })
+            .map(|_| ())
+    }
+
+    /// Replaces the `reserved` tokens of a request with the `charged` ones.
+    fn settle(&self, reserved: u64, charged: u64) {
+        if charged > reserved {
+            self.used.fetch_add(charged - reserved, Ordering::Relaxed);
+        } else {
+            self.used.fetch_sub(reserved - charged, Ordering::Relaxed);
+        }
+    }
+}
+
+/// Returned when a request would go over the [TokenBudget] of an embedder. Retrieve it with
+/// `error.downcast_ref::<BudgetExceeded>()` to keep the embeddings of the texts sent before.
+#[derive(Debug)]
+pub struct BudgetExceeded {
+    pub max_total_tokens: u64,
+    pub used: u64,
+    /// The embeddings of the texts embedded before the budget ran out.
+    pub embeddings: Vec<EmbeddingResult>,
+    /// The positions in the batch of the texts of [BudgetExceeded::embeddings], in increasing
+    /// order.
+    pub indices: Vec<usize>,
+}
+
+impl BudgetExceeded {
+    /// Moves the embeddings of a batch made of texts of another one, text `i` of the batch being
+    /// text `positions[i]` of the other, to the positions of the other batch.
+    pub(crate) fn remap(self, positions: &[usize]) -> Self {
+        let mut embedded = self
+            .indices
+            .into_iter()
+            .map(|index| positions[index])
+            .zip(self.embeddings)
+            .collect::<Vec<_>>();
+        embedded.sort_by_key(|(index, _)| *index);
+        let (indices, embeddings) = embedded.into_iter().unzip();
+        Self {
+            indices,
+            embeddings,
+            ..self
+        }
+    }
+}
+
+impl std::fmt::Display for BudgetExceeded {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "Token budget of {} exceeded after {} tokens, {} texts of the batch were embedded",
+            self.max_total_tokens,
+            self.used,
+            self.embeddings.len()
+        )
+    }
+}
+
+impl std::error::Error for BudgetExceeded {}
+
+/// Embeds `text_batch` with `request`, which returns the embeddings of the texts it is given
+/// and the tokens reported by the API. Without a budget the whole batch goes to `request`.
+/// With one, the batch is sent in requests of at most `max_batch_size` texts, stopping with
+/// [BudgetExceeded] before the first request whose estimated tokens would go over the budget.
+/// The estimate is reserved before sending the request, then replaced by the tokens charged.
+pub(crate) async fn embed_within_budget<'a, F, Fut>(
+    budget: Option<&TokenBudget>,
+    text_batch: &'a [String],
+    max_batch_size: usize,
+    mut request: F,
+) -> Result<Vec<EmbeddingResult>, anyhow::Error>
+where
+    F: FnMut(&'a [String]) -> Fut,
+    Fut: Future<Output = Result<(Vec<EmbeddingResult>, Option<u64>), anyhow::Error>>,
+{
+    let Some(budget) = budget else {
+        return Ok(request(text_batch).await?.0);
+    };
+    let mut embeddings = Vec::with_capacity(text_batch.len());
+    for batch in text_batch.chunks(max_batch_size.max(1)) {
+        let estimate = estimate_tokens(batch);
+        if let Err(used) = budget.reserve(estimate) {
+            return Err(BudgetExceeded {
+                max_total_tokens: budget.max_total_tokens,
+                used,
+                indices: (0..embeddings.len()).collect(),
+                embeddings,
+            }
+            .into());
+        }
+        let (batch_embeddings, tokens) = match request(batch).await {
+            Ok(response) => response,
+            Err(e) => {
+                budget.settle(estimate, 0);
+                return Err(e);
+            }
+        };
+        budget.settle(estimate, tokens.unwrap_or(estimate));
+        embeddings.extend(batch_embeddings);
+    }
+    Ok(embeddings)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_header_map() {
+        let headers = header_map(&HashMap::from([(
+            "X-Org-Id".to_string(),
+            "org-1".to_string(),
+        )]))
+        .unwrap();
+        assert_eq!(headers["x-org-id"], "org-1");
+
+        let name = header_map(&HashMap::from([("X Org".to_string(), "org-1".to_string())]));
+        assert!(name
+            .unwrap_err()
+            .to_string()
+            .contains("Invalid header name"));
+        let value = header_map(&HashMap::from([("X-Org".to_string(), "a\nb".to_string())]));
+        assert!(value.unwrap_err().to_string().contains("Invalid value"));
+    }
+
+    #[test]
+    fn test_http_client_proxy() {
+        assert!(http_client(Some("http://proxy.internal:3128")).is_ok());
+        assert!(http_client(Some("not a url")).is_err());
+    }
+
+    #[tokio::test]
+    async fn test_token_budget_concurrent_requests() {
+        // "Hello world" is estimated at 3 tokens, the budget only fits one request.
+        let budget = TokenBudget::new(4);
+        let texts = vec!["Hello world".to_string()];
+        let request = |batch: &[String]| {
+            let embeddings = vec![EmbeddingResult::DenseVector(vec![1.0]); batch.len()];
+            async move {
+                tokio::task::yield_now().await;
+                Ok::<_, anyhow::Error>((embeddings, None))
+            }
+        };
+        let (first, second) = tokio::join!(
+            embed_within_budget(Some(&budget), &texts, 1, request),
+            embed_within_budget(Some(&budget), &texts, 1, request),
+        );
+
+        assert!(first.is_ok());
+        assert!(second
+            .unwrap_err()
+            .downcast_ref::<BudgetExceeded>()
+            .is_some());
+        assert_eq!(budget.used(), 3);
+    }
+
+    #[test]
+    fn test_budget_exceeded_remap() {
+        let exceeded = BudgetExceeded {
+            max_total_tokens: 10,
+            used: 8,
+            embeddings: vec![
+                EmbeddingResult::DenseVector(vec![0.0]),
+                EmbeddingResult::DenseVector(vec![1.0]),
+            ],
+            indices: vec![0, 1],
+        };
+        // The batch held the texts 2, 0 and 1 of the other one.
+        let exceeded = exceeded.remap(&[2, 0, 1]);
+
+        assert_eq!(exceeded.indices, vec![0, 2]);
+        assert_eq!(exceeded.embeddings[0].to_dense().unwrap(), vec![1.0]);
+        assert_eq!(exceeded.embeddings[1].to_dense().unwrap(), vec![0.0]);
+    }
+}
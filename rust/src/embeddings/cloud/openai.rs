@@ -1,8 +1,14 @@
-use reqwest::Client;
+use std::collections::HashMap;
+
+use reqwest::{header::HeaderMap, Client};
 use serde::Deserialize;
 use serde_json::json;
 
-use crate::embeddings::embed::EmbeddingResult;
+use super::{embed_within_budget, header_map, http_client, TokenBudget};
+use crate::embeddings::embed::{EmbeddingResult, ModelMetadata};
+
+/// Maximum number of inputs accepted by a single embeddings request.
+pub(crate) const MAX_BATCH_SIZE: usize = 2048;
 
 #[derive(Deserialize, Debug, Default)]
 pub struct OpenAIEmbedResponse {
@@ -29,7 +35,9 @@ pub struct OpenAIEmbedder {
     url: String,
     model: String,
     api_key: String,
+    extra_headers: HeaderMap,
     client: Client,
+    budget: Option<TokenBudget>,
 }
 
 impl Default for OpenAIEmbedder {
@@ -47,19 +55,72 @@ impl OpenAIEmbedder {
             model,
             url: "https://api.openai.com/v1/embeddings".to_string(),
             api_key,
+            extra_headers: HeaderMap::new(),
             client: Client::new(),
+            budget: None,
         }
     }
 
+    pub fn model_metadata(&self) -> ModelMetadata {
+        ModelMetadata::new("openai", &self.model, None)
+    }
+
+    /// Adds headers to every request, e.g. the headers required by an API gateway. They are
+    /// sent after the built-in ones, so they can also replace them.
+    pub fn with_extra_headers(
+        mut self,
+        extra_headers: &HashMap<String, String>,
+    ) -> Result<Self, anyhow::Error> {
+        self.extra_headers = header_map(extra_headers)?;
+        Ok(self)
+    }
+
+    /// Sends every request through `proxy`, see [http_client].
+    pub fn with_proxy(mut self, proxy: &str) -> Result<Self, anyhow::Error> {
+        self.client = http_client(Some(proxy))?;
+        Ok(self)
+    }
+
+    /// Sends every request with `client` instead of a client of its own, e.g. a client with
+    /// custom root certificates or a client certificate for mTLS, or one whose connection pool
+    /// is shared with the rest of the application. Replaces the proxy of `with_proxy`.
+    pub fn with_client(mut self, client: Client) -> Self {
+        self.client = client;
+        self
+    }
+
+    /// Stops embedding with a [BudgetExceeded](super::BudgetExceeded) error once
+    /// `max_total_tokens` tokens were sent, see [TokenBudget].
+    pub fn with_max_total_tokens(mut self, max_total_tokens: u64) -> Self {
+        self.budget = Some(TokenBudget::new(max_total_tokens));
+        self
+    }
+
+    pub fn token_budget(&self) -> Option<&TokenBudget> {
+        self.budget.as_ref()
+    }
+
     pub async fn embed(
         &self,
         text_batch: &[String],
     ) -> Result<Vec<EmbeddingResult>, anyhow::Error> {
+        embed_within_budget(self.budget.as_ref(), text_batch, MAX_BATCH_SIZE, |batch| {
+            self.request(batch)
+        })
+        .await
+    }
+
+    /// Sends one request, returning the embeddings and the tokens used.
+    async fn request(
+        &self,
+        text_batch: &[String],
+    ) -> Result<(Vec<EmbeddingResult>, Option<u64>), anyhow::Error> {
         let response = self
             .client
             .post(&self.url)
             .header("Content-Type", "application/json")
             .header("Authorization", format!("Bearer {}", self.api_key))
+            .headers(self.extra_headers.clone())
             .json(&json!({
                 "input": text_batch,
                 "model": self.model,
@@ -77,7 +138,7 @@ impl OpenAIEmbedder {
             .map(|data| EmbeddingResult::DenseVector(data.embedding.clone()))
             .collect::<Vec<_>>();
 
-        Ok(encodings)
+        Ok((encodings, Some(data.usage.total_tokens as u64)))
     }
 }
 
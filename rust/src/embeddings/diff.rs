@@ -0,0 +1,160 @@
+//! Matching the chunks of two versions of a document, see [embed_diff](crate::embed_diff).
+//!
+//! Chunks with the same text are unchanged. The other chunks are paired by cosine similarity,
+//! most similar pairs first, and a pair at or above the threshold is a modified chunk. What is
+//! left of the new version was added and what is left of the old version was removed, so an
+//! index can be updated by deleting the removed and modified chunks and inserting the added
+//! and modified ones.
+
+use std::collections::HashMap;
+
+use anyhow::Result;
+
+use super::embed::EmbedData;
+
+/// Default cosine similarity from which two chunks are the same chunk modified.
+pub const DEFAULT_DIFF_THRESHOLD: f32 = 0.8;
+
+/// A chunk of the old version and the chunk of the new version it became.
+#[derive(Debug, Clone)]
+pub struct ModifiedChunk {
+    pub old: EmbedData,
+    pub new: EmbedData,
+    /// Cosine similarity of the two embeddings.
+    pub similarity: f32,
+}
+
+/// The changes between two versions of a document, chunk by chunk. The chunks of each version
+/// keep their `chunk_index` in their metadata.
+#[derive(Debug, Clone, Default)]
+pub struct DocumentDiff {
+    /// Chunks of the new version matching no chunk of the old version.
+    pub added: Vec<EmbedData>,
+    /// Chunks of the old version matching no chunk of the new version.
+    pub removed: Vec<EmbedData>,
+    pub modified: Vec<ModifiedChunk>,
+    /// Chunks of the new version found with the same text in the old version.
+    pub unchanged: Vec<EmbedData>,
+}
+
+impl DocumentDiff {
+    /// Whether the two versions have the same chunks.
+    pub fn is_unchanged(&self) -> bool {
+        self.added.is_empty() && self.removed.is_empty() && self.modified.is_empty()
+    }
+}
+
+/// Matches the embedded chunks of the old and new versions of a document. Two chunks with
+/// different texts are a modified chunk when the cosine similarity of their embeddings is at
+/// least `threshold`. Each chunk is matched at most once.
+pub fn diff_chunks(
+    old: Vec<EmbedData>,
+    new: Vec<EmbedData>,
+    threshold: f32,
+) -> Result<DocumentDiff> {
+    let mut old_by_text = HashMap::<Option<String>, Vec<usize>>::new();
+    for (i, chunk) in old.iter().enumerate().rev() {
+        old_by_text.entry(chunk.text.clone()).or_default().push(i);
+    }
+    let mut old_matched = vec![false; old.len()];
+    let mut unchanged = vec![false; new.len()];
+    for (j, chunk) in new.iter().enumerate() {
+        if let Some(i) = old_by_text
+            .get_mut(&chunk.text)
+            .and_then(|indices| indices.pop())
+        {
+            old_matched[i] = true;
+            unchanged[j] = true;
+        }
+    }
+
+    let mut pairs = Vec::new();
+    for (i, old_chunk) in old.iter().enumerate().filter(|(i, _)| !old_matched[*i]) {
+        for (j, new_chunk) in new.iter().enumerate().filter(|(j, _)| !unchanged[*j]) {
+            let similarity = old_chunk.embedding.similarity(&new_chunk.embedding)?;
+            if similarity >= threshold {
+                pairs.push((similarity, i, j));
+            }
+        }
+    }
+    pairs.sort_by(|a, b| b.0.total_cmp(&a.0));
+    let mut modified_from = vec![None; new.len()];
+    for (similarity, i, j) in pairs {
+        if !old_matched[i] && modified_from[j].is_none() {
+            old_matched[i] = true;
+            modified_from[j] = Some((i, similarity));
+        }
+    }
+
+    let mut diff = DocumentDiff::default();
+    for (j, chunk) in new.into_iter().enumerate() {
+        match modified_from[j] {
+            Some((i, similarity)) => diff.modified.push(ModifiedChunk {
+                old: old[i].clone(),
+                new: chunk,
+                similarity,
+            }),
+            None if unchanged[j] => diff.unchanged.push(chunk),
+            None => diff.added.push(chunk),
+        }
+    }
+    diff.removed = old
+        .into_iter()
+        .zip(old_matched)
+        .filter(|(_, matched)| !matched)
+        .map(|(chunk, _)| chunk)
+        .collect();
+    Ok(diff)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::embeddings::embed::EmbeddingResult;
+
+    fn chunk(text: &str, vector: [f32; 2]) -> EmbedData {
+        EmbedData::new(
+            EmbeddingResult::DenseVector(vector.to_vec()),
+            Some(text.to_string()),
+            None,
+        )
+    }
+
+    fn texts(chunks: &[EmbedData]) -> Vec<&str> {
+        chunks
+            .iter()
+            .map(|chunk| chunk.text.as_deref().unwrap())
+            .collect()
+    }
+
+    #[test]
+    fn test_diff_chunks() {
+        let old = vec![
+            chunk("intro", [1.0, 0.0]),
+            chunk("methods", [0.0, 1.0]),
+            chunk("appendix", [-1.0, 0.0]),
+        ];
+        let new = vec![
+            chunk("intro", [1.0, 0.0]),
+            chunk("new methods", [0.1, 1.0]),
+            chunk("results", [-0.5, -1.0]),
+        ];
+        let diff = diff_chunks(old.clone(), new.clone(), DEFAULT_DIFF_THRESHOLD).unwrap();
+        assert_eq!(texts(&diff.unchanged), ["intro"]);
+        assert_eq!(diff.modified.len(), 1);
+        assert_eq!(diff.modified[0].old.text.as_deref(), Some("methods"));
+        assert_eq!(diff.modified[0].new.text.as_deref(), Some("new methods"));
+        assert!(diff.modified[0].similarity > 0.99);
+        assert_eq!(texts(&diff.added), ["results"]);
+        assert_eq!(texts(&diff.removed), ["appendix"]);
+        assert!(!diff.is_unchanged());
+
+        // Below the threshold, the edited chunk is a removal and an addition.
+        let diff = diff_chunks(old.clone(), new, 1.0).unwrap();
+        assert!(diff.modified.is_empty());
+        assert_eq!(texts(&diff.added), ["new methods", "results"]);
+        assert_eq!(texts(&diff.removed), ["methods", "appendix"]);
+
+        assert!(diff_chunks(old.clone(), old, 1.0).unwrap().is_unchanged());
+    }
+}
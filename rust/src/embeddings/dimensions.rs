@@ -0,0 +1,145 @@
+//! Checking that every vector of a run has the same dimension, see
+//! [TextEmbedConfig::validate_dimensions](crate::config::TextEmbedConfig::validate_dimensions).
+//!
+//! A model loaded from the wrong files, or a pool mixing two models, produces vectors of the
+//! wrong size that vector databases reject or, worse, store in an index nothing matches.
+
+use std::sync::Mutex;
+
+use anyhow::{anyhow, Result};
+
+use super::embed::{EmbedData, EmbeddingResult};
+
+/// Number of characters of the offending chunk quoted in errors.
+const QUOTED_CHARS: usize = 50;
+
+/// Checks the dimension of embeddings against the dimension of the model, or when the model
+/// doesn't tell, against the dimension of the first embedding checked.
+#[derive(Debug, Default)]
+pub struct DimensionValidator {
+    expected: Mutex<Option<usize>>,
+}
+
+impl DimensionValidator {
+    /// `expected` is usually [ModelMetadata::dimension](super::embed::ModelMetadata::dimension).
+    pub fn new(expected: Option<usize>) -> Self {
+        Self {
+            expected: Mutex::new(expected),
+        }
+    }
+
+    /// The dimension every vector must have, None until the first embedding is checked when
+    /// the model didn't tell.
+    pub fn expected(&self) -> Option<usize> {
+        *self.expected.lock().unwrap()
+    }
+
+    /// Fails on the first embedding of the wrong dimension, naming its file and quoting its
+    /// chunk.
+    pub fn check(&self, embeddings: &[EmbedData]) -> Result<()> {
+        let mut expected = self.expected.lock().unwrap();
+        for embedding in embeddings {
+            let dimensions = dimensions(&embedding.embedding);
+            let Some(&first) = dimensions.first() else {
+                continue;
+            };
+            let expected = *expected.get_or_insert(first);
+            if let Some(&wrong) = dimensions.iter().find(|&&dimension| dimension != expected) {
+                return Err(mismatch_error(embedding, wrong, expected));
+            }
+        }
+        Ok(())
+    }
+
+    /// Removes the embeddings of the wrong dimension, logging each of them. Used where files
+    /// that fail are skipped rather than failing the whole run.
+    pub fn retain_valid(&self, embeddings: &mut Vec<EmbedData>) {
+        embeddings.retain(
+            |embedding| match self.check(std::slice::from_ref(embedding)) {
+                Ok(()) => true,
+                Err(e) => {
+                    tracing::warn!("Skipping chunk: {}", e);
+                    false
+                }
+            },
+        );
+    }
+}
+
+/// The size of the vector, or of each vector of a multi-vector embedding.
+fn dimensions(embedding: &EmbeddingResult) -> Vec<usize> {
+    match embedding {
+        EmbeddingResult::DenseVector(vector) => vec![vector.len()],
+        EmbeddingResult::HalfVector(vector) => vec![vector.len()],
+        EmbeddingResult::DenseWithTokens { pooled, .. } => vec![pooled.len()],
+        EmbeddingResult::MultiVector(vectors) => vectors.iter().map(Vec::len).collect(),
+    }
+}
+
+fn mismatch_error(embedding: &EmbedData, dimension: usize, expected: usize) -> anyhow::Error {
+    let text = embedding.text.as_deref().unwrap_or_default();
+    let mut quote = text.chars().take(QUOTED_CHARS).collect::<String>();
+    if quote.len() < text.len() {
+        quote.push_str("...");
+    }
+    let file = embedding
+        .metadata
+        .as_ref()
+        .and_then(|metadata| metadata.get("file_name"));
+    match file {
+        Some(file) => anyhow!(
+            "Embedding of dimension {} instead of {} for the chunk {:?} of {}",
+            dimension,
+            expected,
+            quote,
+            file
+        ),
+        None => anyhow!(
+            "Embedding of dimension {} instead of {} for the chunk {:?}",
+            dimension,
+            expected,
+            quote
+        ),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use super::*;
+
+    fn embed_data(text: &str, dimension: usize) -> EmbedData {
+        let metadata = HashMap::from([("file_name".to_string(), "notes.txt".to_string())]);
+        EmbedData::new(
+            EmbeddingResult::DenseVector(vec![0.5; dimension]),
+            Some(text.to_string()),
+            Some(metadata),
+        )
+    }
+
+    #[test]
+    fn test_dimension_validator() {
+        let validator = DimensionValidator::new(None);
+        validator
+            .check(&[embed_data("a", 4), embed_data("b", 4)])
+            .unwrap();
+        assert_eq!(validator.expected(), Some(4));
+        let error = validator.check(&[embed_data("corrupt", 3)]).unwrap_err();
+        assert_eq!(
+            error.to_string(),
+            "Embedding of dimension 3 instead of 4 for the chunk \"corrupt\" of notes.txt"
+        );
+
+        let mut embeddings = vec![embed_data("a", 4), embed_data("b", 8), embed_data("c", 4)];
+        validator.retain_valid(&mut embeddings);
+        assert_eq!(embeddings.len(), 2);
+
+        let multi_vector = EmbedData::new(
+            EmbeddingResult::MultiVector(vec![vec![0.0; 4], vec![0.0; 5]]),
+            None,
+            None,
+        );
+        assert!(validator.check(&[multi_vector]).is_err());
+    }
+}
@@ -1,7 +1,10 @@
 use crate::file_processor::audio::audio_processor::Segment;
 use crate::Dtype;
 
+use super::batch_size::BatchSizeProbe;
 use super::cloud::cohere::CohereEmbedder;
+use super::cloud::gemini::GeminiEmbedder;
+use super::cloud::mistral::MistralEmbedder;
 use super::cloud::openai::OpenAIEmbedder;
 use super::local::bert::{BertEmbed, BertEmbedder, SparseBertEmbedder};
 
@@ -9,22 +12,109 @@ use super::local::clip::ClipEmbedder;
 use super::local::colpali::{ColPaliEmbed, ColPaliEmbedder};
 use super::local::jina::{JinaEmbed, JinaEmbedder};
 use super::local::modernbert::ModernBertEmbedder;
+use super::local::pooling::Pooling;
 use super::local::text_embedding::ONNXModel;
+use super::scoped_model_dtype;
+use super::utils::{scoped_add_special_tokens, scoped_cache_dir};
 use anyhow::anyhow;
-use serde::Deserialize;
+use half::f16;
+use serde::{Deserialize, Serialize};
 use std::collections::HashMap;
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
+use std::sync::{Mutex, OnceLock};
+use tokenizers::Tokenizer;
 #[cfg(feature = "ort")]
 use {
     super::local::colbert::OrtColbertEmbedder,
+    super::local::session_pool::scoped_session_pool_size,
     super::local::ort_bert::{OrtBertEmbedder, OrtSparseBertEmbedder},
     super::local::ort_jina::OrtJinaEmbedder,
 };
 
-#[derive(Deserialize, Debug, Clone)]
+#[derive(Serialize, Deserialize, Debug, Clone)]
 pub enum EmbeddingResult {
     DenseVector(Vec<f32>),
     MultiVector(Vec<Vec<f32>>),
+    /// A dense vector in half precision, returned when the output dtype is [OutputDtype::F16].
+    HalfVector(Vec<f16>),
+    /// The pooled vector of a text along with the output of the model for each of its tokens,
+    /// from one forward pass, see
+    /// [BertEmbedder::with_token_embeddings](super::local::bert::BertEmbedder::with_token_embeddings).
+    /// Used as the pooled vector by dense operations and as the token vectors by
+    /// [EmbeddingResult::to_multi_vector].
+    DenseWithTokens {
+        pooled: Vec<f32>,
+        tokens: Vec<Vec<f32>>,
+    },
+}
+
+/// Precision of the dense vectors returned by the embedding functions.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum OutputDtype {
+    #[default]
+    F32,
+    /// Half precision, which halves the memory and bandwidth of the vectors for a negligible
+    /// loss of recall. Multi-vector embeddings are kept in f32.
+    F16,
+}
+
+/// Precision the weights of local candle models are loaded in, see
+/// [EmbedderBuilder::model_dtype].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ModelDtype {
+    F32,
+    /// Half precision, which halves the memory of the weights.
+    F16,
+    /// Brain float, with the range of f32 and the size of f16. Only used on GPUs, CPU kernels
+    /// are slow.
+    BF16,
+}
+
+/// Metadata entry holding the id of the model that produced an embedding.
+pub const EMBEDDING_MODEL_KEY: &str = "embedding_model";
+
+/// Metadata entry telling chunk embeddings, "chunk", from whole document embeddings,
+/// "document", when
+/// [TextEmbedConfig::document_vector](crate::config::TextEmbedConfig::document_vector) is set.
+pub const GRANULARITY_KEY: &str = "granularity";
+
+/// Describes the model behind an [Embedder], so that stored vectors can be traced back to the
+/// model that produced them.
+#[derive(Debug, Clone, Default)]
+pub struct ModelMetadata {
+    /// The architecture, e.g. "bert" or "clip", or the provider of cloud models, e.g. "openai".
+    pub architecture: String,
+    /// The Hugging Face model id, or the model name of cloud models.
+    pub model_id: String,
+    pub revision: Option<String>,
+    /// Size of the vectors, of every vector for multi-vector models. None when it is only known
+    /// after embedding.
+    pub dimension: Option<usize>,
+    /// How token embeddings are pooled, None for models that do not pool.
+    pub pooling: Option<Pooling>,
+    /// Whether the vectors are L2-normalized.
+    pub normalized: bool,
+}
+
+impl ModelMetadata {
+    pub fn new(architecture: &str, model_id: &str, revision: Option<&str>) -> Self {
+        Self {
+            architecture: architecture.to_string(),
+            model_id: model_id.to_string(),
+            revision: revision.map(str::to_string),
+            ..Default::default()
+        }
+    }
+
+    pub fn with_dimension(mut self, dimension: Option<usize>) -> Self {
+        self.dimension = dimension;
+        self
+    }
+
+    pub fn with_pooling(mut self, pooling: Option<Pooling>) -> Self {
+        self.pooling = pooling;
+        self
+    }
 }
 
 impl From<Vec<f32>> for EmbeddingResult {
@@ -39,10 +129,18 @@ impl From<Vec<Vec<f32>>> for EmbeddingResult {
     }
 }
 
+impl From<Vec<f16>> for EmbeddingResult {
+    fn from(value: Vec<f16>) -> Self {
+        EmbeddingResult::HalfVector(value)
+    }
+}
+
 impl EmbeddingResult {
     pub fn to_dense(&self) -> Result<Vec<f32>, anyhow::Error> {
         match self {
             EmbeddingResult::DenseVector(x) => Ok(x.to_vec()),
+            EmbeddingResult::HalfVector(x) => Ok(x.iter().map(|v| v.to_f32()).collect()),
+            EmbeddingResult::DenseWithTokens { pooled, .. } => Ok(pooled.to_vec()),
             EmbeddingResult::MultiVector(_) => Err(anyhow!(
                 "Multi-vector Embedding are not supported for this operation"
             )),
@@ -52,11 +150,81 @@ impl EmbeddingResult {
     pub fn to_multi_vector(&self) -> Result<Vec<Vec<f32>>, anyhow::Error> {
         match self {
             EmbeddingResult::MultiVector(x) => Ok(x.to_vec()),
-            EmbeddingResult::DenseVector(_) => Err(anyhow!(
+            EmbeddingResult::DenseWithTokens { tokens, .. } => Ok(tokens.to_vec()),
+            EmbeddingResult::DenseVector(_) | EmbeddingResult::HalfVector(_) => Err(anyhow!(
                 "Dense Embedding are not supported for this operation"
             )),
         }
     }
+
+    /// Copies a dense vector into `out`, which must have the dimension of the vector.
+    pub fn write_dense(&self, out: &mut [f32]) -> Result<(), anyhow::Error> {
+        let dimension = match self {
+            EmbeddingResult::DenseVector(x) => x.len(),
+            EmbeddingResult::HalfVector(x) => x.len(),
+            EmbeddingResult::DenseWithTokens { pooled, .. } => pooled.len(),
+            EmbeddingResult::MultiVector(_) => {
+                return Err(anyhow!(
+                    "Multi-vector Embedding are not supported for this operation"
+                ))
+            }
+        };
+        if dimension != out.len() {
+            return Err(anyhow!(
+                "Expected embeddings of dimension {}, got {}",
+                out.len(),
+                dimension
+            ));
+        }
+        match self {
+            EmbeddingResult::DenseVector(x) => out.copy_from_slice(x),
+            EmbeddingResult::DenseWithTokens { pooled, .. } => out.copy_from_slice(pooled),
+            EmbeddingResult::HalfVector(x) => {
+                for (value, half) in out.iter_mut().zip(x) {
+                    *value = half.to_f32();
+                }
+            }
+            EmbeddingResult::MultiVector(_) => unreachable!(),
+        }
+        Ok(())
+    }
+
+    /// Converts a dense vector to `dtype`. Multi-vector embeddings and dense vectors with their
+    /// tokens are returned unchanged.
+    pub fn to_dtype(self, dtype: OutputDtype) -> Self {
+        match (self, dtype) {
+            (EmbeddingResult::DenseVector(x), OutputDtype::F16) => {
+                EmbeddingResult::HalfVector(x.iter().map(|v| f16::from_f32(*v)).collect())
+            }
+            (EmbeddingResult::HalfVector(x), OutputDtype::F32) => {
+                EmbeddingResult::DenseVector(x.iter().map(|v| v.to_f32()).collect())
+            }
+            (embedding, _) => embedding,
+        }
+    }
+
+    /// Cosine similarity of two dense vectors, or the late interaction (MaxSim) score of two
+    /// multi-vector embeddings: the sum over `self` of the best cosine similarity in `other`.
+    /// Dense vectors with their tokens are compared through their pooled vector.
+    pub fn similarity(&self, other: &EmbeddingResult) -> Result<f32, anyhow::Error> {
+        match (self, other) {
+            (EmbeddingResult::MultiVector(query), EmbeddingResult::MultiVector(document)) => {
+                Ok(query
+                    .iter()
+                    .map(|q| {
+                        document
+                            .iter()
+                            .map(|d| cosine(q, d))
+                            .fold(f32::MIN, f32::max)
+                    })
+                    .sum())
+            }
+            (EmbeddingResult::MultiVector(_), _) | (_, EmbeddingResult::MultiVector(_)) => Err(
+                anyhow!("Cannot compare a dense embedding with a multi-vector embedding"),
+            ),
+            _ => Ok(cosine(&self.to_dense()?, &other.to_dense()?)),
+        }
+    }
 }
 
 #[derive(Deserialize, Debug, Clone)]
@@ -64,6 +232,14 @@ pub struct EmbedData {
     pub embedding: EmbeddingResult,
     pub text: Option<String>,
     pub metadata: Option<HashMap<String, String>>,
+    /// Whether the embedder L2-normalized the vector, in which case the dot product equals the
+    /// cosine similarity and the vector must not be normalized again.
+    #[serde(default)]
+    pub is_normalized: bool,
+    /// Embeddings of the same text by every model of [crate::embed_file_multi], keyed by model
+    /// name. `embedding` is the one of the first model. Empty for single model functions.
+    #[serde(default)]
+    pub embeddings: HashMap<String, EmbeddingResult>,
 }
 
 impl EmbedData {
@@ -76,15 +252,41 @@ impl EmbedData {
             embedding,
             text,
             metadata,
+            is_normalized: false,
+            embeddings: HashMap::new(),
         }
     }
 
+    pub fn with_normalized(mut self, is_normalized: bool) -> Self {
+        self.is_normalized = is_normalized;
+        self
+    }
+
+    /// Records the id of the model that produced the embedding in the `embedding_model`
+    /// metadata entry. An empty id, from embedders that do not describe their model, is
+    /// ignored.
+    pub fn with_embedding_model(mut self, model_id: &str) -> Self {
+        if !model_id.is_empty() {
+            self.metadata
+                .get_or_insert_with(HashMap::new)
+                .insert(EMBEDDING_MODEL_KEY.to_string(), model_id.to_string());
+        }
+        self
+    }
+
+    /// The dense vector as the base64 of its little endian f32 bytes, see
+    /// [base64_embedding](super::base64_embedding). Fails for multi-vector embeddings.
+    pub fn embedding_base64(&self) -> Result<String, anyhow::Error> {
+        Ok(super::base64_embedding::encode(&self.embedding.to_dense()?))
+    }
+
     pub fn __str__(&self) -> String {
         format!(
-            "EmbedData(embedding: {:?}, text: {:?}, metadata: {:?})",
+            "EmbedData(embedding: {:?}, text: {:?}, metadata: {:?}, is_normalized: {})",
             self.embedding,
             self.text,
-            self.metadata.clone()
+            self.metadata.clone(),
+            self.is_normalized
         )
     }
 }
@@ -97,6 +299,8 @@ pub trait AudioDecoder {
 pub enum TextEmbedder {
     OpenAI(OpenAIEmbedder),
     Cohere(CohereEmbedder),
+    Gemini(GeminiEmbedder),
+    Mistral(MistralEmbedder),
     Jina(Box<dyn JinaEmbed + Send + Sync>),
     Bert(Box<dyn BertEmbed + Send + Sync>),
     ColBert(Box<dyn BertEmbed + Send + Sync>),
@@ -109,9 +313,12 @@ impl TextEmbedder {
         text_batch: &[String],
         batch_size: Option<usize>,
     ) -> Result<Vec<EmbeddingResult>, anyhow::Error> {
+        let batch_size = batch_size.or(self.recommended_batch_size());
         match self {
             TextEmbedder::OpenAI(embedder) => embedder.embed(text_batch).await,
             TextEmbedder::Cohere(embedder) => embedder.embed(text_batch).await,
+            TextEmbedder::Gemini(embedder) => embedder.embed(text_batch).await,
+            TextEmbedder::Mistral(embedder) => embedder.embed(text_batch).await,
             TextEmbedder::Jina(embedder) => embedder.embed(text_batch, batch_size),
             TextEmbedder::Bert(embedder) => embedder.embed(text_batch, batch_size),
             TextEmbedder::ColBert(embedder) => embedder.embed(text_batch, batch_size),
@@ -119,6 +326,261 @@ impl TextEmbedder {
         }
     }
 
+    /// Embeds search queries. Providers that distinguish queries from documents get the query
+    /// input type (Cohere) or task type (Gemini), local models add their query prefix.
+    pub async fn embed_query_batch(
+        &self,
+        text_batch: &[String],
+        batch_size: Option<usize>,
+    ) -> Result<Vec<EmbeddingResult>, anyhow::Error> {
+        let batch_size = batch_size.or(self.recommended_batch_size());
+        match self {
+            TextEmbedder::Cohere(embedder) => embedder.embed_query(text_batch).await,
+            TextEmbedder::Gemini(embedder) => embedder.embed_query(text_batch).await,
+            TextEmbedder::Jina(embedder) => embedder.embed_query_batch(text_batch, batch_size),
+            TextEmbedder::Bert(embedder)
+            | TextEmbedder::ColBert(embedder)
+            | TextEmbedder::ModernBert(embedder) => {
+                embedder.embed_query_batch(text_batch, batch_size)
+            }
+            TextEmbedder::OpenAI(_) | TextEmbedder::Mistral(_) => {
+                self.embed(text_batch, batch_size).await
+            }
+        }
+    }
+
+    /// Embeds documents to be searched, such as the chunks of a file. Local models add their
+    /// document prefix. Cloud models embed documents by default, so this is the same as
+    /// [TextEmbedder::embed] for them.
+    pub async fn embed_document_batch(
+        &self,
+        text_batch: &[String],
+        batch_size: Option<usize>,
+    ) -> Result<Vec<EmbeddingResult>, anyhow::Error> {
+        let batch_size = batch_size.or(self.recommended_batch_size());
+        match self {
+            TextEmbedder::Jina(embedder) => embedder.embed_document_batch(text_batch, batch_size),
+            TextEmbedder::Bert(embedder)
+            | TextEmbedder::ColBert(embedder)
+            | TextEmbedder::ModernBert(embedder) => {
+                embedder.embed_document_batch(text_batch, batch_size)
+            }
+            _ => self.embed(text_batch, batch_size).await,
+        }
+    }
+
+    /// The batch size used when none is configured. Local models take it from the model map
+    /// when they are listed there, long context models get smaller batches since their padded
+    /// batches are larger. Cloud models send every text in one request and return None.
+    pub fn recommended_batch_size(&self) -> Option<usize> {
+        match self {
+            TextEmbedder::OpenAI(_)
+            | TextEmbedder::Cohere(_)
+            | TextEmbedder::Gemini(_)
+            | TextEmbedder::Mistral(_) => None,
+            TextEmbedder::Jina(embedder) => Some(embedder.recommended_batch_size()),
+            TextEmbedder::Bert(embedder)
+            | TextEmbedder::ColBert(embedder)
+            | TextEmbedder::ModernBert(embedder) => Some(embedder.recommended_batch_size()),
+        }
+    }
+
+    /// The largest number of texts a cloud provider accepts in one request, used to batch the
+    /// chunks of several files together, see
+    /// [CrossFileBatcher](crate::embeddings::cloud::batcher::CrossFileBatcher). Local models
+    /// return None.
+    pub fn max_batch_size(&self) -> Option<usize> {
+        match self {
+            TextEmbedder::OpenAI(_) => Some(super::cloud::openai::MAX_BATCH_SIZE),
+            TextEmbedder::Cohere(_) => Some(super::cloud::cohere::MAX_BATCH_SIZE),
+            TextEmbedder::Gemini(_) => Some(super::cloud::gemini::MAX_BATCH_SIZE),
+            TextEmbedder::Mistral(_) => Some(super::cloud::mistral::MAX_BATCH_SIZE),
+            _ => None,
+        }
+    }
+
+    /// Times batches of `sample` of growing size and returns the one with the best throughput,
+    /// stopping at the first failure such as running out of memory, at `max_batch_size` or
+    /// when larger batches stop paying off. See [BatchSizeProbe]. Texts of `sample` are repeated
+    /// when it is smaller than the batch being timed. Cloud models ignore the batch size and
+    /// return `max_batch_size` without probing.
+    pub async fn tune_batch_size(&self, sample: &[String], max_batch_size: usize) -> usize {
+        let Some(recommended) = self.recommended_batch_size() else {
+            return max_batch_size;
+        };
+        if sample.is_empty() {
+            return recommended.min(max_batch_size);
+        }
+        // Warm up so that one-time initialization is not counted in the first batch.
+        if self.embed(&sample[..1], Some(1)).await.is_err() {
+            return 1;
+        }
+        let mut probe = BatchSizeProbe::new(max_batch_size);
+        while let Some(size) = probe.next_size() {
+            let batch = sample
+                .iter()
+                .cycle()
+                .take(size)
+                .cloned()
+                .collect::<Vec<_>>();
+            let start = std::time::Instant::now();
+            let elapsed = self
+                .embed(&batch, Some(size))
+                .await
+                .map(|_| start.elapsed());
+            probe.record(size, elapsed);
+        }
+        probe.best()
+    }
+
+    /// Adds headers to every request of a cloud model, see
+    /// [OpenAIEmbedder::with_extra_headers]. Local models make no requests and return an error.
+    pub fn with_extra_headers(
+        self,
+        extra_headers: &HashMap<String, String>,
+    ) -> Result<Self, anyhow::Error> {
+        match self {
+            TextEmbedder::OpenAI(embedder) => Ok(TextEmbedder::OpenAI(
+                embedder.with_extra_headers(extra_headers)?,
+            )),
+            TextEmbedder::Cohere(embedder) => Ok(TextEmbedder::Cohere(
+                embedder.with_extra_headers(extra_headers)?,
+            )),
+            TextEmbedder::Gemini(embedder) => Ok(TextEmbedder::Gemini(
+                embedder.with_extra_headers(extra_headers)?,
+            )),
+            TextEmbedder::Mistral(embedder) => Ok(TextEmbedder::Mistral(
+                embedder.with_extra_headers(extra_headers)?,
+            )),
+            _ => Err(anyhow!("Extra headers are only supported by cloud models")),
+        }
+    }
+
+    /// Sends the requests of a cloud model through `proxy`, see
+    /// [http_client](super::cloud::http_client). Local models download their weights through
+    /// the proxies of the environment variables and return an error.
+    pub fn with_proxy(self, proxy: &str) -> Result<Self, anyhow::Error> {
+        match self {
+            TextEmbedder::OpenAI(embedder) => Ok(TextEmbedder::OpenAI(embedder.with_proxy(proxy)?)),
+            TextEmbedder::Cohere(embedder) => Ok(TextEmbedder::Cohere(embedder.with_proxy(proxy)?)),
+            TextEmbedder::Gemini(embedder) => Ok(TextEmbedder::Gemini(embedder.with_proxy(proxy)?)),
+            TextEmbedder::Mistral(embedder) => {
+                Ok(TextEmbedder::Mistral(embedder.with_proxy(proxy)?))
+            }
+            _ => Err(anyhow!(
+                "A proxy can only be set for cloud models, local models use HTTPS_PROXY"
+            )),
+        }
+    }
+
+    /// Sends the requests of a cloud model with `client`, e.g. to use mTLS or custom root
+    /// certificates. Local models make no requests and return an error.
+    pub fn with_client(self, client: reqwest::Client) -> Result<Self, anyhow::Error> {
+        match self {
+            TextEmbedder::OpenAI(embedder) => {
+                Ok(TextEmbedder::OpenAI(embedder.with_client(client)))
+            }
+            TextEmbedder::Cohere(embedder) => {
+                Ok(TextEmbedder::Cohere(embedder.with_client(client)))
+            }
+            TextEmbedder::Gemini(embedder) => {
+                Ok(TextEmbedder::Gemini(embedder.with_client(client)))
+            }
+            TextEmbedder::Mistral(embedder) => {
+                Ok(TextEmbedder::Mistral(embedder.with_client(client)))
+            }
+            _ => Err(anyhow!("A HTTP client can only be set for cloud models")),
+        }
+    }
+
+    /// Limits the tokens a cloud model may send, see [TokenBudget](super::cloud::TokenBudget).
+    /// Local models make no requests and return an error.
+    pub fn with_max_total_tokens(self, max_total_tokens: u64) -> Result<Self, anyhow::Error> {
+        match self {
+            TextEmbedder::OpenAI(embedder) => Ok(TextEmbedder::OpenAI(
+                embedder.with_max_total_tokens(max_total_tokens),
+            )),
+            TextEmbedder::Cohere(embedder) => Ok(TextEmbedder::Cohere(
+                embedder.with_max_total_tokens(max_total_tokens),
+            )),
+            TextEmbedder::Gemini(embedder) => Ok(TextEmbedder::Gemini(
+                embedder.with_max_total_tokens(max_total_tokens),
+            )),
+            TextEmbedder::Mistral(embedder) => Ok(TextEmbedder::Mistral(
+                embedder.with_max_total_tokens(max_total_tokens),
+            )),
+            _ => Err(anyhow!("A token budget can only be set for cloud models")),
+        }
+    }
+
+    /// The token budget of a cloud model, None when it has none or for local models.
+    pub fn token_budget(&self) -> Option<&super::cloud::TokenBudget> {
+        match self {
+            TextEmbedder::OpenAI(embedder) => embedder.token_budget(),
+            TextEmbedder::Cohere(embedder) => embedder.token_budget(),
+            TextEmbedder::Gemini(embedder) => embedder.token_budget(),
+            TextEmbedder::Mistral(embedder) => embedder.token_budget(),
+            _ => None,
+        }
+    }
+
+    /// Whether the embeddings returned by this model are L2-normalized. OpenAI and Mistral return
+    /// unit vectors, Cohere does not document it so they are treated as unnormalized.
+    pub fn is_normalized(&self) -> bool {
+        match self {
+            TextEmbedder::OpenAI(_) | TextEmbedder::Mistral(_) => true,
+            TextEmbedder::Cohere(_) => false,
+            TextEmbedder::Gemini(embedder) => embedder.is_normalized(),
+            TextEmbedder::Jina(_)
+            | TextEmbedder::Bert(_)
+            | TextEmbedder::ColBert(_)
+            | TextEmbedder::ModernBert(_) => true,
+        }
+    }
+
+    /// Describes the model, see [ModelMetadata].
+    pub fn metadata(&self) -> ModelMetadata {
+        let metadata = match self {
+            TextEmbedder::OpenAI(embedder) => embedder.model_metadata(),
+            TextEmbedder::Cohere(embedder) => embedder.model_metadata(),
+            TextEmbedder::Gemini(embedder) => embedder.model_metadata(),
+            TextEmbedder::Mistral(embedder) => embedder.model_metadata(),
+            TextEmbedder::Jina(embedder) => embedder.model_metadata(),
+            TextEmbedder::Bert(embedder)
+            | TextEmbedder::ColBert(embedder)
+            | TextEmbedder::ModernBert(embedder) => embedder.model_metadata(),
+        };
+        ModelMetadata {
+            normalized: self.is_normalized(),
+            ..metadata
+        }
+    }
+
+    /// The tokenizer of local models. None for cloud models, which tokenize on the server.
+    pub fn tokenizer(&self) -> Option<&Tokenizer> {
+        match self {
+            TextEmbedder::OpenAI(_)
+            | TextEmbedder::Cohere(_)
+            | TextEmbedder::Gemini(_)
+            | TextEmbedder::Mistral(_) => None,
+            TextEmbedder::Jina(embedder) => embedder.tokenizer(),
+            TextEmbedder::Bert(embedder)
+            | TextEmbedder::ColBert(embedder)
+            | TextEmbedder::ModernBert(embedder) => embedder.tokenizer(),
+        }
+    }
+
+    /// The prefixes local models add to queries and documents before embedding them, as
+    /// `(query, document)`. Cloud models embed the texts as given.
+    pub fn prefixes(&self) -> (Option<&str>, Option<&str>) {
+        match self {
+            TextEmbedder::Bert(embedder)
+            | TextEmbedder::ColBert(embedder)
+            | TextEmbedder::ModernBert(embedder) => embedder.prefixes(),
+            _ => (None, None),
+        }
+    }
+
     pub fn from_pretrained_hf(
         model: &str,
         model_id: &str,
@@ -234,6 +696,14 @@ impl TextEmbedder {
                 model_id.to_string(),
                 api_key,
             ))),
+            "gemini" | "Gemini" => Ok(Self::Gemini(GeminiEmbedder::new(
+                model_id.to_string(),
+                api_key,
+            ))),
+            "mistral" | "Mistral" => Ok(Self::Mistral(MistralEmbedder::new(
+                model_id.to_string(),
+                api_key,
+            ))),
             _ => Err(anyhow::anyhow!("Model not supported")),
         }
     }
@@ -269,6 +739,25 @@ impl From<Embedder> for TextEmbedder {
 }
 
 impl VisionEmbedder {
+    /// CLIP and ColPali embeddings are returned as produced by the model, without normalization.
+    pub fn is_normalized(&self) -> bool {
+        match self {
+            VisionEmbedder::Clip(_) | VisionEmbedder::ColPali(_) => false,
+        }
+    }
+
+    /// Describes the model, see [ModelMetadata].
+    pub fn metadata(&self) -> ModelMetadata {
+        let metadata = match self {
+            VisionEmbedder::Clip(embedder) => embedder.metadata.clone(),
+            VisionEmbedder::ColPali(embedder) => embedder.model_metadata(),
+        };
+        ModelMetadata {
+            normalized: self.is_normalized(),
+            ..metadata
+        }
+    }
+
     pub fn from_pretrained_hf(
         model: &str,
         model_id: &str,
@@ -352,6 +841,21 @@ pub struct EmbedderBuilder {
     // The ONNX Model ID that you want to use
     onnx_model_id: Option<ONNXModel>,
     dtype: Option<Dtype>,
+    // Size of the returned embeddings, for cloud models that support truncation
+    output_dimensionality: Option<usize>,
+    // Headers added to the requests of cloud models
+    extra_headers: Option<HashMap<String, String>>,
+    // Proxy of the requests of cloud models
+    proxy: Option<String>,
+    // Directory Hugging Face Hub downloads are cached in
+    cache_dir: Option<PathBuf>,
+    // Whether local models wrap texts in their special tokens
+    add_special_tokens: Option<bool>,
+    // Precision the weights of local candle models are loaded in
+    model_dtype: Option<ModelDtype>,
+    // Number of sessions of ONNX models
+    #[cfg(feature = "ort")]
+    session_pool_size: Option<usize>,
 }
 
 impl EmbedderBuilder {
@@ -365,6 +869,14 @@ impl EmbedderBuilder {
             path_in_repo: None,
             onnx_model_id: None,
             dtype: None,
+            output_dimensionality: None,
+            extra_headers: None,
+            proxy: None,
+            cache_dir: None,
+            add_special_tokens: None,
+            model_dtype: None,
+            #[cfg(feature = "ort")]
+            session_pool_size: None,
         }
     }
 
@@ -411,7 +923,66 @@ impl EmbedderBuilder {
         self
     }
 
+    /// Requests embeddings of this size from cloud providers that support it (Gemini).
+    pub fn output_dimensionality(mut self, output_dimensionality: Option<usize>) -> Self {
+        self.output_dimensionality = output_dimensionality;
+        self
+    }
+
+    /// Adds headers to the requests of cloud models, e.g. for corporate API gateways. Header
+    /// names and values are validated when the embedder is built.
+    pub fn extra_headers(mut self, extra_headers: Option<HashMap<String, String>>) -> Self {
+        self.extra_headers = extra_headers;
+        self
+    }
+
+    /// Sends the requests of cloud models through this proxy instead of the ones of the
+    /// `HTTPS_PROXY`/`HTTP_PROXY` environment variables. Hugging Face Hub downloads always use
+    /// the environment variables, which also accept `socks5://` proxies.
+    pub fn proxy(mut self, proxy: Option<&str>) -> Self {
+        self.proxy = proxy.map(|s| s.to_string());
+        self
+    }
+
+    /// Downloads models to and loads them from this directory instead of `$HF_HOME/hub` or
+    /// `~/.cache/huggingface/hub`, e.g. a shared mount or a cache baked into a container
+    /// image. It has the layout of the Hugging Face cache, so existing caches can be reused.
+    pub fn cache_dir(mut self, cache_dir: Option<PathBuf>) -> Self {
+        self.cache_dir = cache_dir;
+        self
+    }
+
+    /// Whether the tokenizers of local models add the special tokens of the model, e.g.
+    /// `[CLS] text [SEP]`. Turn it off for models that expect none, such as some instruction
+    /// models. Query and document prefixes stay inside the special tokens when they are added,
+    /// see [load_tokenizer](super::utils::load_tokenizer). Defaults to true.
+    pub fn add_special_tokens(mut self, add_special_tokens: Option<bool>) -> Self {
+        self.add_special_tokens = add_special_tokens;
+        self
+    }
+
+    /// Loads the weights of BERT models in this precision. f16 and bf16 halve the memory of
+    /// large models on GPUs, the embeddings are still returned in f32. bf16 falls back to f32
+    /// on the CPU. Defaults to the precision the weights were saved in on GPUs, and f32 on the
+    /// CPU, see [select_dtype](super::select_dtype).
+    pub fn model_dtype(mut self, model_dtype: Option<ModelDtype>) -> Self {
+        self.model_dtype = model_dtype;
+        self
+    }
+
+    /// Loads ONNX models as this many sessions, so that as many concurrent calls to a shared
+    /// embedder run in parallel, see [SessionPool](super::local::session_pool::SessionPool).
+    /// Each session holds its own copy of the weights. Defaults to 1.
+    #[cfg(feature = "ort")]
+    pub fn session_pool_size(mut self, session_pool_size: Option<usize>) -> Self {
+        self.session_pool_size = session_pool_size;
+        self
+    }
+
     pub fn from_pretrained_hf(self) -> Result<Embedder, anyhow::Error> {
+        let _cache_dir = scoped_cache_dir(self.cache_dir);
+        let _special_tokens = scoped_add_special_tokens(self.add_special_tokens);
+        let _model_dtype = scoped_model_dtype(self.model_dtype);
         match self.model_id {
             Some(model_id) => Embedder::from_pretrained_hf(
                 &self.model_architecture,
@@ -424,6 +995,10 @@ impl EmbedderBuilder {
     }
 
     pub fn from_pretrained_onnx(self) -> Result<Embedder, anyhow::Error> {
+        let _cache_dir = scoped_cache_dir(self.cache_dir);
+        let _special_tokens = scoped_add_special_tokens(self.add_special_tokens);
+        #[cfg(feature = "ort")]
+        let _session_pool_size = scoped_session_pool_size(self.session_pool_size);
         match (self.onnx_model_id, self.model_id) {
             (None, None) => Err(anyhow::anyhow!(
                 "Either model_id or onnx_model_id is required"
@@ -450,21 +1025,252 @@ impl EmbedderBuilder {
         }
     }
 
+    /// Loads a `bert`, `jina` or `clip` model from a local directory instead of the Hugging Face
+    /// Hub, for air-gapped machines and fine-tuned models. The directory holds `config.json`,
+    /// `tokenizer.json` and the weights as `model.safetensors` or `pytorch_model.bin`, like a
+    /// model repository, and an error lists the files that are missing.
+    ///
+    /// ```rust,no_run
+    /// use embed_anything::embeddings::embed::EmbedderBuilder;
+    ///
+    /// let embedder = EmbedderBuilder::new()
+    ///     .model_architecture("bert")
+    ///     .from_local_dir("/models/my-fine-tuned-bert")
+    ///     .unwrap();
+    /// ```
+    pub fn from_local_dir(self, dir: impl AsRef<Path>) -> Result<Embedder, anyhow::Error> {
+        let dir = dir.as_ref();
+        let _special_tokens = scoped_add_special_tokens(self.add_special_tokens);
+        let _model_dtype = scoped_model_dtype(self.model_dtype);
+        match self.model_architecture.as_str() {
+            "bert" | "Bert" => Ok(Embedder::Text(TextEmbedder::Bert(Box::new(
+                BertEmbedder::from_local_dir(dir)?,
+            )))),
+            "jina" | "Jina" => Ok(Embedder::Text(TextEmbedder::Jina(Box::new(
+                JinaEmbedder::from_local_dir(dir)?,
+            )))),
+            "clip" | "Clip" | "CLIP" => Ok(Embedder::Vision(VisionEmbedder::Clip(
+                ClipEmbedder::from_local_dir(dir)?,
+            ))),
+            architecture => Err(anyhow!(
+                "Loading from a local directory is not supported for {:?} models, only for bert, jina and clip",
+                architecture
+            )),
+        }
+    }
+
     pub fn from_pretrained_cloud(self) -> Result<Embedder, anyhow::Error> {
-        Embedder::from_pretrained_cloud(
+        let embedder = Embedder::from_pretrained_cloud(
             &self.model_architecture,
             &self.model_id.unwrap(),
             self.api_key,
-        )
+        )?;
+        let embedder = match embedder {
+            Embedder::Text(TextEmbedder::Gemini(gemini)) => Embedder::Text(TextEmbedder::Gemini(
+                gemini.with_output_dimensionality(self.output_dimensionality),
+            )),
+            embedder => embedder,
+        };
+        let embedder = match (embedder, self.extra_headers) {
+            (Embedder::Text(embedder), Some(extra_headers)) => {
+                Embedder::Text(embedder.with_extra_headers(&extra_headers)?)
+            }
+            (embedder, _) => embedder,
+        };
+        match (embedder, self.proxy) {
+            (Embedder::Text(embedder), Some(proxy)) => {
+                Ok(Embedder::Text(embedder.with_proxy(&proxy)?))
+            }
+            (embedder, _) => Ok(embedder),
+        }
     }
 }
 
+/// A loaded model. Every embedder is `Send + Sync` and embeds through `&self`, so servers can
+/// load a model once and share it between request handlers as an `Arc<Embedder>`, embedding
+/// concurrently. Models that need exclusive access while running, like ColPali, lock it
+/// internally, so concurrent calls to them wait for each other.
 pub enum Embedder {
     Text(TextEmbedder),
     Vision(VisionEmbedder),
 }
 
+// Fails to compile when a model stops being shareable between threads.
+const _: fn() = || {
+    fn assert_send_sync<T: Send + Sync>() {}
+    assert_send_sync::<Embedder>();
+    assert_send_sync::<TextEmbedder>();
+    assert_send_sync::<VisionEmbedder>();
+};
+
+/// Sentences embedded by [Embedder::self_check].
+const SELF_CHECK_SENTENCES: [&str; 2] = [
+    "The quick brown fox jumps over the lazy dog.",
+    "Embeddings map pieces of text to vectors of numbers.",
+];
+
+/// Outcome of a successful [Embedder::self_check].
+#[derive(Debug, Clone, PartialEq)]
+pub struct SelfCheckReport {
+    /// Size of the embedding vectors.
+    pub dimension: usize,
+    /// Number of vectors per text. Greater than 1 for multi-vector models such as ColBERT.
+    pub vectors_per_text: usize,
+    /// Whether all vectors have a unit L2 norm.
+    pub unit_norm: bool,
+    /// Cosine similarity between the two check sentences.
+    pub similarity: f32,
+}
+
+fn check_embeddings(embeddings: &[EmbeddingResult]) -> Result<SelfCheckReport, anyhow::Error> {
+    // Multi-vector embeddings are compared through their mean vector.
+    let mut pooled = Vec::with_capacity(embeddings.len());
+    let mut dimension = None;
+    let mut vectors_per_text = 1;
+    let mut unit_norm = true;
+    for embedding in embeddings {
+        let vectors = match embedding {
+            EmbeddingResult::DenseVector(_)
+            | EmbeddingResult::HalfVector(_)
+            | EmbeddingResult::DenseWithTokens { .. } => vec![embedding.to_dense()?],
+            EmbeddingResult::MultiVector(vectors) => vectors.clone(),
+        };
+        vectors_per_text = vectors_per_text.max(vectors.len());
+        for vector in &vectors {
+            let expected = *dimension.get_or_insert(vector.len());
+            if vector.is_empty() || expected != vector.len() {
+                return Err(anyhow!(
+                    "Self check failed: inconsistent embedding dimension, expected {}, got {}",
+                    expected,
+                    vector.len()
+                ));
+            }
+            if vector.iter().any(|value| !value.is_finite()) {
+                return Err(anyhow!("Self check failed: embedding contains NaN or Inf"));
+            }
+            let norm = vector.iter().map(|value| value * value).sum::<f32>().sqrt();
+            unit_norm &= (norm - 1.0).abs() < 1e-3;
+        }
+        let mut mean = vec![0.0; dimension.unwrap_or_default()];
+        for vector in &vectors {
+            for (total, value) in mean.iter_mut().zip(vector) {
+                *total += value / vectors.len() as f32;
+            }
+        }
+        pooled.push(mean);
+    }
+
+    let similarity = cosine(&pooled[0], &pooled[1]);
+    let identical = pooled[0]
+        .iter()
+        .zip(&pooled[1])
+        .all(|(a, b)| (a - b).abs() < 1e-6);
+    if identical {
+        return Err(anyhow!(
+            "Self check failed: different sentences produced the same embedding"
+        ));
+    }
+
+    Ok(SelfCheckReport {
+        dimension: dimension.unwrap_or_default(),
+        vectors_per_text,
+        unit_norm,
+        similarity,
+    })
+}
+
+pub(crate) fn cosine(a: &[f32], b: &[f32]) -> f32 {
+    let dot = a.iter().zip(b).map(|(a, b)| a * b).sum::<f32>();
+    let norm_a = a.iter().map(|a| a * a).sum::<f32>().sqrt();
+    let norm_b = b.iter().map(|b| b * b).sum::<f32>().sqrt();
+    dot / (norm_a * norm_b).max(f32::EPSILON)
+}
+
+/// Similarity used to rank embeddings, by [top_k_with_metric](super::top_k_with_metric) and
+/// the index of the `hnsw` feature. Closer vectors always get higher scores, so distances are
+/// negated.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Metric {
+    #[default]
+    Cosine,
+    /// Dot product, for vectors whose norm matters. The `hnsw` index expects L2 normalized
+    /// vectors with it, the returned scores are the exact dot products.
+    Dot,
+    /// Euclidean distance, scored as its negation.
+    Euclidean,
+    /// Number of differing bits of binary embeddings, scored as its negation. A coordinate is
+    /// a set bit when it is positive, so vectors of 0 and 1 and vectors of -1 and 1 both work.
+    Hamming,
+}
+
+impl Metric {
+    /// The score of `b` for the query `a`, higher is closer. The vectors must have the same
+    /// dimension.
+    pub fn score(&self, a: &[f32], b: &[f32]) -> f32 {
+        match self {
+            Metric::Cosine => cosine(a, b),
+            Metric::Dot => a.iter().zip(b).map(|(a, b)| a * b).sum(),
+            Metric::Euclidean => -a
+                .iter()
+                .zip(b)
+                .map(|(a, b)| (a - b) * (a - b))
+                .sum::<f32>()
+                .sqrt(),
+            Metric::Hamming => {
+                let differing = a
+                    .iter()
+                    .zip(b)
+                    .filter(|(a, b)| (**a > 0.0) != (**b > 0.0))
+                    .count();
+                -(differing as f32)
+            }
+        }
+    }
+}
+
 impl Embedder {
+    pub fn is_normalized(&self) -> bool {
+        match self {
+            Self::Text(embedder) => embedder.is_normalized(),
+            Self::Vision(embedder) => embedder.is_normalized(),
+        }
+    }
+
+    /// Describes the model, see [ModelMetadata].
+    pub fn metadata(&self) -> ModelMetadata {
+        match self {
+            Self::Text(embedder) => embedder.metadata(),
+            Self::Vision(embedder) => embedder.metadata(),
+        }
+    }
+
+    /// The tokenizer of local text models, see [TextEmbedder::tokenizer].
+    pub fn tokenizer(&self) -> Option<&Tokenizer> {
+        match self {
+            Self::Text(embedder) => embedder.tokenizer(),
+            Self::Vision(_) => None,
+        }
+    }
+
+    /// The prefixes of local text models, see [TextEmbedder::prefixes].
+    pub fn prefixes(&self) -> (Option<&str>, Option<&str>) {
+        match self {
+            Self::Text(embedder) => embedder.prefixes(),
+            Self::Vision(_) => (None, None),
+        }
+    }
+
+    /// The Hugging Face model id, or the model name of cloud models. Empty for custom
+    /// embedders that do not describe their model.
+    pub fn model_id(&self) -> String {
+        self.metadata().model_id
+    }
+
+    /// The revision the model was loaded from, None for the default branch and cloud models.
+    pub fn revision(&self) -> Option<String> {
+        self.metadata().revision
+    }
+
     pub async fn embed(
         &self,
         text_batch: &[String],
@@ -476,6 +1282,178 @@ impl Embedder {
         }
     }
 
+    pub async fn embed_query_batch(
+        &self,
+        text_batch: &[String],
+        batch_size: Option<usize>,
+    ) -> Result<Vec<EmbeddingResult>, anyhow::Error> {
+        match self {
+            Self::Text(embedder) => embedder.embed_query_batch(text_batch, batch_size).await,
+            Self::Vision(embedder) => embedder.embed_query_batch(text_batch, batch_size),
+        }
+    }
+
+    pub async fn embed_document_batch(
+        &self,
+        text_batch: &[String],
+        batch_size: Option<usize>,
+    ) -> Result<Vec<EmbeddingResult>, anyhow::Error> {
+        match self {
+            Self::Text(embedder) => embedder.embed_document_batch(text_batch, batch_size).await,
+            Self::Vision(embedder) => embedder.embed_document_batch(text_batch, batch_size),
+        }
+    }
+
+    /// Embeds `text_batch` into `out`, a flat buffer of `text_batch.len() * dimension` values
+    /// holding the dense vector of each text one after the other. Meant for services that
+    /// recycle their buffers: texts are embedded one batch at a time, so only the vectors of
+    /// the current batch are allocated. The dimension is taken from the length of `out`, an
+    /// error is returned when it does not match the model or the model returns multi-vector
+    /// embeddings.
+    pub async fn embed_into(
+        &self,
+        text_batch: &[String],
+        out: &mut [f32],
+        batch_size: Option<usize>,
+    ) -> Result<(), anyhow::Error> {
+        if text_batch.is_empty() {
+            return match out.len() {
+                0 => Ok(()),
+                len => Err(anyhow!(
+                    "Expected an empty buffer for no texts, got {}",
+                    len
+                )),
+            };
+        }
+        if out.is_empty() || out.len() % text_batch.len() != 0 {
+            return Err(anyhow!(
+                "Buffer of {} values does not hold a vector for each of {} texts",
+                out.len(),
+                text_batch.len()
+            ));
+        }
+        let dimension = out.len() / text_batch.len();
+        let batch_size = batch_size
+            .or(self.recommended_batch_size())
+            .unwrap_or(text_batch.len())
+            .max(1);
+        for (texts, out) in text_batch
+            .chunks(batch_size)
+            .zip(out.chunks_mut(batch_size * dimension))
+        {
+            let embeddings = self.embed(texts, Some(batch_size)).await?;
+            if embeddings.len() != texts.len() {
+                return Err(anyhow!(
+                    "Expected {} embeddings, got {}",
+                    texts.len(),
+                    embeddings.len()
+                ));
+            }
+            for (embedding, out) in embeddings.iter().zip(out.chunks_mut(dimension)) {
+                embedding.write_dense(out)?;
+            }
+        }
+        Ok(())
+    }
+
+    /// See [TextEmbedder::recommended_batch_size]. Vision models use 32.
+    pub fn recommended_batch_size(&self) -> Option<usize> {
+        match self {
+            Self::Text(embedder) => embedder.recommended_batch_size(),
+            Self::Vision(_) => Some(32),
+        }
+    }
+
+    /// See [TextEmbedder::tune_batch_size]. Vision models return their recommended batch size.
+    /// The batch size found is kept, so later calls on the same embedder with the same
+    /// `max_batch_size` return it without probing again.
+    pub async fn tune_batch_size(&self, sample: &[String], max_batch_size: usize) -> usize {
+        static TUNED: OnceLock<Mutex<HashMap<(usize, String, usize), usize>>> = OnceLock::new();
+        // Keyed by address, as a model is loaded once and shared, and by model id so that a
+        // model loaded at the address of a dropped one is probed again.
+        let key = (
+            self as *const Self as usize,
+            self.model_id(),
+            max_batch_size,
+        );
+        let tuned = TUNED.get_or_init(Default::default);
+        let cached = tuned.lock().unwrap().get(&key).copied();
+        if let Some(batch_size) = cached {
+            return batch_size;
+        }
+        let batch_size = match self {
+            Self::Text(embedder) => embedder.tune_batch_size(sample, max_batch_size).await,
+            Self::Vision(_) => max_batch_size.min(32),
+        };
+        // Without a sample nothing was probed.
+        if !sample.is_empty() {
+            tuned.lock().unwrap().insert(key, batch_size);
+        }
+        batch_size
+    }
+
+    /// Embeds a few fixed sentences and verifies the output is sane: one embedding per
+    /// sentence, a consistent dimension, only finite values and distinct vectors for distinct
+    /// sentences. Meant to be called at startup to catch corrupt weights or a misconfigured
+    /// model before serving traffic.
+    pub async fn self_check(&self) -> Result<SelfCheckReport, anyhow::Error> {
+        let sentences = SELF_CHECK_SENTENCES
+            .iter()
+            .map(|sentence| sentence.to_string())
+            .collect::<Vec<_>>();
+        let embeddings = self.embed(&sentences, None).await?;
+        if embeddings.len() != sentences.len() {
+            return Err(anyhow!(
+                "Self check failed: expected {} embeddings, got {}",
+                sentences.len(),
+                embeddings.len()
+            ));
+        }
+        check_embeddings(&embeddings)
+    }
+
+    /// Scores every image against every text label with a vision model such as CLIP, e.g. for
+    /// zero-shot classification. Returns one row per image and one column per label holding
+    /// the cosine similarity of their embeddings, so the best label of an image is the column
+    /// with the highest score. Labels are embedded as queries. Fails with text models and
+    /// models returning multi-vector embeddings.
+    ///
+    /// ```rust,no_run
+    /// use embed_anything::embeddings::embed::Embedder;
+    ///
+    /// let embedder =
+    ///     Embedder::from_pretrained_hf("clip", "openai/clip-vit-base-patch32", None, None).unwrap();
+    /// let labels = vec!["a photo of a cat".to_string(), "a photo of a dog".to_string()];
+    /// let scores = embedder
+    ///     .encode_image_and_text_pairs(&["pet.jpg"], &labels)
+    ///     .unwrap();
+    /// println!("cat: {}, dog: {}", scores[0][0], scores[0][1]);
+    /// ```
+    pub fn encode_image_and_text_pairs<T: AsRef<std::path::Path>>(
+        &self,
+        images: &[T],
+        labels: &[String],
+    ) -> Result<Vec<Vec<f32>>, anyhow::Error> {
+        let Self::Vision(embedder) = self else {
+            return Err(anyhow!(
+                "Scoring images against labels requires a vision model such as CLIP"
+            ));
+        };
+        let labels = embedder
+            .embed_query_batch(labels, None)?
+            .iter()
+            .map(EmbeddingResult::to_dense)
+            .collect::<Result<Vec<_>, _>>()?;
+        embedder
+            .embed_image_batch(images)?
+            .iter()
+            .map(|image| {
+                let image = image.embedding.to_dense()?;
+                Ok(labels.iter().map(|label| cosine(&image, label)).collect())
+            })
+            .collect()
+    }
+
     pub fn from_pretrained_hf(
         model_architecture: &str,
         model_id: &str,
@@ -532,6 +1510,12 @@ impl Embedder {
             "cohere" | "Cohere" => Ok(Self::Text(TextEmbedder::from_pretrained_cloud(
                 model, model_id, api_key,
             )?)),
+            "gemini" | "Gemini" => Ok(Self::Text(TextEmbedder::from_pretrained_cloud(
+                model, model_id, api_key,
+            )?)),
+            "mistral" | "Mistral" => Ok(Self::Text(TextEmbedder::from_pretrained_cloud(
+                model, model_id, api_key,
+            )?)),
             _ => Err(anyhow::anyhow!("Model not supported")),
         }
     }
@@ -591,6 +1575,28 @@ impl EmbedImage for Embedder {
             _ => Err(anyhow::anyhow!("Model not supported for vision embedding")),
         }
     }
+
+    fn embed_image_batch_pipelined<T: AsRef<std::path::Path> + Sync>(
+        &self,
+        image_paths: &[T],
+        decode_threads: Option<usize>,
+        buffer_size: usize,
+    ) -> anyhow::Result<Vec<EmbedData>> {
+        match self {
+            Self::Vision(embedder) => {
+                embedder.embed_image_batch_pipelined(image_paths, decode_threads, buffer_size)
+            }
+            _ => Err(anyhow::anyhow!("Model not supported for vision embedding")),
+        }
+    }
+
+    fn is_normalized(&self) -> bool {
+        Embedder::is_normalized(self)
+    }
+
+    fn model_metadata(&self) -> ModelMetadata {
+        self.metadata()
+    }
 }
 
 pub trait TextEmbed {
@@ -599,6 +1605,25 @@ pub trait TextEmbed {
         text_batch: &[String],
         batch_size: Option<usize>,
     ) -> Result<Vec<EmbeddingResult>, anyhow::Error>;
+
+    /// Embeds search queries. Asymmetric models override this to add their query prefix.
+    fn embed_query_batch(
+        &self,
+        text_batch: &[String],
+        batch_size: Option<usize>,
+    ) -> Result<Vec<EmbeddingResult>, anyhow::Error> {
+        self.embed(text_batch, batch_size)
+    }
+
+    /// Embeds documents to be searched. Asymmetric models override this to add their document
+    /// prefix.
+    fn embed_document_batch(
+        &self,
+        text_batch: &[String],
+        batch_size: Option<usize>,
+    ) -> Result<Vec<EmbeddingResult>, anyhow::Error> {
+        self.embed(text_batch, batch_size)
+    }
 }
 
 impl TextEmbed for VisionEmbedder {
@@ -624,6 +1649,26 @@ pub trait EmbedImage {
         &self,
         image_paths: &[T],
     ) -> anyhow::Result<Vec<EmbedData>>;
+    /// Embeds a batch of images like [EmbedImage::embed_image_batch], decoding images on
+    /// `decode_threads` threads while the model runs, with at most `buffer_size` decoded images
+    /// waiting for the model. Models without a pipelined implementation embed the batch as
+    /// usual.
+    fn embed_image_batch_pipelined<T: AsRef<std::path::Path> + Sync>(
+        &self,
+        image_paths: &[T],
+        _decode_threads: Option<usize>,
+        _buffer_size: usize,
+    ) -> anyhow::Result<Vec<EmbedData>> {
+        self.embed_image_batch(image_paths)
+    }
+    /// Whether the image embeddings are L2-normalized.
+    fn is_normalized(&self) -> bool {
+        false
+    }
+    /// Describes the model. Embedders that do not override this report an unknown model.
+    fn model_metadata(&self) -> ModelMetadata {
+        ModelMetadata::default()
+    }
 }
 
 impl EmbedImage for VisionEmbedder {
@@ -654,4 +1699,369 @@ impl EmbedImage for VisionEmbedder {
             ),
         }
     }
+
+    fn embed_image_batch_pipelined<T: AsRef<std::path::Path> + Sync>(
+        &self,
+        image_paths: &[T],
+        decode_threads: Option<usize>,
+        buffer_size: usize,
+    ) -> anyhow::Result<Vec<EmbedData>> {
+        match self {
+            Self::Clip(embedder) => {
+                embedder.embed_image_batch_pipelined(image_paths, decode_threads, buffer_size)
+            }
+            Self::ColPali(_) => self.embed_image_batch(image_paths),
+        }
+    }
+
+    fn model_metadata(&self) -> ModelMetadata {
+        self.metadata()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use super::*;
+
+    #[test]
+    fn test_check_embeddings() {
+        let report = check_embeddings(&[
+            EmbeddingResult::DenseVector(vec![1.0, 0.0]),
+            EmbeddingResult::DenseVector(vec![0.6, 0.8]),
+        ])
+        .unwrap();
+        assert_eq!(report.dimension, 2);
+        assert_eq!(report.vectors_per_text, 1);
+        assert!(report.unit_norm);
+        assert!((report.similarity - 0.6).abs() < 1e-6);
+    }
+
+    #[test]
+    fn test_check_embeddings_failures() {
+        let nan = check_embeddings(&[
+            EmbeddingResult::DenseVector(vec![f32::NAN, 0.0]),
+            EmbeddingResult::DenseVector(vec![0.6, 0.8]),
+        ]);
+        assert!(nan.unwrap_err().to_string().contains("NaN"));
+
+        let dimension = check_embeddings(&[
+            EmbeddingResult::DenseVector(vec![1.0, 0.0]),
+            EmbeddingResult::DenseVector(vec![0.6, 0.8, 0.0]),
+        ]);
+        assert!(dimension.unwrap_err().to_string().contains("dimension"));
+
+        let identical = check_embeddings(&[
+            EmbeddingResult::DenseVector(vec![0.6, 0.8]),
+            EmbeddingResult::DenseVector(vec![0.6, 0.8]),
+        ]);
+        assert!(identical
+            .unwrap_err()
+            .to_string()
+            .contains("same embedding"));
+    }
+
+    #[test]
+    fn test_half_precision_round_trip() {
+        let vector = vec![0.0, 1.0, -0.5, 0.123_456, -3.75e-3, 42.1];
+        let half = EmbeddingResult::DenseVector(vector.clone()).to_dtype(OutputDtype::F16);
+        assert!(matches!(half, EmbeddingResult::HalfVector(_)));
+        for (value, round_trip) in vector.iter().zip(half.to_dense().unwrap()) {
+            assert!((value - round_trip).abs() <= value.abs() * 1e-3);
+        }
+        let json = serde_json::to_string(&half).unwrap();
+        let deserialized: EmbeddingResult = serde_json::from_str(&json).unwrap();
+        assert_eq!(deserialized.to_dense().unwrap(), half.to_dense().unwrap());
+        let full = half.to_dtype(OutputDtype::F32);
+        assert!(matches!(full, EmbeddingResult::DenseVector(_)));
+
+        let multi = EmbeddingResult::MultiVector(vec![vec![0.5]]).to_dtype(OutputDtype::F16);
+        assert!(matches!(multi, EmbeddingResult::MultiVector(_)));
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn test_tune_batch_size_on_cpu() {
+        let embedder = Embedder::from_pretrained_hf(
+            "bert",
+            "sentence-transformers/all-MiniLM-L6-v2",
+            None,
+            None,
+        )
+        .unwrap();
+        let sample = SELF_CHECK_SENTENCES
+            .iter()
+            .map(|sentence| sentence.to_string())
+            .collect::<Vec<_>>();
+        let batch_size = embedder.tune_batch_size(&sample, 64).await;
+        assert!((1..=64).contains(&batch_size));
+        assert!(batch_size.is_power_of_two());
+    }
+
+    #[tokio::test(flavor = "multi_thread", worker_threads = 8)]
+    async fn test_concurrent_embed() {
+        let embedder = Arc::new(
+            Embedder::from_pretrained_hf(
+                "bert",
+                "sentence-transformers/all-MiniLM-L6-v2",
+                None,
+                None,
+            )
+            .unwrap(),
+        );
+        let texts = (0..6)
+            .map(|i| format!("Sentence number {} is embedded by many tasks at once.", i))
+            .collect::<Vec<_>>();
+        let expected = embedder.embed(&texts, None).await.unwrap();
+
+        let tasks = (0..32)
+            .map(|_| {
+                let embedder = embedder.clone();
+                let texts = texts.clone();
+                tokio::spawn(async move { embedder.embed(&texts, None).await })
+            })
+            .collect::<Vec<_>>();
+        for task in tasks {
+            let embeddings = task.await.unwrap().unwrap();
+            assert_eq!(embeddings.len(), expected.len());
+            for (embedding, expected) in embeddings.iter().zip(&expected) {
+                let embedding = embedding.to_dense().unwrap();
+                let expected = expected.to_dense().unwrap();
+                assert!(embedding
+                    .iter()
+                    .zip(&expected)
+                    .all(|(value, expected)| (value - expected).abs() < 1e-5));
+            }
+        }
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn test_add_special_tokens() {
+        let load = |add_special_tokens| {
+            EmbedderBuilder::new()
+                .model_architecture("bert")
+                .model_id(Some("sentence-transformers/all-MiniLM-L6-v2"))
+                .add_special_tokens(add_special_tokens)
+                .from_pretrained_hf()
+                .unwrap()
+        };
+        let tokens = |embedder: &Embedder| {
+            let encoding = embedder
+                .tokenizer()
+                .unwrap()
+                .encode("query: Hello world", true)
+                .unwrap();
+            encoding.get_tokens().to_vec()
+        };
+        let with_specials = load(None);
+        let without_specials = load(Some(false));
+        // The tokens of the reference tokenizer of the model.
+        assert_eq!(
+            tokens(&with_specials),
+            ["[CLS]", "query", ":", "hello", "world", "[SEP]"]
+        );
+        assert_eq!(tokens(&without_specials), ["query", ":", "hello", "world"]);
+
+        let texts = vec!["Hello world".to_string()];
+        let with_specials = with_specials.embed(&texts, None).await.unwrap();
+        let without_specials = without_specials.embed(&texts, None).await.unwrap();
+        assert_ne!(
+            with_specials[0].to_dense().unwrap(),
+            without_specials[0].to_dense().unwrap()
+        );
+    }
+
+    #[test]
+    fn test_encode_image_and_text_pairs() {
+        let embedder =
+            Embedder::from_pretrained_hf("clip", "openai/clip-vit-base-patch32", None, None)
+                .unwrap();
+        let labels = vec![
+            "a photo of a cat".to_string(),
+            "a photo of a dog".to_string(),
+        ];
+        let scores = embedder
+            .encode_image_and_text_pairs(&["../test_files/clip/cat1.jpg"], &labels)
+            .unwrap();
+        assert_eq!(scores.len(), 1);
+        assert_eq!(scores[0].len(), 2);
+        assert!(scores[0][0] > scores[0][1]);
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn test_model_dtype() {
+        let load = |model_dtype| {
+            EmbedderBuilder::new()
+                .model_architecture("bert")
+                .model_id(Some("sentence-transformers/all-MiniLM-L6-v2"))
+                .model_dtype(model_dtype)
+                .from_pretrained_hf()
+                .unwrap()
+        };
+        let texts = vec![
+            "The cat sits on the mat.".to_string(),
+            "A longer sentence, padded to the length of the first one in the batch.".to_string(),
+        ];
+        let full = load(Some(ModelDtype::F32));
+        let half = load(Some(ModelDtype::F16));
+        let full = full.embed(&texts, None).await.unwrap();
+        let half = half.embed(&texts, None).await.unwrap();
+        for (full, half) in full.iter().zip(&half) {
+            let values = half.to_dense().unwrap();
+            assert!(values.iter().all(|value| value.is_finite()));
+            assert!(full.similarity(half).unwrap() > 0.999);
+        }
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn test_embed_into() {
+        let embedder = Embedder::from_pretrained_hf(
+            "bert",
+            "sentence-transformers/all-MiniLM-L6-v2",
+            None,
+            None,
+        )
+        .unwrap();
+        let texts = SELF_CHECK_SENTENCES
+            .iter()
+            .chain(["A third sentence."].iter())
+            .map(|sentence| sentence.to_string())
+            .collect::<Vec<_>>();
+        let embeddings = embedder.embed(&texts, None).await.unwrap();
+        let dimension = embeddings[0].to_dense().unwrap().len();
+
+        // A batch size that does not divide the number of texts, to cover the last batch.
+        let mut out = vec![0.0; texts.len() * dimension];
+        embedder
+            .embed_into(&texts, &mut out, Some(2))
+            .await
+            .unwrap();
+        for (embedding, vector) in embeddings.iter().zip(out.chunks(dimension)) {
+            for (expected, value) in embedding.to_dense().unwrap().iter().zip(vector) {
+                assert!((expected - value).abs() < 1e-5);
+            }
+        }
+
+        let mut short = vec![0.0; texts.len() * dimension - 1];
+        assert!(embedder.embed_into(&texts, &mut short, None).await.is_err());
+        let mut wrong_dimension = vec![0.0; texts.len() * (dimension + 1)];
+        assert!(embedder
+            .embed_into(&texts, &mut wrong_dimension, None)
+            .await
+            .is_err());
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn test_model_metadata() {
+        let embedder = Embedder::from_pretrained_hf(
+            "bert",
+            "sentence-transformers/all-MiniLM-L6-v2",
+            None,
+            None,
+        )
+        .unwrap();
+        let metadata = embedder.metadata();
+        assert_eq!(metadata.architecture, "bert");
+        assert_eq!(
+            embedder.model_id(),
+            "sentence-transformers/all-MiniLM-L6-v2"
+        );
+        assert_eq!(embedder.revision(), None);
+        assert_eq!(metadata.dimension, Some(384));
+        assert!(matches!(metadata.pooling, Some(Pooling::Mean)));
+        assert!(metadata.normalized);
+
+        let embeddings = crate::embed_query(vec!["Hello".to_string()], &embedder, None)
+            .await
+            .unwrap();
+        let metadata = embeddings[0].metadata.as_ref().unwrap();
+        assert_eq!(
+            metadata[EMBEDDING_MODEL_KEY],
+            "sentence-transformers/all-MiniLM-L6-v2"
+        );
+    }
+
+    #[test]
+    fn test_cache_dir() {
+        let cache_dir = tempdir::TempDir::new("hf_cache").unwrap();
+        let embedder = EmbedderBuilder::new()
+            .model_architecture("bert")
+            .model_id(Some("sentence-transformers/all-MiniLM-L6-v2"))
+            .cache_dir(Some(cache_dir.path().to_path_buf()))
+            .from_pretrained_hf()
+            .unwrap();
+        assert_eq!(embedder.metadata().dimension, Some(384));
+
+        let snapshots = cache_dir
+            .path()
+            .join("models--sentence-transformers--all-MiniLM-L6-v2")
+            .join("snapshots");
+        let snapshot = std::fs::read_dir(snapshots)
+            .unwrap()
+            .next()
+            .unwrap()
+            .unwrap();
+        for file in ["config.json", "tokenizer.json", "model.safetensors"] {
+            assert!(snapshot.path().join(file).exists(), "{} is missing", file);
+        }
+        // The directory only applies while the model is loaded.
+        assert_ne!(
+            crate::embeddings::utils::hf_cache_dir(),
+            Some(cache_dir.path().to_path_buf())
+        );
+    }
+
+    #[tokio::test]
+    async fn test_from_local_dir() {
+        let model_dir = tempdir::TempDir::new("local_model").unwrap();
+        let load = || {
+            EmbedderBuilder::new()
+                .model_architecture("bert")
+                .from_local_dir(model_dir.path())
+        };
+        let error = load().err().unwrap().to_string();
+        assert!(
+            error.ends_with(
+                "missing config.json, tokenizer.json, model.safetensors or pytorch_model.bin"
+            ),
+            "{}",
+            error
+        );
+
+        // Lay out the directory with the files of a Hub model.
+        let model_id = "sentence-transformers/all-MiniLM-L6-v2";
+        let repo = super::super::utils::hf_api_builder()
+            .build()
+            .unwrap()
+            .model(model_id.to_string());
+        for file in ["config.json", "tokenizer.json"] {
+            std::fs::copy(repo.get(file).unwrap(), model_dir.path().join(file)).unwrap();
+        }
+        let error = load().err().unwrap().to_string();
+        assert!(
+            error.ends_with("missing model.safetensors or pytorch_model.bin"),
+            "{}",
+            error
+        );
+        std::fs::copy(
+            repo.get("model.safetensors").unwrap(),
+            model_dir.path().join("model.safetensors"),
+        )
+        .unwrap();
+
+        let local = load().unwrap();
+        let hub = Embedder::from_pretrained_hf("bert", model_id, None, None).unwrap();
+        assert_eq!(local.model_id(), model_dir.path().to_string_lossy());
+        let text = vec!["Loaded without the Hub".to_string()];
+        let local = local.embed(&text, None).await.unwrap();
+        let hub = hub.embed(&text, None).await.unwrap();
+        assert_eq!(local[0].to_dense().unwrap(), hub[0].to_dense().unwrap());
+
+        let error = EmbedderBuilder::new()
+            .model_architecture("openai")
+            .from_local_dir(model_dir.path())
+            .err()
+            .unwrap();
+        assert!(error.to_string().contains("only for bert, jina and clip"));
+    }
 }
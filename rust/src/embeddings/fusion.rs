@@ -0,0 +1,117 @@
+//! Helpers to combine dense and sparse retrieval for hybrid search.
+//!
+//! [hybrid_score] mixes the scores of one document, which suits reranking a candidate set.
+//! [rrf] merges whole rankings, e.g. the results of a dense and a sparse index, using only the
+//! positions of the documents so that scores on different scales don't need calibrating.
+
+use std::collections::{HashMap, HashSet};
+
+use super::embed::cosine;
+
+/// The `k` of [rrf] recommended by the paper introducing reciprocal rank fusion.
+pub const RRF_K: usize = 60;
+
+/// Scores a document as `alpha * dense + (1 - alpha) * sparse`, where `dense` is the cosine
+/// similarity of the dense embeddings and `sparse` the dot product of the sparse embeddings,
+/// as returned by a SPLADE model. `alpha` = 1.0 only uses the dense score and 0.0 only the
+/// sparse score.
+///
+/// Sparse scores are not bounded by 1 like cosine similarities, so the best `alpha` depends on
+/// the models and is usually found on a few labelled queries.
+///
+/// # Panics
+///
+/// Panics if the query and document embeddings of a kind have different dimensions.
+pub fn hybrid_score(
+    dense_query: &[f32],
+    dense_document: &[f32],
+    sparse_query: &[f32],
+    sparse_document: &[f32],
+    alpha: f32,
+) -> f32 {
+    assert_eq!(
+        dense_query.len(),
+        dense_document.len(),
+        "Dense embeddings have different dimensions"
+    );
+    assert_eq!(
+        sparse_query.len(),
+        sparse_document.len(),
+        "Sparse embeddings have different dimensions"
+    );
+    let dense = cosine(dense_query, dense_document);
+    let sparse = sparse_query
+        .iter()
+        .zip(sparse_document)
+        .map(|(q, d)| q * d)
+        .sum::<f32>();
+    alpha * dense + (1.0 - alpha) * sparse
+}
+
+/// Merges rankings with reciprocal rank fusion. Each ranking lists document ids, best first,
+/// and a document scores `1 / (k + rank)` in every ranking it appears in, with ranks starting
+/// at 1. Larger `k` flatten the difference between the top ranks, see [RRF_K].
+///
+/// Returns every document with its fused score, best first. Ties are ordered by id, and a
+/// document repeated in a ranking only counts at its best rank.
+pub fn rrf(rankings: Vec<Vec<usize>>, k: usize) -> Vec<(usize, f32)> {
+    let mut scores = HashMap::<usize, f32>::new();
+    for ranking in rankings {
+        let mut seen = HashSet::new();
+        for (rank, id) in ranking.into_iter().enumerate() {
+            if seen.insert(id) {
+                *scores.entry(id).or_default() += 1.0 / (k + rank + 1) as f32;
+            }
+        }
+    }
+    let mut fused = scores.into_iter().collect::<Vec<_>>();
+    fused.sort_by(|a, b| b.1.total_cmp(&a.1).then(a.0.cmp(&b.0)));
+    fused
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_hybrid_score() {
+        let dense_query = [1.0, 0.0];
+        let dense_document = [3.0, 4.0];
+        let sparse_query = [0.0, 2.0, 1.0, 0.0];
+        let sparse_document = [5.0, 1.5, 0.0, 1.0];
+        let score = |alpha| {
+            hybrid_score(
+                &dense_query,
+                &dense_document,
+                &sparse_query,
+                &sparse_document,
+                alpha,
+            )
+        };
+        // The cosine similarity is 0.6 and the dot product of the sparse embeddings 3.0.
+        assert!((score(1.0) - 0.6).abs() < 1e-6);
+        assert!((score(0.0) - 3.0).abs() < 1e-6);
+        assert!((score(0.5) - 1.8).abs() < 1e-6);
+    }
+
+    #[test]
+    #[should_panic(expected = "Sparse embeddings have different dimensions")]
+    fn test_hybrid_score_dimensions() {
+        hybrid_score(&[1.0], &[1.0], &[1.0, 2.0], &[1.0], 0.5);
+    }
+
+    #[test]
+    fn test_rrf() {
+        let fused = rrf(vec![vec![1, 2, 3], vec![3, 1, 4]], 60);
+        let ids = fused.iter().map(|(id, _)| *id).collect::<Vec<_>>();
+        assert_eq!(ids, vec![1, 3, 2, 4]);
+        assert!((fused[0].1 - (1.0 / 61.0 + 1.0 / 62.0)).abs() < 1e-6);
+        assert!((fused[1].1 - (1.0 / 63.0 + 1.0 / 61.0)).abs() < 1e-6);
+        assert!((fused[2].1 - 1.0 / 62.0).abs() < 1e-6);
+        assert!((fused[3].1 - 1.0 / 63.0).abs() < 1e-6);
+
+        // Repeated ids count once, and ties are ordered by id.
+        assert_eq!(rrf(vec![vec![7, 7], vec![5]], 0), vec![(5, 1.0), (7, 1.0)]);
+        assert!(rrf(Vec::new(), RRF_K).is_empty());
+    }
+}
@@ -0,0 +1,61 @@
+//! Hypothetical document embeddings (HyDE), a query expansion for retrieval.
+//!
+//! A short question and the passage answering it are worded differently, so their embeddings
+//! can be far apart. [embed_query_hyde](crate::embed_query_hyde) asks a language model to write
+//! a passage answering the query, right or wrong, and embeds that passage instead: it reads
+//! like the documents of the index and lands closer to them.
+//!
+//! The crate doesn't generate text. Implement [TextGenerator] over the client of your language
+//! model:
+//!
+//! ```rust,no_run
+//! use embed_anything::{
+//!     embed_query_hyde,
+//!     embeddings::{
+//!         embed::Embedder,
+//!         hyde::{hyde_prompt, TextGenerator},
+//!     },
+//! };
+//! use futures::future::BoxFuture;
+//!
+//! struct Llm;
+//!
+//! impl Llm {
+//!     async fn complete(&self, prompt: &str) -> anyhow::Result<String> {
+//!         // Call the model here.
+//!         Ok(format!("An answer to {}", prompt))
+//!     }
+//! }
+//!
+//! impl TextGenerator for Llm {
+//!     fn generate<'a>(&'a self, query: &'a str) -> BoxFuture<'a, anyhow::Result<String>> {
+//!         Box::pin(async move { self.complete(&hyde_prompt(query)).await })
+//!     }
+//! }
+//!
+//! async fn search(embedder: &Embedder) {
+//!     let embedding = embed_query_hyde("How do rivers form?", &Llm, embedder, None)
+//!         .await
+//!         .unwrap();
+//! }
+//! ```
+
+use futures::future::BoxFuture;
+
+/// Metadata entry holding the query a hypothetical document was written for.
+pub const HYDE_QUERY_KEY: &str = "query";
+
+/// Writes a passage answering a query, for [embed_query_hyde](crate::embed_query_hyde). The
+/// method returns a boxed future so the trait stays object safe.
+pub trait TextGenerator: Send + Sync {
+    /// Generates a hypothetical document answering `query`, see [hyde_prompt] for a prompt.
+    fn generate<'a>(&'a self, query: &'a str) -> BoxFuture<'a, anyhow::Result<String>>;
+}
+
+/// The prompt of the HyDE paper, asking for a passage that answers `query`.
+pub fn hyde_prompt(query: &str) -> String {
+    format!(
+        "Please write a passage to answer the question.\nQuestion: {}\nPassage:",
+        query
+    )
+}
@@ -5,22 +5,28 @@ extern crate intel_mkl_src;
 extern crate accelerate_src;
 
 use std::collections::HashMap;
+use std::path::Path;
 
-use crate::embeddings::embed::EmbeddingResult;
+use crate::embeddings::batch_size::embed_with_oom_fallback;
+use crate::embeddings::embed::{EmbeddingResult, ModelMetadata};
 use crate::embeddings::local::text_embedding::get_model_info_by_hf_id;
-use crate::embeddings::utils::{get_attention_mask, tokenize_batch};
-use crate::embeddings::{normalize_l2, select_device};
+use crate::embeddings::metrics::BatchTimer;
+use crate::embeddings::utils::{
+    add_prefix, check_model_dir, check_vocab_size, default_pad_to_multiple_of, get_attention_mask,
+    hf_api_builder, load_tokenizer, set_pad_to_multiple_of, tokenize_batch, ModelFiles,
+    SentenceTransformersPrompts,
+};
+use crate::embeddings::{normalize_l2, select_device, select_dtype};
 use crate::models::bert::{BertForMaskedLM, BertModel, Config, DTYPE};
 use anyhow::Error as E;
 use candle_core::{DType, Device, Tensor};
 use candle_nn::VarBuilder;
-use hf_hub::api::sync::ApiBuilder;
 use hf_hub::Repo;
 
 use serde::Deserialize;
 use tokenizers::{AddedToken, PaddingParams, Tokenizer, TruncationParams};
 
-use super::pooling::{ModelOutput, Pooling};
+use super::pooling::{AttentionMask, ModelOutput, Pooling, SentenceTransformersPooling};
 
 pub trait BertEmbed {
     fn embed(
@@ -28,6 +34,47 @@ pub trait BertEmbed {
         text_batch: &[String],
         batch_size: Option<usize>,
     ) -> Result<Vec<EmbeddingResult>, anyhow::Error>;
+
+    /// See
+    /// [TextEmbed::embed_query_batch](crate::embeddings::embed::TextEmbed::embed_query_batch).
+    fn embed_query_batch(
+        &self,
+        text_batch: &[String],
+        batch_size: Option<usize>,
+    ) -> Result<Vec<EmbeddingResult>, anyhow::Error> {
+        self.embed(text_batch, batch_size)
+    }
+
+    /// See
+    /// [TextEmbed::embed_document_batch](crate::embeddings::embed::TextEmbed::embed_document_batch).
+    fn embed_document_batch(
+        &self,
+        text_batch: &[String],
+        batch_size: Option<usize>,
+    ) -> Result<Vec<EmbeddingResult>, anyhow::Error> {
+        self.embed(text_batch, batch_size)
+    }
+
+    /// The batch size used when none is given.
+    fn recommended_batch_size(&self) -> usize {
+        32
+    }
+
+    /// Describes the model. Embedders that do not override this report an unknown model.
+    fn model_metadata(&self) -> ModelMetadata {
+        ModelMetadata::default()
+    }
+
+    /// The tokenizer of the model, used to report the texts it truncates. None for embedders
+    /// that don't expose it.
+    fn tokenizer(&self) -> Option<&Tokenizer> {
+        None
+    }
+
+    /// The prefixes added to queries and documents, as `(query, document)`.
+    fn prefixes(&self) -> (Option<&str>, Option<&str>) {
+        (None, None)
+    }
 }
 #[derive(Debug, Deserialize, Clone)]
 pub struct TokenizerConfig {
@@ -52,6 +99,16 @@ pub struct BertEmbedder {
     pub model: BertModel,
     pub pooling: Pooling,
     pub tokenizer: Tokenizer,
+    /// Default batch size of the model, from the model map when the model is listed there.
+    pub batch_size: usize,
+    /// Prepended to queries, e.g. "query: " for E5 models.
+    pub query_prefix: Option<String>,
+    /// Prepended to documents, e.g. "passage: " for E5 models.
+    pub document_prefix: Option<String>,
+    pub metadata: ModelMetadata,
+    /// Return the output of the model for each token along with the pooled vector, see
+    /// [BertEmbedder::with_token_embeddings].
+    pub token_embeddings: bool,
 }
 
 impl Default for BertEmbedder {
@@ -70,32 +127,47 @@ impl BertEmbedder {
         revision: Option<String>,
         token: Option<&str>,
     ) -> Result<Self, E> {
-        let model_info = get_model_info_by_hf_id(&model_id);
-        let pooling = match model_info {
-            Some(info) => info
-                .model
-                .get_default_pooling_method()
-                .unwrap_or(Pooling::Mean),
-            None => Pooling::Mean,
+        let metadata = ModelMetadata::new("bert", &model_id, revision.as_deref());
+        let api = hf_api_builder()
+            .with_token(token.map(|s| s.to_string()))
+            .build()
+            .unwrap();
+        let api = match revision {
+            Some(rev) => api.repo(Repo::with_revision(model_id, hf_hub::RepoType::Model, rev)),
+            None => api.repo(hf_hub::Repo::new(
+                model_id.to_string(),
+                hf_hub::RepoType::Model,
+            )),
         };
+        Self::from_files(&api, metadata)
+    }
 
-        let (config_filename, tokenizer_filename, weights_filename) = {
-            let api = ApiBuilder::new()
-                .with_token(token.map(|s| s.to_string()))
-                .build()
-                .unwrap();
-            let api = match revision {
-                Some(rev) => api.repo(Repo::with_revision(model_id, hf_hub::RepoType::Model, rev)),
-                None => api.repo(hf_hub::Repo::new(
-                    model_id.to_string(),
-                    hf_hub::RepoType::Model,
-                )),
-            };
-            let config = api.get("config.json")?;
-            let tokenizer = api.get("tokenizer.json")?;
-            let weights = match api.get("model.safetensors") {
+    /// Loads a model saved in `dir`, e.g. a fine-tuned model, without contacting the Hugging
+    /// Face Hub. The directory is laid out like a model repository, see [check_model_dir].
+    pub fn from_local_dir(dir: &Path) -> Result<Self, E> {
+        check_model_dir(dir)?;
+        let metadata = ModelMetadata::new("bert", &dir.to_string_lossy(), None);
+        Self::from_files(dir, metadata)
+    }
+
+    fn from_files(api: &(impl ModelFiles + ?Sized), metadata: ModelMetadata) -> Result<Self, E> {
+        let model_info = get_model_info_by_hf_id(&metadata.model_id);
+        let batch_size = model_info.map_or(32, |info| info.model.get_default_batch_size());
+        let (query_prefix, document_prefix) =
+            model_info.map_or((None, None), |info| info.model.get_default_prefixes());
+
+        let (config_filename, tokenizer_filename, weights_filename, pooling, prompts) = {
+            // The pooling declared by sentence-transformers models takes precedence over the
+            // model map.
+            let pooling = SentenceTransformersPooling::from_repo(api)
+                .or_else(|| model_info.and_then(|info| info.model.get_default_pooling_method()))
+                .unwrap_or(Pooling::Mean);
+            let prompts = SentenceTransformersPrompts::from_repo(api);
+            let config = api.file("config.json")?;
+            let tokenizer = api.file("tokenizer.json")?;
+            let weights = match api.file("model.safetensors") {
                 Ok(safetensors) => safetensors,
-                Err(_) => match api.get("pytorch_model.bin") {
+                Err(_) => match api.file("pytorch_model.bin") {
                     Ok(pytorch_model) => pytorch_model,
                     Err(e) => {
                         return Err(anyhow::Error::msg(format!(
@@ -106,11 +178,12 @@ impl BertEmbedder {
                 },
             };
 
-            (config, tokenizer, weights)
+            (config, tokenizer, weights, pooling, prompts)
         };
-        let config = std::fs::read_to_string(config_filename)?;
-        let config: Config = serde_json::from_str(&config)?;
-        let mut tokenizer = Tokenizer::from_file(tokenizer_filename).map_err(E::msg)?;
+        let config_json = std::fs::read_to_string(config_filename)?;
+        let config: Config = serde_json::from_str(&config_json)?;
+        let mut tokenizer = load_tokenizer(tokenizer_filename)?;
+        check_vocab_size(&tokenizer, config.vocab_size, &metadata.model_id)?;
 
         let pp = PaddingParams {
             strategy: tokenizers::PaddingStrategy::BatchLongest,
@@ -128,12 +201,15 @@ impl BertEmbedder {
             .unwrap();
 
         let device = select_device();
+        let dtype = select_dtype(&device, &config_json);
+        // Padding is masked out of the pooling, so it only changes the speed.
+        set_pad_to_multiple_of(&mut tokenizer, default_pad_to_multiple_of(&device));
 
         let vb = if weights_filename.ends_with("model.safetensors") {
-            unsafe { VarBuilder::from_mmaped_safetensors(&[weights_filename], DTYPE, &device)? }
+            unsafe { VarBuilder::from_mmaped_safetensors(&[weights_filename], dtype, &device)? }
         } else {
             println!("Can't find model.safetensors, loading from pytorch_model.bin");
-            VarBuilder::from_pth(&weights_filename, DTYPE, &device)?
+            VarBuilder::from_pth(&weights_filename, dtype, &device)?
         };
 
         let model = BertModel::load(vb, &config)?;
@@ -143,8 +219,50 @@ impl BertEmbedder {
             model,
             tokenizer,
             pooling,
+            batch_size,
+            // Prompts of sentence-transformers models take precedence over the model map.
+            query_prefix: prompts.0.or(query_prefix.map(str::to_string)),
+            document_prefix: prompts.1.or(document_prefix.map(str::to_string)),
+            metadata: metadata.with_dimension(Some(config.hidden_size)),
+            token_embeddings: false,
         })
     }
+
+    /// Overrides the query and document prefixes. Models listed in the model map get the
+    /// prefixes they were trained with by default.
+    pub fn with_prefixes(
+        mut self,
+        query_prefix: Option<&str>,
+        document_prefix: Option<&str>,
+    ) -> Self {
+        self.query_prefix = query_prefix.map(str::to_string);
+        self.document_prefix = document_prefix.map(str::to_string);
+        self
+    }
+
+    /// Returns [EmbeddingResult::DenseWithTokens] instead of dense vectors: the pooled vector of
+    /// each text and the output of the model for each of its tokens, from the same forward
+    /// pass, e.g. to compare pooling strategies or to rerank with late interaction. Padding
+    /// tokens are left out, so the pooled vector is the pooling of the tokens, normalized.
+    ///
+    /// The tokens take as much memory as one vector per token: a chunk of 256 tokens embedded
+    /// with a model of dimension 384 holds 256 * 384 * 4 bytes, about 400 KB, instead of 1.5 KB.
+    pub fn with_token_embeddings(mut self, token_embeddings: bool) -> Self {
+        self.token_embeddings = token_embeddings;
+        self
+    }
+
+    /// Pads batches to a multiple of `multiple` tokens, [GPU_PAD_TO_MULTIPLE_OF] by default,
+    /// which speeds up fp16 inference on GPUs. Has no effect on CPU. None pads batches to their
+    /// longest sequence only.
+    ///
+    /// [GPU_PAD_TO_MULTIPLE_OF]: crate::embeddings::utils::GPU_PAD_TO_MULTIPLE_OF
+    pub fn with_pad_to_multiple_of(mut self, multiple: Option<usize>) -> Self {
+        if !self.model.device.is_cpu() {
+            set_pad_to_multiple_of(&mut self.tokenizer, multiple);
+        }
+        self
+    }
 }
 
 impl BertEmbed for BertEmbedder {
@@ -153,32 +271,93 @@ impl BertEmbed for BertEmbedder {
         text_batch: &[String],
         batch_size: Option<usize>,
     ) -> Result<Vec<EmbeddingResult>, anyhow::Error> {
-        let batch_size = batch_size.unwrap_or(32);
-        let mut encodings: Vec<EmbeddingResult> = Vec::new();
+        let batch_size = batch_size.unwrap_or(self.batch_size);
 
-        for mini_text_batch in text_batch.chunks(batch_size) {
-            let token_ids =
-                tokenize_batch(&self.tokenizer, mini_text_batch, &self.model.device).unwrap();
-            let token_type_ids = token_ids.zeros_like().unwrap();
+        embed_with_oom_fallback(text_batch, batch_size, |mini_text_batch| {
+            let mut timer = BatchTimer::start();
+            let token_ids = tokenize_batch(&self.tokenizer, mini_text_batch, &self.model.device)?;
+            let token_type_ids = token_ids.zeros_like()?;
+            let attention_mask =
+                get_attention_mask(&self.tokenizer, mini_text_batch, &self.model.device)?;
+            timer.tokenized();
+            // Pooled in f32 whatever the dtype of the weights.
             let embeddings: Tensor = self
                 .model
-                .forward(&token_ids, &token_type_ids, None)
-                .unwrap();
+                .forward(&token_ids, &token_type_ids, Some(&attention_mask))?
+                .to_dtype(DType::F32)?;
+            let tokens = if self.token_embeddings {
+                Some(embeddings.to_vec3::<f32>()?)
+            } else {
+                None
+            };
             let pooled_output = self
                 .pooling
-                .pool(&ModelOutput::Tensor(embeddings.clone()))?
+                .pool(
+                    &ModelOutput::Tensor(embeddings.clone()),
+                    &AttentionMask::Tensor(attention_mask.clone()),
+                )?
                 .to_tensor()?;
 
-            let embeddings = normalize_l2(&pooled_output).unwrap();
-            let batch_encodings = embeddings.to_vec2::<f32>().unwrap();
+            let embeddings = normalize_l2(&pooled_output)?;
+            let batch_encodings = embeddings.to_vec2::<f32>()?;
+            timer.finish(mini_text_batch.len(), token_ids.elem_count());
 
-            encodings.extend(
-                batch_encodings
-                    .iter()
-                    .map(|x| EmbeddingResult::DenseVector(x.to_vec())),
-            );
-        }
-        Ok(encodings)
+            let Some(tokens) = tokens else {
+                return Ok(batch_encodings
+                    .into_iter()
+                    .map(EmbeddingResult::DenseVector)
+                    .collect());
+            };
+            let lengths = attention_mask.sum(1)?.to_vec1::<u32>()?;
+            Ok(batch_encodings
+                .into_iter()
+                .zip(tokens)
+                .zip(lengths)
+                .map(|((pooled, mut tokens), length)| {
+                    tokens.truncate(length as usize);
+                    EmbeddingResult::DenseWithTokens { pooled, tokens }
+                })
+                .collect())
+        })
+    }
+
+    fn embed_query_batch(
+        &self,
+        text_batch: &[String],
+        batch_size: Option<usize>,
+    ) -> Result<Vec<EmbeddingResult>, anyhow::Error> {
+        let text_batch = add_prefix(self.query_prefix.as_deref(), text_batch);
+        self.embed(&text_batch, batch_size)
+    }
+
+    fn embed_document_batch(
+        &self,
+        text_batch: &[String],
+        batch_size: Option<usize>,
+    ) -> Result<Vec<EmbeddingResult>, anyhow::Error> {
+        let text_batch = add_prefix(self.document_prefix.as_deref(), text_batch);
+        self.embed(&text_batch, batch_size)
+    }
+
+    fn recommended_batch_size(&self) -> usize {
+        self.batch_size
+    }
+
+    fn model_metadata(&self) -> ModelMetadata {
+        self.metadata
+            .clone()
+            .with_pooling(Some(self.pooling.clone()))
+    }
+
+    fn tokenizer(&self) -> Option<&Tokenizer> {
+        Some(&self.tokenizer)
+    }
+
+    fn prefixes(&self) -> (Option<&str>, Option<&str>) {
+        (
+            self.query_prefix.as_deref(),
+            self.document_prefix.as_deref(),
+        )
     }
 }
 
@@ -187,12 +366,14 @@ pub struct SparseBertEmbedder {
     pub model: BertForMaskedLM,
     pub device: Device,
     pub dtype: DType,
+    pub metadata: ModelMetadata,
 }
 
 impl SparseBertEmbedder {
     pub fn new(model_id: String, revision: Option<String>, token: Option<&str>) -> Result<Self, E> {
+        let metadata = ModelMetadata::new("sparse-bert", &model_id, revision.as_deref());
         let (config_filename, tokenizer_filename, weights_filename) = {
-            let api = ApiBuilder::new()
+            let api = hf_api_builder()
                 .with_token(token.map(|s| s.to_string()))
                 .build()
                 .unwrap();
@@ -222,7 +403,8 @@ impl SparseBertEmbedder {
         };
         let config = std::fs::read_to_string(config_filename)?;
         let config: Config = serde_json::from_str(&config)?;
-        let mut tokenizer = Tokenizer::from_file(tokenizer_filename).map_err(E::msg)?;
+        let mut tokenizer = load_tokenizer(tokenizer_filename)?;
+        check_vocab_size(&tokenizer, config.vocab_size, &metadata.model_id)?;
 
         let pp = PaddingParams {
             strategy: tokenizers::PaddingStrategy::BatchLongest,
@@ -240,6 +422,8 @@ impl SparseBertEmbedder {
             .unwrap();
 
         let device = select_device();
+        // Padding is masked out of the sparse vectors, so it only changes the speed.
+        set_pad_to_multiple_of(&mut tokenizer, default_pad_to_multiple_of(&device));
         let vb = if weights_filename.ends_with("model.safetensors") {
             unsafe { VarBuilder::from_mmaped_safetensors(&[weights_filename], DTYPE, &device)? }
         } else {
@@ -253,8 +437,22 @@ impl SparseBertEmbedder {
             tokenizer,
             device,
             dtype: DTYPE,
+            // Sparse vectors have one weight per token of the vocabulary.
+            metadata: metadata.with_dimension(Some(config.vocab_size)),
         })
     }
+
+    /// Pads batches to a multiple of `multiple` tokens, [GPU_PAD_TO_MULTIPLE_OF] by default,
+    /// which speeds up fp16 inference on GPUs. Has no effect on CPU. None pads batches to their
+    /// longest sequence only.
+    ///
+    /// [GPU_PAD_TO_MULTIPLE_OF]: crate::embeddings::utils::GPU_PAD_TO_MULTIPLE_OF
+    pub fn with_pad_to_multiple_of(mut self, multiple: Option<usize>) -> Self {
+        if !self.device.is_cpu() {
+            set_pad_to_multiple_of(&mut self.tokenizer, multiple);
+        }
+        self
+    }
 }
 
 impl BertEmbed for SparseBertEmbedder {
@@ -264,17 +462,17 @@ impl BertEmbed for SparseBertEmbedder {
         batch_size: Option<usize>,
     ) -> Result<Vec<EmbeddingResult>, anyhow::Error> {
         let batch_size = batch_size.unwrap_or(32);
-        let mut encodings: Vec<EmbeddingResult> = Vec::new();
 
-        for mini_text_batch in text_batch.chunks(batch_size) {
-            let token_ids = tokenize_batch(&self.tokenizer, mini_text_batch, &self.device).unwrap();
-            let token_type_ids = token_ids.zeros_like().unwrap();
-            let embeddings: Tensor = self
-                .model
-                .forward(&token_ids, &token_type_ids, None)
-                .unwrap();
+        embed_with_oom_fallback(text_batch, batch_size, |mini_text_batch| {
+            let mut timer = BatchTimer::start();
+            let token_ids = tokenize_batch(&self.tokenizer, mini_text_batch, &self.device)?;
+            let token_type_ids = token_ids.zeros_like()?;
             let attention_mask =
-                get_attention_mask(&self.tokenizer, mini_text_batch, &self.device).unwrap();
+                get_attention_mask(&self.tokenizer, mini_text_batch, &self.device)?;
+            timer.tokenized();
+            let embeddings: Tensor =
+                self.model
+                    .forward(&token_ids, &token_type_ids, Some(&attention_mask))?;
 
             let batch_encodings = Tensor::log(
                 &Tensor::try_from(1.0)?
@@ -286,15 +484,203 @@ impl BertEmbed for SparseBertEmbedder {
             let batch_encodings = batch_encodings
                 .broadcast_mul(&attention_mask.unsqueeze(2)?.to_dtype(self.dtype)?)?
                 .max(1)?;
-            let batch_encodings = normalize_l2(&batch_encodings)?;
+            let batch_encodings = normalize_l2(&batch_encodings)?.to_vec2::<f32>()?;
+            timer.finish(mini_text_batch.len(), token_ids.elem_count());
 
-            encodings.extend(
-                batch_encodings
-                    .to_vec2::<f32>()?
-                    .into_iter()
-                    .map(|x| EmbeddingResult::DenseVector(x.to_vec())),
-            );
+            Ok(batch_encodings
+                .into_iter()
+                .map(EmbeddingResult::DenseVector)
+                .collect())
+        })
+    }
+
+    fn model_metadata(&self) -> ModelMetadata {
+        self.metadata.clone()
+    }
+
+    fn tokenizer(&self) -> Option<&Tokenizer> {
+        Some(&self.tokenizer)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_vocab_mismatch() {
+        // The config of a revision with a smaller vocabulary than the tokenizer. The weights are
+        // never read.
+        let api = hf_api_builder()
+            .build()
+            .unwrap()
+            .model("sentence-transformers/all-MiniLM-L6-v2".to_string());
+        let dir = tempfile::tempdir().unwrap();
+        let config = std::fs::read_to_string(api.get("config.json").unwrap()).unwrap();
+        let mut config: serde_json::Value = serde_json::from_str(&config).unwrap();
+        config["vocab_size"] = 30000.into();
+        std::fs::write(dir.path().join("config.json"), config.to_string()).unwrap();
+        std::fs::copy(
+            api.get("tokenizer.json").unwrap(),
+            dir.path().join("tokenizer.json"),
+        )
+        .unwrap();
+        std::fs::write(dir.path().join("model.safetensors"), []).unwrap();
+
+        let error = BertEmbedder::from_local_dir(dir.path()).err().unwrap();
+        assert!(error
+            .to_string()
+            .contains("token ids up to 30521, but the model only has embeddings for 30000 tokens"));
+    }
+
+    #[test]
+    fn test_custom_pooling_receives_padding_mask() {
+        let mut embedder = BertEmbedder::new(
+            "sentence-transformers/all-MiniLM-L6-v2".to_string(),
+            None,
+            None,
+        )
+        .unwrap();
+        let masks = std::sync::Arc::new(std::sync::Mutex::new(Vec::new()));
+        let received = masks.clone();
+        embedder.pooling = Pooling::custom(move |output, mask| {
+            let ModelOutput::Tensor(tensor) = output else {
+                anyhow::bail!("expected a tensor");
+            };
+            received
+                .lock()
+                .unwrap()
+                .push(mask.to_tensor()?.to_vec2::<u32>()?);
+            Ok(tensor.mean(1)?)
+        });
+        let texts = vec![
+            "Short".to_string(),
+            "A longer text that the short one is padded to".to_string(),
+        ];
+        embedder.embed(&texts, Some(2)).unwrap();
+
+        let lengths = texts
+            .iter()
+            .map(|text| {
+                embedder
+                    .tokenizer
+                    .encode(text.as_str(), true)
+                    .unwrap()
+                    .len()
+            })
+            .collect::<Vec<_>>();
+        let expected = lengths
+            .iter()
+            .map(|&length| (0..lengths[1]).map(|i| u32::from(i < length)).collect())
+            .collect::<Vec<Vec<u32>>>();
+        assert!(lengths[0] < lengths[1]);
+        assert_eq!(*masks.lock().unwrap(), vec![expected]);
+    }
+
+    #[test]
+    fn test_token_embeddings() {
+        let embedder = BertEmbedder::new(
+            "sentence-transformers/all-MiniLM-L6-v2".to_string(),
+            None,
+            None,
+        )
+        .unwrap();
+        // One batch, in which the shorter text is padded.
+        let texts = vec![
+            "The cat sat on the mat by the door of the house.".to_string(),
+            "Embeddings for every token".to_string(),
+        ];
+        let dense = embedder.embed(&texts, Some(2)).unwrap();
+        let embedder = embedder.with_token_embeddings(true);
+        let embeddings = embedder.embed(&texts, Some(2)).unwrap();
+
+        for ((embedding, dense), text) in embeddings.iter().zip(&dense).zip(&texts) {
+            let EmbeddingResult::DenseWithTokens { pooled, tokens } = embedding else {
+                panic!("Expected a vector with its tokens, got {:?}", embedding);
+            };
+            assert_eq!(pooled, &dense.to_dense().unwrap());
+            let encoding = embedder.tokenizer.encode(text.as_str(), true).unwrap();
+            assert_eq!(tokens.len(), encoding.len());
+
+            // The pooling of the model over the returned tokens, normalized like `pooled`.
+            let shape = (1, tokens.len(), pooled.len());
+            let output = Tensor::from_vec(tokens.concat(), shape, &Device::Cpu).unwrap();
+            let mask = Tensor::ones((1, tokens.len()), DType::U32, &Device::Cpu).unwrap();
+            let expected = embedder
+                .pooling
+                .pool(&ModelOutput::Tensor(output), &AttentionMask::Tensor(mask))
+                .unwrap()
+                .to_tensor()
+                .unwrap();
+            let expected = normalize_l2(&expected).unwrap().to_vec2::<f32>().unwrap();
+            for (value, expected) in pooled.iter().zip(&expected[0]) {
+                assert!((value - expected).abs() < 1e-5);
+            }
         }
-        Ok(encodings)
+    }
+
+    #[test]
+    fn test_pooling_from_sentence_transformers_config() {
+        // Declares CLS pooling in 1_Pooling/config.json and is not in the model map.
+        let embedder = BertEmbedder::new(
+            "sentence-transformers/multi-qa-MiniLM-L6-cos-v1".to_string(),
+            None,
+            None,
+        )
+        .unwrap();
+        assert!(matches!(embedder.pooling, Pooling::Cls));
+
+        let embedder = BertEmbedder::new(
+            "sentence-transformers/all-MiniLM-L6-v2".to_string(),
+            None,
+            None,
+        )
+        .unwrap();
+        assert!(matches!(embedder.pooling, Pooling::Mean));
+    }
+
+    #[test]
+    fn test_prompts_from_sentence_transformers_config() {
+        // Defines a query prompt in config_sentence_transformers.json.
+        let embedder = BertEmbedder::new(
+            "Snowflake/snowflake-arctic-embed-xs".to_string(),
+            None,
+            None,
+        )
+        .unwrap();
+        assert_eq!(
+            embedder.query_prefix.as_deref(),
+            Some("Represent this sentence for searching relevant passages: ")
+        );
+        assert_eq!(embedder.document_prefix, None);
+
+        let embedder = embedder.with_prefixes(Some("query: "), None);
+        assert_eq!(embedder.query_prefix.as_deref(), Some("query: "));
+    }
+
+    #[test]
+    fn test_arctic_embed() {
+        let embedder =
+            BertEmbedder::new("Snowflake/snowflake-arctic-embed-m".to_string(), None, None)
+                .unwrap();
+        assert!(matches!(embedder.pooling, Pooling::Cls));
+        assert_eq!(embedder.metadata.dimension, Some(768));
+        assert_eq!(embedder.batch_size, 32);
+        let prefix = "Represent this sentence for searching relevant passages: ";
+        assert_eq!(embedder.query_prefix.as_deref(), Some(prefix));
+        assert_eq!(embedder.document_prefix, None);
+
+        let text = vec!["What is an embedding?".to_string()];
+        let prefixed = vec![format!("{}{}", prefix, text[0])];
+        let query = embedder.embed_query_batch(&text, None).unwrap();
+        let document = embedder.embed_document_batch(&text, None).unwrap();
+        let plain = embedder.embed(&text, None).unwrap()[0].to_dense().unwrap();
+        let with_prefix = embedder.embed(&prefixed, None).unwrap()[0]
+            .to_dense()
+            .unwrap();
+        assert_eq!(plain.len(), 768);
+        assert_eq!(query[0].to_dense().unwrap(), with_prefix);
+        assert_eq!(document[0].to_dense().unwrap(), plain);
+        assert_ne!(plain, with_prefix);
     }
 }
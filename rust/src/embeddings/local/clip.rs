@@ -9,20 +9,53 @@ use std::{collections::HashMap, fs};
 use anyhow::Error as E;
 
 use crate::{
-    embeddings::{embed::EmbeddingResult, select_device},
+    embeddings::{
+        embed::EmbeddingResult,
+        select_device,
+        utils::{check_model_dir, hf_api_builder, ModelFiles},
+    },
     models::clip::{self, ClipConfig},
 };
 use candle_core::{DType, Device, Tensor};
 
 use candle_nn::VarBuilder;
+use rayon::prelude::*;
 use tokenizers::Tokenizer;
 
-use crate::embeddings::embed::{EmbedData, EmbedImage};
+use crate::embeddings::embed::{EmbedData, EmbedImage, ModelMetadata};
+
+/// A region of an image, in pixels from the top left corner.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct BBox {
+    pub x: u32,
+    pub y: u32,
+    pub width: u32,
+    pub height: u32,
+}
+
+impl BBox {
+    pub fn new(x: u32, y: u32, width: u32, height: u32) -> Self {
+        Self {
+            x,
+            y,
+            width,
+            height,
+        }
+    }
+}
+
+impl std::fmt::Display for BBox {
+    /// Formats the region as `x,y,width,height`, the format of the `bbox` metadata.
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{},{},{},{}", self.x, self.y, self.width, self.height)
+    }
+}
 
 pub struct ClipEmbedder {
     pub model: clip::ClipModel,
     pub tokenizer: Tokenizer,
     pub device: Device,
+    pub metadata: ModelMetadata,
 }
 impl Default for ClipEmbedder {
     fn default() -> Self {
@@ -37,7 +70,7 @@ impl Default for ClipEmbedder {
 
 impl ClipEmbedder {
     pub fn new(model_id: String, revision: Option<&str>, token: Option<&str>) -> Result<Self, E> {
-        let api = hf_hub::api::sync::ApiBuilder::new()
+        let api = hf_api_builder()
             .with_token(token.map(|s| s.to_string()))
             .build()?;
 
@@ -52,14 +85,33 @@ impl ClipEmbedder {
                 hf_hub::RepoType::Model,
             )),
         };
+        let metadata = ModelMetadata::new("clip", &model_id, revision);
+        Self::from_files(&api, Self::get_tokenizer(None)?, metadata)
+    }
+
+    /// Loads a model saved in `dir` without contacting the Hugging Face Hub, see
+    /// [BertEmbedder::from_local_dir](super::bert::BertEmbedder::from_local_dir). Unlike
+    /// [ClipEmbedder::new], the tokenizer is read from the directory too.
+    pub fn from_local_dir(dir: &std::path::Path) -> Result<Self, E> {
+        check_model_dir(dir)?;
+        let tokenizer = dir.join("tokenizer.json").to_string_lossy().to_string();
+        let tokenizer = Self::get_tokenizer(Some(tokenizer))?;
+        let metadata = ModelMetadata::new("clip", &dir.to_string_lossy(), None);
+        Self::from_files(dir, tokenizer, metadata)
+    }
 
+    fn from_files(
+        api: &(impl ModelFiles + ?Sized),
+        tokenizer: Tokenizer,
+        metadata: ModelMetadata,
+    ) -> Result<Self, E> {
         let device = select_device();
 
-        let vb = match api.get("model.safetensors") {
+        let vb = match api.file("model.safetensors") {
             Ok(safetensors) => unsafe {
                 VarBuilder::from_mmaped_safetensors(&[safetensors], DType::F32, &device)?
             },
-            Err(_) => match api.get("pytorch_model.bin") {
+            Err(_) => match api.file("pytorch_model.bin") {
                 Ok(pytorch_model) => VarBuilder::from_pth(pytorch_model, DType::F32, &device)?,
                 Err(e) => {
                     return Err(anyhow::Error::msg(format!(
@@ -69,24 +121,25 @@ impl ClipEmbedder {
                 }
             },
         };
-        let config_filename = api.get("config.json")?;
+        let config_filename = api.file("config.json")?;
 
         let config: String = std::fs::read_to_string(config_filename)?;
         let config: ClipConfig = serde_json::from_str(&config)?;
         let model = clip::ClipModel::new(vb, &config)?;
 
-        let tokenizer = Self::get_tokenizer(None)?;
+        let metadata = metadata.with_dimension(Some(config.text_config.projection_dim));
         Ok(ClipEmbedder {
             model,
             tokenizer,
             device,
+            metadata,
         })
     }
 
     pub fn get_tokenizer(tokenizer: Option<String>) -> anyhow::Result<Tokenizer> {
         let tokenizer = match tokenizer {
             None => {
-                let api = hf_hub::api::sync::Api::new()?;
+                let api = hf_api_builder().build()?;
                 let api = api.repo(hf_hub::Repo::with_revision(
                     "openai/clip-vit-base-patch32".to_string(),
                     hf_hub::RepoType::Model,
@@ -147,21 +200,20 @@ impl ClipEmbedder {
         image_size: usize,
     ) -> anyhow::Result<Tensor> {
         let img = image::ImageReader::open(path)?.decode()?;
-        let (height, width) = (image_size, image_size);
-        let img = img.resize_to_fill(
-            width as u32,
-            height as u32,
-            image::imageops::FilterType::Triangle,
-        );
+        self.preprocess(img, image_size)
+    }
 
-        let img = img.to_rgb8();
+    /// Resizes an image to `image_size` and scales its pixels to [-1, 1].
+    fn preprocess(&self, img: image::DynamicImage, image_size: usize) -> anyhow::Result<Tensor> {
+        self.pixels_to_tensor(resize_image(img, image_size), image_size)
+    }
 
-        let img = img.into_raw();
-        let img = Tensor::from_vec(img, (height, width, 3), &self.device)?
+    /// Converts the RGB pixels returned by [resize_image] to the input of the vision model.
+    fn pixels_to_tensor(&self, pixels: Vec<u8>, image_size: usize) -> anyhow::Result<Tensor> {
+        let img = Tensor::from_vec(pixels, (image_size, image_size, 3), &self.device)?
             .permute((2, 0, 1))?
             .to_dtype(DType::F32)?
             .affine(2. / 255., -1.)?;
-        // .unsqueeze(0)?;
         Ok(img)
     }
 
@@ -212,6 +264,108 @@ impl ClipEmbedder {
 
         Ok(encodings)
     }
+
+    /// Embeds regions of an image, such as the figures and tables found by a layout model,
+    /// instead of the whole image. Returns one [EmbedData] per region, in order, with the
+    /// region in the `bbox` metadata as `x,y,width,height`. Each region is cropped and then
+    /// preprocessed like a whole image.
+    pub fn embed_image_regions<T: AsRef<std::path::Path>>(
+        &self,
+        image_path: T,
+        regions: Vec<BBox>,
+    ) -> anyhow::Result<Vec<EmbedData>> {
+        let config = clip::ClipConfig::vit_base_patch32();
+        let img = image::ImageReader::open(&image_path)?.decode()?;
+        let file_name = fs::canonicalize(&image_path)?.to_string_lossy().to_string();
+
+        let mut crops = Vec::with_capacity(regions.len());
+        for region in &regions {
+            let fits = region.width > 0
+                && region.height > 0
+                && region
+                    .x
+                    .checked_add(region.width)
+                    .is_some_and(|right| right <= img.width())
+                && region
+                    .y
+                    .checked_add(region.height)
+                    .is_some_and(|bottom| bottom <= img.height());
+            if !fits {
+                return Err(anyhow::anyhow!(
+                    "Region {} is outside of the {}x{} image",
+                    region,
+                    img.width(),
+                    img.height()
+                ));
+            }
+            let crop = img.crop_imm(region.x, region.y, region.width, region.height);
+            crops.push(self.preprocess(crop, config.vision_config.image_size)?);
+        }
+
+        let mut encodings = Vec::with_capacity(crops.len());
+        for batch in crops.chunks(32) {
+            let images = Tensor::stack(batch, 0)?;
+            encodings.extend(self.model.get_image_features(&images)?.to_vec2::<f32>()?);
+        }
+
+        Ok(encodings
+            .into_iter()
+            .zip(regions)
+            .map(|(encoding, region)| {
+                let metadata = HashMap::from([
+                    ("file_name".to_string(), file_name.clone()),
+                    ("bbox".to_string(), region.to_string()),
+                ]);
+                EmbedData::new(EmbeddingResult::DenseVector(encoding), None, Some(metadata))
+            })
+            .collect())
+    }
+}
+
+/// Decodes an image and resizes it to `image_size`, returning its RGB pixels. This is the CPU
+/// bound part of the preprocessing, which [ClipEmbedder::embed_image_batch_pipelined] runs on
+/// several threads.
+fn resize_image(img: image::DynamicImage, image_size: usize) -> Vec<u8> {
+    img.resize_to_fill(
+        image_size as u32,
+        image_size as u32,
+        image::imageops::FilterType::Triangle,
+    )
+    .to_rgb8()
+    .into_raw()
+}
+
+fn decode_image<T: AsRef<std::path::Path>>(path: T, image_size: usize) -> anyhow::Result<Vec<u8>> {
+    let img = image::ImageReader::open(path)?.decode()?;
+    Ok(resize_image(img, image_size))
+}
+
+/// Pairs the embeddings of a batch of images with their paths.
+fn image_embed_data<T: AsRef<std::path::Path>>(
+    encodings: Vec<Vec<f32>>,
+    image_paths: &[T],
+) -> Vec<EmbedData> {
+    encodings
+        .into_iter()
+        .zip(image_paths)
+        .map(|(data, path)| {
+            let mut metadata = HashMap::new();
+            metadata.insert(
+                "file_name".to_string(),
+                fs::canonicalize(path)
+                    .unwrap()
+                    .to_str()
+                    .unwrap()
+                    .to_string(),
+            );
+
+            EmbedData::new(
+                EmbeddingResult::DenseVector(data),
+                Some(path.as_ref().to_str().unwrap().to_string()),
+                Some(metadata),
+            )
+        })
+        .collect::<Vec<_>>()
 }
 
 impl EmbedImage for ClipEmbedder {
@@ -235,28 +389,62 @@ impl EmbedImage for ClipEmbedder {
             encodings.extend(batch_encodings);
         }
 
-        let embeddings = encodings
-            .iter()
-            .zip(image_paths)
-            .map(|(data, path)| {
-                let mut metadata = HashMap::new();
-                metadata.insert(
-                    "file_name".to_string(),
-                    fs::canonicalize(path)
-                        .unwrap()
-                        .to_str()
-                        .unwrap()
-                        .to_string(),
-                );
-
-                EmbedData::new(
-                    EmbeddingResult::DenseVector(data.to_vec()),
-                    Some(path.as_ref().to_str().unwrap().to_string()),
-                    Some(metadata),
-                )
-            })
-            .collect::<Vec<_>>();
-        Ok(embeddings)
+        Ok(image_embed_data(encodings, image_paths))
+    }
+
+    /// Decodes the images on a rayon pool of `decode_threads` threads, while the model embeds
+    /// the images decoded so far in batches of 32. The channel between them holds at most
+    /// `buffer_size` images, so decoding runs ahead of inference by at most that many images.
+    fn embed_image_batch_pipelined<T: AsRef<std::path::Path> + Sync>(
+        &self,
+        image_paths: &[T],
+        decode_threads: Option<usize>,
+        buffer_size: usize,
+    ) -> anyhow::Result<Vec<EmbedData>> {
+        let image_size = clip::ClipConfig::vit_base_patch32()
+            .vision_config
+            .image_size;
+        let mut pool = rayon::ThreadPoolBuilder::new();
+        if let Some(decode_threads) = decode_threads {
+            pool = pool.num_threads(decode_threads);
+        }
+        let pool = pool.build()?;
+        let (tx, rx) = std::sync::mpsc::sync_channel(buffer_size.max(1));
+
+        let mut encodings = vec![Vec::new(); image_paths.len()];
+        std::thread::scope(|scope| -> anyhow::Result<()> {
+            scope.spawn(move || {
+                pool.install(|| {
+                    image_paths
+                        .par_iter()
+                        .enumerate()
+                        .for_each_with(tx, |tx, (i, path)| {
+                            // Fails once the model stopped on an error, the rest is skipped.
+                            let _ = tx.send((i, decode_image(path, image_size)));
+                        })
+                })
+            });
+
+            let mut indices = Vec::with_capacity(32);
+            let mut images = Vec::with_capacity(32);
+            let mut received = rx.into_iter().peekable();
+            while let Some((i, pixels)) = received.next() {
+                indices.push(i);
+                images.push(self.pixels_to_tensor(pixels?, image_size)?);
+                if images.len() == 32 || received.peek().is_none() {
+                    let batch = Tensor::stack(&images, 0)?;
+                    let batch_encodings =
+                        self.model.get_image_features(&batch)?.to_vec2::<f32>()?;
+                    for (i, encoding) in indices.drain(..).zip(batch_encodings) {
+                        encodings[i] = encoding;
+                    }
+                    images.clear();
+                }
+            }
+            Ok(())
+        })?;
+
+        Ok(image_embed_data(encodings, image_paths))
     }
 
     fn embed_image<T: AsRef<std::path::Path>>(
@@ -282,6 +470,10 @@ impl EmbedImage for ClipEmbedder {
             metadata.clone(),
         ))
     }
+
+    fn model_metadata(&self) -> ModelMetadata {
+        self.metadata.clone()
+    }
 }
 
 #[cfg(test)]
@@ -341,4 +533,60 @@ mod tests {
             .unwrap();
         assert_eq!(embeddings.len(), 2);
     }
+
+    #[test]
+    fn test_embed_image_batch_pipelined() {
+        let clip_embedder = ClipEmbedder::default();
+        let paths = [
+            "../test_files/clip/cat1.jpg",
+            "../test_files/clip/dog1.jpg",
+            "../test_files/clip/monkey1.jpg",
+        ];
+        let serial = clip_embedder.embed_image_batch(&paths).unwrap();
+        let pipelined = clip_embedder
+            .embed_image_batch_pipelined(&paths, Some(2), 1)
+            .unwrap();
+        assert_eq!(pipelined.len(), paths.len());
+        for (serial, pipelined) in serial.iter().zip(&pipelined) {
+            assert_eq!(serial.text, pipelined.text);
+            let serial = serial.embedding.to_dense().unwrap();
+            let pipelined = pipelined.embedding.to_dense().unwrap();
+            assert!(serial
+                .iter()
+                .zip(&pipelined)
+                .all(|(a, b)| (a - b).abs() < 1e-5));
+        }
+
+        let missing = ["../test_files/clip/missing.jpg"];
+        assert!(clip_embedder
+            .embed_image_batch_pipelined(&missing, None, 4)
+            .is_err());
+    }
+
+    #[test]
+    fn test_embed_image_regions() {
+        let clip_embedder = ClipEmbedder::default();
+        let path = "../test_files/clip/cat1.jpg";
+        let (width, height) = image::image_dimensions(path).unwrap();
+        let regions = vec![
+            BBox::new(0, 0, width / 2, height / 2),
+            BBox::new(width / 2, height / 2, width / 2, height / 2),
+        ];
+        let embeddings = clip_embedder
+            .embed_image_regions(path, regions.clone())
+            .unwrap();
+        assert_eq!(embeddings.len(), 2);
+        for (embedding, region) in embeddings.iter().zip(&regions) {
+            let metadata = embedding.metadata.as_ref().unwrap();
+            assert_eq!(metadata["bbox"], region.to_string());
+            assert!(metadata["file_name"].ends_with("cat1.jpg"));
+        }
+        let first = embeddings[0].embedding.to_dense().unwrap();
+        let second = embeddings[1].embedding.to_dense().unwrap();
+        assert_eq!(first.len(), second.len());
+        assert!(first.iter().zip(&second).any(|(a, b)| (a - b).abs() > 1e-4));
+
+        let outside = vec![BBox::new(width - 1, 0, 2, 2)];
+        assert!(clip_embedder.embed_image_regions(path, outside).is_err());
+    }
 }
@@ -7,22 +7,22 @@ extern crate accelerate_src;
 use std::ops::Mul;
 
 use anyhow::{Error as E, Result};
-use hf_hub::{api::sync::Api, Repo};
+use hf_hub::Repo;
 use ndarray::{Array2, Array3, Axis};
 use ort::{
-    execution_providers::{CUDAExecutionProvider, CoreMLExecutionProvider, ExecutionProvider},
-    session::{builder::GraphOptimizationLevel, Session},
+    execution_providers::{CUDAExecutionProvider, ExecutionProvider},
     value::Value,
 };
 use rayon::{iter::ParallelIterator, slice::ParallelSlice};
 use tokenizers::{PaddingParams, Tokenizer, TruncationParams};
 
 use crate::embeddings::{
-        embed::EmbeddingResult,
-        utils::{get_attention_mask_ndarray, tokenize_batch_ndarray},
+        embed::{EmbeddingResult, ModelMetadata},
+        utils::{get_attention_mask_ndarray, hf_api_builder, load_tokenizer, tokenize_batch_ndarray},
     };
 
 use super::bert::{BertEmbed, TokenizerConfig};
+use super::session_pool::SessionPool;
 
 pub trait ColbertEmbed {
     fn embed(
@@ -36,11 +36,12 @@ pub trait ColbertEmbed {
 #[derive(Debug)]
 pub struct OrtColbertEmbedder {
     pub tokenizer: Tokenizer,
-    pub model: Session,
+    pub model: SessionPool,
     pub document_marker_token_id: Option<i64>,
     pub query_marker_token_id: Option<i64>,
     pub pad_id: Option<i64>,
     pub mask_token: Option<String>,
+    pub metadata: ModelMetadata,
 }
 
 impl OrtColbertEmbedder {
@@ -56,7 +57,7 @@ impl OrtColbertEmbedder {
         };
 
         let (_, tokenizer_filename, weights_filename, tokenizer_config_filename, data_filename) = {
-            let api = Api::new().unwrap();
+            let api = hf_api_builder().build().unwrap();
             let api = match revision {
                 Some(rev) => api.repo(Repo::with_revision(
                     hf_model_id.to_string(),
@@ -106,7 +107,7 @@ impl OrtColbertEmbedder {
             (None, None) => 128,
         };
 
-        let mut tokenizer = Tokenizer::from_file(tokenizer_filename).map_err(E::msg)?;
+        let mut tokenizer = load_tokenizer(tokenizer_filename)?;
         let mask_token = tokenizer_config.clone().mask_token;
         let pad_id = match mask_token.clone() {
             Some(mask_token) => tokenizer_config.get_token_id_from_token(&mask_token),
@@ -136,15 +137,7 @@ impl OrtColbertEmbedder {
             println!("Session is using CUDAExecutionProvider");
         }
 
-        let threads = std::thread::available_parallelism().unwrap().get();
-        let model = Session::builder()?
-            .with_execution_providers([
-                CUDAExecutionProvider::default().build(),
-                CoreMLExecutionProvider::default().build(),
-            ])?
-            .with_optimization_level(GraphOptimizationLevel::Level3)?
-            .with_intra_threads(threads)?
-            .commit_from_file(weights_filename)?;
+        let model = SessionPool::load(&weights_filename)?;
 
         Ok(OrtColbertEmbedder {
             tokenizer,
@@ -153,6 +146,7 @@ impl OrtColbertEmbedder {
             query_marker_token_id,
             pad_id,
             mask_token,
+            metadata: ModelMetadata::new("colbert", hf_model_id, revision),
         })
     }
 }
@@ -217,7 +211,7 @@ impl ColbertEmbed for OrtColbertEmbedder {
                         Value::from_array(token_type_ids.clone())?.into(),
                     ));
                 }
-                let outputs = self.model.run(inputs)?;
+                let outputs = self.model.session().run(inputs)?;
                 let embeddings: Array3<f32> = outputs
                     [self.model.outputs.first().unwrap().name.as_str()]
                 .try_extract_tensor::<f32>()?
@@ -287,7 +281,7 @@ impl BertEmbed for OrtColbertEmbedder {
                         Value::from_array(token_type_ids.clone())?.into(),
                     ));
                 }
-                let outputs = self.model.run(inputs)?;
+                let outputs = self.model.session().run(inputs)?;
                 let embeddings: Array3<f32> = outputs
                     [self.model.outputs.first().unwrap().name.as_str()]
                 .try_extract_tensor::<f32>()?
@@ -324,4 +318,12 @@ impl BertEmbed for OrtColbertEmbedder {
 
         Ok(encodings)
     }
+
+    fn model_metadata(&self) -> ModelMetadata {
+        self.metadata.clone()
+    }
+
+    fn tokenizer(&self) -> Option<&Tokenizer> {
+        Some(&self.tokenizer)
+    }
 }
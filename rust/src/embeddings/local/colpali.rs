@@ -2,8 +2,9 @@ use std::path::PathBuf;
 use std::sync::RwLock;
 use std::{collections::HashMap, path::Path};
 
-use crate::embeddings::embed::{EmbedData, EmbeddingResult};
+use crate::embeddings::embed::{EmbedData, EmbeddingResult, ModelMetadata};
 use crate::embeddings::select_device;
+use crate::embeddings::utils::hf_api_builder;
 use crate::models::{colpali::Model, paligemma};
 use anyhow::Error as E;
 use base64::Engine;
@@ -30,8 +31,16 @@ pub trait ColPaliEmbed {
     ) -> anyhow::Result<EmbedData>;
 
     fn embed_image_batch(&self, image_paths: &[PathBuf]) -> anyhow::Result<Vec<EmbedData>>;
+
+    /// Describes the model. Embedders that do not override this report an unknown model.
+    fn model_metadata(&self) -> ModelMetadata {
+        ModelMetadata::default()
+    }
 }
 
+/// Size of every vector of ColPali embeddings, set by its text projection.
+pub(crate) const COLPALI_DIMENSION: usize = 128;
+
 pub struct ColPaliEmbedder {
     pub model: RwLock<Model>,
     pub tokenizer: Tokenizer,
@@ -39,11 +48,12 @@ pub struct ColPaliEmbedder {
     pub device: Device,
     dtype: DType,
     dummy_input: Tensor,
+    metadata: ModelMetadata,
 }
 
 impl ColPaliEmbedder {
     pub fn new(model_id: &str, revision: Option<&str>) -> Result<Self, anyhow::Error> {
-        let api = hf_hub::api::sync::Api::new()?;
+        let api = hf_api_builder().build()?;
         let repo: hf_hub::api::sync::ApiRepo = match revision {
             Some(rev) => api.repo(hf_hub::Repo::with_revision(
                 model_id.to_string(),
@@ -109,6 +119,8 @@ impl ColPaliEmbedder {
             device,
             dtype,
             dummy_input,
+            metadata: ModelMetadata::new("colpali", model_id, revision)
+                .with_dimension(Some(COLPALI_DIMENSION)),
         })
     }
 
@@ -283,6 +295,10 @@ impl ColPaliEmbed for ColPaliEmbedder {
         }
         Ok(embed_data)
     }
+
+    fn model_metadata(&self) -> ModelMetadata {
+        self.metadata.clone()
+    }
 }
 
 fn tokenize_batch(
@@ -12,9 +12,10 @@ use ort::session::Session;
 use rayon::prelude::*;
 use tokenizers::{PaddingParams, Tokenizer, TruncationParams};
 
-use crate::embeddings::embed::{EmbedData, EmbeddingResult};
+use crate::embeddings::embed::{EmbedData, EmbeddingResult, ModelMetadata};
+use crate::embeddings::utils::hf_api_builder;
 
-use super::colpali::{get_images_from_pdf, ColPaliEmbed};
+use super::colpali::{get_images_from_pdf, ColPaliEmbed, COLPALI_DIMENSION};
 
 pub struct OrtColPaliEmbedder {
     pub model: Session,
@@ -22,11 +23,12 @@ pub struct OrtColPaliEmbedder {
     pub image_size: usize,
     pub num_channels: usize,
     dummy_input: Array2<i64>,
+    metadata: ModelMetadata,
 }
 
 impl OrtColPaliEmbedder {
     pub fn new(model_id: &str, revision: Option<&str>) -> Result<Self, E> {
-        let api = hf_hub::api::sync::Api::new()?;
+        let api = hf_api_builder().build()?;
         let repo: hf_hub::api::sync::ApiRepo = match revision {
             Some(rev) => api.repo(hf_hub::Repo::with_revision(
                 model_id.to_string(),
@@ -97,6 +99,8 @@ impl OrtColPaliEmbedder {
             image_size,
             num_channels,
             dummy_input,
+            metadata: ModelMetadata::new("colpali", model_id, revision)
+                .with_dimension(Some(COLPALI_DIMENSION)),
         })
     }
 }
@@ -346,6 +350,10 @@ impl ColPaliEmbed for OrtColPaliEmbedder {
             .collect::<Vec<_>>();
         Ok(e)
     }
+
+    fn model_metadata(&self) -> ModelMetadata {
+        self.metadata.clone()
+    }
 }
 
 fn pages_to_array(
@@ -4,9 +4,17 @@ extern crate intel_mkl_src;
 #[cfg(feature = "accelerate")]
 extern crate accelerate_src;
 
+use crate::embeddings::batch_size::embed_with_oom_fallback;
+use crate::embeddings::metrics::BatchTimer;
 use crate::embeddings::select_device;
-use crate::embeddings::{embed::EmbeddingResult, normalize_l2};
+use crate::embeddings::{
+    embed::{EmbeddingResult, ModelMetadata},
+    normalize_l2,
+    utils::{check_model_dir, check_vocab_size, hf_api_builder, load_tokenizer, ModelFiles},
+};
 use crate::models::jina_bert::{BertModel, Config};
+
+use super::pooling::Pooling;
 use anyhow::Error as E;
 use candle_core::{DType, Device, Tensor};
 use candle_nn::{Module, VarBuilder};
@@ -20,6 +28,43 @@ pub trait JinaEmbed {
         text_batch: &[String],
         batch_size: Option<usize>,
     ) -> Result<Vec<EmbeddingResult>, anyhow::Error>;
+
+    /// See
+    /// [TextEmbed::embed_query_batch](crate::embeddings::embed::TextEmbed::embed_query_batch).
+    fn embed_query_batch(
+        &self,
+        text_batch: &[String],
+        batch_size: Option<usize>,
+    ) -> Result<Vec<EmbeddingResult>, anyhow::Error> {
+        self.embed(text_batch, batch_size)
+    }
+
+    /// See
+    /// [TextEmbed::embed_document_batch](crate::embeddings::embed::TextEmbed::embed_document_batch).
+    fn embed_document_batch(
+        &self,
+        text_batch: &[String],
+        batch_size: Option<usize>,
+    ) -> Result<Vec<EmbeddingResult>, anyhow::Error> {
+        self.embed(text_batch, batch_size)
+    }
+
+    /// The batch size used when none is given. Jina models have an 8192 token context, so
+    /// their padded batches are large.
+    fn recommended_batch_size(&self) -> usize {
+        16
+    }
+
+    /// Describes the model. Embedders that do not override this report an unknown model.
+    fn model_metadata(&self) -> ModelMetadata {
+        ModelMetadata::default()
+    }
+
+    /// The tokenizer of the model, used to report the texts it truncates. None for embedders
+    /// that don't expose it.
+    fn tokenizer(&self) -> Option<&Tokenizer> {
+        None
+    }
 }
 
 ///jina-embeddings-v2-base-en is an English, monolingual embedding model supporting 8192 sequence length. It is based on a BERT architecture (JinaBERT) that supports the symmetric bidirectional variant of ALiBi to allow longer sequence length. The backbone jina-bert-v2-base-en is pretrained on the C4 dataset. The model is further trained on Jina AI's collection of more than 400 millions of sentence pairs and hard negatives. These pairs were obtained from various domains and were carefully selected through a thorough cleaning process.
@@ -36,6 +81,7 @@ pub trait JinaEmbed {
 pub struct JinaEmbedder {
     pub model: BertModel,
     pub tokenizer: Tokenizer,
+    pub metadata: ModelMetadata,
 }
 
 impl Default for JinaEmbedder {
@@ -46,7 +92,7 @@ impl Default for JinaEmbedder {
 
 impl JinaEmbedder {
     pub fn new(model_id: &str, revision: Option<&str>, token: Option<&str>) -> Result<Self, E> {
-        let api = hf_hub::api::sync::ApiBuilder::new()
+        let api = hf_api_builder()
             .with_token(token.map(|s| s.to_string()))
             .build()?;
         let api = match revision {
@@ -57,18 +103,30 @@ impl JinaEmbedder {
             )),
             None => api.repo(Repo::new(model_id.to_string(), hf_hub::RepoType::Model)),
         };
+        Self::from_files(&api, ModelMetadata::new("jina", model_id, revision))
+    }
 
-        let config_filename = api.get("config.json")?;
-        let tokenizer_filename = api.get("tokenizer.json")?;
-        let mut tokenizer = Tokenizer::from_file(tokenizer_filename).map_err(E::msg)?;
+    /// Loads a model saved in `dir` without contacting the Hugging Face Hub, see
+    /// [BertEmbedder::from_local_dir](super::bert::BertEmbedder::from_local_dir).
+    pub fn from_local_dir(dir: &std::path::Path) -> Result<Self, E> {
+        check_model_dir(dir)?;
+        let metadata = ModelMetadata::new("jina", &dir.to_string_lossy(), None);
+        Self::from_files(dir, metadata)
+    }
+
+    fn from_files(api: &(impl ModelFiles + ?Sized), metadata: ModelMetadata) -> Result<Self, E> {
+        let config_filename = api.file("config.json")?;
+        let tokenizer_filename = api.file("tokenizer.json")?;
+        let mut tokenizer = load_tokenizer(tokenizer_filename)?;
         let config = std::fs::read_to_string(config_filename)?;
         let config: Config = serde_json::from_str(&config)?;
+        check_vocab_size(&tokenizer, config.vocab_size, &metadata.model_id)?;
         let device = select_device();
-        let vb = match api.get("model.safetensors") {
+        let vb = match api.file("model.safetensors") {
             Ok(safetensors) => unsafe {
                 VarBuilder::from_mmaped_safetensors(&[safetensors], DType::F32, &device)?
             },
-            Err(_) => match api.get("pytorch_model.bin") {
+            Err(_) => match api.file("pytorch_model.bin") {
                 Ok(pytorch_model) => VarBuilder::from_pth(pytorch_model, DType::F32, &device)?,
                 Err(e) => {
                     return Err(anyhow::Error::msg(format!(
@@ -85,7 +143,12 @@ impl JinaEmbedder {
             ..Default::default()
         };
         tokenizer.with_padding(Some(pp));
-        Ok(Self { model, tokenizer })
+        let metadata = metadata.with_dimension(Some(config.hidden_size));
+        Ok(Self {
+            model,
+            tokenizer,
+            metadata,
+        })
     }
 
     pub fn tokenize_batch(&self, text_batch: &[String], device: &Device) -> anyhow::Result<Tensor> {
@@ -108,25 +171,26 @@ impl JinaEmbedder {
         text_batch: &[String],
         batch_size: Option<usize>,
     ) -> Result<Vec<EmbeddingResult>, anyhow::Error> {
-        let mut encodings: Vec<EmbeddingResult> = Vec::new();
         let batch_size = batch_size.unwrap_or(32);
-        for mini_text_batch in text_batch.chunks(batch_size) {
-            let token_ids = self
-                .tokenize_batch(mini_text_batch, &self.model.device)
-                .unwrap();
-            let embeddings = self.model.forward(&token_ids).unwrap();
-            let (_n_sentence, n_tokens, _hidden_size) = embeddings.dims3().unwrap();
+        embed_with_oom_fallback(text_batch, batch_size, |mini_text_batch| {
+            let mut timer = BatchTimer::start();
+            let token_ids = self.tokenize_batch(mini_text_batch, &self.model.device)?;
+            timer.tokenized();
+            let embeddings = self.model.forward(&token_ids)?;
+            let (_n_sentence, n_tokens, _hidden_size) = embeddings.dims3()?;
 
-            let embeddings = (embeddings.sum(1).unwrap() / (n_tokens as f64)).unwrap();
-            let embeddings = normalize_l2(&embeddings).unwrap();
+            let embeddings = (embeddings.sum(1)? / (n_tokens as f64))?;
+            let embeddings = normalize_l2(&embeddings)?;
 
             // Avoid using to_vec2() and instead work with the Tensor directly
-            encodings.extend((0..embeddings.dim(0)?).map(|i| {
-                EmbeddingResult::DenseVector(embeddings.get(i).unwrap().to_vec1().unwrap())
-            }));
-        }
-
-        Ok(encodings)
+            let encodings = (0..embeddings.dim(0)?)
+                .map(|i| -> Result<EmbeddingResult, E> {
+                    Ok(EmbeddingResult::DenseVector(embeddings.get(i)?.to_vec1()?))
+                })
+                .collect::<Result<Vec<_>, _>>()?;
+            timer.finish(mini_text_batch.len(), token_ids.elem_count());
+            Ok(encodings)
+        })
     }
 }
 
@@ -138,6 +202,14 @@ impl JinaEmbed for JinaEmbedder {
     ) -> Result<Vec<EmbeddingResult>, anyhow::Error> {
         self.embed(text_batch, batch_size)
     }
+
+    fn model_metadata(&self) -> ModelMetadata {
+        self.metadata.clone().with_pooling(Some(Pooling::Mean))
+    }
+
+    fn tokenizer(&self) -> Option<&Tokenizer> {
+        Some(&self.tokenizer)
+    }
 }
 
 #[cfg(test)]
@@ -8,6 +8,8 @@ pub mod colpali_ort;
 pub mod jina;
 pub mod model_info;
 pub mod pooling;
+#[cfg(feature = "ort")]
+pub mod session_pool;
 pub mod text_embedding;
 #[cfg(feature = "ort")]
 pub mod ort_jina;
@@ -1,29 +1,39 @@
 use super::bert::{BertEmbed, TokenizerConfig};
-use super::pooling::{ModelOutput, Pooling};
+use super::pooling::{AttentionMask, ModelOutput, Pooling};
+use super::session_pool::SessionPool;
 use super::text_embedding::ONNXModel;
-use crate::embeddings::embed::EmbeddingResult;
+use crate::embeddings::batch_size::embed_with_oom_fallback;
+use crate::embeddings::embed::{EmbeddingResult, ModelMetadata};
+use crate::embeddings::local::text_embedding::{
+    get_model_info, get_model_info_by_hf_id, models_map,
+};
+use crate::embeddings::normalize_l2_ndarray;
 use crate::embeddings::utils::{
-    get_attention_mask_ndarray, get_type_ids_ndarray, tokenize_batch_ndarray,
+    add_prefix, get_attention_mask_ndarray, get_type_ids_ndarray, hf_api_builder, load_tokenizer,
+    onnx_weights_path, tokenize_batch_ndarray, SentenceTransformersPrompts,
 };
-use crate::embeddings::local::text_embedding::models_map;
 
 use crate::Dtype;
-use hf_hub::api::sync::Api;
+use anyhow::Error as E;
 use hf_hub::Repo;
 use ndarray::prelude::*;
-use ort::execution_providers::{CUDAExecutionProvider, CoreMLExecutionProvider, ExecutionProvider};
-use ort::session::builder::GraphOptimizationLevel;
-use ort::session::Session;
+use ort::execution_providers::{CUDAExecutionProvider, ExecutionProvider};
 use ort::value::Value;
 use rayon::prelude::*;
 use tokenizers::{PaddingParams, Tokenizer, TruncationParams};
-use anyhow::Error as E;
 
 #[derive(Debug)]
 pub struct OrtBertEmbedder {
     pub tokenizer: Tokenizer,
-    pub model: Session,
+    pub model: SessionPool,
     pub pooling: Pooling,
+    /// Default batch size of the model, from the model map when the model is listed there.
+    pub batch_size: usize,
+    /// Prepended to queries, e.g. "query: " for E5 models.
+    pub query_prefix: Option<String>,
+    /// Prepended to documents, e.g. "passage: " for E5 models.
+    pub document_prefix: Option<String>,
+    pub metadata: ModelMetadata,
 }
 
 impl OrtBertEmbedder {
@@ -55,16 +65,23 @@ impl OrtBertEmbedder {
                 .unwrap_or(Pooling::Mean),
             None => Pooling::Mean,
         };
-        let path = match path_in_repo {
-            Some(path) => path,
-            None => match model_name {
-                Some(name) => models_map().get(&name).unwrap().model_file.as_str(),
-                None => "model.onnx",
-            },
+        let model_info = match model_name {
+            Some(name) => get_model_info(&name),
+            None => get_model_info_by_hf_id(hf_model_id),
         };
+        let batch_size = model_info.map_or(32, |info| info.model.get_default_batch_size());
+        let metadata = ModelMetadata::new("bert", hf_model_id, revision)
+            .with_dimension(model_info.map(|info| info.dim));
+        let (query_prefix, document_prefix) =
+            model_info.map_or((None, None), |info| info.model.get_default_prefixes());
+        let default_path = match model_name {
+            Some(name) => models_map().get(&name).unwrap().model_file.as_str(),
+            None => "model.onnx",
+        };
+        let model_path = onnx_weights_path(path_in_repo, default_path, dtype.as_ref());
 
-        let (_, tokenizer_filename, weights_filename, tokenizer_config_filename) = {
-            let api = Api::new().unwrap();
+        let (_, tokenizer_filename, weights_filename, tokenizer_config_filename, prompts) = {
+            let api = hf_api_builder().build().unwrap();
             let api = match revision {
                 Some(rev) => api.repo(Repo::with_revision(
                     hf_model_id.to_string(),
@@ -79,20 +96,9 @@ impl OrtBertEmbedder {
             let config = api.get("config.json")?;
             let tokenizer = api.get("tokenizer.json")?;
             let tokenizer_config = api.get("tokenizer_config.json")?;
-            let base_path = path.rsplit_once('/').map(|(p, _)| p).unwrap_or("");
-            let model_path = match dtype {
-                Some(Dtype::Q4F16) => format!("{base_path}/model_q4f16.onnx"),
-                Some(Dtype::F16) => format!("{base_path}/model_fp16.onnx"),
-                Some(Dtype::INT8) => format!("{base_path}/model_int8.onnx"),
-                Some(Dtype::Q4) => format!("{base_path}/model_q4.onnx"),
-                Some(Dtype::UINT8) => format!("{base_path}/model_uint8.onnx"),
-                Some(Dtype::BNB4) => format!("{base_path}/model_bnb4.onnx"),
-                Some(Dtype::F32) => format!("{base_path}/model.onnx"),
-                Some(Dtype::QUANTIZED) => format!("{base_path}/model_quantized.onnx"),
-                None => path.to_string(),
-            };
             let weights = api.get(model_path.as_str());
-            (config, tokenizer, weights, tokenizer_config)
+            let prompts = SentenceTransformersPrompts::from_repo(&api);
+            (config, tokenizer, weights, tokenizer_config, prompts)
         };
 
         let weights_filename = match weights_filename {
@@ -116,7 +122,7 @@ impl OrtBertEmbedder {
             (None, None) => 128,
         };
 
-        let mut tokenizer = Tokenizer::from_file(tokenizer_filename).map_err(E::msg)?;
+        let mut tokenizer = load_tokenizer(tokenizer_filename)?;
 
         let pp = PaddingParams {
             strategy: tokenizers::PaddingStrategy::BatchLongest,
@@ -140,22 +146,31 @@ impl OrtBertEmbedder {
             println!("Session is using CUDAExecutionProvider");
         }
 
-        let threads = std::thread::available_parallelism().unwrap().get();
-        let model = Session::builder()?
-            .with_execution_providers([
-                CUDAExecutionProvider::default().build(),
-                CoreMLExecutionProvider::default().build(),
-            ])?
-            .with_optimization_level(GraphOptimizationLevel::Level3)?
-            .with_intra_threads(threads)?
-            .commit_from_file(weights_filename)?;
+        let model = SessionPool::load(&weights_filename)?;
 
         Ok(OrtBertEmbedder {
             tokenizer,
             model,
             pooling,
+            batch_size,
+            // Prompts of sentence-transformers models take precedence over the model map.
+            query_prefix: prompts.0.or(query_prefix.map(str::to_string)),
+            document_prefix: prompts.1.or(document_prefix.map(str::to_string)),
+            metadata,
         })
     }
+
+    /// Overrides the query and document prefixes. Models listed in the model map get the
+    /// prefixes they were trained with by default.
+    pub fn with_prefixes(
+        mut self,
+        query_prefix: Option<&str>,
+        document_prefix: Option<&str>,
+    ) -> Self {
+        self.query_prefix = query_prefix.map(str::to_string);
+        self.document_prefix = document_prefix.map(str::to_string);
+        self
+    }
 }
 
 impl BertEmbed for OrtBertEmbedder {
@@ -164,59 +179,108 @@ impl BertEmbed for OrtBertEmbedder {
         text_batch: &[String],
         batch_size: Option<usize>,
     ) -> Result<Vec<EmbeddingResult>, E> {
-        let batch_size = batch_size.unwrap_or(32);
+        let batch_size = batch_size.unwrap_or(self.batch_size);
         let encodings = text_batch
             .par_chunks(batch_size)
-            .flat_map(|mini_text_batch| -> Result<Vec<Vec<f32>>, E> {
-                let input_ids: Array2<i64> =
-                    tokenize_batch_ndarray(&self.tokenizer, mini_text_batch)?;
-                let token_type_ids: Array2<i64> = Array2::zeros(input_ids.raw_dim());
-                let attention_mask: Array2<i64> = Array2::ones(input_ids.raw_dim());
-
-                let input_names = self
-                    .model
-                    .inputs
-                    .iter()
-                    .map(|input| input.name.as_str())
-                    .collect::<Vec<_>>();
-
-                let mut inputs =
-                    ort::inputs!["input_ids" => input_ids, "attention_mask" => attention_mask]?;
-                if input_names.iter().any(|&x| x == "token_type_ids") {
-                    inputs.push((
-                        "token_type_ids".into(),
-                        Value::from_array(token_type_ids.clone())?.into(),
-                    ));
-                }
-                let outputs = self.model.run(inputs)?;
-                let embeddings: Array3<f32> = outputs
-                    [self.model.outputs.first().unwrap().name.as_str()]
-                .try_extract_tensor::<f32>()?
-                .to_owned()
-                .into_dimensionality::<ndarray::Ix3>()?;
-                let (_, _, _) = embeddings.dim();
-                let embeddings = self
-                    .pooling
-                    .pool(&ModelOutput::Array(embeddings))?
-                    .to_array()?;
-                let norms = embeddings.mapv(|x| x * x).sum_axis(Axis(1)).mapv(f32::sqrt);
-                let embeddings = &embeddings / &norms.insert_axis(Axis(1));
-
-                Ok(embeddings.outer_iter().map(|row| row.to_vec()).collect())
+            .map(|mini_text_batch| {
+                embed_with_oom_fallback(
+                    mini_text_batch,
+                    mini_text_batch.len(),
+                    |mini_text_batch| -> Result<Vec<Vec<f32>>, E> {
+                        let input_ids: Array2<i64> =
+                            tokenize_batch_ndarray(&self.tokenizer, mini_text_batch)?;
+                        let token_type_ids: Array2<i64> = Array2::zeros(input_ids.raw_dim());
+                        let attention_mask: Array2<i64> =
+                            get_attention_mask_ndarray(&self.tokenizer, mini_text_batch)?;
+
+                        let input_names = self
+                            .model
+                            .inputs
+                            .iter()
+                            .map(|input| input.name.as_str())
+                            .collect::<Vec<_>>();
+
+                        let mut inputs =
+                            ort::inputs!["input_ids" => input_ids, "attention_mask" => attention_mask.clone()]?;
+                        if input_names.iter().any(|&x| x == "token_type_ids") {
+                            inputs.push((
+                                "token_type_ids".into(),
+                                Value::from_array(token_type_ids.clone())?.into(),
+                            ));
+                        }
+                        let outputs = self.model.session().run(inputs)?;
+                        let embeddings: Array3<f32> = outputs
+                            [self.model.outputs.first().unwrap().name.as_str()]
+                        .try_extract_tensor::<f32>()?
+                        .to_owned()
+                        .into_dimensionality::<ndarray::Ix3>()?;
+                        let (_, _, _) = embeddings.dim();
+                        let embeddings = self
+                            .pooling
+                            .pool(
+                                &ModelOutput::Array(embeddings),
+                                &AttentionMask::Array(attention_mask),
+                            )?
+                            .to_array()?;
+                        let embeddings = normalize_l2_ndarray(&embeddings);
+
+                        Ok(embeddings.outer_iter().map(|row| row.to_vec()).collect())
+                    },
+                )
             })
-            .flatten()
-            .collect::<Vec<_>>();
+            .collect::<Result<Vec<_>, E>>()?;
 
         Ok(encodings
-            .iter()
-            .map(|x| EmbeddingResult::DenseVector(x.to_vec()))
+            .into_iter()
+            .flatten()
+            .map(EmbeddingResult::DenseVector)
             .collect())
     }
+
+    fn embed_query_batch(
+        &self,
+        text_batch: &[String],
+        batch_size: Option<usize>,
+    ) -> Result<Vec<EmbeddingResult>, E> {
+        let text_batch = add_prefix(self.query_prefix.as_deref(), text_batch);
+        self.embed(&text_batch, batch_size)
+    }
+
+    fn embed_document_batch(
+        &self,
+        text_batch: &[String],
+        batch_size: Option<usize>,
+    ) -> Result<Vec<EmbeddingResult>, E> {
+        let text_batch = add_prefix(self.document_prefix.as_deref(), text_batch);
+        self.embed(&text_batch, batch_size)
+    }
+
+    fn recommended_batch_size(&self) -> usize {
+        self.batch_size
+    }
+
+    fn model_metadata(&self) -> ModelMetadata {
+        self.metadata
+            .clone()
+            .with_pooling(Some(self.pooling.clone()))
+    }
+
+    fn tokenizer(&self) -> Option<&Tokenizer> {
+        Some(&self.tokenizer)
+    }
+
+    fn prefixes(&self) -> (Option<&str>, Option<&str>) {
+        (
+            self.query_prefix.as_deref(),
+            self.document_prefix.as_deref(),
+        )
+    }
 }
 
 pub struct OrtSparseBertEmbedder {
     pub tokenizer: Tokenizer,
-    pub model: Session,
+    pub model: SessionPool,
+    pub metadata: ModelMetadata,
 }
 
 impl OrtSparseBertEmbedder {
@@ -247,7 +311,7 @@ impl OrtSparseBertEmbedder {
         };
 
         let (_, tokenizer_filename, weights_filename, tokenizer_config_filename) = {
-            let api = Api::new().unwrap();
+            let api = hf_api_builder().build().unwrap();
             let api = match revision {
                 Some(rev) => api.repo(Repo::with_revision(
                     hf_model_id.to_string(),
@@ -278,7 +342,7 @@ impl OrtSparseBertEmbedder {
             (None, Some(model_max_len)) => model_max_len,
             (None, None) => 128,
         };
-        let mut tokenizer = Tokenizer::from_file(tokenizer_filename).map_err(E::msg)?;
+        let mut tokenizer = load_tokenizer(tokenizer_filename)?;
         let pp = PaddingParams {
             strategy: tokenizers::PaddingStrategy::BatchLongest,
             ..Default::default()
@@ -300,17 +364,16 @@ impl OrtSparseBertEmbedder {
             println!("Session is using CUDAExecutionProvider");
         }
 
-        let threads = std::thread::available_parallelism().unwrap().get();
-        let model = Session::builder()?
-            .with_execution_providers([
-                CUDAExecutionProvider::default().build(),
-                CoreMLExecutionProvider::default().build(),
-            ])?
-            .with_optimization_level(GraphOptimizationLevel::Level3)?
-            .with_intra_threads(threads)?
-            .commit_from_file(weights_filename)?;
-
-        Ok(OrtSparseBertEmbedder { tokenizer, model })
+        let model = SessionPool::load(&weights_filename)?;
+
+        // Sparse vectors have one weight per token of the vocabulary.
+        let metadata = ModelMetadata::new("sparse-bert", hf_model_id, revision)
+            .with_dimension(Some(tokenizer.get_vocab_size(true)));
+        Ok(OrtSparseBertEmbedder {
+            tokenizer,
+            model,
+            metadata,
+        })
     }
 }
 
@@ -321,26 +384,56 @@ impl BertEmbed for OrtSparseBertEmbedder {
         batch_size: Option<usize>,
     ) -> Result<Vec<EmbeddingResult>, anyhow::Error> {
         let batch_size = batch_size.unwrap_or(32);
-        let encodings = text_batch.par_chunks(batch_size).flat_map(|mini_text_batch| -> Result<Vec<Vec<f32>>, E> {
-            let token_ids: Array2<i64> = tokenize_batch_ndarray(&self.tokenizer, mini_text_batch)?;
-            let token_type_ids: Array2<i64> = get_type_ids_ndarray(&self.tokenizer, mini_text_batch)?;
-            let attention_mask = get_attention_mask_ndarray(&self.tokenizer, mini_text_batch)?;
-            let outputs = self.model.run(ort::inputs!["input_ids" => token_ids, "input_mask" => attention_mask.clone(), "segment_ids" => token_type_ids]?).unwrap();
-            let embeddings: Array3<f32> = outputs["output"]
-                .try_extract_tensor::<f32>()?
-                .to_owned()
-                .into_dimensionality::<ndarray::Ix3>()?;
-            let relu_log: ArrayBase<ndarray::OwnedRepr<f32>, Dim<[usize; 3]>> = embeddings.mapv(|x| (1.0 + x.max(0.0)).ln());
-            let weighted_log = relu_log * attention_mask.clone().mapv(|x| x as f32).insert_axis(Axis(2));
-            let scores = weighted_log.fold_axis(Axis(1), f32::NEG_INFINITY, |r, &v| r.max(v));
-            let norms = scores.mapv(|x| x * x).sum_axis(Axis(1)).mapv(f32::sqrt);
-            let embeddings = &scores / &norms.insert_axis(Axis(1));
-            Ok(embeddings.outer_iter().map(|row| row.to_vec()).collect())
-        }).flatten().collect::<Vec<_>>();
+        let encodings = text_batch
+            .par_chunks(batch_size)
+            .map(|mini_text_batch| {
+                embed_with_oom_fallback(
+                    mini_text_batch,
+                    mini_text_batch.len(),
+                    |mini_text_batch| -> Result<Vec<Vec<f32>>, E> {
+                        let token_ids: Array2<i64> =
+                            tokenize_batch_ndarray(&self.tokenizer, mini_text_batch)?;
+                        let token_type_ids: Array2<i64> =
+                            get_type_ids_ndarray(&self.tokenizer, mini_text_batch)?;
+                        let attention_mask =
+                            get_attention_mask_ndarray(&self.tokenizer, mini_text_batch)?;
+                        let outputs = self.model.session().run(ort::inputs![
+                            "input_ids" => token_ids,
+                            "input_mask" => attention_mask.clone(),
+                            "segment_ids" => token_type_ids
+                        ]?)?;
+                        let embeddings: Array3<f32> = outputs["output"]
+                            .try_extract_tensor::<f32>()?
+                            .to_owned()
+                            .into_dimensionality::<ndarray::Ix3>()?;
+                        let relu_log: ArrayBase<ndarray::OwnedRepr<f32>, Dim<[usize; 3]>> =
+                            embeddings.mapv(|x| (1.0 + x.max(0.0)).ln());
+                        let weighted_log = relu_log
+                            * attention_mask
+                                .clone()
+                                .mapv(|x| x as f32)
+                                .insert_axis(Axis(2));
+                        let scores =
+                            weighted_log.fold_axis(Axis(1), f32::NEG_INFINITY, |r, &v| r.max(v));
+                        let embeddings = normalize_l2_ndarray(&scores);
+                        Ok(embeddings.outer_iter().map(|row| row.to_vec()).collect())
+                    },
+                )
+            })
+            .collect::<Result<Vec<_>, E>>()?;
 
         Ok(encodings
-            .iter()
-            .map(|x| EmbeddingResult::DenseVector(x.to_vec()))
+            .into_iter()
+            .flatten()
+            .map(EmbeddingResult::DenseVector)
             .collect())
     }
+
+    fn model_metadata(&self) -> ModelMetadata {
+        self.metadata.clone()
+    }
+
+    fn tokenizer(&self) -> Option<&Tokenizer> {
+        Some(&self.tokenizer)
+    }
 }
@@ -1,28 +1,30 @@
 
 use hf_hub::Repo;
 use ndarray::prelude::*;
-use hf_hub::api::sync::Api;
 use super::bert::TokenizerConfig;
 use super::jina::JinaEmbed;
-use super::pooling::{ModelOutput, Pooling};
+use super::pooling::{AttentionMask, ModelOutput, Pooling};
+use super::session_pool::SessionPool;
 use tokenizers::{PaddingParams, Tokenizer, TruncationParams};
-use super::text_embedding::{models_map, ONNXModel};
-use crate::embeddings::embed::EmbeddingResult;
+use super::text_embedding::{get_model_info_by_hf_id, models_map, ONNXModel};
+use crate::embeddings::batch_size::embed_with_oom_fallback;
+use crate::embeddings::embed::{EmbeddingResult, ModelMetadata};
+use crate::embeddings::normalize_l2_ndarray;
+use crate::embeddings::utils::{
+    get_attention_mask_ndarray, hf_api_builder, load_tokenizer, onnx_weights_path,
+};
 use crate::Dtype;
 use anyhow::Error as E;
 use rayon::prelude::*;
 
-use {
-    ort::execution_providers::{CUDAExecutionProvider, CoreMLExecutionProvider, ExecutionProvider},
-    ort::session::builder::GraphOptimizationLevel,
-    ort::session::Session,
-};
+use ort::execution_providers::{CUDAExecutionProvider, ExecutionProvider};
 #[derive(Debug)]
 pub struct OrtJinaEmbedder {
-    pub session: Session,
+    pub session: SessionPool,
     pub version: String,
     pub tokenizer: Tokenizer,
     pub pooling: Pooling,
+    pub metadata: ModelMetadata,
 }
 
 impl OrtJinaEmbedder {
@@ -54,16 +56,14 @@ impl OrtJinaEmbedder {
                 .unwrap_or(Pooling::Mean),
             None => Pooling::Mean,
         };
-        let path = match path_in_repo {
-            Some(path) => path,
-            None => match model_name {
-                Some(name) => models_map().get(&name).unwrap().model_file.as_str(),
-                None => "model.onnx",
-            },
+        let default_path = match model_name {
+            Some(name) => models_map().get(&name).unwrap().model_file.as_str(),
+            None => "model.onnx",
         };
+        let model_path = onnx_weights_path(path_in_repo, default_path, dtype.as_ref());
 
         let (_, tokenizer_filename, weights_filename, tokenizer_config_filename) = {
-            let api = Api::new().unwrap();
+            let api = hf_api_builder().build().unwrap();
             let api = match revision {
                 Some(rev) => api.repo(Repo::with_revision(
                     hf_model_id.to_string(),
@@ -78,20 +78,8 @@ impl OrtJinaEmbedder {
             let config = api.get("config.json")?;
             let tokenizer = api.get("tokenizer.json")?;
             let tokenizer_config = api.get("tokenizer_config.json")?;
-            let base_path = path.rsplit_once('/').map(|(p, _)| p).unwrap_or("");
-            let model_path = match dtype {
-                Some(Dtype::Q4F16) => format!("{base_path}/model_q4f16.onnx"),
-                Some(Dtype::F16) => format!("{base_path}/model_fp16.onnx"),
-                Some(Dtype::INT8) => format!("{base_path}/model_int8.onnx"),
-                Some(Dtype::Q4) => format!("{base_path}/model_q4.onnx"),
-                Some(Dtype::UINT8) => format!("{base_path}/model_uint8.onnx"),
-                Some(Dtype::BNB4) => format!("{base_path}/model_bnb4.onnx"),
-                Some(Dtype::F32) => format!("{base_path}/model.onnx"),
-                Some(Dtype::QUANTIZED) => format!("{base_path}/model_quantized.onnx"),
-                None => path.to_string(),
-            };
             let weights = api.get(model_path.as_str());
-            let _ = api.get(format!("{path}_data").as_str());
+            let _ = api.get(format!("{model_path}_data").as_str());
 
             (config, tokenizer, weights, tokenizer_config)
 
@@ -118,7 +106,7 @@ impl OrtJinaEmbedder {
             (None, None) => 128,
         };
 
-        let mut tokenizer = Tokenizer::from_file(tokenizer_filename).map_err(E::msg)?;
+        let mut tokenizer = load_tokenizer(tokenizer_filename)?;
 
         let pp = PaddingParams {
             strategy: tokenizers::PaddingStrategy::BatchLongest,
@@ -142,15 +130,7 @@ impl OrtJinaEmbedder {
             println!("Session is using CUDAExecutionProvider");
         }
 
-        let threads = std::thread::available_parallelism().unwrap().get();
-        let model = Session::builder()?
-            .with_execution_providers([
-                CUDAExecutionProvider::default().build(),
-                CoreMLExecutionProvider::default().build(),
-            ])?
-            .with_optimization_level(GraphOptimizationLevel::Level3)?
-            .with_intra_threads(threads)?
-            .commit_from_file(weights_filename)?;
+        let model = SessionPool::load(&weights_filename)?;
 
         let version = match (model_name, model_id) {
             (Some(ONNXModel::JINAV3), _) => "v3",
@@ -158,11 +138,17 @@ impl OrtJinaEmbedder {
             _ => "v2",
         };
 
+        let dimension = match model_name {
+            Some(name) => models_map().get(&name).map(|info| info.dim),
+            None => get_model_info_by_hf_id(hf_model_id).map(|info| info.dim),
+        };
+
         Ok(OrtJinaEmbedder {
             session: model,
             version: version.to_string(),
             tokenizer,
             pooling,
+            metadata: ModelMetadata::new("jina", hf_model_id, revision).with_dimension(dimension),
         })
     }
 
@@ -199,49 +185,68 @@ impl JinaEmbed for OrtJinaEmbedder {
         let batch_size = batch_size.unwrap_or(32);
         let encodings = text_batch
             .par_chunks(batch_size)
-            .flat_map(|mini_text_batch| -> Result<Vec<Vec<f32>>, E> {
-                let token_ids: Array2<i64> = self.tokenize_batch(mini_text_batch)?;
-                let token_type_ids: Array2<i64> = Array2::zeros(token_ids.raw_dim());
-                let attention_mask: Array2<i64> = Array2::ones(token_ids.raw_dim());
-
-                let embeddings = if self.version == "v3" {
-                    let outputs = self.session.run(ort::inputs! {
-                        "input_ids" => token_ids,
-                        "attention_mask" => attention_mask,
-                        "task_id" => Array1::<i64>::from_vec(vec![4])
-                    }?)?;
-                    outputs["text_embeds"]
-                        .try_extract_tensor::<f32>()?
-                        .to_owned()
-                        .into_dimensionality::<ndarray::Ix3>()?
-                } else {
-                    let outputs = self.session.run(ort::inputs! {
-                        "input_ids" => token_ids,
-                        "token_type_ids" => token_type_ids,
-                        "attention_mask" => attention_mask
-                    }?)?;
-                    outputs["last_hidden_state"]
-                        .try_extract_tensor::<f32>()?
-                        .to_owned()
-                        .into_dimensionality::<ndarray::Ix3>()?
-                };
-
-                let (_, _, _) = embeddings.dim();
-                let embeddings = self
-                    .pooling
-                    .pool(&ModelOutput::Array(embeddings))?
-                    .to_array()?;
-                let norms = embeddings.mapv(|x| x * x).sum_axis(Axis(1)).mapv(f32::sqrt);
-                let embeddings = &embeddings / &norms.insert_axis(Axis(1));
-
-                Ok(embeddings.outer_iter().map(|row| row.to_vec()).collect())
+            .map(|mini_text_batch| {
+                embed_with_oom_fallback(
+                    mini_text_batch,
+                    mini_text_batch.len(),
+                    |mini_text_batch| -> Result<Vec<Vec<f32>>, E> {
+                        let token_ids: Array2<i64> = self.tokenize_batch(mini_text_batch)?;
+                        let token_type_ids: Array2<i64> = Array2::zeros(token_ids.raw_dim());
+                        let attention_mask: Array2<i64> =
+                            get_attention_mask_ndarray(&self.tokenizer, mini_text_batch)?;
+
+                        let embeddings = if self.version == "v3" {
+                            let outputs = self.session.session().run(ort::inputs! {
+                                "input_ids" => token_ids,
+                                "attention_mask" => attention_mask.clone(),
+                                "task_id" => Array1::<i64>::from_vec(vec![4])
+                            }?)?;
+                            outputs["text_embeds"]
+                                .try_extract_tensor::<f32>()?
+                                .to_owned()
+                                .into_dimensionality::<ndarray::Ix3>()?
+                        } else {
+                            let outputs = self.session.session().run(ort::inputs! {
+                                "input_ids" => token_ids,
+                                "token_type_ids" => token_type_ids,
+                                "attention_mask" => attention_mask.clone()
+                            }?)?;
+                            outputs["last_hidden_state"]
+                                .try_extract_tensor::<f32>()?
+                                .to_owned()
+                                .into_dimensionality::<ndarray::Ix3>()?
+                        };
+
+                        let (_, _, _) = embeddings.dim();
+                        let embeddings = self
+                            .pooling
+                            .pool(
+                                &ModelOutput::Array(embeddings),
+                                &AttentionMask::Array(attention_mask),
+                            )?
+                            .to_array()?;
+                        let embeddings = normalize_l2_ndarray(&embeddings);
+
+                        Ok(embeddings.outer_iter().map(|row| row.to_vec()).collect())
+                    },
+                )
             })
-            .flatten()
-            .collect::<Vec<_>>();
+            .collect::<Result<Vec<_>, E>>()?;
 
         Ok(encodings
-            .iter()
-            .map(|x| EmbeddingResult::DenseVector(x.to_vec()))
+            .into_iter()
+            .flatten()
+            .map(EmbeddingResult::DenseVector)
             .collect())
     }
+
+    fn model_metadata(&self) -> ModelMetadata {
+        self.metadata
+            .clone()
+            .with_pooling(Some(self.pooling.clone()))
+    }
+
+    fn tokenizer(&self) -> Option<&Tokenizer> {
+        Some(&self.tokenizer)
+    }
 }
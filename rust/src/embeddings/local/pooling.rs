@@ -1,12 +1,106 @@
+use std::sync::Arc;
+
 use candle_core::Tensor;
 use ndarray::prelude::*;
 use ndarray::{Array2, Array3};
+use serde::Deserialize;
+
+use crate::embeddings::utils::ModelFiles;
 
-#[derive(Debug, Clone, Default)]
+/// A pooling function supplied by the caller. It receives the token embeddings of a batch,
+/// shaped `(batch_size, sequence_length, hidden_size)`, and the attention mask the model was
+/// run with, shaped `(batch_size, sequence_length)` with 1 for attended tokens and 0 for
+/// padding. It returns one embedding per text, shaped `(batch_size, hidden_size)`.
+pub type PoolingFn =
+    dyn Fn(&ModelOutput, &AttentionMask) -> Result<Tensor, anyhow::Error> + Send + Sync;
+
+#[derive(Clone, Default)]
 pub enum Pooling {
+    /// The mean of the tokens that are not padding.
     #[default]
     Mean,
     Cls,
+    /// The element-wise maximum over the tokens that are not padding.
+    Max,
+    Custom(Arc<PoolingFn>),
+}
+
+impl std::fmt::Debug for Pooling {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Pooling::Mean => write!(f, "Mean"),
+            Pooling::Cls => write!(f, "Cls"),
+            Pooling::Max => write!(f, "Max"),
+            Pooling::Custom(_) => write!(f, "Custom"),
+        }
+    }
+}
+
+/// The `config.json` of the Pooling module of a sentence-transformers model, usually
+/// `1_Pooling/config.json`.
+#[derive(Debug, Default, Deserialize)]
+pub struct SentenceTransformersPooling {
+    #[serde(default)]
+    pub pooling_mode_cls_token: bool,
+    #[serde(default)]
+    pub pooling_mode_mean_tokens: bool,
+    #[serde(default)]
+    pub pooling_mode_max_tokens: bool,
+    #[serde(default)]
+    pub pooling_mode_mean_sqrt_len_tokens: bool,
+    #[serde(default)]
+    pub pooling_mode_weightedmean_tokens: bool,
+    #[serde(default)]
+    pub pooling_mode_lasttoken: bool,
+}
+
+impl SentenceTransformersPooling {
+    /// The declared pooling, or None when it is not supported.
+    pub fn pooling(&self) -> Option<Pooling> {
+        let other_modes = self.pooling_mode_mean_sqrt_len_tokens
+            || self.pooling_mode_weightedmean_tokens
+            || self.pooling_mode_lasttoken;
+        match (
+            self.pooling_mode_cls_token,
+            self.pooling_mode_mean_tokens,
+            self.pooling_mode_max_tokens,
+        ) {
+            _ if other_modes => None,
+            (true, false, false) => Some(Pooling::Cls),
+            (false, true, false) => Some(Pooling::Mean),
+            (false, false, true) => Some(Pooling::Max),
+            _ => None,
+        }
+    }
+
+    /// Reads the pooling of a sentence-transformers model from its repository. The Pooling
+    /// module is found with `modules.json`, falling back to `1_Pooling`. Returns None for
+    /// repositories without a Pooling module and for pooling that is not supported.
+    pub fn from_repo(api: &(impl ModelFiles + ?Sized)) -> Option<Pooling> {
+        #[derive(Deserialize)]
+        struct Module {
+            path: String,
+            #[serde(rename = "type")]
+            module_type: String,
+        }
+
+        let path = api
+            .file("modules.json")
+            .ok()
+            .and_then(|modules| std::fs::read_to_string(modules).ok())
+            .and_then(|modules| serde_json::from_str::<Vec<Module>>(&modules).ok())
+            .and_then(|modules| {
+                modules
+                    .into_iter()
+                    .find(|module| module.module_type.ends_with("Pooling"))
+            })
+            .map_or("1_Pooling".to_string(), |module| module.path);
+        let config = api.file(&format!("{}/config.json", path)).ok()?;
+        let config = std::fs::read_to_string(config).ok()?;
+        serde_json::from_str::<SentenceTransformersPooling>(&config)
+            .ok()?
+            .pooling()
+    }
 }
 
 #[derive(Debug, Clone)]
@@ -36,11 +130,59 @@ pub enum ModelOutput {
     Array(Array3<f32>),
 }
 
+/// The attention mask of a batch, in the same representation as its [ModelOutput].
+pub enum AttentionMask {
+    Tensor(Tensor),
+    Array(Array2<i64>),
+}
+
+impl AttentionMask {
+    pub fn to_tensor(&self) -> Result<Tensor, anyhow::Error> {
+        Ok(match self {
+            AttentionMask::Tensor(tensor) => tensor.clone(),
+            AttentionMask::Array(array) => Tensor::from_slice(
+                array.as_standard_layout().as_slice().unwrap_or_default(),
+                array.dim(),
+                &candle_core::Device::Cpu,
+            )?,
+        })
+    }
+}
+
 impl Pooling {
-    pub fn pool(&self, output: &ModelOutput) -> Result<PooledOutput, anyhow::Error> {
+    /// Creates a [Pooling::Custom] from a closure. See [PoolingFn] for the expected shapes.
+    pub fn custom(
+        pool: impl Fn(&ModelOutput, &AttentionMask) -> Result<Tensor, anyhow::Error>
+            + Send
+            + Sync
+            + 'static,
+    ) -> Self {
+        Pooling::Custom(Arc::new(pool))
+    }
+
+    pub fn pool(
+        &self,
+        output: &ModelOutput,
+        attention_mask: &AttentionMask,
+    ) -> Result<PooledOutput, anyhow::Error> {
         match self {
             Pooling::Cls => Self::cls(output),
-            Pooling::Mean => Self::mean(output),
+            Pooling::Mean => Self::mean(output, attention_mask),
+            Pooling::Max => Self::max(output, attention_mask),
+            Pooling::Custom(pool) => {
+                let pooled = pool(output, attention_mask)?;
+                // Keep the representation of the model output, ONNX models expect an array back.
+                Ok(match output {
+                    ModelOutput::Tensor(_) => PooledOutput::Tensor(pooled),
+                    ModelOutput::Array(_) => {
+                        let (rows, cols) = pooled.dims2()?;
+                        PooledOutput::Array(Array2::from_shape_vec(
+                            (rows, cols),
+                            pooled.flatten_all()?.to_vec1::<f32>()?,
+                        )?)
+                    }
+                })
+            }
         }
     }
 
@@ -56,16 +198,232 @@ impl Pooling {
         }
     }
 
-    fn mean(output: &ModelOutput) -> Result<PooledOutput, anyhow::Error> {
+    fn mean(
+        output: &ModelOutput,
+        attention_mask: &AttentionMask,
+    ) -> Result<PooledOutput, anyhow::Error> {
+        let mask = attention_mask
+            .to_tensor()?
+            .to_dtype(candle_core::DType::F32)?;
         match output {
-            ModelOutput::Tensor(tensor) => tensor
-                .mean(1)
-                .map(PooledOutput::Tensor)
-                .map_err(|_| anyhow::anyhow!("Mean of empty tensor")),
-            ModelOutput::Array(array) => array
-                .mean_axis(Axis(1))
-                .map(PooledOutput::Array)
-                .ok_or_else(|| anyhow::anyhow!("Mean of empty array")),
+            ModelOutput::Tensor(tensor) => {
+                if tensor.dim(1)? == 0 {
+                    return Err(anyhow::anyhow!("Mean of empty tensor"));
+                }
+                let mask = mask
+                    .to_device(tensor.device())?
+                    .to_dtype(tensor.dtype())?
+                    .unsqueeze(2)?;
+                // Texts without any token, which tokenizers don't produce, get a zero vector.
+                let counts = mask.sum(1)?.maximum(1.0)?;
+                let sums = tensor.broadcast_mul(&mask)?.sum(1)?;
+                Ok(PooledOutput::Tensor(sums.broadcast_div(&counts)?))
+            }
+            ModelOutput::Array(array) => {
+                if array.dim().1 == 0 {
+                    return Err(anyhow::anyhow!("Mean of empty array"));
+                }
+                let mask =
+                    Array2::from_shape_vec(mask.dims2()?, mask.flatten_all()?.to_vec1::<f32>()?)?;
+                let counts = mask
+                    .sum_axis(Axis(1))
+                    .mapv(|count| count.max(1.0))
+                    .insert_axis(Axis(1));
+                let sums = (array * &mask.insert_axis(Axis(2))).sum_axis(Axis(1));
+                Ok(PooledOutput::Array(sums / counts))
+            }
+        }
+    }
+
+    fn max(
+        output: &ModelOutput,
+        attention_mask: &AttentionMask,
+    ) -> Result<PooledOutput, anyhow::Error> {
+        let mask = attention_mask
+            .to_tensor()?
+            .to_dtype(candle_core::DType::U8)?;
+        match output {
+            ModelOutput::Tensor(tensor) => {
+                // Padding is set to -inf so that it never wins the maximum.
+                let mask = mask
+                    .to_device(tensor.device())?
+                    .unsqueeze(2)?
+                    .broadcast_as(tensor.shape())?;
+                let padding = Tensor::full(f32::NEG_INFINITY, tensor.shape(), tensor.device())?
+                    .to_dtype(tensor.dtype())?;
+                Ok(PooledOutput::Tensor(
+                    mask.where_cond(tensor, &padding)?.max(1)?,
+                ))
+            }
+            ModelOutput::Array(array) => {
+                let mask = mask.to_vec2::<u8>()?;
+                let (batch_size, _, hidden_size) = array.dim();
+                let mut pooled = Array2::from_elem((batch_size, hidden_size), f32::NEG_INFINITY);
+                for (text, mut row) in pooled.outer_iter_mut().enumerate() {
+                    for (token, embedding) in array.slice(s![text, .., ..]).outer_iter().enumerate()
+                    {
+                        if mask[text][token] != 0 {
+                            row.zip_mut_with(&embedding, |max, &x| *max = max.max(x));
+                        }
+                    }
+                }
+                Ok(PooledOutput::Array(pooled))
+            }
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_sentence_transformers_pooling() {
+        let parse = |config: &str| {
+            serde_json::from_str::<SentenceTransformersPooling>(config)
+                .unwrap()
+                .pooling()
+        };
+        let cls = parse(r#"{"word_embedding_dimension": 384, "pooling_mode_cls_token": true}"#);
+        assert!(matches!(cls, Some(Pooling::Cls)));
+        let mean = parse(
+            r#"{"pooling_mode_cls_token": false, "pooling_mode_mean_tokens": true,
+                "pooling_mode_max_tokens": false}"#,
+        );
+        assert!(matches!(mean, Some(Pooling::Mean)));
+        let max = parse(r#"{"pooling_mode_max_tokens": true}"#);
+        assert!(matches!(max, Some(Pooling::Max)));
+        assert!(parse(r#"{"pooling_mode_lasttoken": true}"#).is_none());
+        assert!(
+            parse(r#"{"pooling_mode_mean_tokens": true, "pooling_mode_max_tokens": true}"#)
+                .is_none()
+        );
+    }
+
+    #[test]
+    fn test_max_pooling() {
+        // The second text has one padding token, larger than its real tokens.
+        let values = [
+            [[1f32, -2., 3.], [4., -5., 0.], [-1., 2., 2.]],
+            [[-3., -4., -1.], [-2., -6., 5.], [9., 9., 9.]],
+        ];
+        let mask = [[1i64, 1, 1], [1, 1, 0]];
+        let expected = vec![vec![4f32, 2., 3.], vec![-2., -4., 5.]];
+
+        let flat = values.iter().flatten().flatten().copied().collect();
+        let output = Array3::from_shape_vec((2, 3, 3), flat).unwrap();
+        let pooled = Pooling::Max
+            .pool(
+                &ModelOutput::Array(output),
+                &AttentionMask::Array(array![[1i64, 1, 1], [1, 1, 0]]),
+            )
+            .unwrap()
+            .to_array()
+            .unwrap();
+        assert_eq!(
+            pooled
+                .outer_iter()
+                .map(|row| row.to_vec())
+                .collect::<Vec<_>>(),
+            expected
+        );
+
+        let device = candle_core::Device::Cpu;
+        let output = Tensor::new(&values, &device).unwrap();
+        let mask = Tensor::new(&mask, &device).unwrap();
+        let pooled = Pooling::Max
+            .pool(&ModelOutput::Tensor(output), &AttentionMask::Tensor(mask))
+            .unwrap()
+            .to_tensor()
+            .unwrap();
+        assert_eq!(pooled.to_vec2::<f32>().unwrap(), expected);
+    }
+
+    #[test]
+    fn test_mean_pooling() {
+        // The second text has one padding token, left out of its mean.
+        let values = [
+            [[1f32, -2., 3.], [4., -5., 0.], [-2., 1., 3.]],
+            [[-3., -4., -1.], [-1., -6., 5.], [9., 9., 9.]],
+        ];
+        let mask = [[1i64, 1, 1], [1, 1, 0]];
+        let expected = vec![vec![1f32, -2., 2.], vec![-2., -5., 2.]];
+
+        let flat = values.iter().flatten().flatten().copied().collect();
+        let output = Array3::from_shape_vec((2, 3, 3), flat).unwrap();
+        let pooled = Pooling::Mean
+            .pool(
+                &ModelOutput::Array(output),
+                &AttentionMask::Array(array![[1i64, 1, 1], [1, 1, 0]]),
+            )
+            .unwrap()
+            .to_array()
+            .unwrap();
+        assert_eq!(
+            pooled
+                .outer_iter()
+                .map(|row| row.to_vec())
+                .collect::<Vec<_>>(),
+            expected
+        );
+
+        let device = candle_core::Device::Cpu;
+        let output = Tensor::new(&values, &device).unwrap();
+        let mask = Tensor::new(&mask, &device).unwrap();
+        let pooled = Pooling::Mean
+            .pool(&ModelOutput::Tensor(output), &AttentionMask::Tensor(mask))
+            .unwrap()
+            .to_tensor()
+            .unwrap();
+        assert_eq!(pooled.to_vec2::<f32>().unwrap(), expected);
+    }
+
+    #[test]
+    fn test_custom_pooling() {
+        // Averages the first and last token of every text.
+        let first_last = Pooling::custom(|output, _| {
+            let ModelOutput::Tensor(tensor) = output else {
+                anyhow::bail!("expected a tensor");
+            };
+            let (_, seq_len, _) = tensor.dims3()?;
+            let first = tensor.get_on_dim(1, 0)?;
+            let last = tensor.get_on_dim(1, seq_len - 1)?;
+            Ok(((first + last)? / 2.0)?)
+        });
+        let device = candle_core::Device::Cpu;
+        let output = Tensor::new(&[[[1f32, 2.], [3., 4.], [5., 6.]]], &device).unwrap();
+        let mask =
+            AttentionMask::Tensor(Tensor::ones((1, 3), candle_core::DType::U32, &device).unwrap());
+        let pooled = first_last
+            .pool(&ModelOutput::Tensor(output), &mask)
+            .unwrap()
+            .to_tensor()
+            .unwrap();
+        assert_eq!(pooled.to_vec2::<f32>().unwrap(), vec![vec![3., 4.]]);
+    }
+
+    #[test]
+    fn test_custom_pooling_array() {
+        // Sums the embeddings of the tokens that are not padding.
+        let masked_sum = Pooling::custom(|output, mask| {
+            let ModelOutput::Array(array) = output else {
+                anyhow::bail!("expected an array");
+            };
+            let output = Tensor::from_slice(
+                array.as_slice().unwrap(),
+                array.dim(),
+                &candle_core::Device::Cpu,
+            )?;
+            let mask = mask.to_tensor()?.to_dtype(candle_core::DType::F32)?;
+            Ok(output.broadcast_mul(&mask.unsqueeze(2)?)?.sum(1)?)
+        });
+        let output = Array3::from_shape_vec((1, 3, 2), vec![1f32, 2., 3., 4., 5., 6.]).unwrap();
+        let mask = AttentionMask::Array(array![[1i64, 1, 0]]);
+        let pooled = masked_sum
+            .pool(&ModelOutput::Array(output), &mask)
+            .unwrap()
+            .to_array()
+            .unwrap();
+        assert_eq!(pooled, array![[4f32, 6.]]);
+    }
+}
@@ -0,0 +1,214 @@
+//! Several ONNX Runtime sessions of one model, so that concurrent calls to a shared embedder
+//! run in parallel.
+//!
+//! A session can run concurrently, but all its runs share the threads of the session. The
+//! ONNX embedders hold a [SessionPool] instead, and each call to `embed` runs on the next
+//! session of the pool in turn, with the CPU threads split between the sessions. A pool of one
+//! session, the default, behaves like a single session. Set the size with
+//! [EmbedderBuilder::session_pool_size](crate::embeddings::embed::EmbedderBuilder::session_pool_size)
+//! or [scoped_session_pool_size], e.g. to the number of requests a server handles at once.
+
+use std::{
+    cell::Cell,
+    ops::Deref,
+    path::Path,
+    sync::atomic::{AtomicUsize, Ordering},
+};
+
+use anyhow::{anyhow, Result};
+use candle_core::DeviceLocation;
+use ort::{
+    execution_providers::{
+        CUDAExecutionProvider, CoreMLExecutionProvider, ExecutionProviderDispatch,
+    },
+    session::{builder::GraphOptimizationLevel, Session},
+};
+
+use crate::embeddings::scoped_device_location;
+
+thread_local! {
+    static POOL_SIZE: Cell<Option<usize>> = const { Cell::new(None) };
+}
+
+/// Restores the pool size set before [scoped_session_pool_size] when dropped.
+#[must_use = "the pool size is reset when the guard is dropped"]
+pub struct SessionPoolSizeGuard {
+    previous: Option<usize>,
+}
+
+impl Drop for SessionPoolSizeGuard {
+    fn drop(&mut self) {
+        POOL_SIZE.set(self.previous);
+    }
+}
+
+/// Makes the ONNX models loaded on the current thread create `size` sessions, until the
+/// returned guard is dropped. None keeps the current size, which is 1 by default.
+pub fn scoped_session_pool_size(size: Option<usize>) -> SessionPoolSizeGuard {
+    let previous = POOL_SIZE.get();
+    POOL_SIZE.set(size.or(previous));
+    SessionPoolSizeGuard { previous }
+}
+
+/// The number of sessions of the ONNX models loaded on the current thread.
+pub fn session_pool_size() -> usize {
+    POOL_SIZE.get().unwrap_or(1)
+}
+
+/// Sessions loaded from the same weights, handed out in turn. Dereferences to the first
+/// session, e.g. to read the inputs and outputs of the model.
+#[derive(Debug)]
+pub struct SessionPool {
+    sessions: Vec<Session>,
+    next: AtomicUsize,
+}
+
+impl SessionPool {
+    /// Builds `size` sessions with `build`, which is given the number of intra-op threads of
+    /// each session.
+    pub fn new<F>(size: usize, build: F) -> Result<Self>
+    where
+        F: Fn(usize) -> Result<Session>,
+    {
+        if size == 0 {
+            return Err(anyhow!("A session pool needs at least one session"));
+        }
+        let threads = std::thread::available_parallelism()?.get();
+        let intra_threads = (threads / size).max(1);
+        let sessions = (0..size)
+            .map(|_| build(intra_threads))
+            .collect::<Result<Vec<_>>>()?;
+        Ok(Self {
+            sessions,
+            next: AtomicUsize::new(0),
+        })
+    }
+
+    /// Loads [session_pool_size] sessions of the ONNX model in `weights`, on the device set with
+    /// [scoped_device](crate::embeddings::scoped_device), else on CUDA or CoreML when
+    /// available.
+    pub fn load(weights: &Path) -> Result<Self> {
+        Self::new(session_pool_size(), |intra_threads| {
+            Ok(Session::builder()?
+                .with_execution_providers(execution_providers())?
+                .with_optimization_level(GraphOptimizationLevel::Level3)?
+                .with_intra_threads(intra_threads)?
+                .commit_from_file(weights)?)
+        })
+    }
+
+    /// The session to run the next call on.
+    pub fn session(&self) -> &Session {
+        let next = self.next.fetch_add(1, Ordering::Relaxed);
+        &self.sessions[next % self.sessions.len()]
+    }
+
+    pub fn len(&self) -> usize {
+        self.sessions.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.sessions.is_empty()
+    }
+}
+
+/// The execution providers of the device set with
+/// [scoped_device](crate::embeddings::scoped_device), e.g. CUDA on the GPU of an
+/// [EmbedderPool](crate::embeddings::pool::EmbedderPool) entry. Without one, CUDA on the first
+/// GPU or CoreML when available, and the CPU otherwise.
+fn execution_providers() -> Vec<ExecutionProviderDispatch> {
+    match scoped_device_location() {
+        Some(DeviceLocation::Cpu) => Vec::new(),
+        Some(DeviceLocation::Cuda { gpu_id }) => vec![CUDAExecutionProvider::default()
+            .with_device_id(gpu_id as i32)
+            .build()],
+        Some(DeviceLocation::Metal { .. }) => vec![CoreMLExecutionProvider::default().build()],
+        None => vec![
+            CUDAExecutionProvider::default().build(),
+            CoreMLExecutionProvider::default().build(),
+        ],
+    }
+}
+
+impl Deref for SessionPool {
+    type Target = Session;
+
+    fn deref(&self) -> &Session {
+        &self.sessions[0]
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Instant;
+
+    use candle_core::Device;
+
+    use super::*;
+    use crate::embeddings::{
+        local::{bert::BertEmbed, ort_bert::OrtBertEmbedder, text_embedding::ONNXModel},
+        scoped_device,
+    };
+
+    #[test]
+    fn test_scoped_session_pool_size() {
+        assert_eq!(session_pool_size(), 1);
+        {
+            let _size = scoped_session_pool_size(Some(3));
+            let _unchanged = scoped_session_pool_size(None);
+            assert_eq!(session_pool_size(), 3);
+        }
+        assert_eq!(session_pool_size(), 1);
+        assert!(SessionPool::new(0, |_| Err(anyhow!("not built"))).is_err());
+    }
+
+    #[test]
+    fn test_execution_providers_of_scoped_device() {
+        assert_eq!(execution_providers().len(), 2);
+        let _device = scoped_device(Some(Device::Cpu));
+        assert!(execution_providers().is_empty());
+    }
+
+    #[test]
+    fn test_concurrent_calls_overlap() {
+        let embedder = {
+            let _size = scoped_session_pool_size(Some(2));
+            OrtBertEmbedder::new(Some(ONNXModel::AllMiniLML6V2), None, None, None, None).unwrap()
+        };
+        assert_eq!(embedder.model.len(), 2);
+        // Successive calls take different sessions.
+        assert!(!std::ptr::eq(
+            embedder.model.session(),
+            embedder.model.session()
+        ));
+
+        // On a single core the calls can't run in parallel.
+        if std::thread::available_parallelism().unwrap().get() < 2 {
+            return;
+        }
+        let texts = (0..64)
+            .map(|i| format!("Sentence {} of a batch embedded while another one runs.", i))
+            .collect::<Vec<_>>();
+        // The wall time of `calls` calls made at once.
+        let wall_time = |calls: usize| {
+            let start = Instant::now();
+            std::thread::scope(|scope| {
+                for _ in 0..calls {
+                    scope.spawn(|| embedder.embed(&texts, Some(64)).unwrap());
+                }
+            });
+            start.elapsed()
+        };
+        // Warms up both sessions.
+        wall_time(2);
+        let serial = wall_time(1) + wall_time(1);
+        let concurrent = wall_time(2);
+        // Serialized calls would take as long as the calls made one after the other.
+        assert!(
+            concurrent.as_secs_f64() < 0.8 * serial.as_secs_f64(),
+            "{:?} for concurrent calls, {:?} for serial ones",
+            concurrent,
+            serial
+        );
+    }
+}
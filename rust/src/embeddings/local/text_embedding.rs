@@ -81,6 +81,14 @@ pub enum ONNXModel {
     SPLADEPPENV2,
     /// onnx-models/jina-colbert-v1-en-onnx
     JinaColBERTv1,
+    /// Snowflake/snowflake-arctic-embed-xs
+    SnowflakeArcticEmbedXS,
+    /// Snowflake/snowflake-arctic-embed-s
+    SnowflakeArcticEmbedS,
+    /// Snowflake/snowflake-arctic-embed-m
+    SnowflakeArcticEmbedM,
+    /// Snowflake/snowflake-arctic-embed-l
+    SnowflakeArcticEmbedL,
 }
 
 /// Centralized function to initialize the models map.
@@ -366,6 +374,38 @@ fn init_models_map() -> HashMap<ONNXModel, ModelInfo<ONNXModel>> {
             model_code: String::from("onnx-models/jina-colbert-v1-en-onnx"),
             model_file: String::from("model.onnx"),
         },
+        ModelInfo {
+            model: ONNXModel::SnowflakeArcticEmbedXS,
+            dim: 384,
+            description: String::from("Extra small English retrieval model from Snowflake"),
+            hf_model_id: String::from("Snowflake/snowflake-arctic-embed-xs"),
+            model_code: String::from("Snowflake/snowflake-arctic-embed-xs"),
+            model_file: String::from("onnx/model.onnx"),
+        },
+        ModelInfo {
+            model: ONNXModel::SnowflakeArcticEmbedS,
+            dim: 384,
+            description: String::from("Small English retrieval model from Snowflake"),
+            hf_model_id: String::from("Snowflake/snowflake-arctic-embed-s"),
+            model_code: String::from("Snowflake/snowflake-arctic-embed-s"),
+            model_file: String::from("onnx/model.onnx"),
+        },
+        ModelInfo {
+            model: ONNXModel::SnowflakeArcticEmbedM,
+            dim: 768,
+            description: String::from("Medium English retrieval model from Snowflake"),
+            hf_model_id: String::from("Snowflake/snowflake-arctic-embed-m"),
+            model_code: String::from("Snowflake/snowflake-arctic-embed-m"),
+            model_file: String::from("onnx/model.onnx"),
+        },
+        ModelInfo {
+            model: ONNXModel::SnowflakeArcticEmbedL,
+            dim: 1024,
+            description: String::from("Large English retrieval model from Snowflake"),
+            hf_model_id: String::from("Snowflake/snowflake-arctic-embed-l"),
+            model_code: String::from("Snowflake/snowflake-arctic-embed-l"),
+            model_file: String::from("onnx/model.onnx"),
+        },
     ];
 
     // TODO: Use when out in stable
@@ -452,6 +492,91 @@ impl ONNXModel {
             ONNXModel::SPLADEPPENV1 => Some(Pooling::Mean),
             ONNXModel::SPLADEPPENV2 => Some(Pooling::Mean),
             ONNXModel::JinaColBERTv1 => None,
+
+            ONNXModel::SnowflakeArcticEmbedXS => Some(Pooling::Cls),
+            ONNXModel::SnowflakeArcticEmbedS => Some(Pooling::Cls),
+            ONNXModel::SnowflakeArcticEmbedM => Some(Pooling::Cls),
+            ONNXModel::SnowflakeArcticEmbedL => Some(Pooling::Cls),
+        }
+    }
+
+    /// Prefixes the model was trained with for asymmetric retrieval, as `(query, document)`.
+    pub fn get_default_prefixes(&self) -> (Option<&'static str>, Option<&'static str>) {
+        match self {
+            ONNXModel::BGEBaseENV15
+            | ONNXModel::BGEBaseENV15Q
+            | ONNXModel::BGELargeENV15
+            | ONNXModel::BGELargeENV15Q
+            | ONNXModel::BGESmallENV15
+            | ONNXModel::BGESmallENV15Q
+            | ONNXModel::MxbaiEmbedLargeV1
+            | ONNXModel::MxbaiEmbedLargeV1Q
+            | ONNXModel::SnowflakeArcticEmbedXS
+            | ONNXModel::SnowflakeArcticEmbedS
+            | ONNXModel::SnowflakeArcticEmbedM
+            | ONNXModel::SnowflakeArcticEmbedL => (
+                Some("Represent this sentence for searching relevant passages: "),
+                None,
+            ),
+            ONNXModel::BGESmallZHV15 => (Some("为这个句子生成表示以用于检索相关文章："), None),
+            ONNXModel::NomicEmbedTextV1
+            | ONNXModel::NomicEmbedTextV15
+            | ONNXModel::NomicEmbedTextV15Q => {
+                (Some("search_query: "), Some("search_document: "))
+            }
+            ONNXModel::MultilingualE5Small
+            | ONNXModel::MultilingualE5Base
+            | ONNXModel::MultilingualE5Large => (Some("query: "), Some("passage: ")),
+            _ => (None, None),
+        }
+    }
+
+    /// Batch size that fits most GPUs for this model. Larger models and models with a long
+    /// context get smaller batches.
+    pub fn get_default_batch_size(&self) -> usize {
+        match self {
+            ONNXModel::AllMiniLML6V2
+            | ONNXModel::AllMiniLML6V2Q
+            | ONNXModel::AllMiniLML12V2
+            | ONNXModel::AllMiniLML12V2Q
+            | ONNXModel::ParaphraseMLMiniLML12V2
+            | ONNXModel::ParaphraseMLMiniLML12V2Q
+            | ONNXModel::BGESmallENV15
+            | ONNXModel::BGESmallENV15Q
+            | ONNXModel::BGESmallZHV15
+            | ONNXModel::MultilingualE5Small
+            | ONNXModel::SnowflakeArcticEmbedXS
+            | ONNXModel::SnowflakeArcticEmbedS => 64,
+
+            ONNXModel::BGEBaseENV15
+            | ONNXModel::BGEBaseENV15Q
+            | ONNXModel::ParaphraseMLMpnetBaseV2
+            | ONNXModel::MultilingualE5Base
+            | ONNXModel::SPLADEPPENV1
+            | ONNXModel::SPLADEPPENV2
+            | ONNXModel::JinaColBERTv1
+            | ONNXModel::SnowflakeArcticEmbedM => 32,
+
+            ONNXModel::BGELargeENV15
+            | ONNXModel::BGELargeENV15Q
+            | ONNXModel::MultilingualE5Large
+            | ONNXModel::MxbaiEmbedLargeV1
+            | ONNXModel::MxbaiEmbedLargeV1Q
+            | ONNXModel::SnowflakeArcticEmbedL => 16,
+
+            // Long context models, up to 8192 tokens.
+            ONNXModel::ModernBERTBase
+            | ONNXModel::NomicEmbedTextV1
+            | ONNXModel::NomicEmbedTextV15
+            | ONNXModel::NomicEmbedTextV15Q
+            | ONNXModel::GTEBaseENV15
+            | ONNXModel::GTEBaseENV15Q
+            | ONNXModel::JINAV2SMALLEN
+            | ONNXModel::JINAV2BASEEN => 16,
+            ONNXModel::ModernBERTLarge
+            | ONNXModel::GTELargeENV15
+            | ONNXModel::GTELargeENV15Q
+            | ONNXModel::JINAV3 => 8,
         }
     }
 
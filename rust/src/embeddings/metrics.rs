@@ -0,0 +1,195 @@
+//! Opt-in timing of the batches run by local models.
+//!
+//! Call [enable_metrics] before embedding and read the totals with [metrics]. Every batch
+//! records the time spent tokenizing and the time spent in the model, which tells whether a
+//! workload is tokenizer-bound or model-bound. Each batch is also emitted as a `tracing` debug
+//! event. When disabled, recording a batch costs a single atomic load.
+
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::time::{Duration, Instant};
+
+/// The counters the functions of this module read and reset.
+static GLOBAL: MetricsCollector = MetricsCollector::new();
+
+/// Totals recorded since metrics were enabled or last reset.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct EmbedMetrics {
+    pub batches: u64,
+    pub texts: u64,
+    /// Tokens fed to the model, padding included.
+    pub tokens: u64,
+    pub tokenize_time: Duration,
+    pub model_time: Duration,
+}
+
+impl EmbedMetrics {
+    /// Tokens processed per second of tokenization and inference.
+    pub fn tokens_per_second(&self) -> f64 {
+        let seconds = (self.tokenize_time + self.model_time).as_secs_f64();
+        if seconds == 0.0 {
+            0.0
+        } else {
+            self.tokens as f64 / seconds
+        }
+    }
+
+    pub fn mean_batch_time(&self) -> Duration {
+        if self.batches == 0 {
+            Duration::ZERO
+        } else {
+            (self.tokenize_time + self.model_time) / self.batches as u32
+        }
+    }
+}
+
+pub fn enable_metrics(enabled: bool) {
+    GLOBAL.enabled.store(enabled, Ordering::Relaxed);
+}
+
+pub fn metrics_enabled() -> bool {
+    GLOBAL.enabled.load(Ordering::Relaxed)
+}
+
+pub fn metrics() -> EmbedMetrics {
+    GLOBAL.metrics()
+}
+
+pub fn reset_metrics() {
+    GLOBAL.reset();
+}
+
+/// The counters batches are recorded in.
+pub(crate) struct MetricsCollector {
+    enabled: AtomicBool,
+    batches: AtomicU64,
+    texts: AtomicU64,
+    tokens: AtomicU64,
+    tokenize_nanos: AtomicU64,
+    model_nanos: AtomicU64,
+}
+
+impl MetricsCollector {
+    const fn new() -> Self {
+        Self {
+            enabled: AtomicBool::new(false),
+            batches: AtomicU64::new(0),
+            texts: AtomicU64::new(0),
+            tokens: AtomicU64::new(0),
+            tokenize_nanos: AtomicU64::new(0),
+            model_nanos: AtomicU64::new(0),
+        }
+    }
+
+    fn metrics(&self) -> EmbedMetrics {
+        EmbedMetrics {
+            batches: self.batches.load(Ordering::Relaxed),
+            texts: self.texts.load(Ordering::Relaxed),
+            tokens: self.tokens.load(Ordering::Relaxed),
+            tokenize_time: Duration::from_nanos(self.tokenize_nanos.load(Ordering::Relaxed)),
+            model_time: Duration::from_nanos(self.model_nanos.load(Ordering::Relaxed)),
+        }
+    }
+
+    fn reset(&self) {
+        for counter in [
+            &self.batches,
+            &self.texts,
+            &self.tokens,
+            &self.tokenize_nanos,
+            &self.model_nanos,
+        ] {
+            counter.store(0, Ordering::Relaxed);
+        }
+    }
+}
+
+/// Measures one batch. Create it before tokenizing, call [BatchTimer::tokenized] once the
+/// inputs are ready and [BatchTimer::finish] after the forward pass.
+pub(crate) struct BatchTimer<'a> {
+    collector: &'a MetricsCollector,
+    start: Option<Instant>,
+    tokenized: Option<Instant>,
+}
+
+impl BatchTimer<'static> {
+    pub(crate) fn start() -> Self {
+        BatchTimer::start_in(&GLOBAL)
+    }
+}
+
+impl<'a> BatchTimer<'a> {
+    /// Measures one batch into `collector` instead of the global counters.
+    fn start_in(collector: &'a MetricsCollector) -> Self {
+        Self {
+            collector,
+            start: collector.enabled.load(Ordering::Relaxed).then(Instant::now),
+            tokenized: None,
+        }
+    }
+
+    pub(crate) fn tokenized(&mut self) {
+        if self.start.is_some() {
+            self.tokenized = Some(Instant::now());
+        }
+    }
+
+    pub(crate) fn finish(self, texts: usize, tokens: usize) {
+        let Some(start) = self.start else {
+            return;
+        };
+        let tokenized = self.tokenized.unwrap_or(start);
+        let tokenize_time = tokenized - start;
+        let model_time = tokenized.elapsed();
+
+        let collector = self.collector;
+        collector.batches.fetch_add(1, Ordering::Relaxed);
+        collector.texts.fetch_add(texts as u64, Ordering::Relaxed);
+        collector.tokens.fetch_add(tokens as u64, Ordering::Relaxed);
+        collector
+            .tokenize_nanos
+            .fetch_add(tokenize_time.as_nanos() as u64, Ordering::Relaxed);
+        collector
+            .model_nanos
+            .fetch_add(model_time.as_nanos() as u64, Ordering::Relaxed);
+        tracing::debug!(
+            texts,
+            tokens,
+            tokenize_ms = tokenize_time.as_secs_f64() * 1000.0,
+            model_ms = model_time.as_secs_f64() * 1000.0,
+            "embedded batch"
+        );
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_counters_increment() {
+        // A collector of its own, so that models embedding in other tests are not counted.
+        let collector = MetricsCollector::new();
+        BatchTimer::start_in(&collector).finish(4, 64);
+        assert_eq!(collector.metrics().batches, 0);
+
+        collector.enabled.store(true, Ordering::Relaxed);
+        for _ in 0..3 {
+            let mut timer = BatchTimer::start_in(&collector);
+            std::thread::sleep(Duration::from_millis(2));
+            timer.tokenized();
+            std::thread::sleep(Duration::from_millis(2));
+            timer.finish(4, 64);
+        }
+
+        let metrics = collector.metrics();
+        assert_eq!(metrics.batches, 3);
+        assert_eq!(metrics.texts, 12);
+        assert_eq!(metrics.tokens, 192);
+        assert!(metrics.tokenize_time >= Duration::from_millis(6));
+        assert!(metrics.model_time >= Duration::from_millis(6));
+        assert!(metrics.tokens_per_second() > 0.0);
+
+        collector.reset();
+        assert_eq!(collector.metrics(), EmbedMetrics::default());
+    }
+}
@@ -1,17 +1,37 @@
 //! This module contains the different embedding models that can be used to generate embeddings for the text data.
 
-use std::{collections::HashMap, rc::Rc};
+use std::{
+    cell::{Cell, RefCell},
+    collections::HashMap,
+    rc::Rc,
+};
 
-use candle_core::{Device, Tensor};
-use embed::{EmbedData, Embedder, EmbeddingResult};
+use candle_core::{DType, Device, DeviceLocation, Tensor};
+use embed::{
+    EmbedData, Embedder, EmbeddingResult, Metric, ModelDtype, OutputDtype, EMBEDDING_MODEL_KEY,
+    GRANULARITY_KEY,
+};
 
 use crate::file_processor::audio::audio_processor::Segment;
 
+pub mod async_embedder;
+pub mod base64_embedding;
+pub mod batch_size;
+#[cfg(feature = "cache")]
+pub mod cache;
 pub mod cloud;
+pub mod diff;
+pub mod dimensions;
 pub mod embed;
+pub mod fusion;
+pub mod hyde;
 pub mod local;
+pub mod metrics;
+pub mod pool;
+pub mod registry;
 pub mod utils;
 
+use ndarray::{Array2, Axis};
 use rayon::prelude::*;
 pub fn get_text_metadata(
     encodings: &Rc<Vec<EmbeddingResult>>,
@@ -26,6 +46,137 @@ pub fn get_text_metadata(
     Ok(final_embeddings)
 }
 
+/// Records whether the embedder that produced `embeddings` normalized them.
+pub fn mark_normalized(embeddings: &mut [EmbedData], is_normalized: bool) {
+    for embedding in embeddings {
+        embedding.is_normalized = is_normalized;
+    }
+}
+
+/// Records the id of the model that produced `embeddings` in their `embedding_model` metadata
+/// entry. An empty id, from embedders that do not describe their model, is ignored.
+pub fn mark_embedding_model(embeddings: &mut [EmbedData], model_id: &str) {
+    if model_id.is_empty() {
+        return;
+    }
+    for embedding in embeddings {
+        embedding
+            .metadata
+            .get_or_insert_with(HashMap::new)
+            .insert(EMBEDDING_MODEL_KEY.to_string(), model_id.to_string());
+    }
+}
+
+/// Sets the `granularity` metadata entry of `embeddings`, see [GRANULARITY_KEY].
+pub fn mark_granularity(embeddings: &mut [EmbedData], granularity: &str) {
+    for embedding in embeddings {
+        embedding
+            .metadata
+            .get_or_insert_with(HashMap::new)
+            .insert(GRANULARITY_KEY.to_string(), granularity.to_string());
+    }
+}
+
+/// Averages the chunk embeddings of a document into a single vector, weighting each chunk by
+/// its length in characters, and L2-normalizes the mean. The returned [EmbedData] has no text
+/// and holds `metadata` with `granularity` set to "document".
+///
+/// Returns None when there are no chunks or their embeddings are not dense vectors.
+pub fn document_embedding(
+    embeddings: &[EmbedData],
+    metadata: &Option<HashMap<String, String>>,
+) -> Option<EmbedData> {
+    let mut sum: Vec<f32> = Vec::new();
+    let mut total_weight = 0.0;
+    for embedding in embeddings {
+        let vector = embedding.embedding.to_dense().ok()?;
+        let weight = embedding
+            .text
+            .as_ref()
+            .map_or(1, |text| text.chars().count().max(1)) as f32;
+        if sum.is_empty() {
+            sum = vec![0.0; vector.len()];
+        } else if sum.len() != vector.len() {
+            return None;
+        }
+        for (total, value) in sum.iter_mut().zip(vector) {
+            *total += weight * value;
+        }
+        total_weight += weight;
+    }
+    if total_weight == 0.0 {
+        return None;
+    }
+    let norm = sum.iter().map(|x| x * x).sum::<f32>().sqrt().max(1e-12);
+    let mean = sum.iter().map(|x| x / norm).collect();
+    let mut document = EmbedData::new(EmbeddingResult::DenseVector(mean), None, metadata.clone());
+    document.is_normalized = true;
+    mark_granularity(std::slice::from_mut(&mut document), "document");
+    Some(document)
+}
+
+/// Converts the dense vectors of `embeddings`, including the ones of every model of
+/// [crate::embed_file_multi], to `output_dtype`. `None` keeps them as they are.
+pub fn convert_output_dtype(embeddings: &mut [EmbedData], output_dtype: Option<OutputDtype>) {
+    let Some(output_dtype) = output_dtype else {
+        return;
+    };
+    let convert = |embedding: &mut EmbeddingResult| {
+        let value = std::mem::replace(embedding, EmbeddingResult::DenseVector(Vec::new()));
+        *embedding = value.to_dtype(output_dtype);
+    };
+    for embedding in embeddings {
+        convert(&mut embedding.embedding);
+        embedding.embeddings.values_mut().for_each(convert);
+    }
+}
+
+/// Returns the index and score of the `k` embeddings most similar to `query`, best first. See
+/// [EmbeddingResult::similarity] for the score.
+pub fn top_k(
+    query: &EmbeddingResult,
+    embeddings: &[EmbedData],
+    k: usize,
+) -> anyhow::Result<Vec<(usize, f32)>> {
+    top_k_with_metric(query, embeddings, k, Metric::Cosine)
+}
+
+/// Like [top_k], scoring the embeddings with `metric`. Multi-vector embeddings are only
+/// supported with [Metric::Cosine].
+pub fn top_k_with_metric(
+    query: &EmbeddingResult,
+    embeddings: &[EmbedData],
+    k: usize,
+    metric: Metric,
+) -> anyhow::Result<Vec<(usize, f32)>> {
+    let dense_query = match metric {
+        Metric::Cosine => None,
+        _ => Some(query.to_dense()?),
+    };
+    let mut scores = embeddings
+        .iter()
+        .map(|embedding| match &dense_query {
+            None => query.similarity(&embedding.embedding),
+            Some(query) => {
+                let vector = embedding.embedding.to_dense()?;
+                if vector.len() != query.len() {
+                    return Err(anyhow::anyhow!(
+                        "Embedding dimension {} does not match query dimension {}",
+                        vector.len(),
+                        query.len()
+                    ));
+                }
+                Ok(metric.score(query, &vector))
+            }
+        })
+        .enumerate()
+        .map(|(i, score)| score.map(|score| (i, score)))
+        .collect::<anyhow::Result<Vec<_>>>()?;
+    scores.sort_by(|a, b| b.1.total_cmp(&a.1));
+    scores.truncate(k);
+    Ok(scores)
+}
+
 pub fn get_audio_metadata<T: AsRef<std::path::Path>>(
     encodings: Vec<EmbeddingResult>,
     segments: Vec<Segment>,
@@ -63,6 +214,16 @@ pub fn text_batch_from_audio(segments: &[Segment]) -> Vec<String> {
         .collect()
 }
 
+/// The full transcript of an audio file, the trimmed text of its segments separated by spaces.
+pub fn transcript_from_audio(segments: &[Segment]) -> String {
+    segments
+        .iter()
+        .map(|segment| segment.dr.text.trim())
+        .filter(|text| !text.is_empty())
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
 pub async fn embed_audio<T: AsRef<std::path::Path>>(
     embedder: &Embedder,
     segments: Vec<Segment>,
@@ -70,15 +231,108 @@ pub async fn embed_audio<T: AsRef<std::path::Path>>(
     batch_size: Option<usize>,
 ) -> Result<Vec<EmbedData>, anyhow::Error> {
     let text_batch = text_batch_from_audio(&segments);
-    let encodings = embedder.embed(&text_batch, batch_size).await?;
-    get_audio_metadata(encodings, segments, audio_file)
+    let encodings = embedder
+        .embed_document_batch(&text_batch, batch_size)
+        .await?;
+    let mut embeddings = get_audio_metadata(encodings, segments, audio_file)?;
+    mark_normalized(&mut embeddings, embedder.is_normalized());
+    mark_embedding_model(&mut embeddings, &embedder.model_id());
+    Ok(embeddings)
 }
 
+/// Norm below which a vector is treated as a zero vector by [normalize_l2].
+pub const NORM_EPSILON: f32 = 1e-12;
+
+/// L2-normalizes the rows of `v`, see [normalize_l2_with_epsilon].
 pub fn normalize_l2(v: &Tensor) -> candle_core::Result<Tensor> {
-    v.broadcast_div(&v.sqr()?.sum_keepdim(1)?.sqrt()?)
+    normalize_l2_with_epsilon(v, NORM_EPSILON)
+}
+
+/// L2-normalizes the rows of `v`. Rows with a norm below `epsilon`, such as the all-zero output
+/// of a sparse model for an empty text, are returned as zero vectors instead of NaN.
+pub fn normalize_l2_with_epsilon(v: &Tensor, epsilon: f32) -> candle_core::Result<Tensor> {
+    let norms = v.sqr()?.sum_keepdim(1)?.sqrt()?;
+    let is_zero = norms.lt(epsilon as f64)?;
+    let zero_vectors = is_zero
+        .to_dtype(DType::U32)?
+        .sum_all()?
+        .to_scalar::<u32>()?;
+    let normalized = v.broadcast_div(&norms.maximum(epsilon as f64)?)?;
+    if zero_vectors == 0 {
+        return Ok(normalized);
+    }
+    tracing::warn!(
+        "{} vectors with a norm of about zero are returned as zero vectors",
+        zero_vectors
+    );
+    is_zero
+        .broadcast_as(v.shape())?
+        .where_cond(&v.zeros_like()?, &normalized)
+}
+
+/// [normalize_l2] for the ndarray outputs of ONNX models.
+pub fn normalize_l2_ndarray(v: &Array2<f32>) -> Array2<f32> {
+    normalize_l2_ndarray_with_epsilon(v, NORM_EPSILON)
+}
+
+/// [normalize_l2_with_epsilon] for the ndarray outputs of ONNX models.
+pub fn normalize_l2_ndarray_with_epsilon(v: &Array2<f32>, epsilon: f32) -> Array2<f32> {
+    let norms = v.mapv(|x| x * x).sum_axis(Axis(1)).mapv(f32::sqrt);
+    let zero_vectors = norms.iter().filter(|norm| **norm < epsilon).count();
+    if zero_vectors > 0 {
+        tracing::warn!(
+            "{} vectors with a norm of about zero are returned as zero vectors",
+            zero_vectors
+        );
+    }
+    let mut normalized = v.to_owned();
+    for (mut row, norm) in normalized.outer_iter_mut().zip(norms) {
+        if norm < epsilon {
+            row.fill(0.0);
+        } else {
+            row /= norm;
+        }
+    }
+    normalized
+}
+
+thread_local! {
+    static DEVICE: RefCell<Option<Device>> = const { RefCell::new(None) };
+    static MODEL_DTYPE: Cell<Option<ModelDtype>> = const { Cell::new(None) };
+}
+
+/// Restores the device set before [scoped_device] when dropped.
+#[must_use = "the device is reset when the guard is dropped"]
+pub struct DeviceGuard {
+    previous: Option<Device>,
 }
 
+impl Drop for DeviceGuard {
+    fn drop(&mut self) {
+        DEVICE.set(self.previous.take());
+    }
+}
+
+/// Makes the models loaded on the current thread use `device`, until the returned guard is
+/// dropped. None keeps the current device, see [select_device] for the default.
+pub fn scoped_device(device: Option<Device>) -> DeviceGuard {
+    let previous = DEVICE.with_borrow(|current| current.clone());
+    DEVICE.set(device.or_else(|| previous.clone()));
+    DeviceGuard { previous }
+}
+
+/// The device set with [scoped_device] on the current thread, None when models are loaded on
+/// the default one of [select_device].
+pub(crate) fn scoped_device_location() -> Option<DeviceLocation> {
+    DEVICE.with_borrow(|device| device.as_ref().map(Device::location))
+}
+
+/// The device models are loaded on: the one set with [scoped_device], else the first GPU of
+/// the enabled backend when available, else the CPU.
 pub fn select_device() -> Device {
+    if let Some(device) = DEVICE.with_borrow(|device| device.clone()) {
+        return device;
+    }
     #[cfg(feature = "metal")]
     {
         Device::new_metal(0).unwrap_or(Device::Cpu)
@@ -92,3 +346,205 @@ pub fn select_device() -> Device {
         Device::Cpu
     }
 }
+
+/// Restores the model dtype set before [scoped_model_dtype] when dropped.
+#[must_use = "the model dtype is reset when the guard is dropped"]
+pub struct ModelDtypeGuard {
+    previous: Option<ModelDtype>,
+}
+
+impl Drop for ModelDtypeGuard {
+    fn drop(&mut self) {
+        MODEL_DTYPE.set(self.previous);
+    }
+}
+
+/// Makes the models loaded on the current thread load their weights in `dtype`, until the
+/// returned guard is dropped. None keeps the current setting, see [select_dtype] for the
+/// default.
+pub fn scoped_model_dtype(dtype: Option<ModelDtype>) -> ModelDtypeGuard {
+    let previous = MODEL_DTYPE.get();
+    MODEL_DTYPE.set(dtype.or(previous));
+    ModelDtypeGuard { previous }
+}
+
+/// The dtype the weights of a model are loaded in on `device`: the one set with
+/// [scoped_model_dtype], else on GPUs the `torch_dtype` the weights were saved in, read from
+/// the `config.json` of the model, else f32.
+///
+/// bf16 falls back to f32 on the CPU, with a warning, as its CPU kernels are slow.
+pub fn select_dtype(device: &Device, config: &str) -> DType {
+    let dtype = match MODEL_DTYPE.get() {
+        Some(ModelDtype::F32) => DType::F32,
+        Some(ModelDtype::F16) => DType::F16,
+        Some(ModelDtype::BF16) => DType::BF16,
+        None if device.is_cpu() => DType::F32,
+        None => serde_json::from_str::<serde_json::Value>(config)
+            .ok()
+            .and_then(|config| match config["torch_dtype"].as_str()? {
+                "float16" => Some(DType::F16),
+                "bfloat16" => Some(DType::BF16),
+                _ => None,
+            })
+            .unwrap_or(DType::F32),
+    };
+    if dtype == DType::BF16 && device.is_cpu() {
+        tracing::warn!("bf16 is slow on the CPU, loading the model in f32 instead");
+        return DType::F32;
+    }
+    dtype
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_select_dtype() {
+        let config = r#"{"torch_dtype": "bfloat16"}"#;
+        assert_eq!(select_dtype(&Device::Cpu, config), DType::F32);
+        {
+            let _dtype = scoped_model_dtype(Some(ModelDtype::F16));
+            assert_eq!(select_dtype(&Device::Cpu, config), DType::F16);
+            let _unchanged = scoped_model_dtype(None);
+            assert_eq!(select_dtype(&Device::Cpu, "{}"), DType::F16);
+        }
+        let _dtype = scoped_model_dtype(Some(ModelDtype::BF16));
+        assert_eq!(select_dtype(&Device::Cpu, config), DType::F32);
+    }
+
+    #[test]
+    fn test_mark_normalized() {
+        let encodings = Rc::new(vec![EmbeddingResult::DenseVector(vec![0.6, 0.8])]);
+        let mut embeddings =
+            get_text_metadata(&encodings, &vec!["hello".to_string()], &None).unwrap();
+        assert!(!embeddings[0].is_normalized);
+        mark_normalized(&mut embeddings, true);
+        assert!(embeddings[0].is_normalized);
+    }
+
+    #[test]
+    fn test_convert_output_dtype() {
+        let encodings = Rc::new(vec![EmbeddingResult::DenseVector(vec![0.6, 0.8])]);
+        let mut embeddings =
+            get_text_metadata(&encodings, &vec!["hello".to_string()], &None).unwrap();
+        convert_output_dtype(&mut embeddings, None);
+        assert!(matches!(
+            embeddings[0].embedding,
+            EmbeddingResult::DenseVector(_)
+        ));
+        convert_output_dtype(&mut embeddings, Some(OutputDtype::F16));
+        let EmbeddingResult::HalfVector(half) = &embeddings[0].embedding else {
+            panic!("expected a half precision vector");
+        };
+        assert!((half[1].to_f32() - 0.8).abs() < 1e-3);
+    }
+
+    #[test]
+    fn test_normalize_zero_vector() {
+        let v = Tensor::new(&[[0.0f32, 0.0], [3.0, 4.0]], &Device::Cpu).unwrap();
+        let normalized = normalize_l2(&v).unwrap().to_vec2::<f32>().unwrap();
+        assert_eq!(normalized, vec![vec![0.0, 0.0], vec![0.6, 0.8]]);
+
+        let v = Array2::from_shape_vec((2, 2), vec![0.0f32, 0.0, 3.0, 4.0]).unwrap();
+        let normalized = normalize_l2_ndarray(&v);
+        assert!(normalized.iter().all(|x| x.is_finite()));
+        assert_eq!(normalized.row(0).to_vec(), vec![0.0, 0.0]);
+        assert!((normalized[[1, 0]] - 0.6).abs() < 1e-6);
+        assert!((normalized[[1, 1]] - 0.8).abs() < 1e-6);
+    }
+
+    #[test]
+    fn test_document_embedding() {
+        let encodings = Rc::new(vec![
+            EmbeddingResult::DenseVector(vec![1.0, 0.0, 2.0]),
+            EmbeddingResult::DenseVector(vec![0.0, 3.0, 1.0]),
+        ]);
+        let texts = vec!["a".repeat(30), "b".repeat(10)];
+        let embeddings = get_text_metadata(&encodings, &texts, &None).unwrap();
+        let metadata = Some(HashMap::from([(
+            "file_name".to_string(),
+            "doc.txt".to_string(),
+        )]));
+        let document = document_embedding(&embeddings, &metadata).unwrap();
+
+        // The mean weighted by length is (30 * [1, 0, 2] + 10 * [0, 3, 1]) / 40, normalized.
+        let mean = [30.0 / 40.0, 30.0 / 40.0, 70.0 / 40.0];
+        let norm = mean.iter().map(|x: &f32| x * x).sum::<f32>().sqrt();
+        let vector = document.embedding.to_dense().unwrap();
+        for (value, expected) in vector.iter().zip(mean) {
+            assert!((value - expected / norm).abs() < 1e-6);
+        }
+        assert!(document.is_normalized);
+        assert!(document.text.is_none());
+        let metadata = document.metadata.unwrap();
+        assert_eq!(metadata[GRANULARITY_KEY], "document");
+        assert_eq!(metadata["file_name"], "doc.txt");
+
+        assert!(document_embedding(&[], &None).is_none());
+        let multi = EmbedData::new(EmbeddingResult::MultiVector(vec![vec![1.0]]), None, None);
+        assert!(document_embedding(&[multi], &None).is_none());
+    }
+
+    #[test]
+    fn test_top_k() {
+        let encodings = Rc::new(vec![
+            EmbeddingResult::DenseVector(vec![1.0, 0.0]),
+            EmbeddingResult::DenseVector(vec![0.0, 2.0]),
+            EmbeddingResult::DenseVector(vec![1.0, 1.0]),
+        ]);
+        let texts = vec!["x".to_string(), "y".to_string(), "xy".to_string()];
+        let embeddings = get_text_metadata(&encodings, &texts, &None).unwrap();
+        let query = EmbeddingResult::DenseVector(vec![0.0, 1.0]);
+        let top = top_k(&query, &embeddings, 2).unwrap();
+        assert_eq!(top.iter().map(|(i, _)| *i).collect::<Vec<_>>(), vec![1, 2]);
+        assert!((top[0].1 - 1.0).abs() < 1e-6);
+
+        let multi = EmbeddingResult::MultiVector(vec![vec![1.0, 0.0]]);
+        assert!(top_k(&multi, &embeddings, 2).is_err());
+    }
+
+    #[test]
+    fn test_top_k_with_metric() {
+        let embeddings = [
+            vec![1.2, 0.0],
+            vec![3.0, 3.0],
+            vec![0.5, 0.6],
+            vec![-1.0, 0.0],
+        ]
+        .into_iter()
+        .map(|vector| EmbedData::new(EmbeddingResult::DenseVector(vector), None, None))
+        .collect::<Vec<_>>();
+        let query = EmbeddingResult::DenseVector(vec![0.6, 0.5]);
+        let nearest = |metric| {
+            top_k_with_metric(&query, &embeddings, 4, metric)
+                .unwrap()
+                .into_iter()
+                .map(|(i, _)| i)
+                .collect::<Vec<_>>()
+        };
+        // The direction of [3, 3] is closest, but [0.5, 0.6] is nearest and [3, 3] is longest.
+        assert_eq!(nearest(Metric::Cosine), vec![1, 2, 0, 3]);
+        assert_eq!(nearest(Metric::Dot), vec![1, 0, 2, 3]);
+        assert_eq!(nearest(Metric::Euclidean), vec![2, 0, 3, 1]);
+        let top = top_k_with_metric(&query, &embeddings, 1, Metric::Euclidean).unwrap();
+        assert!((top[0].1 + 0.02f32.sqrt()).abs() < 1e-6);
+
+        let bits = [
+            vec![1.0, 0.0, 1.0, 1.0],
+            vec![0.0, 1.0, 0.0, 0.0],
+            vec![1.0, 0.0, 0.0, 1.0],
+        ]
+        .into_iter()
+        .map(|vector| EmbedData::new(EmbeddingResult::DenseVector(vector), None, None))
+        .collect::<Vec<_>>();
+        let query = EmbeddingResult::DenseVector(vec![1.0, 0.0, 0.0, 0.0]);
+        let top = top_k_with_metric(&query, &bits, 3, Metric::Hamming).unwrap();
+        assert_eq!(top, vec![(2, -1.0), (0, -2.0), (1, -2.0)]);
+
+        let multi = EmbeddingResult::MultiVector(vec![vec![1.0, 0.0]]);
+        assert!(top_k_with_metric(&multi, &embeddings, 2, Metric::Dot).is_err());
+        let short = EmbeddingResult::DenseVector(vec![1.0]);
+        assert!(top_k_with_metric(&short, &embeddings, 2, Metric::Euclidean).is_err());
+    }
+}
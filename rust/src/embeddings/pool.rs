@@ -0,0 +1,144 @@
+//! Copies of a model on several devices, to embed a directory on every GPU of a server.
+//!
+//! Load an [EmbedderPool] and set it with
+//! [TextEmbedConfig::with_embedder_pool](crate::config::TextEmbedConfig::with_embedder_pool).
+//! [embed_directory_stream](crate::embed_directory_stream) then hands each buffer of chunks to
+//! the first copy that is free, so that every device stays busy and faster ones take more of
+//! the work.
+//!
+//! ```rust,no_run
+//! use std::sync::Arc;
+//!
+//! use embed_anything::{
+//!     config::TextEmbedConfig,
+//!     embed_directory_stream,
+//!     embeddings::{
+//!         embed::{EmbedData, Embedder},
+//!         pool::EmbedderPool,
+//!     },
+//! };
+//!
+//! async fn embed_on_every_gpu() {
+//!     let devices = (0..8).map(|i| format!("cuda:{}", i)).collect::<Vec<_>>();
+//!     let load = || {
+//!         Embedder::from_pretrained_hf("bert", "sentence-transformers/all-MiniLM-L6-v2", None, None)
+//!     };
+//!     let pool = EmbedderPool::load(&devices, load).unwrap();
+//!     let embedder = pool.embedders()[0].1.clone();
+//!     let config = TextEmbedConfig::default().with_embedder_pool(Arc::new(pool));
+//!     let adapter = None::<fn(Vec<EmbedData>)>;
+//!     embed_directory_stream("docs".into(), &embedder, None, Some(&config), adapter)
+//!         .await
+//!         .unwrap();
+//! }
+//! ```
+
+use std::sync::Arc;
+
+use anyhow::{anyhow, Result};
+use candle_core::Device;
+
+use super::{embed::Embedder, scoped_device};
+
+/// Parses a device name: "cpu", "cuda" or "metal" for the first GPU, or "cuda:N" and "metal:N"
+/// for the GPU with ordinal N. GPUs fail to load when the matching feature is not enabled.
+pub fn parse_device(name: &str) -> Result<Device> {
+    let (backend, ordinal) = match name.trim().split_once(':') {
+        Some((backend, ordinal)) => (
+            backend,
+            ordinal
+                .parse::<usize>()
+                .map_err(|_| anyhow!("Invalid device ordinal in {}", name))?,
+        ),
+        None => (name.trim(), 0),
+    };
+    match backend.to_lowercase().as_str() {
+        "cpu" => Ok(Device::Cpu),
+        "cuda" => Ok(Device::new_cuda(ordinal)?),
+        "metal" => Ok(Device::new_metal(ordinal)?),
+        _ => Err(anyhow!(
+            "Unknown device {}, expected cpu, cuda:N or metal:N",
+            name
+        )),
+    }
+}
+
+/// Embedders keyed by the name of the device they run on.
+#[derive(Clone)]
+pub struct EmbedderPool {
+    embedders: Vec<(String, Arc<Embedder>)>,
+}
+
+impl EmbedderPool {
+    /// A pool of already loaded embedders, each with the name of its device. Fails when
+    /// `embedders` is empty.
+    pub fn new(embedders: Vec<(String, Arc<Embedder>)>) -> Result<Self> {
+        if embedders.is_empty() {
+            return Err(anyhow!("An embedder pool needs at least one embedder"));
+        }
+        Ok(Self { embedders })
+    }
+
+    /// Calls `load` once per device of `devices`, see [parse_device], with the models it loads
+    /// placed on that device, ONNX models included. Cloud models are not placed on a device, a
+    /// pool of them only sends more requests at once.
+    pub fn load<F, E>(devices: &[String], load: F) -> Result<Self>
+    where
+        F: Fn() -> Result<E>,
+        E: Into<Arc<Embedder>>,
+    {
+        let embedders = devices
+            .iter()
+            .map(|name| {
+                let _device = scoped_device(Some(parse_device(name)?));
+                let embedder = load().map_err(|e| anyhow!("Loading on {}: {}", name, e))?;
+                Ok((name.clone(), embedder.into()))
+            })
+            .collect::<Result<Vec<_>>>()?;
+        Self::new(embedders)
+    }
+
+    /// The embedders with the name of their device, in the order they were given.
+    pub fn embedders(&self) -> &[(String, Arc<Embedder>)] {
+        &self.embedders
+    }
+
+    pub fn devices(&self) -> Vec<&str> {
+        self.embedders
+            .iter()
+            .map(|(device, _)| device.as_str())
+            .collect()
+    }
+
+    pub fn len(&self) -> usize {
+        self.embedders.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.embedders.is_empty()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::embeddings::select_device;
+
+    #[test]
+    fn test_parse_device() {
+        assert!(parse_device("cpu").unwrap().is_cpu());
+        assert!(parse_device(" CPU ").unwrap().is_cpu());
+        assert!(parse_device("cuda:x").is_err());
+        assert!(parse_device("tpu:0").is_err());
+    }
+
+    #[test]
+    fn test_load_on_device() {
+        let pool = EmbedderPool::load(&["cpu".to_string()], || {
+            assert!(select_device().is_cpu());
+            Err::<Embedder, _>(anyhow!("no model"))
+        });
+        assert!(pool.err().unwrap().to_string().contains("Loading on cpu"));
+        assert!(EmbedderPool::new(Vec::new()).is_err());
+    }
+}
@@ -0,0 +1,217 @@
+//! Several models served by name, for servers where each request picks its model.
+//!
+//! Models are registered with a loader and only loaded on first use. With a cap on the number
+//! of loaded models, loading one more evicts the least recently used one, which is loaded
+//! again on its next use.
+//!
+//! ```rust,no_run
+//! use embed_anything::embeddings::{embed::Embedder, registry::ModelRegistry};
+//!
+//! async fn serve(tenant_model: &str, queries: Vec<String>) {
+//!     let registry = ModelRegistry::new(Some(2));
+//!     registry.register("minilm", || {
+//!         Embedder::from_pretrained_hf("bert", "sentence-transformers/all-MiniLM-L6-v2", None, None)
+//!     });
+//!     registry.register("bge", || {
+//!         Embedder::from_pretrained_hf("bert", "BAAI/bge-small-en-v1.5", None, None)
+//!     });
+//!     let embeddings = registry
+//!         .embed_query(tenant_model, queries, None)
+//!         .await
+//!         .unwrap();
+//! }
+//! ```
+
+use std::collections::{HashMap, VecDeque};
+use std::sync::{Arc, Mutex};
+
+use anyhow::{anyhow, Result};
+
+use super::embed::{EmbedData, Embedder};
+use crate::config::TextEmbedConfig;
+
+/// Loads a model of a [ModelRegistry].
+pub type ModelLoader = Arc<dyn Fn() -> Result<Arc<Embedder>> + Send + Sync>;
+
+#[derive(Default)]
+struct Models {
+    loaders: HashMap<String, ModelLoader>,
+    /// Loaded models, from the least to the most recently used.
+    loaded: VecDeque<(String, Arc<Embedder>)>,
+}
+
+/// Models keyed by name, loaded on first use and evicted in least recently used order once
+/// more than `max_models` are loaded.
+pub struct ModelRegistry {
+    max_models: Option<usize>,
+    models: Mutex<Models>,
+}
+
+impl ModelRegistry {
+    /// A registry keeping at most `max_models` models loaded, or every model used when None.
+    pub fn new(max_models: Option<usize>) -> Self {
+        Self {
+            max_models: max_models.map(|max_models| max_models.max(1)),
+            models: Mutex::new(Models::default()),
+        }
+    }
+
+    /// Registers a model under `name`, loaded with `load` on first use. Replaces the model
+    /// registered under the same name, unloading it.
+    pub fn register<F>(&self, name: &str, load: F)
+    where
+        F: Fn() -> Result<Embedder> + Send + Sync + 'static,
+    {
+        self.register_loader(name, Arc::new(move || load().map(Arc::new)));
+    }
+
+    /// Registers a model whose loader returns a shared embedder, see [ModelRegistry::register].
+    pub fn register_loader(&self, name: &str, loader: ModelLoader) {
+        let mut models = self.models.lock().unwrap();
+        models.loaders.insert(name.to_string(), loader);
+        models.loaded.retain(|(loaded, _)| loaded != name);
+    }
+
+    /// The model registered under `name`, loading it when it is not loaded. Loading a model
+    /// evicts the least recently used one when the registry is full. An evicted model is
+    /// dropped once the requests using it complete.
+    pub fn get(&self, name: &str) -> Result<Arc<Embedder>> {
+        let loader = {
+            let mut models = self.models.lock().unwrap();
+            if let Some(embedder) = models.touch(name) {
+                return Ok(embedder);
+            }
+            models
+                .loaders
+                .get(name)
+                .cloned()
+                .ok_or_else(|| anyhow!("No model registered under {:?}", name))?
+        };
+        // Loading can take seconds, the other models are served in the meantime.
+        let embedder = loader()?;
+
+        let mut models = self.models.lock().unwrap();
+        // Another request may have loaded the model in the meantime.
+        if let Some(embedder) = models.touch(name) {
+            return Ok(embedder);
+        }
+        if let Some(max_models) = self.max_models {
+            while models.loaded.len() >= max_models {
+                models.loaded.pop_front();
+            }
+        }
+        models
+            .loaded
+            .push_back((name.to_string(), Arc::clone(&embedder)));
+        Ok(embedder)
+    }
+
+    /// Embeds queries with the model registered under `name`, see [crate::embed_query].
+    pub async fn embed_query(
+        &self,
+        name: &str,
+        query: Vec<String>,
+        config: Option<&TextEmbedConfig>,
+    ) -> Result<Vec<EmbedData>> {
+        let embedder = self.get(name)?;
+        crate::embed_query(query, &embedder, config).await
+    }
+
+    /// Unloads the model registered under `name`, which stays registered. Returns whether it
+    /// was loaded.
+    pub fn unload(&self, name: &str) -> bool {
+        let mut models = self.models.lock().unwrap();
+        let len = models.loaded.len();
+        models.loaded.retain(|(loaded, _)| loaded != name);
+        models.loaded.len() < len
+    }
+
+    /// The names of the registered models, sorted.
+    pub fn registered(&self) -> Vec<String> {
+        let models = self.models.lock().unwrap();
+        let mut names = models.loaders.keys().cloned().collect::<Vec<_>>();
+        names.sort();
+        names
+    }
+
+    /// The names of the loaded models, from the least to the most recently used.
+    pub fn loaded(&self) -> Vec<String> {
+        let models = self.models.lock().unwrap();
+        models.loaded.iter().map(|(name, _)| name.clone()).collect()
+    }
+
+    pub fn max_models(&self) -> Option<usize> {
+        self.max_models
+    }
+}
+
+impl Models {
+    /// The loaded model `name`, moved to the most recently used end.
+    fn touch(&mut self, name: &str) -> Option<Arc<Embedder>> {
+        let index = self.loaded.iter().position(|(loaded, _)| loaded == name)?;
+        let model = self.loaded.remove(index)?;
+        let embedder = Arc::clone(&model.1);
+        self.loaded.push_back(model);
+        Some(embedder)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    use super::*;
+    use crate::embeddings::{cloud::openai::OpenAIEmbedder, embed::TextEmbedder};
+
+    /// Registers a model that makes no request when loaded, counting its loads.
+    fn register(registry: &ModelRegistry, name: &str) -> Arc<AtomicUsize> {
+        let loads = Arc::new(AtomicUsize::new(0));
+        let counter = Arc::clone(&loads);
+        registry.register(name, move || {
+            counter.fetch_add(1, Ordering::SeqCst);
+            let embedder = OpenAIEmbedder::new("text-embedding-3-small".into(), Some("key".into()));
+            Ok(Embedder::Text(TextEmbedder::OpenAI(embedder)))
+        });
+        loads
+    }
+
+    #[test]
+    fn test_lazy_loading() {
+        let registry = ModelRegistry::new(None);
+        let loads = register(&registry, "a");
+        assert_eq!(loads.load(Ordering::SeqCst), 0);
+        assert!(registry.loaded().is_empty());
+
+        let first = registry.get("a").unwrap();
+        let second = registry.get("a").unwrap();
+        assert!(Arc::ptr_eq(&first, &second));
+        assert_eq!(loads.load(Ordering::SeqCst), 1);
+        assert_eq!(registry.loaded(), ["a"]);
+
+        let error = registry.get("b").err().unwrap();
+        assert_eq!(error.to_string(), "No model registered under \"b\"");
+    }
+
+    #[test]
+    fn test_lru_eviction() {
+        let registry = ModelRegistry::new(Some(2));
+        let loads = ["a", "b", "c"].map(|name| register(&registry, name));
+        let count = |i: usize| loads[i].load(Ordering::SeqCst);
+
+        registry.get("a").unwrap();
+        registry.get("b").unwrap();
+        // "a" is now more recently used than "b", which is evicted to load "c".
+        registry.get("a").unwrap();
+        registry.get("c").unwrap();
+        assert_eq!(registry.loaded(), ["a", "c"]);
+
+        registry.get("b").unwrap();
+        assert_eq!(registry.loaded(), ["c", "b"]);
+        assert_eq!((count(0), count(1), count(2)), (1, 2, 1));
+
+        assert!(registry.unload("c"));
+        assert!(!registry.unload("c"));
+        assert_eq!(registry.loaded(), ["b"]);
+        assert_eq!(registry.registered(), ["a", "b", "c"]);
+    }
+}
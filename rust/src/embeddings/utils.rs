@@ -1,7 +1,19 @@
-use anyhow::Error as E;
+use std::{
+    borrow::Cow,
+    cell::{Cell, RefCell},
+    collections::HashMap,
+    path::{Path, PathBuf},
+    sync::Arc,
+};
+
+use anyhow::{anyhow, Error as E};
 use candle_core::{Device, Tensor};
+use hf_hub::api::sync::{ApiBuilder, ApiRepo};
 use ndarray::Array2;
-use tokenizers::Tokenizer;
+use serde::Deserialize;
+use tokenizers::{processors::PostProcessorWrapper, Tokenizer};
+
+use crate::Dtype;
 
 pub fn tokenize_batch(
     tokenizer: &Tokenizer,
@@ -115,3 +127,604 @@ pub fn get_type_ids_ndarray(
     .unwrap();
     Ok(token_ids_array)
 }
+
+thread_local! {
+    static CACHE_DIR: RefCell<Option<PathBuf>> = const { RefCell::new(None) };
+    static ADD_SPECIAL_TOKENS: Cell<Option<bool>> = const { Cell::new(None) };
+}
+
+/// Restores the cache directory set before [scoped_cache_dir] when dropped.
+#[must_use = "the cache directory is reset when the guard is dropped"]
+pub struct CacheDirGuard {
+    previous: Option<PathBuf>,
+}
+
+impl Drop for CacheDirGuard {
+    fn drop(&mut self) {
+        CACHE_DIR.set(self.previous.take());
+    }
+}
+
+/// Makes the models loaded on the current thread download to and load from `cache_dir`,
+/// until the returned guard is dropped. None keeps the current directory. See
+/// [hf_api_builder] for the default.
+pub fn scoped_cache_dir(cache_dir: Option<PathBuf>) -> CacheDirGuard {
+    let previous = CACHE_DIR.with_borrow(|current| current.clone());
+    CACHE_DIR.set(cache_dir.or_else(|| previous.clone()));
+    CacheDirGuard { previous }
+}
+
+/// The cache directory of the Hugging Face Hub: the one set with [scoped_cache_dir], else
+/// `$HF_HOME/hub`, else None for the default of `hf_hub`, `~/.cache/huggingface/hub`.
+pub fn hf_cache_dir() -> Option<PathBuf> {
+    CACHE_DIR
+        .with_borrow(|cache_dir| cache_dir.clone())
+        .or_else(|| std::env::var_os("HF_HOME").map(|home| PathBuf::from(home).join("hub")))
+}
+
+/// An [ApiBuilder] caching downloads in [hf_cache_dir]. Models downloading from the Hugging
+/// Face Hub start from it.
+pub fn hf_api_builder() -> ApiBuilder {
+    match hf_cache_dir() {
+        Some(cache_dir) => ApiBuilder::new().with_cache_dir(cache_dir),
+        None => ApiBuilder::new(),
+    }
+}
+
+/// Restores the special tokens setting set before [scoped_add_special_tokens] when dropped.
+#[must_use = "the special tokens setting is reset when the guard is dropped"]
+pub struct SpecialTokensGuard {
+    previous: Option<bool>,
+}
+
+impl Drop for SpecialTokensGuard {
+    fn drop(&mut self) {
+        ADD_SPECIAL_TOKENS.set(self.previous);
+    }
+}
+
+/// Makes the tokenizers loaded on the current thread with [load_tokenizer] add the special
+/// tokens of their model or not, until the returned guard is dropped. None keeps the current
+/// setting, which adds them by default.
+pub fn scoped_add_special_tokens(add_special_tokens: Option<bool>) -> SpecialTokensGuard {
+    let previous = ADD_SPECIAL_TOKENS.get();
+    ADD_SPECIAL_TOKENS.set(add_special_tokens.or(previous));
+    SpecialTokensGuard { previous }
+}
+
+/// Whether the tokenizers loaded on the current thread add special tokens.
+pub fn add_special_tokens() -> bool {
+    ADD_SPECIAL_TOKENS.get().unwrap_or(true)
+}
+
+/// Reads a `tokenizer.json`. The tokenizer wraps every text in the special tokens of the model,
+/// e.g. `[CLS] text [SEP]` for BERT, unless [scoped_add_special_tokens] turned them off for
+/// models that expect none, such as some instruction models.
+///
+/// Query and document prefixes are prepended to the text before it is tokenized, so they sit
+/// inside the special tokens, `[CLS] query: text [SEP]`, as in sentence-transformers. Without
+/// special tokens the sequence is `query: text`, and CLS pooling reads the first token of the
+/// prefix.
+pub fn load_tokenizer<P: AsRef<Path>>(path: P) -> anyhow::Result<Tokenizer> {
+    let mut tokenizer = Tokenizer::from_file(path).map_err(E::msg)?;
+    if !add_special_tokens() {
+        // The post-processor adds the special tokens, texts are encoded as they are without it.
+        tokenizer.with_post_processor(None::<PostProcessorWrapper>);
+    }
+    Ok(tokenizer)
+}
+
+/// Fails when `tokenizer` produces token ids outside of the embedding table of `model`, which
+/// has `vocab_size` rows. This happens when the tokenizer and the weights come from different
+/// revisions of the model, and would otherwise fail on the first text with an index error of
+/// the embedding layer.
+pub fn check_vocab_size(
+    tokenizer: &Tokenizer,
+    vocab_size: usize,
+    model: &str,
+) -> anyhow::Result<()> {
+    let Some(&max_id) = tokenizer.get_vocab(true).values().max() else {
+        return Ok(());
+    };
+    if max_id as usize >= vocab_size {
+        return Err(anyhow!(
+            "The tokenizer of {} has token ids up to {}, but the model only has embeddings for {} tokens. The tokenizer and the weights likely come from different revisions of the model.",
+            model,
+            max_id,
+            vocab_size
+        ));
+    }
+    Ok(())
+}
+
+/// The files of a model, read from a Hugging Face Hub repository or from a local directory laid
+/// out like one.
+pub trait ModelFiles {
+    /// The path of `filename`, downloaded first for repositories. Errors when the model has no
+    /// such file.
+    fn file(&self, filename: &str) -> anyhow::Result<PathBuf>;
+}
+
+impl ModelFiles for ApiRepo {
+    fn file(&self, filename: &str) -> anyhow::Result<PathBuf> {
+        Ok(self.get(filename)?)
+    }
+}
+
+impl ModelFiles for Path {
+    fn file(&self, filename: &str) -> anyhow::Result<PathBuf> {
+        let path = self.join(filename);
+        if path.is_file() {
+            Ok(path)
+        } else {
+            Err(anyhow!("{} not found", path.display()))
+        }
+    }
+}
+
+/// Checks that `dir` holds the files needed to load a model from it, `config.json`,
+/// `tokenizer.json` and the weights as `model.safetensors` or `pytorch_model.bin`. The error
+/// lists every missing file.
+pub fn check_model_dir(dir: &Path) -> anyhow::Result<()> {
+    if !dir.is_dir() {
+        return Err(anyhow!("Model directory {} does not exist", dir.display()));
+    }
+    let mut missing = ["config.json", "tokenizer.json"]
+        .into_iter()
+        .filter(|filename| !dir.join(filename).is_file())
+        .map(str::to_string)
+        .collect::<Vec<_>>();
+    if !["model.safetensors", "pytorch_model.bin"]
+        .iter()
+        .any(|filename| dir.join(filename).is_file())
+    {
+        missing.push("model.safetensors or pytorch_model.bin".to_string());
+    }
+    if missing.is_empty() {
+        Ok(())
+    } else {
+        Err(anyhow!(
+            "Model directory {} is missing {}",
+            dir.display(),
+            missing.join(", ")
+        ))
+    }
+}
+
+/// Padding multiple of batches on GPUs. Tensor cores multiply tiles of 8 fp16 values, so
+/// sequence lengths that are a multiple of 8 keep them busy.
+pub const GPU_PAD_TO_MULTIPLE_OF: usize = 8;
+
+/// The multiple batches are padded to by default on `device`: [GPU_PAD_TO_MULTIPLE_OF] on
+/// GPUs, and none on CPU where padding tokens are only extra work.
+pub fn default_pad_to_multiple_of(device: &Device) -> Option<usize> {
+    (!device.is_cpu()).then_some(GPU_PAD_TO_MULTIPLE_OF)
+}
+
+/// Pads the batches encoded by `tokenizer` to a multiple of `multiple` tokens instead of the
+/// length of their longest sequence. None or 1 turns it off. Tokenizers without padding are
+/// left as they are.
+///
+/// The truncation length is rounded down to the multiple, so that padding never makes a
+/// sequence longer than the model accepts.
+pub fn set_pad_to_multiple_of(tokenizer: &mut Tokenizer, multiple: Option<usize>) {
+    let multiple = multiple.filter(|multiple| *multiple > 1);
+    let Some(padding) = tokenizer.get_padding_mut() else {
+        return;
+    };
+    padding.pad_to_multiple_of = multiple;
+    if let (Some(multiple), Some(truncation)) = (multiple, tokenizer.get_truncation_mut()) {
+        if truncation.max_length >= multiple {
+            truncation.max_length -= truncation.max_length % multiple;
+        }
+    }
+}
+
+/// A text cut by the tokenizer because it is longer than the model accepts, passed to
+/// [TextEmbedConfig::on_truncation](crate::config::TextEmbedConfig::on_truncation).
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TruncationEvent {
+    /// The length of the text in characters.
+    pub text_len: usize,
+    /// The number of tokens the model reads. The rest of the text is not embedded.
+    pub max_length: usize,
+    /// The file the text was read from, None for texts given directly.
+    pub file: Option<String>,
+}
+
+pub type TruncationCallback = Arc<dyn Fn(&TruncationEvent) + Send + Sync>;
+
+/// Calls `on_truncation` for every text of `text_batch` that `tokenizer` truncates. Tokenizers
+/// without truncation never truncate.
+pub fn report_truncation(
+    tokenizer: &Tokenizer,
+    text_batch: &[String],
+    file: Option<&str>,
+    on_truncation: &TruncationCallback,
+) -> anyhow::Result<()> {
+    let Some(max_length) = tokenizer
+        .get_truncation()
+        .map(|truncation| truncation.max_length)
+    else {
+        return Ok(());
+    };
+    let encodings = tokenizer
+        .encode_batch(text_batch.to_vec(), true)
+        .map_err(E::msg)?;
+    for (text, encoding) in text_batch.iter().zip(encodings) {
+        // The tokens cut by truncation are kept as overflowing encodings.
+        if !encoding.get_overflowing().is_empty() {
+            on_truncation(&TruncationEvent {
+                text_len: text.chars().count(),
+                max_length,
+                file: file.map(str::to_string),
+            });
+        }
+    }
+    Ok(())
+}
+
+/// Prepends `prefix` to every text, e.g. "query: " for E5 models. Returns the texts as they
+/// are when there is no prefix.
+pub fn add_prefix<'a>(prefix: Option<&str>, text_batch: &'a [String]) -> Cow<'a, [String]> {
+    match prefix {
+        Some(prefix) if !prefix.is_empty() => Cow::Owned(
+            text_batch
+                .iter()
+                .map(|text| format!("{}{}", prefix, text))
+                .collect(),
+        ),
+        _ => Cow::Borrowed(text_batch),
+    }
+}
+
+/// The indices of `text_batch` from its shortest text to its longest, counted in tokens of
+/// `tokenizer`, or in characters without one. Texts of the same length keep their order.
+/// Embedding texts in this order puts texts of similar length in the same batches, which
+/// wastes less compute on padding, see [restore_order] to undo it.
+pub fn length_order(tokenizer: Option<&Tokenizer>, text_batch: &[String]) -> Vec<usize> {
+    let encodings =
+        tokenizer.and_then(|tokenizer| tokenizer.encode_batch(text_batch.to_vec(), false).ok());
+    let lengths = match encodings {
+        // Padding tokens don't count, in case the tokenizer pads its batches.
+        Some(encodings) => encodings
+            .iter()
+            .map(|encoding| encoding.get_attention_mask().iter().sum::<u32>() as usize)
+            .collect::<Vec<_>>(),
+        None => text_batch.iter().map(|text| text.chars().count()).collect(),
+    };
+    let mut order = (0..text_batch.len()).collect::<Vec<_>>();
+    order.sort_by_key(|&index| lengths[index]);
+    order
+}
+
+/// Puts the results of texts embedded in `order`, as returned by [length_order], back in the
+/// order of the texts.
+pub fn restore_order<T>(sorted: Vec<T>, order: &[usize]) -> Vec<T> {
+    let mut results = order.iter().zip(sorted).collect::<Vec<_>>();
+    results.sort_unstable_by_key(|(&index, _)| index);
+    results.into_iter().map(|(_, result)| result).collect()
+}
+
+/// The prompts of a sentence-transformers model, read from `config_sentence_transformers.json`.
+#[derive(Debug, Default, Deserialize)]
+pub struct SentenceTransformersPrompts {
+    #[serde(default)]
+    pub prompts: HashMap<String, String>,
+}
+
+impl SentenceTransformersPrompts {
+    /// The query and document prompts, used as prefixes. The document prompt is the one named
+    /// `document` or `passage`. Empty prompts are None.
+    pub fn prefixes(&self) -> (Option<String>, Option<String>) {
+        let prompt = |names: &[&str]| {
+            names
+                .iter()
+                .find_map(|name| self.prompts.get(*name))
+                .filter(|prompt| !prompt.is_empty())
+                .cloned()
+        };
+        (prompt(&["query"]), prompt(&["document", "passage"]))
+    }
+
+    /// Reads the query and document prompts of a model repository. Returns None for the
+    /// prompts the repository does not define.
+    pub fn from_repo(api: &(impl ModelFiles + ?Sized)) -> (Option<String>, Option<String>) {
+        api.file("config_sentence_transformers.json")
+            .ok()
+            .and_then(|config| std::fs::read_to_string(config).ok())
+            .and_then(|config| serde_json::from_str::<Self>(&config).ok())
+            .map_or((None, None), |config| config.prefixes())
+    }
+}
+
+/// Returns the ONNX file to download from a model repository. `path_in_repo` is used verbatim
+/// when provided. Otherwise the file name follows the usual `model_<dtype>.onnx` naming, in the
+/// folder of `default_path`, or `default_path` itself when no dtype is requested.
+pub fn onnx_weights_path(
+    path_in_repo: Option<&str>,
+    default_path: &str,
+    dtype: Option<&Dtype>,
+) -> String {
+    if let Some(path) = path_in_repo {
+        return path.to_string();
+    }
+    let file_name = match dtype {
+        Some(Dtype::Q4F16) => "model_q4f16.onnx",
+        Some(Dtype::F16) => "model_fp16.onnx",
+        Some(Dtype::INT8) => "model_int8.onnx",
+        Some(Dtype::Q4) => "model_q4.onnx",
+        Some(Dtype::UINT8) => "model_uint8.onnx",
+        Some(Dtype::BNB4) => "model_bnb4.onnx",
+        Some(Dtype::F32) => "model.onnx",
+        Some(Dtype::QUANTIZED) => "model_quantized.onnx",
+        None => return default_path.to_string(),
+    };
+    match default_path.rsplit_once('/') {
+        Some((folder, _)) => format!("{folder}/{file_name}"),
+        None => file_name.to_string(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_length_order() {
+        let texts = ["a longer text", "short", "a", "tiny"].map(String::from);
+        let order = length_order(None, &texts);
+        assert_eq!(order, vec![2, 3, 1, 0]);
+        let sorted = order.iter().map(|&i| texts[i].clone()).collect::<Vec<_>>();
+        assert_eq!(restore_order(sorted, &order), texts);
+    }
+
+    #[test]
+    fn test_sentence_transformers_prompts() {
+        let config: SentenceTransformersPrompts = serde_json::from_str(
+            r#"{"prompts": {"query": "Represent this sentence for searching relevant passages: ", "document": ""},
+                "default_prompt_name": null}"#,
+        )
+        .unwrap();
+        assert_eq!(
+            config.prefixes(),
+            (
+                Some("Represent this sentence for searching relevant passages: ".to_string()),
+                None
+            )
+        );
+        let config: SentenceTransformersPrompts =
+            serde_json::from_str(r#"{"prompts": {"query": "query: ", "passage": "passage: "}}"#)
+                .unwrap();
+        assert_eq!(
+            config.prefixes(),
+            (Some("query: ".to_string()), Some("passage: ".to_string()))
+        );
+        let config: SentenceTransformersPrompts =
+            serde_json::from_str(r#"{"__version__": {"sentence_transformers": "2.2.2"}}"#).unwrap();
+        assert_eq!(config.prefixes(), (None, None));
+    }
+
+    #[test]
+    fn test_onnx_weights_path_from_dtype() {
+        assert_eq!(
+            onnx_weights_path(None, "onnx/model.onnx", Some(&Dtype::QUANTIZED)),
+            "onnx/model_quantized.onnx"
+        );
+        assert_eq!(
+            onnx_weights_path(None, "model.onnx", Some(&Dtype::F16)),
+            "model_fp16.onnx"
+        );
+        assert_eq!(
+            onnx_weights_path(None, "onnx/model.onnx", None),
+            "onnx/model.onnx"
+        );
+    }
+
+    #[test]
+    fn test_onnx_weights_path_is_authoritative() {
+        assert_eq!(
+            onnx_weights_path(
+                Some("weights/custom.onnx"),
+                "onnx/model.onnx",
+                Some(&Dtype::Q4)
+            ),
+            "weights/custom.onnx"
+        );
+        assert_eq!(
+            onnx_weights_path(Some("model.onnx"), "onnx/model.onnx", Some(&Dtype::F16)),
+            "model.onnx"
+        );
+    }
+
+    #[test]
+    fn test_set_pad_to_multiple_of() {
+        use tokenizers::{
+            models::wordlevel::WordLevel, pre_tokenizers::whitespace::Whitespace, PaddingParams,
+            TruncationParams,
+        };
+
+        let vocab = HashMap::from([("[PAD]".to_string(), 0), ("word".to_string(), 1)]);
+        let model = WordLevel::builder()
+            .vocab(vocab)
+            .unk_token("[PAD]".to_string())
+            .build()
+            .unwrap();
+        let mut tokenizer = Tokenizer::new(model);
+        tokenizer.with_pre_tokenizer(Some(Whitespace::default()));
+        tokenizer
+            .with_padding(Some(PaddingParams::default()))
+            .with_truncation(Some(TruncationParams {
+                max_length: 13,
+                ..Default::default()
+            }))
+            .unwrap();
+        let lengths = |tokenizer: &Tokenizer, texts: Vec<&str>| -> Vec<usize> {
+            tokenizer
+                .encode_batch(texts, true)
+                .unwrap()
+                .iter()
+                .map(|encoding| encoding.len())
+                .collect()
+        };
+
+        assert_eq!(lengths(&tokenizer, vec!["word word word", "word"]), [3, 3]);
+        set_pad_to_multiple_of(&mut tokenizer, Some(GPU_PAD_TO_MULTIPLE_OF));
+        assert_eq!(lengths(&tokenizer, vec!["word word word", "word"]), [8, 8]);
+        assert_eq!(tokenizer.get_truncation().unwrap().max_length, 8);
+        let long = "word ".repeat(12);
+        assert_eq!(lengths(&tokenizer, vec![long.as_str()]), [8]);
+        set_pad_to_multiple_of(&mut tokenizer, None);
+        assert_eq!(lengths(&tokenizer, vec!["word word word", "word"]), [3, 3]);
+
+        assert_eq!(default_pad_to_multiple_of(&Device::Cpu), None);
+    }
+
+    #[test]
+    fn test_scoped_cache_dir() {
+        let default = hf_cache_dir();
+        {
+            let _outer = scoped_cache_dir(Some(PathBuf::from("/models")));
+            assert_eq!(hf_cache_dir(), Some(PathBuf::from("/models")));
+            {
+                let _inner = scoped_cache_dir(None);
+                assert_eq!(hf_cache_dir(), Some(PathBuf::from("/models")));
+            }
+            assert_eq!(hf_cache_dir(), Some(PathBuf::from("/models")));
+            // Other threads keep their own directory.
+            let other = std::thread::spawn(hf_cache_dir).join().unwrap();
+            assert_eq!(other, default);
+        }
+        assert_eq!(hf_cache_dir(), default);
+    }
+
+    #[test]
+    fn test_load_tokenizer_special_tokens() {
+        use tokenizers::{
+            models::wordlevel::WordLevel, pre_tokenizers::whitespace::Whitespace,
+            processors::template::TemplateProcessing,
+        };
+
+        let vocab = ["[UNK]", "[CLS]", "[SEP]", "query", ":", "hello", "world"]
+            .iter()
+            .enumerate()
+            .map(|(id, token)| (token.to_string(), id as u32))
+            .collect::<HashMap<_, _>>();
+        let model = WordLevel::builder()
+            .vocab(vocab)
+            .unk_token("[UNK]".to_string())
+            .build()
+            .unwrap();
+        let mut tokenizer = Tokenizer::new(model);
+        tokenizer.with_pre_tokenizer(Some(Whitespace::default()));
+        // The post-processor of BERT tokenizers.
+        tokenizer.with_post_processor(Some(
+            TemplateProcessing::builder()
+                .try_single("[CLS] $A [SEP]")
+                .unwrap()
+                .special_tokens(vec![("[CLS]", 1), ("[SEP]", 2)])
+                .build()
+                .unwrap(),
+        ));
+        let directory = tempfile::tempdir().unwrap();
+        let path = directory.path().join("tokenizer.json");
+        tokenizer.save(&path, false).unwrap();
+
+        let texts = add_prefix(Some("query: "), &["hello world".to_string()]).into_owned();
+        let tokens = |tokenizer: &Tokenizer| {
+            let encodings = tokenizer.encode_batch(texts.clone(), true).unwrap();
+            encodings[0].get_tokens().to_vec()
+        };
+        assert!(add_special_tokens());
+        assert_eq!(
+            tokens(&load_tokenizer(&path).unwrap()),
+            ["[CLS]", "query", ":", "hello", "world", "[SEP]"]
+        );
+        {
+            let _special_tokens = scoped_add_special_tokens(Some(false));
+            let _unchanged = scoped_add_special_tokens(None);
+            assert_eq!(
+                tokens(&load_tokenizer(&path).unwrap()),
+                ["query", ":", "hello", "world"]
+            );
+        }
+        assert!(add_special_tokens());
+    }
+
+    #[test]
+    fn test_check_vocab_size() {
+        use tokenizers::{models::wordlevel::WordLevel, AddedToken};
+
+        let vocab = (0..6)
+            .map(|id| (format!("token{}", id), id))
+            .collect::<HashMap<_, _>>();
+        let model = WordLevel::builder()
+            .vocab(vocab)
+            .unk_token("token0".to_string())
+            .build()
+            .unwrap();
+        let mut tokenizer = Tokenizer::new(model);
+        check_vocab_size(&tokenizer, 6, "bert").unwrap();
+
+        // Added tokens come after the vocabulary, as in a newer revision of the tokenizer.
+        tokenizer.add_special_tokens(&[AddedToken::from("[NEW]", true)]);
+        let error = check_vocab_size(&tokenizer, 6, "bert").unwrap_err();
+        assert!(error
+            .to_string()
+            .contains("token ids up to 6, but the model only has embeddings for 6 tokens"));
+    }
+
+    #[test]
+    fn test_report_truncation() {
+        use std::sync::Mutex;
+        use tokenizers::{
+            models::wordlevel::WordLevel, pre_tokenizers::whitespace::Whitespace, TruncationParams,
+        };
+
+        let vocab = HashMap::from([("[UNK]".to_string(), 0), ("word".to_string(), 1)]);
+        let model = WordLevel::builder()
+            .vocab(vocab)
+            .unk_token("[UNK]".to_string())
+            .build()
+            .unwrap();
+        let mut tokenizer = Tokenizer::new(model);
+        tokenizer.with_pre_tokenizer(Some(Whitespace::default()));
+        let events = Arc::new(Mutex::new(Vec::new()));
+        let on_truncation: TruncationCallback = {
+            let events = Arc::clone(&events);
+            Arc::new(move |event: &TruncationEvent| events.lock().unwrap().push(event.clone()))
+        };
+        let texts = vec!["word ".repeat(3), "word ".repeat(6)];
+
+        // Without truncation, nothing is cut.
+        report_truncation(&tokenizer, &texts, None, &on_truncation).unwrap();
+        assert!(events.lock().unwrap().is_empty());
+
+        tokenizer
+            .with_truncation(Some(TruncationParams {
+                max_length: 4,
+                ..Default::default()
+            }))
+            .unwrap();
+        report_truncation(&tokenizer, &texts, Some("notes.txt"), &on_truncation).unwrap();
+        assert_eq!(
+            *events.lock().unwrap(),
+            vec![TruncationEvent {
+                text_len: 30,
+                max_length: 4,
+                file: Some("notes.txt".to_string()),
+            }]
+        );
+    }
+
+    #[test]
+    fn test_add_prefix() {
+        let texts = vec!["what is rust".to_string()];
+        assert_eq!(
+            add_prefix(Some("query: "), &texts)[0],
+            "query: what is rust"
+        );
+        assert!(matches!(add_prefix(None, &texts), Cow::Borrowed(_)));
+        assert!(matches!(add_prefix(Some(""), &texts), Cow::Borrowed(_)));
+    }
+}
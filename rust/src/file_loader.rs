@@ -1,11 +1,43 @@
-use std::{collections::HashSet, io::Error, path::PathBuf};
+use std::{
+    collections::HashSet,
+    io::Error,
+    path::{Path, PathBuf},
+    sync::Arc,
+    time::SystemTime,
+};
 
 use regex::Regex;
 use walkdir::WalkDir;
 // use tokio::fs;
 
+/// What a [FileFilter] knows about a file, read from the file system without opening it.
+#[derive(Debug, Clone)]
+pub struct FileMeta {
+    pub path: PathBuf,
+    /// Size in bytes.
+    pub size: u64,
+    /// Last modification time, None on platforms that don't record it.
+    pub modified: Option<SystemTime>,
+}
+
+impl FileMeta {
+    pub fn from_path(path: &Path) -> Result<Self, Error> {
+        let metadata = std::fs::metadata(path)?;
+        Ok(Self {
+            path: path.to_path_buf(),
+            size: metadata.len(),
+            modified: metadata.modified().ok(),
+        })
+    }
+}
+
+/// Predicate deciding whether a file found in a directory is embedded. Files are skipped when
+/// it returns false, before their content is read.
+pub type FileFilter = Arc<dyn Fn(&FileMeta) -> bool + Send + Sync>;
+
 pub struct FileParser {
     pub files: Vec<String>,
+    file_filter: Option<FileFilter>,
 }
 
 impl Default for FileParser {
@@ -16,7 +48,25 @@ impl Default for FileParser {
 
 impl FileParser {
     pub fn new() -> Self {
-        Self { files: Vec::new() }
+        Self {
+            files: Vec::new(),
+            file_filter: None,
+        }
+    }
+
+    /// Only keeps the files for which `file_filter` returns true.
+    pub fn with_file_filter(mut self, file_filter: Option<FileFilter>) -> Self {
+        self.file_filter = file_filter;
+        self
+    }
+
+    /// Whether `path` passes the file filter. Files whose metadata can't be read are kept, so
+    /// that the error is reported when they are read.
+    fn is_selected(&self, path: &Path) -> bool {
+        match (&self.file_filter, FileMeta::from_path(path)) {
+            (Some(file_filter), Ok(meta)) => file_filter(&meta),
+            _ => true,
+        }
     }
 
     pub fn get_text_files(
@@ -26,7 +76,7 @@ impl FileParser {
     ) -> Result<Vec<String>, Error> {
         let extension_regex = match extensions {
             Some(exts) => Regex::new(&format!(r"\.({})$", exts.join("|"))).unwrap(),
-            None => Regex::new(r"\.(pdf|md|txt|docx)$").unwrap(),
+            None => Regex::new(r"\.(pdf|md|txt|docx|rtf|odt)$").unwrap(),
         };
 
         let entries = std::fs::read_dir(directory_path)?;
@@ -36,7 +86,9 @@ impl FileParser {
             let entry = entry?;
             if entry.file_type()?.is_file() {
                 let file_name = entry.file_name();
-                if extension_regex.is_match(file_name.to_str().unwrap_or("")) {
+                if extension_regex.is_match(file_name.to_str().unwrap_or(""))
+                    && self.is_selected(&entry.path())
+                {
                     let absolute_path =
                         std::fs::canonicalize(entry.path()).unwrap_or_else(|_| entry.path());
                     files.push(absolute_path.to_string_lossy().to_string());
@@ -56,6 +108,7 @@ impl FileParser {
             .filter_map(|entry| entry.ok())
             .filter(|entry| entry.file_type().is_file())
             .filter(|entry| image_regex.is_match(entry.file_name().to_str().unwrap_or("")))
+            .filter(|entry| self.is_selected(entry.path()))
             .map(|entry| {
                 let absolute_path = entry
                     .path()
@@ -77,6 +130,7 @@ impl FileParser {
             .filter_map(|entry| entry.ok())
             .filter(|entry| entry.file_type().is_file())
             .filter(|entry| audio_regex.is_match(entry.file_name().to_str().unwrap_or("")))
+            .filter(|entry| self.is_selected(entry.path()))
             .map(|entry| {
                 let absolute_path = entry
                     .path()
@@ -205,6 +259,21 @@ mod tests {
         assert_eq!(audio_files.len(), 2);
     }
 
+    #[test]
+    fn test_file_filter_by_size() {
+        let temp_dir = TempDir::new("example").unwrap();
+        std::fs::write(temp_dir.path().join("small.txt"), "small").unwrap();
+        std::fs::write(temp_dir.path().join("large.txt"), "large".repeat(1000)).unwrap();
+
+        let file_filter: FileFilter = Arc::new(|meta: &FileMeta| meta.size < 1000);
+        let files = FileParser::new()
+            .with_file_filter(Some(file_filter))
+            .get_text_files(&PathBuf::from(temp_dir.path()), None)
+            .unwrap();
+        assert_eq!(files.len(), 1);
+        assert!(files[0].ends_with("small.txt"));
+    }
+
     #[test]
     fn test_get_files_to_index() {
         let temp_dir = TempDir::new("example").unwrap();
@@ -0,0 +1,259 @@
+//! Reading the files held in `.zip`, `.tar.gz` and `.tgz` archives, so that
+//! [embed_file](crate::embed_file) and [embed_directory_stream](crate::embed_directory_stream)
+//! embed them like the files of a directory when
+//! [TextEmbedConfig::extract_archives](crate::config::TextEmbedConfig::extract_archives) is set.
+//!
+//! The extractors read files from disk, so the selected entries are unpacked into a temporary
+//! directory that is removed when the [ExtractedArchive] is dropped. Nothing else is written,
+//! and the decompressed size is capped to guard against archives crafted to fill the disk.
+
+use std::{
+    collections::HashMap,
+    fs::File,
+    io::{Read, Write},
+    path::{Component, Path, PathBuf},
+};
+
+use anyhow::{anyhow, Result};
+use flate2::read::GzDecoder;
+use tempfile::TempDir;
+
+use crate::file_loader::FileMeta;
+
+/// Metadata entry holding the path of the archive a chunk was read from.
+pub const ARCHIVE_KEY: &str = "archive";
+/// Metadata entry holding the path of the file of a chunk inside its archive.
+pub const ARCHIVE_PATH_KEY: &str = "archive_path";
+/// Default limit on the bytes unpacked from one archive, 1 GiB.
+pub const DEFAULT_MAX_ARCHIVE_BYTES: u64 = 1 << 30;
+
+/// Whether `path` names an archive that can be extracted, by its extension.
+pub fn is_archive<T: AsRef<Path>>(path: T) -> bool {
+    let name = path
+        .as_ref()
+        .file_name()
+        .map(|name| name.to_string_lossy().to_lowercase())
+        .unwrap_or_default();
+    name.ends_with(".zip") || name.ends_with(".tar.gz") || name.ends_with(".tgz")
+}
+
+/// A file unpacked from an archive.
+#[derive(Debug, Clone)]
+pub struct ArchiveEntry {
+    /// The path of the file inside the archive, with `/` separators.
+    pub path: String,
+    /// Where the file was unpacked.
+    pub file: PathBuf,
+}
+
+/// The selected files of an archive, unpacked into a temporary directory.
+pub struct ExtractedArchive {
+    archive: PathBuf,
+    entries: Vec<ArchiveEntry>,
+    // Removes the unpacked files when dropped.
+    _directory: TempDir,
+}
+
+impl ExtractedArchive {
+    /// Unpacks the regular files of `archive` for which `select` returns true. `select` is
+    /// given the path the file would have if the archive was a directory, and the size the
+    /// archive declares for it.
+    ///
+    /// Fails once more than `max_bytes` are unpacked, whatever sizes the archive declares.
+    /// Entries whose path is absolute or leaves the archive with `..` are skipped.
+    pub fn extract<T, F>(archive: T, max_bytes: u64, select: F) -> Result<Self>
+    where
+        T: AsRef<Path>,
+        F: Fn(&FileMeta) -> bool,
+    {
+        let archive = archive.as_ref().to_path_buf();
+        let mut extractor = Extractor {
+            archive: &archive,
+            directory: tempfile::tempdir()?,
+            remaining: max_bytes,
+            max_bytes,
+            entries: Vec::new(),
+        };
+        let selected = |path: &Path, size: u64| {
+            select(&FileMeta {
+                path: archive.join(path),
+                size,
+                modified: None,
+            })
+        };
+        let name = archive.to_string_lossy().to_lowercase();
+        if name.ends_with(".zip") {
+            let mut zip = zip::ZipArchive::new(File::open(&archive)?)?;
+            for index in 0..zip.len() {
+                let mut entry = zip.by_index(index)?;
+                if entry.is_dir() {
+                    continue;
+                }
+                let Some(path) = entry.enclosed_name() else {
+                    tracing::warn!("Skipping {} in {:?}: unsafe path", entry.name(), archive);
+                    continue;
+                };
+                if selected(&path, entry.size()) {
+                    extractor.unpack(&path, entry.size(), &mut entry)?;
+                }
+            }
+        } else if name.ends_with(".tar.gz") || name.ends_with(".tgz") {
+            let mut tar = tar::Archive::new(GzDecoder::new(File::open(&archive)?));
+            for entry in tar.entries()? {
+                let mut entry = entry?;
+                if !entry.header().entry_type().is_file() {
+                    continue;
+                }
+                let path = entry.path()?.into_owned();
+                if !path
+                    .components()
+                    .all(|component| matches!(component, Component::Normal(_)))
+                {
+                    tracing::warn!("Skipping {:?} in {:?}: unsafe path", path, archive);
+                    continue;
+                }
+                let size = entry.header().size()?;
+                if selected(&path, size) {
+                    extractor.unpack(&path, size, &mut entry)?;
+                }
+            }
+        } else {
+            return Err(anyhow!(
+                "{:?} is not a .zip, .tar.gz or .tgz archive",
+                archive
+            ));
+        }
+
+        let Extractor {
+            entries, directory, ..
+        } = extractor;
+        Ok(Self {
+            archive,
+            entries,
+            _directory: directory,
+        })
+    }
+
+    /// The path of the archive.
+    pub fn archive(&self) -> &Path {
+        &self.archive
+    }
+
+    /// The unpacked files, in the order of the archive.
+    pub fn entries(&self) -> &[ArchiveEntry] {
+        &self.entries
+    }
+
+    /// The metadata identifying the chunks of `entry`: the archive under [ARCHIVE_KEY], the
+    /// path inside it under [ARCHIVE_PATH_KEY], and a `file_name` joining both, so that the
+    /// chunks of different files of the archive are told apart.
+    pub fn metadata(&self, entry: &ArchiveEntry) -> HashMap<String, String> {
+        let archive = self.archive.to_string_lossy().to_string();
+        HashMap::from([
+            (
+                "file_name".to_string(),
+                format!("{}/{}", archive, entry.path),
+            ),
+            (ARCHIVE_KEY.to_string(), archive),
+            (ARCHIVE_PATH_KEY.to_string(), entry.path.clone()),
+        ])
+    }
+}
+
+struct Extractor<'a> {
+    archive: &'a Path,
+    directory: TempDir,
+    remaining: u64,
+    max_bytes: u64,
+    entries: Vec<ArchiveEntry>,
+}
+
+impl Extractor<'_> {
+    fn unpack(&mut self, path: &Path, declared_size: u64, reader: &mut impl Read) -> Result<()> {
+        let too_large = || {
+            anyhow!(
+                "{:?} unpacks to more than {} bytes",
+                self.archive,
+                self.max_bytes
+            )
+        };
+        if declared_size > self.remaining {
+            return Err(too_large());
+        }
+        let file = self.directory.path().join(path);
+        if let Some(parent) = file.parent() {
+            std::fs::create_dir_all(parent)?;
+        }
+        // Declared sizes can lie, so reading stops one byte past the limit.
+        let mut output = File::create(&file)?;
+        let written = std::io::copy(&mut reader.by_ref().take(self.remaining + 1), &mut output)?;
+        output.flush()?;
+        if written > self.remaining {
+            return Err(too_large());
+        }
+        self.remaining -= written;
+        let path = path
+            .components()
+            .map(|component| component.as_os_str().to_string_lossy())
+            .collect::<Vec<_>>()
+            .join("/");
+        self.entries.push(ArchiveEntry { path, file });
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn inner_paths(extracted: &ExtractedArchive) -> Vec<&str> {
+        extracted
+            .entries()
+            .iter()
+            .map(|entry| entry.path.as_str())
+            .collect()
+    }
+
+    #[test]
+    fn test_extract_archives() {
+        assert!(is_archive("docs/Corpus.ZIP"));
+        assert!(is_archive("corpus.tar.gz"));
+        assert!(!is_archive("corpus.gz"));
+
+        for archive in ["../test_files/archive.zip", "../test_files/archive.tar.gz"] {
+            let extracted = ExtractedArchive::extract(archive, DEFAULT_MAX_ARCHIVE_BYTES, |meta| {
+                !meta.path.ends_with("data.bin")
+            })
+            .unwrap();
+            assert_eq!(
+                inner_paths(&extracted),
+                vec!["notes/first.txt", "notes/second.md"]
+            );
+            let entry = &extracted.entries()[0];
+            assert!(std::fs::read_to_string(&entry.file)
+                .unwrap()
+                .contains("first"));
+            let metadata = extracted.metadata(entry);
+            assert_eq!(metadata[ARCHIVE_KEY], archive);
+            assert_eq!(metadata[ARCHIVE_PATH_KEY], "notes/first.txt");
+            assert_eq!(
+                metadata["file_name"],
+                format!("{}/notes/first.txt", archive)
+            );
+        }
+    }
+
+    #[test]
+    fn test_size_limit() {
+        let result = ExtractedArchive::extract("../test_files/archive.zip", 16, |_| true);
+        assert!(result
+            .err()
+            .unwrap()
+            .to_string()
+            .contains("more than 16 bytes"));
+        let directory = tempfile::tempdir().unwrap();
+        let not_archive = directory.path().join("file.txt");
+        std::fs::write(&not_archive, "text").unwrap();
+        assert!(ExtractedArchive::extract(&not_archive, 16, |_| true).is_err());
+    }
+}
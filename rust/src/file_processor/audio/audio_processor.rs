@@ -8,7 +8,10 @@ use std::path::PathBuf;
 
 use anyhow::{Error as E, Result};
 use candle_core::{Device, IndexOp, Tensor};
-use candle_nn::{ops::softmax, VarBuilder};
+use candle_nn::{
+    ops::{log_softmax, softmax},
+    VarBuilder,
+};
 use hf_hub::{api::sync::Api, Repo, RepoType};
 use rand::{distributions::Distribution, SeedableRng};
 use tokenizers::Tokenizer;
@@ -17,6 +20,8 @@ use candle_transformers::models::whisper::{self as m, Config};
 
 use crate::embeddings::select_device;
 
+use super::vad::VadConfig;
+
 #[cfg(feature = "audio")]
 use {crate::embeddings::embed::AudioDecoder, candle_transformers::models::whisper::audio};
 
@@ -60,11 +65,94 @@ impl WhichAudioDecoderModel {
     }
 }
 
+/// Seed of the temperature fallback sampling when none is set with
+/// [AudioDecoderModel::set_seed].
+pub const DEFAULT_SEED: u64 = 299792458;
+
+/// Decoding settings of Whisper. The defaults are the ones of whisper.cpp and of the reference
+/// implementation: greedy decoding at temperature 0, retried at temperatures increasing by 0.2
+/// when the transcript of a segment is unlikely.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct DecodingParams {
+    /// Number of candidate transcripts kept by beam search when decoding at temperature 0. 1
+    /// is greedy decoding, which is the fastest. Defaults to 1.
+    pub beam_size: usize,
+    /// Temperature of the first decoding of a segment. 0 picks the most likely tokens.
+    /// Defaults to 0.
+    pub temperature: f64,
+    /// Added to the temperature every time the decoding of a segment is retried, up to a
+    /// temperature of 1. 0 disables the retries. Defaults to 0.2.
+    pub temperature_increment: f64,
+    /// Segments whose probability of holding no speech is higher, and whose transcript is
+    /// unlikely, are skipped. Defaults to 0.6.
+    pub no_speech_threshold: f64,
+    /// Decoding is retried at a higher temperature when the average log probability of the
+    /// tokens is lower. Defaults to -1.
+    pub logprob_threshold: f64,
+}
+
+impl Default for DecodingParams {
+    fn default() -> Self {
+        Self {
+            beam_size: 1,
+            temperature: 0.0,
+            temperature_increment: 0.2,
+            no_speech_threshold: m::NO_SPEECH_THRESHOLD,
+            logprob_threshold: m::LOGPROB_THRESHOLD,
+        }
+    }
+}
+
+impl DecodingParams {
+    pub fn with_beam_size(mut self, beam_size: usize) -> Self {
+        self.beam_size = beam_size.max(1);
+        self
+    }
+
+    pub fn with_temperature(mut self, temperature: f64, temperature_increment: f64) -> Self {
+        self.temperature = temperature;
+        self.temperature_increment = temperature_increment;
+        self
+    }
+
+    pub fn with_no_speech_threshold(mut self, no_speech_threshold: f64) -> Self {
+        self.no_speech_threshold = no_speech_threshold;
+        self
+    }
+
+    pub fn with_logprob_threshold(mut self, logprob_threshold: f64) -> Self {
+        self.logprob_threshold = logprob_threshold;
+        self
+    }
+
+    /// The temperatures tried in turn to decode a segment.
+    pub fn temperatures(&self) -> Vec<f64> {
+        let mut temperatures = vec![self.temperature];
+        if self.temperature_increment > 0.0 {
+            // Counted in steps to avoid accumulating rounding errors.
+            let steps = ((1.0 - self.temperature) / self.temperature_increment + 1e-6).floor();
+            for step in 1..=steps.max(0.0) as usize {
+                temperatures.push(self.temperature + step as f64 * self.temperature_increment);
+            }
+        }
+        temperatures
+    }
+}
+
 pub struct AudioDecoderModel {
     pub model: WhichAudioDecoderModel,
     pub tokenizer: Tokenizer,
     pub config: Config,
     pub device: Device,
+    /// Seed of the sampling used when greedy decoding of a segment fails and the decoder
+    /// retries at a higher temperature. Transcripts are reproducible for a given seed.
+    pub seed: u64,
+    /// When set, the audio is cut on silences and every utterance is transcribed on its own,
+    /// see [AudioDecoderModel::with_vad].
+    pub vad: Option<VadConfig>,
+    /// Beam size, temperatures and thresholds of the decoding, see
+    /// [AudioDecoderModel::with_decoding].
+    pub decoding: DecodingParams,
 }
 
 #[derive(Debug, Clone, Default)]
@@ -178,6 +266,10 @@ impl<'a> Decoder<'a> {
         if !self.timestamps {
             tokens.push(self.no_timestamps_token);
         }
+        let beam_size = model.decoding.beam_size;
+        if t == 0f64 && beam_size > 1 {
+            return self.beam_search(&audio_features, tokens, beam_size);
+        }
         for i in 0..sample_len {
             let tokens_t = Tensor::new(tokens.as_slice(), mel.device())?;
 
@@ -249,18 +341,102 @@ impl<'a> Decoder<'a> {
         })
     }
 
+    /// Decodes with beam search, keeping the `beam_size` most likely transcripts at every step
+    /// and returning the one with the highest average log probability.
+    fn beam_search(
+        &mut self,
+        audio_features: &Tensor,
+        prefix: Vec<u32>,
+        beam_size: usize,
+    ) -> Result<DecodingResult> {
+        let model = &mut self.model;
+        let max_len = model.model.config().max_target_positions;
+        let sample_len = max_len / 2;
+        let device = audio_features.device();
+        let mut no_speech_prob = f64::NAN;
+        // Candidate transcripts and the sum of the log probabilities of their tokens.
+        let mut beams = vec![(prefix, 0f64)];
+        let mut finished = Vec::new();
+        for i in 0..sample_len {
+            let mut candidates = Vec::with_capacity(beams.len() * beam_size);
+            for (beam, (tokens, sum_logprob)) in beams.iter().enumerate() {
+                let tokens_t = Tensor::new(tokens.as_slice(), device)?.unsqueeze(0)?;
+                // Only the cross attention is cached, it is computed once for all beams.
+                let flush = i == 0 && beam == 0;
+                let ys = model
+                    .model
+                    .decoder_forward(&tokens_t, audio_features, flush)?;
+                if i == 0 {
+                    let logits = model.model.decoder_final_linear(&ys.i(..1)?)?.i(0)?.i(0)?;
+                    no_speech_prob = softmax(&logits, 0)?
+                        .i(self.no_speech_token as usize)?
+                        .to_scalar::<f32>()? as f64;
+                }
+                let (_, seq_len, _) = ys.dims3()?;
+                let logits = model
+                    .model
+                    .decoder_final_linear(&ys.i((..1, seq_len - 1..))?)?
+                    .i(0)?
+                    .i(0)?
+                    .broadcast_add(&self.suppress_tokens)?;
+                let logprobs: Vec<f32> = log_softmax(&logits, 0)?.to_vec1()?;
+                let mut best = logprobs.iter().enumerate().collect::<Vec<_>>();
+                best.sort_by(|(_, a), (_, b)| b.total_cmp(a));
+                for (token, logprob) in best.into_iter().take(beam_size) {
+                    let mut tokens = tokens.clone();
+                    tokens.push(token as u32);
+                    candidates.push((tokens, sum_logprob + *logprob as f64));
+                }
+            }
+            candidates.sort_by(|(_, a), (_, b)| b.total_cmp(a));
+            beams = Vec::with_capacity(beam_size);
+            for (tokens, sum_logprob) in candidates {
+                let last = *tokens.last().unwrap();
+                if last == self.eot_token || tokens.len() > max_len {
+                    if finished.len() < beam_size {
+                        finished.push((tokens, sum_logprob));
+                    }
+                } else if beams.len() < beam_size {
+                    beams.push((tokens, sum_logprob));
+                }
+            }
+            if finished.len() >= beam_size || beams.is_empty() {
+                break;
+            }
+        }
+        finished.extend(beams);
+        let average = |(tokens, sum_logprob): &(Vec<u32>, f64)| sum_logprob / tokens.len() as f64;
+        let (tokens, sum_logprob) = finished
+            .into_iter()
+            .max_by(|a, b| average(a).total_cmp(&average(b)))
+            .ok_or_else(|| anyhow::anyhow!("beam search produced no transcript"))?;
+        let text = self.model.tokenizer.decode(&tokens, true).map_err(E::msg)?;
+        let avg_logprob = sum_logprob / tokens.len() as f64;
+
+        Ok(DecodingResult {
+            tokens,
+            text,
+            avg_logprob,
+            no_speech_prob,
+            temperature: 0f64,
+            compression_ratio: f64::NAN,
+        })
+    }
+
     pub fn decode_with_fallback(&mut self, segment: &Tensor) -> Result<DecodingResult> {
-        for (i, &t) in m::TEMPERATURES.iter().enumerate() {
+        let decoding = self.model.decoding;
+        let temperatures = decoding.temperatures();
+        for (i, &t) in temperatures.iter().enumerate() {
             let dr: Result<DecodingResult> = self.decode(segment, t);
-            if i == m::TEMPERATURES.len() - 1 {
+            if i == temperatures.len() - 1 {
                 return dr;
             }
             // On errors, we try again with a different temperature.
             match dr {
                 Ok(dr) => {
                     let needs_fallback = dr.compression_ratio > m::COMPRESSION_RATIO_THRESHOLD
-                        || dr.avg_logprob < m::LOGPROB_THRESHOLD;
-                    if !needs_fallback || dr.no_speech_prob > m::NO_SPEECH_THRESHOLD {
+                        || dr.avg_logprob < decoding.logprob_threshold;
+                    if !needs_fallback || dr.no_speech_prob > decoding.no_speech_threshold {
                         return Ok(dr);
                     }
                 }
@@ -284,7 +460,10 @@ impl<'a> Decoder<'a> {
             let segment_duration = (segment_size * m::HOP_LENGTH) as f64 / m::SAMPLE_RATE as f64;
             let dr = self.decode_with_fallback(&mel_segment)?;
             seek += segment_size;
-            if dr.no_speech_prob > m::NO_SPEECH_THRESHOLD && dr.avg_logprob < m::LOGPROB_THRESHOLD {
+            let decoding = self.model.decoding;
+            if dr.no_speech_prob > decoding.no_speech_threshold
+                && dr.avg_logprob < decoding.logprob_threshold
+            {
                 println!("no speech detected, skipping {seek} {dr:?}");
                 continue;
             }
@@ -526,6 +705,9 @@ impl AudioDecoderModel {
                     tokenizer,
                     config,
                     device,
+                    seed: DEFAULT_SEED,
+                    vad: None,
+                    decoding: DecodingParams::default(),
                 })
             }
             true => {
@@ -553,16 +735,45 @@ impl AudioDecoderModel {
                     tokenizer,
                     config,
                     device,
+                    seed: DEFAULT_SEED,
+                    vad: None,
+                    decoding: DecodingParams::default(),
                 })
             }
         }
     }
+
+    /// Runs voice activity detection before Whisper, so that segments follow the pauses of the
+    /// speaker instead of fixed 30s windows. The `start` and `end` metadata of the embeddings
+    /// are then the boundaries of the utterances.
+    pub fn with_vad(mut self, vad: Option<VadConfig>) -> Self {
+        self.vad = vad;
+        self
+    }
+
+    /// Overrides the beam size, temperatures and thresholds of the decoding. Noisy audio
+    /// often transcribes better with beam search and a lower `no_speech_threshold`.
+    pub fn with_decoding(mut self, decoding: DecodingParams) -> Self {
+        self.decoding = decoding;
+        self
+    }
+
+    /// Sets the seed of the temperature fallback sampling, and of the random number generator
+    /// of CUDA and Metal devices. Candle can't seed the CPU generator, which the decoder does
+    /// not use.
+    pub fn set_seed(&mut self, seed: u64) -> Result<()> {
+        self.seed = seed;
+        if !self.device.is_cpu() {
+            self.device.set_seed(seed)?;
+        }
+        Ok(())
+    }
 }
 
 #[cfg(feature = "audio")]
 mod audio_processing {
     use super::*;
-    use crate::file_processor::audio::pcm_decode;
+    use crate::file_processor::audio::{pcm_decode, vad::detect_speech};
 
     impl AudioDecoderModel {
         pub fn process_audio<T: AsRef<std::path::Path>>(
@@ -580,37 +791,57 @@ mod audio_processing {
                 &mut mel_filters,
             );
 
-            let (pcm_data, sample_rate) = pcm_decode::audio_processing::pcm_decode(audio_path)?;
-            if sample_rate != m::SAMPLE_RATE as u32 {
-                anyhow::bail!("input file must have a {} sampling rate", m::SAMPLE_RATE)
-            }
+            // Whisper expects 16kHz mono audio.
+            let sample_rate = m::SAMPLE_RATE as u32;
+            let pcm_data =
+                pcm_decode::audio_processing::pcm_decode_resampled(audio_path, sample_rate)?;
             println!("pcm data loaded {}", pcm_data.len());
-            let mel = audio::pcm_to_mel(&self.config, &pcm_data, &mel_filters);
-            let mel_len = mel.len();
-            let mel = Tensor::from_vec(
-                mel,
-                (
-                    1,
-                    self.config.num_mel_bins,
-                    mel_len / self.config.num_mel_bins,
-                ),
-                &self.device,
-            )?;
-            println!("loaded mel: {:?}", mel.dims());
+            let config = self.config.clone();
+            let device = self.device.clone();
+            let to_mel = |pcm: &[f32]| -> Result<Tensor> {
+                let mel = audio::pcm_to_mel(&config, pcm, &mel_filters);
+                let mel_len = mel.len();
+                Ok(Tensor::from_vec(
+                    mel,
+                    (1, config.num_mel_bins, mel_len / config.num_mel_bins),
+                    &device,
+                )?)
+            };
 
             let language_token = None;
 
+            let seed = self.seed;
+            let vad = self.vad;
             let mut dc = Decoder::new(
                 self,
-                299792458,
-                &self.device.clone(),
+                seed,
+                &device,
                 language_token,
                 Some(Task::Transcribe),
                 false,
                 false,
             )?;
-            let segments = dc.run(&mel)?;
 
+            let Some(vad) = vad else {
+                let mel = to_mel(&pcm_data)?;
+                println!("loaded mel: {:?}", mel.dims());
+                return dc.run(&mel);
+            };
+            let mut segments = Vec::new();
+            for utterance in detect_speech(&pcm_data, sample_rate, &vad) {
+                let mel = to_mel(&pcm_data[utterance.start..utterance.end])?;
+                let offset = utterance.start_secs(sample_rate);
+                let end = utterance.end_secs(sample_rate);
+                // The mel spectrogram is padded, so the last segment is cut at the end of the
+                // utterance.
+                for mut segment in dc.run(&mel)? {
+                    segment.start += offset;
+                    segment.duration = segment.duration.min(end - segment.start);
+                    if segment.duration > 0.0 {
+                        segments.push(segment);
+                    }
+                }
+            }
             Ok(segments)
         }
     }
@@ -621,3 +852,46 @@ mod audio_processing {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_default_temperatures() {
+        let temperatures = DecodingParams::default().temperatures();
+        assert_eq!(temperatures.len(), m::TEMPERATURES.len());
+        for (t, expected) in temperatures.iter().zip(m::TEMPERATURES) {
+            assert!((t - expected).abs() < 1e-9);
+        }
+
+        let params = DecodingParams::default().with_temperature(0.5, 0.0);
+        assert_eq!(params.temperatures(), vec![0.5]);
+    }
+
+    #[cfg(feature = "audio")]
+    #[test]
+    fn test_greedy_and_beam_search_decoding() {
+        let transcript = |decoding: DecodingParams| {
+            let mut model = AudioDecoderModel::from_pretrained(
+                Some("openai/whisper-tiny.en"),
+                Some("main"),
+                "tiny-en",
+                false,
+            )
+            .unwrap()
+            .with_decoding(decoding);
+            model
+                .process_audio("../test_files/audio/samples_jfk.wav")
+                .unwrap()
+                .iter()
+                .map(|segment| segment.dr.text.clone())
+                .collect::<String>()
+        };
+
+        let greedy = transcript(DecodingParams::default());
+        let beam = transcript(DecodingParams::default().with_beam_size(3));
+        assert!(greedy.contains("country"));
+        assert!(beam.contains("country"));
+    }
+}
@@ -1,26 +1,100 @@
+/// Zero crossings of the sinc kernel on each side of a sample.
+const SINC_ZERO_CROSSINGS: usize = 16;
+
+/// Resamples mono `pcm` from `from` Hz to `to` Hz with a Hann windowed sinc filter. When
+/// downsampling, the cutoff of the filter is lowered to the new Nyquist frequency so that high
+/// frequencies don't alias.
+pub fn resample(pcm: &[f32], from: u32, to: u32) -> Vec<f32> {
+    if from == to || pcm.is_empty() {
+        return pcm.to_vec();
+    }
+    let ratio = to as f64 / from as f64;
+    let cutoff = ratio.min(1.0);
+    // Half width of the kernel, in input samples.
+    let half_width = SINC_ZERO_CROSSINGS as f64 / cutoff;
+    let len = (pcm.len() as f64 * ratio).round() as usize;
+
+    (0..len)
+        .map(|i| {
+            let center = i as f64 / ratio;
+            let first = (center - half_width).ceil().max(0.0) as usize;
+            let last = ((center + half_width).floor() as usize).min(pcm.len() - 1);
+            let mut sum = 0.0;
+            let mut weights = 0.0;
+            for (j, sample) in pcm.iter().enumerate().take(last + 1).skip(first) {
+                let x = j as f64 - center;
+                let window = 0.5 + 0.5 * (std::f64::consts::PI * x / half_width).cos();
+                let weight = cutoff * sinc(cutoff * x) * window;
+                sum += *sample as f64 * weight;
+                weights += weight;
+            }
+            // Normalizing by the sum of the weights keeps the gain at 1 near the edges, where
+            // the kernel is cut.
+            if weights.abs() > f64::EPSILON {
+                (sum / weights) as f32
+            } else {
+                0.0
+            }
+        })
+        .collect()
+}
+
+fn sinc(x: f64) -> f64 {
+    if x.abs() < 1e-9 {
+        1.0
+    } else {
+        let x = std::f64::consts::PI * x;
+        x.sin() / x
+    }
+}
+
 #[cfg(feature = "audio")]
 pub mod audio_processing {
+    use anyhow::anyhow;
     use symphonia::core::audio::{AudioBufferRef, Signal};
     use symphonia::core::codecs::{DecoderOptions, CODEC_TYPE_NULL};
     use symphonia::core::conv::FromSample;
 
-    fn conv<T>(samples: &mut Vec<f32>, data: std::borrow::Cow<symphonia::core::audio::AudioBuffer<T>>)
-    where
+    /// Averages the channels of `data` into `samples`.
+    fn conv<T>(
+        samples: &mut Vec<f32>,
+        data: std::borrow::Cow<symphonia::core::audio::AudioBuffer<T>>,
+    ) where
         T: symphonia::core::sample::Sample,
         f32: symphonia::core::conv::FromSample<T>,
     {
-        samples.extend(data.chan(0).iter().map(|v| f32::from_sample(*v)))
+        let channels = data.spec().channels.count();
+        let start = samples.len();
+        samples.extend(data.chan(0).iter().map(|v| f32::from_sample(*v)));
+        for channel in 1..channels {
+            for (sample, v) in samples[start..].iter_mut().zip(data.chan(channel)) {
+                *sample += f32::from_sample(*v);
+            }
+        }
+        if channels > 1 {
+            for sample in samples[start..].iter_mut() {
+                *sample /= channels as f32;
+            }
+        }
     }
 
-    pub(crate) fn pcm_decode<P: AsRef<std::path::Path>>(path: P) -> anyhow::Result<(Vec<f32>, u32)> {
+    /// Decodes an audio file into mono samples, averaging the channels, and returns them with
+    /// their sample rate. WAV, MP3, FLAC, OGG Vorbis and M4A/AAC files are supported. The
+    /// extension is used as a hint, the format is detected from the content.
+    pub(crate) fn pcm_decode<P: AsRef<std::path::Path>>(
+        path: P,
+    ) -> anyhow::Result<(Vec<f32>, u32)> {
         // Open the media source.
-        let src = std::fs::File::open(path)?;
+        let src = std::fs::File::open(&path)?;
 
         // Create the media source stream.
         let mss = symphonia::core::io::MediaSourceStream::new(Box::new(src), Default::default());
 
-        // Create a probe hint using the file's extension. [Optional]
-        let hint = symphonia::core::probe::Hint::new();
+        // Create a probe hint using the file's extension.
+        let mut hint = symphonia::core::probe::Hint::new();
+        if let Some(extension) = path.as_ref().extension().and_then(|e| e.to_str()) {
+            hint.with_extension(extension);
+        }
 
         // Use the default options for metadata and format readers.
         let meta_opts: symphonia::core::meta::MetadataOptions = Default::default();
@@ -36,17 +110,15 @@ pub mod audio_processing {
             .tracks()
             .iter()
             .find(|t| t.codec_params.codec != CODEC_TYPE_NULL)
-            .expect("no supported audio tracks");
+            .ok_or_else(|| anyhow!("No supported audio track in {:?}", path.as_ref()))?;
 
         // Use the default options for the decoder.
         let dec_opts: DecoderOptions = Default::default();
 
         // Create a decoder for the track.
-        let mut decoder = symphonia::default::get_codecs()
-            .make(&track.codec_params, &dec_opts)
-            .expect("unsupported codec");
+        let mut decoder = symphonia::default::get_codecs().make(&track.codec_params, &dec_opts)?;
         let track_id = track.id;
-        let sample_rate = track.codec_params.sample_rate.unwrap_or(0);
+        let mut sample_rate = track.codec_params.sample_rate.unwrap_or(0);
         let mut pcm_data = Vec::new();
         // The decode loop.
         while let Ok(packet) = format.next_packet() {
@@ -59,8 +131,11 @@ pub mod audio_processing {
             if packet.track_id() != track_id {
                 continue;
             }
-            match decoder.decode(&packet)? {
-                AudioBufferRef::F32(buf) => pcm_data.extend(buf.chan(0)),
+            let decoded = decoder.decode(&packet)?;
+            // Some containers, such as raw AAC streams, only give the rate in the packets.
+            sample_rate = decoded.spec().rate;
+            match decoded {
+                AudioBufferRef::F32(data) => conv(&mut pcm_data, data),
                 AudioBufferRef::U8(data) => conv(&mut pcm_data, data),
                 AudioBufferRef::U16(data) => conv(&mut pcm_data, data),
                 AudioBufferRef::U24(data) => conv(&mut pcm_data, data),
@@ -74,4 +149,70 @@ pub mod audio_processing {
         }
         Ok((pcm_data, sample_rate))
     }
+
+    /// Decodes an audio file into mono samples at `sample_rate`, see [pcm_decode].
+    pub(crate) fn pcm_decode_resampled<P: AsRef<std::path::Path>>(
+        path: P,
+        sample_rate: u32,
+    ) -> anyhow::Result<Vec<f32>> {
+        let (pcm_data, source_rate) = pcm_decode(path)?;
+        Ok(super::resample(&pcm_data, source_rate, sample_rate))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_resample() {
+        let tone = |rate: u32, secs: f32| {
+            (0..(rate as f32 * secs) as usize)
+                .map(|i| (2.0 * std::f32::consts::PI * 440.0 * i as f32 / rate as f32).sin())
+                .collect::<Vec<_>>()
+        };
+        let resampled = resample(&tone(44100, 0.5), 44100, 16000);
+        assert_eq!(resampled.len(), 8000);
+        // Away from the edges, the tone is the same as one generated at 16kHz.
+        let expected = tone(16000, 0.5);
+        for i in 100..7900 {
+            assert!((resampled[i] - expected[i]).abs() < 0.01);
+        }
+
+        assert_eq!(resample(&tone(8000, 0.5), 8000, 16000).len(), 8000);
+        assert_eq!(resample(&[0.5; 10], 16000, 16000), vec![0.5; 10]);
+    }
+
+    #[cfg(feature = "audio")]
+    #[test]
+    fn test_decode_formats() {
+        use audio_processing::{pcm_decode, pcm_decode_resampled};
+
+        // 0.3s of a 440Hz tone, in stereo.
+        let (pcm, sample_rate) =
+            pcm_decode("../test_files/audio/tone_22050hz_stereo.flac").unwrap();
+        assert_eq!(sample_rate, 22050);
+        assert_eq!(pcm.len(), 6615);
+        let pcm =
+            pcm_decode_resampled("../test_files/audio/tone_22050hz_stereo.flac", 16000).unwrap();
+        assert_eq!(pcm.len(), 4800);
+        assert!(pcm.iter().any(|sample| sample.abs() > 0.2));
+
+        // 20 frames of silence.
+        for file in ["silence_44100hz.mp3", "silence_44100hz.m4a"] {
+            let path = format!("../test_files/audio/{}", file);
+            let (pcm, sample_rate) = pcm_decode(&path).unwrap();
+            assert_eq!(sample_rate, 44100);
+            assert!(!pcm.is_empty());
+            let resampled = pcm_decode_resampled(&path, 16000).unwrap();
+            assert_eq!(
+                resampled.len(),
+                (pcm.len() as f64 * 16000.0 / 44100.0).round() as usize
+            );
+            assert!(resampled.iter().all(|sample| sample.abs() < 1e-3));
+        }
+
+        let (_, sample_rate) = pcm_decode("../test_files/audio/samples_jfk.wav").unwrap();
+        assert_eq!(sample_rate, 16000);
+    }
 }
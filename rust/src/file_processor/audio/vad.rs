@@ -0,0 +1,188 @@
+//! Energy based voice activity detection, used to cut audio on silences before transcription so
+//! that every Whisper segment is a natural utterance instead of a fixed 30s window.
+
+/// Length of the frames the energy is measured on.
+const FRAME_MS: usize = 30;
+/// Frames quieter than this are silence whatever the sensitivity.
+const SILENCE_DB: f32 = -60.0;
+/// Recordings whose quiet and loud frames are closer than this have no pause to cut on.
+const MIN_DYNAMIC_RANGE_DB: f32 = 10.0;
+/// Speech shorter than this, such as a click, is ignored.
+const MIN_SPEECH_MS: usize = 90;
+
+#[derive(Debug, Clone, Copy)]
+pub struct VadConfig {
+    /// Between 0 and 1. Higher values count quieter frames as speech, which helps with soft
+    /// voices but keeps more background noise. Defaults to 0.5.
+    pub sensitivity: f32,
+    /// Pauses shorter than this, such as the ones between words, stay inside an utterance.
+    /// Defaults to 300.
+    pub min_silence_ms: u32,
+}
+
+impl Default for VadConfig {
+    fn default() -> Self {
+        Self {
+            sensitivity: 0.5,
+            min_silence_ms: 300,
+        }
+    }
+}
+
+impl VadConfig {
+    pub fn new(sensitivity: f32) -> Self {
+        Self {
+            sensitivity,
+            ..Default::default()
+        }
+    }
+
+    pub fn with_min_silence_ms(mut self, min_silence_ms: u32) -> Self {
+        self.min_silence_ms = min_silence_ms;
+        self
+    }
+}
+
+/// An utterance, as a range of samples. `end` is exclusive.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SpeechSegment {
+    pub start: usize,
+    pub end: usize,
+}
+
+impl SpeechSegment {
+    pub fn start_secs(&self, sample_rate: u32) -> f64 {
+        self.start as f64 / sample_rate as f64
+    }
+
+    pub fn end_secs(&self, sample_rate: u32) -> f64 {
+        self.end as f64 / sample_rate as f64
+    }
+}
+
+/// Returns the utterances of mono `pcm`, separated by pauses of at least
+/// [VadConfig::min_silence_ms].
+///
+/// The threshold between speech and silence is placed between the noise floor and the speech
+/// level of the recording, so it doesn't depend on the recording volume.
+pub fn detect_speech(pcm: &[f32], sample_rate: u32, config: &VadConfig) -> Vec<SpeechSegment> {
+    let frame_len = (sample_rate as usize * FRAME_MS / 1000).max(1);
+    let energies = pcm.chunks(frame_len).map(frame_db).collect::<Vec<_>>();
+    if energies.is_empty() {
+        return Vec::new();
+    }
+
+    let mut sorted = energies.clone();
+    sorted.sort_by(f32::total_cmp);
+    let floor = sorted[(sorted.len() - 1) / 10];
+    let peak = sorted[(sorted.len() - 1) * 9 / 10];
+    if peak < SILENCE_DB {
+        return Vec::new();
+    }
+    if peak - floor < MIN_DYNAMIC_RANGE_DB {
+        return vec![SpeechSegment {
+            start: 0,
+            end: pcm.len(),
+        }];
+    }
+    let sensitivity = config.sensitivity.clamp(0.0, 1.0);
+    let threshold = (floor + (peak - floor) * (1.0 - sensitivity)).max(SILENCE_DB);
+
+    // Runs of speech frames, as frame ranges.
+    let mut runs: Vec<(usize, usize)> = Vec::new();
+    for (i, energy) in energies.iter().enumerate() {
+        if *energy <= threshold {
+            continue;
+        }
+        match runs.last_mut() {
+            Some((_, end)) if *end == i => *end = i + 1,
+            _ => runs.push((i, i + 1)),
+        }
+    }
+
+    let min_silence = (config.min_silence_ms as usize).div_ceil(FRAME_MS);
+    let mut merged: Vec<(usize, usize)> = Vec::new();
+    for (start, end) in runs {
+        match merged.last_mut() {
+            Some((_, last_end)) if start - *last_end < min_silence => *last_end = end,
+            _ => merged.push((start, end)),
+        }
+    }
+
+    // One frame of padding on each side keeps the onset and the decay of the voice, which are
+    // quieter than the threshold.
+    let min_speech = MIN_SPEECH_MS.div_ceil(FRAME_MS);
+    merged
+        .into_iter()
+        .filter(|(start, end)| end - start >= min_speech)
+        .map(|(start, end)| SpeechSegment {
+            start: start.saturating_sub(1) * frame_len,
+            end: ((end + 1) * frame_len).min(pcm.len()),
+        })
+        .collect()
+}
+
+/// RMS level of a frame, in dBFS.
+fn frame_db(frame: &[f32]) -> f32 {
+    let mean_square = frame.iter().map(|x| x * x).sum::<f32>() / frame.len() as f32;
+    10.0 * mean_square.max(1e-12).log10()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SAMPLE_RATE: u32 = 16000;
+
+    /// Appends `secs` of a 440Hz tone, or of faint noise when `amplitude` is 0.
+    fn push(pcm: &mut Vec<f32>, secs: f32, amplitude: f32) {
+        let n = (secs * SAMPLE_RATE as f32) as usize;
+        pcm.extend((0..n).map(|i| {
+            let t = i as f32 / SAMPLE_RATE as f32;
+            let noise = if i % 2 == 0 { 1e-4 } else { -1e-4 };
+            amplitude * (2.0 * std::f32::consts::PI * 440.0 * t).sin() + noise
+        }));
+    }
+
+    #[test]
+    fn test_detect_speech() {
+        let mut pcm = Vec::new();
+        push(&mut pcm, 0.5, 0.0);
+        push(&mut pcm, 1.0, 0.5);
+        // Too short to split the utterance.
+        push(&mut pcm, 0.1, 0.0);
+        push(&mut pcm, 0.5, 0.5);
+        push(&mut pcm, 0.6, 0.0);
+        push(&mut pcm, 0.8, 0.3);
+        push(&mut pcm, 0.5, 0.0);
+
+        let segments = detect_speech(&pcm, SAMPLE_RATE, &VadConfig::default());
+        assert_eq!(segments.len(), 2);
+        let tolerance = 0.07;
+        let expected = [(0.5, 2.1), (2.7, 3.5)];
+        for (segment, (start, end)) in segments.iter().zip(expected) {
+            assert!((segment.start_secs(SAMPLE_RATE) - start).abs() < tolerance);
+            assert!((segment.end_secs(SAMPLE_RATE) - end).abs() < tolerance);
+        }
+
+        assert!(detect_speech(&vec![0.0; 16000], SAMPLE_RATE, &VadConfig::default()).is_empty());
+    }
+
+    #[cfg(feature = "audio")]
+    #[test]
+    fn test_boundaries_fall_on_silences() {
+        use crate::file_processor::audio::pcm_decode::audio_processing::pcm_decode;
+
+        let (pcm, sample_rate) = pcm_decode("../test_files/audio/samples_jfk.wav").unwrap();
+        let segments = detect_speech(&pcm, sample_rate, &VadConfig::default());
+        assert!(segments.len() > 1);
+
+        let frame_len = sample_rate as usize * FRAME_MS / 1000;
+        let loudest = pcm.chunks(frame_len).map(frame_db).fold(f32::MIN, f32::max);
+        for pair in segments.windows(2) {
+            let pause = &pcm[pair[0].end..pair[1].start];
+            assert!(!pause.is_empty());
+            assert!(frame_db(pause) < loudest - 15.0);
+        }
+    }
+}
@@ -0,0 +1,213 @@
+use unicode_normalization::UnicodeNormalization;
+
+/// Cleanup applied to extracted text before it is chunked, set with
+/// [TextEmbedConfig::with_text_cleaning](crate::config::TextEmbedConfig::with_text_cleaning).
+///
+/// The default is a mild clean that collapses whitespace and strips control characters. Use
+/// [CleaningOptions::all] to also join words hyphenated across lines and normalize Unicode,
+/// which helps with text extracted from PDF files, or [CleaningOptions::none] to embed the text
+/// as extracted.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct CleaningOptions {
+    /// Joins words hyphenated at a line break, e.g. "inter-\nnational" becomes "international",
+    /// and removes soft hyphens. Hyphens followed by a capitalized word are kept.
+    pub dehyphenate: bool,
+    /// Replaces runs of spaces and tabs with a single space, trims lines and keeps at most one
+    /// blank line between paragraphs.
+    pub collapse_whitespace: bool,
+    /// Removes control characters other than newlines and tabs, e.g. form feeds and NUL bytes.
+    pub strip_control: bool,
+    /// Applies Unicode NFKC normalization, which e.g. replaces the ligature "ﬁ" with "fi" and
+    /// non-breaking spaces with spaces.
+    pub normalize_unicode: bool,
+}
+
+impl Default for CleaningOptions {
+    fn default() -> Self {
+        Self {
+            dehyphenate: false,
+            collapse_whitespace: true,
+            strip_control: true,
+            normalize_unicode: false,
+        }
+    }
+}
+
+impl CleaningOptions {
+    /// Leaves the text as extracted.
+    pub fn none() -> Self {
+        Self {
+            dehyphenate: false,
+            collapse_whitespace: false,
+            strip_control: false,
+            normalize_unicode: false,
+        }
+    }
+
+    /// Applies every cleaning operation.
+    pub fn all() -> Self {
+        Self {
+            dehyphenate: true,
+            collapse_whitespace: true,
+            strip_control: true,
+            normalize_unicode: true,
+        }
+    }
+
+    pub fn with_dehyphenate(mut self, dehyphenate: bool) -> Self {
+        self.dehyphenate = dehyphenate;
+        self
+    }
+
+    pub fn with_collapse_whitespace(mut self, collapse_whitespace: bool) -> Self {
+        self.collapse_whitespace = collapse_whitespace;
+        self
+    }
+
+    pub fn with_strip_control(mut self, strip_control: bool) -> Self {
+        self.strip_control = strip_control;
+        self
+    }
+
+    pub fn with_normalize_unicode(mut self, normalize_unicode: bool) -> Self {
+        self.normalize_unicode = normalize_unicode;
+        self
+    }
+
+    /// Applies the enabled operations to `text`.
+    pub fn clean(&self, text: &str) -> String {
+        let mut text = text.to_string();
+        if self.strip_control {
+            text = strip_control(&text);
+        }
+        if self.normalize_unicode {
+            text = text.nfkc().collect();
+        }
+        if self.dehyphenate {
+            text = dehyphenate(&text);
+        }
+        if self.collapse_whitespace {
+            text = collapse_whitespace(&text);
+        }
+        text
+    }
+}
+
+fn strip_control(text: &str) -> String {
+    text.chars()
+        .filter(|c| !c.is_control() || matches!(c, '\n' | '\r' | '\t'))
+        .collect()
+}
+
+fn dehyphenate(text: &str) -> String {
+    let chars = text.chars().collect::<Vec<_>>();
+    let mut cleaned = String::with_capacity(text.len());
+    let mut i = 0;
+    while i < chars.len() {
+        let c = chars[i];
+        if c == '\u{AD}' {
+            // A soft hyphen only marks where a word may be broken.
+            i += 1;
+            while matches!(chars.get(i), Some(' ' | '\t' | '\r' | '\n')) {
+                i += 1;
+            }
+            continue;
+        }
+        if c == '-' && i > 0 && chars[i - 1].is_alphabetic() {
+            let mut next = i + 1;
+            while matches!(chars.get(next), Some(' ' | '\t' | '\r')) {
+                next += 1;
+            }
+            if chars.get(next) == Some(&'\n') {
+                next += 1;
+                while matches!(chars.get(next), Some(' ' | '\t')) {
+                    next += 1;
+                }
+                if chars.get(next).is_some_and(|c| c.is_lowercase()) {
+                    i = next;
+                    continue;
+                }
+            }
+        }
+        cleaned.push(c);
+        i += 1;
+    }
+    cleaned
+}
+
+fn collapse_whitespace(text: &str) -> String {
+    let mut cleaned = String::with_capacity(text.len());
+    let mut blank_lines = 0;
+    for line in text.lines() {
+        let line = line.split_whitespace().collect::<Vec<_>>().join(" ");
+        if line.is_empty() {
+            blank_lines += 1;
+            continue;
+        }
+        if !cleaned.is_empty() {
+            cleaned.push_str(if blank_lines > 0 { "\n\n" } else { "\n" });
+        }
+        blank_lines = 0;
+        cleaned.push_str(&line);
+    }
+    cleaned
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_dehyphenate() {
+        let options = CleaningOptions::none().with_dehyphenate(true);
+        assert_eq!(
+            options.clean("The inter-\nnational trade agree- \n  ment was signed."),
+            "The international trade agreement was signed."
+        );
+        assert_eq!(options.clean("co\u{AD}operation"), "cooperation");
+        // Hyphenated compounds and names broken at the hyphen keep it.
+        assert_eq!(
+            options.clean("a well-known fact\nSmith-\nJones"),
+            "a well-known fact\nSmith-\nJones"
+        );
+        assert_eq!(options.clean("- item\n- item"), "- item\n- item");
+    }
+
+    #[test]
+    fn test_collapse_whitespace() {
+        let options = CleaningOptions::none().with_collapse_whitespace(true);
+        assert_eq!(
+            options.clean("  Title  \r\n\n\n\n First\t\tline   of text.  \nSecond line.\n\n"),
+            "Title\n\nFirst line of text.\nSecond line."
+        );
+    }
+
+    #[test]
+    fn test_strip_control() {
+        let options = CleaningOptions::none().with_strip_control(true);
+        assert_eq!(
+            options.clean("Page 1\u{c}Page 2\u{0}\u{7}\n\tindented"),
+            "Page 1Page 2\n\tindented"
+        );
+    }
+
+    #[test]
+    fn test_normalize_unicode() {
+        let options = CleaningOptions::none().with_normalize_unicode(true);
+        assert_eq!(
+            options.clean("\u{FB01}nancial e\u{301}te\u{A0}report"),
+            "financial \u{E9}te report"
+        );
+    }
+
+    #[test]
+    fn test_default_and_all() {
+        let messy = "Inter-\nnational\u{0}  \u{FB01}rms\n\n\n";
+        assert_eq!(
+            CleaningOptions::default().clean(messy),
+            "Inter-\nnational \u{FB01}rms"
+        );
+        assert_eq!(CleaningOptions::all().clean(messy), "International firms");
+        assert_eq!(CleaningOptions::none().clean(messy), messy);
+    }
+}
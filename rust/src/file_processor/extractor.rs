@@ -0,0 +1,295 @@
+use std::{
+    collections::HashMap,
+    path::Path,
+    sync::{Arc, OnceLock, RwLock},
+};
+
+use anyhow::Result;
+
+use crate::{
+    chunkers::code::CodeLanguage,
+    text_loader::{join_pages, ExtractedDocument},
+};
+
+use super::{
+    docx_processor::DocxProcessor,
+    html_processor::HtmlProcessor,
+    markdown_processor::MarkdownProcessor,
+    odt_processor::OdtProcessor,
+    pdf_processor::PdfProcessor,
+    pdf_tables::{TableMode, CONTAINS_TABLE_KEY},
+    rtf_processor::RtfProcessor,
+    txt_processor::TxtProcessor,
+};
+
+/// Reads the text of the files of a format. Implement it to embed formats that EmbedAnything
+/// doesn't read, and add it with [register_extractor].
+pub trait Extractor: Send + Sync {
+    /// Whether the extractor reads `path`, usually judging by its extension.
+    fn can_handle(&self, path: &Path) -> bool;
+
+    /// Extracts the text of `path`. File system metadata and `file_type` are added by
+    /// [crate::text_loader::TextLoader::extract_document] when the extractor leaves them out.
+    fn extract(&self, path: &Path) -> Result<ExtractedDocument>;
+}
+
+fn registry() -> &'static RwLock<Vec<Arc<dyn Extractor>>> {
+    static REGISTRY: OnceLock<RwLock<Vec<Arc<dyn Extractor>>>> = OnceLock::new();
+    REGISTRY.get_or_init(|| RwLock::new(Vec::new()))
+}
+
+/// Adds `extractor` to the extractors consulted by [crate::text_loader::TextLoader]. Registered
+/// extractors are tried before the built-in ones, the most recently registered first, so they
+/// can also replace how a supported format is read.
+pub fn register_extractor(extractor: impl Extractor + 'static) {
+    registry().write().unwrap().push(Arc::new(extractor));
+}
+
+/// The registered extractor handling `path`, if any.
+pub fn registered_extractor(path: &Path) -> Option<Arc<dyn Extractor>> {
+    registry()
+        .read()
+        .unwrap()
+        .iter()
+        .rev()
+        .find(|extractor| extractor.can_handle(path))
+        .cloned()
+}
+
+fn extension(path: &Path) -> &str {
+    path.extension()
+        .and_then(|extension| extension.to_str())
+        .unwrap_or_default()
+}
+
+fn document(path: &Path, text: String) -> ExtractedDocument {
+    ExtractedDocument {
+        text,
+        pages: Vec::new(),
+        file_type: extension(path).to_string(),
+        metadata: HashMap::new(),
+    }
+}
+
+/// Reads PDF files page by page, with OCR when `use_ocr` is set.
+#[derive(Debug, Clone, Default)]
+pub struct PdfExtractor {
+    pub use_ocr: bool,
+    pub tesseract_path: Option<String>,
+    /// When set, the text is read from the layout of the pages, with tables written as the
+    /// mode says, and the [CONTAINS_TABLE_KEY] metadata entry tells whether a table was
+    /// found. Ignored with OCR.
+    pub table_mode: Option<TableMode>,
+}
+
+impl PdfExtractor {
+    pub const EXTENSIONS: &'static [&'static str] = &["pdf"];
+
+    pub fn new(use_ocr: bool, tesseract_path: Option<&str>) -> Self {
+        Self {
+            use_ocr,
+            tesseract_path: tesseract_path.map(str::to_string),
+            table_mode: None,
+        }
+    }
+
+    pub fn with_table_mode(mut self, table_mode: Option<TableMode>) -> Self {
+        self.table_mode = table_mode;
+        self
+    }
+}
+
+impl Extractor for PdfExtractor {
+    fn can_handle(&self, path: &Path) -> bool {
+        Self::EXTENSIONS.contains(&extension(path))
+    }
+
+    fn extract(&self, path: &Path) -> Result<ExtractedDocument> {
+        if let (Some(table_mode), false) = (self.table_mode, self.use_ocr) {
+            let (pages, tables): (Vec<_>, Vec<_>) =
+                PdfProcessor::extract_pages_with_tables(path, table_mode)?
+                    .into_iter()
+                    .unzip();
+            let (text, pages) = join_pages(pages);
+            let mut document = ExtractedDocument {
+                pages,
+                ..document(path, text)
+            };
+            let contains_table = tables.into_iter().any(|table| table);
+            document
+                .metadata
+                .insert(CONTAINS_TABLE_KEY.to_string(), contains_table.to_string());
+            return Ok(document);
+        }
+        let pages =
+            PdfProcessor::extract_pages(path, self.use_ocr, self.tesseract_path.as_deref())?;
+        let (text, pages) = join_pages(pages);
+        Ok(ExtractedDocument {
+            pages,
+            ..document(path, text)
+        })
+    }
+}
+
+/// Reads the headings, paragraphs and code blocks of HTML files.
+#[derive(Default)]
+pub struct HtmlExtractor {
+    processor: HtmlProcessor,
+}
+
+impl HtmlExtractor {
+    pub const EXTENSIONS: &'static [&'static str] = &["html", "htm"];
+
+    pub fn new(processor: HtmlProcessor) -> Self {
+        Self { processor }
+    }
+}
+
+impl Extractor for HtmlExtractor {
+    fn can_handle(&self, path: &Path) -> bool {
+        Self::EXTENSIONS.contains(&extension(path))
+    }
+
+    fn extract(&self, path: &Path) -> Result<ExtractedDocument> {
+        let html = self.processor.process_html_file(path, None::<String>)?;
+        let text = [html.headers, html.paragraphs, html.codes]
+            .into_iter()
+            .flatten()
+            .flatten()
+            .filter(|text| !text.is_empty())
+            .collect::<Vec<_>>()
+            .join("\n\n");
+        let mut document = document(path, text);
+        if let Some(title) = html.title {
+            document.metadata.insert("title".to_string(), title);
+        }
+        Ok(document)
+    }
+}
+
+/// Reads Markdown, plain text, DOCX, RTF and ODT files, and source code in the languages of
+/// [CodeLanguage].
+#[derive(Debug, Clone, Copy, Default)]
+pub struct TextExtractor;
+
+impl TextExtractor {
+    /// Extensions of the documents read, source code extensions are in
+    /// [CodeLanguage::EXTENSIONS].
+    pub const EXTENSIONS: &'static [&'static str] = &["md", "txt", "docx", "rtf", "odt"];
+}
+
+impl Extractor for TextExtractor {
+    fn can_handle(&self, path: &Path) -> bool {
+        let extension = extension(path);
+        Self::EXTENSIONS.contains(&extension) || CodeLanguage::from_extension(extension).is_some()
+    }
+
+    fn extract(&self, path: &Path) -> Result<ExtractedDocument> {
+        let text = match extension(path) {
+            "md" => MarkdownProcessor::extract_text(&path)?,
+            "docx" => DocxProcessor::extract_text(&path)?,
+            "rtf" => RtfProcessor::extract_text(&path)?,
+            "odt" => OdtProcessor::extract_text(&path)?,
+            _ => TxtProcessor::extract_text(&path)?,
+        };
+        Ok(document(path, text))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::text_loader::TextLoader;
+    use tempdir::TempDir;
+
+    /// Reads `key=value` lines, keeping the values as text and the keys as metadata.
+    struct KeyValueExtractor;
+
+    impl Extractor for KeyValueExtractor {
+        fn can_handle(&self, path: &Path) -> bool {
+            extension(path) == "kv"
+        }
+
+        fn extract(&self, path: &Path) -> Result<ExtractedDocument> {
+            let content = std::fs::read_to_string(path)?;
+            let (keys, values): (Vec<_>, Vec<_>) = content
+                .lines()
+                .filter_map(|line| line.split_once('='))
+                .unzip();
+            let mut document = document(path, values.join("\n"));
+            document.metadata.insert("keys".to_string(), keys.join(","));
+            Ok(document)
+        }
+    }
+
+    #[test]
+    fn test_register_extractor() {
+        let directory = TempDir::new("extractor").unwrap();
+        let file = directory.path().join("notes.kv");
+        std::fs::write(&file, "title=Quarterly notes\nbody=Revenue went up.\n").unwrap();
+        assert!(!TextLoader::is_supported(&file));
+        assert!(TextLoader::extract_text(&file, false, None).is_err());
+
+        register_extractor(KeyValueExtractor);
+        assert!(TextLoader::is_supported(&file));
+        let document = TextLoader::extract_document(&file, false, None).unwrap();
+        assert_eq!(document.text, "Quarterly notes\nRevenue went up.");
+        assert_eq!(document.file_type, "kv");
+        assert_eq!(document.metadata.get("keys").unwrap(), "title,body");
+        assert!(document.metadata.contains_key("file_name"));
+
+        // Built-in formats are still read by the built-in extractors.
+        let text = TextLoader::extract_text(&"../test_files/test.txt", false, None).unwrap();
+        assert_eq!(
+            text,
+            "This is a test file to see how txt embedding works !\n"
+        );
+    }
+
+    #[test]
+    fn test_html_extractor() {
+        let document = HtmlExtractor::default()
+            .extract(Path::new("../test_files/test.html"))
+            .unwrap();
+        assert_eq!(document.text, "My First Heading\n\nMy first paragraph.");
+        assert_eq!(document.file_type, "html");
+    }
+
+    #[test]
+    fn test_pdf_table_modes() {
+        let path = Path::new("../test_files/table.pdf");
+        let extract = |table_mode| {
+            PdfExtractor::default()
+                .with_table_mode(table_mode)
+                .extract(path)
+                .unwrap()
+        };
+
+        let default = extract(None);
+        assert!(default.text.contains("Apples"));
+        assert!(!default.metadata.contains_key(CONTAINS_TABLE_KEY));
+
+        let linear = extract(Some(TableMode::Linear));
+        assert!(linear
+            .text
+            .contains("Product Units Price\nApples 120 0.50\n"));
+        assert_eq!(linear.metadata[CONTAINS_TABLE_KEY], "true");
+
+        let markdown = extract(Some(TableMode::Markdown));
+        assert!(markdown.text.contains(
+            "| Product | Units | Price |\n| --- | --- | --- |\n| Apples | 120 | 0.50 |\n\
+             | Pears | 80 | 0.75 |\n| Plums | 45 | 1.20 |"
+        ));
+        assert!(markdown.text.starts_with(
+            "Quarterly fruit sales\nThe table below lists the units sold and the unit price.\n\n|"
+        ));
+        assert!(markdown
+            .text
+            .ends_with("|\n\nPrices are in dollars per fruit."));
+
+        let skipped = extract(Some(TableMode::Skip));
+        assert!(!skipped.text.contains("Apples"));
+        assert!(skipped.text.contains("Prices are in dollars per fruit."));
+        assert_eq!(skipped.metadata[CONTAINS_TABLE_KEY], "true");
+    }
+}
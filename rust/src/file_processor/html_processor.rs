@@ -1,8 +1,8 @@
 use crate::embeddings::embed::{EmbedData, Embedder};
-use crate::embeddings::get_text_metadata;
+use crate::embeddings::{get_text_metadata, mark_embedding_model, mark_normalized};
 use crate::text_loader::{SplittingStrategy, TextLoader};
 use anyhow::Result;
-use scraper::{Html, Selector};
+use scraper::{ElementRef, Html, Selector};
 use serde_json::json;
 use std::collections::{HashMap, HashSet};
 use std::rc::Rc;
@@ -111,11 +111,16 @@ impl HtmlDocument {
                 "full_text": content,
             });
 
-            let metadata_hashmap: HashMap<String, String> = serde_json::from_value(metadata)?;
+            let mut metadata_hashmap: HashMap<String, String> = serde_json::from_value(metadata)?;
+            if let Some(title) = &self.title {
+                metadata_hashmap.insert("title".to_string(), title.trim().to_string());
+            }
 
-            let encodings = embedder.embed(&chunks, batch_size).await?;
-            let embeddings =
+            let encodings = embedder.embed_document_batch(&chunks, batch_size).await?;
+            let mut embeddings =
                 get_text_metadata(&Rc::new(encodings), &chunks, &Some(metadata_hashmap))?;
+            mark_normalized(&mut embeddings, embedder.is_normalized());
+            mark_embedding_model(&mut embeddings, &embedder.model_id());
             embed_data.extend(embeddings);
         }
 
@@ -123,8 +128,22 @@ impl HtmlDocument {
     }
 }
 
+/// Class and id fragments of elements that usually hold the main content.
+const POSITIVE_HINTS: &[&str] = &[
+    "article", "body", "content", "entry", "main", "page", "post", "text", "blog", "story",
+];
+/// Class and id fragments of boilerplate such as navigation, comments and ads.
+const NEGATIVE_HINTS: &[&str] = &[
+    "comment", "footer", "footnote", "masthead", "meta", "nav", "sidebar", "sponsor", "ad-",
+    "advert", "menu", "share", "social", "related", "widget", "banner", "promo", "cookie",
+];
+/// Tags that never contain the main content.
+const BOILERPLATE_TAGS: &[&str] = &["nav", "header", "footer", "aside", "form"];
+
 /// A Struct for processing HTML files.
-pub struct HtmlProcessor;
+pub struct HtmlProcessor {
+    readability: bool,
+}
 
 impl Default for HtmlProcessor {
     fn default() -> Self {
@@ -134,7 +153,14 @@ impl Default for HtmlProcessor {
 
 impl HtmlProcessor {
     pub fn new() -> Self {
-        Self {}
+        Self { readability: false }
+    }
+
+    /// Only keeps the main content of the page, found with a readability-style heuristic, and
+    /// drops navigation, footers and other boilerplate.
+    pub fn with_readability(mut self, readability: bool) -> Self {
+        self.readability = readability;
+        self
     }
 
     /// Extracts the contents of an HTML file.
@@ -177,9 +203,14 @@ impl HtmlProcessor {
     ) -> Result<HtmlDocument> {
         // check if https is in the website. If not, add it.
         let document = Html::parse_document(&html.into());
-        let headers = self.get_text_from_tag("h1,h2,h3", &document)?;
-        let paragraphs = self.get_text_from_tag("p", &document)?;
-        let codes = self.get_text_from_tag("code", &document)?;
+        let root = if self.readability {
+            main_content(&document).unwrap_or_else(|| document.root_element())
+        } else {
+            document.root_element()
+        };
+        let headers = self.get_text_from_tag("h1,h2,h3", root)?;
+        let paragraphs = self.get_text_from_tag("p", root)?;
+        let codes = self.get_text_from_tag("code", root)?;
         let origin = origin.map(Into::into);
         let links = match &origin {
             Some(origin) => Some(self.extract_links(&origin.clone(), &document)?),
@@ -198,9 +229,9 @@ impl HtmlProcessor {
         Ok(web_page)
     }
 
-    fn get_text_from_tag(&self, tag: &str, document: &Html) -> Result<Vec<String>> {
+    fn get_text_from_tag(&self, tag: &str, root: ElementRef) -> Result<Vec<String>> {
         let selector = Selector::parse(tag).expect("invalid selector for tag");
-        Ok(document
+        Ok(root
             .select(&selector)
             .map(|element| element.text().collect::<String>().trim().to_string())
             .collect())
@@ -228,12 +259,111 @@ impl HtmlProcessor {
             .next()
         {
             Ok(Some(title_element.text().collect::<String>()))
+        } else if let Some(og_title) = document
+            .select(&Selector::parse(r#"meta[property="og:title"]"#).expect("invalid selector"))
+            .next()
+        {
+            Ok(og_title
+                .value()
+                .attr("content")
+                .map(|title| title.to_string()))
         } else {
             Ok(None)
         }
     }
 }
 
+/// Finds the element holding the main content, following the scoring used by Mozilla's
+/// Readability: text blocks give points to their parent and half to their grandparent, class
+/// names adjust the score and link heavy elements are penalized.
+fn main_content(document: &Html) -> Option<ElementRef<'_>> {
+    let selector = Selector::parse("p, pre, td").expect("invalid selector for content");
+    let mut scores = HashMap::new();
+
+    for block in document.select(&selector) {
+        if block
+            .ancestors()
+            .filter_map(ElementRef::wrap)
+            .any(|ancestor| is_boilerplate(ancestor))
+        {
+            continue;
+        }
+        let text = block.text().collect::<String>();
+        let length = text.trim().chars().count();
+        if length < 25 {
+            continue;
+        }
+        let score = 1.0 + text.matches(',').count() as f32 + (length as f32 / 100.0).min(3.0);
+        for (level, ancestor) in block
+            .ancestors()
+            .filter_map(ElementRef::wrap)
+            .take(2)
+            .enumerate()
+        {
+            *scores
+                .entry(ancestor.id())
+                .or_insert_with(|| initial_score(ancestor)) += score / (level + 1) as f32;
+        }
+    }
+
+    scores
+        .into_iter()
+        .filter_map(|(id, score)| {
+            let element = ElementRef::wrap(document.tree.get(id)?)?;
+            Some((element, score * (1.0 - link_density(element))))
+        })
+        .max_by(|(_, a), (_, b)| a.total_cmp(b))
+        .map(|(element, _)| element)
+}
+
+fn class_weight(element: ElementRef) -> f32 {
+    let names = format!(
+        "{} {}",
+        element.value().attr("class").unwrap_or_default(),
+        element.value().id().unwrap_or_default()
+    )
+    .to_lowercase();
+    let mut weight = 0.0;
+    if NEGATIVE_HINTS.iter().any(|hint| names.contains(hint)) {
+        weight -= 25.0;
+    }
+    if POSITIVE_HINTS.iter().any(|hint| names.contains(hint)) {
+        weight += 25.0;
+    }
+    weight
+}
+
+fn initial_score(element: ElementRef) -> f32 {
+    let tag_score = match element.value().name() {
+        "article" | "main" => 10.0,
+        "div" => 5.0,
+        "pre" | "td" | "blockquote" => 3.0,
+        "address" | "ol" | "ul" | "dl" | "dd" | "dt" | "li" | "form" => -3.0,
+        "h1" | "h2" | "h3" | "h4" | "h5" | "h6" | "th" => -5.0,
+        _ => 0.0,
+    };
+    tag_score + class_weight(element)
+}
+
+fn is_boilerplate(element: ElementRef) -> bool {
+    BOILERPLATE_TAGS.contains(&element.value().name()) || class_weight(element) < 0.0
+}
+
+/// Share of the element's text that is inside links.
+fn link_density(element: ElementRef) -> f32 {
+    let length = element.text().map(|text| text.len()).sum::<usize>();
+    if length == 0 {
+        return 0.0;
+    }
+    let selector = Selector::parse("a").expect("invalid selector for link");
+    let link_length = element
+        .select(&selector)
+        .flat_map(|link| link.text())
+        .map(|text| text.len())
+        .sum::<usize>();
+    link_length as f32 / length as f32
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -253,4 +383,41 @@ mod tests {
         let result = html_processor.process_html_file(html_file, Some("https://example.com/"));
         assert!(result.is_err());
     }
+
+    const ARTICLE_PAGE: &str = r#"<html>
+<head><title>Rust embeddings</title></head>
+<body>
+  <nav><p>Home | Blog | About us, careers, press and contact</p></nav>
+  <div class="sidebar"><p>Subscribe to our newsletter for weekly updates, tips and news.</p></div>
+  <div id="main-content">
+    <article>
+      <h1>Embedding text in Rust</h1>
+      <p>Embeddings map text to vectors, so that similar sentences end up close together.</p>
+      <p>Local models, like BERT or Jina, can run on the CPU, the GPU, or through ONNX.</p>
+    </article>
+  </div>
+  <footer><p>Copyright 2024, all rights reserved, privacy policy and terms of use.</p></footer>
+</body>
+</html>"#;
+
+    #[test]
+    fn test_readability_keeps_article() {
+        let document = HtmlProcessor::new()
+            .with_readability(true)
+            .process_html(ARTICLE_PAGE, None::<String>)
+            .unwrap();
+        let paragraphs = document.paragraphs.unwrap();
+        assert_eq!(paragraphs.len(), 2);
+        assert!(paragraphs[0].starts_with("Embeddings map text"));
+        assert_eq!(document.headers.unwrap(), vec!["Embedding text in Rust"]);
+        assert_eq!(document.title.as_deref(), Some("Rust embeddings"));
+    }
+
+    #[test]
+    fn test_without_readability_keeps_everything() {
+        let document = HtmlProcessor::new()
+            .process_html(ARTICLE_PAGE, None::<String>)
+            .unwrap();
+        assert_eq!(document.paragraphs.unwrap().len(), 5);
+    }
 }
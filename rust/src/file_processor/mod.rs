@@ -1,6 +1,9 @@
 /// This module contains the file processor for different file types.
 pub mod pdf_processor;
 
+/// This module contains the detection of tables in PDF pages.
+pub mod pdf_tables;
+
 /// This module contains the file processor for markdown files.
 pub mod markdown_processor;
 
@@ -10,10 +13,32 @@ pub mod txt_processor;
 /// This module contains the processor to process web links.
 pub mod website_processor;
 
+/// This module contains the robots.txt parser used when crawling websites.
+pub mod robots;
+
 /// This module contains the file processor for HTML files.
 pub mod html_processor;
 
 /// This module contains the file processor for DOCX files.
 pub mod docx_processor;
 
+/// This module contains the file processor for RTF files.
+pub mod rtf_processor;
+
+/// This module contains the file processor for OpenDocument text files.
+pub mod odt_processor;
+
+/// This module contains the extraction of the files held in zip and tar.gz archives.
+pub mod archive;
+
+/// This module contains the extractors reading the text of files, and the registry of custom
+/// extractors.
+pub mod extractor;
+
+/// This module contains the cleanup applied to extracted text before chunking.
+pub mod cleaning;
+
+/// This module contains the redaction of personal data in extracted text.
+pub mod redaction;
+
 pub mod audio;
@@ -0,0 +1,161 @@
+use std::io::Read;
+
+use anyhow::Error;
+use xmlparser::{ElementEnd, Token, Tokenizer};
+
+/// A struct for processing OpenDocument text (ODT) files.
+pub struct OdtProcessor;
+
+impl OdtProcessor {
+    /// Extracts text from an ODT file. Paragraphs and headings are separated by double
+    /// newlines.
+    ///
+    /// # Arguments
+    ///
+    /// * `file_path` - The path to the ODT file.
+    ///
+    /// # Returns
+    ///
+    /// Returns a `Result` containing the extracted text as a `String` if successful,
+    /// or an `Error` if an error occurred during the extraction process.
+    pub fn extract_text<T: AsRef<std::path::Path>>(file_path: &T) -> Result<String, Error> {
+        let file = std::fs::File::open(file_path)?;
+        let mut archive = zip::ZipArchive::new(file)?;
+        let mut content = String::new();
+        archive
+            .by_name("content.xml")?
+            .read_to_string(&mut content)?;
+        Self::content_to_text(&content)
+    }
+
+    /// Converts the `content.xml` of an OpenDocument file to plain text.
+    pub fn content_to_text(content: &str) -> Result<String, Error> {
+        let mut paragraphs = Vec::new();
+        let mut current = String::new();
+        // Depth of nested paragraphs, e.g. a paragraph inside a frame or a note.
+        let mut depth: usize = 0;
+        let mut element = "";
+        let mut spaces = 1;
+
+        for token in Tokenizer::from(content) {
+            match token? {
+                Token::ElementStart { prefix, local, .. } => {
+                    element = if prefix.as_str() == "text" {
+                        local.as_str()
+                    } else {
+                        ""
+                    };
+                    match element {
+                        "p" | "h" => depth += 1,
+                        "s" => spaces = 1,
+                        _ => {}
+                    }
+                }
+                Token::Attribute {
+                    prefix,
+                    local,
+                    value,
+                    ..
+                } if element == "s" && prefix.as_str() == "text" && local.as_str() == "c" => {
+                    spaces = value.as_str().parse().unwrap_or(1);
+                }
+                Token::ElementEnd { end, .. } => {
+                    let closed = match end {
+                        ElementEnd::Open => {
+                            push_inline(&mut current, element, spaces);
+                            None
+                        }
+                        ElementEnd::Empty => {
+                            push_inline(&mut current, element, spaces);
+                            Some(element)
+                        }
+                        ElementEnd::Close(prefix, local) if prefix.as_str() == "text" => {
+                            Some(local.as_str())
+                        }
+                        ElementEnd::Close(..) => None,
+                    };
+                    element = "";
+                    if let Some("p" | "h") = closed {
+                        depth = depth.saturating_sub(1);
+                        let paragraph = current.trim();
+                        if !paragraph.is_empty() {
+                            paragraphs.push(paragraph.to_string());
+                        }
+                        current.clear();
+                    }
+                }
+                Token::Text { text } if depth > 0 => current.push_str(&unescape(text.as_str())),
+                Token::Cdata { text, .. } if depth > 0 => current.push_str(text.as_str()),
+                _ => {}
+            }
+        }
+
+        Ok(paragraphs.join("\n\n"))
+    }
+}
+
+/// Adds the text represented by an empty inline element such as `<text:s text:c="3"/>`.
+fn push_inline(current: &mut String, element: &str, spaces: usize) {
+    match element {
+        "s" => current.push_str(&" ".repeat(spaces)),
+        "tab" => current.push('\t'),
+        "line-break" => current.push('\n'),
+        _ => {}
+    }
+}
+
+fn unescape(text: &str) -> String {
+    let mut out = String::with_capacity(text.len());
+    let mut rest = text;
+    while let Some(start) = rest.find('&') {
+        out.push_str(&rest[..start]);
+        rest = &rest[start..];
+        let Some(end) = rest.find(';') else {
+            break;
+        };
+        let entity = &rest[1..end];
+        let decoded = match entity {
+            "amp" => Some('&'),
+            "lt" => Some('<'),
+            "gt" => Some('>'),
+            "quot" => Some('"'),
+            "apos" => Some('\''),
+            _ => entity
+                .strip_prefix("#x")
+                .map(|hex| u32::from_str_radix(hex, 16).ok())
+                .unwrap_or_else(|| entity.strip_prefix('#').and_then(|dec| dec.parse().ok()))
+                .and_then(char::from_u32),
+        };
+        match decoded {
+            Some(c) => {
+                out.push(c);
+                rest = &rest[end + 1..];
+            }
+            None => {
+                out.push('&');
+                rest = &rest[1..];
+            }
+        }
+    }
+    out.push_str(rest);
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_extract_text() {
+        let text = OdtProcessor::extract_text(&"../test_files/test.odt").unwrap();
+        assert_eq!(
+            text,
+            "Test document\n\nHello, world!\n\nFish &  chips\tare great.\nNew line in the same paragraph."
+        );
+    }
+
+    #[test]
+    fn test_extract_text_invalid_file() {
+        OdtProcessor::extract_text(&"../test_files/test.txt").unwrap_err();
+    }
+}
@@ -1,5 +1,8 @@
 use crate::tesseract::input::{Args, Image};
+use crate::text_loader::check_page_range;
 use anyhow::Error;
+
+use super::pdf_tables::{PageLayout, TableMode};
 use image::DynamicImage;
 use pdf2image::{Pages, RenderOptionsBuilder, PDF};
 
@@ -28,6 +31,159 @@ impl PdfProcessor {
             pdf_extract::extract_text(file_path).map_err(|e| anyhow::anyhow!(e))
         }
     }
+
+    /// Extracts the text of every page of a PDF file, in page order.
+    ///
+    /// # Arguments
+    ///
+    /// * `file_path` - The path to the PDF file.
+    ///
+    /// # Returns
+    ///
+    /// Returns a `Result` containing one `String` per page if successful, or an `Error` if
+    /// an error occurred during the extraction process.
+    pub fn extract_pages<T: AsRef<std::path::Path>>(
+        file_path: T,
+        use_ocr: bool,
+        tesseract_path: Option<&str>,
+    ) -> Result<Vec<String>, Error> {
+        if use_ocr {
+            extract_pages_with_ocr(&file_path, tesseract_path)
+        } else {
+            pdf_extract::extract_text_by_pages(file_path).map_err(|e| anyhow::anyhow!(e))
+        }
+    }
+
+    /// Extracts the pages of a PDF file one at a time, so that only the text of the current
+    /// page is held in memory. The parsed document stays in memory, see
+    /// [TextEmbedConfig::max_document_bytes](crate::config::TextEmbedConfig::max_document_bytes)
+    /// to bound its size. OCR is not supported.
+    pub fn pages<T: AsRef<std::path::Path>>(file_path: T) -> Result<PdfPages, Error> {
+        let document = load_document(file_path)?;
+        let page_numbers = document.get_pages().into_keys().collect::<Vec<_>>();
+        Ok(PdfPages {
+            document,
+            page_count: page_numbers.len(),
+            page_numbers: page_numbers.into_iter(),
+            table_mode: None,
+            contains_table: false,
+        })
+    }
+
+    /// Extracts the text of every page of a PDF file from the layout of its characters, with
+    /// the tables written as `table_mode` says, see [TableMode]. Also returns whether a table
+    /// was found on each page. OCR is not supported.
+    pub fn extract_pages_with_tables<T: AsRef<std::path::Path>>(
+        file_path: T,
+        table_mode: TableMode,
+    ) -> Result<Vec<(String, bool)>, Error> {
+        let document = load_document(file_path)?;
+        document
+            .get_pages()
+            .into_keys()
+            .map(|page_number| {
+                let mut layout = PageLayout::default();
+                pdf_extract::output_doc_page(&document, &mut layout, page_number)
+                    .map_err(|e| anyhow::anyhow!(e))?;
+                Ok(layout.text(table_mode))
+            })
+            .collect()
+    }
+
+    /// Whether the PDF file is encrypted, judging by the `/Encrypt` entry of its trailer. Only
+    /// the end of the file, where the trailer is, is read.
+    pub fn is_encrypted<T: AsRef<std::path::Path>>(file_path: T) -> bool {
+        use std::io::{Read, Seek, SeekFrom};
+
+        let mut tail = Vec::new();
+        std::fs::File::open(file_path)
+            .and_then(|mut file| {
+                let len = file.metadata()?.len();
+                file.seek(SeekFrom::Start(len.saturating_sub(TRAILER_BYTES)))?;
+                file.read_to_end(&mut tail)
+            })
+            .map(|_| tail.windows(8).any(|window| window == b"/Encrypt"))
+            .unwrap_or(false)
+    }
+}
+
+/// Number of bytes at the end of a PDF file searched for the trailer.
+const TRAILER_BYTES: u64 = 1 << 20;
+
+/// The text of the pages of a PDF file, returned by [PdfProcessor::pages].
+pub struct PdfPages {
+    document: pdf_extract::Document,
+    page_numbers: std::vec::IntoIter<u32>,
+    page_count: usize,
+    table_mode: Option<TableMode>,
+    /// Whether a table was found on the last extracted page.
+    contains_table: bool,
+}
+
+impl PdfPages {
+    /// Number of pages of the document.
+    pub fn page_count(&self) -> usize {
+        self.page_count
+    }
+
+    /// Extracts the pages from their layout with the tables written as `table_mode` says, like
+    /// [PdfProcessor::extract_pages_with_tables]. None extracts them in reading order.
+    pub fn with_table_mode(mut self, table_mode: Option<TableMode>) -> Self {
+        self.table_mode = table_mode;
+        self
+    }
+
+    /// Whether a table was found on the last extracted page, always false without a table
+    /// mode.
+    pub fn contains_table(&self) -> bool {
+        self.contains_table
+    }
+
+    /// Only extracts the pages `first` to `last`, both included and counted from 1, so that
+    /// the other pages are never read. Fails when the range is not within the document, or
+    /// when pages were already extracted.
+    pub fn select(&mut self, first: usize, last: usize) -> Result<(), Error> {
+        check_page_range(first, last, self.page_count)?;
+        if self.page_numbers.len() < self.page_count {
+            return Err(anyhow::anyhow!(
+                "Pages must be selected before extracting any"
+            ));
+        }
+        self.page_numbers = self.page_numbers.as_slice()[first - 1..last]
+            .to_vec()
+            .into_iter();
+        Ok(())
+    }
+}
+
+impl Iterator for PdfPages {
+    type Item = Result<String, Error>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let page_number = self.page_numbers.next()?;
+        if let Some(table_mode) = self.table_mode {
+            let mut layout = PageLayout::default();
+            let result = pdf_extract::output_doc_page(&self.document, &mut layout, page_number);
+            return Some(result.map_err(|e| anyhow::anyhow!(e)).map(|_| {
+                let (text, contains_table) = layout.text(table_mode);
+                self.contains_table = contains_table;
+                text
+            }));
+        }
+        let mut text = String::new();
+        let mut output = pdf_extract::PlainTextOutput::new(&mut text);
+        let result = pdf_extract::output_doc_page(&self.document, &mut output, page_number);
+        Some(result.map(|_| text).map_err(|e| anyhow::anyhow!(e)))
+    }
+}
+
+fn load_document<T: AsRef<std::path::Path>>(file_path: T) -> Result<pdf_extract::Document, Error> {
+    let mut document = pdf_extract::Document::load(file_path)?;
+    if document.is_encrypted() {
+        // Many PDF files are encrypted with an empty password to restrict printing.
+        document.decrypt("")?;
+    }
+    Ok(document)
 }
 
 fn get_images_from_pdf<T: AsRef<std::path::Path>>(
@@ -52,6 +208,13 @@ fn extract_text_with_ocr<T: AsRef<std::path::Path>>(
     file_path: &T,
     tesseract_path: Option<&str>,
 ) -> Result<String, Error> {
+    Ok(extract_pages_with_ocr(file_path, tesseract_path)?.join("\n"))
+}
+
+fn extract_pages_with_ocr<T: AsRef<std::path::Path>>(
+    file_path: &T,
+    tesseract_path: Option<&str>,
+) -> Result<Vec<String>, Error> {
     let images = get_images_from_pdf(file_path)?;
     let texts: Result<Vec<String>, Error> = images
         .iter()
@@ -62,7 +225,7 @@ fn extract_text_with_ocr<T: AsRef<std::path::Path>>(
             )
         })
         .collect();
-    Ok(texts.unwrap().join("\n"))
+    Ok(texts.unwrap())
 }
 
 #[cfg(test)]
@@ -83,6 +246,29 @@ mod tests {
         assert_eq!(text.len(), 4271);
     }
 
+    #[test]
+    fn test_pages() {
+        let expected = PdfProcessor::extract_pages("../test_files/test.pdf", false, None).unwrap();
+        let pages = PdfProcessor::pages("../test_files/test.pdf").unwrap();
+        assert_eq!(pages.page_count(), expected.len());
+        let pages = pages.collect::<Result<Vec<_>, _>>().unwrap();
+        assert_eq!(pages, expected);
+    }
+
+    #[test]
+    fn test_pages_with_tables() {
+        let path = "../test_files/table.pdf";
+        let expected = PdfProcessor::extract_pages_with_tables(path, TableMode::Markdown).unwrap();
+        let mut pages = PdfProcessor::pages(path)
+            .unwrap()
+            .with_table_mode(Some(TableMode::Markdown));
+        for (text, contains_table) in expected {
+            assert_eq!(pages.next().unwrap().unwrap(), text);
+            assert_eq!(pages.contains_table(), contains_table);
+        }
+        assert!(pages.next().is_none());
+    }
+
     #[test]
     fn test_extract_text_with_ocr() {
         let pdf_file = "../test_files/test.pdf";
@@ -0,0 +1,323 @@
+//! Finding the tables of PDF pages from the position of their characters, see [TableMode].
+//!
+//! The text of a PDF has no notion of tables: a table is characters placed in rows and
+//! columns. Extracted in reading order, its cells run into each other and read as gibberish to
+//! embedding models. Here the characters of a page are grouped into lines, and a line into
+//! cells wherever two characters are more than [CELL_GAP_EMS] apart. Consecutive lines with the
+//! same number of short cells, each overlapping the cell above it, form a table.
+//!
+//! This finds tables laid out on a grid, with or without rules. Tables with merged cells or
+//! cells wrapping over several lines are split where the number of cells changes.
+
+use std::str::FromStr;
+
+use anyhow::anyhow;
+use pdf_extract::{MediaBox, OutputDev, OutputError, Transform};
+
+/// Metadata entry set to "true" when a table was found in a PDF file, and "false" otherwise.
+/// Only set when a [TableMode] is given.
+pub const CONTAINS_TABLE_KEY: &str = "contains_table";
+
+/// Horizontal space, in font sizes, from which two characters of a line are in different
+/// cells.
+pub const CELL_GAP_EMS: f64 = 1.0;
+/// Space, in font sizes, from which two characters of a cell are in different words.
+const WORD_GAP_EMS: f64 = 0.15;
+/// Longest cell of a table row, longer cells are taken for the lines of a text in columns.
+const MAX_CELL_CHARS: usize = 40;
+
+/// How the tables of PDF files are written in the extracted text.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum TableMode {
+    /// Each row on a line, its cells separated by a space.
+    #[default]
+    Linear,
+    /// As Markdown tables, the first row being the header.
+    Markdown,
+    /// Left out of the text.
+    Skip,
+}
+
+impl FromStr for TableMode {
+    type Err = anyhow::Error;
+
+    fn from_str(mode: &str) -> Result<Self, Self::Err> {
+        match mode.to_lowercase().as_str() {
+            "linear" => Ok(Self::Linear),
+            "markdown" => Ok(Self::Markdown),
+            "skip" => Ok(Self::Skip),
+            _ => Err(anyhow!(
+                "Unknown table mode {}, expected linear, markdown or skip",
+                mode
+            )),
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+struct Glyph {
+    x: f64,
+    y: f64,
+    width: f64,
+    size: f64,
+    text: String,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+struct Cell {
+    text: String,
+    start: f64,
+    end: f64,
+}
+
+/// Collects the characters of a page with their position, to be given to
+/// [pdf_extract::output_doc_page].
+#[derive(Debug, Default)]
+pub(crate) struct PageLayout {
+    glyphs: Vec<Glyph>,
+}
+
+impl OutputDev for PageLayout {
+    fn begin_page(
+        &mut self,
+        _page_num: u32,
+        _media_box: &MediaBox,
+        _art_box: Option<(f64, f64, f64, f64)>,
+    ) -> Result<(), OutputError> {
+        self.glyphs.clear();
+        Ok(())
+    }
+
+    fn end_page(&mut self) -> Result<(), OutputError> {
+        Ok(())
+    }
+
+    fn output_character(
+        &mut self,
+        trm: &Transform,
+        width: f64,
+        _spacing: f64,
+        font_size: f64,
+        char: &str,
+    ) -> Result<(), OutputError> {
+        // `trm` maps text space to the page, the font size is applied on top of it.
+        self.glyphs.push(Glyph {
+            x: trm.m31,
+            y: trm.m32,
+            width: width * font_size * trm.m11.abs(),
+            size: font_size * (trm.m11 * trm.m22).abs().sqrt(),
+            text: char.to_string(),
+        });
+        Ok(())
+    }
+
+    fn begin_word(&mut self) -> Result<(), OutputError> {
+        Ok(())
+    }
+
+    fn end_word(&mut self) -> Result<(), OutputError> {
+        Ok(())
+    }
+
+    fn end_line(&mut self) -> Result<(), OutputError> {
+        Ok(())
+    }
+}
+
+impl PageLayout {
+    /// Writes the text of the page, top to bottom, with its tables written as `table_mode`
+    /// says and set apart by blank lines. Also returns whether a table was found.
+    pub(crate) fn text(&self, table_mode: TableMode) -> (String, bool) {
+        let lines = self.lines();
+        let mut blocks = Vec::new();
+        let mut contains_table = false;
+        let mut start = 0;
+        while start < lines.len() {
+            let end = table_end(&lines, start);
+            if end - start < 2 {
+                blocks.push(join_cells(&lines[start]));
+                start += 1;
+                continue;
+            }
+            contains_table = true;
+            let rows = &lines[start..end];
+            match table_mode {
+                TableMode::Linear => blocks.extend(rows.iter().map(|row| join_cells(row))),
+                TableMode::Markdown => blocks.push(format!("\n{}\n", markdown_table(rows))),
+                TableMode::Skip => {}
+            }
+            start = end;
+        }
+        let text = blocks.join("\n").replace("\n\n\n", "\n\n");
+        (text.trim().to_string(), contains_table)
+    }
+
+    /// The characters grouped into lines from the top of the page, and lines into cells.
+    fn lines(&self) -> Vec<Vec<Cell>> {
+        let mut glyphs = self.glyphs.iter().collect::<Vec<_>>();
+        // The y axis of PDF pages points up.
+        glyphs.sort_by(|a, b| b.y.total_cmp(&a.y));
+        let mut lines: Vec<Vec<&Glyph>> = Vec::new();
+        for glyph in glyphs {
+            match lines.last_mut() {
+                Some(line) if (line[0].y - glyph.y).abs() < line[0].size.max(glyph.size) / 2.0 => {
+                    line.push(glyph)
+                }
+                _ => lines.push(vec![glyph]),
+            }
+        }
+
+        lines
+            .into_iter()
+            .map(|mut line| {
+                line.sort_by(|a, b| a.x.total_cmp(&b.x));
+                let mut cells: Vec<Cell> = Vec::new();
+                for glyph in line {
+                    let gap = cells.last().map(|cell| glyph.x - cell.end);
+                    match (cells.last_mut(), gap) {
+                        (Some(cell), Some(gap)) if gap < CELL_GAP_EMS * glyph.size => {
+                            if gap > WORD_GAP_EMS * glyph.size && !cell.text.ends_with(' ') {
+                                cell.text.push(' ');
+                            }
+                            cell.text.push_str(&glyph.text);
+                            cell.end = cell.end.max(glyph.x + glyph.width);
+                        }
+                        _ => cells.push(Cell {
+                            text: glyph.text.clone(),
+                            start: glyph.x,
+                            end: glyph.x + glyph.width,
+                        }),
+                    }
+                }
+                cells
+                    .into_iter()
+                    .map(|cell| Cell {
+                        text: cell.text.split_whitespace().collect::<Vec<_>>().join(" "),
+                        ..cell
+                    })
+                    .filter(|cell| !cell.text.is_empty())
+                    .collect::<Vec<_>>()
+            })
+            .filter(|cells| !cells.is_empty())
+            .collect()
+    }
+}
+
+/// The end of the table starting at line `start`, or `start + 1` when no table starts there.
+fn table_end(lines: &[Vec<Cell>], start: usize) -> usize {
+    let is_row = |cells: &[Cell]| {
+        cells.len() >= 2
+            && cells
+                .iter()
+                .all(|cell| cell.text.chars().count() <= MAX_CELL_CHARS)
+    };
+    if !is_row(&lines[start]) {
+        return start + 1;
+    }
+    let mut end = start + 1;
+    while end < lines.len() && is_row(&lines[end]) && aligned(&lines[end - 1], &lines[end]) {
+        end += 1;
+    }
+    end
+}
+
+/// Whether every cell of `row` is below a cell of `above`.
+fn aligned(above: &[Cell], row: &[Cell]) -> bool {
+    above.len() == row.len()
+        && above
+            .iter()
+            .zip(row)
+            .all(|(above, cell)| cell.start <= above.end && above.start <= cell.end)
+}
+
+fn join_cells(cells: &[Cell]) -> String {
+    cells
+        .iter()
+        .map(|cell| cell.text.as_str())
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+fn markdown_table(rows: &[Vec<Cell>]) -> String {
+    let markdown_row = |cells: Vec<&str>| format!("| {} |", cells.join(" | "));
+    let mut table = rows
+        .iter()
+        .map(|row| {
+            markdown_row(
+                row.iter()
+                    .map(|cell| cell.text.as_str())
+                    .collect::<Vec<_>>(),
+            )
+        })
+        .collect::<Vec<_>>();
+    table.insert(1, markdown_row(vec!["---"; rows[0].len()]));
+    table.join("\n")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Lays out `lines` of cells from the top of a page, in 10 point characters 5 points wide.
+    fn layout(lines: &[&[(f64, &str)]]) -> PageLayout {
+        let mut glyphs = Vec::new();
+        for (row, cells) in lines.iter().enumerate() {
+            for (x, text) in cells.iter() {
+                for (i, c) in text.chars().enumerate() {
+                    glyphs.push(Glyph {
+                        x: x + 5.0 * i as f64,
+                        y: 700.0 - 14.0 * row as f64,
+                        width: 5.0,
+                        size: 10.0,
+                        text: c.to_string(),
+                    });
+                }
+            }
+        }
+        PageLayout { glyphs }
+    }
+
+    #[test]
+    fn test_table_modes() {
+        let page = layout(&[
+            &[(72.0, "Fruit sales")],
+            &[(72.0, "Product"), (200.0, "Units")],
+            &[(72.0, "Apples"), (205.0, "120")],
+            &[(72.0, "Pears"), (205.0, "80")],
+            &[(72.0, "Prices are in dollars.")],
+        ]);
+        assert_eq!(
+            page.text(TableMode::Linear),
+            (
+                "Fruit sales\nProduct Units\nApples 120\nPears 80\nPrices are in dollars.".into(),
+                true
+            )
+        );
+        assert_eq!(
+            page.text(TableMode::Markdown).0,
+            "Fruit sales\n\n| Product | Units |\n| --- | --- |\n| Apples | 120 |\n| Pears | 80 |\n\nPrices are in dollars."
+        );
+        assert_eq!(
+            page.text(TableMode::Skip).0,
+            "Fruit sales\nPrices are in dollars."
+        );
+        assert_eq!(
+            "Markdown".parse::<TableMode>().unwrap(),
+            TableMode::Markdown
+        );
+        assert!("html".parse::<TableMode>().is_err());
+    }
+
+    #[test]
+    fn test_columns_of_text_are_not_tables() {
+        // Two columns of prose, their lines are too long for cells.
+        let left = "The river starts high in the mountains and";
+        let right = "Farmers along its banks grow wheat in summer";
+        let page = layout(&[
+            &[(72.0, left), (320.0, right)],
+            &[(72.0, left), (320.0, right)],
+        ]);
+        let (_, contains_table) = page.text(TableMode::Markdown);
+        assert!(!contains_table);
+    }
+}
@@ -0,0 +1,155 @@
+use std::sync::OnceLock;
+
+use regex::{Captures, Regex};
+
+/// Masks personal data in extracted text before it is embedded and stored, set with
+/// [TextEmbedConfig::with_redact](crate::config::TextEmbedConfig::with_redact).
+///
+/// Matches are replaced with `[EMAIL]`, `[PHONE]` or `[CARD]`. The patterns are regular
+/// expressions tuned for common formats, so redaction is best effort: unusual formats are
+/// missed and some other numbers may be masked. Don't rely on it for compliance.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RedactionConfig {
+    /// Masks email addresses such as "jane.doe@example.com".
+    pub emails: bool,
+    /// Masks phone numbers written with separators or an international prefix, such as
+    /// "(555) 123-4567" or "+44 20 7946 0958".
+    pub phone_numbers: bool,
+    /// Masks runs of 13 to 19 digits, optionally grouped with spaces or dashes, that pass the
+    /// Luhn checksum of payment card numbers.
+    pub credit_cards: bool,
+}
+
+impl Default for RedactionConfig {
+    fn default() -> Self {
+        Self {
+            emails: true,
+            phone_numbers: true,
+            credit_cards: true,
+        }
+    }
+}
+
+fn email() -> &'static Regex {
+    static EMAIL: OnceLock<Regex> = OnceLock::new();
+    EMAIL.get_or_init(|| {
+        Regex::new(r"\b[A-Za-z0-9._%+-]+@[A-Za-z0-9-]+(?:\.[A-Za-z0-9-]+)*\.[A-Za-z]{2,}\b")
+            .unwrap()
+    })
+}
+
+fn phone_number() -> &'static Regex {
+    static PHONE_NUMBER: OnceLock<Regex> = OnceLock::new();
+    PHONE_NUMBER.get_or_init(|| {
+        Regex::new(
+            r"(?:\+\d{1,3}[ .-]?)?(?:\(\d{1,4}\)[ .-]?|\b\d{2,4}[ .-])\d{3,4}[ .-]?\d{3,4}\b",
+        )
+        .unwrap()
+    })
+}
+
+fn card_number() -> &'static Regex {
+    static CARD_NUMBER: OnceLock<Regex> = OnceLock::new();
+    CARD_NUMBER.get_or_init(|| Regex::new(r"\b\d(?:[ -]?\d){12,18}\b").unwrap())
+}
+
+impl RedactionConfig {
+    pub fn with_emails(mut self, emails: bool) -> Self {
+        self.emails = emails;
+        self
+    }
+
+    pub fn with_phone_numbers(mut self, phone_numbers: bool) -> Self {
+        self.phone_numbers = phone_numbers;
+        self
+    }
+
+    pub fn with_credit_cards(mut self, credit_cards: bool) -> Self {
+        self.credit_cards = credit_cards;
+        self
+    }
+
+    /// Masks the enabled kinds of personal data in `text`.
+    pub fn redact(&self, text: &str) -> String {
+        let mut text = text.to_string();
+        if self.emails {
+            text = email().replace_all(&text, "[EMAIL]").into_owned();
+        }
+        // Card numbers go first, as their groups of digits also look like phone numbers.
+        if self.credit_cards {
+            text = card_number()
+                .replace_all(&text, |captures: &Captures| {
+                    let number = &captures[0];
+                    if luhn_valid(number) {
+                        "[CARD]".to_string()
+                    } else {
+                        number.to_string()
+                    }
+                })
+                .into_owned();
+        }
+        if self.phone_numbers {
+            text = phone_number().replace_all(&text, "[PHONE]").into_owned();
+        }
+        text
+    }
+}
+
+fn luhn_valid(number: &str) -> bool {
+    let digits = number
+        .chars()
+        .filter_map(|c| c.to_digit(10))
+        .collect::<Vec<_>>();
+    let sum = digits
+        .iter()
+        .rev()
+        .enumerate()
+        .map(|(i, &digit)| match i % 2 {
+            0 => digit,
+            _ if digit * 2 > 9 => digit * 2 - 9,
+            _ => digit * 2,
+        })
+        .sum::<u32>();
+    sum % 10 == 0
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_redact() {
+        let redaction = RedactionConfig::default();
+        assert_eq!(
+            redaction.redact("Write to jane.doe+news@mail.example.co.uk or call (555) 123-4567."),
+            "Write to [EMAIL] or call [PHONE]."
+        );
+        assert_eq!(
+            redaction.redact("Call +44 20 7946 0958 or 555.123.4567, card 4111 1111 1111 1111."),
+            "Call [PHONE] or [PHONE], card [CARD]."
+        );
+        assert_eq!(
+            redaction.redact("Paid with 5500-0000-0000-0004."),
+            "Paid with [CARD]."
+        );
+    }
+
+    #[test]
+    fn test_untouched_text() {
+        let redaction = RedactionConfig::default();
+        let text = "Version 1.2.3 was released on 2024-01-15 to 12000 users, order \
+                    4111111111111112 and ISBN 978-0-306-40615-7. Reach us @support.";
+        assert_eq!(redaction.redact(text), text);
+    }
+
+    #[test]
+    fn test_disabled_kinds() {
+        let redaction = RedactionConfig::default()
+            .with_emails(false)
+            .with_phone_numbers(false);
+        assert_eq!(
+            redaction.redact("jane@example.com, 555-123-4567, 4111 1111 1111 1111"),
+            "jane@example.com, 555-123-4567, [CARD]"
+        );
+    }
+}
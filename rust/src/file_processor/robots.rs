@@ -0,0 +1,162 @@
+/// A parsed robots.txt file. Supports the `User-agent`, `Allow` and `Disallow` directives,
+/// including the `*` and `$` wildcards. The most specific (longest) matching rule wins and
+/// `Allow` wins ties, as described in RFC 9309.
+#[derive(Debug, Clone, Default)]
+pub struct RobotsTxt {
+    groups: Vec<Group>,
+}
+
+#[derive(Debug, Clone, Default)]
+struct Group {
+    user_agents: Vec<String>,
+    rules: Vec<Rule>,
+}
+
+#[derive(Debug, Clone)]
+struct Rule {
+    allow: bool,
+    pattern: String,
+}
+
+impl RobotsTxt {
+    pub fn parse(content: &str) -> Self {
+        let mut groups: Vec<Group> = Vec::new();
+        // Consecutive User-agent lines share the rules that follow them.
+        let mut in_agent_lines = false;
+
+        for line in content.lines() {
+            let line = line.split('#').next().unwrap_or_default().trim();
+            let Some((key, value)) = line.split_once(':') else {
+                continue;
+            };
+            let value = value.trim();
+            match key.trim().to_lowercase().as_str() {
+                "user-agent" => {
+                    if !in_agent_lines {
+                        groups.push(Group::default());
+                    }
+                    in_agent_lines = true;
+                    if let Some(group) = groups.last_mut() {
+                        group.user_agents.push(value.to_lowercase());
+                    }
+                }
+                directive @ ("allow" | "disallow") => {
+                    in_agent_lines = false;
+                    // An empty Disallow allows everything, which is the same as no rule.
+                    if value.is_empty() {
+                        continue;
+                    }
+                    if let Some(group) = groups.last_mut() {
+                        group.rules.push(Rule {
+                            allow: directive == "allow",
+                            pattern: value.to_string(),
+                        });
+                    }
+                }
+                _ => in_agent_lines = false,
+            }
+        }
+
+        Self { groups }
+    }
+
+    /// Whether `user_agent` may fetch `path`, which should include the query string.
+    pub fn is_allowed(&self, user_agent: &str, path: &str) -> bool {
+        // Groups are matched on the product token, e.g. "mybot" for "MyBot/1.0".
+        let product = user_agent
+            .split('/')
+            .next()
+            .unwrap_or_default()
+            .trim()
+            .to_lowercase();
+        let mut groups = self
+            .groups
+            .iter()
+            .filter(|group| {
+                group
+                    .user_agents
+                    .iter()
+                    .any(|agent| agent != "*" && product.contains(agent.as_str()))
+            })
+            .peekable();
+        let groups: Vec<&Group> = if groups.peek().is_some() {
+            groups.collect()
+        } else {
+            self.groups
+                .iter()
+                .filter(|group| group.user_agents.iter().any(|agent| agent == "*"))
+                .collect()
+        };
+
+        groups
+            .iter()
+            .flat_map(|group| group.rules.iter())
+            .filter(|rule| matches_pattern(&rule.pattern, path))
+            .max_by_key(|rule| (rule.pattern.len(), rule.allow))
+            .map_or(true, |rule| rule.allow)
+    }
+}
+
+fn matches_pattern(pattern: &str, path: &str) -> bool {
+    let (pattern, anchored) = match pattern.strip_suffix('$') {
+        Some(pattern) => (pattern, true),
+        None => (pattern, false),
+    };
+    let mut parts = pattern.split('*');
+    let first = parts.next().unwrap_or_default();
+    let Some(mut rest) = path.strip_prefix(first) else {
+        return false;
+    };
+    let parts = parts.collect::<Vec<_>>();
+    for (i, part) in parts.iter().enumerate() {
+        // The last part of an anchored pattern has to match the end of the path.
+        if anchored && i == parts.len() - 1 {
+            return rest.ends_with(part);
+        }
+        match rest.find(part) {
+            Some(index) => rest = &rest[index + part.len()..],
+            None => return false,
+        }
+    }
+    !anchored || rest.is_empty()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const ROBOTS: &str = "
+# Example
+User-agent: *
+Disallow: /private
+Allow: /private/public
+Disallow: /*.pdf$
+
+User-agent: BadBot
+User-agent: OtherBot
+Disallow: /
+";
+
+    #[test]
+    fn test_wildcard_group() {
+        let robots = RobotsTxt::parse(ROBOTS);
+        assert!(robots.is_allowed("EmbedAnything/0.5", "/blog/post"));
+        assert!(!robots.is_allowed("EmbedAnything/0.5", "/private/page"));
+        assert!(robots.is_allowed("EmbedAnything/0.5", "/private/public/page"));
+        assert!(!robots.is_allowed("EmbedAnything/0.5", "/files/report.pdf"));
+        assert!(robots.is_allowed("EmbedAnything/0.5", "/files/report.pdf?x=1"));
+    }
+
+    #[test]
+    fn test_specific_group() {
+        let robots = RobotsTxt::parse(ROBOTS);
+        assert!(!robots.is_allowed("BadBot/2.0", "/blog/post"));
+        assert!(!robots.is_allowed("otherbot", "/"));
+    }
+
+    #[test]
+    fn test_empty_robots_allows_everything() {
+        let robots = RobotsTxt::parse("User-agent: *\nDisallow:\n");
+        assert!(robots.is_allowed("EmbedAnything/0.5", "/anything"));
+    }
+}
@@ -0,0 +1,229 @@
+use anyhow::Error;
+
+/// Destinations whose content is formatting or document information rather than body text.
+const SKIPPED_DESTINATIONS: &[&str] = &[
+    "fonttbl",
+    "colortbl",
+    "stylesheet",
+    "listtable",
+    "listoverridetable",
+    "rsidtbl",
+    "info",
+    "pict",
+    "object",
+    "header",
+    "headerl",
+    "headerr",
+    "headerf",
+    "footer",
+    "footerl",
+    "footerr",
+    "footerf",
+    "fldinst",
+    "themedata",
+    "colorschememapping",
+    "datastore",
+    "latentstyles",
+    "xmlnstbl",
+    "generator",
+];
+
+/// A struct for processing RTF files.
+pub struct RtfProcessor;
+
+#[derive(Clone, Copy)]
+struct GroupState {
+    skip: bool,
+    /// Number of fallback characters following a `\u` control word.
+    unicode_skip: usize,
+}
+
+impl RtfProcessor {
+    /// Extracts text from an RTF file. Paragraphs are separated by double newlines.
+    ///
+    /// # Arguments
+    ///
+    /// * `file_path` - The path to the RTF file.
+    ///
+    /// # Returns
+    ///
+    /// Returns a `Result` containing the extracted text as a `String` if successful,
+    /// or an `Error` if an error occurred during the extraction process.
+    pub fn extract_text<T: AsRef<std::path::Path>>(file_path: &T) -> Result<String, Error> {
+        let bytes = std::fs::read(file_path)?;
+        if !bytes.starts_with(b"{\\rtf") {
+            return Err(anyhow::anyhow!(
+                "{:?} is not an RTF file",
+                file_path.as_ref()
+            ));
+        }
+        Ok(Self::rtf_to_text(&bytes))
+    }
+
+    /// Converts RTF content to plain text. Bytes written as `\'hh` are decoded as Latin-1.
+    pub fn rtf_to_text(rtf: &[u8]) -> String {
+        let mut out = String::new();
+        let mut stack: Vec<GroupState> = Vec::new();
+        let mut state = GroupState {
+            skip: false,
+            unicode_skip: 1,
+        };
+        // Fallback characters left to drop after a `\u` control word.
+        let mut pending_skip = 0;
+
+        let mut i = 0;
+        while i < rtf.len() {
+            let byte = rtf[i];
+            i += 1;
+            match byte {
+                b'{' => {
+                    stack.push(state);
+                    pending_skip = 0;
+                }
+                b'}' => {
+                    state = stack.pop().unwrap_or(state);
+                    pending_skip = 0;
+                }
+                b'\r' | b'\n' => {}
+                b'\\' => {
+                    let Some(&next) = rtf.get(i) else {
+                        break;
+                    };
+                    match next {
+                        b'\\' | b'{' | b'}' => {
+                            i += 1;
+                            emit(&mut out, &state, &mut pending_skip, next as char);
+                        }
+                        b'\'' => {
+                            let hex = rtf.get(i + 1..i + 3).unwrap_or_default();
+                            i += 1 + hex.len();
+                            if let Ok(value) = u8::from_str_radix(&String::from_utf8_lossy(hex), 16)
+                            {
+                                emit(&mut out, &state, &mut pending_skip, value as char);
+                            }
+                        }
+                        b'*' => {
+                            i += 1;
+                            state.skip = true;
+                        }
+                        b'~' => {
+                            i += 1;
+                            emit(&mut out, &state, &mut pending_skip, ' ');
+                        }
+                        b'_' => {
+                            i += 1;
+                            emit(&mut out, &state, &mut pending_skip, '-');
+                        }
+                        b'\r' | b'\n' => {
+                            i += 1;
+                            emit(&mut out, &state, &mut pending_skip, '\n');
+                            emit(&mut out, &state, &mut pending_skip, '\n');
+                        }
+                        next if next.is_ascii_alphabetic() => {
+                            let start = i;
+                            while i < rtf.len() && rtf[i].is_ascii_alphabetic() {
+                                i += 1;
+                            }
+                            let word = String::from_utf8_lossy(&rtf[start..i]).to_string();
+                            let param_start = i;
+                            if i < rtf.len() && rtf[i] == b'-' {
+                                i += 1;
+                            }
+                            while i < rtf.len() && rtf[i].is_ascii_digit() {
+                                i += 1;
+                            }
+                            let param = String::from_utf8_lossy(&rtf[param_start..i])
+                                .parse::<i32>()
+                                .ok();
+                            // A single space delimits the control word and is not part of the text.
+                            if i < rtf.len() && rtf[i] == b' ' {
+                                i += 1;
+                            }
+
+                            match word.as_str() {
+                                "par" | "sect" | "page" => {
+                                    emit(&mut out, &state, &mut pending_skip, '\n');
+                                    emit(&mut out, &state, &mut pending_skip, '\n');
+                                }
+                                "line" | "row" => emit(&mut out, &state, &mut pending_skip, '\n'),
+                                "tab" | "cell" => emit(&mut out, &state, &mut pending_skip, '\t'),
+                                "emdash" => emit(&mut out, &state, &mut pending_skip, '—'),
+                                "endash" => emit(&mut out, &state, &mut pending_skip, '–'),
+                                "bullet" => emit(&mut out, &state, &mut pending_skip, '•'),
+                                "lquote" => emit(&mut out, &state, &mut pending_skip, '‘'),
+                                "rquote" => emit(&mut out, &state, &mut pending_skip, '’'),
+                                "ldblquote" => emit(&mut out, &state, &mut pending_skip, '“'),
+                                "rdblquote" => emit(&mut out, &state, &mut pending_skip, '”'),
+                                "uc" => state.unicode_skip = param.unwrap_or(1).max(0) as usize,
+                                "u" => {
+                                    // Negative values encode code points above 32767.
+                                    let code = param.unwrap_or_default();
+                                    let code = if code < 0 { code + 65536 } else { code };
+                                    if let Some(c) = char::from_u32(code as u32) {
+                                        emit(&mut out, &state, &mut pending_skip, c);
+                                    }
+                                    pending_skip = state.unicode_skip;
+                                }
+                                word if SKIPPED_DESTINATIONS.contains(&word) => state.skip = true,
+                                _ => {}
+                            }
+                        }
+                        _ => i += 1,
+                    }
+                }
+                byte => emit(&mut out, &state, &mut pending_skip, byte as char),
+            }
+        }
+
+        out.split("\n\n")
+            .map(|paragraph| {
+                paragraph
+                    .lines()
+                    .map(str::trim)
+                    .collect::<Vec<_>>()
+                    .join("\n")
+                    .trim()
+                    .to_string()
+            })
+            .filter(|paragraph| !paragraph.is_empty())
+            .collect::<Vec<_>>()
+            .join("\n\n")
+    }
+}
+
+fn emit(out: &mut String, state: &GroupState, pending_skip: &mut usize, c: char) {
+    if state.skip {
+        return;
+    }
+    if *pending_skip > 0 {
+        *pending_skip -= 1;
+    } else {
+        out.push(c);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_extract_text() {
+        let text = RtfProcessor::extract_text(&"../test_files/test.rtf").unwrap();
+        assert_eq!(
+            text,
+            "Hello, world!\n\nThis is a caf\u{e9} with an \u{201c}RTF\u{201d} document.\n\nIt has three paragraphs."
+        );
+    }
+
+    #[test]
+    fn test_unicode_and_skipped_groups() {
+        let rtf =
+            br"{\rtf1{\fonttbl{\f0 Arial;}}{\*\generator Writer;}\uc1 Na\u239?ve {\b bold}\par}";
+        assert_eq!(RtfProcessor::rtf_to_text(rtf), "Na\u{ef}ve bold");
+    }
+
+    #[test]
+    fn test_extract_text_invalid_file() {
+        RtfProcessor::extract_text(&"../test_files/test.txt").unwrap_err();
+    }
+}
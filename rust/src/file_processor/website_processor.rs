@@ -1,20 +1,110 @@
 use std::{
     collections::{HashMap, HashSet},
     rc::Rc,
+    sync::Mutex,
+    time::{Duration, Instant},
 };
 
-use anyhow::Result;
+use anyhow::{anyhow, Result};
+use reqwest::{
+    blocking::{Client, Response},
+    redirect::Policy,
+};
 use serde_json::json;
+use url::Url;
 
 use crate::{
+    config::CrawlConfig,
     embeddings::{
         embed::{EmbedData, Embedder},
-        get_text_metadata,
+        get_text_metadata, mark_embedding_model, mark_normalized,
     },
-    file_processor::html_processor::HtmlProcessor,
+    file_processor::{html_processor::HtmlProcessor, robots::RobotsTxt},
     text_loader::{SplittingStrategy, TextLoader},
 };
 
+/// Why a webpage couldn't be fetched. It can be recovered from the error returned by
+/// [WebsiteProcessor::process_website] with `downcast_ref::<FetchError>()`.
+#[derive(Debug)]
+pub enum FetchError {
+    /// The host name couldn't be resolved.
+    Dns { url: String, source: reqwest::Error },
+    /// The connection was refused or dropped, or the response couldn't be read.
+    Connection { url: String, source: reqwest::Error },
+    /// No response within [CrawlConfig::timeout_ms].
+    Timeout { url: String },
+    /// The server answered with an error status.
+    Status { url: String, status: u16 },
+}
+
+impl FetchError {
+    /// Classifies an error raised while fetching `url`.
+    pub fn from_reqwest(url: &str, error: reqwest::Error) -> Self {
+        let url = url.to_string();
+        if error.is_timeout() {
+            return FetchError::Timeout { url };
+        }
+        if let Some(status) = error.status() {
+            return FetchError::Status {
+                url,
+                status: status.as_u16(),
+            };
+        }
+        if error.is_connect() && is_dns_error(&error) {
+            return FetchError::Dns { url, source: error };
+        }
+        FetchError::Connection { url, source: error }
+    }
+
+    /// Whether sending the request again may succeed: after a timeout, a connection error or a
+    /// 5xx or 429 status.
+    pub fn is_retryable(&self) -> bool {
+        match self {
+            FetchError::Dns { .. } => false,
+            FetchError::Connection { .. } | FetchError::Timeout { .. } => true,
+            FetchError::Status { status, .. } => *status >= 500 || *status == 429,
+        }
+    }
+}
+
+impl std::fmt::Display for FetchError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            FetchError::Dns { url, source } => {
+                write!(f, "Could not resolve the host of {}: {}", url, source)
+            }
+            FetchError::Connection { url, source } => {
+                write!(f, "Request to {} failed: {}", url, source)
+            }
+            FetchError::Timeout { url } => write!(f, "Request to {} timed out", url),
+            FetchError::Status { url, status } => write!(f, "{} returned status {}", url, status),
+        }
+    }
+}
+
+impl std::error::Error for FetchError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            FetchError::Dns { source, .. } | FetchError::Connection { source, .. } => Some(source),
+            FetchError::Timeout { .. } | FetchError::Status { .. } => None,
+        }
+    }
+}
+
+/// Whether a connection error comes from a failed name lookup. reqwest doesn't tell, so the
+/// sources of the error are searched for the message of the resolver.
+fn is_dns_error(error: &reqwest::Error) -> bool {
+    let mut source = std::error::Error::source(error);
+    while let Some(error) = source {
+        let message = error.to_string();
+        if message.contains("dns error") || message.contains("failed to lookup address") {
+            return true;
+        }
+        source = error.source();
+    }
+    false
+}
+
 #[derive(Debug)]
 pub struct WebPage {
     pub url: String,
@@ -118,11 +208,16 @@ impl WebPage {
                 "full_text": content,
             });
 
-            let metadata_hashmap: HashMap<String, String> = serde_json::from_value(metadata)?;
+            let mut metadata_hashmap: HashMap<String, String> = serde_json::from_value(metadata)?;
+            if let Some(title) = &self.title {
+                metadata_hashmap.insert("title".to_string(), title.trim().to_string());
+            }
 
-            let encodings = embedder.embed(&chunks, batch_size).await?;
-            let embeddings =
+            let encodings = embedder.embed_document_batch(&chunks, batch_size).await?;
+            let mut embeddings =
                 get_text_metadata(&Rc::new(encodings), &chunks, &Some(metadata_hashmap))?;
+            mark_normalized(&mut embeddings, embedder.is_normalized());
+            mark_embedding_model(&mut embeddings, &embedder.model_id());
             embed_data.extend(embeddings);
         }
 
@@ -151,15 +246,46 @@ impl Default for WebsiteProcessor {
 
 pub struct WebsiteProcessor {
     html_processor: HtmlProcessor,
+    crawl_config: CrawlConfig,
+    client: Client,
+    /// Parsed robots.txt per origin, fetched on first use.
+    robots: Mutex<HashMap<String, RobotsTxt>>,
+    /// Earliest time the next request to a host may be sent.
+    next_request: Mutex<HashMap<String, Instant>>,
 }
 
 impl WebsiteProcessor {
     pub fn new() -> Self {
+        let crawl_config = CrawlConfig::default();
         Self {
             html_processor: HtmlProcessor::new(),
+            client: build_client(&crawl_config),
+            crawl_config,
+            robots: Mutex::new(HashMap::new()),
+            next_request: Mutex::new(HashMap::new()),
         }
     }
 
+    pub fn with_crawl_config(mut self, crawl_config: CrawlConfig) -> Self {
+        self.client = build_client(&crawl_config);
+        self.crawl_config = crawl_config;
+        self
+    }
+
+    /// See [HtmlProcessor::with_readability].
+    pub fn with_readability(mut self, readability: bool) -> Self {
+        self.html_processor = HtmlProcessor::new().with_readability(readability);
+        self
+    }
+
+    pub fn crawl_config(&self) -> &CrawlConfig {
+        &self.crawl_config
+    }
+
+    /// Fetches and parses `website`. Blocks the calling thread on the requests, the crawl
+    /// delay of [CrawlConfig] and the backoff between retries, so async code must run it off
+    /// the runtime, e.g. with `tokio::task::spawn_blocking` as
+    /// [embed_webpage](crate::embed_webpage) and [embed_urls](crate::embed_urls) do.
     pub fn process_website(&self, website: &str) -> Result<WebPage> {
         // check if https is in the website. If not, add it.
         let website = if website.starts_with("http") {
@@ -168,22 +294,148 @@ impl WebsiteProcessor {
             &format!("https://{}", website)
         };
 
-        let response = reqwest::blocking::get(website)?.text()?;
-        let html_document = self.html_processor.process_html(response, Some(website))?;
+        let url = Url::parse(website)?;
+        if self.crawl_config.respect_robots && !self.is_allowed(&url) {
+            return Err(anyhow!("{} is disallowed by robots.txt", website));
+        }
+        let response = self.fetch(&url)?;
+        // Servers that omit the header are assumed to serve HTML.
+        let content_type = response
+            .headers()
+            .get(reqwest::header::CONTENT_TYPE)
+            .and_then(|value| value.to_str().ok())
+            .map(|value| {
+                value
+                    .split(';')
+                    .next()
+                    .unwrap_or_default()
+                    .trim()
+                    .to_lowercase()
+            })
+            .unwrap_or_else(|| "text/html".to_string());
 
-        let web_page = WebPage {
-            url: website.to_string(),
-            title: html_document.title,
-            headers: html_document.headers,
-            paragraphs: html_document.paragraphs,
-            codes: html_document.codes,
-            links: html_document.links,
+        let extension = match content_type.as_str() {
+            "text/html" | "application/xhtml+xml" => {
+                let html = response
+                    .text()
+                    .map_err(|e| FetchError::from_reqwest(website, e))?;
+                let html_document = self.html_processor.process_html(html, Some(website))?;
+                return Ok(WebPage {
+                    url: website.to_string(),
+                    title: html_document.title,
+                    headers: html_document.headers,
+                    paragraphs: html_document.paragraphs,
+                    codes: html_document.codes,
+                    links: html_document.links,
+                });
+            }
+            "application/pdf" => "pdf",
+            "text/plain" => "txt",
+            "text/markdown" | "text/x-markdown" => "md",
+            _ => {
+                return Err(anyhow!(
+                    "Unsupported content type {} for {}",
+                    content_type,
+                    website
+                ))
+            }
         };
 
-        Ok(web_page)
+        // Documents go through the same extractors as local files.
+        let file = tempfile::Builder::new()
+            .suffix(&format!(".{}", extension))
+            .tempfile()?;
+        let bytes = response
+            .bytes()
+            .map_err(|e| FetchError::from_reqwest(website, e))?;
+        std::fs::write(file.path(), bytes)?;
+        let text = TextLoader::extract_text(&file.path(), false, None)?;
+        let paragraphs = text
+            .split("\n\n")
+            .map(|paragraph| paragraph.trim().to_string())
+            .filter(|paragraph| !paragraph.is_empty())
+            .collect::<Vec<_>>();
+
+        Ok(WebPage {
+            url: website.to_string(),
+            paragraphs: Some(paragraphs),
+            ..Default::default()
+        })
+    }
+
+    /// Sends a GET request to `url`, retrying up to [CrawlConfig::max_retries] times when the
+    /// failure may be temporary, see [FetchError::is_retryable].
+    fn fetch(&self, url: &Url) -> Result<Response, FetchError> {
+        let mut attempt = 0;
+        loop {
+            self.wait_for_host(url);
+            let result = self
+                .client
+                .get(url.clone())
+                .send()
+                .and_then(Response::error_for_status)
+                .map_err(|e| FetchError::from_reqwest(url.as_str(), e));
+            match result {
+                Err(e) if e.is_retryable() && attempt < self.crawl_config.max_retries => {
+                    let backoff = self.crawl_config.retry_backoff_ms << attempt.min(16);
+                    std::thread::sleep(Duration::from_millis(backoff));
+                    attempt += 1;
+                }
+                result => return result,
+            }
+        }
+    }
+
+    fn is_allowed(&self, url: &Url) -> bool {
+        let origin = url.origin().ascii_serialization();
+        let mut path = url.path().to_string();
+        if let Some(query) = url.query() {
+            path = format!("{}?{}", path, query);
+        }
+
+        let mut robots = self.robots.lock().unwrap();
+        let robots_txt = robots.entry(origin.clone()).or_insert_with(|| {
+            // A missing or unreachable robots.txt allows everything.
+            self.client
+                .get(format!("{}/robots.txt", origin))
+                .send()
+                .ok()
+                .filter(|response| response.status().is_success())
+                .and_then(|response| response.text().ok())
+                .map(|content| RobotsTxt::parse(&content))
+                .unwrap_or_default()
+        });
+        robots_txt.is_allowed(&self.crawl_config.user_agent, &path)
+    }
+
+    /// Sleeps the calling thread until `delay_ms` has passed since the previous request to the
+    /// same host.
+    fn wait_for_host(&self, url: &Url) {
+        if self.crawl_config.delay_ms == 0 {
+            return;
+        }
+        let host = url.host_str().unwrap_or_default().to_string();
+        let delay = Duration::from_millis(self.crawl_config.delay_ms);
+        let scheduled = {
+            let mut next_request = self.next_request.lock().unwrap();
+            let now = Instant::now();
+            let scheduled = next_request.get(&host).map_or(now, |next| (*next).max(now));
+            next_request.insert(host, scheduled + delay);
+            scheduled
+        };
+        std::thread::sleep(scheduled.saturating_duration_since(Instant::now()));
     }
 }
 
+fn build_client(crawl_config: &CrawlConfig) -> Client {
+    Client::builder()
+        .user_agent(crawl_config.user_agent.clone())
+        .redirect(Policy::limited(crawl_config.max_redirects))
+        .timeout(Duration::from_millis(crawl_config.timeout_ms))
+        .build()
+        .expect("Failed to build the HTTP client")
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -195,4 +447,129 @@ mod tests {
         let result = website_processor.process_website(website);
         assert!(result.is_ok());
     }
+
+    #[test]
+    fn test_crawl_config() {
+        let mut server = mockito::Server::new();
+        let robots = server
+            .mock("GET", "/robots.txt")
+            .with_body("User-agent: *\nDisallow: /private\n")
+            .expect(1)
+            .create();
+        let page = server
+            .mock("GET", "/page")
+            .match_header("user-agent", "TestBot/1.0")
+            .with_header("content-type", "text/html")
+            .with_body("<html><body><p>Hello there.</p></body></html>")
+            .expect(2)
+            .create();
+
+        let website_processor = WebsiteProcessor::new().with_crawl_config(CrawlConfig {
+            user_agent: "TestBot/1.0".to_string(),
+            delay_ms: 300,
+            ..Default::default()
+        });
+        let url = format!("{}/page", server.url());
+        let start = Instant::now();
+        website_processor.process_website(&url).unwrap();
+        website_processor.process_website(&url).unwrap();
+        assert!(start.elapsed() >= Duration::from_millis(300));
+
+        let private = website_processor.process_website(&format!("{}/private", server.url()));
+        assert!(private.is_err());
+        robots.assert();
+        page.assert();
+    }
+
+    #[test]
+    fn test_retries() {
+        let mut server = mockito::Server::new();
+        let failure = server
+            .mock("GET", "/flaky")
+            .with_status(500)
+            .expect(1)
+            .create();
+        let success = server
+            .mock("GET", "/flaky")
+            .with_header("content-type", "text/html")
+            .with_body("<html><body><p>Back up.</p></body></html>")
+            .expect(1)
+            .create();
+        let missing = server
+            .mock("GET", "/missing")
+            .with_status(404)
+            .expect(1)
+            .create();
+
+        let website_processor = WebsiteProcessor::new().with_crawl_config(CrawlConfig {
+            respect_robots: false,
+            retry_backoff_ms: 10,
+            ..Default::default()
+        });
+        let web_page = website_processor
+            .process_website(&format!("{}/flaky", server.url()))
+            .unwrap();
+        assert_eq!(web_page.paragraphs.unwrap(), vec!["Back up."]);
+        failure.assert();
+        success.assert();
+
+        // Client errors are not retried.
+        let error = website_processor
+            .process_website(&format!("{}/missing", server.url()))
+            .unwrap_err();
+        assert!(matches!(
+            error.downcast_ref::<FetchError>(),
+            Some(FetchError::Status { status: 404, .. })
+        ));
+        missing.assert();
+    }
+
+    #[test]
+    fn test_pdf_content_type() {
+        let mut server = mockito::Server::new();
+        server
+            .mock("GET", "/paper")
+            .with_header("content-type", "application/pdf")
+            .with_body(std::fs::read("../test_files/test.pdf").unwrap())
+            .create();
+
+        let website_processor = WebsiteProcessor::new();
+        let web_page = website_processor
+            .process_website(&format!("{}/paper", server.url()))
+            .unwrap();
+        let paragraphs = web_page.paragraphs.unwrap();
+        assert!(!paragraphs.is_empty());
+        assert!(paragraphs
+            .iter()
+            .all(|paragraph| !paragraph.contains("%PDF")));
+    }
+
+    #[test]
+    fn test_text_and_unsupported_content_types() {
+        let mut server = mockito::Server::new();
+        server
+            .mock("GET", "/notes")
+            .with_header("content-type", "text/plain; charset=utf-8")
+            .with_body("First paragraph.\n\nSecond paragraph.")
+            .create();
+        server
+            .mock("GET", "/logo")
+            .with_header("content-type", "image/png")
+            .with_body([0u8, 1, 2])
+            .create();
+
+        let website_processor = WebsiteProcessor::new();
+        let web_page = website_processor
+            .process_website(&format!("{}/notes", server.url()))
+            .unwrap();
+        assert_eq!(
+            web_page.paragraphs.unwrap(),
+            vec!["First paragraph.", "Second paragraph."]
+        );
+
+        let error = website_processor
+            .process_website(&format!("{}/logo", server.url()))
+            .unwrap_err();
+        assert!(error.to_string().contains("image/png"));
+    }
 }
@@ -0,0 +1,379 @@
+//! In-memory approximate nearest neighbour index over [EmbedData], backed by `hnsw_rs`.
+//!
+//! This is meant for small to medium corpora where running a vector database is overkill.
+//!
+//! # Example
+//!
+//! ```rust,no_run
+//! use embed_anything::index::{HnswIndex, Metric};
+//! # let data = vec![];
+//!
+//! let mut index = HnswIndex::new(Metric::Cosine);
+//! index.add(data).unwrap();
+//! let results = index.search(&[0.1, 0.2, 0.3], 5).unwrap();
+//! index.save("index.json").unwrap();
+//! let index = HnswIndex::load("index.json").unwrap();
+//! ```
+
+use std::{collections::HashMap, fs, path::Path};
+
+use anyhow::{anyhow, Result};
+use hnsw_rs::prelude::{DistCosine, DistDot, DistHamming, DistL2, Hnsw};
+use serde::{Deserialize, Serialize};
+
+use crate::embeddings::embed::EmbedData;
+pub use crate::embeddings::embed::Metric;
+
+const MAX_NB_CONNECTION: usize = 16;
+const MAX_LAYER: usize = 16;
+const EF_CONSTRUCTION: usize = 200;
+const INITIAL_CAPACITY: usize = 10_000;
+
+/// A single hit returned by [HnswIndex::search].
+#[derive(Debug, Clone)]
+pub struct SearchResult {
+    /// Position of the entry in insertion order.
+    pub index: usize,
+    pub score: f32,
+    pub text: Option<String>,
+    pub metadata: Option<HashMap<String, String>>,
+}
+
+#[derive(Serialize, Deserialize)]
+struct Entry {
+    vector: Vec<f32>,
+    text: Option<String>,
+    metadata: Option<HashMap<String, String>>,
+}
+
+#[derive(Deserialize)]
+struct SavedIndex {
+    metric: Metric,
+    entries: Vec<Entry>,
+}
+
+enum Graph {
+    Cosine(Hnsw<'static, f32, DistCosine>),
+    Dot(Hnsw<'static, f32, DistDot>),
+    Euclidean(Hnsw<'static, f32, DistL2>),
+    /// Holds the vectors as bits, see [Metric::Hamming].
+    Hamming(Hnsw<'static, f32, DistHamming>),
+}
+
+/// Sets each coordinate to 1.0 when it is positive and to 0.0 otherwise.
+fn to_bits(vector: &[f32]) -> Vec<f32> {
+    vector
+        .iter()
+        .map(|&x| if x > 0.0 { 1.0 } else { 0.0 })
+        .collect()
+}
+
+impl Graph {
+    fn new(metric: Metric) -> Self {
+        match metric {
+            Metric::Cosine => Graph::Cosine(Hnsw::new(
+                MAX_NB_CONNECTION,
+                INITIAL_CAPACITY,
+                MAX_LAYER,
+                EF_CONSTRUCTION,
+                DistCosine {},
+            )),
+            Metric::Dot => Graph::Dot(Hnsw::new(
+                MAX_NB_CONNECTION,
+                INITIAL_CAPACITY,
+                MAX_LAYER,
+                EF_CONSTRUCTION,
+                DistDot {},
+            )),
+            Metric::Euclidean => Graph::Euclidean(Hnsw::new(
+                MAX_NB_CONNECTION,
+                INITIAL_CAPACITY,
+                MAX_LAYER,
+                EF_CONSTRUCTION,
+                DistL2 {},
+            )),
+            Metric::Hamming => Graph::Hamming(Hnsw::new(
+                MAX_NB_CONNECTION,
+                INITIAL_CAPACITY,
+                MAX_LAYER,
+                EF_CONSTRUCTION,
+                DistHamming {},
+            )),
+        }
+    }
+
+    fn insert(&self, vector: &[f32], id: usize) {
+        match self {
+            Graph::Cosine(hnsw) => hnsw.insert_slice((vector, id)),
+            Graph::Dot(hnsw) => hnsw.insert_slice((vector, id)),
+            Graph::Euclidean(hnsw) => hnsw.insert_slice((vector, id)),
+            Graph::Hamming(hnsw) => hnsw.insert_slice((&to_bits(vector), id)),
+        }
+    }
+
+    fn search(&self, query: &[f32], k: usize) -> Vec<usize> {
+        let ef_search = (2 * k).max(64);
+        let neighbours = match self {
+            Graph::Cosine(hnsw) => hnsw.search(query, k, ef_search),
+            Graph::Dot(hnsw) => hnsw.search(query, k, ef_search),
+            Graph::Euclidean(hnsw) => hnsw.search(query, k, ef_search),
+            Graph::Hamming(hnsw) => hnsw.search(&to_bits(query), k, ef_search),
+        };
+        neighbours.into_iter().map(|n| n.d_id).collect()
+    }
+}
+
+/// HNSW index that keeps the text and metadata of every embedding next to its vector.
+pub struct HnswIndex {
+    metric: Metric,
+    graph: Graph,
+    entries: Vec<Entry>,
+}
+
+impl HnswIndex {
+    pub fn new(metric: Metric) -> Self {
+        Self {
+            metric,
+            graph: Graph::new(metric),
+            entries: Vec::new(),
+        }
+    }
+
+    pub fn metric(&self) -> Metric {
+        self.metric
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    pub fn dimension(&self) -> Option<usize> {
+        self.entries.first().map(|entry| entry.vector.len())
+    }
+
+    /// Adds dense embeddings to the index. Multi-vector embeddings are rejected.
+    pub fn add(&mut self, data: Vec<EmbedData>) -> Result<()> {
+        for embed_data in data {
+            let vector = embed_data.embedding.to_dense()?;
+            self.push(Entry {
+                vector,
+                text: embed_data.text,
+                metadata: embed_data.metadata,
+            })?;
+        }
+        Ok(())
+    }
+
+    fn push(&mut self, entry: Entry) -> Result<()> {
+        if let Some(dimension) = self.dimension() {
+            if entry.vector.len() != dimension {
+                return Err(anyhow!(
+                    "Embedding dimension {} does not match index dimension {}",
+                    entry.vector.len(),
+                    dimension
+                ));
+            }
+        }
+        let id = self.entries.len();
+        self.graph.insert(&entry.vector, id);
+        self.entries.push(entry);
+        Ok(())
+    }
+
+    /// Returns the `k` nearest entries to `query`, best first.
+    pub fn search(&self, query: &[f32], k: usize) -> Result<Vec<SearchResult>> {
+        if self.is_empty() || k == 0 {
+            return Ok(Vec::new());
+        }
+        if Some(query.len()) != self.dimension() {
+            return Err(anyhow!(
+                "Query dimension {} does not match index dimension {:?}",
+                query.len(),
+                self.dimension()
+            ));
+        }
+
+        let mut results = self
+            .graph
+            .search(query, k)
+            .into_iter()
+            .map(|id| {
+                let entry = &self.entries[id];
+                SearchResult {
+                    index: id,
+                    score: self.score(query, &entry.vector),
+                    text: entry.text.clone(),
+                    metadata: entry.metadata.clone(),
+                }
+            })
+            .collect::<Vec<_>>();
+        results.sort_by(|a, b| b.score.total_cmp(&a.score));
+        Ok(results)
+    }
+
+    fn score(&self, a: &[f32], b: &[f32]) -> f32 {
+        self.metric.score(a, b)
+    }
+
+    /// Writes the vectors, texts and metadata to `path` as JSON. The graph is rebuilt by
+    /// [HnswIndex::load].
+    pub fn save<P: AsRef<Path>>(&self, path: P) -> Result<()> {
+        let file = fs::File::create(path)?;
+        serde_json::to_writer(
+            std::io::BufWriter::new(file),
+            &SavedIndexRef {
+                metric: self.metric,
+                entries: &self.entries,
+            },
+        )?;
+        Ok(())
+    }
+
+    pub fn load<P: AsRef<Path>>(path: P) -> Result<Self> {
+        let file = fs::File::open(path)?;
+        let saved: SavedIndex = serde_json::from_reader(std::io::BufReader::new(file))?;
+        let mut index = Self::new(saved.metric);
+        for entry in saved.entries {
+            index.push(entry)?;
+        }
+        Ok(index)
+    }
+}
+
+#[derive(Serialize)]
+struct SavedIndexRef<'a> {
+    metric: Metric,
+    entries: &'a [Entry],
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::embeddings::embed::EmbeddingResult;
+    use rand::{rngs::StdRng, Rng, SeedableRng};
+    use tempdir::TempDir;
+
+    fn random_data(n: usize, dim: usize) -> Vec<EmbedData> {
+        let mut rng = StdRng::seed_from_u64(42);
+        (0..n)
+            .map(|i| {
+                let vector = (0..dim)
+                    .map(|_| rng.gen_range(-1.0..1.0))
+                    .collect::<Vec<f32>>();
+                let norm = vector.iter().map(|x| x * x).sum::<f32>().sqrt();
+                let vector = vector.into_iter().map(|x| x / norm).collect();
+                EmbedData::new(
+                    EmbeddingResult::DenseVector(vector),
+                    Some(format!("text {}", i)),
+                    None,
+                )
+            })
+            .collect()
+    }
+
+    fn exact_neighbours(index: &HnswIndex, query: &[f32], k: usize) -> Vec<usize> {
+        let mut scores = index
+            .entries
+            .iter()
+            .enumerate()
+            .map(|(i, entry)| (i, index.score(query, &entry.vector)))
+            .collect::<Vec<_>>();
+        scores.sort_by(|a, b| b.1.total_cmp(&a.1));
+        scores.into_iter().take(k).map(|(i, _)| i).collect()
+    }
+
+    #[test]
+    fn test_search_recall() {
+        let data = random_data(48, 16);
+        let mut index = HnswIndex::new(Metric::Cosine);
+        index.add(data.clone()).unwrap();
+        assert_eq!(index.len(), 48);
+
+        let k = 5;
+        let mut found = 0;
+        for embed_data in &data {
+            let query = embed_data.embedding.to_dense().unwrap();
+            let results = index.search(&query, k).unwrap();
+            let expected = exact_neighbours(&index, &query, k);
+            found += results
+                .iter()
+                .filter(|r| expected.contains(&r.index))
+                .count();
+            assert_eq!(results[0].text, embed_data.text);
+        }
+        let recall = found as f32 / (data.len() * k) as f32;
+        assert!(recall > 0.9, "recall {}", recall);
+    }
+
+    #[test]
+    fn test_metrics() {
+        let vectors = [
+            vec![1.2, 0.0],
+            vec![3.0, 3.0],
+            vec![0.5, 0.6],
+            vec![-1.0, 0.0],
+        ];
+        let data = vectors
+            .iter()
+            .map(|vector| EmbedData::new(EmbeddingResult::DenseVector(vector.clone()), None, None))
+            .collect::<Vec<_>>();
+        let query = [0.6, 0.5];
+        // Dot products are tested on normalized vectors above, as the graph expects them.
+        for (metric, nearest, farthest) in [(Metric::Cosine, 1, 3), (Metric::Euclidean, 2, 1)] {
+            let mut index = HnswIndex::new(metric);
+            index.add(data.clone()).unwrap();
+            let results = index.search(&query, 4).unwrap();
+            assert_eq!(results[0].index, nearest, "{:?}", metric);
+            assert_eq!(results[0].score, metric.score(&query, &vectors[nearest]));
+            assert_eq!(results.last().unwrap().index, farthest);
+        }
+
+        let bits = [
+            vec![1.0, -1.0, 1.0, 1.0],
+            vec![-1.0, 1.0, -1.0, -1.0],
+            vec![1.0, -1.0, -1.0, 1.0],
+        ]
+        .into_iter()
+        .map(|vector| EmbedData::new(EmbeddingResult::DenseVector(vector), None, None))
+        .collect::<Vec<_>>();
+        let mut index = HnswIndex::new(Metric::Hamming);
+        index.add(bits).unwrap();
+        let results = index.search(&[1.0, -1.0, -1.0, -1.0], 3).unwrap();
+        assert_eq!(results[0].index, 2);
+        assert_eq!(results[0].score, -1.0);
+        assert_eq!(results[2].score, -2.0);
+    }
+
+    #[test]
+    fn test_dimension_mismatch() {
+        let mut index = HnswIndex::new(Metric::Dot);
+        index.add(random_data(2, 4)).unwrap();
+        assert!(index.add(random_data(1, 3)).is_err());
+        assert!(index.search(&[0.0; 3], 1).is_err());
+    }
+
+    #[test]
+    fn test_save_and_load() {
+        let dir = TempDir::new("hnsw_index").unwrap();
+        let path = dir.path().join("index.json");
+        let data = random_data(20, 8);
+        let mut index = HnswIndex::new(Metric::Dot);
+        index.add(data.clone()).unwrap();
+        index.save(&path).unwrap();
+
+        let loaded = HnswIndex::load(&path).unwrap();
+        assert_eq!(loaded.len(), 20);
+        assert_eq!(loaded.metric(), Metric::Dot);
+        let query = data[3].embedding.to_dense().unwrap();
+        let before = index.search(&query, 3).unwrap();
+        let after = loaded.search(&query, 3).unwrap();
+        assert_eq!(
+            before.iter().map(|r| r.index).collect::<Vec<_>>(),
+            after.iter().map(|r| r.index).collect::<Vec<_>>()
+        );
+    }
+}
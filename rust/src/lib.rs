@@ -42,6 +42,11 @@
 //!
 //! ## Generate embeddings
 //!
+//! Local embedding is deterministic: the same inputs and model give bit-identical vectors on the
+//! same device, as the models run in inference mode without dropout. The only stochastic step is
+//! the temperature fallback of audio transcription, which can be seeded with
+//! [TextEmbedConfig::seed](config::TextEmbedConfig::seed).
+//!
 //! # Example: Embed a text file
 //!
 //! Let's see how embed_anything can help us generate embeddings from a plain text file:
@@ -57,11 +62,14 @@
 //! let embedding = embed_file("path/to/file.txt", &embedder, None, None);
 //! ```
 
+pub mod adapters;
 pub mod chunkers;
 pub mod config;
 pub mod embeddings;
 pub mod file_loader;
 pub mod file_processor;
+#[cfg(feature = "hnsw")]
+pub mod index;
 pub mod models;
 #[cfg(feature = "ort")]
 pub mod reranker;
@@ -70,17 +78,37 @@ pub mod text_loader;
 
 use std::{collections::HashMap, fs, path::PathBuf, rc::Rc, sync::Arc};
 
+use adapters::Adapter;
 use anyhow::Result;
 use config::{ImageEmbedConfig, TextEmbedConfig};
 use embeddings::{
-    embed::{EmbedData, EmbedImage, Embedder, TextEmbedder, VisionEmbedder},
+    batch_size::MAX_AUTO_BATCH_SIZE,
+    cloud::batcher::CrossFileBatcher,
+    diff::{diff_chunks, DocumentDiff, DEFAULT_DIFF_THRESHOLD},
+    dimensions::DimensionValidator,
+    document_embedding,
+    embed::{EmbedData, EmbedImage, Embedder, EmbeddingResult, TextEmbedder, VisionEmbedder},
     get_text_metadata,
+    hyde::{TextGenerator, HYDE_QUERY_KEY},
+    mark_embedding_model, mark_granularity, mark_normalized, normalize_l2_ndarray, top_k,
 };
 use file_loader::FileParser;
-use file_processor::audio::audio_processor::AudioDecoderModel;
+use file_processor::{
+    archive::{is_archive, ExtractedArchive, DEFAULT_MAX_ARCHIVE_BYTES},
+    audio::audio_processor::AudioDecoderModel,
+    extractor::{HtmlExtractor, PdfExtractor, TextExtractor},
+    pdf_processor::PdfProcessor,
+    pdf_tables::CONTAINS_TABLE_KEY,
+};
 use itertools::Itertools;
+use ndarray::Array2;
 use rayon::prelude::*;
-use text_loader::{SplittingStrategy, TextLoader};
+use chunkers::{
+    code::CodeLanguage,
+    language::add_chunk_languages,
+    spans::{chunk_spans, END_CHAR_KEY, START_CHAR_KEY},
+};
+use text_loader::{ExtractedDocument, FileLoadingError, SplittingStrategy, TextLoader};
 use tokio::sync::mpsc; // Add this at the top of your file
 
 
@@ -137,12 +165,274 @@ pub async fn embed_query(
     let _chunk_size = config.chunk_size.unwrap_or(256);
     let batch_size = config.batch_size;
 
-    let encodings = embedder.embed(&query, batch_size).await?;
-    let embeddings = get_text_metadata(&Rc::new(encodings), &query, &None)?;
+    report_truncation(embedder.tokenizer(), &query, None, config)?;
+    let encodings = config
+        .embed_query_batch(embedder, &query, batch_size)
+        .await?;
+    let mut embeddings = get_text_metadata(&Rc::new(encodings), &query, &None)?;
+    store_embedded_text(&mut embeddings, embedder.prefixes().0, config);
+    mark_normalized(&mut embeddings, embedder.is_normalized());
+    mark_embedding_model(&mut embeddings, &embedder.model_id());
+    config.finish_embeddings(&mut embeddings);
 
     Ok(embeddings)
 }
 
+/// Embeds queries like [embed_query], but returns one result per query so that a bad query
+/// doesn't fail the others. Empty and whitespace-only queries fail without being embedded.
+/// The other queries are embedded in one batch; if the batch fails, they are embedded one at
+/// a time to tell which ones fail.
+///
+/// # Example
+///
+/// ```rust,no_run
+/// use embed_anything::{embeddings::embed::Embedder, try_embed_query};
+///
+/// async fn embed(embedder: &Embedder) {
+///     let queries = vec!["What is Rust?".to_string(), "".to_string()];
+///     for (query, result) in queries.clone().iter().zip(try_embed_query(queries, embedder, None).await) {
+///         match result {
+///             Ok(embedding) => println!("{}: {} dimensions", query, embedding.embedding.to_dense().unwrap().len()),
+///             Err(e) => println!("{:?} failed: {}", query, e),
+///         }
+///     }
+/// }
+/// ```
+pub async fn try_embed_query(
+    query: Vec<String>,
+    embedder: &Embedder,
+    config: Option<&TextEmbedConfig>,
+) -> Vec<Result<EmbedData>> {
+    let mut results = query
+        .iter()
+        .map(|text| {
+            text.trim()
+                .is_empty()
+                .then(|| Err(anyhow::anyhow!("Query {:?} is empty", text)))
+        })
+        .collect::<Vec<Option<Result<EmbedData>>>>();
+    let (indices, texts): (Vec<_>, Vec<_>) = query
+        .into_iter()
+        .enumerate()
+        .filter(|(i, _)| results[*i].is_none())
+        .unzip();
+
+    if !texts.is_empty() {
+        match embed_query(texts.clone(), embedder, config).await {
+            Ok(embeddings) => {
+                for (i, embedding) in indices.iter().zip(embeddings) {
+                    results[*i] = Some(Ok(embedding));
+                }
+            }
+            Err(_) => {
+                for (i, text) in indices.iter().zip(texts) {
+                    let embedding =
+                        embed_query(vec![text], embedder, config)
+                            .await
+                            .and_then(|embeddings| {
+                                embeddings
+                                    .into_iter()
+                                    .next()
+                                    .ok_or_else(|| anyhow::anyhow!("No embedding was returned"))
+                            });
+                    results[*i] = Some(embedding);
+                }
+            }
+        }
+    }
+    results
+        .into_iter()
+        .map(|result| result.unwrap_or_else(|| Err(anyhow::anyhow!("No embedding was returned"))))
+        .collect()
+}
+
+/// Embeds a query with hypothetical document embeddings (HyDE): `generator` writes a passage
+/// answering `query`, and the passage is embedded as a document in place of the query, so that
+/// it is compared to the documents of an index in their own wording. See
+/// [embeddings::hyde] for an example generator.
+///
+/// The text of the result is the generated passage, and its metadata holds the original query
+/// under [HYDE_QUERY_KEY]. Fails when the generator fails or writes nothing.
+pub async fn embed_query_hyde(
+    query: &str,
+    generator: &dyn TextGenerator,
+    embedder: &Embedder,
+    config: Option<&TextEmbedConfig>,
+) -> Result<EmbedData> {
+    let binding = TextEmbedConfig::default();
+    let config = config.unwrap_or(&binding);
+
+    let document = generator.generate(query).await?;
+    if document.trim().is_empty() {
+        return Err(anyhow::anyhow!(
+            "The generator wrote no document for the query {:?}",
+            query
+        ));
+    }
+    let texts = vec![document];
+    report_truncation(embedder.tokenizer(), &texts, None, config)?;
+    let encodings = config
+        .embed_document_batch(embedder, &texts, config.batch_size)
+        .await?;
+    let metadata = HashMap::from([(HYDE_QUERY_KEY.to_string(), query.to_string())]);
+    let mut embeddings = get_text_metadata(&Rc::new(encodings), &texts, &Some(metadata))?;
+    store_embedded_text(&mut embeddings, embedder.prefixes().1, config);
+    mark_normalized(&mut embeddings, embedder.is_normalized());
+    mark_embedding_model(&mut embeddings, &embedder.model_id());
+    config.finish_embeddings(&mut embeddings);
+
+    embeddings
+        .into_iter()
+        .next()
+        .ok_or_else(|| anyhow::anyhow!("No embedding was returned"))
+}
+
+/// Metadata key holding the id of the [Record] an embedding was made from.
+pub const RECORD_ID_KEY: &str = "record_id";
+
+/// A text to embed along with its id, e.g. a row of a database table. See [embed_records].
+#[derive(Debug, Clone, Default)]
+pub struct Record {
+    pub id: String,
+    pub text: String,
+    /// Copied to the metadata of the embeddings of the record.
+    pub metadata: HashMap<String, String>,
+}
+
+impl Record {
+    pub fn new(id: impl Into<String>, text: impl Into<String>) -> Self {
+        Self {
+            id: id.into(),
+            text: text.into(),
+            metadata: HashMap::new(),
+        }
+    }
+
+    pub fn with_metadata(mut self, metadata: HashMap<String, String>) -> Self {
+        self.metadata = metadata;
+        self
+    }
+}
+
+/// Embeds texts that don't come from files, such as rows of a database, keeping their ids.
+///
+/// The id of every record is stored under [RECORD_ID_KEY] in the metadata of its embeddings,
+/// next to the metadata of the record. Records are embedded whole unless
+/// [TextEmbedConfig::chunk_records] is set, in which case they are split like the text of a
+/// file and the `chunk_index` of every chunk is added to its metadata.
+///
+/// # Example
+///
+/// ```rust,no_run
+/// use embed_anything::{embed_records, embeddings::embed::{EmbedData, Embedder}, Record};
+///
+/// async fn embed(embedder: &Embedder) {
+///     let records = vec![
+///         Record::new("42", "The invoice was paid on time."),
+///         Record::new("43", "The delivery was late."),
+///     ];
+///     let embeddings = embed_records(records, embedder, None, None::<fn(Vec<EmbedData>)>)
+///         .await
+///         .unwrap()
+///         .unwrap();
+/// }
+/// ```
+pub async fn embed_records<F>(
+    records: Vec<Record>,
+    embedder: &Embedder,
+    config: Option<&TextEmbedConfig>,
+    adapter: Option<F>,
+) -> Result<Option<Vec<EmbedData>>>
+where
+    F: Adapter,
+{
+    let binding = TextEmbedConfig::default();
+    let config = config.unwrap_or(&binding);
+    let records = records
+        .into_iter()
+        .map(|record| Record {
+            text: config.redact_text(record.text),
+            ..record
+        })
+        .collect::<Vec<_>>();
+    let record_metadata = |record: &Record| {
+        let mut metadata = record.metadata.clone();
+        metadata.insert(RECORD_ID_KEY.to_string(), record.id.clone());
+        metadata
+    };
+
+    let mut texts = Vec::with_capacity(records.len());
+    let mut metadata = Vec::with_capacity(records.len());
+    if config.chunk_records.unwrap_or(false) {
+        let splitting_strategy = config
+            .splitting_strategy
+            .unwrap_or(SplittingStrategy::Sentence);
+        let textloader = TextLoader::new(
+            config.chunk_size.unwrap_or(256),
+            config.overlap_ratio.unwrap_or(0.0),
+        )
+        .with_language(config.language.as_deref())
+        .with_sentence_backend(config.sentence_splitter.clone())
+        .with_min_chunk_size(
+            config.min_chunk_size,
+            config.min_chunk_policy.unwrap_or_default(),
+        );
+        for record in &records {
+            let chunks = match splitting_strategy {
+                SplittingStrategy::Code { language } => textloader
+                    .split_code(&record.text, language)?
+                    .into_iter()
+                    .map(|chunk| chunk.text)
+                    .collect(),
+                _ => textloader
+                    .split_into_chunks(
+                        &record.text,
+                        splitting_strategy,
+                        config.semantic_encoder.clone(),
+                    )
+                    .unwrap_or_default(),
+            };
+            for (index, chunk) in chunks.into_iter().enumerate() {
+                let mut chunk_metadata = record_metadata(record);
+                chunk_metadata.insert("chunk_index".to_string(), index.to_string());
+                texts.push(chunk);
+                metadata.push(chunk_metadata);
+            }
+        }
+    } else {
+        for record in &records {
+            metadata.push(record_metadata(record));
+        }
+        texts.extend(records.into_iter().map(|record| record.text));
+    }
+
+    report_truncation(embedder.tokenizer(), &texts, None, config)?;
+    let encodings = config
+        .embed_document_batch(embedder, &texts, config.batch_size)
+        .await?;
+    let mut embeddings = encodings
+        .into_iter()
+        .zip(texts)
+        .zip(metadata)
+        .map(|((encoding, text), metadata)| EmbedData::new(encoding, Some(text), Some(metadata)))
+        .collect::<Vec<_>>();
+    if let Some(validator) = dimension_validator(embedder.metadata().dimension, config) {
+        validator.check(&embeddings)?;
+    }
+    store_embedded_text(&mut embeddings, embedder.prefixes().1, config);
+    mark_normalized(&mut embeddings, embedder.is_normalized());
+    mark_embedding_model(&mut embeddings, &embedder.model_id());
+    config.finish_embeddings(&mut embeddings);
+
+    if let Some(adapter) = adapter {
+        adapter.upsert(embeddings);
+        adapter.flush();
+        Ok(None)
+    } else {
+        Ok(Some(embeddings))
+    }
+}
+
 /// Embeds the text from a file using the specified embedding model.
 ///
 /// # Arguments
@@ -152,6 +442,9 @@ pub async fn embed_query(
 /// * `config` - An optional `EmbedConfig` object specifying the configuration for the embedding model.
 /// * 'adapter' - An optional `Adapter` object to send the embeddings to a vector database.
 ///
+/// With [TextEmbedConfig::extract_archives], a `.zip`, `.tar.gz` or `.tgz` file is unpacked and
+/// each supported file inside it is embedded, see [file_processor::archive].
+///
 /// # Returns
 ///
 /// A vector of `EmbedData` objects representing the embeddings of the file.
@@ -178,603 +471,3553 @@ pub async fn embed_file<T: AsRef<std::path::Path>, F>(
     adapter: Option<F>,
 ) -> Result<Option<Vec<EmbedData>>>
 where
-    F: Fn(Vec<EmbedData>), // Add Send trait bound here
+    F: Adapter,
 {
-  
-
-    match embedder {
-        Embedder::Text(embedder) => {
-            emb_text(
-                file_name,
-                embedder,
-                config,
-                adapter,
-            )
-            .await
+    let archive_config = config.filter(|config| config.extract_archives.unwrap_or(false));
+    let embeddings = match (archive_config, embedder) {
+        (Some(config), _) if is_archive(&file_name) => {
+            emb_archive(file_name.as_ref(), embedder, config, adapter.as_ref()).await?
+        }
+        (_, Embedder::Text(embedder)) => {
+            emb_text(file_name, embedder, config, adapter.as_ref()).await?
         }
-        Embedder::Vision(embedder) => Ok(Some(vec![emb_image(file_name, embedder).unwrap()])),
+        (_, Embedder::Vision(embedder)) => {
+            return Ok(Some(vec![emb_image(file_name, embedder).unwrap()]))
+        }
+    };
+    if let Some(adapter) = &adapter {
+        adapter.flush();
     }
+    Ok(embeddings)
 }
 
-/// Embeddings of a webpage using the specified embedding model.
+/// Loads a model from the Hugging Face hub, embeds a file with it and frees the model before
+/// returning. This suits scripts and CLI tools that embed once and would otherwise keep the
+/// model in memory until they exit.
 ///
-/// # Arguments
+/// Loading a model is slow, so this is inefficient when called repeatedly. Create an
+/// [Embedder] once and call [embed_file] for each file instead.
 ///
-/// * `embedder` - The embedding model to use. Supported options are "OpenAI", "Jina", and "Bert".
-/// * `webpage` - The webpage to embed.
+/// # Example
 ///
-/// # Returns
+/// ```rust,no_run
+/// use embed_anything::embed_file_oneshot;
 ///
-/// The embeddings of the webpage.
+/// async fn embed() {
+///     let embeddings = embed_file_oneshot(
+///         "path/to/file.pdf",
+///         "bert",
+///         "sentence-transformers/all-MiniLM-L6-v2",
+///         None,
+///     )
+///     .await
+///     .unwrap();
+/// }
+/// ```
+pub async fn embed_file_oneshot<T: AsRef<std::path::Path>>(
+    file_name: T,
+    model_architecture: &str,
+    model_id: &str,
+    config: Option<&TextEmbedConfig>,
+) -> Result<Vec<EmbedData>> {
+    let embedder = Embedder::from_pretrained_hf(model_architecture, model_id, None, None)?;
+    let embeddings = embed_file(file_name, &embedder, config, None::<fn(Vec<EmbedData>)>).await?;
+    drop(embedder);
+    Ok(embeddings.unwrap_or_default())
+}
+
+/// Embeds a file with several models, extracting and chunking it only once. This is meant for
+/// hybrid search, where every chunk is stored with a dense and a sparse vector.
 ///
-/// # Errors
+/// `embedders` pairs a name with each model. Every returned [EmbedData] holds the embeddings of
+/// all models in [EmbedData::embeddings], keyed by that name, while `embedding` and
+/// `is_normalized` are the ones of the first model. An adapter for hybrid upserts reads the
+/// vectors it needs from the map, for example
+/// `data.embeddings["dense"]` and `data.embeddings["sparse"]`.
 ///
-/// Returns an error if the specified embedding model is invalid.
+/// Only text files are supported, vision models embed the chunks as text.
 ///
 /// # Example
 ///
-/// ```
-/// use embed_anything::embed_webpage;
-/// use embed_anything::embeddings::embed::{Embedder, TextEmbedder};
-/// use embed_anything::embeddings::local::jina::JinaEmbedder;
+/// ```rust,no_run
+/// use embed_anything::embed_file_multi;
+/// use embed_anything::embeddings::embed::{EmbedData, Embedder};
 ///
-/// let embedder = Embedder::Text(TextEmbedder::Jina(Box::new(JinaEmbedder::default())));
-/// let embeddings = embed_webpage("https://en.wikipedia.org/wiki/Embedding".into(), &embedder, None, None).unwrap();
+/// async fn embed() {
+///     let dense = Embedder::from_pretrained_hf("bert", "BAAI/bge-small-en-v1.5", None, None).unwrap();
+///     let sparse =
+///         Embedder::from_pretrained_hf("sparse-bert", "prithivida/Splade_PP_en_v1", None, None).unwrap();
+///     let embeddings = embed_file_multi(
+///         "path/to/file.pdf",
+///         &[("dense", &dense), ("sparse", &sparse)],
+///         None,
+///         None::<fn(Vec<EmbedData>)>,
+///     )
+///     .await
+///     .unwrap()
+///     .unwrap();
+///     let sparse_vector = &embeddings[0].embeddings["sparse"];
+/// }
 /// ```
-pub async fn embed_webpage<F>(
-    url: String,
-    embedder: &Embedder,
+pub async fn embed_file_multi<T: AsRef<std::path::Path>, F>(
+    file_name: T,
+    embedders: &[(&str, &Embedder)],
     config: Option<&TextEmbedConfig>,
-    // Callback function
     adapter: Option<F>,
 ) -> Result<Option<Vec<EmbedData>>>
 where
-    F: Fn(Vec<EmbedData>),
+    F: Adapter,
 {
-    let website_processor = file_processor::website_processor::WebsiteProcessor::new();
-    let webpage = website_processor.process_website(url.as_ref())?;
-
-    // if let Embedder::Clip(_) = embedder {
-    //     return Err(anyhow!("Clip model does not support webpage embedding"));
-    // }
-
+    let Some((primary_name, primary)) = embedders.first() else {
+        return Err(anyhow::anyhow!("At least one embedder is required"));
+    };
     let binding = TextEmbedConfig::default();
     let config = config.unwrap_or(&binding);
-    let chunk_size = config.chunk_size.unwrap_or(256);
-    let overlap_ratio = config.overlap_ratio.unwrap_or(0.0);
-    let batch_size = config.batch_size;
+    let (chunks, chunk_metadata, metadata) = chunk_file(&file_name, config)?;
 
-    let embeddings = webpage
-        .embed_webpage(embedder, chunk_size, overlap_ratio, batch_size)
-        .await?;
+    let mut encodings_by_model = Vec::with_capacity(embedders.len());
+    for (name, embedder) in embedders {
+        let batch_size = if config.auto_batch_size.unwrap_or(false) {
+            let max_batch_size = config.batch_size.unwrap_or(MAX_AUTO_BATCH_SIZE);
+            Some(embedder.tune_batch_size(&chunks, max_batch_size).await)
+        } else {
+            config.batch_size
+        };
+        report_truncation(
+            embedder.tokenizer(),
+            &chunks,
+            Some(&file_name.as_ref().to_string_lossy()),
+            config,
+        )?;
+        let encodings = config
+            .embed_document_batch(*embedder, &chunks, batch_size)
+            .await?;
+        encodings_by_model.push((name.to_string(), encodings));
+    }
+
+    let mut embeddings = chunks
+        .into_iter()
+        .enumerate()
+        .map(|(i, chunk)| {
+            let by_model: HashMap<String, EmbeddingResult> = encodings_by_model
+                .iter()
+                .map(|(name, encodings)| (name.clone(), encodings[i].clone()))
+                .collect();
+            let mut embedding = EmbedData::new(
+                by_model[*primary_name].clone(),
+                Some(chunk),
+                metadata.clone(),
+            )
+            .with_normalized(primary.is_normalized())
+            .with_embedding_model(&primary.model_id());
+            embedding.embeddings = by_model;
+            embedding
+        })
+        .collect::<Vec<_>>();
+    store_embedded_text(&mut embeddings, primary.prefixes().1, config);
+    merge_chunk_metadata(&mut embeddings, chunk_metadata);
+    config.finish_embeddings(&mut embeddings);
 
-    // Send embeddings to vector database
     if let Some(adapter) = adapter {
-        adapter(embeddings);
+        adapter.upsert(embeddings);
+        adapter.flush();
         Ok(None)
     } else {
         Ok(Some(embeddings))
     }
 }
 
-/// Embeds an HTML document using the specified embedding model.
-///
-/// # Arguments
-///
-/// * `file_name` - The path of the HTML document to embed.
-/// * `origin` - The original URL of the document. If specified, links can be resolved and metadata points to the site.
-/// * `embedder` - The embedding model to use. Supported options are "OpenAI", "Jina", and "Bert".
-///
-/// # Returns
-///
-/// The embeddings of the HTML document.
+/// Embeds the text of several files, batching the chunks of different files together.
 ///
-/// # Errors
+/// With a cloud embedder, chunks are sent in requests of the largest size the provider
+/// accepts, see [TextEmbedder::max_batch_size], instead of one request per file. This cuts
+/// the number of requests on corpora of many small files. Local models get batches of
+/// `buffer_size` chunks. Files that fail to load are skipped and logged.
 ///
-/// Returns an error if the specified embedding model is invalid.
+/// The adapter, when given, is called once per file with the embeddings of its chunks, in the
+/// order of `files`.
 ///
 /// # Example
 ///
-/// ```
-/// use embed_anything::embed_html;
-/// use embed_anything::embeddings::embed::{Embedder, TextEmbedder};
-/// use embed_anything::embeddings::local::jina::JinaEmbedder;
+/// ```rust,no_run
+/// use embed_anything::embed_files;
+/// use embed_anything::embeddings::embed::{EmbedData, Embedder};
 ///
-/// async fn get_embeddings() {
-///     let embeddings = embed_html(
-///         "test_files/test.html",
-///         Some("https://example.com/"),
-///         &Embedder::from_pretrained_hf("JINA", "jinaai/jina-embeddings-v2-small-en", None).unwrap(),
+/// async fn embed() {
+///     let embedder = Embedder::from_pretrained_cloud("openai", "text-embedding-3-small", None)
+///         .unwrap();
+///     let embeddings = embed_files(
+///         &["notes/a.md", "notes/b.md", "notes/c.md"],
+///         &embedder,
 ///         None,
-///         None,
-///     ).await.unwrap();
+///         None::<fn(Vec<EmbedData>)>,
+///     )
+///     .await
+///     .unwrap()
+///     .unwrap();
 /// }
 /// ```
-pub async fn embed_html(
-    file_name: impl AsRef<std::path::Path>,
-    origin: Option<impl Into<String>>,
+pub async fn embed_files<T: AsRef<std::path::Path>, F>(
+    files: &[T],
     embedder: &Embedder,
     config: Option<&TextEmbedConfig>,
-    // Callback function
-    adapter: Option<Box<dyn FnOnce(Vec<EmbedData>)>>,
-) -> Result<Option<Vec<EmbedData>>> {
-    let html_processor = file_processor::html_processor::HtmlProcessor::new();
-    let html = html_processor.process_html_file(file_name.as_ref(), origin)?;
-
-    let binding = TextEmbedConfig::default();
-    let config = config.unwrap_or(&binding);
-    let chunk_size = config.chunk_size.unwrap_or(256);
-    let overlap_ratio = config.overlap_ratio.unwrap_or(0.0);
-    let batch_size = config.batch_size;
-
-    let embeddings = html
-        .embed_webpage(embedder, chunk_size, overlap_ratio, batch_size)
-        .await?;
-
-    // Send embeddings to vector database
-    if let Some(adapter) = adapter {
-        adapter(embeddings);
-        Ok(None)
-    } else {
-        Ok(Some(embeddings))
-    }
-}
-
-#[allow(clippy::too_many_arguments)]
-async fn emb_text<T: AsRef<std::path::Path>, F>(
-    file: T,
-    embedding_model: &TextEmbedder,
-    config: Option<&TextEmbedConfig>,
     adapter: Option<F>,
 ) -> Result<Option<Vec<EmbedData>>>
 where
-    F: Fn(Vec<EmbedData>),
+    F: Adapter,
 {
-
     let binding = TextEmbedConfig::default();
     let config = config.unwrap_or(&binding);
-    let chunk_size = config.chunk_size.unwrap_or(256);
-    let overlap_ratio = config.overlap_ratio.unwrap_or(0.0);
-    let batch_size = config.batch_size;
-    let splitting_strategy = config
-        .splitting_strategy
-        .unwrap_or(SplittingStrategy::Sentence);
-    let semantic_encoder = config.semantic_encoder.clone();
-    let use_ocr = config.use_ocr.unwrap_or(false);
-    let tesseract_path = config.tesseract_path.clone();
-    let text = TextLoader::extract_text(&file, use_ocr, tesseract_path.as_deref())?;
-    let textloader = TextLoader::new(chunk_size, overlap_ratio);
-    let chunks = textloader
-        .split_into_chunks(
-            &text,
-            splitting_strategy,
-            semantic_encoder,
-        )
-        .unwrap_or_default();
+    let max_batch_size = match embedder {
+        Embedder::Text(embedder) => embedder.max_batch_size(),
+        Embedder::Vision(_) => None,
+    }
+    .unwrap_or(config.buffer_size.unwrap_or(100));
+    let mut batcher = CrossFileBatcher::new(embedder, max_batch_size);
+    let validator = dimension_validator(embedder.metadata().dimension, config);
 
-    let metadata = TextLoader::get_metadata(file).ok();
+    let mut all_embeddings = Vec::new();
+    let mut emit = |mut embeddings: Vec<EmbedData>| {
+        if let Some(validator) = &validator {
+            validator.retain_valid(&mut embeddings);
+        }
+        config.finish_embeddings(&mut embeddings);
+        if let Some(adapter) = &adapter {
+            adapter.upsert(embeddings);
+        } else {
+            all_embeddings.extend(embeddings);
+        }
+    };
+    for file in files {
+        let (chunks, chunk_metadata, metadata) = match chunk_file(file, config) {
+            Ok(chunks) => chunks,
+            Err(e) => {
+                tracing::warn!("Skipping {:?}: {:?}", file.as_ref(), e);
+                continue;
+            }
+        };
+        let metadata = chunk_metadata
+            .into_iter()
+            .map(|extra| {
+                let mut metadata = metadata.clone().unwrap_or_default();
+                metadata.extend(extra);
+                Some(metadata)
+            })
+            .collect();
+        for embeddings in batcher.add_file(chunks, metadata).await? {
+            emit(embeddings);
+        }
+    }
+    for embeddings in batcher.finish().await? {
+        emit(embeddings);
+    }
 
-    if let Some(adapter) = adapter {
-        let encodings = embedding_model.embed(&chunks, batch_size).await.unwrap();
-        let embeddings = get_text_metadata(&Rc::new(encodings), &chunks, &metadata).unwrap();
-        adapter(embeddings);
+    if let Some(adapter) = &adapter {
+        adapter.flush();
         Ok(None)
     } else {
-        let encodings = embedding_model.embed(&chunks, batch_size).await.unwrap();
-        let embeddings = get_text_metadata(&Rc::new(encodings), &chunks, &metadata).unwrap();
-
-        Ok(Some(embeddings))
+        Ok(Some(all_embeddings))
     }
 }
 
-fn emb_image<T: AsRef<std::path::Path>>(
-    image_path: T,
-    embedding_model: &VisionEmbedder,
-) -> Result<EmbedData> {
-    let mut metadata = HashMap::new();
-    metadata.insert(
-        "file_name".to_string(),
-        fs::canonicalize(&image_path)?.to_str().unwrap().to_string(),
-    );
-    let embedding = embedding_model
-        .embed_image(&image_path, Some(metadata))
-        .unwrap();
-
-    Ok(embedding.clone())
+/// A chunk returned by [similarity_search].
+#[derive(Debug, Clone)]
+pub struct SearchResult {
+    pub text: Option<String>,
+    /// Cosine similarity with the query, see [EmbeddingResult::similarity].
+    pub score: f32,
+    pub metadata: Option<HashMap<String, String>>,
 }
 
-#[cfg(feature = "audio")]
-pub async fn emb_audio<T: AsRef<std::path::Path>>(
-    audio_file: T,
-    audio_decoder: &mut AudioDecoderModel,
+/// Embeds a file, or every supported file of a directory, and returns the `k` chunks most
+/// similar to `query`, best first. The chunks are ranked in memory, so no vector database is
+/// needed. This is meant for small collections, everything is embedded again on each call.
+///
+/// # Example
+///
+/// ```rust,no_run
+/// use std::sync::Arc;
+///
+/// use embed_anything::embeddings::embed::Embedder;
+/// use embed_anything::similarity_search;
+///
+/// async fn search() {
+///     let embedder = Arc::new(
+///         Embedder::from_pretrained_hf("bert", "BAAI/bge-small-en-v1.5", None, None).unwrap(),
+///     );
+///     let results = similarity_search("What is attention?", "path/to/docs", &embedder, None, 5)
+///         .await
+///         .unwrap();
+///     for result in results {
+///         println!("{:.3} {:?}", result.score, result.text);
+///     }
+/// }
+/// ```
+pub async fn similarity_search<T: AsRef<std::path::Path>>(
+    query: &str,
+    path: T,
     embedder: &Arc<Embedder>,
-    text_embed_config: Option<&TextEmbedConfig>,
-) -> Result<Option<Vec<EmbedData>>> {
-    use file_processor::audio::audio_processor;
+    config: Option<&TextEmbedConfig>,
+    k: usize,
+) -> Result<Vec<SearchResult>> {
+    let path = path.as_ref();
+    let embeddings = if path.is_dir() {
+        embed_directory_stream(
+            path.to_path_buf(),
+            embedder,
+            None,
+            config,
+            None::<fn(Vec<EmbedData>)>,
+        )
+        .await?
+    } else {
+        embed_file(path, embedder, config, None::<fn(Vec<EmbedData>)>).await?
+    }
+    .unwrap_or_default();
 
-    let segments: Vec<audio_processor::Segment> = audio_decoder.process_audio(&audio_file).unwrap();
-    let embeddings = embed_audio(
-        embedder,
-        segments,
-        audio_file,
-        text_embed_config
-            .unwrap_or(&TextEmbedConfig::default())
-            .batch_size,
-    )
-    .await?;
-
-    Ok(Some(embeddings))
-}
-
-#[cfg(not(feature = "audio"))]
-pub async fn emb_audio<T: AsRef<std::path::Path>>(
-    _audio_file: T,
-    _audio_decoder: &mut AudioDecoderModel,
-    _embedder: &Arc<Embedder>,
-    _text_embed_config: Option<&TextEmbedConfig>,
-) -> Result<Option<Vec<EmbedData>>> {
-    Err(anyhow::anyhow!(
-        "The 'audio' feature is not enabled. Please enable it to use the emb_audio function."
-    ))
+    let query = embed_query(vec![query.to_string()], embedder, config).await?;
+    let ranked = top_k(&query[0].embedding, &embeddings, k)?;
+    Ok(ranked
+        .into_iter()
+        .map(|(i, score)| SearchResult {
+            text: embeddings[i].text.clone(),
+            score,
+            metadata: embeddings[i].metadata.clone(),
+        })
+        .collect())
 }
 
-/// Embeds images in a directory using the specified embedding model.
+/// Embeds `texts` and returns the n×n matrix of their cosine similarities, e.g. to find near
+/// duplicates or to cluster a small set of texts. The vectors are normalized and multiplied
+/// in a single matrix product. The texts are embedded as documents, with the embedding cache
+/// and [TextEmbedConfig::sort_by_length] of `config`. Models returning multi-vector embeddings
+/// are not supported.
 ///
-/// # Arguments
+/// # Example
 ///
-/// * `directory` - A `PathBuf` representing the directory containing the images to embed.
-/// * `embedder` - A reference to the embedding model to use.
-/// * `config` - An optional `ImageEmbedConfig` object specifying the configuration for the embedding model. Default buffer size is 100.
-/// * `adapter` - An optional callback function to handle the embeddings.
+/// ```rust,no_run
+/// use embed_anything::embeddings::embed::Embedder;
+/// use embed_anything::pairwise_similarity;
 ///
-/// # Returns
-/// An `Option` containing a vector of `EmbedData` objects representing the embeddings of the images, or `None` if an adapter is used.
+/// async fn duplicates() {
+///     let embedder =
+///         Embedder::from_pretrained_hf("bert", "BAAI/bge-small-en-v1.5", None, None).unwrap();
+///     let texts = vec!["A cat sat on the mat.".to_string(), "A cat sits on a mat.".to_string()];
+///     let similarities = pairwise_similarity(&texts, &embedder, None).await.unwrap();
+///     println!("{:.3}", similarities[[0, 1]]);
+/// }
+/// ```
+pub async fn pairwise_similarity(
+    texts: &[String],
+    embedder: &Embedder,
+    config: Option<&TextEmbedConfig>,
+) -> Result<Array2<f32>> {
+    let binding = TextEmbedConfig::default();
+    let config = config.unwrap_or(&binding);
+    let embeddings = config
+        .embed_document_batch(embedder, texts, config.batch_size)
+        .await?;
+    let dimension = match embeddings.first() {
+        Some(embedding) => embedding.to_dense()?.len(),
+        None => return Ok(Array2::zeros((0, 0))),
+    };
+    let mut vectors = Array2::zeros((embeddings.len(), dimension));
+    for (embedding, mut row) in embeddings.iter().zip(vectors.rows_mut()) {
+        let vector = embedding.to_dense()?;
+        if vector.len() != dimension {
+            return Err(anyhow::anyhow!(
+                "Expected embeddings of dimension {}, got {}",
+                dimension,
+                vector.len()
+            ));
+        }
+        row.assign(&ndarray::ArrayView1::from(&vector));
+    }
+    let vectors = normalize_l2_ndarray(&vectors);
+    Ok(vectors.dot(&vectors.t()))
+}
+
+/// Chunks and embeds two versions of a document and returns which chunks were added, removed,
+/// modified or left unchanged, with their embeddings, to update an index chunk by chunk.
+/// Both versions are split like the text of a file. Chunks with the same text are unchanged,
+/// and a chunk of the new version is the modified version of a chunk of the old one when
+/// their cosine similarity reaches [TextEmbedConfig::diff_threshold]. See
+/// [embeddings::diff] for the matching.
 ///
-/// # Errors
-/// Returns a `Result` with an error if the embedding process fails.
+/// The chunks of both versions get their `chunk_index` in their metadata. Models returning
+/// multi-vector embeddings are compared with their late interaction score, which the
+/// threshold must account for.
 ///
 /// # Example
 ///
-/// ```rust
-/// use embed_anything::embed_image_directory;
-/// use std::path::PathBuf;
-/// use std::sync::Arc;
-/// use embed_anything::embeddings::embed::Embedder;
+/// ```rust,no_run
+/// use embed_anything::{embed_diff, embeddings::embed::Embedder};
 ///
-/// async fn embed_images() {
-///     let directory = PathBuf::from("/path/to/directory");
-///     let embedder = Arc::new(Embedder::from_pretrained_hf("clip", "openai/clip-vit-base-patch16", None).unwrap());
-///     let embeddings = embed_image_directory(directory, &embedder, None, None).await.unwrap();
+/// async fn update(embedder: &Embedder, old: &str, new: &str) {
+///     let diff = embed_diff(old, new, embedder, None).await.unwrap();
+///     for chunk in diff.removed.iter().chain(diff.modified.iter().map(|m| &m.old)) {
+///         println!("delete {:?}", chunk.metadata.as_ref().unwrap()["chunk_index"]);
+///     }
+///     for chunk in diff.added.iter().chain(diff.modified.iter().map(|m| &m.new)) {
+///         println!("insert {:?}", chunk.text);
+///     }
 /// }
 /// ```
-/// This will output the embeddings of the images in the specified directory using the specified embedding model.
-///
-pub async fn embed_image_directory<T: EmbedImage + Send + Sync + 'static, F>(
-    directory: PathBuf,
-    embedding_model: &Arc<T>,
-    config: Option<&ImageEmbedConfig>,
-    adapter: Option<F>,
-) -> Result<Option<Vec<EmbedData>>>
-where
-    F: Fn(Vec<EmbedData>),
-{
-    let mut file_parser = FileParser::new();
-    file_parser.get_image_paths(&directory).unwrap();
-
-    let buffer_size = config
-        .unwrap_or(&ImageEmbedConfig::default())
-        .buffer_size
-        .unwrap_or(100);
-
-    let (tx, mut rx) = mpsc::unbounded_channel();
-    let (collector_tx, mut collector_rx) = mpsc::unbounded_channel();
-
-    let embedder = embedding_model.clone();
-
-    let pb = indicatif::ProgressBar::new(file_parser.files.len() as u64);
-    pb.set_style(
-        indicatif::ProgressStyle::with_template(
-            "{spinner:.green} [{elapsed_precise}] [{bar:40.cyan/blue}] {pos}/{len} ({eta})",
-        )
-        .unwrap(),
+pub async fn embed_diff(
+    old_text: &str,
+    new_text: &str,
+    embedder: &Embedder,
+    config: Option<&TextEmbedConfig>,
+) -> Result<DocumentDiff> {
+    let binding = TextEmbedConfig::default();
+    let config = config.unwrap_or(&binding);
+    let splitting_strategy = config
+        .splitting_strategy
+        .unwrap_or(SplittingStrategy::Sentence);
+    let textloader = TextLoader::new(
+        config.chunk_size.unwrap_or(256),
+        config.overlap_ratio.unwrap_or(0.0),
+    )
+    .with_language(config.language.as_deref())
+    .with_sentence_backend(config.sentence_splitter.clone())
+    .with_min_chunk_size(
+        config.min_chunk_size,
+        config.min_chunk_policy.unwrap_or_default(),
     );
 
-    let processing_task = tokio::spawn({
-        async move {
-            // make image buffer
-            let mut image_buffer = Vec::with_capacity(buffer_size);
-            let mut files_processed: std::collections::HashSet<String> =
-                std::collections::HashSet::new();
-            while let Some(image) = rx.recv().await {
-                image_buffer.push(image);
-
-                if image_buffer.len() == buffer_size {
-                    // Ensure embedder is mutable and not wrapped in Arc
-                    match process_images(&image_buffer, embedder.clone()).await {
-                        Ok(embeddings) => {
-                            let files = embeddings
-                                .iter()
-                                .cloned()
-                                .map(|e| e.metadata.unwrap().get("file_name").unwrap().to_string())
-                                .collect::<Vec<_>>();
-
-                            let unique_files = files.into_iter().unique().collect::<Vec<_>>();
-                            let old_len = files_processed.len() as u64;
-                            files_processed.extend(unique_files);
-                            let new_len = files_processed.len() as u64;
-
-                            pb.inc(new_len - old_len);
-
-                            if let Err(e) = collector_tx.send(embeddings) {
-                                eprintln!("Error sending embeddings to collector: {:?}", e);
-                            }
-                        }
-                        Err(e) => eprintln!("Error processing images: {:?}", e),
-                    }
-
-                    image_buffer.clear();
-                }
-            }
-
-            // Process any remaining images
-            if !image_buffer.is_empty() {
-                match process_images(&image_buffer, embedder).await {
-                    Ok(embeddings) => {
-                        let files = embeddings
-                            .iter()
-                            .cloned()
-                            .map(|e| e.metadata.unwrap().get("file_name").unwrap().to_string())
-                            .collect::<Vec<_>>();
-                        let unique_files = files.into_iter().unique().collect::<Vec<_>>();
-                        let old_len = files_processed.len() as u64;
-                        files_processed.extend(unique_files);
-                        let new_len = files_processed.len() as u64;
-
-                        pb.inc(new_len - old_len);
-
-                        if let Err(e) = collector_tx.send(embeddings) {
-                            eprintln!("Error sending embeddings to collector: {:?}", e);
-                        }
-                    }
-                    Err(e) => eprintln!("Error processing images: {:?}", e),
-                }
-            }
-        }
-    });
-
-    file_parser.files.par_iter().for_each(|image| {
-        if let Err(e) = tx.send(image.clone()) {
-            eprintln!("Error sending image: {:?}", e);
-        }
-    });
-
-    drop(tx);
-
-    let mut all_embeddings = Vec::new();
-    while let Some(embeddings) = collector_rx.recv().await {
-        if let Some(adapter) = &adapter {
-            adapter(embeddings.to_vec());
-        } else {
-            all_embeddings.extend(embeddings.to_vec());
+    let mut versions = Vec::with_capacity(2);
+    for text in [old_text, new_text] {
+        let text = config.redact_text(text.to_string());
+        let chunks: Vec<String> = match splitting_strategy {
+            SplittingStrategy::Code { language } => textloader
+                .split_code(&text, language)?
+                .into_iter()
+                .map(|chunk| chunk.text)
+                .collect(),
+            _ => textloader
+                .split_into_chunks(&text, splitting_strategy, config.semantic_encoder.clone())
+                .unwrap_or_default(),
+        };
+        if chunks.is_empty() {
+            versions.push(Vec::new());
+            continue;
         }
+        report_truncation(embedder.tokenizer(), &chunks, None, config)?;
+        let encodings = config
+            .embed_document_batch(embedder, &chunks, config.batch_size)
+            .await?;
+        let mut embeddings = encodings
+            .into_iter()
+            .zip(chunks)
+            .enumerate()
+            .map(|(index, (encoding, chunk))| {
+                let metadata = HashMap::from([("chunk_index".to_string(), index.to_string())]);
+                EmbedData::new(encoding, Some(chunk), Some(metadata))
+            })
+            .collect::<Vec<_>>();
+        store_embedded_text(&mut embeddings, embedder.prefixes().1, config);
+        mark_normalized(&mut embeddings, embedder.is_normalized());
+        mark_embedding_model(&mut embeddings, &embedder.model_id());
+        config.finish_embeddings(&mut embeddings);
+        versions.push(embeddings);
     }
 
-    // Wait for the spawned task to complete
-    processing_task.await.unwrap();
+    let new = versions.pop().unwrap_or_default();
+    let old = versions.pop().unwrap_or_default();
+    diff_chunks(
+        old,
+        new,
+        config.diff_threshold.unwrap_or(DEFAULT_DIFF_THRESHOLD),
+    )
+}
 
-    if adapter.is_some() {
-        Ok(None)
+/// A chunk produced by [chunk_preview].
+#[derive(Debug, Clone)]
+pub struct ChunkPreview {
+    pub text: String,
+    /// Size of the chunk in the tokens of the chunking tokenizer, which is close to but not
+    /// always equal to the count of the embedding model.
+    pub token_count: usize,
+    pub file: String,
+    /// Position of the chunk within its file.
+    pub chunk_index: usize,
+}
+
+/// Extracts and chunks a file, or every supported file of a directory, without embedding
+/// anything. The chunks are the ones [embed_file] and [embed_directory_stream] would embed
+/// with the same config, so this can be used to tune `chunk_size` or estimate the token cost
+/// of a cloud model before running the real job.
+///
+/// Note that [SplittingStrategy::Semantic] still runs the semantic encoder to find the
+/// boundaries. Directories are always split by sentence or code, like
+/// [embed_directory_stream].
+///
+/// # Example
+///
+/// ```rust
+/// use embed_anything::chunk_preview;
+///
+/// async fn preview() {
+///     let chunks = chunk_preview("path/to/file.pdf", None).await.unwrap();
+///     let tokens: usize = chunks.iter().map(|chunk| chunk.token_count).sum();
+///     println!("{} chunks, {} tokens", chunks.len(), tokens);
+/// }
+/// ```
+pub async fn chunk_preview<T: AsRef<std::path::Path>>(
+    path: T,
+    config: Option<&TextEmbedConfig>,
+) -> Result<Vec<ChunkPreview>> {
+    let binding = TextEmbedConfig::default();
+    let config = config.unwrap_or(&binding);
+    let chunk_size = config.chunk_size.unwrap_or(256);
+    let overlap_ratio = config.overlap_ratio.unwrap_or(0.0);
+    let textloader = TextLoader::new(chunk_size, overlap_ratio)
+        .with_language(config.language.as_deref())
+        .with_sentence_backend(config.sentence_splitter.clone())
+        .with_min_chunk_size(
+            config.min_chunk_size,
+            config.min_chunk_policy.unwrap_or_default(),
+        );
+
+    let path = path.as_ref();
+    let is_directory = path.is_dir();
+    let files = if is_directory {
+        FileParser::new()
+            .with_file_filter(config.file_filter.clone())
+            .get_text_files(&path.to_path_buf(), None)?
     } else {
-        Ok(Some(all_embeddings))
+        vec![path.to_string_lossy().to_string()]
+    };
+
+    let mut previews = Vec::new();
+    for file in files {
+        let text = match config.extract_text(&file) {
+            Ok((text, _)) => text,
+            // A single file reports its error, directories skip unreadable files.
+            Err(e) if !is_directory => return Err(e),
+            Err(_) => continue,
+        };
+        let chunks = match config.splitting_strategy {
+            Some(SplittingStrategy::Code { language }) => {
+                let language = if is_directory {
+                    std::path::Path::new(&file)
+                        .extension()
+                        .and_then(|extension| extension.to_str())
+                        .and_then(CodeLanguage::from_extension)
+                        .unwrap_or(language)
+                } else {
+                    language
+                };
+                textloader
+                    .split_code(&text, language)?
+                    .into_iter()
+                    .map(|chunk| chunk.text)
+                    .collect()
+            }
+            _ if is_directory => textloader
+                .split_into_chunks(&text, SplittingStrategy::Sentence, None)
+                .unwrap_or_else(|| vec![text.clone()])
+                .into_iter()
+                .filter(|chunk| !chunk.trim().is_empty())
+                .collect(),
+            splitting_strategy => textloader
+                .split_into_chunks(
+                    &text,
+                    splitting_strategy.unwrap_or(SplittingStrategy::Sentence),
+                    config.semantic_encoder.clone(),
+                )
+                .unwrap_or_default(),
+        };
+        previews.extend(
+            chunks
+                .into_iter()
+                .enumerate()
+                .map(|(chunk_index, text)| ChunkPreview {
+                    token_count: textloader.token_count(&text),
+                    text,
+                    file: file.clone(),
+                    chunk_index,
+                }),
+        );
     }
+    Ok(previews)
 }
 
-async fn process_images<E: EmbedImage>(
-    image_buffer: &[String],
-    embedder: Arc<E>,
-) -> Result<Arc<Vec<EmbedData>>> {
-    let embeddings = embedder.embed_image_batch(image_buffer)?;
-    Ok(Arc::new(embeddings))
+/// Extracts the text of a file without chunking or embedding it, for use in other pipelines.
+/// Every format supported by [embed_file] can be read. PDF files keep their page boundaries,
+/// see [ExtractedDocument] for what else is returned.
+///
+/// Use [TextLoader::extract_document] to extract scanned PDF files with OCR.
+///
+/// # Example
+///
+/// ```rust,no_run
+/// use embed_anything::extract_text;
+///
+/// let document = extract_text("path/to/file.pdf").unwrap();
+/// println!("{} ({} pages)", document.file_type, document.pages.len());
+/// for page in &document.pages {
+///     let text = &document.text[page.start..page.end];
+///     println!("page {}: {} characters", page.page_number, text.chars().count());
+/// }
+/// ```
+pub fn extract_text<T: AsRef<std::path::Path>>(path: T) -> Result<ExtractedDocument> {
+    TextLoader::extract_document(&path, false, None)
 }
 
-/// Embeds text from files in a directory using the specified embedding model.
+/// The extensions of the files that [embed_file] and [extract_text] can read, without the
+/// leading dot, e.g. to filter a file picker. Formats added with
+/// [register_extractor](file_processor::extractor::register_extractor) are not listed, as
+/// extractors may match files by more than their extension.
+pub fn supported_extensions() -> Vec<&'static str> {
+    [
+        PdfExtractor::EXTENSIONS,
+        TextExtractor::EXTENSIONS,
+        HtmlExtractor::EXTENSIONS,
+        CodeLanguage::EXTENSIONS,
+    ]
+    .concat()
+}
+
+/// Embeddings of a webpage using the specified embedding model.
 ///
 /// # Arguments
 ///
-/// * `directory` - A `PathBuf` representing the directory containing the files to embed.
-/// * `embedder` - A reference to the embedding model to use.
-/// * `extensions` - An optional vector of strings representing the file extensions to consider for embedding. If `None`, all files in the directory will be considered.
-/// * `config` - An optional `TextEmbedConfig` object specifying the configuration for the embedding model.
-/// * `adapter` - An optional callback function to handle the embeddings.
+/// * `embedder` - The embedding model to use. Supported options are "OpenAI", "Jina", and "Bert".
+/// * `webpage` - The webpage to embed.
 ///
 /// # Returns
-/// An `Option` containing a vector of `EmbedData` objects representing the embeddings of the files, or `None` if an adapter is used.
+///
+/// The embeddings of the webpage.
 ///
 /// # Errors
-/// Returns a `Result` with an error if the embedding process fails.
+///
+/// Returns an error if the specified embedding model is invalid.
 ///
 /// # Example
 ///
-/// ```rust
-/// use embed_anything::embed_directory_stream;
-/// use std::path::PathBuf;
-/// use std::sync::Arc;
-/// use embed_anything::config::TextEmbedConfig;
-/// use embed_anything::embeddings::embed::Embedder;
+/// ```
+/// use embed_anything::embed_webpage;
+/// use embed_anything::embeddings::embed::{Embedder, TextEmbedder};
+/// use embed_anything::embeddings::local::jina::JinaEmbedder;
 ///
-/// async fn generate_embeddings() {
-///     let directory = PathBuf::from("/path/to/directory");
-///     let embedder = Arc::new(Embedder::from_pretrained_hf("clip", "openai/clip-vit-base-patch16", None).unwrap());
-///     let config = Some(&TextEmbedConfig::default());
-///     let extensions = Some(vec!["txt".to_string(), "pdf".to_string()]);
-///     let embeddings = embed_directory_stream(directory, &embedder, extensions, config, None).await.unwrap();
-/// }
+/// let embedder = Embedder::Text(TextEmbedder::Jina(Box::new(JinaEmbedder::default())));
+/// let embeddings = embed_webpage("https://en.wikipedia.org/wiki/Embedding".into(), &embedder, None, None).unwrap();
 /// ```
-/// This will output the embeddings of the files in the specified directory using the specified embedding model.
-pub async fn embed_directory_stream<F>(
-    directory: PathBuf,
-    embedder: &Arc<Embedder>,
-    extensions: Option<Vec<String>>,
+pub async fn embed_webpage<F>(
+    url: String,
+    embedder: &Embedder,
     config: Option<&TextEmbedConfig>,
+    // Callback function
     adapter: Option<F>,
 ) -> Result<Option<Vec<EmbedData>>>
 where
-    F: Fn(Vec<EmbedData>),
+    F: Adapter,
 {
-    println!("Embedding directory: {:?}", directory);
-
     let binding = TextEmbedConfig::default();
     let config = config.unwrap_or(&binding);
-    let chunk_size = config.chunk_size.unwrap_or(binding.chunk_size.unwrap());
-    let buffer_size = config.buffer_size.unwrap_or(binding.buffer_size.unwrap());
-    let batch_size = config.batch_size;
-    let use_ocr = config.use_ocr.unwrap_or(false);
-    let tesseract_path = config.tesseract_path.as_deref();
-    let overlap_ratio = config.overlap_ratio.unwrap_or(0.0);
-    let mut file_parser = FileParser::new();
-    file_parser.get_text_files(&directory, extensions)?;
-    let files = file_parser.files.clone();
-    let (tx, mut rx) = mpsc::unbounded_channel();
-    let (collector_tx, mut collector_rx) = mpsc::unbounded_channel();
 
-    let embedder = embedder.clone();
-    let pb = indicatif::ProgressBar::new(files.len() as u64);
-    pb.set_style(
-        indicatif::ProgressStyle::with_template(
-            "{spinner:.green} [{elapsed_precise}] [{bar:40.cyan/blue}] {pos}/{len} ({eta})",
-        )
-        .unwrap(),
-    );
+    let crawl_config = config.crawl_config.clone().unwrap_or_default();
+    let readability = config.readability.unwrap_or(false);
+    // Fetching blocks on the request, retries and crawl delay, so it runs off the runtime.
+    let webpage = tokio::task::spawn_blocking(move || {
+        file_processor::website_processor::WebsiteProcessor::new()
+            .with_crawl_config(crawl_config)
+            .with_readability(readability)
+            .process_website(url.as_ref())
+    })
+    .await??;
+
+    // if let Embedder::Clip(_) = embedder {
+    //     return Err(anyhow!("Clip model does not support webpage embedding"));
+    // }
+
+    let chunk_size = config.chunk_size.unwrap_or(256);
+    let overlap_ratio = config.overlap_ratio.unwrap_or(0.0);
+    let batch_size = config.batch_size;
+
+    let mut embeddings = webpage
+        .embed_webpage(embedder, chunk_size, overlap_ratio, batch_size)
+        .await?;
+    config.finish_embeddings(&mut embeddings);
+
+    // Send embeddings to vector database
+    if let Some(adapter) = adapter {
+        adapter.upsert(embeddings);
+        adapter.flush();
+        Ok(None)
+    } else {
+        Ok(Some(embeddings))
+    }
+}
+
+/// Maximum number of webpages fetched at the same time by [embed_urls].
+const MAX_CONCURRENT_FETCHES: usize = 8;
+
+/// Embeds a list of webpages using the specified embedding model.
+///
+/// Pages are fetched concurrently, at most [MAX_CONCURRENT_FETCHES] at a time, following the
+/// [CrawlConfig](config::CrawlConfig) of `config`, and embedded one after the other. Every
+/// chunk carries the page it came from in the `url` metadata. Pages that fail to download or
+/// are disallowed by robots.txt are skipped and logged, use [try_embed_urls] to get them.
+///
+/// # Arguments
+///
+/// * `urls` - The webpages to embed.
+/// * `embedder` - The embedding model to use.
+/// * `config` - The configuration for the embedding.
+/// * `adapter` - Callback invoked with the embeddings of each page.
+///
+/// # Returns
+///
+/// The embeddings of all pages, or None when an adapter is used.
+pub async fn embed_urls<F>(
+    urls: Vec<String>,
+    embedder: &Embedder,
+    config: Option<&TextEmbedConfig>,
+    // Callback function
+    adapter: Option<F>,
+) -> Result<Option<Vec<EmbedData>>>
+where
+    F: Adapter,
+{
+    let (embeddings, failures) = try_embed_urls(urls, embedder, config, adapter).await?;
+    for (url, e) in failures {
+        tracing::warn!("Skipping {}: {:?}", url, e);
+    }
+    Ok(embeddings)
+}
+
+/// Embeds webpages like [embed_urls], and also returns the pages that could not be fetched,
+/// with their error. Fetch errors can be told apart by downcasting them to
+/// [FetchError](file_processor::website_processor::FetchError). Failing pages don't stop the
+/// others, only failing to embed a page is returned as an error.
+///
+/// # Example
+///
+/// ```rust,no_run
+/// use embed_anything::{embeddings::embed::{EmbedData, Embedder}, try_embed_urls};
+///
+/// async fn embed(embedder: &Embedder, urls: Vec<String>) {
+///     let (embeddings, failures) = try_embed_urls(urls, embedder, None, None::<fn(Vec<EmbedData>)>)
+///         .await
+///         .unwrap();
+///     for (url, e) in failures {
+///         println!("{} failed: {}", url, e);
+///     }
+/// }
+/// ```
+#[allow(clippy::type_complexity)]
+pub async fn try_embed_urls<F>(
+    urls: Vec<String>,
+    embedder: &Embedder,
+    config: Option<&TextEmbedConfig>,
+    adapter: Option<F>,
+) -> Result<(Option<Vec<EmbedData>>, Vec<(String, anyhow::Error)>)>
+where
+    F: Adapter,
+{
+    let binding = TextEmbedConfig::default();
+    let config = config.unwrap_or(&binding);
+    let chunk_size = config.chunk_size.unwrap_or(256);
+    let overlap_ratio = config.overlap_ratio.unwrap_or(0.0);
+    let batch_size = config.batch_size;
+
+    let crawl_config = config.crawl_config.clone().unwrap_or_default();
+    let readability = config.readability.unwrap_or(false);
+    // Fetching blocks on the requests, retries and crawl delays, so it runs off the runtime.
+    let (urls, results) = tokio::task::spawn_blocking(move || -> Result<_> {
+        // The processor is shared by the fetching threads so the crawl delay applies across
+        // them.
+        let website_processor = file_processor::website_processor::WebsiteProcessor::new()
+            .with_crawl_config(crawl_config)
+            .with_readability(readability);
+        let pool = rayon::ThreadPoolBuilder::new()
+            .num_threads(MAX_CONCURRENT_FETCHES.min(urls.len()).max(1))
+            .build()?;
+        let results = pool.install(|| {
+            urls.par_iter()
+                .map(|url| website_processor.process_website(url))
+                .collect::<Vec<_>>()
+        });
+        Ok((urls, results))
+    })
+    .await??;
+    let mut webpages = Vec::with_capacity(results.len());
+    let mut failures = Vec::new();
+    for (url, result) in urls.into_iter().zip(results) {
+        match result {
+            Ok(webpage) => webpages.push(webpage),
+            Err(e) => failures.push((url, e)),
+        }
+    }
+
+    let mut all_embeddings = Vec::new();
+    for webpage in webpages {
+        let mut embeddings = webpage
+            .embed_webpage(embedder, chunk_size, overlap_ratio, batch_size)
+            .await?;
+        config.finish_embeddings(&mut embeddings);
+        if let Some(adapter) = &adapter {
+            adapter.upsert(embeddings);
+        } else {
+            all_embeddings.extend(embeddings);
+        }
+    }
+
+    if let Some(adapter) = &adapter {
+        adapter.flush();
+        Ok((None, failures))
+    } else {
+        Ok((Some(all_embeddings), failures))
+    }
+}
+
+/// Embeds an HTML document using the specified embedding model.
+///
+/// # Arguments
+///
+/// * `file_name` - The path of the HTML document to embed.
+/// * `origin` - The original URL of the document. If specified, links can be resolved and metadata points to the site.
+/// * `embedder` - The embedding model to use. Supported options are "OpenAI", "Jina", and "Bert".
+///
+/// # Returns
+///
+/// The embeddings of the HTML document.
+///
+/// # Errors
+///
+/// Returns an error if the specified embedding model is invalid.
+///
+/// # Example
+///
+/// ```
+/// use embed_anything::embed_html;
+/// use embed_anything::embeddings::embed::{Embedder, TextEmbedder};
+/// use embed_anything::embeddings::local::jina::JinaEmbedder;
+///
+/// async fn get_embeddings() {
+///     let embeddings = embed_html(
+///         "test_files/test.html",
+///         Some("https://example.com/"),
+///         &Embedder::from_pretrained_hf("JINA", "jinaai/jina-embeddings-v2-small-en", None).unwrap(),
+///         None,
+///         None,
+///     ).await.unwrap();
+/// }
+/// ```
+pub async fn embed_html(
+    file_name: impl AsRef<std::path::Path>,
+    origin: Option<impl Into<String>>,
+    embedder: &Embedder,
+    config: Option<&TextEmbedConfig>,
+    // Callback function
+    adapter: Option<Box<dyn FnOnce(Vec<EmbedData>)>>,
+) -> Result<Option<Vec<EmbedData>>> {
+    let binding = TextEmbedConfig::default();
+    let config = config.unwrap_or(&binding);
+
+    let html_processor = file_processor::html_processor::HtmlProcessor::new()
+        .with_readability(config.readability.unwrap_or(false));
+    let html = html_processor.process_html_file(file_name.as_ref(), origin)?;
+
+    let chunk_size = config.chunk_size.unwrap_or(256);
+    let overlap_ratio = config.overlap_ratio.unwrap_or(0.0);
+    let batch_size = config.batch_size;
+
+    let mut embeddings = html
+        .embed_webpage(embedder, chunk_size, overlap_ratio, batch_size)
+        .await?;
+    config.finish_embeddings(&mut embeddings);
+
+    // Send embeddings to vector database
+    if let Some(adapter) = adapter {
+        adapter(embeddings);
+        Ok(None)
+    } else {
+        Ok(Some(embeddings))
+    }
+}
+
+/// Unpacks the files of `archive` embedded with [TextEmbedConfig::extract_archives]: those of
+/// a supported type that pass the file filter, and have one of `extensions` when given.
+fn extract_archive(
+    archive: &std::path::Path,
+    config: &TextEmbedConfig,
+    extensions: Option<&[String]>,
+) -> Result<ExtractedArchive> {
+    let max_bytes = config
+        .max_archive_bytes
+        .unwrap_or(DEFAULT_MAX_ARCHIVE_BYTES);
+    ExtractedArchive::extract(archive, max_bytes, |meta| {
+        let extension = meta
+            .path
+            .extension()
+            .map(|extension| extension.to_string_lossy().to_lowercase());
+        let requested = match extensions {
+            Some(extensions) => extensions
+                .iter()
+                .any(|requested| Some(requested.to_lowercase()) == extension),
+            None => true,
+        };
+        requested
+            && TextLoader::is_supported(&meta.path)
+            && config
+                .file_filter
+                .as_ref()
+                .map_or(true, |filter| filter(meta))
+    })
+}
+
+/// Embeds the files inside an archive, tagging their chunks with the metadata of
+/// [ExtractedArchive::metadata]. Inner files that fail to load are skipped and logged, like
+/// the files of a directory.
+async fn emb_archive<F>(
+    archive: &std::path::Path,
+    embedder: &Embedder,
+    config: &TextEmbedConfig,
+    adapter: Option<&F>,
+) -> Result<Option<Vec<EmbedData>>>
+where
+    F: Adapter,
+{
+    let Embedder::Text(embedder) = embedder else {
+        return Err(anyhow::anyhow!(
+            "Archives can only be embedded with a text embedder"
+        ));
+    };
+    let extracted = extract_archive(archive, config, None)?;
+    let mut embeddings = Vec::new();
+    for entry in extracted.entries() {
+        let adapter = None::<&fn(Vec<EmbedData>)>;
+        let entry_embeddings = match emb_text(&entry.file, embedder, Some(config), adapter).await {
+            Ok(entry_embeddings) => entry_embeddings.unwrap_or_default(),
+            Err(e) => {
+                tracing::warn!("Skipping {} in {:?}: {:?}", entry.path, archive, e);
+                continue;
+            }
+        };
+        let archive_metadata = extracted.metadata(entry);
+        for mut embedding in entry_embeddings {
+            embedding
+                .metadata
+                .get_or_insert_with(HashMap::new)
+                .extend(archive_metadata.clone());
+            embeddings.push(embedding);
+        }
+    }
+
+    if let Some(adapter) = adapter {
+        adapter.upsert(embeddings);
+        Ok(None)
+    } else {
+        Ok(Some(embeddings))
+    }
+}
+
+#[allow(clippy::too_many_arguments)]
+async fn emb_text<T: AsRef<std::path::Path>, F>(
+    file: T,
+    embedding_model: &TextEmbedder,
+    config: Option<&TextEmbedConfig>,
+    adapter: Option<&F>,
+) -> Result<Option<Vec<EmbedData>>>
+where
+    F: Adapter,
+{
+
+    let binding = TextEmbedConfig::default();
+    let config = config.unwrap_or(&binding);
+    let is_pdf = file
+        .as_ref()
+        .extension()
+        .is_some_and(|extension| extension == "pdf");
+    if config.streaming_extraction.unwrap_or(false) && is_pdf && !config.use_ocr.unwrap_or(false) {
+        return emb_pdf_pages(file, embedding_model, config, adapter).await;
+    }
+    let batch_size = config.batch_size;
+    let (chunks, chunk_metadata, mut metadata) = chunk_file(&file, config)?;
+    let file_path = prepended_path(file.as_ref(), None, config);
+    if let Some(file_path) = &file_path {
+        metadata
+            .get_or_insert_with(HashMap::new)
+            .insert(FILE_PATH_KEY.to_string(), file_path.clone());
+    }
+    let embedded = with_file_path(&chunks, 0, file_path.as_deref(), config);
+    report_truncation(
+        embedding_model.tokenizer(),
+        &embedded,
+        Some(&file.as_ref().to_string_lossy()),
+        config,
+    )?;
+
+    let batch_size = if config.auto_batch_size.unwrap_or(false) {
+        let max_batch_size = batch_size.unwrap_or(MAX_AUTO_BATCH_SIZE);
+        let tuned = embedding_model
+            .tune_batch_size(&embedded, max_batch_size)
+            .await;
+        Some(tuned)
+    } else {
+        batch_size
+    };
+    let encodings = config
+        .embed_document_batch(embedding_model, &embedded, batch_size)
+        .await?;
+    let mut embeddings = get_text_metadata(&Rc::new(encodings), &chunks, &metadata)?;
+    if let Some(validator) = dimension_validator(embedding_model.metadata().dimension, config) {
+        validator.check(&embeddings)?;
+    }
+    let document = match config.document_vector {
+        Some(true) => document_embedding(&embeddings, &metadata),
+        _ => None,
+    };
+    store_embedded_text(&mut embeddings, embedding_model.prefixes().1, config);
+    mark_normalized(&mut embeddings, embedding_model.is_normalized());
+    merge_chunk_metadata(&mut embeddings, chunk_metadata);
+    if let Some(document) = document {
+        mark_granularity(&mut embeddings, "chunk");
+        embeddings.push(document);
+    }
+    mark_embedding_model(&mut embeddings, &embedding_model.metadata().model_id);
+    config.finish_embeddings(&mut embeddings);
+
+    if let Some(adapter) = adapter {
+        adapter.upsert(embeddings);
+        Ok(None)
+    } else {
+        Ok(Some(embeddings))
+    }
+}
+
+/// Embeds a PDF file one page at a time for [TextEmbedConfig::streaming_extraction]. Only the
+/// current page and up to `buffer_size` chunks are held in memory; each batch is handed to the
+/// adapter as soon as it is embedded. With a [TextEmbedConfig::table_mode], each chunk tells
+/// whether its page holds a table.
+async fn emb_pdf_pages<T: AsRef<std::path::Path>, F>(
+    file: T,
+    embedding_model: &TextEmbedder,
+    config: &TextEmbedConfig,
+    adapter: Option<&F>,
+) -> Result<Option<Vec<EmbedData>>>
+where
+    F: Adapter,
+{
+    TextLoader::check_document_size(&file, config.max_document_bytes)?;
+    let mut pages = PdfProcessor::pages(&file)
+        .map_err(|e| FileLoadingError::ExtractionFailed {
+            path: file.as_ref().to_string_lossy().to_string(),
+            source: e,
+        })?
+        .with_table_mode(config.table_mode);
+    let mut metadata =
+        TextLoader::get_metadata_with_sidecar(&file, config.sidecar_metadata.unwrap_or(false))?;
+    metadata.insert("file_type".to_string(), "pdf".to_string());
+    metadata.insert("page_count".to_string(), pages.page_count().to_string());
+    let (first_page, last_page) = match config.page_range {
+        Some((first, last)) => {
+            pages.select(first, last)?;
+            metadata.insert("page_range".to_string(), format!("{}-{}", first, last));
+            (first, last)
+        }
+        None => (1, pages.page_count()),
+    };
+    let max_pages = config.max_pages.unwrap_or(usize::MAX);
+    if last_page + 1 - first_page > max_pages {
+        metadata.insert("truncated".to_string(), "true".to_string());
+    }
+    let file_path = prepended_path(file.as_ref(), None, config);
+    if let Some(file_path) = &file_path {
+        metadata.insert(FILE_PATH_KEY.to_string(), file_path.clone());
+    }
+
+    let buffer_size = config.buffer_size.unwrap_or(100).max(1);
+    let validator = dimension_validator(embedding_model.metadata().dimension, config);
+    let splitting_strategy = config
+        .splitting_strategy
+        .unwrap_or(SplittingStrategy::Sentence);
+    let textloader = TextLoader::new(
+        config.chunk_size.unwrap_or(256),
+        config.overlap_ratio.unwrap_or(0.0),
+    )
+    .with_language(config.language.as_deref())
+    .with_sentence_backend(config.sentence_splitter.clone())
+    .with_min_chunk_size(
+        config.min_chunk_size,
+        config.min_chunk_policy.unwrap_or_default(),
+    );
+
+    let mut chunk_buffer = Vec::with_capacity(buffer_size);
+    let mut chunk_metadata = Vec::with_capacity(buffer_size);
+    let mut collected = Vec::new();
+    let mut chunk_count = 0;
+    // Spans count from the start of the embedded pages joined by a blank line, as when the
+    // text is extracted whole.
+    let mut page_offset = 0;
+    for page_number in (first_page..=last_page).take(max_pages) {
+        let Some(page) = pages.next() else {
+            break;
+        };
+        let page = config.clean_text(&page?);
+        let page = page.trim();
+        let mut page_metadata =
+            HashMap::from([("page_number".to_string(), page_number.to_string())]);
+        if config.table_mode.is_some() {
+            page_metadata.insert(
+                CONTAINS_TABLE_KEY.to_string(),
+                pages.contains_table().to_string(),
+            );
+        }
+        let chunks = textloader
+            .split_into_chunks(page, splitting_strategy, config.semantic_encoder.clone())
+            .unwrap_or_default();
+        let spans = chunk_spans(page, &chunks);
+        for (chunk, span) in chunks.into_iter().zip(spans) {
+            let mut extra = page_metadata.clone();
+            if let Some((start, end)) = span {
+                let (start, end) = (page_offset + start, page_offset + end);
+                extra.insert(START_CHAR_KEY.to_string(), start.to_string());
+                extra.insert(END_CHAR_KEY.to_string(), end.to_string());
+            }
+            chunk_buffer.push(chunk);
+            chunk_metadata.push(extra);
+            if chunk_buffer.len() == buffer_size {
+                let embedded =
+                    with_file_path(&chunk_buffer, chunk_count, file_path.as_deref(), config)
+                        .into_owned();
+                chunk_count += chunk_buffer.len();
+                let embeddings = embed_page_chunks(
+                    &mut chunk_buffer,
+                    &embedded,
+                    &mut chunk_metadata,
+                    &metadata,
+                    embedding_model,
+                    config,
+                )
+                .await?;
+                if let Some(validator) = &validator {
+                    validator.check(&embeddings)?;
+                }
+                match &adapter {
+                    Some(adapter) => adapter.upsert(embeddings),
+                    None => collected.extend(embeddings),
+                }
+            }
+        }
+        page_offset += page.chars().count() + 2;
+    }
+    if !chunk_buffer.is_empty() {
+        let embedded =
+            with_file_path(&chunk_buffer, chunk_count, file_path.as_deref(), config).into_owned();
+        chunk_count += chunk_buffer.len();
+        let embeddings = embed_page_chunks(
+            &mut chunk_buffer,
+            &embedded,
+            &mut chunk_metadata,
+            &metadata,
+            embedding_model,
+            config,
+        )
+        .await?;
+        if let Some(validator) = &validator {
+            validator.check(&embeddings)?;
+        }
+        match &adapter {
+            Some(adapter) => adapter.upsert(embeddings),
+            None => collected.extend(embeddings),
+        }
+    }
+    if chunk_count == 0 {
+        return Err(
+            FileLoadingError::EmptyDocument(file.as_ref().to_string_lossy().to_string()).into(),
+        );
+    }
+
+    if adapter.is_some() {
+        Ok(None)
+    } else {
+        Ok(Some(collected))
+    }
+}
+
+/// Embeds and drains the chunks buffered by [emb_pdf_pages] and their metadata, such as the
+/// page they come from. `embedded` are the texts the model embeds for the chunks, see
+/// [with_file_path].
+async fn embed_page_chunks(
+    chunks: &mut Vec<String>,
+    embedded: &[String],
+    chunk_metadata: &mut Vec<HashMap<String, String>>,
+    metadata: &HashMap<String, String>,
+    embedding_model: &TextEmbedder,
+    config: &TextEmbedConfig,
+) -> Result<Vec<EmbedData>> {
+    let file = metadata.get("file_name").map(String::as_str);
+    report_truncation(embedding_model.tokenizer(), embedded, file, config)?;
+    let encodings = config
+        .embed_document_batch(embedding_model, embedded, config.batch_size)
+        .await?;
+    let mut embeddings = get_text_metadata(&Rc::new(encodings), chunks, &Some(metadata.clone()))?;
+    store_embedded_text(&mut embeddings, embedding_model.prefixes().1, config);
+    if config.detect_language.unwrap_or(false) {
+        add_chunk_languages(chunks, chunk_metadata);
+    }
+    merge_chunk_metadata(&mut embeddings, chunk_metadata.drain(..).collect());
+    chunks.clear();
+    mark_normalized(&mut embeddings, embedding_model.is_normalized());
+    mark_embedding_model(&mut embeddings, &embedding_model.metadata().model_id);
+    config.finish_embeddings(&mut embeddings);
+    Ok(embeddings)
+}
+
+/// Extracts and chunks a file for [emb_text] and [embed_file_multi]. Returns the chunks, the
+/// per chunk metadata, which holds the span of the chunk and the symbol of code chunks, and
+/// the file metadata.
+#[allow(clippy::type_complexity)]
+fn chunk_file<T: AsRef<std::path::Path>>(
+    file: T,
+    config: &TextEmbedConfig,
+) -> Result<(
+    Vec<String>,
+    Vec<HashMap<String, String>>,
+    Option<HashMap<String, String>>,
+)> {
+    let chunk_size = config.chunk_size.unwrap_or(256);
+    let overlap_ratio = config.overlap_ratio.unwrap_or(0.0);
+    let splitting_strategy = config
+        .splitting_strategy
+        .unwrap_or(SplittingStrategy::Sentence);
+    let semantic_encoder = config.semantic_encoder.clone();
+    TextLoader::check_document_size(&file, config.max_document_bytes)?;
+    let (text, extraction_metadata) = config.extract_text(&file)?;
+    let textloader = TextLoader::new(chunk_size, overlap_ratio)
+        .with_language(config.language.as_deref())
+        .with_sentence_backend(config.sentence_splitter.clone())
+        .with_min_chunk_size(
+            config.min_chunk_size,
+            config.min_chunk_policy.unwrap_or_default(),
+        );
+    let (chunks, mut chunk_metadata) =
+        textloader.split_with_metadata(&text, splitting_strategy, semantic_encoder)?;
+    add_chunk_spans(&text, &chunks, &mut chunk_metadata);
+    if config.detect_language.unwrap_or(false) {
+        add_chunk_languages(&chunks, &mut chunk_metadata);
+    }
+
+    let mut metadata =
+        TextLoader::get_metadata_with_sidecar(&file, config.sidecar_metadata.unwrap_or(false)).ok();
+    if !extraction_metadata.is_empty() {
+        metadata
+            .get_or_insert_with(HashMap::new)
+            .extend(extraction_metadata);
+    }
+    Ok((chunks, chunk_metadata, metadata))
+}
+
+/// Metadata key holding the path embedded with [TextEmbedConfig::prepend_filename].
+pub const FILE_PATH_KEY: &str = "file_path";
+
+/// The path of `file` embedded with [TextEmbedConfig::prepend_filename], relative to `base`,
+/// the canonical path of the embedded directory, or to the working directory when None. Paths
+/// outside of it are kept as given. None when the option is off.
+fn prepended_path(
+    file: &std::path::Path,
+    base: Option<&std::path::Path>,
+    config: &TextEmbedConfig,
+) -> Option<String> {
+    if !config.prepend_filename.unwrap_or(false) {
+        return None;
+    }
+    let base = match base {
+        Some(base) => Some(base.to_path_buf()),
+        None => std::env::current_dir().ok(),
+    };
+    let relative = base
+        .and_then(|base| file.strip_prefix(base).ok())
+        .unwrap_or(file);
+    Some(relative.to_string_lossy().to_string())
+}
+
+/// The texts the model embeds for `chunks`, the first of which is the chunk `first_index` of
+/// its file: `path` on the line before the first chunk of the file, or before every chunk with
+/// [TextEmbedConfig::prepend_filename_to_every_chunk]. The chunks as is when `path` is None.
+fn with_file_path<'a>(
+    chunks: &'a [String],
+    first_index: usize,
+    path: Option<&str>,
+    config: &TextEmbedConfig,
+) -> std::borrow::Cow<'a, [String]> {
+    let Some(path) = path else {
+        return std::borrow::Cow::Borrowed(chunks);
+    };
+    let every_chunk = config.prepend_filename_to_every_chunk.unwrap_or(false);
+    let texts = chunks
+        .iter()
+        .enumerate()
+        .map(|(i, chunk)| {
+            if every_chunk || first_index + i == 0 {
+                format!("{}\n{}", path, chunk)
+            } else {
+                chunk.clone()
+            }
+        })
+        .collect();
+    std::borrow::Cow::Owned(texts)
+}
+
+/// Calls [TextEmbedConfig::on_truncation] for the texts `tokenizer` truncates.
+fn report_truncation(
+    tokenizer: Option<&tokenizers::Tokenizer>,
+    texts: &[String],
+    file: Option<&str>,
+    config: &TextEmbedConfig,
+) -> Result<()> {
+    if let (Some(tokenizer), Some(on_truncation)) = (tokenizer, &config.on_truncation) {
+        embeddings::utils::report_truncation(tokenizer, texts, file, on_truncation)?;
+    }
+    Ok(())
+}
+
+/// The validator of [TextEmbedConfig::validate_dimensions], for a model producing vectors of
+/// `dimension` when it tells.
+fn dimension_validator(
+    dimension: Option<usize>,
+    config: &TextEmbedConfig,
+) -> Option<DimensionValidator> {
+    config
+        .validate_dimensions
+        .unwrap_or(false)
+        .then(|| DimensionValidator::new(dimension))
+}
+
+/// Stores the text the model embedded, with its `prefix`, in place of the text as given when
+/// [TextEmbedConfig::store_original_text] is false.
+fn store_embedded_text(
+    embeddings: &mut [EmbedData],
+    prefix: Option<&str>,
+    config: &TextEmbedConfig,
+) {
+    if config.store_original_text.unwrap_or(true) {
+        return;
+    }
+    let Some(prefix) = prefix else {
+        return;
+    };
+    for embedding in embeddings {
+        if let Some(text) = &mut embedding.text {
+            text.insert_str(0, prefix);
+        }
+    }
+}
+
+/// Adds the [chunk_spans] of `chunks` in `text`, the text they were split from, to their
+/// metadata.
+fn add_chunk_spans(text: &str, chunks: &[String], chunk_metadata: &mut [HashMap<String, String>]) {
+    for (metadata, span) in chunk_metadata.iter_mut().zip(chunk_spans(text, chunks)) {
+        if let Some((start, end)) = span {
+            metadata.insert(START_CHAR_KEY.to_string(), start.to_string());
+            metadata.insert(END_CHAR_KEY.to_string(), end.to_string());
+        }
+    }
+}
+
+/// Adds per chunk metadata, such as the symbol of a code chunk, to the file level metadata.
+fn merge_chunk_metadata(
+    embeddings: &mut [EmbedData],
+    chunk_metadata: Vec<HashMap<String, String>>,
+) {
+    for (embedding, extra) in embeddings.iter_mut().zip(chunk_metadata) {
+        embedding
+            .metadata
+            .get_or_insert_with(HashMap::new)
+            .extend(extra);
+    }
+}
+
+fn emb_image<T: AsRef<std::path::Path>>(
+    image_path: T,
+    embedding_model: &VisionEmbedder,
+) -> Result<EmbedData> {
+    let mut metadata = HashMap::new();
+    metadata.insert(
+        "file_name".to_string(),
+        fs::canonicalize(&image_path)?.to_str().unwrap().to_string(),
+    );
+    let embedding = embedding_model
+        .embed_image(&image_path, Some(metadata))
+        .unwrap();
+
+    Ok(embedding
+        .with_normalized(embedding_model.is_normalized())
+        .with_embedding_model(&embedding_model.metadata().model_id))
+}
+
+#[cfg(feature = "audio")]
+pub async fn emb_audio<T: AsRef<std::path::Path>>(
+    audio_file: T,
+    audio_decoder: &mut AudioDecoderModel,
+    embedder: &Arc<Embedder>,
+    text_embed_config: Option<&TextEmbedConfig>,
+) -> Result<Option<Vec<EmbedData>>> {
+    let audio =
+        emb_audio_with_transcript(audio_file, audio_decoder, embedder, text_embed_config).await?;
+    Ok(Some(audio.embeddings))
+}
+
+/// Embeddings of an audio file along with its transcript, returned by
+/// [emb_audio_with_transcript].
+#[derive(Debug, Clone)]
+pub struct AudioEmbedding {
+    /// One embedding per transcribed segment, with the full text of the segment as `text`.
+    pub embeddings: Vec<EmbedData>,
+    /// The text of all segments, separated by spaces.
+    pub transcript: String,
+}
+
+/// Like [emb_audio], but also returns the full transcript, so that it can be displayed with
+/// search results without transcribing the file again.
+#[cfg(feature = "audio")]
+pub async fn emb_audio_with_transcript<T: AsRef<std::path::Path>>(
+    audio_file: T,
+    audio_decoder: &mut AudioDecoderModel,
+    embedder: &Arc<Embedder>,
+    text_embed_config: Option<&TextEmbedConfig>,
+) -> Result<AudioEmbedding> {
+    use file_processor::audio::audio_processor;
+
+    if let Some(seed) = text_embed_config.and_then(|config| config.seed) {
+        audio_decoder.set_seed(seed)?;
+    }
+    let segments: Vec<audio_processor::Segment> = audio_decoder.process_audio(&audio_file)?;
+    let transcript = embeddings::transcript_from_audio(&segments);
+    let binding = TextEmbedConfig::default();
+    let config = text_embed_config.unwrap_or(&binding);
+    let mut embeddings = embed_audio(embedder, segments, audio_file, config.batch_size).await?;
+    config.finish_embeddings(&mut embeddings);
+
+    Ok(AudioEmbedding {
+        embeddings,
+        transcript,
+    })
+}
+
+#[cfg(not(feature = "audio"))]
+pub async fn emb_audio<T: AsRef<std::path::Path>>(
+    _audio_file: T,
+    _audio_decoder: &mut AudioDecoderModel,
+    _embedder: &Arc<Embedder>,
+    _text_embed_config: Option<&TextEmbedConfig>,
+) -> Result<Option<Vec<EmbedData>>> {
+    Err(anyhow::anyhow!(
+        "The 'audio' feature is not enabled. Please enable it to use the emb_audio function."
+    ))
+}
+
+#[cfg(not(feature = "audio"))]
+pub async fn emb_audio_with_transcript<T: AsRef<std::path::Path>>(
+    _audio_file: T,
+    _audio_decoder: &mut AudioDecoderModel,
+    _embedder: &Arc<Embedder>,
+    _text_embed_config: Option<&TextEmbedConfig>,
+) -> Result<AudioEmbedding> {
+    Err(anyhow::anyhow!(
+        "The 'audio' feature is not enabled. Please enable it to use the emb_audio_with_transcript function."
+    ))
+}
+
+/// Number of transcribed files waiting to be embedded by [emb_audio_directory].
+#[cfg(feature = "audio")]
+const AUDIO_QUEUE_SIZE: usize = 4;
+
+/// Transcribes and embeds the audio files of a directory. The decoder transcribes one file at a
+/// time while the transcripts of the previous files are embedded, with at most a few
+/// transcripts waiting. Files that fail to decode are skipped with a warning.
+///
+/// Every segment gets the metadata of its file, along with its `start`, `end`, `text` and
+/// `segment_index` within the file.
+///
+/// # Errors
+///
+/// Stops at the first transcript that fails to embed, e.g. with
+/// [BudgetExceeded](embeddings::cloud::BudgetExceeded), and returns its error.
+#[cfg(feature = "audio")]
+pub async fn emb_audio_directory(
+    directory: PathBuf,
+    audio_decoder: &mut AudioDecoderModel,
+    embedder: &Arc<Embedder>,
+    text_embed_config: Option<&TextEmbedConfig>,
+) -> Result<Vec<EmbedData>> {
+    use file_processor::audio::audio_processor::Segment;
+
+    let binding = TextEmbedConfig::default();
+    let config = text_embed_config.unwrap_or(&binding);
+    if let Some(seed) = config.seed {
+        audio_decoder.set_seed(seed)?;
+    }
+    let files = FileParser::new()
+        .with_file_filter(config.file_filter.clone())
+        .get_audio_files(&directory)?;
+
+    let (tx, mut rx) = mpsc::channel::<(String, Vec<Segment>)>(AUDIO_QUEUE_SIZE);
+    let embedding_task = tokio::spawn({
+        let embedder = embedder.clone();
+        let batch_size = config.batch_size;
+        async move {
+            let mut all_embeddings = Vec::new();
+            while let Some((file, segments)) = rx.recv().await {
+                let mut embeddings = embed_audio(&embedder, segments, &file, batch_size).await?;
+                let file_metadata = TextLoader::get_metadata(&file).unwrap_or_default();
+                for (i, embedding) in embeddings.iter_mut().enumerate() {
+                    let metadata = embedding.metadata.get_or_insert_with(HashMap::new);
+                    metadata.insert("segment_index".to_string(), i.to_string());
+                    for (key, value) in &file_metadata {
+                        metadata.entry(key.clone()).or_insert_with(|| value.clone());
+                    }
+                }
+                all_embeddings.extend(embeddings);
+            }
+            Ok::<_, anyhow::Error>(all_embeddings)
+        }
+    });
+
+    for file in files {
+        match audio_decoder.process_audio(&file) {
+            Ok(segments) => {
+                if tx.send((file, segments)).await.is_err() {
+                    break;
+                }
+            }
+            Err(e) => tracing::warn!("Skipping {}: {:?}", file, e),
+        }
+    }
+    drop(tx);
+
+    let mut embeddings = embedding_task.await??;
+    config.finish_embeddings(&mut embeddings);
+    Ok(embeddings)
+}
+
+#[cfg(not(feature = "audio"))]
+pub async fn emb_audio_directory(
+    _directory: PathBuf,
+    _audio_decoder: &mut AudioDecoderModel,
+    _embedder: &Arc<Embedder>,
+    _text_embed_config: Option<&TextEmbedConfig>,
+) -> Result<Vec<EmbedData>> {
+    Err(anyhow::anyhow!(
+        "The 'audio' feature is not enabled. Please enable it to use the emb_audio_directory function."
+    ))
+}
+
+/// Embeds images in a directory using the specified embedding model.
+///
+/// # Arguments
+///
+/// * `directory` - A `PathBuf` representing the directory containing the images to embed.
+/// * `embedder` - A reference to the embedding model to use.
+/// * `config` - An optional `ImageEmbedConfig` object specifying the configuration for the embedding model. Default buffer size is 100.
+/// * `adapter` - An optional callback function to handle the embeddings.
+///
+/// # Returns
+/// An `Option` containing a vector of `EmbedData` objects representing the embeddings of the images, or `None` if an adapter is used.
+///
+/// # Errors
+/// Returns a `Result` with an error if the embedding process fails.
+///
+/// # Example
+///
+/// ```rust
+/// use embed_anything::embed_image_directory;
+/// use std::path::PathBuf;
+/// use std::sync::Arc;
+/// use embed_anything::embeddings::embed::Embedder;
+///
+/// async fn embed_images() {
+///     let directory = PathBuf::from("/path/to/directory");
+///     let embedder = Arc::new(Embedder::from_pretrained_hf("clip", "openai/clip-vit-base-patch16", None).unwrap());
+///     let embeddings = embed_image_directory(directory, &embedder, None, None).await.unwrap();
+/// }
+/// ```
+/// This will output the embeddings of the images in the specified directory using the specified embedding model.
+///
+pub async fn embed_image_directory<T: EmbedImage + Send + Sync + 'static, F>(
+    directory: PathBuf,
+    embedding_model: &Arc<T>,
+    config: Option<&ImageEmbedConfig>,
+    adapter: Option<F>,
+) -> Result<Option<Vec<EmbedData>>>
+where
+    F: Adapter,
+{
+    let binding = ImageEmbedConfig::default();
+    let config = config.unwrap_or(&binding);
+    let mut file_parser = FileParser::new().with_file_filter(config.file_filter.clone());
+    file_parser.get_image_paths(&directory).unwrap();
+    let buffer_size = config.buffer_size.unwrap_or(100);
+    let decode_threads = config.decode_threads;
+
+    let (tx, mut rx) = mpsc::unbounded_channel();
+    let (collector_tx, mut collector_rx) = mpsc::unbounded_channel();
+
+    let embedder = embedding_model.clone();
+
+    let pb = indicatif::ProgressBar::new(file_parser.files.len() as u64);
+    pb.set_style(
+        indicatif::ProgressStyle::with_template(
+            "{spinner:.green} [{elapsed_precise}] [{bar:40.cyan/blue}] {pos}/{len} ({eta})",
+        )
+        .unwrap(),
+    );
 
     let processing_task = tokio::spawn({
+        async move {
+            // make image buffer
+            let mut image_buffer = Vec::with_capacity(buffer_size);
+            let mut files_processed: std::collections::HashSet<String> =
+                std::collections::HashSet::new();
+            while let Some(image) = rx.recv().await {
+                image_buffer.push(image);
+
+                if image_buffer.len() == buffer_size {
+                    // Ensure embedder is mutable and not wrapped in Arc
+                    match process_images(&image_buffer, embedder.clone(), decode_threads).await {
+                        Ok(embeddings) => {
+                            let files = embeddings
+                                .iter()
+                                .cloned()
+                                .map(|e| e.metadata.unwrap().get("file_name").unwrap().to_string())
+                                .collect::<Vec<_>>();
+
+                            let unique_files = files.into_iter().unique().collect::<Vec<_>>();
+                            let old_len = files_processed.len() as u64;
+                            files_processed.extend(unique_files);
+                            let new_len = files_processed.len() as u64;
+
+                            pb.inc(new_len - old_len);
+
+                            if let Err(e) = collector_tx.send(embeddings) {
+                                eprintln!("Error sending embeddings to collector: {:?}", e);
+                            }
+                        }
+                        Err(e) => eprintln!("Error processing images: {:?}", e),
+                    }
+
+                    image_buffer.clear();
+                }
+            }
+
+            // Process any remaining images
+            if !image_buffer.is_empty() {
+                match process_images(&image_buffer, embedder, decode_threads).await {
+                    Ok(embeddings) => {
+                        let files = embeddings
+                            .iter()
+                            .cloned()
+                            .map(|e| e.metadata.unwrap().get("file_name").unwrap().to_string())
+                            .collect::<Vec<_>>();
+                        let unique_files = files.into_iter().unique().collect::<Vec<_>>();
+                        let old_len = files_processed.len() as u64;
+                        files_processed.extend(unique_files);
+                        let new_len = files_processed.len() as u64;
+
+                        pb.inc(new_len - old_len);
+
+                        if let Err(e) = collector_tx.send(embeddings) {
+                            eprintln!("Error sending embeddings to collector: {:?}", e);
+                        }
+                    }
+                    Err(e) => eprintln!("Error processing images: {:?}", e),
+                }
+            }
+        }
+    });
+
+    file_parser.files.par_iter().for_each(|image| {
+        if let Err(e) = tx.send(image.clone()) {
+            eprintln!("Error sending image: {:?}", e);
+        }
+    });
+
+    drop(tx);
+
+    let mut all_embeddings = Vec::new();
+    while let Some(embeddings) = collector_rx.recv().await {
+        if let Some(adapter) = &adapter {
+            adapter.upsert(embeddings.to_vec());
+        } else {
+            all_embeddings.extend(embeddings.to_vec());
+        }
+    }
+
+    // Wait for the spawned task to complete
+    processing_task.await.unwrap();
+
+    if let Some(adapter) = &adapter {
+        adapter.flush();
+        Ok(None)
+    } else {
+        Ok(Some(all_embeddings))
+    }
+}
+
+async fn process_images<E: EmbedImage>(
+    image_buffer: &[String],
+    embedder: Arc<E>,
+    decode_threads: Option<usize>,
+) -> Result<Arc<Vec<EmbedData>>> {
+    let mut embeddings =
+        embedder.embed_image_batch_pipelined(image_buffer, decode_threads, image_buffer.len())?;
+    mark_normalized(&mut embeddings, embedder.is_normalized());
+    mark_embedding_model(&mut embeddings, &embedder.model_metadata().model_id);
+    Ok(Arc::new(embeddings))
+}
+
+/// Embeds text from files in a directory using the specified embedding model.
+///
+/// # Arguments
+///
+/// * `directory` - A `PathBuf` representing the directory containing the files to embed.
+/// * `embedder` - A reference to the embedding model to use.
+/// * `extensions` - An optional vector of strings representing the file extensions to consider for embedding. If `None`, all files in the directory will be considered.
+/// * `config` - An optional `TextEmbedConfig` object specifying the configuration for the embedding model.
+/// * `adapter` - An optional callback function to handle the embeddings.
+///
+/// # Returns
+/// An `Option` containing a vector of `EmbedData` objects representing the embeddings of the files, or `None` if an adapter is used.
+///
+/// # Errors
+/// Returns a `Result` with an error if the embedding process fails. Embedding stops at the
+/// first chunks that fail to embed, such as when the token budget of a cloud model runs out,
+/// and their error is returned with the embeddings so far, see [PartialEmbeddings].
+///
+/// # Example
+///
+/// ```rust
+/// use embed_anything::embed_directory_stream;
+/// use std::path::PathBuf;
+/// use std::sync::Arc;
+/// use embed_anything::config::TextEmbedConfig;
+/// use embed_anything::embeddings::embed::Embedder;
+///
+/// async fn generate_embeddings() {
+///     let directory = PathBuf::from("/path/to/directory");
+///     let embedder = Arc::new(Embedder::from_pretrained_hf("clip", "openai/clip-vit-base-patch16", None).unwrap());
+///     let config = Some(&TextEmbedConfig::default());
+///     let extensions = Some(vec!["txt".to_string(), "pdf".to_string()]);
+///     let embeddings = embed_directory_stream(directory, &embedder, extensions, config, None).await.unwrap();
+/// }
+/// ```
+/// This will output the embeddings of the files in the specified directory using the specified embedding model.
+pub async fn embed_directory_stream<F>(
+    directory: PathBuf,
+    embedder: &Arc<Embedder>,
+    extensions: Option<Vec<String>>,
+    config: Option<&TextEmbedConfig>,
+    adapter: Option<F>,
+) -> Result<Option<Vec<EmbedData>>>
+where
+    F: Adapter,
+{
+    println!("Embedding directory: {:?}", directory);
+
+    let binding = TextEmbedConfig::default();
+    let config = config.unwrap_or(&binding);
+    let chunk_size = config.chunk_size.unwrap_or(binding.chunk_size.unwrap());
+    let buffer_size = config.buffer_size.unwrap_or(binding.buffer_size.unwrap());
+    let mut batch_size = config.batch_size;
+    let mut auto_batch_size = config.auto_batch_size.unwrap_or(false);
+    let overlap_ratio = config.overlap_ratio.unwrap_or(0.0);
+    let sidecar_metadata = config.sidecar_metadata.unwrap_or(false);
+    let mut file_parser = FileParser::new().with_file_filter(config.file_filter.clone());
+    file_parser.get_text_files(&directory, extensions.clone())?;
+    // Archives are replaced by the files they hold, which stay unpacked until the directory is
+    // embedded.
+    let mut archives = Vec::new();
+    let mut archive_metadata = HashMap::new();
+    if config.extract_archives.unwrap_or(false) {
+        let (found, files): (Vec<_>, Vec<_>) = file_parser
+            .files
+            .drain(..)
+            .partition(|file| is_archive(file));
+        file_parser.files = files;
+        for archive in found {
+            let extracted = match extract_archive(
+                std::path::Path::new(&archive),
+                config,
+                extensions.as_deref(),
+            ) {
+                Ok(extracted) => extracted,
+                Err(e) => {
+                    tracing::warn!("Skipping {}: {:?}", archive, e);
+                    continue;
+                }
+            };
+            for entry in extracted.entries() {
+                let file = entry.file.to_string_lossy().to_string();
+                archive_metadata.insert(file.clone(), extracted.metadata(entry));
+                file_parser.files.push(file);
+            }
+            archives.push(extracted);
+        }
+    }
+    // Directories often hold files that can't be embedded, they are skipped unless asked
+    // otherwise.
+    let (supported, unsupported): (Vec<_>, Vec<_>) = file_parser
+        .files
+        .drain(..)
+        .partition(|file| TextLoader::is_supported(file));
+    for file in unsupported {
+        if config.error_on_unsupported.unwrap_or(false) {
+            return Err(FileLoadingError::UnsupportedFileType(file).into());
+        }
+        tracing::warn!("Skipping unsupported file {}", file);
+    }
+    file_parser.files = supported;
+    let files = file_parser.files.clone();
+    let (tx, mut rx) = mpsc::unbounded_channel();
+    let (buffer_tx, buffer_rx) = mpsc::unbounded_channel();
+    let (collector_tx, mut collector_rx) = mpsc::unbounded_channel();
+
+    // Only cloned to report truncation, the embedder is moved to the processing tasks.
+    let tokenizer = config
+        .on_truncation
+        .as_ref()
+        .and_then(|_| embedder.tokenizer().cloned());
+    let embedders = match config.load_embedder_pool()? {
+        Some(pool) => pool
+            .embedders()
+            .iter()
+            .map(|(_, embedder)| embedder.clone())
+            .collect::<Vec<_>>(),
+        None => vec![embedder.clone()],
+    };
+    let pb = indicatif::ProgressBar::new(files.len() as u64);
+    pb.set_style(
+        indicatif::ProgressStyle::with_template(
+            "{spinner:.green} [{elapsed_precise}] [{bar:40.cyan/blue}] {pos}/{len} ({eta})",
+        )
+        .unwrap(),
+    );
+
+    // Set by the first processing task that fails, to stop reading files and embedding.
+    let stopped = Arc::new(std::sync::atomic::AtomicBool::new(false));
+
+    // Gathers the chunks into buffers of `buffer_size`.
+    let buffering_task = tokio::spawn({
+        let embedder = embedders[0].clone();
+        let stopped = stopped.clone();
         async move {
             let mut chunk_buffer = Vec::with_capacity(buffer_size);
+            let mut embedded_buffer: Vec<Option<String>> = Vec::with_capacity(buffer_size);
             let mut metadata_buffer = Vec::with_capacity(buffer_size);
-            let mut files_processed: std::collections::HashSet<String> =
-                std::collections::HashSet::new();
+            loop {
+                let received = rx.recv().await;
+                let done = received.is_none();
+                if let Some((chunk, embedded, metadata)) = received {
+                    // The chunks received once embedding stopped are dropped.
+                    if stopped.load(std::sync::atomic::Ordering::Relaxed) {
+                        continue;
+                    }
+                    chunk_buffer.push(chunk);
+                    embedded_buffer.push(embedded);
+                    metadata_buffer.push(metadata);
+                }
+                if chunk_buffer.len() == buffer_size || (done && !chunk_buffer.is_empty()) {
+                    // The batch size is tuned once, on the first buffer.
+                    if auto_batch_size {
+                        let max_batch_size = batch_size.unwrap_or(MAX_AUTO_BATCH_SIZE);
+                        let tuned = embedder
+                            .tune_batch_size(&chunk_buffer, max_batch_size)
+                            .await;
+                        batch_size = Some(tuned);
+                        auto_batch_size = false;
+                    }
+                    // The texts the model embeds, when some differ from the stored chunks.
+                    let embedded = std::mem::take(&mut embedded_buffer);
+                    let embedded = embedded.iter().any(Option::is_some).then(|| {
+                        chunk_buffer
+                            .iter()
+                            .zip(embedded)
+                            .map(|(chunk, embedded)| embedded.unwrap_or_else(|| chunk.clone()))
+                            .collect::<Vec<_>>()
+                    });
+                    let buffer = (
+                        std::mem::take(&mut chunk_buffer),
+                        embedded,
+                        std::mem::take(&mut metadata_buffer),
+                        batch_size,
+                    );
+                    if let Err(e) = buffer_tx.send(buffer) {
+                        tracing::warn!("Error sending chunks: {:?}", e);
+                    }
+                }
+                if done {
+                    break;
+                }
+            }
+        }
+    });
+
+    // Each embedder takes the next buffer as soon as it is free, so that the embedders of a
+    // pool all stay busy.
+    let buffer_rx = Arc::new(tokio::sync::Mutex::new(buffer_rx));
+    let processing_tasks = embedders
+        .into_iter()
+        .map(|embedder| {
+            let buffer_rx = buffer_rx.clone();
+            let collector_tx = collector_tx.clone();
+            let task_config = config.clone();
+            let stopped = stopped.clone();
+            tokio::spawn(async move {
+                while !stopped.load(std::sync::atomic::Ordering::Relaxed) {
+                    let buffer = buffer_rx.lock().await.recv().await;
+                    let Some((chunks, embedded, metadata, batch_size)) = buffer else {
+                        break;
+                    };
+                    let result = embed_chunks(
+                        &chunks,
+                        embedded.as_deref(),
+                        &metadata,
+                        &embedder,
+                        batch_size,
+                        Some(&task_config),
+                    )
+                    .await;
+                    // An error, such as running out of token budget, would fail every later
+                    // buffer too, so it stops the run.
+                    let embeddings = match result {
+                        Ok(embeddings) => embeddings,
+                        Err(e) => {
+                            stopped.store(true, std::sync::atomic::Ordering::Relaxed);
+                            return Err(e);
+                        }
+                    };
+                    if let Err(e) = collector_tx.send(embeddings) {
+                        eprintln!("Error sending embeddings to collector: {:?}", e);
+                    }
+                }
+                Ok(())
+            })
+        })
+        .collect::<Vec<_>>();
+    drop(collector_tx);
+
+    let textloader = TextLoader::new(chunk_size, overlap_ratio)
+        .with_language(config.language.as_deref())
+        .with_sentence_backend(config.sentence_splitter.clone())
+        .with_min_chunk_size(
+            config.min_chunk_size,
+            config.min_chunk_policy.unwrap_or_default(),
+        );
+    let base = fs::canonicalize(&directory).unwrap_or_else(|_| directory.clone());
+    // The texts the model embeds for the chunks of a file, None for the chunks embedded as is.
+    let embedded_texts = |chunks: &[String], file_path: Option<&str>| -> Vec<Option<String>> {
+        match with_file_path(chunks, 0, file_path, config) {
+            std::borrow::Cow::Owned(texts) => texts.into_iter().map(Some).collect(),
+            std::borrow::Cow::Borrowed(_) => vec![None; chunks.len()],
+        }
+    };
+
+    file_parser.files.iter().for_each(|file| {
+        if stopped.load(std::sync::atomic::Ordering::Relaxed) {
+            return;
+        }
+        if let Err(e) = TextLoader::check_document_size(file, config.max_document_bytes) {
+            tracing::warn!("Skipping {}: {:?}", file, e);
+            return;
+        }
+        let (text, extraction_metadata) = match config.extract_text(file) {
+            Ok(extracted) => extracted,
+            Err(_) => {
+                return;
+            }
+        };
+        let mut metadata = match TextLoader::get_metadata_with_sidecar(file, sidecar_metadata) {
+            Ok(metadata) => metadata,
+            Err(e) => {
+                tracing::warn!("Skipping {}: {:?}", file, e);
+                return;
+            }
+        };
+        metadata.extend(extraction_metadata);
+        if let Some(archive_metadata) = archive_metadata.get(file) {
+            metadata.extend(archive_metadata.clone());
+        }
+        let file_path = prepended_path(std::path::Path::new(file), Some(&base), config);
+        if let Some(file_path) = &file_path {
+            metadata.insert(FILE_PATH_KEY.to_string(), file_path.clone());
+        }
+        // Code chunks and windows carry metadata of their own.
+        let split: Option<Result<(Vec<HashMap<String, String>>, Vec<String>)>> =
+            match config.splitting_strategy {
+                Some(SplittingStrategy::Code { language }) => {
+                    // Each file is parsed with the grammar matching its extension when known.
+                    let language = std::path::Path::new(file)
+                        .extension()
+                        .and_then(|extension| extension.to_str())
+                        .and_then(CodeLanguage::from_extension)
+                        .unwrap_or(language);
+                    Some(textloader.split_code(&text, language).map(|code_chunks| {
+                        code_chunks
+                            .into_iter()
+                            .map(|chunk| (chunk.metadata(), chunk.text))
+                            .unzip()
+                    }))
+                }
+                Some(SplittingStrategy::SlidingWindow {
+                    window_tokens,
+                    stride_tokens,
+                }) => Some(
+                    textloader
+                        .split_windows(&text, window_tokens, stride_tokens)
+                        .map(|windows| {
+                            windows
+                                .into_iter()
+                                .map(|window| (window.metadata(), window.text))
+                                .unzip()
+                        }),
+                ),
+                _ => None,
+            };
+        if let Some(split) = split {
+            let Ok((mut chunk_metadata, texts)) = split else {
+                return;
+            };
+            let embedded = embedded_texts(&texts, file_path.as_deref());
+            if let Err(e) = report_truncation(tokenizer.as_ref(), &texts, Some(file), config) {
+                tracing::warn!("Error reporting truncation: {:?}", e);
+            }
+            add_chunk_spans(&text, &texts, &mut chunk_metadata);
+            if config.detect_language.unwrap_or(false) {
+                add_chunk_languages(&texts, &mut chunk_metadata);
+            }
+            for ((chunk, extra), embedded) in texts.into_iter().zip(chunk_metadata).zip(embedded) {
+                let mut chunk_metadata = metadata.clone();
+                chunk_metadata.extend(extra);
+                if let Err(e) = tx.send((chunk, embedded, Some(chunk_metadata))) {
+                    eprintln!("Error sending chunk: {:?}", e);
+                }
+            }
+            return;
+        }
+        let chunks = textloader
+            .split_into_chunks(&text, SplittingStrategy::Sentence, None)
+            .unwrap_or_else(|| vec![text.clone()])
+            .into_iter()
+            .filter(|chunk| !chunk.trim().is_empty())
+            .collect::<Vec<_>>();
+        if chunks.is_empty() {
+            return;
+        }
+        if let Err(e) = report_truncation(tokenizer.as_ref(), &chunks, Some(file), config) {
+            tracing::warn!("Error reporting truncation: {:?}", e);
+        }
+        let embedded = embedded_texts(&chunks, file_path.as_deref());
+        let mut chunk_metadata = vec![metadata.clone(); chunks.len()];
+        add_chunk_spans(&text, &chunks, &mut chunk_metadata);
+        if config.detect_language.unwrap_or(false) {
+            add_chunk_languages(&chunks, &mut chunk_metadata);
+        }
+        for ((chunk, chunk_metadata), embedded) in
+            chunks.into_iter().zip(chunk_metadata).zip(embedded)
+        {
+            if let Err(e) = tx.send((chunk, embedded, Some(chunk_metadata))) {
+                eprintln!("Error sending chunk: {:?}", e);
+            }
+        }
+    });
+
+    drop(tx);
+
+    let validator = dimension_validator(embedder.metadata().dimension, config);
+    let mut all_embeddings = Vec::new();
+    let mut files_processed = std::collections::HashSet::new();
+    while let Some(embeddings) = collector_rx.recv().await {
+        let mut embeddings = embeddings.to_vec();
+        let old_len = files_processed.len();
+        files_processed.extend(
+            embeddings
+                .iter()
+                .filter_map(|embedding| embedding.metadata.as_ref()?.get("file_name").cloned()),
+        );
+        pb.inc((files_processed.len() - old_len) as u64);
+        if let Some(validator) = &validator {
+            validator.retain_valid(&mut embeddings);
+        }
+        config.finish_embeddings(&mut embeddings);
+        if let Some(adapter) = &adapter {
+            adapter.upsert(embeddings);
+        } else {
+            all_embeddings.extend(embeddings);
+        }
+    }
+    // Wait for the spawned tasks to complete
+    buffering_task.await.unwrap();
+    let mut error = None;
+    for processing_task in processing_tasks {
+        if let Err(e) = processing_task.await.unwrap() {
+            error.get_or_insert(e);
+        }
+    }
+
+    if let Some(adapter) = &adapter {
+        adapter.flush();
+    }
+    if let Some(error) = error {
+        return Err(error.context(PartialEmbeddings {
+            embeddings: all_embeddings,
+        }));
+    }
+    Ok(adapter.is_none().then_some(all_embeddings))
+}
+
+/// Added to the error that stopped [embed_directory_stream], such as the
+/// [BudgetExceeded](embeddings::cloud::BudgetExceeded) of a cloud model, with the embeddings
+/// of the chunks embedded before it. Retrieve it with
+/// `error.downcast_ref::<PartialEmbeddings>()`, the error itself is still retrieved with
+/// `downcast_ref`. The embeddings are empty when an adapter is used, it received them instead.
+#[derive(Debug)]
+pub struct PartialEmbeddings {
+    pub embeddings: Vec<EmbedData>,
+}
+
+impl std::fmt::Display for PartialEmbeddings {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "Embedding stopped after {} embeddings",
+            self.embeddings.len()
+        )
+    }
+}
+
+/// Progress reported by [embed_with_progress_stream].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ProgressEvent {
+    /// Files embedded or failed so far.
+    pub files_done: usize,
+    pub files_total: usize,
+    /// Embeddings returned so far.
+    pub embeddings: usize,
+    /// The file that was just done, None before the first one.
+    pub file: Option<String>,
+}
+
+impl ProgressEvent {
+    /// Share of the files done, from 0.0 to 1.0. An empty directory is done from the start.
+    pub fn fraction(&self) -> f32 {
+        match self.files_total {
+            0 => 1.0,
+            total => self.files_done as f32 / total as f32,
+        }
+    }
+
+    pub fn is_finished(&self) -> bool {
+        self.files_done == self.files_total
+    }
+}
+
+/// An event of [embed_with_progress_stream].
+#[derive(Debug, Clone)]
+pub enum EmbedEvent {
+    Progress(ProgressEvent),
+    /// The embeddings of a file.
+    Data(Vec<EmbedData>),
+    /// A file that could not be embedded, with the error message.
+    Error(String, String),
+}
+
+/// Embeds the files of a directory like [embed_directory_stream], as a stream of events that
+/// carries both the embeddings and the progress, so that a single consumer can drive a UI and
+/// collect the results. Files are embedded one after the other with [embed_file].
+///
+/// The events are ordered as follows:
+/// - the first event is a [ProgressEvent] with no file done,
+/// - each file then yields its [EmbedEvent::Data], or an [EmbedEvent::Error] when it fails,
+///   followed by a [ProgressEvent] counting it. Files without any chunk only yield progress,
+/// - the last event is the [ProgressEvent] counting every file, see
+///   [ProgressEvent::is_finished].
+///
+/// Failing files don't end the stream. Only failing to list the directory is returned as an
+/// error.
+///
+/// # Example
+///
+/// ```rust,no_run
+/// use embed_anything::{embed_with_progress_stream, embeddings::embed::Embedder, EmbedEvent};
+/// use futures::StreamExt;
+///
+/// async fn embed_with_progress(embedder: &Embedder) {
+///     let events = embed_with_progress_stream("docs".into(), embedder, None, None).unwrap();
+///     let mut events = std::pin::pin!(events);
+///     let mut embeddings = Vec::new();
+///     while let Some(event) = events.next().await {
+///         match event {
+///             EmbedEvent::Progress(progress) => println!("{:.0}%", progress.fraction() * 100.0),
+///             EmbedEvent::Data(data) => embeddings.extend(data),
+///             EmbedEvent::Error(file, error) => eprintln!("{}: {}", file, error),
+///         }
+///     }
+/// }
+/// ```
+pub fn embed_with_progress_stream<'a>(
+    directory: PathBuf,
+    embedder: &'a Embedder,
+    extensions: Option<Vec<String>>,
+    config: Option<&'a TextEmbedConfig>,
+) -> Result<impl futures::Stream<Item = EmbedEvent> + 'a> {
+    let files = FileParser::new()
+        .with_file_filter(config.and_then(|config| config.file_filter.clone()))
+        .get_text_files(&directory, extensions)?;
+    let progress = ProgressEvent {
+        files_done: 0,
+        files_total: files.len(),
+        embeddings: 0,
+        file: None,
+    };
+    let pending = std::collections::VecDeque::from([EmbedEvent::Progress(progress.clone())]);
+    let state = (files.into_iter(), pending, progress);
+    Ok(futures::stream::unfold(
+        state,
+        move |(mut files, mut pending, mut progress)| async move {
+            if pending.is_empty() {
+                let file = files.next()?;
+                match embed_file(&file, embedder, config, None::<fn(Vec<EmbedData>)>).await {
+                    Ok(data) => {
+                        let data = data.unwrap_or_default();
+                        progress.embeddings += data.len();
+                        if !data.is_empty() {
+                            pending.push_back(EmbedEvent::Data(data));
+                        }
+                    }
+                    Err(e) => pending.push_back(EmbedEvent::Error(file.clone(), e.to_string())),
+                }
+                progress.files_done += 1;
+                progress.file = Some(file);
+                pending.push_back(EmbedEvent::Progress(progress.clone()));
+            }
+            let event = pending.pop_front()?;
+            Some((event, (files, pending, progress)))
+        },
+    ))
+}
+
+pub async fn process_chunks(
+    chunks: &Vec<String>,
+    metadata: &Vec<Option<HashMap<String, String>>>,
+    embedding_model: &Arc<Embedder>,
+    batch_size: Option<usize>,
+    config: Option<&TextEmbedConfig>,
+) -> Result<Arc<Vec<EmbedData>>> {
+    embed_chunks(chunks, None, metadata, embedding_model, batch_size, config).await
+}
+
+/// Embeds `chunks` like [process_chunks], the model embedding `embedded` in their place when
+/// given, e.g. the chunks with the path of their file, while the chunks are stored.
+async fn embed_chunks(
+    chunks: &[String],
+    embedded: Option<&[String]>,
+    metadata: &[Option<HashMap<String, String>>],
+    embedding_model: &Arc<Embedder>,
+    batch_size: Option<usize>,
+    config: Option<&TextEmbedConfig>,
+) -> Result<Arc<Vec<EmbedData>>> {
+    let binding = TextEmbedConfig::default();
+    let config = config.unwrap_or(&binding);
+    let encodings = config
+        .embed_document_batch(
+            embedding_model.as_ref(),
+            embedded.unwrap_or(chunks),
+            batch_size,
+        )
+        .await?;
+
+    let model_id = embedding_model.model_id();
+    // zip encodings with chunks and metadata
+    let mut embeddings = encodings
+        .into_iter()
+        .zip(chunks)
+        .zip(metadata)
+        .map(|((encoding, chunk), metadata)| {
+            EmbedData::new(encoding.clone(), Some(chunk.clone()), metadata.clone())
+                .with_normalized(embedding_model.is_normalized())
+                .with_embedding_model(&model_id)
+        })
+        .collect::<Vec<_>>();
+    store_embedded_text(&mut embeddings, embedding_model.prefixes().1, config);
+    Ok(Arc::new(embeddings))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::embeddings::{
+        embed::ModelMetadata, local::bert::BertEmbed, pool::EmbedderPool, registry::ModelLoader,
+    };
+    use file_processor::pdf_tables::TableMode;
+
+    #[test]
+    fn test_chunk_file_with_sliding_windows() {
+        let directory = tempdir::TempDir::new("windows").unwrap();
+        let file = directory.path().join("numbers.txt");
+        std::fs::write(&file, "one two three four five six seven eight nine ten").unwrap();
+        let config =
+            TextEmbedConfig::default().with_splitting_strategy(SplittingStrategy::SlidingWindow {
+                window_tokens: 4,
+                stride_tokens: 3,
+            });
+
+        let (chunks, chunk_metadata, _) = chunk_file(&file, &config).unwrap();
+        assert_eq!(
+            chunks,
+            [
+                "one two three four",
+                "four five six seven",
+                "seven eight nine ten"
+            ]
+        );
+        let spans = chunk_metadata
+            .iter()
+            .map(|metadata| {
+                (
+                    metadata["start_token"].as_str(),
+                    metadata["end_token"].as_str(),
+                    metadata[START_CHAR_KEY].as_str(),
+                )
+            })
+            .collect::<Vec<_>>();
+        assert_eq!(
+            spans,
+            [("0", "4", "0"), ("3", "7", "14"), ("6", "10", "28")]
+        );
+    }
+
+    #[test]
+    fn test_chunk_file_with_language_detection() {
+        let directory = tempdir::TempDir::new("languages").unwrap();
+        let file = directory.path().join("bilingual.txt");
+        let english = "The weather was lovely this morning. We walked along the river to the old \
+            town and had lunch in the square.";
+        let spanish = "El tiempo era estupendo esta mañana. Caminamos junto al río hasta el casco \
+            antiguo y comimos en la plaza.";
+        std::fs::write(&file, format!("{}\n\n{}", english, spanish)).unwrap();
+        let config = TextEmbedConfig::default()
+            .with_chunk_size(40, None)
+            .with_detect_language(true);
+
+        let (chunks, chunk_metadata, _) = chunk_file(&file, &config).unwrap();
+        assert_eq!(chunks.len(), 2);
+        for (chunk, metadata) in chunks.iter().zip(&chunk_metadata) {
+            let expected = if english.contains(chunk.as_str()) {
+                "eng"
+            } else {
+                "spa"
+            };
+            assert_eq!(metadata["language"], expected, "{}", chunk);
+            assert!(metadata.contains_key("confidence"));
+        }
+
+        let (_, chunk_metadata, _) = chunk_file(&file, &TextEmbedConfig::default()).unwrap();
+        assert!(!chunk_metadata[0].contains_key("language"));
+    }
+
+    #[test]
+    fn test_supported_extensions() {
+        let extensions = supported_extensions();
+        for extension in ["pdf", "txt", "md", "docx", "rtf", "odt", "html", "py"] {
+            assert!(extensions.contains(&extension), "{} is missing", extension);
+        }
+        // Every listed extension is read by the text loader.
+        for extension in extensions {
+            assert!(TextLoader::is_supported(&format!("file.{}", extension)));
+        }
+        assert!(!TextLoader::is_supported(&"file.xyz"));
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn test_embedding_is_deterministic() {
+        let embedder = Embedder::from_pretrained_hf(
+            "bert",
+            "sentence-transformers/all-MiniLM-L6-v2",
+            None,
+            None,
+        )
+        .unwrap();
+        let query = vec!["The same text, embedded twice.".to_string()];
+        let bits = |embeddings: Vec<EmbedData>| -> Vec<u32> {
+            embeddings[0]
+                .embedding
+                .to_dense()
+                .unwrap()
+                .iter()
+                .map(|x| x.to_bits())
+                .collect()
+        };
+        let first = embed_query(query.clone(), &embedder, None).await.unwrap();
+        let second = embed_query(query, &embedder, None).await.unwrap();
+        assert_eq!(bits(first), bits(second));
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn test_store_original_text() {
+        // Queries get the "Represent this sentence..." prefix of BGE models.
+        let embedder =
+            Embedder::from_pretrained_hf("bert", "BAAI/bge-small-en-v1.5", None, None).unwrap();
+        let prefix = embedder.prefixes().0.unwrap().to_string();
+        let query = vec!["What is Rust?".to_string()];
+        let prefixed = format!("{}{}", prefix, query[0]);
+
+        let embeddings = embed_query(query.clone(), &embedder, None).await.unwrap();
+        assert_eq!(embeddings[0].text.as_deref(), Some("What is Rust?"));
+        let expected = embedder.embed(&[prefixed.clone()], None).await.unwrap();
+        assert_eq!(
+            embeddings[0].embedding.to_dense().unwrap(),
+            expected[0].to_dense().unwrap()
+        );
+
+        let config = TextEmbedConfig::default().with_store_original_text(false);
+        let embeddings = embed_query(query, &embedder, Some(&config)).await.unwrap();
+        assert_eq!(embeddings[0].text, Some(prefixed));
+    }
 
-            while let Some((chunk, metadata)) = rx.recv().await {
-                chunk_buffer.push(chunk);
-                metadata_buffer.push(metadata);
+    #[tokio::test(flavor = "multi_thread")]
+    async fn test_try_embed_query() {
+        let embedder = Embedder::from_pretrained_hf(
+            "bert",
+            "sentence-transformers/all-MiniLM-L6-v2",
+            None,
+            None,
+        )
+        .unwrap();
+        let query = ["What is Rust?", "", "How do I embed a PDF?", "   \n"]
+            .map(str::to_string)
+            .to_vec();
+        let results = try_embed_query(query.clone(), &embedder, None).await;
+        assert_eq!(results.len(), 4);
+        assert!(results[1]
+            .as_ref()
+            .unwrap_err()
+            .to_string()
+            .contains("empty"));
+        assert!(results[3].is_err());
+
+        let expected = embed_query(vec![query[0].clone(), query[2].clone()], &embedder, None)
+            .await
+            .unwrap();
+        for (result, expected) in [&results[0], &results[2]].into_iter().zip(&expected) {
+            let embedding = result.as_ref().unwrap();
+            assert_eq!(embedding.text, expected.text);
+            assert_eq!(
+                embedding.embedding.to_dense().unwrap(),
+                expected.embedding.to_dense().unwrap()
+            );
+        }
+        assert!(try_embed_query(Vec::new(), &embedder, None)
+            .await
+            .is_empty());
+    }
 
-                if chunk_buffer.len() == buffer_size {
-                    match process_chunks(&chunk_buffer, &metadata_buffer, &embedder, batch_size)
-                        .await
-                    {
-                        Ok(embeddings) => {
-                            let files = embeddings
-                                .iter()
-                                .cloned()
-                                .map(|e| e.metadata.unwrap().get("file_name").unwrap().to_string())
-                                .collect::<Vec<_>>();
+    #[tokio::test(flavor = "multi_thread")]
+    async fn test_embed_records() {
+        let embedder = Embedder::from_pretrained_hf(
+            "bert",
+            "sentence-transformers/all-MiniLM-L6-v2",
+            None,
+            None,
+        )
+        .unwrap();
+        let records = vec![
+            Record::new("row-1", "The invoice was paid on time."),
+            Record::new("row-2", "The delivery was late.")
+                .with_metadata(HashMap::from([("table".to_string(), "orders".to_string())])),
+        ];
+
+        let embeddings =
+            embed_records(records.clone(), &embedder, None, None::<fn(Vec<EmbedData>)>)
+                .await
+                .unwrap()
+                .unwrap();
+        assert_eq!(embeddings.len(), 2);
+        for (embedding, record) in embeddings.iter().zip(&records) {
+            let metadata = embedding.metadata.as_ref().unwrap();
+            assert_eq!(metadata[RECORD_ID_KEY], record.id);
+            assert_eq!(embedding.text.as_ref().unwrap(), &record.text);
+        }
+        assert_eq!(embeddings[1].metadata.as_ref().unwrap()["table"], "orders");
+
+        let long = Record::new("row-3", "A sentence about the order. ".repeat(40));
+        let config = TextEmbedConfig::default()
+            .with_chunk_size(32, None)
+            .with_chunk_records(true);
+        let chunks = embed_records(
+            vec![long],
+            &embedder,
+            Some(&config),
+            None::<fn(Vec<EmbedData>)>,
+        )
+        .await
+        .unwrap()
+        .unwrap();
+        assert!(chunks.len() > 1);
+        for (index, chunk) in chunks.iter().enumerate() {
+            let metadata = chunk.metadata.as_ref().unwrap();
+            assert_eq!(metadata[RECORD_ID_KEY], "row-3");
+            assert_eq!(metadata["chunk_index"], index.to_string());
+        }
+    }
 
-                            let unique_files = files.into_iter().unique().collect::<Vec<_>>();
-                            let old_len = files_processed.len() as u64;
-                            files_processed.extend(unique_files);
-                            let new_len = files_processed.len() as u64;
+    #[tokio::test(flavor = "multi_thread")]
+    async fn test_on_truncation() {
+        let embedder = Embedder::from_pretrained_hf(
+            "bert",
+            "sentence-transformers/all-MiniLM-L6-v2",
+            None,
+            None,
+        )
+        .unwrap();
+        let events = Arc::new(std::sync::Mutex::new(Vec::new()));
+        let config = TextEmbedConfig::default().with_on_truncation({
+            let events = Arc::clone(&events);
+            Arc::new(move |event| events.lock().unwrap().push(event.clone()))
+        });
+        let long = "The quick brown fox jumps over the lazy dog. ".repeat(100);
+        let query = vec!["A short query.".to_string(), long.clone()];
+        embed_query(query, &embedder, Some(&config)).await.unwrap();
+
+        let events = events.lock().unwrap();
+        assert_eq!(events.len(), 1);
+        assert_eq!(events[0].text_len, long.chars().count());
+        assert_eq!(events[0].max_length, 512);
+        assert_eq!(events[0].file, None);
+    }
 
-                            pb.inc(new_len - old_len);
+    #[tokio::test(flavor = "multi_thread")]
+    async fn test_streaming_extraction() {
+        let embedder = Embedder::from_pretrained_hf(
+            "bert",
+            "sentence-transformers/all-MiniLM-L6-v2",
+            None,
+            None,
+        )
+        .unwrap();
+        let config = TextEmbedConfig::default()
+            .with_buffer_size(4)
+            .with_streaming_extraction(true);
+        let batches = std::sync::Mutex::new(Vec::new());
+        let adapter = |embeddings: Vec<EmbedData>| batches.lock().unwrap().push(embeddings);
+        let result = embed_file(
+            "../test_files/test.pdf",
+            &embedder,
+            Some(&config),
+            Some(adapter),
+        )
+        .await
+        .unwrap();
+        assert!(result.is_none());
+
+        let batches = batches.into_inner().unwrap();
+        assert!(!batches.is_empty());
+        let page_count = PdfProcessor::extract_pages("../test_files/test.pdf", false, None)
+            .unwrap()
+            .len();
+        for batch in &batches {
+            assert!(batch.len() <= 4);
+            for embedding in batch {
+                let page_number: usize = embedding.metadata.as_ref().unwrap()["page_number"]
+                    .parse()
+                    .unwrap();
+                assert!((1..=page_count).contains(&page_number));
+            }
+        }
+        let spans = batches
+            .iter()
+            .flatten()
+            .map(|embedding| {
+                let metadata = embedding.metadata.as_ref().unwrap();
+                let start: usize = metadata[START_CHAR_KEY].parse().unwrap();
+                let end: usize = metadata[END_CHAR_KEY].parse().unwrap();
+                (start, end)
+            })
+            .collect::<Vec<_>>();
+        assert!(spans.iter().all(|(start, end)| start < end));
+        assert!(spans.windows(2).all(|pair| pair[0].0 < pair[1].0));
+
+        let config = TextEmbedConfig::default().with_max_document_bytes(10);
+        let error = embed_file(
+            "../test_files/test.pdf",
+            &embedder,
+            Some(&config),
+            None::<fn(Vec<EmbedData>)>,
+        )
+        .await
+        .unwrap_err();
+        assert!(matches!(
+            error.downcast_ref::<FileLoadingError>(),
+            Some(FileLoadingError::DocumentTooLarge { max_bytes: 10, .. })
+        ));
+    }
 
-                            if let Err(e) = collector_tx.send(embeddings) {
-                                eprintln!("Error sending embeddings to collector: {:?}", e);
-                            }
-                        }
-                        Err(e) => eprintln!("Error processing chunks: {:?}", e),
-                    }
+    #[tokio::test(flavor = "multi_thread")]
+    async fn test_streaming_extraction_with_tables() {
+        let embedder = Embedder::from_pretrained_hf(
+            "bert",
+            "sentence-transformers/all-MiniLM-L6-v2",
+            None,
+            None,
+        )
+        .unwrap();
+        let config = TextEmbedConfig::default()
+            .with_streaming_extraction(true)
+            .with_table_mode(TableMode::Markdown);
+        let embeddings = embed_file(
+            "../test_files/table.pdf",
+            &embedder,
+            Some(&config),
+            None::<fn(Vec<EmbedData>)>,
+        )
+        .await
+        .unwrap()
+        .unwrap();
+        let pages =
+            PdfProcessor::extract_pages_with_tables("../test_files/table.pdf", TableMode::Markdown)
+                .unwrap();
+        for embedding in &embeddings {
+            let metadata = embedding.metadata.as_ref().unwrap();
+            let page_number: usize = metadata["page_number"].parse().unwrap();
+            let contains_table = pages[page_number - 1].1;
+            assert_eq!(metadata[CONTAINS_TABLE_KEY], contains_table.to_string());
+        }
+        assert!(embeddings
+            .iter()
+            .any(|embedding| embedding.metadata.as_ref().unwrap()[CONTAINS_TABLE_KEY] == "true"));
+    }
 
-                    chunk_buffer.clear();
-                    metadata_buffer.clear();
-                }
+    #[tokio::test(flavor = "multi_thread")]
+    async fn test_page_range() {
+        let embedder = Embedder::from_pretrained_hf(
+            "bert",
+            "sentence-transformers/all-MiniLM-L6-v2",
+            None,
+            None,
+        )
+        .unwrap();
+        let config = TextEmbedConfig::default()
+            .with_chunk_size(64, None)
+            .with_page_range(2, 3);
+        let embeddings = embed_file(
+            "../test_files/test.pdf",
+            &embedder,
+            Some(&config),
+            None::<fn(Vec<EmbedData>)>,
+        )
+        .await
+        .unwrap()
+        .unwrap();
+        assert!(!embeddings.is_empty());
+        assert!(embeddings
+            .iter()
+            .all(|embedding| embedding.metadata.as_ref().unwrap()["page_range"] == "2-3"));
+
+        let config = config
+            .with_streaming_extraction(true)
+            .with_detect_language(true);
+        let embeddings = embed_file(
+            "../test_files/test.pdf",
+            &embedder,
+            Some(&config),
+            None::<fn(Vec<EmbedData>)>,
+        )
+        .await
+        .unwrap()
+        .unwrap();
+        let page_numbers = embeddings
+            .iter()
+            .map(|embedding| embedding.metadata.as_ref().unwrap()["page_number"].as_str())
+            .collect::<std::collections::HashSet<_>>();
+        assert_eq!(page_numbers, ["2", "3"].into());
+        let languages = embeddings
+            .iter()
+            .filter_map(|embedding| embedding.metadata.as_ref().unwrap().get("language"))
+            .collect::<std::collections::HashSet<_>>();
+        assert!(languages.contains(&"eng".to_string()));
+
+        // test.pdf has 3 pages.
+        let config = TextEmbedConfig::default().with_page_range(2, 10);
+        let error = embed_file(
+            "../test_files/test.pdf",
+            &embedder,
+            Some(&config),
+            None::<fn(Vec<EmbedData>)>,
+        )
+        .await
+        .unwrap_err();
+        assert!(error.to_string().contains("2-10"));
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn test_sort_by_length() {
+        let embedder = Embedder::from_pretrained_hf(
+            "bert",
+            "sentence-transformers/all-MiniLM-L6-v2",
+            None,
+            None,
+        )
+        .unwrap();
+        let texts = (0..9)
+            .map(|i| format!("{} {}", "A sentence of some length.".repeat(9 - i), i))
+            .collect::<Vec<_>>();
+        let config = TextEmbedConfig::default().with_batch_size(2);
+        let unsorted = embed_query(texts.clone(), &embedder, Some(&config))
+            .await
+            .unwrap();
+        let config = config.with_sort_by_length(true);
+        let sorted = embed_query(texts.clone(), &embedder, Some(&config))
+            .await
+            .unwrap();
+        assert_eq!(sorted.len(), texts.len());
+        for ((text, unsorted), sorted) in texts.iter().zip(&unsorted).zip(&sorted) {
+            assert_eq!(sorted.text.as_ref(), Some(text));
+            assert!(unsorted.embedding.similarity(&sorted.embedding).unwrap() > 0.9999);
+        }
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn test_prepend_filename() {
+        let embedder = Arc::new(
+            Embedder::from_pretrained_hf(
+                "bert",
+                "sentence-transformers/all-MiniLM-L6-v2",
+                None,
+                None,
+            )
+            .unwrap(),
+        );
+        let directory = tempdir::TempDir::new("prepend").unwrap();
+        let file = directory.path().join("tokenizer_notes.txt");
+        std::fs::write(
+            &file,
+            "The cat sat on the mat. The dog slept by the door. Birds sang in the tall trees.",
+        )
+        .unwrap();
+        let config = TextEmbedConfig::default().with_chunk_size(8, None);
+        let embed = |config: TextEmbedConfig| {
+            let embedder = embedder.clone();
+            let file = file.clone();
+            async move {
+                embed_file(&file, &embedder, Some(&config), None::<fn(Vec<EmbedData>)>)
+                    .await
+                    .unwrap()
+                    .unwrap()
             }
+        };
+        let plain = embed(config.clone()).await;
+        let first = embed(config.clone().with_prepend_filename(true, false)).await;
+        let every = embed(config.clone().with_prepend_filename(true, true)).await;
+        assert!(plain.len() > 1);
+
+        // The path is embedded, not stored, and only changes the first chunk.
+        let similarity =
+            |a: &EmbedData, b: &EmbedData| a.embedding.similarity(&b.embedding).unwrap();
+        let path = file.to_string_lossy().to_string();
+        for (i, ((plain, first), every)) in plain.iter().zip(&first).zip(&every).enumerate() {
+            assert_eq!(first.text, plain.text);
+            assert_eq!(first.metadata.as_ref().unwrap()[FILE_PATH_KEY], path);
+            assert!(!plain.metadata.as_ref().unwrap().contains_key(FILE_PATH_KEY));
+            assert_eq!(similarity(plain, first) > 0.9999, i > 0);
+            assert!(similarity(plain, every) < 0.9999);
+        }
+        let text = format!("{}\n{}", path, plain[0].text.as_ref().unwrap());
+        let expected = embedder.embed(&[text], None).await.unwrap();
+        assert!(first[0].embedding.similarity(&expected[0]).unwrap() > 0.9999);
+
+        // Files of a directory are named relative to it.
+        let config = config.with_prepend_filename(true, false);
+        let embeddings = embed_directory_stream(
+            directory.path().to_path_buf(),
+            &embedder,
+            None,
+            Some(&config),
+            None::<fn(Vec<EmbedData>)>,
+        )
+        .await
+        .unwrap()
+        .unwrap();
+        assert_eq!(embeddings.len(), plain.len());
+        for embedding in &embeddings {
+            let metadata = embedding.metadata.as_ref().unwrap();
+            assert_eq!(metadata[FILE_PATH_KEY], "tokenizer_notes.txt");
+        }
+    }
 
-            // Process any remaining chunks
-            if !chunk_buffer.is_empty() {
-                match process_chunks(&chunk_buffer, &metadata_buffer, &embedder, batch_size).await {
-                    Ok(embeddings) => {
-                        let files = embeddings
-                            .iter()
-                            .cloned()
-                            .map(|e| e.metadata.unwrap().get("file_name").unwrap().to_string())
-                            .collect::<Vec<_>>();
-                        let unique_files = files.into_iter().unique().collect::<Vec<_>>();
-                        let old_len = files_processed.len() as u64;
-                        files_processed.extend(unique_files);
-                        let new_len = files_processed.len() as u64;
+    #[tokio::test(flavor = "multi_thread")]
+    async fn test_embed_file_oneshot() {
+        let embeddings = embed_file_oneshot(
+            "../test_files/test.txt",
+            "bert",
+            "sentence-transformers/all-MiniLM-L6-v2",
+            None,
+        )
+        .await
+        .unwrap();
+        assert!(!embeddings.is_empty());
+        assert_eq!(embeddings[0].embedding.to_dense().unwrap().len(), 384);
+    }
 
-                        pb.inc(new_len - old_len);
+    #[tokio::test(flavor = "multi_thread")]
+    async fn test_embed_file_multi() {
+        let dense = Embedder::from_pretrained_hf(
+            "bert",
+            "sentence-transformers/all-MiniLM-L6-v2",
+            None,
+            None,
+        )
+        .unwrap();
+        let sparse =
+            Embedder::from_pretrained_hf("sparse-bert", "prithivida/Splade_PP_en_v1", None, None)
+                .unwrap();
+        let embeddings = embed_file_multi(
+            "../test_files/test.txt",
+            &[("dense", &dense), ("sparse", &sparse)],
+            None,
+            None::<fn(Vec<EmbedData>)>,
+        )
+        .await
+        .unwrap()
+        .unwrap();
 
-                        if let Err(e) = collector_tx.send(embeddings) {
-                            eprintln!("Error sending embeddings to collector: {:?}", e);
-                        }
-                    }
-                    Err(e) => eprintln!("Error processing chunks: {:?}", e),
-                }
-            }
+        assert!(!embeddings.is_empty());
+        for embedding in &embeddings {
+            assert_eq!(embedding.embeddings.len(), 2);
+            let dense = embedding.embeddings["dense"].to_dense().unwrap();
+            assert_eq!(dense.len(), 384);
+            assert_eq!(embedding.embedding.to_dense().unwrap(), dense);
+            assert!(embedding.embeddings["sparse"].to_dense().unwrap().len() > 384);
         }
-    });
+    }
 
-    let textloader = TextLoader::new(chunk_size, overlap_ratio);
+    #[tokio::test(flavor = "multi_thread")]
+    async fn test_similarity_search() {
+        let embedder = Arc::new(
+            Embedder::from_pretrained_hf(
+                "bert",
+                "sentence-transformers/all-MiniLM-L6-v2",
+                None,
+                None,
+            )
+            .unwrap(),
+        );
+        let results = similarity_search(
+            "How do I make a loaf of bread?",
+            "../test_files/search",
+            &embedder,
+            None,
+            2,
+        )
+        .await
+        .unwrap();
+        assert_eq!(results.len(), 2);
+        assert!(results[0].score > results[1].score);
+        let file_name = &results[0].metadata.as_ref().unwrap()["file_name"];
+        assert!(file_name.ends_with("cooking.txt"));
+
+        let results = similarity_search(
+            "Which planet is the biggest?",
+            "../test_files/search/astronomy.txt",
+            &embedder,
+            None,
+            5,
+        )
+        .await
+        .unwrap();
+        assert_eq!(results.len(), 1);
+        assert!(results[0].text.as_ref().unwrap().contains("Jupiter"));
+    }
 
-    file_parser.files.iter().for_each(|file| {
-        let text = match TextLoader::extract_text(file, use_ocr, tesseract_path) {
-            Ok(text) => text,
-            Err(_) => {
-                return;
+    #[tokio::test(flavor = "multi_thread")]
+    async fn test_pairwise_similarity() {
+        let embedder = Embedder::from_pretrained_hf(
+            "bert",
+            "sentence-transformers/all-MiniLM-L6-v2",
+            None,
+            None,
+        )
+        .unwrap();
+        let texts = vec![
+            "The cat sat on the mat.".to_string(),
+            "A cat was sitting on the mat.".to_string(),
+            "Stock markets fell sharply today.".to_string(),
+        ];
+        let similarities = pairwise_similarity(&texts, &embedder, None).await.unwrap();
+        assert_eq!(similarities.dim(), (3, 3));
+        for i in 0..3 {
+            assert!((similarities[[i, i]] - 1.0).abs() < 1e-4);
+            for j in 0..3 {
+                assert!((similarities[[i, j]] - similarities[[j, i]]).abs() < 1e-6);
             }
+        }
+        assert!(similarities[[0, 1]] > similarities[[0, 2]]);
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn test_chunk_preview() {
+        let config = TextEmbedConfig::default().with_chunk_size(64, None);
+        let previews = chunk_preview("../test_files/test.pdf", Some(&config))
+            .await
+            .unwrap();
+        assert!(previews.len() > 1);
+        for (i, preview) in previews.iter().enumerate() {
+            assert_eq!(preview.chunk_index, i);
+            assert!(preview.token_count > 0 && preview.token_count <= 64);
+            assert!(preview.file.ends_with("test.pdf"));
+        }
+
+        let previews = chunk_preview("../test_files", Some(&config)).await.unwrap();
+        assert!(previews
+            .iter()
+            .any(|preview| preview.file.ends_with("test.md")));
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn test_embed_directory_with_unsupported_file() {
+        let directory = tempdir::TempDir::new("mixed").unwrap();
+        std::fs::copy("../test_files/test.txt", directory.path().join("test.txt")).unwrap();
+        std::fs::write(directory.path().join("archive.zip"), b"PK\x03\x04").unwrap();
+        let embedder = Arc::new(
+            Embedder::from_pretrained_hf(
+                "bert",
+                "sentence-transformers/all-MiniLM-L6-v2",
+                None,
+                None,
+            )
+            .unwrap(),
+        );
+        let extensions = Some(vec!["txt".to_string(), "zip".to_string()]);
+
+        let embeddings = embed_directory_stream(
+            directory.path().to_path_buf(),
+            &embedder,
+            extensions.clone(),
+            None,
+            None::<fn(Vec<EmbedData>)>,
+        )
+        .await
+        .unwrap()
+        .unwrap();
+        assert!(!embeddings.is_empty());
+        for embedding in &embeddings {
+            let metadata = embedding.metadata.as_ref().unwrap();
+            assert!(metadata["file_name"].ends_with("test.txt"));
+        }
+
+        let config = TextEmbedConfig::default().with_error_on_unsupported(true);
+        let result = embed_directory_stream(
+            directory.path().to_path_buf(),
+            &embedder,
+            extensions,
+            Some(&config),
+            None::<fn(Vec<EmbedData>)>,
+        )
+        .await;
+        assert!(result.is_err());
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn test_embed_archives() {
+        let embedder = Arc::new(
+            Embedder::from_pretrained_hf(
+                "bert",
+                "sentence-transformers/all-MiniLM-L6-v2",
+                None,
+                None,
+            )
+            .unwrap(),
+        );
+        use std::collections::BTreeSet;
+
+        let config = TextEmbedConfig::default().with_extract_archives(true);
+        let inner_files = |embeddings: &[EmbedData]| {
+            embeddings
+                .iter()
+                .map(|embedding| {
+                    let metadata = embedding.metadata.as_ref().unwrap();
+                    assert_eq!(
+                        metadata["file_name"],
+                        format!("{}/{}", metadata["archive"], metadata["archive_path"])
+                    );
+                    metadata["archive_path"].clone()
+                })
+                .collect::<BTreeSet<_>>()
         };
-        let chunks = textloader
-            .split_into_chunks(&text, SplittingStrategy::Sentence, None)
-            .unwrap_or_else(|| vec![text.clone()])
+
+        for archive in ["../test_files/archive.zip", "../test_files/archive.tar.gz"] {
+            let embeddings = embed_file(
+                archive,
+                &embedder,
+                Some(&config),
+                None::<fn(Vec<EmbedData>)>,
+            )
+            .await
+            .unwrap()
+            .unwrap();
+            assert_eq!(
+                inner_files(&embeddings),
+                BTreeSet::from(["notes/first.txt", "notes/second.md"].map(String::from))
+            );
+            let mountains = embeddings
+                .iter()
+                .find(|embedding| embedding.text.as_ref().unwrap().contains("mountains"))
+                .unwrap();
+            assert_eq!(
+                mountains.metadata.as_ref().unwrap()["archive_path"],
+                "notes/second.md"
+            );
+        }
+
+        let directory = tempdir::TempDir::new("archives").unwrap();
+        std::fs::copy("../test_files/test.txt", directory.path().join("test.txt")).unwrap();
+        std::fs::copy(
+            "../test_files/archive.zip",
+            directory.path().join("archive.zip"),
+        )
+        .unwrap();
+        let extensions = Some(vec!["txt".to_string(), "zip".to_string()]);
+        let embeddings = embed_directory_stream(
+            directory.path().to_path_buf(),
+            &embedder,
+            extensions,
+            Some(&config.clone().with_error_on_unsupported(true)),
+            None::<fn(Vec<EmbedData>)>,
+        )
+        .await
+        .unwrap()
+        .unwrap();
+        let (inner, outer): (Vec<_>, Vec<_>) = embeddings
             .into_iter()
-            .filter(|chunk| !chunk.trim().is_empty())
+            .partition(|embedding| embedding.metadata.as_ref().unwrap().contains_key("archive"));
+        // The markdown file of the archive doesn't have a requested extension.
+        assert_eq!(
+            inner_files(&inner),
+            BTreeSet::from(["notes/first.txt".to_string()])
+        );
+        assert!(inner[0].metadata.as_ref().unwrap()["archive"].ends_with("archive.zip"));
+        assert!(!outer.is_empty());
+
+        // Zip bombs are stopped by the size limit.
+        let config = config.with_max_archive_bytes(16);
+        let result = embed_file(
+            "../test_files/archive.zip",
+            &embedder,
+            Some(&config),
+            None::<fn(Vec<EmbedData>)>,
+        )
+        .await;
+        assert!(result.is_err());
+    }
+
+    /// Embeds every text as its length in bytes, slowly like a model would, counting the
+    /// texts it embeds.
+    struct SlowEmbedder {
+        embedded: Arc<std::sync::atomic::AtomicUsize>,
+    }
+
+    impl BertEmbed for SlowEmbedder {
+        fn embed(
+            &self,
+            text_batch: &[String],
+            _batch_size: Option<usize>,
+        ) -> Result<Vec<EmbeddingResult>> {
+            std::thread::sleep(std::time::Duration::from_millis(20));
+            self.embedded
+                .fetch_add(text_batch.len(), std::sync::atomic::Ordering::Relaxed);
+            Ok(text_batch
+                .iter()
+                .map(|text| EmbeddingResult::DenseVector(vec![text.len() as f32, 1.0]))
+                .collect())
+        }
+    }
+
+    #[tokio::test(flavor = "multi_thread", worker_threads = 4)]
+    async fn test_embed_directory_with_embedder_pool() {
+        let directory = tempdir::TempDir::new("pool").unwrap();
+        for i in 0..12 {
+            let text = (0..5)
+                .map(|j| format!("File {} has sentence number {}.", i, j))
+                .collect::<Vec<_>>()
+                .join(" ");
+            std::fs::write(directory.path().join(format!("{}.txt", i)), text).unwrap();
+        }
+        let counters = (0..3)
+            .map(|_| Arc::new(std::sync::atomic::AtomicUsize::new(0)))
             .collect::<Vec<_>>();
-        if chunks.is_empty() {
-            return;
+        let embedders = counters
+            .iter()
+            .enumerate()
+            .map(|(i, embedded)| {
+                let embedder = SlowEmbedder {
+                    embedded: embedded.clone(),
+                };
+                let embedder = Embedder::Text(TextEmbedder::Bert(Box::new(embedder)));
+                (format!("cpu:{}", i), Arc::new(embedder))
+            })
+            .collect::<Vec<_>>();
+        let pool = Arc::new(EmbedderPool::new(embedders).unwrap());
+        let config = TextEmbedConfig::default()
+            .with_chunk_size(16, None)
+            .with_buffer_size(2)
+            .with_embedder_pool(pool.clone());
+
+        let embeddings = embed_directory_stream(
+            directory.path().to_path_buf(),
+            &pool.embedders()[0].1,
+            None,
+            Some(&config),
+            None::<fn(Vec<EmbedData>)>,
+        )
+        .await
+        .unwrap()
+        .unwrap();
+
+        let counts = counters
+            .iter()
+            .map(|embedded| embedded.load(std::sync::atomic::Ordering::Relaxed))
+            .collect::<Vec<_>>();
+        assert!(counts.iter().all(|&count| count > 0), "{:?}", counts);
+        assert_eq!(counts.iter().sum::<usize>(), embeddings.len());
+        // Every word of every file is embedded once.
+        let words = embeddings
+            .iter()
+            .map(|embedding| {
+                embedding
+                    .text
+                    .as_deref()
+                    .unwrap()
+                    .split_whitespace()
+                    .count()
+            })
+            .sum::<usize>();
+        assert_eq!(words, 12 * 5 * 6);
+        let files = embeddings
+            .iter()
+            .map(|embedding| embedding.metadata.as_ref().unwrap()["file_name"].clone())
+            .unique()
+            .count();
+        assert_eq!(files, 12);
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn test_embed_directory_on_devices() {
+        let directory = tempdir::TempDir::new("devices").unwrap();
+        for i in 0..4 {
+            let text = format!("File {} is embedded by a copy of the model.", i);
+            std::fs::write(directory.path().join(format!("{}.txt", i)), text).unwrap();
         }
-        let metadata = TextLoader::get_metadata(file).unwrap();
-        for chunk in chunks {
-            if let Err(e) = tx.send((chunk, Some(metadata.clone()))) {
-                eprintln!("Error sending chunk: {:?}", e);
-            }
+        let embedded = Arc::new(std::sync::atomic::AtomicUsize::new(0));
+        let loads = Arc::new(std::sync::atomic::AtomicUsize::new(0));
+        let load = |embedded: &Arc<std::sync::atomic::AtomicUsize>| {
+            let embedder = SlowEmbedder {
+                embedded: embedded.clone(),
+            };
+            Embedder::Text(TextEmbedder::Bert(Box::new(embedder)))
+        };
+        let loader: ModelLoader = {
+            let (embedded, loads) = (embedded.clone(), loads.clone());
+            Arc::new(move || {
+                loads.fetch_add(1, std::sync::atomic::Ordering::Relaxed);
+                Ok(Arc::new(load(&embedded)))
+            })
+        };
+        let config = TextEmbedConfig::default()
+            .with_devices(vec!["cpu".to_string(), "cpu".to_string()], loader);
+        let unused = Arc::new(std::sync::atomic::AtomicUsize::new(0));
+
+        let embeddings = embed_directory_stream(
+            directory.path().to_path_buf(),
+            &Arc::new(load(&unused)),
+            None,
+            Some(&config),
+            None::<fn(Vec<EmbedData>)>,
+        )
+        .await
+        .unwrap()
+        .unwrap();
+
+        assert_eq!(loads.load(std::sync::atomic::Ordering::Relaxed), 2);
+        // The copies embed every chunk, the embedder passed in none.
+        assert_eq!(
+            embedded.load(std::sync::atomic::Ordering::Relaxed),
+            embeddings.len()
+        );
+        assert_eq!(unused.load(std::sync::atomic::Ordering::Relaxed), 0);
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn test_tuned_batch_size_is_kept() {
+        let embedded = Arc::new(std::sync::atomic::AtomicUsize::new(0));
+        let embedder = Embedder::Text(TextEmbedder::Bert(Box::new(SlowEmbedder {
+            embedded: embedded.clone(),
+        })));
+        let sample = vec!["A chunk of the first file.".to_string()];
+
+        let batch_size = embedder.tune_batch_size(&sample, 8).await;
+        let probed = embedded.load(std::sync::atomic::Ordering::Relaxed);
+        assert!(probed > 0);
+
+        assert_eq!(embedder.tune_batch_size(&sample, 8).await, batch_size);
+        assert_eq!(embedded.load(std::sync::atomic::Ordering::Relaxed), probed);
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn test_embed_with_progress_stream() {
+        use futures::StreamExt;
+
+        let directory = tempdir::TempDir::new("progress").unwrap();
+        for i in 0..3 {
+            let text = format!("File {} has a first sentence. And a second one.", i);
+            std::fs::write(directory.path().join(format!("{}.txt", i)), text).unwrap();
         }
-    });
+        std::fs::write(directory.path().join("broken.pdf"), "not a pdf").unwrap();
+        let embedded = Arc::new(std::sync::atomic::AtomicUsize::new(0));
+        let embedder = Embedder::Text(TextEmbedder::Bert(Box::new(SlowEmbedder {
+            embedded: embedded.clone(),
+        })));
+
+        let events =
+            embed_with_progress_stream(directory.path().to_path_buf(), &embedder, None, None)
+                .unwrap()
+                .collect::<Vec<_>>()
+                .await;
+
+        let progress = events
+            .iter()
+            .filter_map(|event| match event {
+                EmbedEvent::Progress(progress) => Some(progress.clone()),
+                _ => None,
+            })
+            .collect::<Vec<_>>();
+        assert_eq!(progress.len(), 5);
+        assert_eq!(progress[0].files_done, 0);
+        assert!(progress
+            .windows(2)
+            .all(|pair| pair[0].files_done + 1 == pair[1].files_done));
+        let EmbedEvent::Progress(last) = events.last().unwrap() else {
+            panic!("The last event is not progress");
+        };
+        assert!(last.is_finished());
+        assert_eq!(last.fraction(), 1.0);
+
+        let errors = events
+            .iter()
+            .filter_map(|event| match event {
+                EmbedEvent::Error(file, _) => Some(file.as_str()),
+                _ => None,
+            })
+            .collect::<Vec<_>>();
+        assert_eq!(errors.len(), 1);
+        assert!(errors[0].ends_with("broken.pdf"));
+        let embeddings = events
+            .iter()
+            .map(|event| match event {
+                EmbedEvent::Data(data) => data.len(),
+                _ => 0,
+            })
+            .sum::<usize>();
+        assert_eq!(embeddings, last.embeddings);
+        assert_eq!(
+            embeddings,
+            embedded.load(std::sync::atomic::Ordering::Relaxed)
+        );
+    }
 
-    drop(tx);
+    /// Embeds texts in 2 dimensions, except those mentioning "corrupt" which get 3.
+    struct DriftingEmbedder;
+
+    impl BertEmbed for DriftingEmbedder {
+        fn embed(
+            &self,
+            text_batch: &[String],
+            _batch_size: Option<usize>,
+        ) -> Result<Vec<EmbeddingResult>> {
+            Ok(text_batch
+                .iter()
+                .map(|text| {
+                    let dimension = if text.contains("corrupt") { 3 } else { 2 };
+                    EmbeddingResult::DenseVector(vec![1.0; dimension])
+                })
+                .collect())
+        }
 
-    let mut all_embeddings = Vec::new();
-    while let Some(embeddings) = collector_rx.recv().await {
-        if let Some(adapter) = &adapter {
-            adapter(embeddings.to_vec());
-        } else {
-            all_embeddings.extend(embeddings.to_vec());
+        fn model_metadata(&self) -> ModelMetadata {
+            ModelMetadata::new("bert", "drifting", None).with_dimension(Some(2))
         }
     }
-    // Wait for the spawned task to complete
-    processing_task.await.unwrap();
 
-    if adapter.is_some() {
-        Ok(None)
-    } else {
-        Ok(Some(all_embeddings))
+    #[tokio::test(flavor = "multi_thread")]
+    async fn test_validate_dimensions() {
+        let directory = tempdir::TempDir::new("dimensions").unwrap();
+        std::fs::write(directory.path().join("good.txt"), "A perfectly fine file.").unwrap();
+        std::fs::write(directory.path().join("bad.txt"), "A corrupt file.").unwrap();
+        let embedder = Arc::new(Embedder::Text(TextEmbedder::Bert(Box::new(
+            DriftingEmbedder,
+        ))));
+        let config = TextEmbedConfig::default().with_validate_dimensions(true);
+
+        let error = embed_file(
+            directory.path().join("bad.txt"),
+            &embedder,
+            Some(&config),
+            None::<fn(Vec<EmbedData>)>,
+        )
+        .await
+        .unwrap_err();
+        assert!(error.to_string().contains("dimension 3 instead of 2"));
+        assert!(error.to_string().contains("bad.txt"));
+        // Without validation, the wrong vector goes through.
+        embed_file(
+            directory.path().join("bad.txt"),
+            &embedder,
+            None,
+            None::<fn(Vec<EmbedData>)>,
+        )
+        .await
+        .unwrap();
+
+        // Directories skip the chunk and carry on.
+        let embeddings = embed_directory_stream(
+            directory.path().to_path_buf(),
+            &embedder,
+            None,
+            Some(&config),
+            None::<fn(Vec<EmbedData>)>,
+        )
+        .await
+        .unwrap()
+        .unwrap();
+        assert_eq!(embeddings.len(), 1);
+        assert_eq!(
+            embeddings[0].text.as_deref(),
+            Some("A perfectly fine file.")
+        );
     }
-}
 
-pub async fn process_chunks(
-    chunks: &Vec<String>,
-    metadata: &Vec<Option<HashMap<String, String>>>,
-    embedding_model: &Arc<Embedder>,
-    batch_size: Option<usize>,
-) -> Result<Arc<Vec<EmbedData>>> {
-    let encodings = embedding_model.embed(chunks, batch_size).await?;
+    /// Counts the records it receives, and how many it had received on each flush. Clones
+    /// share the counts.
+    #[derive(Clone, Default)]
+    struct CountingAdapter {
+        upserted: Arc<std::sync::Mutex<usize>>,
+        flushes: Arc<std::sync::Mutex<Vec<usize>>>,
+    }
 
-    // zip encodings with chunks and metadata
-    let embeddings = encodings
-        .into_iter()
-        .zip(chunks)
-        .zip(metadata)
-        .map(|((encoding, chunk), metadata)| {
-            EmbedData::new(encoding.clone(), Some(chunk.clone()), metadata.clone())
-        })
-        .collect::<Vec<_>>();
-    Ok(Arc::new(embeddings))
+    impl Adapter for CountingAdapter {
+        fn upsert(&self, data: Vec<EmbedData>) {
+            *self.upserted.lock().unwrap() += data.len();
+        }
+
+        fn flush(&self) {
+            let upserted = *self.upserted.lock().unwrap();
+            self.flushes.lock().unwrap().push(upserted);
+        }
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn test_adapter_is_flushed_once() {
+        let directory = tempdir::TempDir::new("flush").unwrap();
+        for i in 0..3 {
+            let text = format!("File {} has a first sentence. And a second one.", i);
+            std::fs::write(directory.path().join(format!("{}.txt", i)), text).unwrap();
+        }
+        let embedded = Arc::new(std::sync::atomic::AtomicUsize::new(0));
+        let embedder = Arc::new(Embedder::Text(TextEmbedder::Bert(Box::new(SlowEmbedder {
+            embedded: embedded.clone(),
+        }))));
+        let config = TextEmbedConfig::default()
+            .with_chunk_size(8, None)
+            .with_buffer_size(2);
+
+        let adapter = CountingAdapter::default();
+        let result = embed_directory_stream(
+            directory.path().to_path_buf(),
+            &embedder,
+            None,
+            Some(&config),
+            Some(adapter.clone()),
+        )
+        .await
+        .unwrap();
+        assert!(result.is_none());
+        let upserted = *adapter.upserted.lock().unwrap();
+        assert!(upserted > 2);
+        assert_eq!(
+            upserted,
+            embedded.load(std::sync::atomic::Ordering::Relaxed)
+        );
+        assert_eq!(*adapter.flushes.lock().unwrap(), [upserted]);
+
+        let adapter = CountingAdapter::default();
+        embed_file(
+            directory.path().join("0.txt"),
+            &embedder,
+            Some(&config),
+            Some(adapter.clone()),
+        )
+        .await
+        .unwrap();
+        let upserted = *adapter.upserted.lock().unwrap();
+        assert!(upserted > 0);
+        assert_eq!(*adapter.flushes.lock().unwrap(), [upserted]);
+    }
+
+    #[tokio::test]
+    async fn test_embed_diff() {
+        let embedder = Embedder::Text(TextEmbedder::Bert(Box::new(SlowEmbedder {
+            embedded: Arc::new(std::sync::atomic::AtomicUsize::new(0)),
+        })));
+        let paragraphs = [
+            "The river starts high in the mountains and runs through a narrow valley.",
+            "Farmers along its banks grow wheat and barley in the summer months.",
+            "The river finally reaches the sea near a small fishing town.",
+        ];
+        let old_text = paragraphs.join("\n\n");
+        let edited = "Farmers along its banks grow corn and barley in the summer months.";
+        let new_text = [paragraphs[0], edited, paragraphs[2]].join("\n\n");
+        // Small enough chunks that every paragraph is a chunk.
+        let config = TextEmbedConfig::default().with_chunk_size(20, None);
+
+        let diff = embed_diff(&old_text, &new_text, &embedder, Some(&config))
+            .await
+            .unwrap();
+        assert!(diff.added.is_empty());
+        assert!(diff.removed.is_empty());
+        assert_eq!(diff.unchanged.len(), 2);
+        assert_eq!(diff.modified.len(), 1);
+        assert_eq!(diff.modified[0].old.text.as_deref(), Some(paragraphs[1]));
+        assert_eq!(diff.modified[0].new.text.as_deref(), Some(edited));
+
+        let diff = embed_diff(&old_text, &old_text, &embedder, Some(&config))
+            .await
+            .unwrap();
+        assert!(diff.is_unchanged());
+    }
+
+    struct StubGenerator;
+
+    impl TextGenerator for StubGenerator {
+        fn generate<'a>(
+            &'a self,
+            query: &'a str,
+        ) -> futures::future::BoxFuture<'a, Result<String>> {
+            Box::pin(async move {
+                match query {
+                    "" => Ok("  ".to_string()),
+                    _ => Ok(format!("Rivers form where rain runs off. {}", query)),
+                }
+            })
+        }
+    }
+
+    #[tokio::test]
+    async fn test_embed_query_hyde() {
+        let embedded = Arc::new(std::sync::atomic::AtomicUsize::new(0));
+        let embedder = Embedder::Text(TextEmbedder::Bert(Box::new(SlowEmbedder {
+            embedded: embedded.clone(),
+        })));
+        let query = "How do rivers form?";
+        let document = StubGenerator.generate(query).await.unwrap();
+
+        let embedding = embed_query_hyde(query, &StubGenerator, &embedder, None)
+            .await
+            .unwrap();
+        // The generated document is embedded, not the query.
+        assert_eq!(
+            embedding.embedding.to_dense().unwrap(),
+            vec![document.len() as f32, 1.0]
+        );
+        assert_eq!(embedding.text.as_deref(), Some(document.as_str()));
+        assert_eq!(embedding.metadata.unwrap()[HYDE_QUERY_KEY], query);
+        assert_eq!(embedded.load(std::sync::atomic::Ordering::Relaxed), 1);
+
+        assert!(embed_query_hyde("", &StubGenerator, &embedder, None)
+            .await
+            .is_err());
+    }
+
+    #[cfg(feature = "audio")]
+    #[tokio::test(flavor = "multi_thread")]
+    async fn test_emb_audio_directory() {
+        let directory = tempdir::TempDir::new("audio").unwrap();
+        std::fs::copy(
+            "../test_files/audio/samples_jfk.wav",
+            directory.path().join("samples_jfk.wav"),
+        )
+        .unwrap();
+        // Not a WAV file, it fails to decode and is skipped.
+        std::fs::write(directory.path().join("broken.wav"), "not audio").unwrap();
+        let mut audio_decoder = AudioDecoderModel::from_pretrained(
+            Some("openai/whisper-tiny.en"),
+            Some("main"),
+            "tiny-en",
+            false,
+        )
+        .unwrap();
+        let embedder = Arc::new(
+            Embedder::from_pretrained_hf(
+                "bert",
+                "sentence-transformers/all-MiniLM-L6-v2",
+                None,
+                None,
+            )
+            .unwrap(),
+        );
+
+        let embeddings = emb_audio_directory(
+            directory.path().to_path_buf(),
+            &mut audio_decoder,
+            &embedder,
+            None,
+        )
+        .await
+        .unwrap();
+        assert!(!embeddings.is_empty());
+        for (i, embedding) in embeddings.iter().enumerate() {
+            let metadata = embedding.metadata.as_ref().unwrap();
+            assert!(metadata["file_name"].ends_with("samples_jfk.wav"));
+            assert_eq!(metadata["segment_index"], i.to_string());
+            assert!(metadata.contains_key("start"));
+        }
+    }
+
+    #[cfg(feature = "audio")]
+    #[tokio::test(flavor = "multi_thread")]
+    async fn test_emb_audio_with_transcript() {
+        let mut audio_decoder = AudioDecoderModel::from_pretrained(
+            Some("openai/whisper-tiny.en"),
+            Some("main"),
+            "tiny-en",
+            false,
+        )
+        .unwrap();
+        let embedder = Arc::new(
+            Embedder::from_pretrained_hf(
+                "bert",
+                "sentence-transformers/all-MiniLM-L6-v2",
+                None,
+                None,
+            )
+            .unwrap(),
+        );
+
+        let audio = emb_audio_with_transcript(
+            "../test_files/audio/samples_jfk.wav",
+            &mut audio_decoder,
+            &embedder,
+            None,
+        )
+        .await
+        .unwrap();
+        assert!(audio.transcript.to_lowercase().contains("country"));
+        let segment_texts = audio
+            .embeddings
+            .iter()
+            .map(|embedding| embedding.text.as_deref().unwrap().trim())
+            .filter(|text| !text.is_empty())
+            .collect::<Vec<_>>();
+        assert_eq!(audio.transcript, segment_texts.join(" "));
+    }
 }
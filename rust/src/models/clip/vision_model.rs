@@ -82,7 +82,9 @@ impl ClipVisionEmbeddings {
         let class_embedding = if vs.contains_tensor("class_embedding") {
             vs.get(c.hidden_size, "class_embedding")?
         } else {
-            Tensor::randn(0f32, 1f32, c.hidden_size, vs.device())?
+            // Checkpoints always have it. Zeros instead of a random init keep the embeddings
+            // deterministic if one doesn't.
+            Tensor::zeros(c.hidden_size, vs.dtype(), vs.device())?
         };
 
         let num_patches = (c.image_size / c.patch_size).pow(2);
@@ -6,26 +6,57 @@ use std::{
 };
 
 use crate::{
-    chunkers::statistical::StatisticalChunker,
-    embeddings::{embed::TextEmbedder, local::jina::JinaEmbedder},
-    file_processor::docx_processor::DocxProcessor,
-};
-use crate::{
-    embeddings::embed::Embedder,
-    file_processor::{markdown_processor::MarkdownProcessor, txt_processor::TxtProcessor},
+    chunkers::{
+        code::{CodeChunk, CodeLanguage, CodeSplitter},
+        sentence::{SentenceBackend, SentenceSplitter},
+        statistical::StatisticalChunker,
+        window::{sliding_windows, WindowChunk},
+    },
+    embeddings::{
+        embed::{Embedder, TextEmbedder},
+        local::jina::JinaEmbedder,
+    },
+    file_processor::{
+        extractor::{registered_extractor, Extractor, HtmlExtractor, PdfExtractor, TextExtractor},
+        pdf_processor::PdfProcessor,
+    },
 };
 use anyhow::Error;
 use chrono::{DateTime, Local};
 use text_splitter::{ChunkConfig, TextSplitter};
 use tokenizers::Tokenizer;
 
-use super::file_processor::pdf_processor::PdfProcessor;
 use rayon::prelude::*;
 
 #[derive(Clone, Copy)]
 pub enum SplittingStrategy {
     Sentence,
     Semantic,
+    /// Splits source code at function and class boundaries. The symbol name and language of
+    /// every chunk are added to its metadata.
+    Code {
+        language: CodeLanguage,
+    },
+    /// Splits the text into windows of `window_tokens` tokens starting every `stride_tokens`
+    /// tokens, for dense retrieval over long documents. Unlike `overlap_ratio`, the stride is
+    /// set independently of the window, and a stride larger than the window samples the text.
+    /// The token span of every window is added to its metadata, see
+    /// [sliding_windows](crate::chunkers::window::sliding_windows). The chunk size and the
+    /// minimum chunk size are ignored.
+    SlidingWindow {
+        window_tokens: usize,
+        stride_tokens: usize,
+    },
+}
+
+/// What to do with a chunk smaller than the minimum chunk size.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum MinChunkPolicy {
+    /// Append the chunk to the previous one. A small first chunk is prepended to the next one.
+    #[default]
+    Merge,
+    /// Leave the chunk out.
+    Drop,
 }
 
 impl Default for TextLoader {
@@ -34,54 +65,240 @@ impl Default for TextLoader {
     }
 }
 
+/// Why the text of a file couldn't be read. It can be recovered from the [Error] returned by
+/// [TextLoader::extract_text] with `downcast_ref::<FileLoadingError>()`.
 #[derive(Debug)]
 pub enum FileLoadingError {
     FileNotFound(String),
+    /// Holds the extension of the file.
     UnsupportedFileType(String),
+    PermissionDenied(String),
+    /// A password protected file, currently detected for PDF files.
+    EncryptedFile(String),
+    /// The extractor of the file failed, e.g. on a corrupted file.
+    ExtractionFailed {
+        path: String,
+        source: Error,
+    },
+    /// The file was read but holds no text, e.g. a scanned PDF read without OCR.
+    EmptyDocument(String),
+    /// The file is bigger than
+    /// [TextEmbedConfig::max_document_bytes](crate::config::TextEmbedConfig::max_document_bytes).
+    DocumentTooLarge {
+        path: String,
+        size: u64,
+        max_bytes: u64,
+    },
+}
+
+impl FileLoadingError {
+    /// Classifies an I/O error raised while reading `path`.
+    pub fn from_io(path: &std::path::Path, error: std::io::Error) -> Self {
+        let path = path.to_string_lossy().to_string();
+        match error.kind() {
+            std::io::ErrorKind::NotFound => FileLoadingError::FileNotFound(path),
+            std::io::ErrorKind::PermissionDenied => FileLoadingError::PermissionDenied(path),
+            _ => FileLoadingError::ExtractionFailed {
+                path,
+                source: error.into(),
+            },
+        }
+    }
 }
+
 impl Display for FileLoadingError {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         match self {
             FileLoadingError::FileNotFound(file) => write!(f, "File not found: {}", file),
-            FileLoadingError::UnsupportedFileType(file) => {
-                write!(f, "Unsupported file type: {}", file)
+            FileLoadingError::UnsupportedFileType(file) => write!(
+                f,
+                "Unsupported file type: {}. Currently supported file types are: pdf, md, txt, docx, rtf, odt, html, rs, py, js, jsx, ts, tsx, and the types of registered extractors",
+                file
+            ),
+            FileLoadingError::PermissionDenied(file) => write!(f, "Permission denied: {}", file),
+            FileLoadingError::EncryptedFile(file) => {
+                write!(f, "Encrypted file: {}. Decrypt it before embedding it", file)
+            }
+            FileLoadingError::ExtractionFailed { path, source } => {
+                write!(f, "Failed to extract the text of {}: {}", path, source)
             }
+            FileLoadingError::EmptyDocument(file) => write!(f, "No text found in {}", file),
+            FileLoadingError::DocumentTooLarge {
+                path,
+                size,
+                max_bytes,
+            } => write!(
+                f,
+                "{} is {} bytes, more than the maximum of {} bytes",
+                path, size, max_bytes
+            ),
+        }
+    }
+}
+
+impl std::error::Error for FileLoadingError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            FileLoadingError::ExtractionFailed { source, .. } => Some(source.as_ref()),
+            _ => None,
         }
     }
 }
 
-impl From<FileLoadingError> for Error {
-    fn from(error: FileLoadingError) -> Self {
-        match error {
-            FileLoadingError::FileNotFound(file) => {
-                Error::msg(format!("File not found: {:?}", file))
+/// Location of a page within [ExtractedDocument::text].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct PageBoundary {
+    /// Page number, starting at 1.
+    pub page_number: usize,
+    /// Byte offset of the first character of the page.
+    pub start: usize,
+    /// Byte offset just past the last character of the page.
+    pub end: usize,
+}
+
+/// The text of a file together with what was learned while extracting it.
+#[derive(Debug, Clone)]
+pub struct ExtractedDocument {
+    pub text: String,
+    /// Page boundaries for formats that have pages, currently PDF. Pages are separated by a
+    /// blank line in `text`. Empty for other formats.
+    pub pages: Vec<PageBoundary>,
+    /// The detected type, which is the file extension, e.g. "pdf" or "rs".
+    pub file_type: String,
+    /// File system metadata as returned by [TextLoader::get_metadata], plus `file_type` and
+    /// `page_count` for paged formats.
+    pub metadata: HashMap<String, String>,
+}
+
+impl ExtractedDocument {
+    /// The text of page `page_number`, starting at 1.
+    pub fn page_text(&self, page_number: usize) -> Option<&str> {
+        self.pages
+            .iter()
+            .find(|page| page.page_number == page_number)
+            .map(|page| &self.text[page.start..page.end])
+    }
+
+    /// The page containing the byte offset `offset` of `text`, e.g. the start of a chunk.
+    pub fn page_at(&self, offset: usize) -> Option<usize> {
+        let last_page = self.pages.last()?.page_number;
+        self.pages
+            .iter()
+            .find(|page| offset < page.end || page.page_number == last_page)
+            .map(|page| page.page_number)
+    }
+
+    /// Keeps the pages `first` to `last`, both included. Pages keep their number, so the text
+    /// may start with page 10. Fails when the range is not within the pages of the document.
+    pub fn select_pages(&mut self, first: usize, last: usize) -> Result<(), Error> {
+        check_page_range(first, last, self.pages.len())?;
+        let start = self.pages[first - 1].start;
+        let end = self.pages[last - 1].end;
+        self.text = self.text[start..end].to_string();
+        self.pages
+            .retain(|page| (first..=last).contains(&page.page_number));
+        for page in &mut self.pages {
+            page.start -= start;
+            page.end -= start;
+        }
+        Ok(())
+    }
+
+    /// Keeps the first `max_pages` pages and at most `max_chars` characters of the text. Pages
+    /// past the new end are dropped. Returns whether anything was removed.
+    pub fn truncate(&mut self, max_pages: Option<usize>, max_chars: Option<usize>) -> bool {
+        let mut end = self.text.len();
+        if let Some(max_pages) = max_pages {
+            if self.pages.len() > max_pages {
+                end = match max_pages.checked_sub(1) {
+                    Some(last_page) => self.pages[last_page].end,
+                    None => 0,
+                };
             }
-            FileLoadingError::UnsupportedFileType(file) => Error::msg(format!(
-                "Unsupported file type: {:?}. Currently supported file types are: pdf, md, txt, docx",
-                file
-            )),
         }
+        if let Some(max_chars) = max_chars {
+            if let Some((offset, _)) = self.text[..end].char_indices().nth(max_chars) {
+                end = offset;
+            }
+        }
+        if end == self.text.len() {
+            return false;
+        }
+        self.text.truncate(end);
+        self.pages.retain(|page| page.start < end);
+        if let Some(last_page) = self.pages.last_mut() {
+            last_page.end = last_page.end.min(end);
+        }
+        true
     }
 }
 
 #[derive(Debug)]
 pub struct TextLoader {
     pub splitter: TextSplitter<Tokenizer>,
+    tokenizer: Tokenizer,
+    chunk_size: usize,
+    overlap_ratio: f32,
+    sentence_splitter: Option<SentenceSplitter>,
+    min_chunk_size: Option<usize>,
+    min_chunk_policy: MinChunkPolicy,
 }
 impl TextLoader {
     pub fn new(chunk_size: usize, overlap_ratio: f32) -> Self {
+        let tokenizer = Tokenizer::from_pretrained("BEE-spoke-data/cl100k_base-mlm", None).unwrap();
         Self {
             splitter: TextSplitter::new(
                 ChunkConfig::new(chunk_size)
                     .with_overlap(chunk_size * overlap_ratio as usize)
                     .unwrap()
-                    .with_sizer(
-                        Tokenizer::from_pretrained("BEE-spoke-data/cl100k_base-mlm", None).unwrap(),
-                    ),
+                    .with_sizer(tokenizer.clone()),
             ),
             // splitter: TextSplitter::new(ChunkConfig::new(chunk_size)),
+            tokenizer,
+            chunk_size,
+            overlap_ratio,
+            sentence_splitter: None,
+            min_chunk_size: None,
+            min_chunk_policy: MinChunkPolicy::default(),
         }
     }
+
+    /// Uses locale aware sentence segmentation for [SplittingStrategy::Sentence]. `language` is
+    /// an ISO 639-1 code such as "fr", "zh" or "ja". Passing None keeps the default splitter.
+    pub fn with_language(mut self, language: Option<&str>) -> Self {
+        self.sentence_splitter = language.map(|language| SentenceSplitter::new(Some(language)));
+        self
+    }
+
+    /// Splits sentences with `backend` for [SplittingStrategy::Sentence], keeping the language
+    /// set with [TextLoader::with_language]. Passing None keeps the default splitter.
+    pub fn with_sentence_backend(mut self, backend: Option<SentenceBackend>) -> Self {
+        if let Some(backend) = backend {
+            let splitter = self.sentence_splitter.take().unwrap_or_default();
+            self.sentence_splitter = Some(splitter.with_backend(backend));
+        }
+        self
+    }
+
+    /// Merges or drops chunks of fewer than `min_chunk_size` tokens, such as a trailing list
+    /// item, depending on `policy`. Chunks split by [SplittingStrategy::Code] are kept as is.
+    pub fn with_min_chunk_size(
+        mut self,
+        min_chunk_size: Option<usize>,
+        policy: MinChunkPolicy,
+    ) -> Self {
+        self.min_chunk_size = min_chunk_size;
+        self.min_chunk_policy = policy;
+        self
+    }
+
+    /// Number of tokens in `text` according to the tokenizer used to size chunks.
+    pub fn token_count(&self, text: &str) -> usize {
+        self.tokenizer
+            .encode(text, false)
+            .map(|encoding| encoding.len())
+            .unwrap_or_default()
+    }
     pub fn split_into_chunks(
         &self,
         text: &str,
@@ -92,12 +309,47 @@ impl TextLoader {
             return None;
         }
 
-        // Remove single newlines but keep double newlines
+        // Line breaks are meaningful in code, so it skips the cleanup below.
+        if let SplittingStrategy::Code { language } = splitting_strategy {
+            let chunks = self.split_code(text, language).ok()?;
+            return Some(chunks.into_iter().map(|chunk| chunk.text).collect());
+        }
+        // Token spans refer to the text as given.
+        if let SplittingStrategy::SlidingWindow {
+            window_tokens,
+            stride_tokens,
+        } = splitting_strategy
+        {
+            let windows = self
+                .split_windows(text, window_tokens, stride_tokens)
+                .ok()?;
+            return Some(windows.into_iter().map(|window| window.text).collect());
+        }
+
+        // Remove single newlines but keep double newlines. Languages written without spaces
+        // must not get one inserted at every line break.
+        let line_joiner = match &self.sentence_splitter {
+            Some(sentence_splitter) => sentence_splitter.separator(),
+            None => " ",
+        };
         let cleaned_text = text
             .replace("\n\n", "{{DOUBLE_NEWLINE}}")
-            .replace("\n", " ")
+            .replace("\n", line_joiner)
             .replace("{{DOUBLE_NEWLINE}}", "\n\n");
         let chunks: Vec<String> = match splitting_strategy {
+            // Overlap is made of whole sentences so overlapping chunks never start mid-sentence.
+            SplittingStrategy::Sentence
+                if self.sentence_splitter.is_some() || self.overlap_ratio > 0.0 =>
+            {
+                let sentence_splitter = self.sentence_splitter.clone().unwrap_or_default();
+                let sentences = sentence_splitter.split(&cleaned_text);
+                sentence_splitter.pack_with_overlap(
+                    &sentences,
+                    self.chunk_size,
+                    self.overlap_ratio,
+                    |chunk| self.token_count(chunk),
+                )
+            }
             SplittingStrategy::Sentence => self
                 .splitter
                 .chunks(&cleaned_text)
@@ -119,9 +371,166 @@ impl TextLoader {
                         .block_on(async { chunker.chunk(&cleaned_text, 64).await })
                 })
             }
+            SplittingStrategy::Code { .. } | SplittingStrategy::SlidingWindow { .. } => {
+                unreachable!()
+            }
+        };
+
+        Some(self.apply_min_chunk_size(chunks, line_joiner))
+    }
+
+    fn apply_min_chunk_size(&self, chunks: Vec<String>, separator: &str) -> Vec<String> {
+        let Some(min_chunk_size) = self.min_chunk_size else {
+            return chunks;
         };
+        let mut kept: Vec<String> = Vec::with_capacity(chunks.len());
+        // A small first chunk waits for the next chunk since there is nothing to merge it into.
+        let mut pending: Option<String> = None;
+        for chunk in chunks {
+            let chunk = match pending.take() {
+                Some(previous) => format!("{}{}{}", previous, separator, chunk),
+                None => chunk,
+            };
+            if self.token_count(&chunk) >= min_chunk_size {
+                kept.push(chunk);
+                continue;
+            }
+            match (self.min_chunk_policy, kept.last_mut()) {
+                (MinChunkPolicy::Drop, _) => {}
+                (MinChunkPolicy::Merge, Some(previous)) => {
+                    previous.push_str(separator);
+                    previous.push_str(&chunk);
+                }
+                (MinChunkPolicy::Merge, None) => pending = Some(chunk),
+            }
+        }
+        kept.extend(pending);
+        kept
+    }
+
+    /// Splits `text` like [TextLoader::split_into_chunks], along with the metadata of each
+    /// chunk: the symbol and language of code chunks and the token span of windows. Chunks of
+    /// the other strategies get empty metadata.
+    #[allow(clippy::type_complexity)]
+    pub fn split_with_metadata(
+        &self,
+        text: &str,
+        splitting_strategy: SplittingStrategy,
+        semantic_encoder: Option<Arc<Embedder>>,
+    ) -> Result<(Vec<String>, Vec<HashMap<String, String>>), Error> {
+        match splitting_strategy {
+            SplittingStrategy::Code { language } => {
+                let code_chunks = self.split_code(text, language)?;
+                let chunk_metadata = code_chunks.iter().map(|chunk| chunk.metadata()).collect();
+                let chunks = code_chunks.into_iter().map(|chunk| chunk.text).collect();
+                Ok((chunks, chunk_metadata))
+            }
+            SplittingStrategy::SlidingWindow {
+                window_tokens,
+                stride_tokens,
+            } => {
+                let windows = self.split_windows(text, window_tokens, stride_tokens)?;
+                let chunk_metadata = windows.iter().map(|window| window.metadata()).collect();
+                let chunks = windows.into_iter().map(|window| window.text).collect();
+                Ok((chunks, chunk_metadata))
+            }
+            _ => {
+                let chunks = self
+                    .split_into_chunks(text, splitting_strategy, semantic_encoder)
+                    .unwrap_or_default();
+                let chunk_metadata = vec![HashMap::new(); chunks.len()];
+                Ok((chunks, chunk_metadata))
+            }
+        }
+    }
 
-        Some(chunks)
+    /// Splits source code with tree-sitter, keeping functions and classes whole when they fit
+    /// in the chunk size.
+    pub fn split_code(&self, text: &str, language: CodeLanguage) -> Result<Vec<CodeChunk>, Error> {
+        CodeSplitter::new(language, self.chunk_size).split(text, |chunk| self.token_count(chunk))
+    }
+
+    /// Splits text into windows of `window_tokens` tokens every `stride_tokens` tokens, counted
+    /// with the tokenizer used to size chunks.
+    pub fn split_windows(
+        &self,
+        text: &str,
+        window_tokens: usize,
+        stride_tokens: usize,
+    ) -> Result<Vec<WindowChunk>, Error> {
+        sliding_windows(&self.tokenizer, text, window_tokens, stride_tokens)
+    }
+
+    /// Whether [TextLoader::extract_text] can read `file`, either with a registered
+    /// [Extractor] or with a built-in one.
+    pub fn is_supported<T: AsRef<std::path::Path>>(file: &T) -> bool {
+        Self::extractor(file.as_ref(), PdfExtractor::default()).is_some()
+    }
+
+    /// The extractor reading `file`, PDF files being read with `pdf`. Registered extractors
+    /// come first, see [crate::file_processor::extractor::register_extractor].
+    fn extractor(file: &std::path::Path, pdf: PdfExtractor) -> Option<Arc<dyn Extractor>> {
+        if let Some(extractor) = registered_extractor(file) {
+            return Some(extractor);
+        }
+        let built_in: [Arc<dyn Extractor>; 3] = [
+            Arc::new(pdf),
+            Arc::new(HtmlExtractor::default()),
+            Arc::new(TextExtractor),
+        ];
+        built_in
+            .into_iter()
+            .find(|extractor| extractor.can_handle(file))
+    }
+
+    /// Reads `file` with the extractor handling it, PDF files with `pdf`. Failures are reported
+    /// as a [FileLoadingError].
+    pub(crate) fn extract<T: AsRef<std::path::Path>>(
+        file: &T,
+        pdf: PdfExtractor,
+    ) -> Result<ExtractedDocument, Error> {
+        let file = file.as_ref();
+        let path = file.to_string_lossy().to_string();
+        if !file.exists() {
+            return Err(FileLoadingError::FileNotFound(path).into());
+        }
+        if let Err(error) = fs::File::open(file) {
+            return Err(FileLoadingError::from_io(file, error).into());
+        }
+        let extractor = Self::extractor(file, pdf).ok_or_else(|| {
+            FileLoadingError::UnsupportedFileType(
+                file.extension()
+                    .unwrap_or_default()
+                    .to_string_lossy()
+                    .to_string(),
+            )
+        })?;
+        let is_encrypted = || {
+            file.extension().is_some_and(|extension| extension == "pdf")
+                && PdfProcessor::is_encrypted(file)
+        };
+        let document = match extractor.extract(file) {
+            Ok(document) => document,
+            // Custom extractors may classify their errors themselves.
+            Err(error) if error.is::<FileLoadingError>() => return Err(error),
+            Err(error) => {
+                let error = match error.downcast::<std::io::Error>() {
+                    Ok(error) => FileLoadingError::from_io(file, error),
+                    Err(_) if is_encrypted() => FileLoadingError::EncryptedFile(path),
+                    Err(source) => FileLoadingError::ExtractionFailed { path, source },
+                };
+                return Err(error.into());
+            }
+        };
+        if document.text.trim().is_empty() {
+            return Err(if is_encrypted() {
+                FileLoadingError::EncryptedFile(path)
+            } else {
+                FileLoadingError::EmptyDocument(path)
+            }
+            .into());
+        }
+        Ok(document)
     }
 
     pub fn extract_text<T: AsRef<std::path::Path>>(
@@ -129,34 +538,66 @@ impl TextLoader {
         use_ocr: bool,
         tesseract_path: Option<&str>,
     ) -> Result<String, Error> {
-        if !file.as_ref().exists() {
-            return Err(FileLoadingError::FileNotFound(
-                file.as_ref().to_str().unwrap().to_string(),
-            )
-            .into());
+        Ok(Self::extract(file, PdfExtractor::new(use_ocr, tesseract_path))?.text)
+    }
+
+    /// Like [TextLoader::extract_text], but keeps the page boundaries of PDF files and returns
+    /// the detected type and metadata of the file along with the text.
+    pub fn extract_document<T: AsRef<std::path::Path>>(
+        file: &T,
+        use_ocr: bool,
+        tesseract_path: Option<&str>,
+    ) -> Result<ExtractedDocument, Error> {
+        let mut document = Self::extract(file, PdfExtractor::new(use_ocr, tesseract_path))?;
+        if document.file_type.is_empty() {
+            document.file_type = file
+                .as_ref()
+                .extension()
+                .and_then(|extension| extension.to_str())
+                .unwrap_or_default()
+                .to_string();
         }
-        let file_extension = file.as_ref().extension().unwrap();
-        match file_extension.to_str().unwrap() {
-            "pdf" => PdfProcessor::extract_text(file, use_ocr, tesseract_path),
-            "md" => MarkdownProcessor::extract_text(file),
-            "txt" => TxtProcessor::extract_text(file),
-            "docx" => DocxProcessor::extract_text(file),
-            _ => Err(FileLoadingError::UnsupportedFileType(
-                file.as_ref()
-                    .extension()
-                    .unwrap()
-                    .to_str()
-                    .unwrap()
-                    .to_string(),
-            )
-            .into()),
+
+        // What the extractor found wins over the file system metadata.
+        let mut metadata = Self::get_metadata(file)?;
+        metadata.insert("file_type".to_string(), document.file_type.clone());
+        if !document.pages.is_empty() {
+            metadata.insert("page_count".to_string(), document.pages.len().to_string());
         }
+        metadata.extend(std::mem::take(&mut document.metadata));
+        document.metadata = metadata;
+        Ok(document)
+    }
+
+    /// Fails with [FileLoadingError::DocumentTooLarge] when `file` is bigger than `max_bytes`,
+    /// before anything is read from it. None accepts files of any size.
+    pub fn check_document_size<T: AsRef<std::path::Path>>(
+        file: T,
+        max_bytes: Option<u64>,
+    ) -> Result<(), Error> {
+        let Some(max_bytes) = max_bytes else {
+            return Ok(());
+        };
+        let file = file.as_ref();
+        let size = fs::metadata(file)
+            .map_err(|e| FileLoadingError::from_io(file, e))?
+            .len();
+        if size > max_bytes {
+            return Err(FileLoadingError::DocumentTooLarge {
+                path: file.to_string_lossy().to_string(),
+                size,
+                max_bytes,
+            }
+            .into());
+        }
+        Ok(())
     }
 
     pub fn get_metadata<T: AsRef<std::path::Path>>(
         file: T,
     ) -> Result<HashMap<String, String>, Error> {
-        let metadata = fs::metadata(&file).unwrap();
+        let metadata =
+            fs::metadata(&file).map_err(|e| FileLoadingError::from_io(file.as_ref(), e))?;
         let mut metadata_map = HashMap::new();
         metadata_map.insert(
             "created".to_string(),
@@ -173,6 +614,88 @@ impl TextLoader {
         );
         Ok(metadata_map)
     }
+
+    /// Reads the sidecar file `<file>.meta.json`, e.g. `doc.pdf.meta.json` for `doc.pdf`. It
+    /// must contain a JSON object. String values are kept as is and other values are stored as
+    /// their JSON text. Returns None when there is no sidecar file.
+    pub fn get_sidecar_metadata<T: AsRef<std::path::Path>>(
+        file: T,
+    ) -> Result<Option<HashMap<String, String>>, Error> {
+        let mut sidecar = file.as_ref().as_os_str().to_owned();
+        sidecar.push(".meta.json");
+        let sidecar = std::path::PathBuf::from(sidecar);
+        if !sidecar.exists() {
+            return Ok(None);
+        }
+        let content = fs::read_to_string(&sidecar)?;
+        let values: serde_json::Map<String, serde_json::Value> = serde_json::from_str(&content)
+            .map_err(|e| anyhow::anyhow!("Invalid sidecar metadata {:?}: {}", sidecar, e))?;
+        Ok(Some(
+            values
+                .into_iter()
+                .map(|(key, value)| match value {
+                    serde_json::Value::String(value) => (key, value),
+                    value => (key, value.to_string()),
+                })
+                .collect(),
+        ))
+    }
+
+    /// Returns the metadata of `file`, merged with its sidecar metadata when `sidecar` is true.
+    ///
+    /// Sidecar entries override the metadata read from the file system (`created`,
+    /// `modified`), except `file_name` which always points to the embedded file. Metadata
+    /// produced per chunk, like the symbol of a code chunk, is applied afterwards and wins over
+    /// both.
+    pub fn get_metadata_with_sidecar<T: AsRef<std::path::Path>>(
+        file: T,
+        sidecar: bool,
+    ) -> Result<HashMap<String, String>, Error> {
+        let mut metadata = Self::get_metadata(&file)?;
+        if sidecar {
+            if let Some(sidecar_metadata) = Self::get_sidecar_metadata(&file)? {
+                let file_name = metadata.remove("file_name");
+                metadata.extend(sidecar_metadata);
+                if let Some(file_name) = file_name {
+                    metadata.insert("file_name".to_string(), file_name);
+                }
+            }
+        }
+        Ok(metadata)
+    }
+}
+
+/// Fails unless `first` to `last` is a range of pages, counted from 1, of a document of
+/// `page_count` pages.
+pub fn check_page_range(first: usize, last: usize, page_count: usize) -> Result<(), Error> {
+    if first == 0 || first > last || last > page_count {
+        return Err(anyhow::anyhow!(
+            "Invalid page range {}-{} for a document of {} pages, pages are counted from 1",
+            first,
+            last,
+            page_count
+        ));
+    }
+    Ok(())
+}
+
+/// Joins the pages with blank lines and records where each page starts and ends.
+pub(crate) fn join_pages(pages: Vec<String>) -> (String, Vec<PageBoundary>) {
+    let mut text = String::new();
+    let mut boundaries = Vec::with_capacity(pages.len());
+    for (index, page) in pages.iter().enumerate() {
+        if index > 0 {
+            text.push_str("\n\n");
+        }
+        let page = page.trim();
+        boundaries.push(PageBoundary {
+            page_number: index + 1,
+            start: text.len(),
+            end: text.len() + page.len(),
+        });
+        text.push_str(page);
+    }
+    (text, boundaries)
 }
 
 #[cfg(test)]
@@ -181,6 +704,193 @@ mod tests {
     use crate::embeddings::{embed::EmbedImage, local::clip::ClipEmbedder};
     use std::path::PathBuf;
 
+    #[test]
+    fn test_sidecar_metadata() {
+        let file_path = PathBuf::from("../test_files/sidecar/report.txt");
+        let sidecar = TextLoader::get_sidecar_metadata(&file_path)
+            .unwrap()
+            .unwrap();
+        assert_eq!(sidecar.get("source").unwrap(), "annual-report");
+        assert_eq!(sidecar.get("year").unwrap(), "2023");
+
+        let metadata = TextLoader::get_metadata_with_sidecar(&file_path, true).unwrap();
+        assert_eq!(metadata.get("author").unwrap(), "Finance team");
+        assert_eq!(metadata.get("modified").unwrap(), "from sidecar");
+        assert!(metadata.get("file_name").unwrap().ends_with("report.txt"));
+
+        let without = TextLoader::get_metadata_with_sidecar(&file_path, false).unwrap();
+        assert!(!without.contains_key("author"));
+        assert!(TextLoader::get_sidecar_metadata("../test_files/test.txt")
+            .unwrap()
+            .is_none());
+    }
+
+    #[test]
+    fn test_extract_document() {
+        let document =
+            TextLoader::extract_document(&"../test_files/test.pdf", false, None).unwrap();
+        assert_eq!(document.file_type, "pdf");
+        assert!(!document.pages.is_empty());
+        assert_eq!(
+            document.metadata.get("page_count").unwrap(),
+            &document.pages.len().to_string()
+        );
+        let first_page = document.page_text(1).unwrap();
+        assert!(document.text.starts_with(first_page));
+        assert_eq!(document.page_at(0), Some(1));
+        assert_eq!(
+            document.page_at(document.text.len()),
+            Some(document.pages.len())
+        );
+
+        let document =
+            TextLoader::extract_document(&"../test_files/test.txt", false, None).unwrap();
+        assert_eq!(document.file_type, "txt");
+        assert!(document.pages.is_empty());
+        assert_eq!(document.page_at(0), None);
+        assert!(document
+            .metadata
+            .get("file_name")
+            .unwrap()
+            .ends_with("test.txt"));
+    }
+
+    #[test]
+    fn test_truncate_document() {
+        let mut document =
+            TextLoader::extract_document(&"../test_files/test.pdf", false, None).unwrap();
+        assert!(document.pages.len() > 1);
+        let first_page = document.page_text(1).unwrap().to_string();
+        assert!(document.truncate(Some(1), None));
+        assert_eq!(document.text, first_page);
+        assert_eq!(document.pages.len(), 1);
+        assert!(!document.truncate(Some(1), None));
+
+        assert!(document.truncate(None, Some(10)));
+        assert_eq!(document.text.chars().count(), 10);
+        assert_eq!(document.page_text(1), Some(document.text.as_str()));
+        assert!(!document.truncate(Some(3), Some(100)));
+    }
+
+    #[test]
+    fn test_select_pages() {
+        let document =
+            TextLoader::extract_document(&"../test_files/test.pdf", false, None).unwrap();
+        let page_count = document.pages.len();
+        assert!(page_count > 2);
+        let second_page = document.page_text(2).unwrap().to_string();
+        let third_page = document.page_text(3).unwrap().to_string();
+
+        let mut selected = document.clone();
+        selected.select_pages(2, 3).unwrap();
+        assert_eq!(selected.text, format!("{}\n\n{}", second_page, third_page));
+        assert_eq!(selected.page_at(0), Some(2));
+        assert_eq!(selected.page_text(3), Some(third_page.as_str()));
+        assert_eq!(selected.page_text(1), None);
+
+        let mut selected = document.clone();
+        assert!(selected.select_pages(0, 1).is_err());
+        assert!(selected.select_pages(3, 2).is_err());
+        assert!(selected.select_pages(1, page_count + 1).is_err());
+        assert_eq!(selected.text, document.text);
+    }
+
+    #[test]
+    fn test_file_loading_errors() {
+        let directory = tempdir::TempDir::new("errors").unwrap();
+        let write = |name: &str, content: &[u8]| {
+            let file = directory.path().join(name);
+            fs::write(&file, content).unwrap();
+            file
+        };
+        let error = |file: &PathBuf| TextLoader::extract_text(file, false, None).unwrap_err();
+
+        let missing = error(&directory.path().join("missing.txt"));
+        assert!(matches!(
+            missing.downcast_ref::<FileLoadingError>(),
+            Some(FileLoadingError::FileNotFound(_))
+        ));
+
+        let unsupported = error(&write("archive.xyz", b"data"));
+        assert!(matches!(
+            unsupported.downcast_ref::<FileLoadingError>(),
+            Some(FileLoadingError::UnsupportedFileType(extension)) if extension == "xyz"
+        ));
+
+        let empty = error(&write("empty.txt", b" \n"));
+        assert!(matches!(
+            empty.downcast_ref::<FileLoadingError>(),
+            Some(FileLoadingError::EmptyDocument(_))
+        ));
+
+        let broken = error(&write("broken.pdf", b"not a pdf"));
+        match broken.downcast_ref::<FileLoadingError>() {
+            Some(error @ FileLoadingError::ExtractionFailed { path, .. }) => {
+                assert!(path.ends_with("broken.pdf"));
+                assert!(std::error::Error::source(error).is_some());
+            }
+            other => panic!("unexpected error {:?}", other),
+        }
+
+        let encrypted = error(&write(
+            "locked.pdf",
+            b"%PDF-1.4\ntrailer\n<< /Root 1 0 R /Encrypt 2 0 R >>\n%%EOF\n",
+        ));
+        assert!(matches!(
+            encrypted.downcast_ref::<FileLoadingError>(),
+            Some(FileLoadingError::EncryptedFile(_))
+        ));
+
+        // Permissions are not enforced for root, so the classification is checked directly.
+        let denied = FileLoadingError::from_io(
+            std::path::Path::new("secret.txt"),
+            std::io::Error::from(std::io::ErrorKind::PermissionDenied),
+        );
+        assert!(matches!(denied, FileLoadingError::PermissionDenied(_)));
+        assert_eq!(denied.to_string(), "Permission denied: secret.txt");
+
+        let large = write("large.txt", &[b'a'; 100]);
+        TextLoader::check_document_size(&large, None).unwrap();
+        TextLoader::check_document_size(&large, Some(100)).unwrap();
+        let too_large = TextLoader::check_document_size(&large, Some(99)).unwrap_err();
+        assert!(matches!(
+            too_large.downcast_ref::<FileLoadingError>(),
+            Some(FileLoadingError::DocumentTooLarge { size: 100, .. })
+        ));
+    }
+
+    #[test]
+    fn test_min_chunk_size() {
+        let chunks = vec![
+            "The first chunk is long enough to be kept.".to_string(),
+            "So is the second chunk of the document.".to_string(),
+            "Ok.".to_string(),
+        ];
+        let merge = TextLoader::new(256, 0.0).with_min_chunk_size(Some(3), MinChunkPolicy::Merge);
+        assert_eq!(
+            merge.apply_min_chunk_size(chunks.clone(), " "),
+            vec![
+                "The first chunk is long enough to be kept.",
+                "So is the second chunk of the document. Ok.",
+            ]
+        );
+        assert_eq!(
+            merge.apply_min_chunk_size(vec!["Hi.".to_string(), chunks[1].clone()], " "),
+            vec!["Hi. So is the second chunk of the document."]
+        );
+
+        let drop = TextLoader::new(256, 0.0).with_min_chunk_size(Some(3), MinChunkPolicy::Drop);
+        assert_eq!(drop.apply_min_chunk_size(chunks.clone(), " "), chunks[..2]);
+    }
+
+    #[test]
+    fn test_join_pages() {
+        let (text, pages) = join_pages(vec![" first\n".to_string(), "second".to_string()]);
+        assert_eq!(text, "first\n\nsecond");
+        assert_eq!(&text[pages[1].start..pages[1].end], "second");
+        assert_eq!(pages[0].end, 5);
+    }
+
     #[test]
     fn test_text_loader() {
         let file_path = PathBuf::from("../test_files/test.pdf");
@@ -202,6 +912,28 @@ mod tests {
         assert!(!text.is_empty());
     }
 
+    #[test]
+    fn test_split_chinese_text() {
+        let text = "今天天气很好。我们去公园散步吧！\n你觉得怎么样？";
+        let text_loader = TextLoader::new(256, 0.0).with_language(Some("zh"));
+        let chunks = text_loader
+            .split_into_chunks(text, SplittingStrategy::Sentence, None)
+            .unwrap();
+        assert_eq!(
+            chunks,
+            vec!["今天天气很好。我们去公园散步吧！你觉得怎么样？"]
+        );
+
+        let text_loader = TextLoader::new(8, 0.0).with_language(Some("zh"));
+        let chunks = text_loader
+            .split_into_chunks(text, SplittingStrategy::Sentence, None)
+            .unwrap();
+        assert!(chunks.len() > 1);
+        assert!(chunks
+            .iter()
+            .all(|chunk| chunk.ends_with(['。', '！', '？'])));
+    }
+
     #[test]
     fn test_metadata() {
         let file_path = PathBuf::from("test_files/test.pdf");
@@ -5,6 +5,7 @@ from embed_anything import (
     embed_query,
     embed_file,
     embed_directory,
+    embed_records,
     ONNXModel,
 )
 
@@ -118,3 +119,30 @@ def test_bert_model_unsupported_file_type(bert_model, tmp_path):
 
     with pytest.raises(ValueError):
         embed_file(str(tmp_path / "unsupported.mp3"), bert_model)
+
+
+def test_bert_model_empty_file(bert_model, tmp_path):
+    with open(tmp_path / "empty.txt", "w") as f:
+        f.write("\n")
+
+    with pytest.raises(ValueError, match="No text found"):
+        embed_file(str(tmp_path / "empty.txt"), bert_model)
+
+
+def test_bert_model_corrupted_file(bert_model, tmp_path):
+    with open(tmp_path / "broken.pdf", "w") as f:
+        f.write("This is not a PDF")
+
+    with pytest.raises(RuntimeError):
+        embed_file(str(tmp_path / "broken.pdf"), bert_model)
+
+
+def test_bert_model_embed_records(bert_model):
+    rows = [
+        {"id": 1, "text": "The invoice was paid on time.", "metadata": {"table": "invoices"}},
+        {"id": "order-2", "text": "The delivery was late."},
+    ]
+    data = embed_records(rows, bert_model)
+    assert [d.metadata["record_id"] for d in data] == ["1", "order-2"]
+    assert data[0].metadata["table"] == "invoices"
+    assert data[1].text == "The delivery was late."